@@ -0,0 +1,59 @@
+//! Decodes a fixture block of 2000 transactions (four token transfers each).
+//!
+//! cargo bench --bench decode_block
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use serde_json::json;
+use solana_sdk::message::Message;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::Transaction;
+use solana_transaction_status::UiConfirmedBlock;
+use spi_wrapper::{decode_block, ProcessorRegistry};
+
+const TRANSACTIONS: usize = 2000;
+
+/// A block as returned by `getBlock` with the base64 encoding.
+fn fixture_block() -> UiConfirmedBlock {
+    let payer = Pubkey::new_unique();
+    let (source, destination) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let transactions: Vec<_> = (0..TRANSACTIONS)
+        .map(|index| {
+            let instructions: Vec<_> = (0..4)
+                .map(|amount| spl_token::instruction::transfer(
+                    &spl_token::id(), &source, &destination, &payer, &[],
+                    amount + index as u64).unwrap())
+                .collect();
+            let transaction = Transaction::new_unsigned(Message::new(&instructions, Some(&payer)));
+
+            json!({
+                "transaction": [
+                    base64::encode(bincode::serialize(&transaction).unwrap()), "base64"],
+                "meta": {
+                    "err": null,
+                    "status": { "Ok": null },
+                    "fee": 5000,
+                    "preBalances": [],
+                    "postBalances": [],
+                },
+            })
+        })
+        .collect();
+
+    serde_json::from_value(json!({
+        "blockhash": bs58::encode([1u8; 32]).into_string(),
+        "previousBlockhash": bs58::encode([0u8; 32]).into_string(),
+        "parentSlot": 0,
+        "transactions": transactions,
+        "blockTime": 1_600_000_000,
+    })).unwrap()
+}
+
+fn decode_fixture_block(c: &mut Criterion) {
+    let registry = ProcessorRegistry::default();
+    let block = fixture_block();
+
+    c.bench_function("decode_block", |b| b.iter(|| decode_block(&registry, 1, &block)));
+}
+
+criterion_group!(benches, decode_fixture_block);
+criterion_main!(benches);
@@ -0,0 +1,60 @@
+//! Compares decoding a batch of 10k token transfers through the deprecated async `process` (one
+//! tokio task per instruction) and the synchronous `decode`.
+//!
+//! cargo bench --bench decode_instructions
+
+use std::time::{Duration, Instant};
+
+use spl_token::instruction::TokenInstruction;
+use spi_wrapper::{Instruction, Network, Symbol};
+
+const BATCH_SIZE: usize = 10_000;
+const RUNS: u32 = 10;
+
+fn batch() -> Vec<Instruction> {
+    let data = TokenInstruction::Transfer { amount: 42 }.pack();
+    let program = Symbol::intern(&spl_token::id().to_string());
+
+    (0..BATCH_SIZE)
+        .map(|index| Instruction {
+            tx_instruction_id: (index % 64) as i16,
+            transaction_hash: format!("transaction-{}", index / 64).into(),
+            program,
+            data: data.clone(),
+            parent_index: -1,
+            timestamp: 0,
+            network: Network::Mainnet,
+        })
+        .collect()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let per_run = elapsed / RUNS;
+    println!("{:>6}: {:?} per batch, {:.0} instructions/s", name, per_run,
+             BATCH_SIZE as f64 / per_run.as_secs_f64());
+}
+
+#[allow(deprecated)]
+fn main() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let mut elapsed = Duration::default();
+    for _ in 0..RUNS {
+        let instructions = batch();
+        let started = Instant::now();
+        let decoded = runtime.block_on(spi_wrapper::process(instructions, None));
+        elapsed += started.elapsed();
+        assert_eq!(decoded.len(), BATCH_SIZE);
+    }
+    report("async", elapsed);
+
+    let mut elapsed = Duration::default();
+    for _ in 0..RUNS {
+        let instructions = batch();
+        let started = Instant::now();
+        let decoded = spi_wrapper::decode(instructions, None);
+        elapsed += started.elapsed();
+        assert_eq!(decoded.len(), BATCH_SIZE);
+    }
+    report("sync", elapsed);
+}
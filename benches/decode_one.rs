@@ -0,0 +1,47 @@
+//! Compares decoding a token transfer one instruction at a time through `ProcessorRegistry::decode`
+//! and its allocation-free fast path `decode_one`, against the sub-microsecond target.
+//!
+//! cargo bench --bench decode_one
+
+use std::time::{Duration, Instant};
+
+use solana_sdk::pubkey::Pubkey;
+use spl_token::instruction::TokenInstruction;
+use spi_wrapper::{Instruction, Network, ProcessorRegistry};
+
+const TOKEN: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+const ITERATIONS: u32 = 1_000_000;
+
+fn report(name: &str, elapsed: Duration) {
+    let per_instruction = elapsed / ITERATIONS;
+    println!("{:>10}: {:?} per instruction{}", name, per_instruction,
+             if per_instruction < Duration::from_micros(1) { "" } else { " (over 1µs)" });
+}
+
+fn main() {
+    let registry = ProcessorRegistry::default();
+    let program_id: Pubkey = TOKEN.parse().unwrap();
+    let data = TokenInstruction::Transfer { amount: 42 }.pack();
+
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        let decoded = registry.decode(Instruction {
+            tx_instruction_id: 0,
+            transaction_hash: "signature".into(),
+            program: TOKEN.into(),
+            data: data.clone(),
+            parent_index: -1,
+            timestamp: 0,
+            network: Network::Mainnet,
+        }, None);
+        assert!(decoded.is_some());
+    }
+    report("decode", started.elapsed());
+
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        let decoded = registry.decode_one(&program_id, &data);
+        assert!(decoded.decoded().is_some());
+    }
+    report("decode_one", started.elapsed());
+}
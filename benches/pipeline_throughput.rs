@@ -0,0 +1,100 @@
+//! Runs 20 fixture blocks of 200 transactions (four token transfers each) through the decoding
+//! pipeline with 1, 2, 4 and 8 workers, to show how its throughput scales with them.
+//!
+//! cargo bench --bench pipeline_throughput
+
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde_json::json;
+use solana_sdk::message::Message;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::Transaction;
+use solana_transaction_status::UiConfirmedBlock;
+use spi_wrapper::ingest::pipeline::{Pipeline, PipelineConfig};
+use spi_wrapper::sinks::dry_run::DryRunSink;
+use spi_wrapper::ProcessorRegistry;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+
+const BLOCKS: u64 = 20;
+const TRANSACTIONS_PER_BLOCK: usize = 200;
+const TRANSFERS_PER_TRANSACTION: u64 = 4;
+
+/// A block as returned by `getBlock` with the base64 encoding.
+fn fixture_block(slot: u64) -> UiConfirmedBlock {
+    let payer = Pubkey::new_unique();
+    let (source, destination) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let transactions: Vec<_> = (0..TRANSACTIONS_PER_BLOCK)
+        .map(|index| {
+            let instructions: Vec<_> = (0..TRANSFERS_PER_TRANSACTION)
+                .map(|amount| spl_token::instruction::transfer(
+                    &spl_token::id(), &source, &destination, &payer, &[],
+                    amount + index as u64).unwrap())
+                .collect();
+            let transaction = Transaction::new_unsigned(Message::new(&instructions, Some(&payer)));
+
+            json!({
+                "transaction": [
+                    base64::encode(bincode::serialize(&transaction).unwrap()), "base64"],
+                "meta": {
+                    "err": null,
+                    "status": { "Ok": null },
+                    "fee": 5000,
+                    "preBalances": [],
+                    "postBalances": [],
+                },
+            })
+        })
+        .collect();
+
+    serde_json::from_value(json!({
+        "blockhash": bs58::encode([slot as u8; 32]).into_string(),
+        "previousBlockhash": bs58::encode([slot as u8 - 1; 32]).into_string(),
+        "parentSlot": slot - 1,
+        "transactions": transactions,
+        "blockTime": 1_600_000_000 + slot,
+    })).unwrap()
+}
+
+async fn run(
+    registry: Arc<ProcessorRegistry>,
+    workers: usize,
+    blocks: Vec<(u64, UiConfirmedBlock)>,
+) {
+    let pipeline = Pipeline::new(registry, PipelineConfig {
+        workers,
+        max_in_flight: 500,
+        ..PipelineConfig::default()
+    });
+    let (sender, receiver) = mpsc::channel(4);
+    tokio::spawn(async move {
+        for block in blocks {
+            if sender.send(block).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    pipeline.run(receiver, &mut DryRunSink::new()).await.unwrap();
+}
+
+fn pipeline_throughput(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let registry = Arc::new(ProcessorRegistry::default());
+    let blocks: Vec<_> = (1..=BLOCKS).map(|slot| (slot, fixture_block(slot))).collect();
+
+    let mut group = c.benchmark_group("pipeline_throughput");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(
+        BLOCKS * TRANSACTIONS_PER_BLOCK as u64 * TRANSFERS_PER_TRANSACTION));
+    for workers in &[1, 2, 4, 8] {
+        group.bench_with_input(BenchmarkId::from_parameter(workers), workers, |b, &workers| {
+            b.iter(|| runtime.block_on(run(registry.clone(), workers, blocks.clone())))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, pipeline_throughput);
+criterion_main!(benches);
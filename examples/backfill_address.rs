@@ -0,0 +1,55 @@
+//! Indexes the whole signature history of an address (wallet or program) into a SQLite database.
+//! Re-running the command resumes from the checkpoint saved next to the database.
+//!
+//! cargo run --example backfill_address --features sqlite -- <rpc urls> <address> <db path>
+//!
+//! Several RPC urls may be given, separated by commas; they're used as a single pool. Logging is
+//! configured through `RUST_LOG` and `SPI_LOG_FORMAT` (`pretty`, `compact` or `json`).
+
+mod common;
+
+use std::sync::Arc;
+
+use solana_sdk::pubkey::Pubkey;
+use spi_wrapper::checkpoint::file::FileCheckpointStore;
+use spi_wrapper::checkpoint::Checkpointing;
+use spi_wrapper::ingest::pool::{RpcPool, RpcPoolConfig};
+use spi_wrapper::ingest::backfill::{backfill_address, BackfillConfig};
+use spi_wrapper::sinks::sqlite::SqliteSink;
+use spi_wrapper::ProcessorRegistry;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    common::init_tracing();
+
+    let mut args = std::env::args().skip(1);
+    let url = args.next()
+        .unwrap_or_else(|| "https://api.mainnet-beta.solana.com".to_string());
+    let address: Pubkey = args.next().ok_or("missing address")?.parse()?;
+    let db_path = args.next().unwrap_or_else(|| "spi-wrapper.sqlite".to_string());
+
+    let config = BackfillConfig {
+        checkpoint: Some(Checkpointing::new(
+            Arc::new(FileCheckpointStore::new(format!("{}.checkpoints", db_path))),
+            &format!("backfill-{}", address),
+        )),
+        ..BackfillConfig::default()
+    };
+    let mut sink = SqliteSink::open(&db_path)?;
+
+    let progress = backfill_address(
+        &Arc::new(RpcPool::new(url.split(',').map(String::from).collect(),
+                               RpcPoolConfig::default())),
+        &address,
+        None,
+        &mut sink,
+        &Arc::new(ProcessorRegistry::default()),
+        &config,
+        |progress| println!("{} transactions processed, earliest slot {:?}",
+                            progress.processed, progress.earliest_slot),
+    ).await?;
+
+    println!("Done: {} processed, {} missing", progress.processed, progress.missing);
+
+    Ok(())
+}
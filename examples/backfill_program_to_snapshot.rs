@@ -0,0 +1,99 @@
+//! Indexes the whole signature history of a program into snapshot files (see
+//! `spi_wrapper::snapshot`), one per page of signatures, reporting progress as it goes.
+//! Interrupting it (Ctrl-C) and running it again resumes from the last page written, which is
+//! checkpointed next to the snapshots.
+//!
+//! cargo run --example backfill_program_to_snapshot --features cli,snapshot -- \
+//!     --rpc-url <rpc urls> --program <program id> --out <snapshot dir>
+//!
+//! Several RPC urls may be given, separated by commas; they're used as a single pool. Flags can be
+//! set through the environment variables `--help` lists instead. Logging is configured through
+//! `RUST_LOG` and `SPI_LOG_FORMAT` (`pretty`, `compact` or `json`).
+
+mod common;
+
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use solana_sdk::pubkey::Pubkey;
+use spi_wrapper::checkpoint::file::FileCheckpointStore;
+use spi_wrapper::checkpoint::Checkpointing;
+use spi_wrapper::ingest::backfill::{backfill_address, BackfillConfig};
+use spi_wrapper::ingest::pool::{RpcPool, RpcPoolConfig};
+use spi_wrapper::shutdown::ShutdownCoordinator;
+use spi_wrapper::sinks::snapshot::SnapshotSink;
+use spi_wrapper::sinks::Sink;
+use spi_wrapper::ProcessorRegistry;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+struct Args {
+    /// The RPC endpoints, separated by commas.
+    #[structopt(long, env = "SPI_RPC_URL", default_value = "https://api.mainnet-beta.solana.com")]
+    rpc_url: String,
+    /// The program whose transactions are indexed.
+    #[structopt(long, env = "SPI_PROGRAM")]
+    program: Pubkey,
+    /// The directory the snapshots are written to.
+    #[structopt(long, env = "SPI_OUT", default_value = "snapshots", parse(from_os_str))]
+    out: PathBuf,
+    /// How many RPC requests may be sent per second.
+    #[structopt(long, env = "SPI_REQUESTS_PER_SECOND", default_value = "10")]
+    requests_per_second: u32,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    common::init_tracing();
+    let args = Args::from_args();
+
+    let config = BackfillConfig {
+        checkpoint: Some(Checkpointing::new(
+            Arc::new(FileCheckpointStore::new(format!("{}.checkpoints", args.out.display()))),
+            &format!("backfill-{}", args.program),
+        )),
+        requests_per_second: args.requests_per_second,
+        ..BackfillConfig::default()
+    };
+    let mut sink = SnapshotSink::open(&args.out)?;
+
+    let progress = ProgressBar::new_spinner();
+    progress.set_style(ProgressStyle::default_spinner()
+        .template("{spinner} {elapsed} {msg}"));
+    let shutdown = ShutdownCoordinator::default();
+    shutdown.listen_for_signals()?;
+    let interrupted = shutdown.token();
+
+    let backfill = backfill_address(
+        &Arc::new(RpcPool::new(args.rpc_url.split(',').map(String::from).collect(),
+                               RpcPoolConfig::default())),
+        &args.program,
+        None,
+        &mut sink,
+        &Arc::new(ProcessorRegistry::default()),
+        &config,
+        |done| progress.set_message(format!("{} transactions, {} missing, back to slot {}",
+            done.processed, done.missing,
+            done.earliest_slot.map_or("-".to_string(), |slot| slot.to_string()))),
+    );
+    let result = tokio::select! {
+        result = backfill => Some(result),
+        _ = interrupted.cancelled() => None,
+    };
+    progress.finish_and_clear();
+    // The pages not checkpointed yet are fetched again on restart.
+    sink.shutdown().await?;
+
+    match result {
+        Some(done) => {
+            let done = done?;
+            println!("Backfilled {} transactions of {} ({} missing) into {}", done.processed,
+                     args.program, done.missing, args.out.display());
+        }
+        None => println!("Interrupted, run again to resume"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,73 @@
+//! Decodes the instructions of a program this crate knows nothing about with a closure registered
+//! at runtime. The toy counter program takes Borsh-encoded instructions.
+//!
+//! cargo run --example closure_processor
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::pubkey::Pubkey;
+use spi_wrapper::{decode_transaction, ProcessError, ProcessorRegistry, RawTransaction};
+
+const COUNTER_PROGRAM: &str = "Counter111111111111111111111111111111111111";
+
+#[derive(BorshSerialize, BorshDeserialize)]
+enum CounterInstruction {
+    // Accounts: [writable] the counter, [signer] its authority.
+    Initialize { start: u64 },
+    // Accounts: [writable] the counter.
+    Increment { amount: u64 },
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut registry = ProcessorRegistry::default();
+    registry.register_fn(COUNTER_PROGRAM, |ctx| {
+        let counter = ctx.accounts.first()
+            .ok_or_else(|| ProcessError::Other("missing the counter account".to_string()))?;
+        let instruction_set = match CounterInstruction::try_from_slice(ctx.data)? {
+            CounterInstruction::Initialize { start } => ctx.instruction_set("initialize", vec![
+                ("counter", counter.to_string()),
+                ("start", start.to_string()),
+            ]),
+            CounterInstruction::Increment { amount } => ctx.instruction_set("increment", vec![
+                ("counter", counter.to_string()),
+                ("amount", amount.to_string()),
+            ]),
+        };
+
+        Ok(vec![instruction_set])
+    });
+
+    let (authority, counter) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let transaction = RawTransaction {
+        slot: 42,
+        block_time: None,
+        transaction_hash: "example-signature".to_string(),
+        account_keys: vec![authority, counter, COUNTER_PROGRAM.parse()?],
+        num_required_signatures: 1,
+        instructions: vec![
+            CompiledInstruction {
+                program_id_index: 2,
+                accounts: vec![1, 0],
+                data: CounterInstruction::Initialize { start: 10 }.try_to_vec()?,
+            },
+            CompiledInstruction {
+                program_id_index: 2,
+                accounts: vec![1],
+                data: CounterInstruction::Increment { amount: 5 }.try_to_vec()?,
+            },
+        ],
+        inner_instructions: vec![],
+        token_mints: vec![],
+        fee: 5000,
+        succeeded: true,
+        stack_heights: vec![],
+        log_messages: vec![],
+        pre_balances: vec![],
+        post_balances: vec![],
+    };
+
+    let record = decode_transaction(&registry, &transaction);
+    println!("{}", serde_json::to_string_pretty(&record)?);
+
+    Ok(())
+}
@@ -0,0 +1,19 @@
+//! Setup shared by the example binaries.
+
+use tracing_subscriber::EnvFilter;
+
+/// Installs a subscriber logging to stderr. What gets logged is read from `RUST_LOG` (`info` by
+/// default, e.g. `RUST_LOG=spi_wrapper=debug` to see every span) and the output format from
+/// `SPI_LOG_FORMAT`: `pretty` (the default), `compact` or `json`.
+pub fn init_tracing() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+
+    match std::env::var("SPI_LOG_FORMAT").as_deref() {
+        Ok("json") => subscriber.json().init(),
+        Ok("compact") => subscriber.compact().init(),
+        _ => subscriber.pretty().init(),
+    }
+}
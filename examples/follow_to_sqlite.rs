@@ -0,0 +1,93 @@
+//! Follows the chain tip over JSON-RPC, decoding every block into a SQLite database until
+//! interrupted (Ctrl-C). The last slot indexed is checkpointed next to the database, so that a
+//! restart resumes from it.
+//!
+//! cargo run --example follow_to_sqlite --features cli -- --rpc-url <rpc urls> --db <db path>
+//!
+//! Several RPC urls may be given, separated by commas; they're used as a single pool. Flags taking
+//! a value can be set through the environment variables `--help` lists instead. Logging is
+//! configured through `RUST_LOG` and `SPI_LOG_FORMAT` (`pretty`, `compact` or `json`).
+
+mod common;
+
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use spi_wrapper::checkpoint::file::FileCheckpointStore;
+use spi_wrapper::checkpoint::Checkpointing;
+use spi_wrapper::ingest::pool::{RpcPool, RpcPoolConfig};
+use spi_wrapper::ingest::rpc::{BlockPoller, BlockPollerConfig};
+use spi_wrapper::shutdown::ShutdownCoordinator;
+use spi_wrapper::sinks::sqlite::SqliteSink;
+use spi_wrapper::sinks::Sink;
+use spi_wrapper::ProcessorRegistry;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+struct Args {
+    /// The RPC endpoints, separated by commas.
+    #[structopt(long, env = "SPI_RPC_URL", default_value = "https://api.mainnet-beta.solana.com")]
+    rpc_url: String,
+    /// The SQLite database to index into.
+    #[structopt(long, env = "SPI_DB", default_value = "spi-wrapper.sqlite", parse(from_os_str))]
+    db: PathBuf,
+    /// The first slot to index when nothing was checkpointed yet, the tip by default.
+    #[structopt(long, env = "SPI_START_SLOT")]
+    start_slot: Option<u64>,
+    /// Stop once caught up with the tip instead of waiting for new blocks.
+    #[structopt(long)]
+    exit_when_caught_up: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    common::init_tracing();
+    let args = Args::from_args();
+
+    let checkpoint = Checkpointing::new(
+        Arc::new(FileCheckpointStore::new(format!("{}.checkpoints", args.db.display()))),
+        "follow",
+    );
+    let poller = BlockPoller::new(
+        Arc::new(RpcPool::new(args.rpc_url.split(',').map(String::from).collect(),
+                              RpcPoolConfig::default())),
+        Arc::new(ProcessorRegistry::default()),
+        BlockPollerConfig {
+            start_slot: args.start_slot,
+            checkpoint: Some(checkpoint.clone()),
+            ..BlockPollerConfig::default()
+        },
+    );
+    let mut sink = SqliteSink::open(&args.db)?;
+
+    let shutdown = ShutdownCoordinator::default();
+    shutdown.listen_for_signals()?;
+    let interrupted = shutdown.token();
+    let result = tokio::select! {
+        result = poller.run(&mut sink) => result,
+        _ = interrupted.cancelled() => Ok(()),
+        _ = caught_up(&poller), if args.exit_when_caught_up => Ok(()),
+    };
+
+    // Checkpoints are only saved once the sink has been flushed, so whatever was written since
+    // the last one is indexed again on restart.
+    sink.shutdown().await?;
+    result?;
+
+    let last_slot = checkpoint.load().await?.and_then(|checkpoint| checkpoint.last_slot);
+    match last_slot {
+        Some(slot) => println!("Indexed up to slot {} into {}", slot, args.db.display()),
+        None => println!("Indexed nothing into {}", args.db.display()),
+    }
+
+    Ok(())
+}
+
+/// Returns once the poller has caught up with the tip.
+async fn caught_up(poller: &BlockPoller) {
+    while !poller.is_caught_up() {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
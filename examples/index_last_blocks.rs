@@ -0,0 +1,42 @@
+//! Indexes the blocks of the last N confirmed slots into a SQLite database.
+//!
+//! cargo run --example index_last_blocks --features sqlite -- <rpc urls> <slot count> <db path>
+//!
+//! Several RPC urls may be given, separated by commas; they're used as a single pool. Logging is
+//! configured through `RUST_LOG` and `SPI_LOG_FORMAT` (`pretty`, `compact` or `json`).
+
+mod common;
+
+use std::sync::Arc;
+
+use spi_wrapper::ingest::pool::{RpcPool, RpcPoolConfig};
+use spi_wrapper::ingest::rpc::{BlockPoller, BlockPollerConfig};
+use spi_wrapper::sinks::sqlite::SqliteSink;
+use spi_wrapper::ProcessorRegistry;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    common::init_tracing();
+
+    let mut args = std::env::args().skip(1);
+    let url = args.next()
+        .unwrap_or_else(|| "https://api.mainnet-beta.solana.com".to_string());
+    let slot_count: u64 = args.next().map(|n| n.parse()).transpose()?.unwrap_or(10);
+    let db_path = args.next().unwrap_or_else(|| "spi-wrapper.sqlite".to_string());
+
+    let poller = BlockPoller::new(
+        Arc::new(RpcPool::new(url.split(',').map(String::from).collect(),
+                              RpcPoolConfig::default())),
+        Arc::new(ProcessorRegistry::default()),
+        BlockPollerConfig::default(),
+    );
+    let mut sink = SqliteSink::open(&db_path)?;
+
+    let tip = poller.tip().await?;
+    let start_slot = tip.saturating_sub(slot_count.saturating_sub(1));
+    let written = poller.index_range(start_slot, tip, &mut sink).await?;
+
+    println!("Indexed {} blocks ({}..={}) into {}", written, start_slot, tip, db_path);
+
+    Ok(())
+}
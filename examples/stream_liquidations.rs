@@ -0,0 +1,134 @@
+//! Streams the transactions of lending programs over the PubSub WebSocket API and POSTs every
+//! obligation liquidation to webhooks, signed when a secret is set (see `spi_wrapper::notify`),
+//! until interrupted (Ctrl-C). The notifications queued are delivered before it exits.
+//!
+//! cargo run --example stream_liquidations --features cli,notify,lending -- \
+//!     --ws-url <ws url> --rpc-url <rpc urls> --webhook <url>
+//!
+//! Programs default to the token lending and Solend ones. `--replay` notifies the transactions
+//! of `getTransaction` responses saved as JSON files (e.g. the fixtures of `tests/fixtures`)
+//! instead of subscribing, to try rules and webhooks out offline. Flags taking a value can be set
+//! through the environment variables `--help` lists instead. Logging is configured through
+//! `RUST_LOG` and `SPI_LOG_FORMAT` (`pretty`, `compact` or `json`).
+
+mod common;
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use reqwest::Url;
+use serde_json::Value;
+use spi_wrapper::ingest::pool::{RpcPool, RpcPoolConfig};
+use spi_wrapper::ingest::websocket::{LogsSubscriber, LogsSubscriberConfig};
+use spi_wrapper::notify::{Notifier, NotifyingSink, Rule, RuleSet, WebhookConfig};
+use spi_wrapper::shutdown::ShutdownCoordinator;
+use spi_wrapper::sinks::dry_run::DryRunSink;
+use spi_wrapper::sinks::Sink;
+use spi_wrapper::{decode_transaction, ProcessorRegistry, RawTransaction};
+use structopt::StructOpt;
+
+const TOKEN_LENDING: &str = "LendZqTs8gn5CTSJU1jWKhKuVpjJGom45nnwPb2AMTi";
+const SOLEND: &str = "So1endDq2YkqhipRh3WViPa8hdiSpxWy6z3Z6tMCpAo";
+const LIQUIDATION: &str = "liquidate-obligation";
+
+#[derive(StructOpt)]
+struct Args {
+    /// The PubSub WebSocket endpoint.
+    #[structopt(long, env = "SPI_WS_URL", default_value = "wss://api.mainnet-beta.solana.com")]
+    ws_url: String,
+    /// The RPC endpoints the transactions are fetched from, separated by commas.
+    #[structopt(long, env = "SPI_RPC_URL", default_value = "https://api.mainnet-beta.solana.com")]
+    rpc_url: String,
+    /// The lending programs to follow. May be repeated.
+    #[structopt(long = "program")]
+    programs: Vec<String>,
+    /// Where liquidations are POSTed. May be repeated.
+    #[structopt(long = "webhook", required = true)]
+    webhooks: Vec<Url>,
+    /// The key the notifications are signed with.
+    #[structopt(long, env = "SPI_WEBHOOK_SECRET")]
+    secret: Option<String>,
+    /// How many liquidations are notified per minute, at most.
+    #[structopt(long, env = "SPI_MAX_PER_MINUTE")]
+    max_per_minute: Option<u32>,
+    /// Notifies the transactions saved in these files instead of subscribing.
+    #[structopt(long, parse(from_os_str))]
+    replay: Vec<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    common::init_tracing();
+    let mut args = Args::from_args();
+    if args.programs.is_empty() {
+        args.programs = vec![TOKEN_LENDING.to_string(), SOLEND.to_string()];
+    }
+
+    let rule = Rule {
+        name: "liquidations".to_string(),
+        // The programs followed are the lending ones already.
+        program: None,
+        function: Some(LIQUIDATION.to_string()),
+        conditions: Vec::new(),
+        accounts: HashSet::new(),
+        webhooks: args.webhooks.clone(),
+        max_per_minute: args.max_per_minute,
+    };
+    let notifier = Notifier::new(RuleSet::new(vec![rule]), WebhookConfig {
+        secret: args.secret.clone(),
+        ..WebhookConfig::default()
+    });
+    let decoded = DryRunSink::new();
+    let mut sink = NotifyingSink::new(decoded.clone(), notifier);
+    let registry = Arc::new(ProcessorRegistry::default());
+
+    let result = if args.replay.is_empty() {
+        let rpc = Arc::new(RpcPool::new(args.rpc_url.split(',').map(String::from).collect(),
+                                        RpcPoolConfig::default()));
+        let mut subscriber = LogsSubscriber::new(rpc, registry, LogsSubscriberConfig {
+            ws_url: args.ws_url.clone(),
+            program_ids: args.programs.clone(),
+            ..LogsSubscriberConfig::default()
+        });
+
+        let shutdown = ShutdownCoordinator::default();
+        shutdown.listen_for_signals()?;
+        let interrupted = shutdown.token();
+        tokio::select! {
+            result = subscriber.run(&mut sink) => result.map_err(Box::<dyn Error>::from),
+            _ = interrupted.cancelled() => Ok(()),
+        }
+    } else {
+        replay(&registry, &args.replay, &mut sink).await
+    };
+
+    // Delivers the notifications queued, or gives up on them.
+    sink.shutdown().await?;
+    result?;
+
+    let liquidations: u64 = decoded.summary().functions.values()
+        .filter_map(|functions| functions.get(LIQUIDATION))
+        .sum();
+    println!("{} liquidations decoded", liquidations);
+
+    Ok(())
+}
+
+/// Decodes the transactions saved at `paths` and writes them to `sink`.
+async fn replay<S: Sink>(
+    registry: &ProcessorRegistry,
+    paths: &[PathBuf],
+    sink: &mut S,
+) -> Result<(), Box<dyn Error>> {
+    for path in paths {
+        let confirmed: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let transaction = RawTransaction::from_confirmed_json(confirmed)?
+            .ok_or_else(|| format!("{} isn't a base64 encoded transaction", path.display()))?;
+        sink.write_transactions(&[decode_transaction(registry, &transaction)]).await?;
+    }
+
+    Ok(sink.flush().await?)
+}
@@ -0,0 +1,43 @@
+//! Dispatches arbitrary instructions through the default registry, none of which may panic.
+//!
+//! cargo +nightly fuzz run registry_dispatch
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use once_cell::sync::Lazy;
+use solana_sdk::instruction::CompiledInstruction;
+use spi_wrapper::{BuiltinProcessor, Instruction, Network, ProcessorRegistry};
+
+static REGISTRY: Lazy<ProcessorRegistry> = Lazy::new(ProcessorRegistry::default);
+static PROGRAM_IDS: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    BuiltinProcessor::all().into_iter().map(|(program_id, _)| program_id).collect()
+});
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    // Picks one of the registered programs, most inputs would miss them otherwise.
+    program: u8,
+    data: Vec<u8>,
+    // The data of the other top-level instructions, which the secp256k1 processor reads.
+    others: Vec<Vec<u8>>,
+}
+
+fuzz_target!(|input: Input| {
+    let program_id = PROGRAM_IDS[input.program as usize % PROGRAM_IDS.len()];
+    let top_level: Vec<_> = std::iter::once(input.data.clone())
+        .chain(input.others)
+        .map(|data| CompiledInstruction { program_id_index: 0, accounts: vec![], data })
+        .collect();
+
+    REGISTRY.decode(Instruction {
+        tx_instruction_id: 0,
+        transaction_hash: "signature".into(),
+        program: program_id.into(),
+        data: input.data,
+        parent_index: -1,
+        timestamp: 0,
+        network: Network::Mainnet,
+    }, Some(&top_level));
+});
@@ -0,0 +1,49 @@
+//! A processor plugin decoding a toy counter program, whose instructions are a one byte tag
+//! followed by a little-endian u64 for `increment` and `decrement`.
+//!
+//! Build it with `cargo build -p spi-example-processor` and list the library in the `plugins` of
+//! the `[programs]` section of the configuration.
+
+use std::convert::TryInto;
+
+use spi_wrapper::plugin::{DecodedInstruction, DecodedProperty, PluginProcessor};
+
+pub const PROGRAM_ADDRESS: &str = "Counter111111111111111111111111111111111111";
+
+#[derive(Default)]
+pub struct CounterProcessor;
+
+impl PluginProcessor for CounterProcessor {
+    fn name(&self) -> &str {
+        "counter"
+    }
+
+    fn program_ids(&self) -> Vec<String> {
+        vec![PROGRAM_ADDRESS.to_string()]
+    }
+
+    fn decode(&self, data: &[u8]) -> Option<DecodedInstruction> {
+        let (tag, rest) = data.split_first()?;
+        let function_name = match tag {
+            0 => return Some(DecodedInstruction {
+                function_name: "initialize".to_string(),
+                properties: Vec::new(),
+            }),
+            1 => "increment",
+            2 => "decrement",
+            _ => return None,
+        };
+        let amount = u64::from_le_bytes(rest.try_into().ok()?);
+
+        Some(DecodedInstruction {
+            function_name: function_name.to_string(),
+            properties: vec![DecodedProperty {
+                key: "amount".to_string(),
+                value: amount.to_string(),
+                parent_key: String::new(),
+            }],
+        })
+    }
+}
+
+spi_wrapper::declare_processor!(CounterProcessor);
@@ -0,0 +1,41 @@
+use serde::{Serialize, Deserialize};
+use solana_sdk::pubkey::Pubkey;
+
+/// An account write observed on-chain, e.g. through a Geyser `update_account` callback.
+#[derive(Clone)]
+pub struct AccountUpdate {
+    pub pubkey: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub executable: bool,
+    pub data: Vec<u8>,
+    // The slot the write happened in.
+    pub slot: u64,
+    // Orders writes to the same account within a slot.
+    pub write_version: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AccountRecord {
+    pub pubkey: String,
+    pub owner: String,
+    pub lamports: u64,
+    pub executable: bool,
+    // The size of the account data; the data itself is not indexed.
+    pub data_len: u64,
+    pub slot: u64,
+    pub write_version: u64,
+}
+
+/// Turns an account write into the record persisted by the sinks.
+pub fn process_account_update(update: &AccountUpdate) -> AccountRecord {
+    AccountRecord {
+        pubkey: update.pubkey.to_string(),
+        owner: update.owner.to_string(),
+        lamports: update.lamports,
+        executable: update.executable,
+        data_len: update.data.len() as u64,
+        slot: update.slot,
+        write_version: update.write_version,
+    }
+}
@@ -0,0 +1,607 @@
+//! Rollups of instruction activity over tumbling windows of time.
+//!
+//! The [`Aggregator`] counts decoded instructions per program and function in windows of a fixed
+//! size, summing the numeric properties it's configured with (e.g. `liquidity_amount`). Windows
+//! are closed by the watermark, the latest timestamp seen: once it's past the end of a window
+//! plus the allowed lateness, the window is complete and handed to the sink. Instructions for a
+//! window that's already closed are counted in a separate, late aggregate of that window.
+//!
+//! Blocks delivered out of order can push the watermark past windows the blocks before them still
+//! had instructions for. With `reorder_slots` set, transactions are held until the latest slot
+//! seen is past theirs by as many slots (see [`ReorderingBuffer`]), and counted in slot order.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::account::AccountRecord;
+use crate::block::BlockRecord;
+use crate::checkpoint::Checkpoint;
+use crate::ingest::timestamps::TimestampUpdate;
+use crate::interner::Symbol;
+use crate::lending::positions::PositionSnapshot;
+use crate::network::Network;
+use crate::ordering::{ReorderingBuffer, DEFAULT_MAX_PENDING};
+use crate::program_versions::ProgramUpgrade;
+use crate::reindex::{StaleFilter, StaleTransaction};
+use crate::reprocess::{RawFilter, RawInstruction};
+use crate::sinks::{Sink, SinkError};
+use crate::transaction::TransactionRecord;
+use crate::InstructionSet;
+
+#[derive(Clone, Debug)]
+pub struct AggregatorConfig {
+    // The size of the windows.
+    pub window: Duration,
+    // How long after its end a window still takes records.
+    pub allowed_lateness: Duration,
+    // The (top level) properties summed, when they're numbers.
+    pub sums: Vec<Symbol>,
+    // How many slots past a slot the latest slot seen has to be for its transactions to be
+    // counted, if they're put back in slot order.
+    pub reorder_slots: Option<u64>,
+    // How many transactions are held at most, past which the oldest slots are counted early.
+    pub max_pending: usize,
+}
+
+impl Default for AggregatorConfig {
+    fn default() -> Self {
+        AggregatorConfig {
+            window: Duration::from_secs(60 * 60),
+            allowed_lateness: Duration::from_secs(5 * 60),
+            sums: Vec::new(),
+            reorder_slots: None,
+            max_pending: DEFAULT_MAX_PENDING,
+        }
+    }
+}
+
+/// The activity of one function of a program of a network over a window.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct WindowAggregate {
+    // Unix timestamps, the end is excluded.
+    pub window_start: i64,
+    pub window_end: i64,
+    pub network: Network,
+    pub program: Symbol,
+    pub function_name: Symbol,
+    // Whether these are the instructions that arrived after the window was closed.
+    pub late: bool,
+    pub count: u64,
+    // By property key.
+    pub sums: BTreeMap<String, u128>,
+}
+
+impl WindowAggregate {
+    /// Adds the counts of `other`, an aggregate of the same window and function.
+    pub fn merge(&mut self, other: &WindowAggregate) {
+        self.count += other.count;
+        for (key, sum) in &other.sums {
+            *self.sums.entry(key.clone()).or_default() += sum;
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    count: u64,
+    sums: BTreeMap<String, u128>,
+}
+
+type Key = (Network, Symbol, Symbol);
+
+/// Tumbling-window counters of the instructions it's fed.
+pub struct Aggregator {
+    config: AggregatorConfig,
+    window_secs: i64,
+    lateness_secs: i64,
+    // The open windows, by start.
+    windows: BTreeMap<i64, HashMap<Key, Counters>>,
+    // What arrived for closed windows since the last drain, by window start.
+    late: BTreeMap<i64, HashMap<Key, Counters>>,
+    watermark: Option<i64>,
+    late_instructions: u64,
+    // The instructions of the transactions not counted yet, if they're put back in slot order.
+    pending: Option<ReorderingBuffer<Vec<InstructionSet>>>,
+}
+
+impl Aggregator {
+    pub fn new(config: AggregatorConfig) -> Self {
+        let window_secs = (config.window.as_secs() as i64).max(1);
+        let lateness_secs = config.allowed_lateness.as_secs() as i64;
+        let pending = config.reorder_slots
+            .map(|slots| ReorderingBuffer::new("aggregate", slots, config.max_pending));
+
+        Aggregator {
+            config,
+            window_secs,
+            lateness_secs,
+            windows: BTreeMap::new(),
+            late: BTreeMap::new(),
+            watermark: None,
+            late_instructions: 0,
+            pending,
+        }
+    }
+
+    /// The latest timestamp seen.
+    pub fn watermark(&self) -> Option<i64> {
+        self.watermark
+    }
+
+    /// How many instructions arrived after their window was closed, since the start.
+    pub fn late_instructions(&self) -> u64 {
+        self.late_instructions
+    }
+
+    /// How many transactions arrived after their slot was counted, if they're put back in slot
+    /// order, since the start.
+    pub fn late_transactions(&self) -> u64 {
+        self.pending.as_ref().map_or(0, ReorderingBuffer::late_records)
+    }
+
+    /// Counts every decoded instruction of a transaction, once its slot is ready if they're put
+    /// back in slot order. The instructions of failed transactions didn't happen, they're left
+    /// out.
+    pub fn observe_transaction(&mut self, transaction: &TransactionRecord) {
+        if let Some(pending) = &mut self.pending {
+            pending.advance(transaction.slot);
+        }
+        if !transaction.succeeded || transaction.instruction_sets.is_empty() {
+            return;
+        }
+        if let Some(pending) = &mut self.pending {
+            pending.push(transaction.slot, transaction.instruction_sets.clone());
+            return;
+        }
+        for instruction_set in &transaction.instruction_sets {
+            self.observe(instruction_set);
+        }
+    }
+
+    /// Drops the transactions of the slots at or after `slot` that weren't counted yet, their
+    /// blocks having been replaced by a fork.
+    pub fn rollback_from_slot(&mut self, slot: u64) {
+        if let Some(pending) = &mut self.pending {
+            pending.rollback_from_slot(slot);
+        }
+    }
+
+    /// Drops the transactions of the given slots that weren't counted yet, their blocks having
+    /// been abandoned.
+    pub fn invalidate(&mut self, slots: &[u64]) {
+        if let Some(pending) = &mut self.pending {
+            pending.invalidate(slots);
+        }
+    }
+
+    /// Counts the instructions of the slots released by the buffer putting them in order.
+    fn count(&mut self, slots: Vec<(u64, Vec<Vec<InstructionSet>>)>) {
+        for instruction_set in slots.iter().flat_map(|(_, transactions)| transactions).flatten() {
+            self.observe(instruction_set);
+        }
+    }
+
+    /// Counts an instruction in the window of its timestamp.
+    pub fn observe(&mut self, instruction_set: &InstructionSet) {
+        let timestamp = instruction_set.function.timestamp;
+        let window_start = timestamp - timestamp.rem_euclid(self.window_secs);
+        let buckets = if self.is_closed(window_start) {
+            self.late_instructions += 1;
+            &mut self.late
+        } else {
+            self.watermark = Some(self.watermark.map_or(timestamp, |mark| mark.max(timestamp)));
+            &mut self.windows
+        };
+
+        let function = &instruction_set.function;
+        let counters = buckets.entry(window_start).or_default()
+            .entry((function.network, function.program, function.function_name))
+            .or_default();
+        counters.count += 1;
+        for property in &instruction_set.properties {
+            if !property.parent_key.is_empty() || !self.config.sums.contains(&property.key) {
+                continue;
+            }
+            if let Ok(value) = property.value.parse::<u128>() {
+                let sum = counters.sums.entry(property.key.to_string()).or_default();
+                *sum = sum.saturating_add(value);
+            }
+        }
+    }
+
+    fn is_closed(&self, window_start: i64) -> bool {
+        self.watermark
+            .map_or(false, |mark| window_start + self.window_secs + self.lateness_secs <= mark)
+    }
+
+    /// Takes the windows closed by the watermark along with the late instructions seen since the
+    /// last call, oldest first.
+    pub fn drain_closed(&mut self) -> Vec<WindowAggregate> {
+        if let Some(ready) = self.pending.as_mut().map(ReorderingBuffer::drain_ready) {
+            self.count(ready);
+        }
+        let open = match self.watermark {
+            // The first window that isn't closed yet.
+            Some(mark) => {
+                let closed_before = mark - self.window_secs - self.lateness_secs;
+                closed_before - closed_before.rem_euclid(self.window_secs) + self.window_secs
+            }
+            None => return Vec::new(),
+        };
+        let still_open = self.windows.split_off(&open);
+        let closed = std::mem::replace(&mut self.windows, still_open);
+
+        let mut aggregates = self.aggregates(closed, false);
+        let late = std::mem::take(&mut self.late);
+        aggregates.extend(self.aggregates(late, true));
+
+        aggregates
+    }
+
+    /// Takes every window, open or not, e.g. before shutting down.
+    pub fn drain(&mut self) -> Vec<WindowAggregate> {
+        if let Some(pending) = self.pending.as_mut().map(ReorderingBuffer::drain) {
+            self.count(pending);
+        }
+        let windows = std::mem::take(&mut self.windows);
+        let mut aggregates = self.aggregates(windows, false);
+        let late = std::mem::take(&mut self.late);
+        aggregates.extend(self.aggregates(late, true));
+
+        aggregates
+    }
+
+    fn aggregates(
+        &self,
+        windows: BTreeMap<i64, HashMap<Key, Counters>>,
+        late: bool,
+    ) -> Vec<WindowAggregate> {
+        let mut aggregates = Vec::new();
+        for (window_start, functions) in windows {
+            let mut functions: Vec<_> = functions.into_iter().collect();
+            functions.sort_by_key(|((network, program, function_name), _)| {
+                (network.as_str(), program.as_str(), function_name.as_str())
+            });
+            aggregates.extend(functions.into_iter().map(|(key, counters)| {
+                let (network, program, function_name) = key;
+                WindowAggregate {
+                    window_start,
+                    window_end: window_start + self.window_secs,
+                    network,
+                    program,
+                    function_name,
+                    late,
+                    count: counters.count,
+                    sums: counters.sums,
+                }
+            }));
+        }
+
+        aggregates
+    }
+}
+
+/// Feeds the transactions written to the wrapped sink to an [`Aggregator`], writing the windows
+/// it closes along with them. The open windows are written on shutdown.
+///
+/// Aggregates aren't rolled back with the slots they were counted from, only the transactions of
+/// the slots held to be put in order are dropped.
+pub struct AggregatingSink<S> {
+    inner: S,
+    aggregator: Aggregator,
+}
+
+impl<S: Sink> AggregatingSink<S> {
+    pub fn new(inner: S, config: AggregatorConfig) -> Self {
+        AggregatingSink { inner, aggregator: Aggregator::new(config) }
+    }
+
+    pub fn aggregator(&self) -> &Aggregator {
+        &self.aggregator
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    async fn aggregate(&mut self, transactions: &[TransactionRecord]) -> Result<(), SinkError> {
+        for transaction in transactions {
+            self.aggregator.observe_transaction(transaction);
+        }
+        let aggregates = self.aggregator.drain_closed();
+        if aggregates.is_empty() {
+            return Ok(());
+        }
+
+        crate::sinks::write_aggregates(&mut self.inner, &aggregates).await
+    }
+}
+
+#[async_trait]
+impl<S: Sink> Sink for AggregatingSink<S> {
+    async fn write_block(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        self.inner.write_block(block).await?;
+        self.aggregate(&block.transactions).await
+    }
+
+    async fn write_block_header(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        self.inner.write_block_header(block).await
+    }
+
+    async fn write_transactions(
+        &mut self,
+        transactions: &[TransactionRecord],
+    ) -> Result<(), SinkError> {
+        self.inner.write_transactions(transactions).await?;
+        self.aggregate(transactions).await
+    }
+
+    async fn write_accounts(&mut self, accounts: &[AccountRecord]) -> Result<(), SinkError> {
+        self.inner.write_accounts(accounts).await
+    }
+
+    async fn write_aggregates(&mut self, aggregates: &[WindowAggregate]) -> Result<(), SinkError> {
+        self.inner.write_aggregates(aggregates).await
+    }
+
+    async fn write_positions(&mut self, snapshots: &[PositionSnapshot]) -> Result<(), SinkError> {
+        self.inner.write_positions(snapshots).await
+    }
+
+    async fn latest_positions(&mut self) -> Result<Vec<PositionSnapshot>, SinkError> {
+        self.inner.latest_positions().await
+    }
+
+    async fn write_program_upgrades(
+        &mut self,
+        upgrades: &[ProgramUpgrade],
+    ) -> Result<(), SinkError> {
+        self.inner.write_program_upgrades(upgrades).await
+    }
+
+    async fn program_upgrades(&mut self) -> Result<Vec<ProgramUpgrade>, SinkError> {
+        self.inner.program_upgrades().await
+    }
+
+    async fn mark_finalized(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        self.inner.mark_finalized(slots).await
+    }
+
+    async fn invalidate(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        self.inner.invalidate(slots).await?;
+        self.aggregator.invalidate(slots);
+
+        Ok(())
+    }
+
+    async fn rollback_from_slot(&mut self, slot: u64) -> Result<(), SinkError> {
+        self.inner.rollback_from_slot(slot).await?;
+        self.aggregator.rollback_from_slot(slot);
+
+        Ok(())
+    }
+
+    async fn has_transaction(&mut self, signature: &str) -> Result<Option<bool>, SinkError> {
+        self.inner.has_transaction(signature).await
+    }
+
+    async fn raw_instructions(
+        &mut self,
+        filter: &RawFilter,
+    ) -> Result<Vec<RawInstruction>, SinkError> {
+        self.inner.raw_instructions(filter).await
+    }
+
+    async fn write_reprocessed(
+        &mut self,
+        instruction_sets: &[InstructionSet],
+    ) -> Result<(), SinkError> {
+        self.inner.write_reprocessed(instruction_sets).await
+    }
+
+    async fn stale_transactions(
+        &mut self,
+        filter: &StaleFilter,
+    ) -> Result<Vec<StaleTransaction>, SinkError> {
+        self.inner.stale_transactions(filter).await
+    }
+
+    async fn estimated_timestamps(
+        &mut self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, i64)>, SinkError> {
+        self.inner.estimated_timestamps(after, limit).await
+    }
+
+    async fn update_timestamps(&mut self, updates: &[TimestampUpdate]) -> Result<(), SinkError> {
+        self.inner.update_timestamps(updates).await
+    }
+
+    fn supports_transactions(&self) -> bool {
+        self.inner.supports_transactions()
+    }
+
+    async fn begin(&mut self) -> Result<(), SinkError> {
+        self.inner.begin().await
+    }
+
+    async fn commit(
+        &mut self,
+        pipeline: &str,
+        checkpoint: Option<&Checkpoint>,
+    ) -> Result<(), SinkError> {
+        self.inner.commit(pipeline, checkpoint).await
+    }
+
+    async fn committed_checkpoint(
+        &mut self,
+        pipeline: &str,
+    ) -> Result<Option<Checkpoint>, SinkError> {
+        self.inner.committed_checkpoint(pipeline).await
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        self.inner.flush().await
+    }
+
+    async fn shutdown(&mut self) -> Result<(), SinkError> {
+        let aggregates = self.aggregator.drain();
+        if !aggregates.is_empty() {
+            crate::sinks::write_aggregates(&mut self.inner, &aggregates).await?;
+        }
+
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use smallvec::smallvec;
+
+    use super::*;
+    use crate::call_tree::CallTree;
+    use crate::{InstructionFunction, InstructionProperty, Network, PropertyType};
+
+    fn instruction(function_name: &str, timestamp: i64, amount: u64) -> InstructionSet {
+        let transaction_hash: Arc<str> = Arc::from("signature");
+        InstructionSet {
+            function: InstructionFunction {
+                tx_instruction_id: 0,
+                transaction_hash: transaction_hash.clone(),
+                parent_index: -1,
+                program: "lending".into(),
+                function_name: function_name.into(),
+                timestamp,
+                network: Network::Mainnet,
+                decoder_version: 0,
+            },
+            properties: smallvec![InstructionProperty {
+                tx_instruction_id: 0,
+                transaction_hash,
+                parent_index: -1,
+                key: "liquidity_amount".into(),
+                value: amount.to_string(),
+                value_type: PropertyType::U64,
+                parent_key: "".into(),
+                timestamp,
+            }],
+            raw_data: None,
+        }
+    }
+
+    fn aggregator() -> Aggregator {
+        Aggregator::new(AggregatorConfig {
+            window: Duration::from_secs(60),
+            allowed_lateness: Duration::from_secs(30),
+            sums: vec!["liquidity_amount".into()],
+            ..AggregatorConfig::default()
+        })
+    }
+
+    fn counts(aggregates: &[WindowAggregate]) -> Vec<(i64, &str, bool, u64, u128)> {
+        aggregates.iter()
+            .map(|aggregate| (aggregate.window_start, aggregate.function_name.as_str(),
+                aggregate.late, aggregate.count, aggregate.sums["liquidity_amount"]))
+            .collect()
+    }
+
+    #[test]
+    fn out_of_order_records_within_the_lateness_update_their_window() {
+        let mut aggregator = aggregator();
+        aggregator.observe(&instruction("deposit", 10, 1));
+        aggregator.observe(&instruction("borrow", 70, 2));
+        aggregator.observe(&instruction("deposit", 59, 3));
+        // 130 closes the first window (which ends at 60, plus 30 of lateness) but not the second.
+        aggregator.observe(&instruction("deposit", 130, 5));
+        aggregator.observe(&instruction("borrow", 61, 4));
+        aggregator.observe(&instruction("deposit", 140, 7));
+
+        assert_eq!(counts(&aggregator.drain_closed()), vec![(0, "deposit", false, 2, 4)]);
+        assert_eq!(aggregator.late_instructions(), 0);
+        assert_eq!(counts(&aggregator.drain()), vec![
+            (60, "borrow", false, 2, 6),
+            (120, "deposit", false, 2, 12),
+        ]);
+    }
+
+    #[test]
+    fn records_beyond_the_lateness_are_counted_separately() {
+        let mut aggregator = aggregator();
+        aggregator.observe(&instruction("deposit", 20, 1));
+        aggregator.observe(&instruction("deposit", 200, 2));
+        assert_eq!(counts(&aggregator.drain_closed()), vec![(0, "deposit", false, 1, 1)]);
+
+        aggregator.observe(&instruction("deposit", 30, 10));
+        aggregator.observe(&instruction("deposit", 100, 20));
+        aggregator.observe(&instruction("deposit", 40, 30));
+        assert_eq!(aggregator.late_instructions(), 3);
+        assert_eq!(aggregator.watermark(), Some(200));
+        assert_eq!(counts(&aggregator.drain_closed()), vec![
+            (0, "deposit", true, 2, 40),
+            (60, "deposit", true, 1, 20),
+        ]);
+        assert!(aggregator.drain_closed().is_empty());
+    }
+
+    #[test]
+    fn slots_delivered_out_of_order_are_counted_as_delivered_in_order() {
+        use rand::seq::SliceRandom;
+        use rand::SeedableRng;
+
+        // A transaction every slot, 20 seconds apart.
+        let transactions: Vec<_> = (0..12u64)
+            .map(|n| {
+                let function_name = if n % 3 == 0 { "borrow" } else { "deposit" };
+                TransactionRecord {
+                    transaction_hash: format!("signature-{}", n),
+                    slot: 100 + n,
+                    fee: 5000,
+                    base_fee_lamports: None,
+                    priority_fee_lamports: None,
+                    compute_unit_price_micro_lamports: None,
+                    succeeded: true,
+                    timestamp: n as i64 * 20,
+                    timestamp_estimated: false,
+                    finalized: false,
+                    network: Network::Mainnet,
+                    instruction_sets: vec![instruction(function_name, n as i64 * 20, n)],
+                    decode_failures: Vec::new(),
+                    call_tree: CallTree::default(),
+                    signer_activity: Vec::new(),
+                    account_lifecycle: Vec::new(),
+                    confirmed: true,
+                    simulation: None,
+                }
+            })
+            .collect();
+        let run = |aggregator: &mut Aggregator, order: &[usize]| {
+            let mut aggregates = Vec::new();
+            for index in order {
+                aggregator.observe_transaction(&transactions[*index]);
+                aggregates.extend(aggregator.drain_closed());
+            }
+            aggregates.extend(aggregator.drain());
+            aggregates
+        };
+
+        let mut order: Vec<_> = (0..transactions.len()).collect();
+        let in_order = run(&mut aggregator(), &order);
+        assert!(in_order.iter().all(|aggregate| !aggregate.late));
+        let mut rng = rand::rngs::StdRng::seed_from_u64(674);
+        for _ in 0..10 {
+            order.shuffle(&mut rng);
+            let mut shuffled = Aggregator::new(AggregatorConfig {
+                reorder_slots: Some(16),
+                ..aggregator().config
+            });
+            assert_eq!(run(&mut shuffled, &order), in_order, "delivered in order {:?}", order);
+            assert_eq!(shuffled.late_instructions(), 0);
+        }
+    }
+}
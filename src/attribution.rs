@@ -0,0 +1,60 @@
+//! Attributing decoded instructions to the wallets that signed their transaction, so that what a
+//! wallet signed can be looked up without going through the properties of every instruction.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::interner::Symbol;
+use crate::network::Network;
+use crate::transaction::RawTransaction;
+use crate::InstructionSet;
+
+/// A decoded instruction of a transaction one of its signers signed: every signer of a
+/// transaction gets one for every instruction decoded in it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignerActivity {
+    pub signer: String,
+    // Whether the signer paid the fee of the transaction, which the first signer does.
+    pub fee_payer: bool,
+    pub program: Symbol,
+    pub function_name: Symbol,
+    pub transaction_hash: Arc<str>,
+    // The index of the instruction, within its parent for inner instructions.
+    pub instruction_index: i16,
+    pub parent_index: i16,
+    pub slot: u64,
+    pub timestamp: i64,
+    #[serde(default)]
+    pub network: Network,
+}
+
+/// The activity of the signers of `transaction` that `instruction_sets` (decoded out of it) make
+/// up, signer by signer, the fee payer first.
+pub fn attribute(
+    transaction: &RawTransaction,
+    instruction_sets: &[InstructionSet],
+) -> Vec<SignerActivity> {
+    let signers = transaction.signers();
+    let mut activity = Vec::with_capacity(signers.len() * instruction_sets.len());
+    for (position, signer) in signers.iter().enumerate() {
+        let signer = signer.to_string();
+        activity.extend(instruction_sets.iter().map(|instruction_set| {
+            let function = &instruction_set.function;
+            SignerActivity {
+                signer: signer.clone(),
+                fee_payer: position == 0,
+                program: function.program,
+                function_name: function.function_name,
+                transaction_hash: function.transaction_hash.clone(),
+                instruction_index: function.tx_instruction_id,
+                parent_index: function.parent_index,
+                slot: transaction.slot,
+                timestamp: function.timestamp,
+                network: function.network,
+            }
+        }));
+    }
+
+    activity
+}
@@ -0,0 +1,137 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use solana_sdk::pubkey::Pubkey;
+use spi_wrapper::checkpoint::Checkpoint;
+use spi_wrapper::config::IndexerConfig;
+use spi_wrapper::ingest::rpc::{BlockPoller, BlockPollerConfig};
+use spi_wrapper::sinks::dry_run::DryRunSink;
+use spi_wrapper::sinks::Sink;
+use structopt::StructOpt;
+
+// How many slots are indexed (and checkpointed) at a time.
+const CHUNK_SLOTS: u64 = 100;
+
+#[derive(StructOpt)]
+pub struct Backfill {
+    /// The configuration file describing the RPC endpoints, programs, sink and checkpoint store.
+    #[structopt(long, default_value = "indexer.toml", parse(from_os_str))]
+    config: PathBuf,
+    /// The cluster to index, over the one of the configuration, whose deployments of the
+    /// programs are decoded.
+    #[structopt(long)]
+    network: Option<String>,
+    /// Only keep the transactions invoking one of these programs, instead of the ones the
+    /// configuration filters on. May be repeated.
+    #[structopt(long = "program")]
+    programs: Vec<Pubkey>,
+    /// The first slot to index.
+    #[structopt(long)]
+    from_slot: u64,
+    /// The last slot to index.
+    #[structopt(long)]
+    to_slot: u64,
+    /// Resume from the last slot checkpointed by a previous backfill.
+    #[structopt(long)]
+    resume: bool,
+    /// Decode and filter as usual, but write nothing (checkpoints included) and print a summary
+    /// of what would have been written instead.
+    #[structopt(long)]
+    dry_run: bool,
+    /// Print the dry run summary as JSON.
+    #[structopt(long, requires = "dry-run")]
+    json: bool,
+}
+
+pub async fn run(args: Backfill) -> Result<i32, Box<dyn Error>> {
+    if args.from_slot > args.to_slot {
+        return Err("--from-slot is after --to-slot".into());
+    }
+
+    if args.dry_run && args.resume {
+        return Err("--resume can't be used with --dry-run, which doesn't checkpoint".into());
+    }
+
+    let mut config = IndexerConfig::from_path(&args.config)?;
+    if let Some(network) = &args.network {
+        config.rpc.network = Some(network.clone());
+    }
+    if !args.programs.is_empty() {
+        config.filters.programs = args.programs.iter().map(Pubkey::to_string).collect();
+    }
+    let dry_run = DryRunSink::new();
+    let mut indexer = if args.dry_run {
+        config.checkpoint = None;
+        config.build_pipeline_with_sink(Box::new(dry_run.clone())).await?
+    } else {
+        config.build_pipeline().await?
+    };
+    for program in &args.programs {
+        if indexer.registry.processor(&program.to_string()).is_none() {
+            return Err(format!("no enabled processor decodes {}", program).into());
+        }
+    }
+
+    // Backfills checkpoint apart from the configured source, so both can share a store.
+    let checkpoint = config.checkpointing().await?.map(|mut checkpoint| {
+        checkpoint.pipeline.push_str("-backfill");
+        checkpoint
+    });
+    let mut start_slot = args.from_slot;
+    if args.resume {
+        let checkpoint = checkpoint.as_ref()
+            .ok_or("--resume needs a [checkpoint] section in the configuration")?;
+        if let Some(last_slot) = checkpoint.load().await?.and_then(|saved| saved.last_slot) {
+            start_slot = start_slot.max(last_slot + 1);
+        }
+    }
+
+    let defaults = BlockPollerConfig::default();
+    let poller = BlockPoller::new(indexer.rpc.clone(), indexer.registry.clone(), BlockPollerConfig {
+        commitment: config.commitment()?,
+        concurrency: config.pipeline.concurrency.unwrap_or(defaults.concurrency),
+        filters: config.decode_filters()?,
+        ..defaults
+    });
+
+    let progress = ProgressBar::new(args.to_slot - args.from_slot + 1);
+    progress.set_style(ProgressStyle::default_bar()
+        .template("{bar:40} {pos}/{len} slots ({per_sec}, ETA {eta}) {msg}"));
+    progress.set_position(start_slot - args.from_slot);
+    progress.reset_eta();
+
+    let mut blocks = 0;
+    let mut next_slot = start_slot;
+    while next_slot <= args.to_slot {
+        let end_slot = args.to_slot.min(next_slot + CHUNK_SLOTS - 1);
+        // A range older than anything indexed, so it isn't checked against the chain.
+        blocks += poller.repair_range(next_slot, end_slot, &mut indexer.sink).await?;
+        // `repair_range` flushed the sink, so everything up to `end_slot` is safe.
+        if let Some(checkpoint) = &checkpoint {
+            checkpoint.save(&Checkpoint::new(Some(end_slot), None)).await?;
+        }
+
+        progress.set_position(end_slot - args.from_slot + 1);
+        progress.set_message(format!("{} blocks", blocks));
+        next_slot = end_slot + 1;
+    }
+
+    indexer.sink.flush().await?;
+    progress.finish_with_message(format!("{} blocks indexed", blocks));
+
+    if args.dry_run {
+        let summary = dry_run.summary();
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+        } else {
+            print!("{}", summary);
+        }
+        // Same as `decode-tx`: some instruction failed to decode.
+        if summary.failure_count() > 0 {
+            return Ok(2);
+        }
+    }
+
+    Ok(0)
+}
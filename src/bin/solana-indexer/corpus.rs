@@ -0,0 +1,131 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use spi_wrapper::config::IndexerConfig;
+use spi_wrapper::corpus::{self, CheckReport, Corpus as CorpusFile, CorpusConfig};
+use spi_wrapper::ingest::pool::RpcPool;
+use spi_wrapper::ProcessorRegistry;
+use structopt::StructOpt;
+
+/// The exit code when a discriminant decodes less than in the previous run.
+const REGRESSED: i32 = 2;
+
+#[derive(StructOpt)]
+pub struct Corpus {
+    #[structopt(subcommand)]
+    command: CorpusCommand,
+}
+
+#[derive(StructOpt)]
+enum CorpusCommand {
+    /// Samples the instructions of a program out of its recent transactions into a corpus file.
+    Collect {
+        /// The program whose instructions are sampled.
+        #[structopt(long)]
+        program: Pubkey,
+        /// How many recent transactions are sampled.
+        #[structopt(long, default_value = "1000")]
+        sample: usize,
+        /// How many bytes of the data instructions are grouped by (8 for Anchor programs).
+        #[structopt(long, default_value = "1")]
+        discriminant_len: usize,
+        /// How many distinct instructions are kept of every discriminant.
+        #[structopt(long, default_value = "100")]
+        per_discriminant: usize,
+        /// Where the corpus is written.
+        #[structopt(long, parse(from_os_str))]
+        out: PathBuf,
+        /// The RPC endpoint to sample the transactions from.
+        #[structopt(long, default_value = "https://api.mainnet-beta.solana.com")]
+        url: String,
+    },
+    /// Decodes every instruction of a corpus, reporting how many decoded by discriminant.
+    Check {
+        /// The corpus file to decode.
+        #[structopt(long, parse(from_os_str))]
+        corpus: PathBuf,
+        /// The report of a previous run, to compare the rates with.
+        #[structopt(long, parse(from_os_str))]
+        previous: Option<PathBuf>,
+        /// Where the report of this run is written, as JSON.
+        #[structopt(long, parse(from_os_str))]
+        report: Option<PathBuf>,
+        /// The configuration file to take the processors from, every built-in one otherwise.
+        #[structopt(long, parse(from_os_str))]
+        config: Option<PathBuf>,
+    },
+}
+
+/// Collects a corpus, or checks one, returning `REGRESSED` when a discriminant decodes less than
+/// in the previous report.
+pub async fn run(args: Corpus) -> Result<i32, Box<dyn Error>> {
+    match args.command {
+        CorpusCommand::Collect {
+            program, sample, discriminant_len, per_discriminant, out, url,
+        } => {
+            if discriminant_len == 0 || per_discriminant == 0 {
+                return Err("--discriminant-len and --per-discriminant can't be 0".into());
+            }
+            let rpc = Arc::new(RpcPool::single(url));
+            let config = CorpusConfig { discriminant_len, per_discriminant };
+            let corpus = corpus::collect(&rpc, &program, sample, config,
+                                         CommitmentConfig::confirmed()).await?;
+            corpus.write(&out)?;
+
+            let discriminants = corpus.entries.iter()
+                .map(|entry| &entry.discriminant)
+                .collect::<std::collections::BTreeSet<_>>()
+                .len();
+            println!("{} instructions of {} discriminants written to {}", corpus.entries.len(),
+                     discriminants, out.display());
+            Ok(0)
+        }
+        CorpusCommand::Check { corpus, previous, report, config } => {
+            let registry = match config {
+                Some(path) => {
+                    let config = IndexerConfig::from_path(&path)?;
+                    config.validate()?;
+                    config.registry()?
+                }
+                None => ProcessorRegistry::default(),
+            };
+            let corpus = CorpusFile::read(&corpus)?;
+            let result = corpus::check(&registry, &corpus);
+
+            for (discriminant, stats) in &result.discriminants {
+                let functions: Vec<_> = stats.functions.iter().map(String::as_str).collect();
+                println!("{:<18} {:>6}/{:<6} {:>6.1}%  {}", discriminant, stats.decoded,
+                         stats.entries, stats.rate() * 100.0, functions.join(", "));
+            }
+            let (entries, decoded) = result.totals();
+            println!("{}: {} of {} instructions decoded", result.program, decoded, entries);
+            if let Some(path) = &report {
+                fs::write(path, serde_json::to_string_pretty(&result)?)?;
+            }
+
+            let previous = match previous {
+                Some(path) => {
+                    let contents = fs::read_to_string(&path)
+                        .map_err(|err| format!("unable to read {}: {}", path.display(), err))?;
+                    serde_json::from_str::<CheckReport>(&contents)?
+                }
+                None => return Ok(0),
+            };
+            let changes = result.diff(&previous);
+            let percent = |rate: Option<f64>| {
+                rate.map_or_else(|| "-".to_string(), |rate| format!("{:.1}%", rate * 100.0))
+            };
+            for change in &changes {
+                let kind = if change.is_regression() { "REGRESSED" } else { "changed" };
+                println!("{} {}: {} -> {}", kind, change.discriminant, percent(change.before),
+                         percent(change.after));
+            }
+
+            Ok(if changes.iter().any(|change| change.is_regression()) { REGRESSED } else { 0 })
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use serde::Serialize;
+use solana_sdk::commitment_config::CommitmentConfig;
+use spi_wrapper::ingest::fetch_transaction;
+use spi_wrapper::ingest::pool::RpcPool;
+use spi_wrapper::{CallTree, Instruction, Network, ProcessorRegistry, Symbol};
+use structopt::StructOpt;
+
+/// The exit code when at least one instruction of a supported program couldn't be decoded.
+const DECODE_FAILED: i32 = 2;
+
+#[derive(StructOpt)]
+pub struct DecodeTx {
+    /// The signature of the transaction.
+    signature: String,
+    /// The RPC endpoint to fetch the transaction from, the public one of the network by default.
+    #[structopt(long)]
+    url: Option<String>,
+    /// The cluster the transaction is from, whose deployments of the programs are decoded.
+    #[structopt(long, default_value = "mainnet")]
+    network: Network,
+    /// How to print the instructions.
+    #[structopt(long, default_value = "table", possible_values = &["json", "table"])]
+    format: String,
+}
+
+#[derive(Serialize)]
+struct DecodedTransaction {
+    signature: String,
+    slot: u64,
+    succeeded: bool,
+    instructions: Vec<DecodedInstruction>,
+}
+
+#[derive(Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum Status {
+    Decoded,
+    // No processor is registered for the program.
+    Unsupported,
+    Failed,
+}
+
+#[derive(Serialize)]
+struct DecodedInstruction {
+    index: i16,
+    // The index of the top-level instruction an inner instruction was invoked by, -1 for
+    // top-level instructions.
+    parent_index: i16,
+    // How deep the instruction was invoked, 1 for top-level instructions.
+    stack_height: u32,
+    // The position of the instruction at every level (see `CallTree`), e.g. `0/0.1`.
+    call_path: String,
+    program: Symbol,
+    // The processor the program is registered with.
+    processor: Option<String>,
+    status: Status,
+    function: Option<Symbol>,
+    properties: Vec<DecodedProperty>,
+}
+
+#[derive(Serialize)]
+struct DecodedProperty {
+    key: Symbol,
+    value: String,
+    parent_key: Symbol,
+}
+
+/// Prints the instructions of the transaction. Returns `DECODE_FAILED` when any of them couldn't
+/// be decoded, 0 otherwise.
+pub async fn run(args: DecodeTx) -> Result<i32, Box<dyn Error>> {
+    let url = args.url.clone()
+        .or_else(|| public_endpoint(args.network).map(String::from))
+        .ok_or("--url is needed for clusters other than mainnet, devnet and testnet")?;
+    let rpc = Arc::new(RpcPool::single(url));
+    let transaction = fetch_transaction(&rpc, &args.signature, CommitmentConfig::confirmed())
+        .await?
+        .ok_or("the transaction wasn't found or couldn't be decoded (versioned transactions \
+        aren't supported by the pinned Solana SDK)")?;
+
+    let registry = ProcessorRegistry::for_network(args.network);
+    let call_tree = CallTree::build(&transaction);
+    let decoded = DecodedTransaction {
+        signature: transaction.transaction_hash.clone(),
+        slot: transaction.slot,
+        succeeded: transaction.succeeded,
+        instructions: transaction.instructions().into_iter()
+            .map(|instruction| decode(&registry, &call_tree, instruction,
+                                      &transaction.instructions))
+            .collect(),
+    };
+
+    match args.format.as_str() {
+        "json" => println!("{}", serde_json::to_string_pretty(&decoded)?),
+        _ => print_table(&decoded),
+    }
+
+    let failed = decoded.instructions.iter()
+        .any(|instruction| instruction.status == Status::Failed);
+    Ok(if failed { DECODE_FAILED } else { 0 })
+}
+
+/// Decodes a single instruction, keeping track of why it couldn't be decoded instead of dropping
+/// it.
+fn decode(
+    registry: &ProcessorRegistry,
+    call_tree: &CallTree,
+    instruction: Instruction,
+    top_level: &[solana_sdk::instruction::CompiledInstruction],
+) -> DecodedInstruction {
+    let processor = registry.processor(&instruction.program)
+        .map(|processor| processor.name().to_string());
+    let node = call_tree.node(instruction.tx_instruction_id, instruction.parent_index);
+    let mut decoded = DecodedInstruction {
+        index: instruction.tx_instruction_id,
+        parent_index: instruction.parent_index,
+        stack_height: node.map_or(1, |node| node.stack_height),
+        call_path: node.map_or_else(String::new, |node| node.call_path.clone()),
+        program: instruction.program,
+        processor,
+        status: Status::Unsupported,
+        function: None,
+        properties: Vec::new(),
+    };
+    if decoded.processor.is_none() {
+        return decoded;
+    }
+
+    match registry.decode(instruction, Some(top_level)) {
+        Some(instruction_set) => {
+            decoded.status = Status::Decoded;
+            decoded.function = Some(instruction_set.function.function_name);
+            decoded.properties = instruction_set.properties.into_iter()
+                .map(|property| DecodedProperty {
+                    key: property.key,
+                    value: property.value,
+                    parent_key: property.parent_key,
+                })
+                .collect();
+        }
+        None => decoded.status = Status::Failed,
+    }
+
+    decoded
+}
+
+fn print_table(transaction: &DecodedTransaction) {
+    println!("{} (slot {}, {})", transaction.signature, transaction.slot,
+             if transaction.succeeded { "succeeded" } else { "failed" });
+
+    for instruction in &transaction.instructions {
+        // Inner instructions are listed right after the instruction invoking them, one level
+        // deeper.
+        let indent = "    ".repeat(instruction.stack_height.saturating_sub(1) as usize);
+        let label = match instruction.call_path.rsplit('/').next() {
+            Some(position) if !position.is_empty() => format!("#{}", position),
+            _ if instruction.parent_index < 0 => format!("#{}", instruction.index),
+            _ => format!("#{}.{}", instruction.parent_index, instruction.index),
+        };
+        let name = match (&instruction.status, instruction.processor.as_deref(),
+                          instruction.function) {
+            (Status::Decoded, Some(processor), Some(function)) => {
+                format!("{} {}", processor, function)
+            }
+            (Status::Failed, Some(processor), _) => format!("{} <failed to decode>", processor),
+            _ => "<unsupported program>".to_string(),
+        };
+        println!("{}{} {} ({})", indent, label, name, instruction.program);
+
+        for property in &instruction.properties {
+            if property.parent_key.is_empty() {
+                println!("{}      {}: {}", indent, property.key, property.value);
+            } else {
+                println!("{}      {}/{}: {}", indent, property.parent_key, property.key,
+                         property.value);
+            }
+        }
+    }
+}
+
+/// The public RPC endpoint of `network`.
+fn public_endpoint(network: Network) -> Option<&'static str> {
+    match network {
+        Network::Mainnet => Some("https://api.mainnet-beta.solana.com"),
+        Network::Devnet => Some("https://api.devnet.solana.com"),
+        Network::Testnet => Some("https://api.testnet.solana.com"),
+        Network::Custom(_) => None,
+    }
+}
@@ -0,0 +1,79 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use spi_wrapper::config::IndexerConfig;
+use spi_wrapper::migrate::{export, MigrateConfig, MigrateSummary};
+use spi_wrapper::sinks::Sink;
+use structopt::StructOpt;
+
+/// The exit code when the rows of a chunk differ between its snapshot and the sink.
+pub const MISMATCHED: i32 = 2;
+
+#[derive(StructOpt)]
+pub struct Export {
+    /// The configuration file describing the sink exported from.
+    #[structopt(long, default_value = "indexer.toml", parse(from_os_str))]
+    config: PathBuf,
+    /// The first slot to export.
+    #[structopt(long)]
+    from_slot: u64,
+    /// The last slot to export.
+    #[structopt(long)]
+    to_slot: u64,
+    /// The directory the snapshots are written to, one per chunk of slots.
+    #[structopt(long, parse(from_os_str))]
+    out: PathBuf,
+    /// How many slots each snapshot spans.
+    #[structopt(long, default_value = "10000")]
+    chunk_slots: u64,
+    /// How many transactions are read from the sink at a time.
+    #[structopt(long, default_value = "500")]
+    batch_size: usize,
+}
+
+/// Exports the slot range to snapshots, resuming at the first chunk missing from `--out`, then
+/// prints how many rows were exported and the chunks whose rows differ from the sink's.
+pub async fn run(args: Export) -> Result<i32, Box<dyn Error>> {
+    if args.from_slot > args.to_slot {
+        return Err("--from-slot is after --to-slot".into());
+    }
+
+    let config = IndexerConfig::from_path(&args.config)?;
+    config.validate()?;
+    let mut sink = config.sink().await?;
+    let migrate_config = MigrateConfig {
+        chunk_slots: args.chunk_slots,
+        batch_size: args.batch_size,
+    };
+    let summary = export(&mut sink, args.from_slot..=args.to_slot, &args.out, &migrate_config)
+        .await?;
+    sink.shutdown().await?;
+
+    let resumed = summary.chunks.iter().filter(|chunk| chunk.resumed).count();
+    println!("Exported slots {} to {} into {}: {} chunks ({} exported before)",
+        args.from_slot, args.to_slot, args.out.display(), summary.chunks.len(), resumed);
+    Ok(report(&summary))
+}
+
+/// Prints the rows of `summary` and its mismatches, returning the exit code.
+pub fn report(summary: &MigrateSummary) -> i32 {
+    let rows = summary.rows();
+    println!("{} transactions, {} functions, {} properties", rows.transactions, rows.functions,
+             rows.properties);
+    if summary.unverified() > 0 {
+        println!("The sink can't count its rows, {} chunks unverified", summary.unverified());
+    }
+
+    let mut code = 0;
+    for chunk in summary.mismatches() {
+        // `mismatches` only returns chunks the sink counted the rows of.
+        let sink_rows = chunk.sink_rows.unwrap_or_default();
+        println!("Slots {} to {}: {} transactions, {} functions, {} properties in the snapshot, \
+            {}, {} and {} in the sink", chunk.first_slot, chunk.last_slot,
+            chunk.rows.transactions, chunk.rows.functions, chunk.rows.properties,
+            sink_rows.transactions, sink_rows.functions, sink_rows.properties);
+        code = MISMATCHED;
+    }
+
+    code
+}
@@ -0,0 +1,50 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use spi_wrapper::config::IndexerConfig;
+use spi_wrapper::shutdown::ShutdownCoordinator;
+use spi_wrapper::sinks::Sink;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+pub struct Follow {
+    /// The configuration file describing the indexer.
+    #[structopt(long, default_value = "indexer.toml", parse(from_os_str))]
+    config: PathBuf,
+    /// The cluster to index, over the one of the configuration, whose deployments of the
+    /// programs are decoded.
+    #[structopt(long)]
+    network: Option<String>,
+    /// How long the sink gets to flush once interrupted, in seconds.
+    #[structopt(long, default_value = "30")]
+    shutdown_timeout: u64,
+}
+
+/// Runs the indexer described by the configuration until it fails or the process receives
+/// SIGINT or SIGTERM, then shuts the sink down and prints where it got to, and how fast.
+pub async fn run(args: Follow) -> Result<i32, Box<dyn Error>> {
+    let mut config = IndexerConfig::from_path(&args.config)?;
+    if let Some(network) = &args.network {
+        config.rpc.network = Some(network.clone());
+    }
+    let mut indexer = config.build_pipeline().await?;
+    let coordinator = ShutdownCoordinator::default();
+    coordinator.listen_for_signals()?;
+    let interrupted = coordinator.token();
+
+    let result = tokio::select! {
+        result = indexer.run() => result,
+        _ = interrupted.cancelled() => Ok(()),
+    };
+
+    // Checkpoints are only saved once the sink has been flushed, so whatever was written since
+    // the last one is indexed again on restart; shutting the sink down just avoids losing it.
+    let deadline = Duration::from_secs(args.shutdown_timeout);
+    tokio::time::timeout(deadline, indexer.sink.shutdown()).await
+        .map_err(|_| format!("the sink didn't shut down within {:?}", deadline))??;
+    println!("{}", serde_json::to_string_pretty(&indexer.stats.snapshot())?);
+    result?;
+
+    Ok(0)
+}
@@ -0,0 +1,39 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use spi_wrapper::config::IndexerConfig;
+use spi_wrapper::migrate::{import, MigrateConfig};
+use spi_wrapper::sinks::Sink;
+use structopt::StructOpt;
+
+use crate::export::report;
+
+#[derive(StructOpt)]
+pub struct Import {
+    /// The configuration file describing the sink imported into.
+    #[structopt(long, default_value = "indexer.toml", parse(from_os_str))]
+    config: PathBuf,
+    /// The directory `export` wrote the snapshots to.
+    #[structopt(long, parse(from_os_str))]
+    from: PathBuf,
+    /// How many transactions are written to the sink at a time.
+    #[structopt(long, default_value = "500")]
+    batch_size: usize,
+}
+
+/// Imports every snapshot `export` wrote, then prints how many rows were imported and the chunks
+/// whose rows differ from the sink's. Importing again is harmless, rows being replaced.
+pub async fn run(args: Import) -> Result<i32, Box<dyn Error>> {
+    let config = IndexerConfig::from_path(&args.config)?;
+    config.validate()?;
+    let mut sink = config.sink().await?;
+    let migrate_config = MigrateConfig {
+        batch_size: args.batch_size,
+        ..MigrateConfig::default()
+    };
+    let summary = import(&args.from, &mut sink, &migrate_config).await?;
+    sink.shutdown().await?;
+
+    println!("Imported {} chunks from {}", summary.chunks.len(), args.from.display());
+    Ok(report(&summary))
+}
@@ -0,0 +1,112 @@
+//! Command line interface to the indexer.
+//!
+//! cargo run --features cli --bin solana-indexer -- decode-tx <signature> --url <rpc url>
+//! cargo run --features cli --bin solana-indexer -- decode-tx <signature> --network devnet
+//! cargo run --features cli --bin solana-indexer -- backfill --from-slot <n> --to-slot <m>
+//! cargo run --features cli --bin solana-indexer -- follow --config indexer.toml
+//! cargo run --features cli --bin solana-indexer -- verify --address <account> --limit <n>
+//! cargo run --features cli --bin solana-indexer -- reindex --program <id> --config indexer.toml
+//! cargo run --features cli --bin solana-indexer -- upgrades --program <id> --config indexer.toml
+//! cargo run --features cli --bin solana-indexer -- corpus collect --program <id> --out <file>
+//! cargo run --features cli --bin solana-indexer -- corpus check --corpus <file> \
+//!     --previous <report> --report <report>
+//! cargo run --features cli,snapshot --bin solana-indexer -- export --from-slot <n> --to-slot <m> \
+//!     --out <dir> --config source.toml
+//! cargo run --features cli,snapshot --bin solana-indexer -- import --from <dir> \
+//!     --config target.toml
+//!
+//! `backfill` and `follow` read the RPC endpoints, programs, sink and checkpoint store from the
+//! configuration file (see `IndexerConfig`), `--network` overriding its network (and with it the
+//! program ids decoded). What gets logged is read from `RUST_LOG`.
+//! `backfill --dry-run` writes nothing, summarizing what it would have written instead.
+//! `verify` compares our decoding with the `jsonParsed` output of RPC, printing the mismatches.
+//! `reindex` decodes again what older versions of the processors of the programs decoded.
+//! `upgrades` backfills the slots programs were upgraded at, which instructions are stamped with
+//! when `[program_versions]` is set.
+//! `corpus collect` samples the instructions of a program into a corpus file, which `corpus check`
+//! decodes, reporting the share of every discriminant that decoded and exiting with 2 when one
+//! decodes less than in the previous report (see `spi_wrapper::corpus`).
+//! `export` and `import` move what a sink holds of a slot range to another sink through snapshot
+//! files (see `spi_wrapper::migrate`), exiting with 2 when the rows of the sink don't add up.
+
+mod backfill;
+mod corpus;
+mod decode_tx;
+#[cfg(feature = "snapshot")]
+mod export;
+mod follow;
+#[cfg(feature = "snapshot")]
+mod import;
+mod reindex;
+mod upgrades;
+mod verify;
+
+use structopt::StructOpt;
+use tracing_subscriber::EnvFilter;
+
+#[derive(StructOpt)]
+#[structopt(name = "solana-indexer", about = "Decodes and indexes Solana program instructions.")]
+enum Command {
+    /// Fetches a transaction and prints what each of its instructions did.
+    DecodeTx(decode_tx::DecodeTx),
+    /// Indexes every block of a slot range.
+    Backfill(backfill::Backfill),
+    /// Indexes new blocks as they're produced, until interrupted.
+    Follow(follow::Follow),
+    /// Compares how transactions are decoded with how RPC parses them.
+    Verify(verify::Verify),
+    /// Re-indexes the rows of programs decoded by older versions of their processors.
+    Reindex(reindex::Reindex),
+    /// Backfills the upgrades of programs into a sink.
+    Upgrades(upgrades::Upgrades),
+    /// Samples the instructions of programs into corpora, and checks how they decode.
+    Corpus(corpus::Corpus),
+    /// Exports the records a sink holds of a slot range to snapshots.
+    #[cfg(feature = "snapshot")]
+    Export(export::Export),
+    /// Imports the snapshots written by `export` into a sink.
+    #[cfg(feature = "snapshot")]
+    Import(import::Import),
+}
+
+#[tokio::main]
+async fn main() {
+    let command = Command::from_args();
+    // Only `follow` is chatty by default; the others have their own output.
+    init_tracing(match command {
+        Command::Follow(_) => "info",
+        _ => "warn",
+    });
+
+    let result = match command {
+        Command::DecodeTx(args) => decode_tx::run(args).await,
+        Command::Backfill(args) => backfill::run(args).await,
+        Command::Follow(args) => follow::run(args).await,
+        Command::Verify(args) => verify::run(args).await,
+        Command::Reindex(args) => reindex::run(args).await,
+        Command::Upgrades(args) => upgrades::run(args).await,
+        Command::Corpus(args) => corpus::run(args).await,
+        #[cfg(feature = "snapshot")]
+        Command::Export(args) => export::run(args).await,
+        #[cfg(feature = "snapshot")]
+        Command::Import(args) => import::run(args).await,
+    };
+
+    match result {
+        Ok(code) => std::process::exit(code),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn init_tracing(default_filter: &str) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(default_filter));
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+}
@@ -0,0 +1,56 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use solana_sdk::pubkey::Pubkey;
+use spi_wrapper::config::IndexerConfig;
+use spi_wrapper::reindex::{reindex, ReindexConfig};
+use spi_wrapper::sinks::Sink;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+pub struct Reindex {
+    /// The configuration file describing the RPC endpoints, processors, sink and checkpoint store.
+    #[structopt(long, default_value = "indexer.toml", parse(from_os_str))]
+    config: PathBuf,
+    /// The program whose rows decoded by an older version of its processor are re-indexed. May
+    /// be repeated.
+    #[structopt(long = "program", required = true)]
+    programs: Vec<Pubkey>,
+    /// How many transactions are re-indexed (and checkpointed) at a time.
+    #[structopt(long, default_value = "100")]
+    batch_size: usize,
+}
+
+/// Re-indexes the stale rows of every program, resuming where the last re-index of the same
+/// processor version stopped when the configuration has a checkpoint store.
+pub async fn run(args: Reindex) -> Result<i32, Box<dyn Error>> {
+    let config = IndexerConfig::from_path(&args.config)?;
+    config.validate()?;
+    let (rpc, registry) = config.connect().await?;
+    for program in &args.programs {
+        if registry.processor(&program.to_string()).is_none() {
+            return Err(format!("no enabled processor decodes {}", program).into());
+        }
+    }
+    // Writing to the sink itself: the rows replaced were aggregated and notified about already.
+    let mut sink = config.sink().await?;
+    let checkpoint = config.checkpointing().await?;
+
+    let reindex_config = ReindexConfig {
+        batch_size: args.batch_size,
+        ..ReindexConfig::default()
+    };
+    for program in &args.programs {
+        let program = program.to_string();
+        let summary = reindex(
+            &mut sink, &registry, &rpc, &program, &reindex_config, checkpoint.as_ref(),
+        ).await?;
+        println!("{}: {} transactions re-indexed ({} out of their raw data, {} fetched again), \
+            {} no longer available, {} instructions no longer decoding",
+            program, summary.transactions, summary.from_raw_data, summary.refetched,
+            summary.missing, summary.failed);
+    }
+    sink.shutdown().await?;
+
+    Ok(0)
+}
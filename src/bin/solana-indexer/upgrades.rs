@@ -0,0 +1,41 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use solana_sdk::pubkey::Pubkey;
+use spi_wrapper::config::IndexerConfig;
+use spi_wrapper::program_versions::ProgramVersions;
+use spi_wrapper::sinks::Sink;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+pub struct Upgrades {
+    /// The configuration file describing the RPC endpoints and the sink.
+    #[structopt(long, default_value = "indexer.toml", parse(from_os_str))]
+    config: PathBuf,
+    /// The program whose upgrades are backfilled. May be repeated.
+    #[structopt(long = "program", required = true)]
+    programs: Vec<Pubkey>,
+}
+
+/// Backfills every deployment and upgrade of the programs out of the history of their
+/// ProgramData accounts into the sink, for the instructions indexed next to be stamped with the
+/// version of their program. Backfilling again is harmless, upgrades being replaced.
+pub async fn run(args: Upgrades) -> Result<i32, Box<dyn Error>> {
+    let config = IndexerConfig::from_path(&args.config)?;
+    config.validate()?;
+    let commitment = config.commitment()?;
+    let (rpc, registry) = config.connect().await?;
+    let mut sink = config.sink().await?;
+
+    let versions = ProgramVersions::new();
+    versions.restore(&sink.program_upgrades().await?);
+    for program in &args.programs {
+        let slots = versions.backfill(&rpc, registry.network(), program, commitment).await?;
+        let slots: Vec<_> = slots.iter().map(|slot| slot.to_string()).collect();
+        println!("{}: {} upgrades known ({})", program, slots.len(), slots.join(", "));
+    }
+    sink.write_program_upgrades(&versions.take_learnt()).await?;
+    sink.shutdown().await?;
+
+    Ok(0)
+}
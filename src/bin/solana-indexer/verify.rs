@@ -0,0 +1,91 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::Serialize;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use spi_wrapper::ingest::pool::RpcPool;
+use spi_wrapper::verify::{verify_transaction, VerifyReport};
+use spi_wrapper::ProcessorRegistry;
+use structopt::StructOpt;
+
+/// The exit code when our decoding disagrees with RPC on at least one transaction.
+const MISMATCHED: i32 = 2;
+
+#[derive(StructOpt)]
+pub struct Verify {
+    /// The signatures of the transactions to verify.
+    signatures: Vec<String>,
+    /// A file listing more signatures, one per line.
+    #[structopt(long, parse(from_os_str))]
+    signatures_file: Option<PathBuf>,
+    /// Also verifies the most recent transactions referencing this account.
+    #[structopt(long)]
+    address: Option<Pubkey>,
+    /// How many recent transactions of `--address` are verified.
+    #[structopt(long, default_value = "100", requires = "address")]
+    limit: usize,
+    /// The RPC endpoint to fetch the transactions from.
+    #[structopt(long, default_value = "https://api.mainnet-beta.solana.com")]
+    url: String,
+}
+
+#[derive(Default, Serialize)]
+struct Report {
+    verified: usize,
+    // Transactions the node didn't have (or couldn't be turned into their raw form).
+    not_found: Vec<String>,
+    // Only the transactions with mismatches.
+    transactions: Vec<VerifyReport>,
+}
+
+/// Compares our decoding of the transactions with RPC's and prints the mismatches as JSON.
+/// Returns `MISMATCHED` when there are any, 0 otherwise.
+pub async fn run(args: Verify) -> Result<i32, Box<dyn Error>> {
+    let rpc = Arc::new(RpcPool::single(args.url.clone()));
+    let commitment = CommitmentConfig::confirmed();
+
+    let mut signatures = args.signatures;
+    if let Some(path) = &args.signatures_file {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("unable to read {}: {}", path.display(), err))?;
+        signatures.extend(contents.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string));
+    }
+    if let Some(address) = args.address {
+        let limit = args.limit;
+        let recent = rpc.call(move |rpc| {
+            rpc.get_signatures_for_address_with_config(&address,
+                GetConfirmedSignaturesForAddress2Config {
+                    limit: Some(limit),
+                    commitment: Some(commitment),
+                    ..GetConfirmedSignaturesForAddress2Config::default()
+                })
+        }).await?;
+        signatures.extend(recent.into_iter().map(|status| status.signature));
+    }
+    if signatures.is_empty() {
+        return Err("no signatures to verify (pass some, --signatures-file or --address)".into());
+    }
+
+    let registry = ProcessorRegistry::default();
+    let mut report = Report::default();
+    for signature in &signatures {
+        match verify_transaction(&rpc, &registry, signature, commitment).await? {
+            Some(transaction) => {
+                report.verified += 1;
+                if !transaction.mismatches.is_empty() {
+                    report.transactions.push(transaction);
+                }
+            }
+            None => report.not_found.push(signature.clone()),
+        }
+    }
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(if report.transactions.is_empty() { 0 } else { MISMATCHED })
+}
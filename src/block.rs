@@ -0,0 +1,183 @@
+use std::sync::Arc;
+
+use serde::{Serialize, Deserialize};
+use solana_transaction_status::{EncodedTransactionWithStatusMeta, UiConfirmedBlock};
+use tracing::debug_span;
+
+use crate::ingest::filter::DecodeFilters;
+use crate::network::Network;
+use crate::registry::ProcessorRegistry;
+use crate::transaction::{decode_transaction_filtered, RawTransaction, TransactionRecord};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BlockRecord {
+    // The slot of this block.
+    pub slot: u64,
+    // The slot of the block this one builds on.
+    pub parent_slot: u64,
+    pub blockhash: String,
+    pub previous_blockhash: String,
+    // The block time reported by the node, if any.
+    pub block_time: Option<i64>,
+    // Whether the block time is an estimate, the node having reported none (see
+    // `ingest::timestamps`).
+    #[serde(default)]
+    pub timestamp_estimated: bool,
+    // Whether the block was read at finalized commitment (or has been confirmed final since).
+    pub finalized: bool,
+    // The cluster the block was produced on.
+    #[serde(default)]
+    pub network: Network,
+    // The epoch of the slot, and the identity of the validator scheduled to lead it, when
+    // enriched with them (see `ingest::leaders`).
+    #[serde(default)]
+    pub epoch: Option<u64>,
+    #[serde(default)]
+    pub leader: Option<String>,
+    // Every transaction in the block, in block order.
+    pub transactions: Vec<TransactionRecord>,
+}
+
+impl BlockRecord {
+    /// The fields of a confirmed block of `network`, without any of its transactions.
+    pub fn header(network: Network, slot: u64, block: &UiConfirmedBlock) -> BlockRecord {
+        BlockRecord {
+            slot,
+            parent_slot: block.parent_slot,
+            blockhash: block.blockhash.clone(),
+            previous_blockhash: block.previous_blockhash.clone(),
+            block_time: block.block_time,
+            timestamp_estimated: false,
+            finalized: false,
+            network,
+            epoch: None,
+            leader: None,
+            transactions: Vec::new(),
+        }
+    }
+
+    /// Flags the block time of the block and of every transaction in it as estimated (or not).
+    pub fn set_timestamp_estimated(&mut self, estimated: bool) {
+        self.timestamp_estimated = estimated;
+        for transaction in &mut self.transactions {
+            transaction.timestamp_estimated = estimated;
+        }
+    }
+
+    /// Flags the block and every transaction in it as finalized (or not).
+    pub fn set_finalized(&mut self, finalized: bool) {
+        self.finalized = finalized;
+        for transaction in &mut self.transactions {
+            transaction.finalized = finalized;
+        }
+    }
+}
+
+/// Decodes every transaction of a confirmed block.
+///
+/// The block is expected to be fetched with full transaction details and a binary encoding;
+/// transactions that cannot be decoded into their raw form are skipped. The record is not
+/// flagged as finalized; that's up to the source, which knows the commitment it read at.
+pub fn decode_block(
+    registry: &ProcessorRegistry,
+    slot: u64,
+    block: &UiConfirmedBlock,
+) -> BlockRecord {
+    let mut transactions = Vec::new();
+    let mut record = decode_block_streaming(registry, slot, block, &mut |transaction| {
+        transactions.push(transaction)
+    });
+    record.transactions = transactions;
+
+    record
+}
+
+/// Same as [`decode_block`], handing every transaction to `on_transaction` as soon as it's
+/// decoded instead of collecting them, so a sink can write them in batches without the whole
+/// block being held in memory. Returns the block header, with no transactions.
+pub fn decode_block_streaming<F: FnMut(TransactionRecord)>(
+    registry: &ProcessorRegistry,
+    slot: u64,
+    block: &UiConfirmedBlock,
+    on_transaction: &mut F,
+) -> BlockRecord {
+    decode_block_inner(registry, &DecodeFilters::none(), slot, block, on_transaction)
+}
+
+/// Same as [`decode_block`], skipping whatever `filters` don't let through without decoding it.
+pub fn decode_block_filtered(
+    registry: &ProcessorRegistry,
+    filters: &DecodeFilters,
+    slot: u64,
+    block: &UiConfirmedBlock,
+) -> BlockRecord {
+    let mut transactions = Vec::new();
+    let mut record = decode_block_inner(registry, filters, slot, block, &mut |transaction| {
+        transactions.push(transaction)
+    });
+    record.transactions = transactions;
+
+    record
+}
+
+fn decode_block_inner<F: FnMut(TransactionRecord)>(
+    registry: &ProcessorRegistry,
+    filters: &DecodeFilters,
+    slot: u64,
+    block: &UiConfirmedBlock,
+    on_transaction: &mut F,
+) -> BlockRecord {
+    let span = debug_span!("decode_block", slot,
+                           transactions = block.transactions.as_ref().map_or(0, Vec::len));
+    let _entered = span.enter();
+    #[cfg(feature = "metrics")]
+    let timer = crate::metrics::metrics().block_processing_seconds.start_timer();
+    for encoded in block.transactions.iter().flatten() {
+        if let Some(transaction) = decode_encoded_transaction_filtered(
+            registry, filters, slot, block.block_time, encoded) {
+            on_transaction(transaction);
+        }
+    }
+    #[cfg(feature = "metrics")]
+    {
+        timer.observe_duration();
+        crate::metrics::metrics().blocks_processed.inc();
+    }
+
+    BlockRecord::header(registry.network(), slot, block)
+}
+
+/// Decodes a transaction of a block as handed out by RPC, or returns `None` when it can't be
+/// decoded into its raw form.
+pub fn decode_encoded_transaction(
+    registry: &ProcessorRegistry,
+    slot: u64,
+    block_time: Option<i64>,
+    encoded: &EncodedTransactionWithStatusMeta,
+) -> Option<TransactionRecord> {
+    decode_encoded_transaction_filtered(
+        registry, &DecodeFilters::none(), slot, block_time, encoded)
+}
+
+/// Same as [`decode_encoded_transaction`], filtered (see
+/// [`decode_transaction_filtered`](crate::transaction::decode_transaction_filtered)).
+pub fn decode_encoded_transaction_filtered(
+    registry: &ProcessorRegistry,
+    filters: &DecodeFilters,
+    slot: u64,
+    block_time: Option<i64>,
+    encoded: &EncodedTransactionWithStatusMeta,
+) -> Option<TransactionRecord> {
+    RawTransaction::from_encoded(slot, block_time, encoded)
+        .and_then(|raw| decode_transaction_filtered(registry, filters, &raw))
+}
+
+/// Async version of [`decode_block`], kept for existing callers.
+#[deprecated(note = "decoding is synchronous, use `decode_block` instead")]
+pub async fn process_block(
+    registry: &Arc<ProcessorRegistry>,
+    slot: u64,
+    block: &UiConfirmedBlock,
+) -> BlockRecord {
+    decode_block(registry, slot, block)
+}
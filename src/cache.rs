@@ -0,0 +1,196 @@
+//! Bounded caches for the lookups made while decoding (the decimals of mints, see
+//! [`MintCache`](crate::mints::MintCache)), so that a long-running ingester doesn't grow them
+//! forever: a cache holds at most `capacity` entries, evicting the least recently used one to
+//! make room, and forgets the entries older than its TTL.
+//!
+//! Every cache counts its hits, misses and evictions (see [`CacheStats`]), also published by
+//! cache name with the `metrics` feature.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// What a cache went through since it was created.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    // Lookups of entries that weren't cached, or were stale.
+    pub misses: u64,
+    // Entries dropped to make room, or for being stale.
+    pub evictions: u64,
+    // How many entries are cached right now.
+    pub size: usize,
+}
+
+/// A map holding at most `capacity` entries, the least recently used being evicted first.
+/// Entries expire `ttl` after being inserted, when set.
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    // What the cache is published as.
+    name: &'static str,
+    capacity: usize,
+    ttl: Option<Duration>,
+    entries: HashMap<K, Entry<V>>,
+    // The keys by last use, oldest first.
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+#[derive(Debug)]
+struct Entry<V> {
+    value: V,
+    expires_at: Option<Instant>,
+    // When the entry was last used, the key of its place in `recency`.
+    tick: u64,
+}
+
+impl<K: Clone + Eq + Hash, V> LruCache<K, V> {
+    pub fn new(name: &'static str, capacity: usize, ttl: Option<Duration>) -> Self {
+        LruCache {
+            name,
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats { size: self.entries.len(), ..self.stats }
+    }
+
+    /// The value of `key`, if cached and fresh, which makes it the most recently used.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let fresh = match self.entries.get(key) {
+            Some(entry) => entry.expires_at.map_or(true, |expires_at| expires_at > Instant::now()),
+            None => false,
+        };
+        if !fresh {
+            if self.remove(key).is_some() {
+                self.evicted(1);
+            }
+            self.stats.misses += 1;
+            #[cfg(feature = "metrics")]
+            crate::metrics::metrics().cache_misses.with_label_values(&[self.name]).inc();
+            return None;
+        }
+
+        self.tick += 1;
+        self.stats.hits += 1;
+        #[cfg(feature = "metrics")]
+        crate::metrics::metrics().cache_hits.with_label_values(&[self.name]).inc();
+        let entry = self.entries.get_mut(key)?;
+        self.recency.remove(&entry.tick);
+        self.recency.insert(self.tick, key.clone());
+        entry.tick = self.tick;
+
+        Some(&entry.value)
+    }
+
+    /// Caches `value` under `key`, evicting the least recently used entries beyond the capacity.
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.tick += 1;
+        let entry = Entry {
+            value,
+            expires_at: self.ttl.map(|ttl| Instant::now() + ttl),
+            tick: self.tick,
+        };
+        if let Some(previous) = self.entries.insert(key.clone(), entry) {
+            self.recency.remove(&previous.tick);
+        }
+        self.recency.insert(self.tick, key);
+
+        let mut evicted = 0;
+        while self.entries.len() > self.capacity {
+            let oldest = match self.recency.keys().next() {
+                Some(oldest) => *oldest,
+                None => break,
+            };
+            if let Some(key) = self.recency.remove(&oldest) {
+                self.entries.remove(&key);
+                evicted += 1;
+            }
+        }
+        self.evicted(evicted);
+        self.publish_size();
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.tick);
+        self.publish_size();
+
+        Some(entry.value)
+    }
+
+    fn evicted(&mut self, count: u64) {
+        self.stats.evictions += count;
+        #[cfg(feature = "metrics")]
+        crate::metrics::metrics().cache_evictions.with_label_values(&[self.name]).inc_by(count);
+    }
+
+    fn publish_size(&self) {
+        #[cfg(feature = "metrics")]
+        crate::metrics::metrics().cache_entries.with_label_values(&[self.name])
+            .set(self.entries.len() as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_entries_are_evicted() {
+        let mut cache = LruCache::new("test", 2, None);
+        cache.insert("usdc", 6);
+        cache.insert("wsol", 9);
+        assert_eq!(cache.get(&"usdc"), Some(&6));
+
+        cache.insert("bonk", 5);
+        assert_eq!(cache.get(&"wsol"), None);
+        assert_eq!(cache.get(&"usdc"), Some(&6));
+        assert_eq!(cache.get(&"bonk"), Some(&5));
+        assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 1, evictions: 1, size: 2 });
+
+        // Inserting a key again doesn't take more room.
+        cache.insert("bonk", 5);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"usdc"), Some(&6));
+    }
+
+    #[test]
+    fn stale_entries_are_evicted() {
+        let mut cache = LruCache::new("test", 2, Some(Duration::from_secs(0)));
+        cache.insert("usdc", 6);
+
+        assert_eq!(cache.get(&"usdc"), None);
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats(), CacheStats { hits: 0, misses: 1, evictions: 1, size: 0 });
+    }
+}
@@ -0,0 +1,352 @@
+//! The tree of cross-program invocations of a transaction.
+//!
+//! `parent_index` only tells which top-level instruction an inner instruction was invoked under,
+//! not by which inner instruction. The stack height of every inner instruction does: nodes report
+//! it along with the inner instructions from 1.14 on, older ones only log it (`Program <id>
+//! invoke [<height>]`). When neither is available, or they don't add up with the inner
+//! instructions (e.g. truncated logs), inner instructions are assumed to be invoked by their
+//! top-level instruction directly.
+
+use serde::{Deserialize, Serialize};
+
+use crate::interner::ProgramId;
+use crate::transaction::RawTransaction;
+use crate::{InstructionProperty, InstructionSet, PropertyType};
+
+/// Where the stack heights of a call tree come from, from the most to the least reliable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StackHeights {
+    // Reported by the node along with the inner instructions.
+    Reported,
+    // Recovered from the invoke lines of the program logs.
+    Logs,
+    // Unknown, every inner instruction being assumed to be invoked by its top-level instruction.
+    Flat,
+}
+
+impl Default for StackHeights {
+    fn default() -> Self {
+        StackHeights::Flat
+    }
+}
+
+/// An instruction of a call tree.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CallNode {
+    pub tx_instruction_id: i16,
+    pub parent_index: i16,
+    pub program: ProgramId,
+    // 1 for top-level instructions, 2 for the instructions they invoke, and so on.
+    pub stack_height: u32,
+    // The position of the instruction at every level, from the top-level instruction down, e.g.
+    // `2/2.1/2.1.3` for the fourth instruction invoked by the second instruction the third
+    // top-level instruction invoked. Positions start at 0, like instruction indexes.
+    pub call_path: String,
+    // The index in the tree of the instruction that invoked this one, none for top-level ones.
+    pub caller: Option<usize>,
+    // The indexes in the tree of the instructions this one invoked, in order.
+    pub callees: Vec<usize>,
+}
+
+/// Every instruction of a transaction, in execution order, linked to the instructions it invoked.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CallTree {
+    pub nodes: Vec<CallNode>,
+    // The least reliable source of the stack heights of the instructions.
+    pub stack_heights: StackHeights,
+}
+
+impl CallTree {
+    /// Rebuilds the call tree of `transaction` out of the stack heights it reports or logs.
+    /// Instructions of unknown programs (out of range program indexes) are left out.
+    pub fn build(transaction: &RawTransaction) -> CallTree {
+        let logged = logged_stack_heights(&transaction.log_messages);
+        let mut tree = CallTree { nodes: Vec::new(), stack_heights: StackHeights::Reported };
+
+        for (index, compiled) in transaction.instructions.iter().enumerate() {
+            let inner: Vec<_> = transaction.inner_instructions.iter()
+                .filter(|ii| ii.index as usize == index)
+                .flat_map(|ii| ii.instructions.iter())
+                .collect();
+            let (heights, source) = match reported_stack_heights(transaction, index, inner.len()) {
+                Some(heights) => (heights, StackHeights::Reported),
+                None => match logged.get(index).filter(|heights| heights.len() == inner.len()) {
+                    Some(heights) => (heights.clone(), StackHeights::Logs),
+                    None => (vec![2; inner.len()], StackHeights::Flat),
+                },
+            };
+            if !inner.is_empty() {
+                tree.stack_heights = tree.stack_heights.max(source);
+            }
+
+            let program = match program_of(transaction, compiled.program_id_index) {
+                Some(program) => program,
+                None => continue,
+            };
+            let root = tree.push(None, index as i16, -1, program, 1);
+            // The instructions being executed, the innermost last.
+            let mut stack = vec![root];
+            for (position, (compiled, height)) in inner.into_iter().zip(heights).enumerate() {
+                // Inner instructions are at least invoked by their top-level instruction.
+                let height = height.max(2);
+                while innermost_height(&tree, &stack) >= height {
+                    stack.pop();
+                }
+                let program = match program_of(transaction, compiled.program_id_index) {
+                    Some(program) => program,
+                    None => continue,
+                };
+                let caller = stack.last().copied();
+                stack.push(tree.push(caller, position as i16, index as i16, program, height));
+            }
+        }
+
+        tree
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The top-level instructions.
+    pub fn roots(&self) -> impl Iterator<Item = &CallNode> {
+        self.nodes.iter().filter(|node| node.caller.is_none())
+    }
+
+    /// The instructions `node` invoked, in order.
+    pub fn callees<'a>(&'a self, node: &'a CallNode) -> impl Iterator<Item = &'a CallNode> {
+        node.callees.iter().map(move |index| &self.nodes[*index])
+    }
+
+    /// The instruction that invoked `node`, none for top-level instructions.
+    pub fn caller(&self, node: &CallNode) -> Option<&CallNode> {
+        node.caller.map(|index| &self.nodes[index])
+    }
+
+    /// The node of the instruction `tx_instruction_id` of `parent_index` (-1 for top-level
+    /// instructions), numbered as in [`RawTransaction::instructions`].
+    pub fn node(&self, tx_instruction_id: i16, parent_index: i16) -> Option<&CallNode> {
+        self.nodes
+            .binary_search_by_key(&execution_order(tx_instruction_id, parent_index),
+                |node| execution_order(node.tx_instruction_id, node.parent_index))
+            .ok()
+            .map(|index| &self.nodes[index])
+    }
+
+    /// Adds the `stack_height` and `call_path` properties of its instruction to
+    /// `instruction_set`.
+    pub fn annotate(&self, instruction_set: &mut InstructionSet) {
+        let function = &instruction_set.function;
+        let node = match self.node(function.tx_instruction_id, function.parent_index) {
+            Some(node) => node,
+            None => return,
+        };
+
+        let property = |key: &str, value: String, value_type| InstructionProperty {
+            tx_instruction_id: function.tx_instruction_id,
+            transaction_hash: function.transaction_hash.clone(),
+            parent_index: function.parent_index,
+            key: key.into(),
+            value,
+            value_type,
+            parent_key: "".into(),
+            timestamp: function.timestamp,
+        };
+        let stack_height =
+            property("stack_height", node.stack_height.to_string(), PropertyType::U64);
+        let call_path = property("call_path", node.call_path.clone(), PropertyType::Text);
+        instruction_set.properties.push(stack_height);
+        instruction_set.properties.push(call_path);
+    }
+
+    fn push(
+        &mut self,
+        caller: Option<usize>,
+        tx_instruction_id: i16,
+        parent_index: i16,
+        program: ProgramId,
+        stack_height: u32,
+    ) -> usize {
+        let index = self.nodes.len();
+        let call_path = match caller {
+            Some(caller) => {
+                let caller = &mut self.nodes[caller];
+                let position = caller.callees.len();
+                caller.callees.push(index);
+                let segment = caller.call_path.rsplit('/').next().unwrap_or_default();
+                format!("{}/{}.{}", caller.call_path, segment, position)
+            }
+            None => tx_instruction_id.to_string(),
+        };
+
+        self.nodes.push(CallNode {
+            tx_instruction_id,
+            parent_index,
+            program,
+            stack_height,
+            call_path,
+            caller,
+            callees: Vec::new(),
+        });
+
+        index
+    }
+}
+
+/// The stack height of the innermost instruction being executed.
+fn innermost_height(tree: &CallTree, stack: &[usize]) -> u32 {
+    stack.last().map_or(0, |index| tree.nodes[*index].stack_height)
+}
+
+/// Sorts instructions the way they're executed: every top-level instruction followed by the
+/// instructions it invoked.
+pub(crate) fn execution_order(tx_instruction_id: i16, parent_index: i16) -> (i16, i16) {
+    if parent_index < 0 {
+        (tx_instruction_id, -1)
+    } else {
+        (parent_index, tx_instruction_id)
+    }
+}
+
+fn program_of(transaction: &RawTransaction, program_id_index: u8) -> Option<ProgramId> {
+    let program = transaction.account_keys.get(program_id_index as usize)?;
+
+    Some(ProgramId::new(&program.to_string()))
+}
+
+/// The stack heights reported for the `len` instructions invoked by the top-level instruction
+/// `index`, if every one of them is.
+fn reported_stack_heights(transaction: &RawTransaction, index: usize, len: usize)
+    -> Option<Vec<u32>> {
+    let mut heights = Vec::with_capacity(len);
+    for (group, inner) in transaction.inner_instructions.iter().enumerate() {
+        if inner.index as usize != index {
+            continue;
+        }
+        let reported = transaction.stack_heights.get(group)
+            .filter(|reported| reported.len() == inner.instructions.len())?;
+        heights.extend_from_slice(reported);
+    }
+
+    Some(heights)
+}
+
+/// The stack heights logged for the instructions invoked by each top-level instruction.
+fn logged_stack_heights(log_messages: &[String]) -> Vec<Vec<u32>> {
+    let mut heights: Vec<Vec<u32>> = Vec::new();
+    for height in log_messages.iter().filter_map(|line| invoke_height(line)) {
+        if height <= 1 {
+            heights.push(Vec::new());
+        } else if let Some(invoked) = heights.last_mut() {
+            invoked.push(height);
+        }
+    }
+
+    heights
+}
+
+/// The stack height of a `Program <id> invoke [<height>]` log line.
+fn invoke_height(line: &str) -> Option<u32> {
+    let (_, height) = line.strip_prefix("Program ")?.rsplit_once(" invoke [")?;
+
+    height.strip_suffix(']')?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::instruction::CompiledInstruction;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_transaction_status::InnerInstructions;
+
+    use super::*;
+
+    fn compiled(program_id_index: u8) -> CompiledInstruction {
+        CompiledInstruction { program_id_index, accounts: vec![], data: vec![] }
+    }
+
+    // A router invoking an AMM which invokes a pool, each of them moving tokens, then a transfer.
+    fn transaction(log_messages: Vec<&str>, stack_heights: Vec<Vec<u32>>) -> RawTransaction {
+        RawTransaction {
+            slot: 1,
+            block_time: None,
+            transaction_hash: "signature".to_string(),
+            account_keys: (0..5).map(|_| Pubkey::new_unique()).collect(),
+            num_required_signatures: 1,
+            instructions: vec![compiled(0), compiled(3)],
+            inner_instructions: vec![InnerInstructions {
+                index: 0,
+                instructions: vec![compiled(1), compiled(2), compiled(3), compiled(3),
+                                   compiled(4)],
+            }],
+            token_mints: vec![],
+            fee: 5000,
+            succeeded: true,
+            stack_heights,
+            log_messages: log_messages.into_iter().map(String::from).collect(),
+            pre_balances: vec![],
+            post_balances: vec![],
+        }
+    }
+
+    fn call_paths(tree: &CallTree) -> Vec<(&str, u32)> {
+        tree.nodes.iter().map(|node| (node.call_path.as_str(), node.stack_height)).collect()
+    }
+
+    const NESTED: &[(&str, u32)] = &[
+        ("0", 1),
+        ("0/0.0", 2),
+        ("0/0.0/0.0.0", 3),
+        ("0/0.0/0.0.0/0.0.0.0", 4),
+        ("0/0.0/0.0.1", 3),
+        ("0/0.1", 2),
+        ("1", 1),
+    ];
+
+    #[test]
+    fn reported_stack_heights_nest_instructions() {
+        let tree = CallTree::build(&transaction(vec![], vec![vec![2, 3, 4, 3, 2]]));
+        assert_eq!(tree.stack_heights, StackHeights::Reported);
+        assert_eq!(call_paths(&tree), NESTED);
+
+        let amm = tree.node(0, 0).unwrap();
+        assert_eq!(tree.caller(amm).unwrap().call_path, "0");
+        let invoked: Vec<_> = tree.callees(amm).map(|node| node.tx_instruction_id).collect();
+        assert_eq!(invoked, vec![1, 3]);
+        assert_eq!(tree.roots().count(), 2);
+    }
+
+    #[test]
+    fn stack_heights_fall_back_to_the_logs() {
+        let logs = vec![
+            "Program Router invoke [1]",
+            "Program AMM invoke [2]",
+            "Program Pool invoke [3]",
+            "Program log: Instruction: Swap",
+            "Program Token invoke [4]",
+            "Program Token success",
+            "Program Pool success",
+            "Program Token invoke [3]",
+            "Program Token success",
+            "Program AMM success",
+            "Program System invoke [2]",
+            "Program System success",
+            "Program Router success",
+            "Program Token invoke [1]",
+            "Program Token success",
+        ];
+        let tree = CallTree::build(&transaction(logs, vec![]));
+        assert_eq!(tree.stack_heights, StackHeights::Logs);
+        assert_eq!(call_paths(&tree), NESTED);
+    }
+
+    #[test]
+    fn unknown_stack_heights_are_flat() {
+        let truncated = vec!["Program Router invoke [1]", "Log truncated"];
+        let tree = CallTree::build(&transaction(truncated, vec![]));
+        assert_eq!(tree.stack_heights, StackHeights::Flat);
+        assert!(tree.nodes.iter().filter(|node| node.parent_index == 0)
+            .all(|node| node.stack_height == 2 && node.caller == Some(0)));
+        assert_eq!(tree.node(4, 0).unwrap().call_path, "0/0.4");
+    }
+}
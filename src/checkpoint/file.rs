@@ -0,0 +1,113 @@
+use std::fs;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+
+use crate::checkpoint::{Checkpoint, CheckpointError, CheckpointStore};
+
+#[derive(Serialize, Deserialize)]
+struct CheckpointFile {
+    checkpoint: Checkpoint,
+    // The hex encoded SHA3-256 of the serialized checkpoint.
+    checksum: String,
+}
+
+/// Stores every pipeline's checkpoint in its own file, `<directory>/<pipeline>.checkpoint`, so
+/// pipeline names have to be valid file names.
+///
+/// Files are replaced atomically and carry a checksum, so a torn or hand-edited checkpoint is
+/// reported instead of silently resuming from the wrong place.
+pub struct FileCheckpointStore {
+    directory: PathBuf,
+}
+
+impl FileCheckpointStore {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        FileCheckpointStore {
+            directory: directory.into(),
+        }
+    }
+
+    fn path(&self, pipeline: &str) -> PathBuf {
+        self.directory.join(format!("{}.checkpoint", pipeline))
+    }
+}
+
+fn checksum(checkpoint: &Checkpoint) -> Result<String, CheckpointError> {
+    Ok(hex::encode(Sha3_256::digest(&serde_json::to_vec(checkpoint)?)))
+}
+
+#[async_trait]
+impl CheckpointStore for FileCheckpointStore {
+    async fn load(&self, pipeline: &str) -> Result<Option<Checkpoint>, CheckpointError> {
+        let path = self.path(pipeline);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let contents = fs::read(&path)?;
+        let file: CheckpointFile = serde_json::from_slice(&contents)
+            .map_err(|_| CheckpointError::Corrupted(path.clone()))?;
+        if checksum(&file.checkpoint)? != file.checksum {
+            return Err(CheckpointError::Corrupted(path));
+        }
+
+        Ok(Some(file.checkpoint))
+    }
+
+    async fn save(&self, pipeline: &str, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        fs::create_dir_all(&self.directory)?;
+
+        let file = CheckpointFile {
+            checkpoint: checkpoint.clone(),
+            checksum: checksum(checkpoint)?,
+        };
+        let path = self.path(pipeline);
+        let temporary = path.with_extension("checkpoint.tmp");
+        fs::write(&temporary, serde_json::to_vec(&file)?)?;
+        fs::rename(&temporary, &path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(name: &str) -> FileCheckpointStore {
+        let directory = std::env::temp_dir()
+            .join(format!("spi-wrapper-checkpoints-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+
+        FileCheckpointStore::new(directory)
+    }
+
+    #[tokio::test]
+    async fn checkpoints_round_trip_per_pipeline() {
+        let store = store("round-trip");
+        let blocks = Checkpoint::new(Some(42), None);
+        let backfill = Checkpoint::new(Some(7), Some("signature".to_string()));
+
+        assert_eq!(store.load("blocks").await.unwrap(), None);
+        store.save("blocks", &blocks).await.unwrap();
+        store.save("backfill", &backfill).await.unwrap();
+
+        assert_eq!(store.load("blocks").await.unwrap(), Some(blocks));
+        assert_eq!(store.load("backfill").await.unwrap(), Some(backfill));
+    }
+
+    #[tokio::test]
+    async fn tampered_checkpoint_is_reported() {
+        let store = store("tampered");
+        store.save("blocks", &Checkpoint::new(Some(42), None)).await.unwrap();
+
+        let path = store.path("blocks");
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::write(&path, contents.replace("42", "43")).unwrap();
+
+        assert!(matches!(store.load("blocks").await, Err(CheckpointError::Corrupted(_))));
+    }
+}
@@ -0,0 +1,94 @@
+pub mod file;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Errors that may be returned while loading or saving checkpoints.
+#[derive(Debug, Error)]
+pub enum CheckpointError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Checkpoint file {0} is corrupted (checksum mismatch or unreadable contents)")]
+    Corrupted(PathBuf),
+    #[error("Unable to serialize checkpoint: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[cfg(feature = "postgres")]
+    #[error("Postgres error: {0}")]
+    Postgres(#[from] tokio_postgres::Error),
+}
+
+/// How far an ingestion pipeline got. Only ever saved once everything up to it has been
+/// written to the sink, so resuming from it may re-index a little but never skips anything.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    // The last slot fully sunk (block sources) or the earliest slot reached (backfills).
+    pub last_slot: Option<u64>,
+    // The last signature fully sunk, for signature-based sources.
+    pub last_signature: Option<String>,
+    // When the checkpoint was saved, in seconds since the Unix epoch.
+    pub updated_at: i64,
+    // The slot ranges processed so far, for pipelines that track gaps.
+    #[serde(default)]
+    pub ranges: Vec<(u64, u64)>,
+}
+
+impl Checkpoint {
+    pub fn new(last_slot: Option<u64>, last_signature: Option<String>) -> Self {
+        Checkpoint {
+            last_slot,
+            last_signature,
+            updated_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs() as i64),
+            ranges: Vec::new(),
+        }
+    }
+}
+
+/// Persists checkpoints, namespaced by pipeline name so several ingesters can share a store.
+#[async_trait]
+pub trait CheckpointStore: Send + Sync {
+    /// The last checkpoint saved for `pipeline`, if any.
+    async fn load(&self, pipeline: &str) -> Result<Option<Checkpoint>, CheckpointError>;
+
+    /// Replaces the checkpoint of `pipeline`.
+    async fn save(&self, pipeline: &str, checkpoint: &Checkpoint) -> Result<(), CheckpointError>;
+}
+
+/// A checkpoint store along with the pipeline name to checkpoint under.
+#[derive(Clone)]
+pub struct Checkpointing {
+    pub store: Arc<dyn CheckpointStore>,
+    pub pipeline: String,
+}
+
+impl Checkpointing {
+    pub fn new(store: Arc<dyn CheckpointStore>, pipeline: &str) -> Self {
+        Checkpointing {
+            store,
+            pipeline: pipeline.to_string(),
+        }
+    }
+
+    pub async fn load(&self) -> Result<Option<Checkpoint>, CheckpointError> {
+        self.store.load(&self.pipeline).await
+    }
+
+    pub async fn save(&self, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        self.store.save(&self.pipeline, checkpoint).await
+    }
+}
+
+impl fmt::Debug for Checkpointing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Checkpointing").field("pipeline", &self.pipeline).finish()
+    }
+}
@@ -0,0 +1,86 @@
+use async_trait::async_trait;
+use tokio_postgres::{Client, NoTls};
+use tracing::error;
+
+use crate::checkpoint::{Checkpoint, CheckpointError, CheckpointStore};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS checkpoints (
+        pipeline TEXT PRIMARY KEY,
+        last_slot BIGINT,
+        last_signature TEXT,
+        updated_at BIGINT NOT NULL
+    );
+    ALTER TABLE checkpoints ADD COLUMN IF NOT EXISTS ranges TEXT;
+";
+
+/// Stores checkpoints in a `checkpoints` table, one row per pipeline.
+pub struct PostgresCheckpointStore {
+    client: Client,
+}
+
+impl PostgresCheckpointStore {
+    /// Connects to the database described by `config` (e.g. `host=localhost user=postgres`)
+    /// and makes sure the table exists.
+    pub async fn connect(config: &str) -> Result<Self, CheckpointError> {
+        let (client, connection) = tokio_postgres::connect(config, NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                error!("[spi-wrapper/checkpoint/postgres] Connection closed: {}", err);
+            }
+        });
+        client.batch_execute(SCHEMA).await?;
+
+        Ok(PostgresCheckpointStore { client })
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for PostgresCheckpointStore {
+    async fn load(&self, pipeline: &str) -> Result<Option<Checkpoint>, CheckpointError> {
+        let row = self.client.query_opt(
+            "SELECT last_slot, last_signature, updated_at, ranges FROM checkpoints
+                WHERE pipeline = $1",
+            &[&pipeline],
+        ).await?;
+
+        match row {
+            Some(row) => {
+                let ranges = match row.get::<_, Option<String>>(3) {
+                    Some(ranges) => serde_json::from_str(&ranges)?,
+                    None => Vec::new(),
+                };
+
+                Ok(Some(Checkpoint {
+                    last_slot: row.get::<_, Option<i64>>(0).map(|slot| slot as u64),
+                    last_signature: row.get(1),
+                    updated_at: row.get(2),
+                    ranges,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn save(&self, pipeline: &str, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+        let ranges = serde_json::to_string(&checkpoint.ranges)?;
+        self.client.execute(
+            "INSERT INTO checkpoints (pipeline, last_slot, last_signature, updated_at, ranges)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (pipeline) DO UPDATE SET
+                    last_slot = excluded.last_slot,
+                    last_signature = excluded.last_signature,
+                    updated_at = excluded.updated_at,
+                    ranges = excluded.ranges",
+            &[
+                &pipeline,
+                &checkpoint.last_slot.map(|slot| slot as i64),
+                &checkpoint.last_signature,
+                &checkpoint.updated_at,
+                &ranges,
+            ],
+        ).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,2166 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use solana_client::client_error::ClientError;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+
+use crate::aggregate::{AggregatingSink, AggregatorConfig};
+use crate::checkpoint::file::FileCheckpointStore;
+use crate::checkpoint::{CheckpointError, CheckpointStore, Checkpointing};
+use crate::health::IngestProgress;
+use crate::ingest::backfill::{backfill_address, BackfillConfig};
+use crate::ingest::dedup::{DedupConfig, DedupError, DedupFilter};
+use crate::ingest::filter::{
+    AccountFilter, DecodeFilters, FilterError, MintFilter, ProgramFilter, DEFAULT_RELOAD_INTERVAL,
+    DEFAULT_SKIP_VOTES,
+};
+use crate::ingest::leaders::LeaderResolver;
+use crate::ingest::pool::{RpcPool, RpcPoolConfig};
+use crate::ingest::rpc::{BlockPoller, BlockPollerConfig};
+use crate::ingest::timestamps::{TimestampConfig, TimestampResolver};
+use crate::ingest::IngestError;
+use crate::interner::Symbol;
+use crate::lending::positions::{PositionTrackingSink, PositionsConfig};
+use crate::mints::{MintCache, MintCacheConfig};
+use crate::network::Network;
+use crate::program_versions::{ProgramVersionSink, ProgramVersions};
+use crate::projection::{Projection, ProjectionRule};
+use crate::redaction::{RedactAction, Redaction, RedactionRule, DEFAULT_MAX_VALUE_BYTES};
+use crate::schema::ProgramSchema;
+use crate::registry::{BuiltinProcessor, ProcessorRegistry, RawCapture};
+use crate::sinks::filter::{FilteredSink, TransactionFilter};
+use crate::sinks::router::{RouteRule, SinkRouter};
+use crate::sinks::{Sink, SinkError};
+use crate::stats::{StatsReporter, DEFAULT_STATS_INTERVAL};
+use crate::watchdog::WatchdogConfig;
+
+/// The environment variable `IndexerConfig::from_env` reads the configuration file path from.
+pub const CONFIG_PATH_VARIABLE: &str = "SPI_CONFIG";
+// The caches whose capacity `pipeline.caches` sets.
+const CACHES: &[&str] = &["mints"];
+
+/// Errors that may be returned while loading a configuration or building an indexer out of it.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Unable to read {path}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("{0} is not set")]
+    MissingPath(&'static str),
+    #[error("Invalid TOML: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("{key}: {message}")]
+    Invalid { key: String, message: String },
+    #[error("Sink error: {0}")]
+    Sink(#[from] SinkError),
+    #[error("Checkpoint error: {0}")]
+    Checkpoint(#[from] CheckpointError),
+    #[error("Account filter error: {0}")]
+    Filter(#[from] FilterError),
+    #[error("Deduplication error: {0}")]
+    Dedup(#[from] DedupError),
+    #[error("RPC error: {0}")]
+    Rpc(#[from] ClientError),
+    #[error("Expected {network} (genesis hash {expected}), the endpoints serve {actual}")]
+    GenesisMismatch { network: Network, expected: String, actual: String },
+    #[cfg(feature = "layout-decoder")]
+    #[error("Layout descriptor error: {0}")]
+    Layout(#[from] crate::LayoutError),
+    #[cfg(feature = "plugins")]
+    #[error("Plugin error: {0}")]
+    Plugin(#[from] crate::plugin::PluginError),
+    #[cfg(feature = "notify")]
+    #[error("Notification rules error: {0}")]
+    Notify(#[from] crate::notify::NotifyError),
+}
+
+impl ConfigError {
+    fn invalid<K: Into<String>, M: Into<String>>(key: K, message: M) -> Self {
+        ConfigError::Invalid { key: key.into(), message: message.into() }
+    }
+}
+
+/// Everything needed to run an indexer, as read from a TOML file:
+///
+/// ```toml
+/// [rpc]
+/// endpoints = ["https://api.mainnet-beta.solana.com"]
+/// commitment = "confirmed"
+/// network = "mainnet"
+///
+/// [programs]
+/// enabled = ["token", "system"]
+/// program_ids = { token = ["<fork program id>"] }
+/// networks = { localnet = { token-lending = ["<vanity program id>"] } }
+/// plugins = ["/opt/indexer/libmy_program_processor.so"]
+/// layouts = ["/opt/indexer/layouts/name_service.toml", "/opt/indexer/layouts/vesting"]
+/// sampling = { "Vote111111111111111111111111111111111111111" = 0.01 }
+///
+/// [source]
+/// kind = "poller"
+///
+/// [sink]
+/// kind = "sqlite"
+/// path = "spi-wrapper.sqlite"
+///
+/// [filters]
+/// accounts_file = "/opt/indexer/accounts.txt"
+/// deny_programs = ["Vote111111111111111111111111111111111111111"]
+///
+/// [pipeline]
+/// caches = { mints = 50000 }
+/// namespace_keys = true
+///
+/// [mints]
+/// decimals = { "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v" = 6 }
+/// resolve = true
+///
+/// [aggregate]
+/// window_secs = 3600
+/// sums = ["liquidity_amount"]
+/// reorder_slots = 32
+///
+/// [positions]
+/// reorder_slots = 32
+///
+/// [program_versions]
+/// resolve = true
+///
+/// [projection]
+/// max_properties = 16
+///
+/// [[projection.rules]]
+/// program = "So1endDq2YkqhipRh3WViPa8hdiSpxWy6z3Z6tMCpAo"
+/// function = "deposit-reserve-liquidity"
+/// keys = ["liquidity_amount", "source_liquidity"]
+///
+/// [redaction]
+/// max_value_bytes = 16384
+///
+/// [[redaction.rules]]
+/// key = "memo"
+/// action = "truncate"
+/// bytes = 256
+///
+/// [dedup]
+/// expected_per_day = 10000000
+///
+/// [phoenix.markets.4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg]
+/// base_lot_size = 1000000
+/// quote_lot_size = 1
+/// tick_size_in_quote_lots_per_base_unit = 1000
+/// base_mint = "So11111111111111111111111111111111111111112"
+/// quote_decimals = 6
+///
+/// [notify]
+/// rules_file = "/opt/indexer/rules.toml"
+/// secret = "${WEBHOOK_SECRET}"
+///
+/// [checkpoint]
+/// store = "postgres"
+/// url = "${DATABASE_URL}"
+/// ```
+///
+/// `${NAME}` in any string is replaced by the `NAME` environment variable, so secrets can stay
+/// out of the file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndexerConfig {
+    pub rpc: RpcSettings,
+    #[serde(default)]
+    pub programs: ProgramSettings,
+    #[serde(default)]
+    pub source: SourceSettings,
+    pub sink: SinkSettings,
+    #[serde(default)]
+    pub filters: FilterSettings,
+    #[serde(default)]
+    pub pipeline: PipelineSettings,
+    pub checkpoint: Option<CheckpointSettings>,
+    // UI amounts are only emitted when set.
+    pub mints: Option<MintSettings>,
+    // Instructions are only rolled up into `instruction_aggregates` when set.
+    pub aggregate: Option<AggregateSettings>,
+    // The positions of lending obligations are only tracked into `lending_positions` when set.
+    pub positions: Option<PositionSettings>,
+    // Instructions are only stamped with the version of their program when set.
+    pub program_versions: Option<ProgramVersionSettings>,
+    // Transactions sunk before are only skipped when set.
+    pub dedup: Option<DedupSettings>,
+    // Webhooks are only notified of matching instructions when set.
+    pub notify: Option<NotifySettings>,
+    // The prices and sizes of Phoenix orders are only emitted for the markets set.
+    pub phoenix: Option<PhoenixSettings>,
+    // Every property is kept when not set.
+    #[serde(default)]
+    pub projection: ProjectionSettings,
+    // Values are capped at 16 KiB when not set.
+    #[serde(default)]
+    pub redaction: RedactionSettings,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RpcSettings {
+    pub endpoints: Vec<String>,
+    // processed, confirmed or finalized.
+    #[serde(default = "default_commitment")]
+    pub commitment: String,
+    // Per endpoint. Defaults to the pool's default.
+    pub requests_per_second: Option<f64>,
+    pub max_retries: Option<u32>,
+    // How many transactions a JSON-RPC batch fetches, and accounts a `getMultipleAccounts` call
+    // (100 at most). Defaults to the pool's default; 1 fetches them one at a time.
+    pub batch_size: Option<usize>,
+    // mainnet, devnet, testnet or any other name. Tags every record; mainnet when not set.
+    pub network: Option<String>,
+    // Checked against the endpoints before indexing. The well-known one of the network when not
+    // set; not checked for other networks.
+    pub genesis_hash: Option<String>,
+}
+
+fn default_commitment() -> String {
+    "confirmed".to_string()
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProgramSettings {
+    // The processors to decode with, by name. Every built-in one when not set.
+    pub enabled: Option<Vec<String>>,
+    // Program ids to decode with a processor on top of its well-known ones (e.g. forks deployed
+    // elsewhere), by processor name.
+    #[serde(default)]
+    pub program_ids: BTreeMap<String, Vec<String>>,
+    // The program ids of processors on a network, by network then processor name, replacing the
+    // well-known ones of the processors listed when indexing that network (e.g. the vanity ids
+    // of a test validator). Processors still have to be enabled.
+    #[serde(default)]
+    pub networks: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+    // Shared libraries to load processors from (with the `plugins` feature). Their processors
+    // decode the program ids they declare, over any built-in one.
+    #[serde(default)]
+    pub plugins: Vec<PathBuf>,
+    // Layout descriptors (TOML, or JSON when named `*.json`) to decode programs with (with the
+    // `layout-decoder` feature, see `spi_wrapper::LayoutDescriptor`), over any built-in processor,
+    // or directories of them. They're reloaded as they change (see `spi_wrapper::LayoutRegistry`).
+    #[serde(default)]
+    pub layouts: Vec<PathBuf>,
+    // The fraction (between 0 and 1) of the transactions whose instructions are decoded, by
+    // program id. Every transaction for the programs not listed.
+    #[serde(default)]
+    pub sampling: BTreeMap<String, f64>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceSettings {
+    // poller (follows the chain tip) or backfill (the history of an address).
+    #[serde(default = "default_source")]
+    pub kind: String,
+    // Poller only.
+    pub start_slot: Option<u64>,
+    pub poll_interval_ms: Option<u64>,
+    pub max_slots_per_poll: Option<u64>,
+    #[serde(default)]
+    pub track_finality: bool,
+    // Whether the block time of blocks read without one is estimated from their neighbors.
+    #[serde(default)]
+    pub estimate_timestamps: bool,
+    // Whether timestamps estimated before are estimated again after every poll.
+    #[serde(default)]
+    pub repair_timestamps: bool,
+    // Whether blocks are tagged with their epoch and the identity of their leader.
+    #[serde(default)]
+    pub enrich_leaders: bool,
+    // Backfill only.
+    pub address: Option<String>,
+}
+
+fn default_source() -> String {
+    "poller".to_string()
+}
+
+impl Default for SourceSettings {
+    fn default() -> Self {
+        SourceSettings {
+            kind: default_source(),
+            start_slot: None,
+            poll_interval_ms: None,
+            max_slots_per_poll: None,
+            track_finality: false,
+            estimate_timestamps: false,
+            repair_timestamps: false,
+            enrich_leaders: false,
+            address: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SinkSettings {
+    // sqlite, snapshot (see `sinks::snapshot`), or router to write to several sinks depending on
+    // the program (see `sinks::router`).
+    pub kind: String,
+    // The database file of the sqlite sink, the directory of the snapshot sink.
+    pub path: Option<String>,
+    // The router only: where what no route matches is written, dropped when not set.
+    pub default: Option<Box<SinkSettings>>,
+    // The router only, the first route matching an instruction set not being the only one
+    // getting it.
+    #[serde(default)]
+    pub routes: Vec<RouteSettings>,
+    // The router only: how many writes a failing route holds on to before failing.
+    pub max_pending: Option<usize>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteSettings {
+    // Tags the metrics of the route.
+    pub name: String,
+    // The route matches the instruction sets of one of these programs, functions and networks,
+    // any of them when empty (but not all three).
+    #[serde(default)]
+    pub programs: Vec<String>,
+    #[serde(default)]
+    pub functions: Vec<String>,
+    #[serde(default)]
+    pub networks: Vec<String>,
+    // Where what the route matches is written, unless it's dropped.
+    pub sink: Option<SinkSettings>,
+    #[serde(default)]
+    pub drop: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterSettings {
+    #[serde(default = "default_include_failed")]
+    pub include_failed: bool,
+    // Only transactions invoking one of these program ids are kept. All of them when empty.
+    #[serde(default)]
+    pub programs: Vec<String>,
+    // Only transactions referencing one of these accounts, or one listed in `accounts_file`, are
+    // decoded. All of them when neither is set.
+    #[serde(default)]
+    pub accounts: Vec<String>,
+    // One account per line, reloaded whenever the file changes.
+    pub accounts_file: Option<PathBuf>,
+    // Only transactions involving one of these token mints are decoded. All of them when empty.
+    #[serde(default)]
+    pub mints: Vec<String>,
+    // With `mints`, only decode the instructions involving one of the mints rather than every
+    // instruction of the transactions involving one.
+    #[serde(default)]
+    pub strict_mints: bool,
+    // Only the instructions of these program ids are decoded, inner instructions included. All of
+    // them when empty.
+    #[serde(default)]
+    pub allow_programs: Vec<String>,
+    // The instructions of these program ids are never decoded (e.g. the vote program).
+    #[serde(default)]
+    pub deny_programs: Vec<String>,
+    // Whether the transactions only casting votes, the bulk of every block, are decoded. Opt-in
+    // (see `DEFAULT_SKIP_VOTES`).
+    #[serde(default = "default_include_votes")]
+    pub include_votes: bool,
+}
+
+fn default_include_failed() -> bool {
+    true
+}
+
+fn default_include_votes() -> bool {
+    !DEFAULT_SKIP_VOTES
+}
+
+impl Default for FilterSettings {
+    fn default() -> Self {
+        FilterSettings {
+            include_failed: true,
+            programs: Vec::new(),
+            accounts: Vec::new(),
+            accounts_file: None,
+            mints: Vec::new(),
+            strict_mints: false,
+            allow_programs: Vec::new(),
+            deny_programs: Vec::new(),
+            include_votes: default_include_votes(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MintSettings {
+    // The decimals of well-known mints, by mint address.
+    #[serde(default)]
+    pub decimals: BTreeMap<String, u8>,
+    // How many other mints are cached. Same as `pipeline.caches.mints`.
+    pub capacity: Option<usize>,
+    pub ttl_secs: Option<u64>,
+    // Whether the mints missing from the cache are fetched from RPC in the background.
+    #[serde(default)]
+    pub resolve: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AggregateSettings {
+    // The size of the windows, an hour by default.
+    pub window_secs: Option<u64>,
+    // How long after its end a window still takes records, five minutes by default.
+    pub allowed_lateness_secs: Option<u64>,
+    // The properties summed per window.
+    #[serde(default)]
+    pub sums: Vec<String>,
+    // How many slots later than a slot have to be seen for its transactions to be counted, if
+    // they're put back in slot order. They're counted as they arrive by default.
+    pub reorder_slots: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PositionSettings {
+    // How many slots later than a slot have to be seen for the slot to be applied, 32 by default.
+    pub reorder_slots: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProgramVersionSettings {
+    // Whether the ProgramData accounts of the programs no upgrade is known of are fetched from
+    // RPC in the background.
+    #[serde(default)]
+    pub resolve: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectionSettings {
+    // The most properties any instruction keeps, no limit by default.
+    pub max_properties: Option<usize>,
+    #[serde(default)]
+    pub rules: Vec<ProjectionRuleSettings>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectionRuleSettings {
+    // The program id whose properties the rule applies to.
+    pub program: String,
+    // The function the rule applies to, every function of the program when unset.
+    pub function: Option<String>,
+    // The keys of the properties kept, along with those nested under them.
+    pub keys: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactionSettings {
+    // The most bytes any property value holds, 16 KiB by default. 0 for no limit.
+    pub max_value_bytes: Option<usize>,
+    #[serde(default)]
+    pub rules: Vec<RedactionRuleSettings>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactionRuleSettings {
+    // The program id whose properties the rule applies to, those of every program when unset.
+    pub program: Option<String>,
+    // The key of the properties the rule applies to, every key when unset.
+    pub key: Option<String>,
+    // truncate, hash or drop.
+    pub action: String,
+    // How many bytes truncated values keep.
+    pub bytes: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DedupSettings {
+    // How many of the latest signatures are remembered exactly.
+    pub recent: Option<usize>,
+    // Sizes the bloom filter remembering older signatures, saved next to the checkpoints of the
+    // file store. There's no bloom filter when unset.
+    pub expected_per_day: Option<u64>,
+    pub false_positive_rate: Option<f64>,
+    // How many days of signatures the bloom filter remembers.
+    pub days: Option<usize>,
+    // Whether the sink is asked about the signatures the bloom filter knows instead of skipping
+    // them, as the bloom filter has false positives.
+    #[serde(default)]
+    pub strict: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotifySettings {
+    // The rules (see `spi_wrapper::notify::rules`), reloaded whenever the file changes.
+    pub rules_file: PathBuf,
+    // The key the webhook bodies are signed with (HMAC-SHA256). Unsigned when unset.
+    pub secret: Option<String>,
+    // How many times a failed delivery is retried, 5 by default.
+    pub max_retries: Option<u32>,
+    // How many deliveries may wait before new ones are dropped, 1024 by default.
+    pub queue_size: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PhoenixSettings {
+    // By market address.
+    #[serde(default)]
+    pub markets: BTreeMap<String, PhoenixMarketSettings>,
+}
+
+/// The header of a Phoenix market. The decimals of each of its tokens are either set, or looked
+/// up by mint like the decimals of UI amounts.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PhoenixMarketSettings {
+    pub base_lot_size: u64,
+    pub quote_lot_size: u64,
+    pub tick_size_in_quote_lots_per_base_unit: u64,
+    pub base_decimals: Option<u8>,
+    pub quote_decimals: Option<u8>,
+    pub base_mint: Option<String>,
+    pub quote_mint: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineSettings {
+    // How many blocks the poller fetches at once.
+    pub concurrency: Option<usize>,
+    // Which instructions keep their raw data: never, on-failure (the default) or always.
+    pub capture_raw: Option<String>,
+    // The capacity of the bounded caches (see `spi_wrapper::cache`), by cache name.
+    #[serde(default)]
+    pub caches: BTreeMap<String, usize>,
+    // Whether the keys of properties are prefixed with the namespace of their program.
+    #[serde(default)]
+    pub namespace_keys: bool,
+    // The namespaces of programs, overriding those of their processors, by program id.
+    #[serde(default)]
+    pub namespaces: BTreeMap<String, String>,
+    // Whether the function names and keys renamed to follow the naming policy are also emitted
+    // as they were, see `spi_wrapper::naming`.
+    #[serde(default)]
+    pub legacy_names: bool,
+    // What decoding an instruction may take before a warning is logged (5 by default), before it
+    // counts as a violation (100 by default), and how many violations in a row degrade its
+    // program (3 by default, 0 never to), see `spi_wrapper::watchdog`.
+    pub soft_budget_ms: Option<u64>,
+    pub hard_budget_ms: Option<u64>,
+    pub max_budget_violations: Option<u32>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckpointSettings {
+    // file or postgres.
+    pub store: String,
+    // The directory of the file store.
+    pub dir: Option<String>,
+    // The connection string of the postgres store.
+    pub url: Option<String>,
+    // The name the checkpoint is saved under.
+    #[serde(default = "default_checkpoint_name")]
+    pub name: String,
+}
+
+fn default_checkpoint_name() -> String {
+    "indexer".to_string()
+}
+
+impl FromStr for IndexerConfig {
+    type Err = ConfigError;
+
+    fn from_str(toml: &str) -> Result<Self, ConfigError> {
+        let mut value: toml::Value = toml::from_str(toml)?;
+        interpolate(&mut value, &mut Vec::new())?;
+
+        serde_path_to_error::deserialize(value).map_err(|err| {
+            let key = err.path().to_string();
+            ConfigError::invalid(key, err.into_inner().to_string())
+        })
+    }
+}
+
+impl IndexerConfig {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let toml = std::fs::read_to_string(path)
+            .map_err(|source| ConfigError::Io { path: path.to_path_buf(), source })?;
+
+        toml.parse()
+    }
+
+    /// Loads the file `SPI_CONFIG` points to.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let path = std::env::var_os(CONFIG_PATH_VARIABLE)
+            .ok_or(ConfigError::MissingPath(CONFIG_PATH_VARIABLE))?;
+
+        IndexerConfig::from_path(path)
+    }
+
+    /// Checks every setting that can be checked without connecting to anything.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.registry()?;
+        self.commitment()?;
+        self.filter()?;
+        self.decode_filters()?;
+        self.aggregator_config()?;
+        self.dedup_config()?;
+        self.notify_rules()?;
+        self.cache_capacity("mints")?;
+        if self.rpc.endpoints.is_empty() {
+            return Err(ConfigError::invalid("rpc.endpoints", "at least one endpoint is needed"));
+        }
+        match self.source.kind.as_str() {
+            "poller" => {}
+            "backfill" => {
+                self.backfill_address()?;
+            }
+            kind => return Err(ConfigError::invalid(
+                "source.kind", format!("unknown source `{}`, expected poller or backfill", kind))),
+        }
+        check_sink(&self.sink, "sink", true)?;
+        if self.sink.kind == "router" {
+            if let Some(default) = &self.sink.default {
+                check_sink(default, "sink.default", false)?;
+            }
+            self.routes()?;
+        } else if !self.sink.routes.is_empty() || self.sink.default.is_some() {
+            return Err(ConfigError::invalid("sink.routes", "only the router sink has routes"));
+        }
+        if let Some(checkpoint) = &self.checkpoint {
+            match checkpoint.store.as_str() {
+                "file" if checkpoint.dir.is_none() => {
+                    return Err(ConfigError::invalid(
+                        "checkpoint.dir", "the file store needs a directory"));
+                }
+                "postgres" if checkpoint.url.is_none() => {
+                    return Err(ConfigError::invalid(
+                        "checkpoint.url", "the postgres store needs a connection url"));
+                }
+                "file" | "postgres" => {}
+                store => return Err(ConfigError::invalid(
+                    "checkpoint.store",
+                    format!("unknown store `{}`, expected file or postgres", store))),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The registry decoding with the enabled processors, under their program ids on the
+    /// configured network and the configured ones.
+    pub fn registry(&self) -> Result<ProcessorRegistry, ConfigError> {
+        let network = self.network();
+        let mut registry = ProcessorRegistry::new();
+        registry.set_network(network);
+        let builtins = self.builtins(network)?;
+
+        match &self.programs.enabled {
+            Some(enabled) => {
+                for (index, name) in enabled.iter().enumerate() {
+                    let processor = processor_named(
+                        name, || format!("programs.enabled[{}]", index))?;
+                    for (program_id, _) in builtins.iter().filter(|(_, p)| *p == processor) {
+                        registry.register(program_id, processor);
+                    }
+                }
+            }
+            None => {
+                for (program_id, processor) in &builtins {
+                    registry.register(program_id, *processor);
+                }
+            }
+        }
+
+        for (name, program_ids) in &self.programs.program_ids {
+            let processor = processor_named(name, || format!("programs.program_ids.{}", name))?;
+            for (index, program_id) in program_ids.iter().enumerate() {
+                parse_pubkey(program_id, || format!("programs.program_ids.{}[{}]", name, index))?;
+                registry.register(program_id, processor);
+            }
+        }
+
+        self.load_layouts(&mut registry)?;
+        self.load_plugins(&mut registry)?;
+
+        for (program_id, rate) in &self.programs.sampling {
+            let key = || format!("programs.sampling.{}", program_id);
+            parse_pubkey(program_id, key)?;
+            if !(0.0..=1.0).contains(rate) {
+                return Err(ConfigError::invalid(
+                    key(), format!("the sample rate must be between 0 and 1, not {}", rate)));
+            }
+            registry.sample(program_id, *rate);
+        }
+
+        if let Some(mints) = self.mint_cache()? {
+            registry.set_mint_cache(mints);
+        }
+        if self.program_versions.is_some() {
+            registry.set_program_versions(ProgramVersions::new());
+        }
+        self.add_phoenix_markets(&mut registry)?;
+        registry.set_raw_capture(self.raw_capture()?);
+        registry.set_watchdog(self.watchdog()?);
+        // Before the keys are namespaced, the rules listing them as they're decoded.
+        registry.set_projection(self.projection(&registry.schema())?);
+        registry.set_redaction(self.redaction()?);
+        registry.set_key_namespacing(self.pipeline.namespace_keys);
+        registry.set_legacy_names(self.pipeline.legacy_names);
+        for (program_id, namespace) in &self.pipeline.namespaces {
+            let key = || format!("pipeline.namespaces.{}", program_id);
+            parse_pubkey(program_id, key)?;
+            if namespace.is_empty() || namespace.contains('.') {
+                return Err(ConfigError::invalid(
+                    key(), format!("`{}` is not a namespace, which is a non-empty name without \
+                                    dots", namespace)));
+            }
+            registry.set_namespace(program_id, namespace);
+        }
+
+        Ok(registry)
+    }
+
+    /// Which properties are kept, as set by `[projection]`. The keys listed have to be emitted by
+    /// the functions they're listed for according to `schema`, the schema of the programs the
+    /// registry decodes (see [`ProcessorRegistry::schema`]).
+    pub fn projection(
+        &self,
+        schema: &BTreeMap<String, ProgramSchema>,
+    ) -> Result<Projection, ConfigError> {
+        let settings = &self.projection;
+        let mut rules = Vec::new();
+        for (index, rule) in settings.rules.iter().enumerate() {
+            let key = || format!("projection.rules[{}]", index);
+            parse_pubkey(&rule.program, || format!("{}.program", key()))?;
+            if rule.keys.is_empty() {
+                return Err(ConfigError::invalid(format!("{}.keys", key()),
+                    "at least one key is kept, drop the properties with a redaction rule"));
+            }
+            let rule = ProjectionRule {
+                program: Symbol::intern(&rule.program),
+                function: rule.function.as_deref().map(Symbol::intern),
+                keys: rule.keys.iter().map(|key| Symbol::intern(key)).collect(),
+            };
+            rule.validate(schema).map_err(|err| ConfigError::invalid(key(), err.to_string()))?;
+            rules.push(rule);
+        }
+
+        Ok(Projection { rules, max_properties: settings.max_properties })
+    }
+
+    /// How property values are redacted, as set by `[redaction]`.
+    pub fn redaction(&self) -> Result<Redaction, ConfigError> {
+        let settings = &self.redaction;
+        let mut rules = Vec::new();
+        for (index, rule) in settings.rules.iter().enumerate() {
+            let key = |field: &str| format!("redaction.rules[{}].{}", index, field);
+            if let Some(program) = &rule.program {
+                parse_pubkey(program, || key("program"))?;
+            }
+            let action = match (rule.action.as_str(), rule.bytes) {
+                ("truncate", Some(bytes)) => RedactAction::Truncate(bytes),
+                ("truncate", None) => {
+                    return Err(ConfigError::invalid(key("bytes"), "truncating needs a length"));
+                }
+                ("hash", None) => RedactAction::Hash,
+                ("drop", None) => RedactAction::Drop,
+                ("hash", Some(_)) | ("drop", Some(_)) => return Err(ConfigError::invalid(
+                    key("bytes"), "only truncated values have a length")),
+                (action, _) => return Err(ConfigError::invalid(key("action"), format!(
+                    "unknown action `{}`, expected truncate, hash or drop", action))),
+            };
+            rules.push(RedactionRule {
+                program: rule.program.as_deref().map(Symbol::intern),
+                key: rule.key.as_deref().map(Symbol::intern),
+                action,
+            });
+        }
+
+        Ok(Redaction {
+            rules,
+            max_value_bytes: match settings.max_value_bytes {
+                None => Some(DEFAULT_MAX_VALUE_BYTES),
+                Some(0) => None,
+                Some(max) => Some(max),
+            },
+        })
+    }
+
+    /// The mint cache described by `[mints]`, seeded, if set.
+    pub fn mint_cache(&self) -> Result<Option<MintCache>, ConfigError> {
+        let settings = match &self.mints {
+            Some(settings) => settings,
+            None => return Ok(None),
+        };
+
+        let defaults = MintCacheConfig::default();
+        let capacity = match (self.cache_capacity("mints")?, settings.capacity) {
+            (Some(_), Some(_)) => return Err(ConfigError::invalid(
+                "pipeline.caches.mints", "the capacity of the mint cache is set by mints.capacity \
+                already")),
+            (Some(capacity), None) | (None, Some(capacity)) => capacity,
+            (None, None) => defaults.capacity,
+        };
+        let cache = MintCache::new(MintCacheConfig {
+            capacity,
+            ttl: settings.ttl_secs.map_or(defaults.ttl, Duration::from_secs),
+        });
+        for (mint, decimals) in &settings.decimals {
+            cache.seed(parse_pubkey(mint, || format!("mints.decimals.{}", mint))?, *decimals);
+        }
+
+        Ok(Some(cache))
+    }
+
+    /// The capacity set for the cache `name` in `pipeline.caches`, if any.
+    fn cache_capacity(&self, name: &str) -> Result<Option<usize>, ConfigError> {
+        let mut names = self.pipeline.caches.keys();
+        if let Some(unknown) = names.find(|cache| !CACHES.contains(&cache.as_str())) {
+            return Err(ConfigError::invalid(format!("pipeline.caches.{}", unknown),
+                format!("unknown cache, expected one of {}", CACHES.join(", "))));
+        }
+
+        Ok(self.pipeline.caches.get(name).copied())
+    }
+
+    /// How instructions are rolled up, if `[aggregate]` is set.
+    pub fn aggregator_config(&self) -> Result<Option<AggregatorConfig>, ConfigError> {
+        let settings = match &self.aggregate {
+            Some(settings) => settings,
+            None => return Ok(None),
+        };
+
+        let defaults = AggregatorConfig::default();
+        if settings.window_secs == Some(0) {
+            return Err(ConfigError::invalid("aggregate.window_secs", "windows can't be empty"));
+        }
+
+        Ok(Some(AggregatorConfig {
+            window: settings.window_secs.map_or(defaults.window, Duration::from_secs),
+            allowed_lateness: settings.allowed_lateness_secs
+                .map_or(defaults.allowed_lateness, Duration::from_secs),
+            sums: settings.sums.iter().map(|key| Symbol::intern(key)).collect(),
+            reorder_slots: settings.reorder_slots,
+            ..defaults
+        }))
+    }
+
+    /// How the positions of lending obligations are tracked, if `[positions]` is set.
+    pub fn positions_config(&self) -> Option<PositionsConfig> {
+        let settings = self.positions.as_ref()?;
+        let defaults = PositionsConfig::default();
+
+        Some(PositionsConfig {
+            reorder_slots: settings.reorder_slots.unwrap_or(defaults.reorder_slots),
+            ..defaults
+        })
+    }
+
+    /// The built-in processors along with the program ids they handle on `network`: their
+    /// well-known ones there (see [`BuiltinProcessor::all_on`]), replaced by those configured for
+    /// the network.
+    fn builtins(&self, network: Network) -> Result<Vec<(String, BuiltinProcessor)>, ConfigError> {
+        let mut builtins: Vec<_> = BuiltinProcessor::all_on(network).into_iter()
+            .map(|(program_id, processor)| (program_id.to_string(), processor))
+            .collect();
+        for (network_name, processors) in &self.programs.networks {
+            for (name, program_ids) in processors {
+                let key = || format!("programs.networks.{}.{}", network_name, name);
+                let processor = processor_named(name, key)?;
+                for (index, program_id) in program_ids.iter().enumerate() {
+                    parse_pubkey(program_id, || format!("{}[{}]", key(), index))?;
+                }
+                if Network::from(network_name.as_str()) != network {
+                    continue;
+                }
+
+                builtins.retain(|(_, builtin)| *builtin != processor);
+                builtins.extend(program_ids.iter()
+                    .map(|program_id| (program_id.clone(), processor)));
+            }
+        }
+
+        Ok(builtins)
+    }
+
+    #[cfg(feature = "layout-decoder")]
+    fn load_layouts(&self, registry: &mut ProcessorRegistry) -> Result<(), ConfigError> {
+        if self.programs.layouts.is_empty() {
+            return Ok(());
+        }
+
+        let layouts = crate::LayoutRegistry::new(Vec::new(), self.programs.layouts.clone())?;
+        registry.set_layouts(Arc::new(layouts));
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "layout-decoder"))]
+    fn load_layouts(&self, _registry: &mut ProcessorRegistry) -> Result<(), ConfigError> {
+        if self.programs.layouts.is_empty() {
+            return Ok(());
+        }
+
+        Err(ConfigError::invalid(
+            "programs.layouts", "layout descriptors aren't supported by this build, rebuild with \
+            the `layout-decoder` feature"))
+    }
+
+    #[cfg(feature = "plugins")]
+    fn load_plugins(&self, registry: &mut ProcessorRegistry) -> Result<(), ConfigError> {
+        let mut loader = crate::plugin::PluginLoader::new();
+        for path in &self.programs.plugins {
+            loader.load(path)?;
+        }
+        loader.register(registry);
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "plugins"))]
+    fn load_plugins(&self, _registry: &mut ProcessorRegistry) -> Result<(), ConfigError> {
+        if self.programs.plugins.is_empty() {
+            return Ok(());
+        }
+
+        Err(ConfigError::invalid(
+            "programs.plugins", "plugins aren't supported by this build, rebuild with the \
+            `plugins` feature"))
+    }
+
+    #[cfg(feature = "phoenix")]
+    fn add_phoenix_markets(&self, registry: &mut ProcessorRegistry) -> Result<(), ConfigError> {
+        let markets = self.phoenix.iter().flat_map(|settings| &settings.markets);
+        for (address, settings) in markets {
+            let key = format!("phoenix.markets.{}", address);
+            let market = parse_pubkey(address, || key.clone())?;
+            let lot_sizes = [
+                ("base_lot_size", settings.base_lot_size),
+                ("quote_lot_size", settings.quote_lot_size),
+                ("tick_size_in_quote_lots_per_base_unit",
+                 settings.tick_size_in_quote_lots_per_base_unit),
+            ];
+            if let Some((name, _)) = lot_sizes.iter().find(|(_, size)| *size == 0) {
+                return Err(ConfigError::invalid(format!("{}.{}", key, name), "can't be 0"));
+            }
+            let mint = |side: &str, mint: &Option<String>, decimals: Option<u8>| {
+                match (mint, decimals) {
+                    (Some(mint), _) => {
+                        parse_pubkey(mint, || format!("{}.{}_mint", key, side)).map(Some)
+                    }
+                    (None, Some(_)) => Ok(None),
+                    (None, None) => Err(ConfigError::invalid(
+                        key.clone(), format!("either {0}_decimals or {0}_mint is needed", side))),
+                }
+            };
+
+            registry.set_phoenix_market(market, crate::PhoenixMarket {
+                base_lot_size: settings.base_lot_size,
+                quote_lot_size: settings.quote_lot_size,
+                tick_size_in_quote_lots_per_base_unit: settings
+                    .tick_size_in_quote_lots_per_base_unit,
+                base_decimals: settings.base_decimals,
+                quote_decimals: settings.quote_decimals,
+                base_mint: mint("base", &settings.base_mint, settings.base_decimals)?,
+                quote_mint: mint("quote", &settings.quote_mint, settings.quote_decimals)?,
+            });
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "phoenix"))]
+    fn add_phoenix_markets(&self, _registry: &mut ProcessorRegistry) -> Result<(), ConfigError> {
+        if self.phoenix.is_none() {
+            return Ok(());
+        }
+
+        Err(ConfigError::invalid(
+            "phoenix", "the `phoenix` processor isn't compiled in, rebuild with the `phoenix` \
+            feature"))
+    }
+
+    /// The notification rules, with their file read, if `[notify]` is set.
+    #[cfg(feature = "notify")]
+    pub fn notify_rules(&self) -> Result<Option<crate::notify::RuleSet>, ConfigError> {
+        match &self.notify {
+            Some(settings) => Ok(Some(crate::notify::RuleSet::from_file(&settings.rules_file)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[cfg(not(feature = "notify"))]
+    fn notify_rules(&self) -> Result<(), ConfigError> {
+        if self.notify.is_none() {
+            return Ok(());
+        }
+
+        Err(ConfigError::invalid(
+            "notify", "notifications aren't supported by this build, rebuild with the `notify` \
+            feature"))
+    }
+
+    /// Wraps `sink` to notify the webhooks of the matching instructions, if `[notify]` is set.
+    #[cfg(feature = "notify")]
+    fn notifying(&self, sink: Box<dyn Sink>) -> Result<Box<dyn Sink>, ConfigError> {
+        use crate::notify::{Notifier, NotifyingSink, WebhookConfig};
+
+        let (settings, rules) = match (&self.notify, self.notify_rules()?) {
+            (Some(settings), Some(rules)) => (settings, rules),
+            _ => return Ok(sink),
+        };
+        rules.watch(DEFAULT_RELOAD_INTERVAL);
+
+        let defaults = WebhookConfig::default();
+        let config = WebhookConfig {
+            secret: settings.secret.clone(),
+            max_retries: settings.max_retries.unwrap_or(defaults.max_retries),
+            queue_size: settings.queue_size.unwrap_or(defaults.queue_size),
+            ..defaults
+        };
+
+        Ok(Box::new(NotifyingSink::new(sink, Notifier::new(rules, config))))
+    }
+
+    #[cfg(not(feature = "notify"))]
+    fn notifying(&self, sink: Box<dyn Sink>) -> Result<Box<dyn Sink>, ConfigError> {
+        self.notify_rules()?;
+
+        Ok(sink)
+    }
+
+    /// The network records are tagged with.
+    pub fn network(&self) -> Network {
+        self.rpc.network.as_deref().map_or_else(Network::default, Network::from)
+    }
+
+    /// Fails if the endpoints serve another cluster than the configured network, so that its
+    /// records aren't tagged with the wrong one.
+    pub async fn check_genesis(&self, rpc: &RpcPool) -> Result<(), ConfigError> {
+        let network = self.network();
+        let expected = match self.rpc.genesis_hash.as_deref().or_else(|| network.genesis_hash()) {
+            Some(expected) => expected.to_string(),
+            None => return Ok(()),
+        };
+
+        let actual = rpc.call(|rpc| rpc.get_genesis_hash()).await?.to_string();
+        if actual != expected {
+            return Err(ConfigError::GenesisMismatch { network, expected, actual });
+        }
+
+        Ok(())
+    }
+
+    pub fn raw_capture(&self) -> Result<RawCapture, ConfigError> {
+        match self.pipeline.capture_raw.as_deref() {
+            None | Some("on-failure") => Ok(RawCapture::OnFailure),
+            Some("never") => Ok(RawCapture::Never),
+            Some("always") => Ok(RawCapture::Always),
+            Some(capture) => Err(ConfigError::invalid(
+                "pipeline.capture_raw",
+                format!("unknown capture `{}`, expected never, on-failure or always", capture))),
+        }
+    }
+
+    pub fn watchdog(&self) -> Result<WatchdogConfig, ConfigError> {
+        let mut watchdog = WatchdogConfig::default();
+        if let Some(soft_budget_ms) = self.pipeline.soft_budget_ms {
+            watchdog.soft_budget = Duration::from_millis(soft_budget_ms);
+        }
+        if let Some(hard_budget_ms) = self.pipeline.hard_budget_ms {
+            watchdog.hard_budget = Duration::from_millis(hard_budget_ms);
+        }
+        if let Some(max_budget_violations) = self.pipeline.max_budget_violations {
+            watchdog.max_violations = max_budget_violations;
+        }
+        if watchdog.soft_budget > watchdog.hard_budget {
+            return Err(ConfigError::invalid("pipeline.soft_budget_ms", format!(
+                "the soft budget ({:?}) can't be over the hard one ({:?})", watchdog.soft_budget,
+                watchdog.hard_budget)));
+        }
+
+        Ok(watchdog)
+    }
+
+    pub fn commitment(&self) -> Result<CommitmentConfig, ConfigError> {
+        match self.rpc.commitment.as_str() {
+            "processed" => Ok(CommitmentConfig::processed()),
+            "confirmed" => Ok(CommitmentConfig::confirmed()),
+            "finalized" => Ok(CommitmentConfig::finalized()),
+            commitment => Err(ConfigError::invalid(
+                "rpc.commitment",
+                format!("unknown commitment `{}`, expected processed, confirmed or finalized",
+                        commitment))),
+        }
+    }
+
+    pub fn filter(&self) -> Result<TransactionFilter, ConfigError> {
+        let mut programs = std::collections::HashSet::new();
+        for (index, program_id) in self.filters.programs.iter().enumerate() {
+            parse_pubkey(program_id, || format!("filters.programs[{}]", index))?;
+            programs.insert(Symbol::intern(program_id));
+        }
+
+        Ok(TransactionFilter {
+            include_failed: self.filters.include_failed,
+            programs,
+        })
+    }
+
+    /// The account filter, with its file read, when any account is configured.
+    pub fn account_filter(&self) -> Result<Option<AccountFilter>, ConfigError> {
+        if self.filters.accounts.is_empty() && self.filters.accounts_file.is_none() {
+            return Ok(None);
+        }
+
+        let mut accounts = Vec::new();
+        for (index, account) in self.filters.accounts.iter().enumerate() {
+            accounts.push(parse_pubkey(account, || format!("filters.accounts[{}]", index))?);
+        }
+        let mut filter = AccountFilter::new(accounts);
+        if let Some(path) = &self.filters.accounts_file {
+            filter = filter.with_file(path)?;
+        }
+
+        Ok(Some(filter))
+    }
+
+    /// The program filter, when any program is allowed or denied.
+    pub fn program_filter(&self) -> Result<Option<ProgramFilter>, ConfigError> {
+        if self.filters.allow_programs.is_empty() && self.filters.deny_programs.is_empty() {
+            return Ok(None);
+        }
+
+        let mut filter = ProgramFilter::default();
+        for (index, program_id) in self.filters.allow_programs.iter().enumerate() {
+            parse_pubkey(program_id, || format!("filters.allow_programs[{}]", index))?;
+            filter = filter.allow(Some(Symbol::intern(program_id)));
+        }
+        for (index, program_id) in self.filters.deny_programs.iter().enumerate() {
+            parse_pubkey(program_id, || format!("filters.deny_programs[{}]", index))?;
+            filter = filter.deny(Some(Symbol::intern(program_id)));
+        }
+
+        Ok(Some(filter))
+    }
+
+    /// The mint filter, when any mint is configured.
+    pub fn mint_filter(&self) -> Result<Option<MintFilter>, ConfigError> {
+        if self.filters.mints.is_empty() {
+            return Ok(None);
+        }
+
+        let mut mints = Vec::new();
+        for (index, mint) in self.filters.mints.iter().enumerate() {
+            mints.push(parse_pubkey(mint, || format!("filters.mints[{}]", index))?);
+        }
+
+        Ok(Some(MintFilter::new(mints, self.filters.strict_mints)))
+    }
+
+    /// Everything skipped before being decoded, but for the dedup filter (see
+    /// [`IndexerConfig::dedup_filter`]), which is loaded from disk.
+    pub fn decode_filters(&self) -> Result<DecodeFilters, ConfigError> {
+        Ok(DecodeFilters {
+            skip_votes: !self.filters.include_votes,
+            dedup: None,
+            accounts: self.account_filter()?,
+            mints: self.mint_filter()?,
+            programs: self.program_filter()?,
+        })
+    }
+
+    /// How transactions sunk before are recognized, if `[dedup]` is set.
+    pub fn dedup_config(&self) -> Result<Option<DedupConfig>, ConfigError> {
+        let settings = match &self.dedup {
+            Some(settings) => settings,
+            None => return Ok(None),
+        };
+
+        let defaults = DedupConfig::default();
+        let false_positive_rate = settings.false_positive_rate
+            .unwrap_or(defaults.false_positive_rate);
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(ConfigError::invalid("dedup.false_positive_rate", format!(
+                "the rate must be between 0 and 1 (excluded), not {}", false_positive_rate)));
+        }
+        if settings.days == Some(0) {
+            return Err(ConfigError::invalid("dedup.days", "at least a day is needed"));
+        }
+
+        Ok(Some(DedupConfig {
+            recent: settings.recent.unwrap_or(defaults.recent),
+            expected_per_day: settings.expected_per_day,
+            false_positive_rate,
+            days: settings.days.unwrap_or(defaults.days),
+            strict: settings.strict,
+        }))
+    }
+
+    /// The dedup filter, loaded from the directory of the file checkpoint store when there's
+    /// one. Other stores only remember signatures until the process exits.
+    pub fn dedup_filter(&self) -> Result<Option<DedupFilter>, ConfigError> {
+        let config = match self.dedup_config()? {
+            Some(config) => config,
+            None => return Ok(None),
+        };
+
+        match &self.checkpoint {
+            Some(CheckpointSettings { store, dir: Some(dir), name, .. }) if store == "file" => {
+                let path = Path::new(dir).join(format!("{}.dedup", name));
+                Ok(Some(DedupFilter::load(config, path)?))
+            }
+            _ => Ok(Some(DedupFilter::new(config))),
+        }
+    }
+
+    fn backfill_address(&self) -> Result<Pubkey, ConfigError> {
+        let address = self.source.address.as_ref()
+            .ok_or_else(|| ConfigError::invalid("source.address", "a backfill needs an address"))?;
+
+        parse_pubkey(address, || "source.address".to_string())
+    }
+
+    /// Builds the registry, the ingestion source and the sink described by the configuration.
+    pub async fn build_pipeline(&self) -> Result<Indexer, ConfigError> {
+        self.validate()?;
+        let sink = self.sink().await?;
+
+        self.build_pipeline_with_sink(sink).await
+    }
+
+    /// Same as [`IndexerConfig::build_pipeline`], writing to `sink` instead of the configured
+    /// sink (e.g. a `DryRunSink`). Records go through the same filters on their way.
+    pub async fn build_pipeline_with_sink(
+        &self,
+        sink: Box<dyn Sink>,
+    ) -> Result<Indexer, ConfigError> {
+        self.validate()?;
+
+        let commitment = self.commitment()?;
+        let (rpc, registry) = self.connect().await?;
+        let checkpoint = self.checkpointing().await?;
+        let mut filters = self.decode_filters()?;
+        filters.dedup = self.dedup_filter()?;
+        if let Some(accounts) = &filters.accounts {
+            accounts.watch(DEFAULT_RELOAD_INTERVAL);
+        }
+        #[cfg(feature = "layout-decoder")]
+        if let Some(layouts) = registry.layouts() {
+            layouts.watch(DEFAULT_RELOAD_INTERVAL);
+        }
+
+        let stats = Arc::new(StatsReporter::new(Arc::new(IngestProgress::default())));
+        let source = match self.source.kind.as_str() {
+            "backfill" => Source::Backfill {
+                address: self.backfill_address()?,
+                config: BackfillConfig {
+                    checkpoint,
+                    commitment,
+                    ..BackfillConfig::default()
+                },
+            },
+            _ => {
+                let defaults = BlockPollerConfig::default();
+                let poller = BlockPoller::new(rpc.clone(), registry.clone(), BlockPollerConfig {
+                    commitment,
+                    track_finality: self.source.track_finality,
+                    concurrency: self.pipeline.concurrency.unwrap_or(defaults.concurrency),
+                    start_slot: self.source.start_slot,
+                    poll_interval: self.source.poll_interval_ms
+                        .map_or(defaults.poll_interval, Duration::from_millis),
+                    max_slots_per_poll: self.source.max_slots_per_poll
+                        .unwrap_or(defaults.max_slots_per_poll),
+                    checkpoint,
+                    filters,
+                    repair_timestamps: self.source.repair_timestamps,
+                }).with_stats(stats.clone());
+                let poller = if self.source.estimate_timestamps {
+                    let resolver = TimestampResolver::new(rpc.clone(), TimestampConfig::default());
+                    poller.with_timestamp_resolver(Arc::new(resolver))
+                } else {
+                    poller
+                };
+                Source::Poller(if self.source.enrich_leaders {
+                    poller.with_leader_resolver(Arc::new(LeaderResolver::new(rpc.clone())))
+                } else {
+                    poller
+                })
+            }
+        };
+
+        // The versions of programs pick up from the upgrades the sink has.
+        let sink: Box<dyn Sink> = match registry.program_versions() {
+            Some(versions) => {
+                let mut sink = sink;
+                versions.restore(&sink.program_upgrades().await?);
+                Box::new(ProgramVersionSink::new(sink, versions.clone(), registry.network()))
+            }
+            None => sink,
+        };
+        let sink: Box<dyn Sink> = match self.aggregator_config()? {
+            Some(config) => Box::new(AggregatingSink::new(sink, config)),
+            None => sink,
+        };
+        let sink: Box<dyn Sink> = match self.positions_config() {
+            Some(config) => Box::new(PositionTrackingSink::new(sink, config)),
+            None => sink,
+        };
+        let sink = self.notifying(sink)?;
+
+        Ok(Indexer {
+            registry,
+            rpc,
+            source,
+            sink: Box::new(FilteredSink::new(sink, self.filter()?)),
+            stats,
+        })
+    }
+
+    /// The RPC endpoints and the registry described by the configuration, once the endpoints are
+    /// checked to serve the configured network. Mint decimals and the versions of programs are
+    /// resolved through the endpoints if configured to.
+    pub async fn connect(&self) -> Result<(Arc<RpcPool>, Arc<ProcessorRegistry>), ConfigError> {
+        let mut pool_config = RpcPoolConfig::default();
+        if let Some(requests_per_second) = self.rpc.requests_per_second {
+            pool_config.requests_per_second = requests_per_second;
+        }
+        if let Some(max_retries) = self.rpc.max_retries {
+            pool_config.max_retries = max_retries;
+        }
+        if let Some(batch_size) = self.rpc.batch_size {
+            pool_config.batch_size = batch_size;
+        }
+        let rpc = Arc::new(RpcPool::new(self.rpc.endpoints.clone(), pool_config));
+        self.check_genesis(&rpc).await?;
+        let registry = Arc::new(self.registry()?);
+        let resolve = self.mints.as_ref().map_or(false, |mints| mints.resolve);
+        if let (Some(mints), true) = (registry.mint_cache(), resolve) {
+            mints.resolve_with(rpc.clone());
+        }
+        let resolve = self.program_versions.as_ref().map_or(false, |versions| versions.resolve);
+        if let (Some(versions), true) = (registry.program_versions(), resolve) {
+            versions.resolve_with(rpc.clone());
+        }
+
+        Ok((rpc, registry))
+    }
+
+    /// The sink described by the configuration, written to directly: without the aggregation,
+    /// notifications and filters of a pipeline.
+    pub async fn sink(&self) -> Result<Box<dyn Sink>, ConfigError> {
+        match self.sink.kind.as_str() {
+            "router" => Ok(Box::new(self.router()?)),
+            _ => self.open_sink(&self.sink, "sink"),
+        }
+    }
+
+    /// The router sink described by `[sink]`, with the sinks of its routes opened.
+    pub fn router(&self) -> Result<SinkRouter, ConfigError> {
+        let default = match &self.sink.default {
+            Some(default) => Some(self.open_sink(default, "sink.default")?),
+            None => None,
+        };
+        let mut router = SinkRouter::new(default);
+        if let Some(max_pending) = self.sink.max_pending {
+            router = router.with_max_pending(max_pending);
+        }
+        for (index, (name, rule, sink)) in self.routes()?.into_iter().enumerate() {
+            router = match sink {
+                Some(sink) => {
+                    let key = format!("sink.routes[{}].sink", index);
+                    router.route(name, rule, self.open_sink(sink, &key)?)
+                }
+                None => router.drop_route(name, rule),
+            };
+        }
+
+        Ok(router)
+    }
+
+    /// The routes of the router sink: their name, rule and sink (none for drop routes).
+    fn routes(&self) -> Result<Vec<(&str, RouteRule, Option<&SinkSettings>)>, ConfigError> {
+        let mut routes = Vec::new();
+        for (index, route) in self.sink.routes.iter().enumerate() {
+            let key = |field: &str| format!("sink.routes[{}].{}", index, field);
+            if route.programs.is_empty() && route.functions.is_empty()
+                && route.networks.is_empty() {
+                return Err(ConfigError::invalid(key("programs"),
+                    "a route needs programs, functions or networks to match, what no route \
+                    matches going to sink.default"));
+            }
+            for (program_index, program) in route.programs.iter().enumerate() {
+                parse_pubkey(program, || format!("{}[{}]", key("programs"), program_index))?;
+            }
+            let sink = match (&route.sink, route.drop) {
+                (Some(sink), false) => {
+                    check_sink(sink, &key("sink"), false)?;
+                    Some(sink)
+                }
+                (None, true) => None,
+                (Some(_), true) => {
+                    return Err(ConfigError::invalid(key("sink"), "drop routes have no sink"));
+                }
+                (None, false) => {
+                    return Err(ConfigError::invalid(key("sink"), "missing, unless drop is set"));
+                }
+            };
+            let rule = RouteRule {
+                programs: route.programs.iter().map(|program| Symbol::intern(program)).collect(),
+                functions: route.functions.iter().map(|function| Symbol::intern(function))
+                    .collect(),
+                networks: route.networks.iter().map(|network| Network::from(network.as_str()))
+                    .collect(),
+            };
+            routes.push((route.name.as_str(), rule, sink));
+        }
+
+        Ok(routes)
+    }
+
+    /// The sink described by `settings` (found at `key`), opened. Routers aren't.
+    fn open_sink(&self, settings: &SinkSettings, key: &str) -> Result<Box<dyn Sink>, ConfigError> {
+        match settings.kind.as_str() {
+            #[cfg(feature = "sqlite")]
+            "sqlite" => {
+                let path = settings.path.as_ref()
+                    .ok_or_else(|| ConfigError::invalid(format!("{}.path", key), "missing"))?;
+                let sink = crate::sinks::sqlite::SqliteSink::open(path)?;
+                Ok(Box::new(sink.with_network(self.network())))
+            }
+            #[cfg(feature = "snapshot")]
+            "snapshot" => {
+                let path = settings.path.as_ref()
+                    .ok_or_else(|| ConfigError::invalid(format!("{}.path", key), "missing"))?;
+                Ok(Box::new(crate::sinks::snapshot::SnapshotSink::open(path)?))
+            }
+            kind => Err(ConfigError::invalid(
+                format!("{}.kind", key), format!("the {} sink isn't enabled in this build", kind))),
+        }
+    }
+
+    /// The checkpoint store described by the configuration, if any, connected.
+    pub async fn checkpointing(&self) -> Result<Option<Checkpointing>, ConfigError> {
+        let checkpoint = match &self.checkpoint {
+            Some(checkpoint) => checkpoint,
+            None => return Ok(None),
+        };
+
+        let store: Arc<dyn CheckpointStore> = match checkpoint.store.as_str() {
+            #[cfg(feature = "postgres")]
+            "postgres" => {
+                let url = checkpoint.url.as_ref()
+                    .ok_or_else(|| ConfigError::invalid("checkpoint.url", "missing"))?;
+                Arc::new(crate::checkpoint::postgres::PostgresCheckpointStore::connect(url).await?)
+            }
+            "file" => {
+                let dir = checkpoint.dir.as_ref()
+                    .ok_or_else(|| ConfigError::invalid("checkpoint.dir", "missing"))?;
+                Arc::new(FileCheckpointStore::new(dir))
+            }
+            store => return Err(ConfigError::invalid(
+                "checkpoint.store", format!("the {} store isn't enabled in this build", store))),
+        };
+
+        Ok(Some(Checkpointing::new(store, &checkpoint.name)))
+    }
+}
+
+/// Where an indexer reads from.
+pub enum Source {
+    Poller(BlockPoller),
+    Backfill { address: Pubkey, config: BackfillConfig },
+}
+
+/// An indexer built out of an [`IndexerConfig`].
+pub struct Indexer {
+    pub registry: Arc<ProcessorRegistry>,
+    pub rpc: Arc<RpcPool>,
+    pub source: Source,
+    pub sink: Box<dyn Sink>,
+    // Where the throughput and lag of the poller are reported.
+    pub stats: Arc<StatsReporter>,
+}
+
+impl Indexer {
+    /// Runs the source until it fails (or, for a backfill, until the history is exhausted),
+    /// logging its stats every `DEFAULT_STATS_INTERVAL`.
+    pub async fn run(&mut self) -> Result<(), IngestError> {
+        let _logger = self.stats.clone().spawn_logger(DEFAULT_STATS_INTERVAL);
+        match &self.source {
+            Source::Poller(poller) => poller.run(&mut self.sink).await,
+            Source::Backfill { address, config } => {
+                backfill_address(&self.rpc, address, None, &mut self.sink, &self.registry,
+                                 config, |_| {}).await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn processor_named<K: FnOnce() -> String>(
+    name: &str,
+    key: K,
+) -> Result<BuiltinProcessor, ConfigError> {
+    let processor = BuiltinProcessor::from_name(name)
+        .ok_or_else(|| ConfigError::invalid(key(), format!("unknown program `{}`", name)))?;
+    if !processor.is_enabled() {
+        return Err(ConfigError::invalid(key(), format!(
+            "the `{}` processor isn't compiled in, rebuild with the `{}` feature", name, name)));
+    }
+
+    Ok(processor)
+}
+
+/// Checks the sink described by `settings`, found at `key`: a router only when `top_level`.
+fn check_sink(settings: &SinkSettings, key: &str, top_level: bool) -> Result<(), ConfigError> {
+    match settings.kind.as_str() {
+        "sqlite" if settings.path.is_none() => {
+            Err(ConfigError::invalid(format!("{}.path", key), "the sqlite sink needs a path"))
+        }
+        "sqlite" => Ok(()),
+        "snapshot" if settings.path.is_none() => {
+            Err(ConfigError::invalid(format!("{}.path", key), "the snapshot sink needs a path"))
+        }
+        "snapshot" => Ok(()),
+        "router" if !top_level => {
+            Err(ConfigError::invalid(format!("{}.kind", key), "routers can't be nested"))
+        }
+        "router" => Ok(()),
+        kind => Err(ConfigError::invalid(format!("{}.kind", key),
+            format!("unknown sink `{}`, expected sqlite, snapshot or router", kind))),
+    }
+}
+
+fn parse_pubkey<K: FnOnce() -> String>(value: &str, key: K) -> Result<Pubkey, ConfigError> {
+    Pubkey::from_str(value)
+        .map_err(|_| ConfigError::invalid(key(), format!("`{}` is not a valid address", value)))
+}
+
+/// Replaces `${NAME}` in every string with the value of the `NAME` environment variable.
+pub(crate) fn interpolate(
+    value: &mut toml::Value,
+    path: &mut Vec<String>,
+) -> Result<(), ConfigError> {
+    match value {
+        toml::Value::String(string) => {
+            let mut interpolated = String::with_capacity(string.len());
+            let mut rest = string.as_str();
+            while let Some(start) = rest.find("${") {
+                let end = rest[start..].find('}').ok_or_else(|| ConfigError::invalid(
+                    path.join("."), "unterminated `${`"))?;
+                let name = &rest[start + 2..start + end];
+                let variable = std::env::var(name).map_err(|_| ConfigError::invalid(
+                    path.join("."), format!("environment variable `{}` is not set", name)))?;
+                interpolated.push_str(&rest[..start]);
+                interpolated.push_str(&variable);
+                rest = &rest[start + end + 1..];
+            }
+            interpolated.push_str(rest);
+            *string = interpolated;
+        }
+        toml::Value::Array(values) => {
+            for (index, value) in values.iter_mut().enumerate() {
+                let last = path.pop().unwrap_or_default();
+                path.push(format!("{}[{}]", last, index));
+                let result = interpolate(value, path);
+                path.pop();
+                path.push(last);
+                result?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                path.push(key.clone());
+                let result = interpolate(value, path);
+                path.pop();
+                result?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "token")]
+    use crate::programs::native_token;
+    #[cfg(feature = "token")]
+    use crate::registry::sampled_in;
+
+    const CONFIG: &str = r#"
+        [rpc]
+        endpoints = ["http://localhost:8899"]
+
+        [programs]
+        enabled = ["token", "system"]
+        program_ids = { token = ["Fork111111111111111111111111111111111111111"] }
+
+        [sink]
+        kind = "sqlite"
+        path = "${SPI_CONFIG_TEST_PATH}"
+    "#;
+
+    #[test]
+    #[cfg(all(feature = "token", feature = "system"))]
+    fn loads_and_interpolates() {
+        std::env::set_var("SPI_CONFIG_TEST_PATH", "/tmp/indexer.sqlite");
+        let config: IndexerConfig = CONFIG.parse().unwrap();
+        assert_eq!(config.sink.path.as_deref(), Some("/tmp/indexer.sqlite"));
+        config.validate().unwrap();
+
+        let registry = config.registry().unwrap();
+        assert_eq!(registry.get(native_token::PROGRAM_ADDRESS), Some(BuiltinProcessor::Token));
+        assert_eq!(registry.get("Fork111111111111111111111111111111111111111"),
+                   Some(BuiltinProcessor::Token));
+        assert_eq!(registry.get("Vote111111111111111111111111111111111111111"), None);
+    }
+
+    #[test]
+    fn vote_transactions_are_opt_in() {
+        std::env::set_var("SPI_CONFIG_TEST_PATH", "/tmp/indexer.sqlite");
+        let config: IndexerConfig = CONFIG.parse().unwrap();
+        assert!(config.decode_filters().unwrap().skip_votes);
+
+        let config: IndexerConfig = format!("{}\n[filters]\ninclude_votes = true\n", CONFIG)
+            .parse()
+            .unwrap();
+        assert!(!config.decode_filters().unwrap().skip_votes);
+    }
+
+    #[test]
+    #[cfg(all(feature = "token", feature = "system"))]
+    fn errors_point_at_the_offending_key() {
+        std::env::set_var("SPI_CONFIG_TEST_PATH", "/tmp/indexer.sqlite");
+        let unknown_program = CONFIG.replace(r#"["token", "system"]"#, r#"["token", "tokens"]"#);
+        let config: IndexerConfig = unknown_program.parse().unwrap();
+        assert_eq!(config.validate().unwrap_err().to_string(),
+                   "programs.enabled[1]: unknown program `tokens`");
+
+        let missing_path = CONFIG.replace(r#"path = "${SPI_CONFIG_TEST_PATH}""#, "");
+        let config: IndexerConfig = missing_path.parse().unwrap();
+        assert_eq!(config.validate().unwrap_err().to_string(),
+                   "sink.path: the sqlite sink needs a path");
+
+        let unset_variable = CONFIG.replace("SPI_CONFIG_TEST_PATH", "SPI_CONFIG_TEST_UNSET");
+        assert_eq!(unset_variable.parse::<IndexerConfig>().unwrap_err().to_string(),
+                   "sink.path: environment variable `SPI_CONFIG_TEST_UNSET` is not set");
+    }
+
+    #[test]
+    #[cfg(not(feature = "serum-market"))]
+    fn programs_not_compiled_in_are_reported() {
+        let config: IndexerConfig = r#"
+            [rpc]
+            endpoints = ["http://localhost:8899"]
+
+            [programs]
+            enabled = ["serum-market"]
+
+            [sink]
+            kind = "sqlite"
+            path = "indexer.sqlite"
+        "#.parse().unwrap();
+
+        assert_eq!(config.validate().unwrap_err().to_string(),
+                   "programs.enabled[0]: the `serum-market` processor isn't compiled in, rebuild \
+                   with the `serum-market` feature");
+    }
+
+    #[test]
+    #[cfg(feature = "token")]
+    fn sampling_is_configured_per_program() {
+        let config = |rate: &str| -> IndexerConfig {
+            format!(r#"
+                [rpc]
+                endpoints = ["http://localhost:8899"]
+
+                [programs.sampling]
+                "{}" = {}
+
+                [sink]
+                kind = "sqlite"
+                path = "indexer.sqlite"
+            "#, native_token::PROGRAM_ADDRESS, rate).parse().unwrap()
+        };
+
+        let registry = config("0.5").registry().unwrap();
+        let rate = registry.sample_rate(native_token::PROGRAM_ADDRESS);
+        assert_eq!(rate, 0.5);
+        assert_eq!(registry.sample_rate("Vote111111111111111111111111111111111111111"), 1.0);
+
+        // Kept or dropped by the hash of the signature alone, the same way every time.
+        for _ in 0..2 {
+            assert!(sampled_in("first", rate));
+            assert!(sampled_in("second", rate));
+            assert!(!sampled_in("signature", rate));
+            assert!(!sampled_in(&"1".repeat(64), rate));
+        }
+
+        assert_eq!(config("1.5").registry().unwrap_err().to_string(),
+                   format!("programs.sampling.{}: the sample rate must be between 0 and 1, not 1.5",
+                           native_token::PROGRAM_ADDRESS));
+    }
+
+    #[test]
+    fn mint_decimals_are_seeded() {
+        let config: IndexerConfig = r#"
+            [rpc]
+            endpoints = ["http://localhost:8899"]
+
+            [sink]
+            kind = "sqlite"
+            path = "indexer.sqlite"
+
+            [mints]
+            decimals = { "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v" = 6 }
+            capacity = 100
+        "#.parse().unwrap();
+
+        let registry = config.registry().unwrap();
+        let usdc = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".parse().unwrap();
+        assert_eq!(registry.mint_cache().unwrap().get(&usdc), Some(6));
+
+        let without: IndexerConfig = r#"
+            [rpc]
+            endpoints = ["http://localhost:8899"]
+
+            [sink]
+            kind = "sqlite"
+            path = "indexer.sqlite"
+        "#.parse().unwrap();
+        assert!(without.registry().unwrap().mint_cache().is_none());
+    }
+
+    #[test]
+    fn phoenix_markets_are_configured() {
+        let config = |market: &str| -> IndexerConfig {
+            format!(r#"
+                [rpc]
+                endpoints = ["http://localhost:8899"]
+
+                [sink]
+                kind = "sqlite"
+                path = "indexer.sqlite"
+
+                [phoenix.markets.4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg]
+                {}
+            "#, market).parse().unwrap()
+        };
+        let sol_usdc = "base_lot_size = 1000000\nquote_lot_size = 1\n\
+            tick_size_in_quote_lots_per_base_unit = 1000\n\
+            base_mint = \"So11111111111111111111111111111111111111112\"\nquote_decimals = 6";
+
+        #[cfg(feature = "phoenix")]
+        {
+            let registry = config(sol_usdc).registry().unwrap();
+            let market = "4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg".parse().unwrap();
+            let market = registry.phoenix_market(&market).unwrap();
+            assert_eq!((market.base_lot_size, market.quote_decimals), (1_000_000, Some(6)));
+            assert!(market.base_mint.is_some());
+
+            let without_decimals = sol_usdc.replace("quote_decimals = 6", "");
+            assert_eq!(config(&without_decimals).registry().unwrap_err().to_string(),
+                       "phoenix.markets.4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg: either \
+                       quote_decimals or quote_mint is needed");
+            let without_ticks = sol_usdc.replace("= 1000\n", "= 0\n");
+            assert_eq!(config(&without_ticks).registry().unwrap_err().to_string(),
+                       "phoenix.markets.4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg.\
+                       tick_size_in_quote_lots_per_base_unit: can't be 0");
+        }
+        #[cfg(not(feature = "phoenix"))]
+        assert_eq!(config(sol_usdc).validate().unwrap_err().to_string(),
+                   "phoenix: the `phoenix` processor isn't compiled in, rebuild with the \
+                   `phoenix` feature");
+    }
+
+    #[test]
+    fn cache_capacities_are_set_per_cache() {
+        let config = |settings: &str| -> IndexerConfig {
+            format!(r#"
+                [rpc]
+                endpoints = ["http://localhost:8899"]
+
+                [sink]
+                kind = "sqlite"
+                path = "indexer.sqlite"
+
+                {}
+            "#, settings).parse().unwrap()
+        };
+
+        let mints = config("[pipeline]\ncaches = { mints = 2 }\n[mints]").mint_cache().unwrap()
+            .unwrap();
+        for _ in 0..3 {
+            mints.insert(Pubkey::new_unique(), 6);
+        }
+        assert_eq!(mints.len(), 2);
+        assert_eq!(mints.stats().evictions, 1);
+
+        assert_eq!(config("[pipeline]\ncaches = { mints = 2 }\n[mints]\ncapacity = 3")
+                       .mint_cache().unwrap_err().to_string(),
+                   "pipeline.caches.mints: the capacity of the mint cache is set by \
+                   mints.capacity already");
+        assert_eq!(config("[pipeline]\ncaches = { labels = 2 }").validate().unwrap_err()
+                       .to_string(),
+                   "pipeline.caches.labels: unknown cache, expected one of mints");
+    }
+
+    #[test]
+    fn aggregation_is_configured() {
+        let config = |aggregate: &str| -> IndexerConfig {
+            format!(r#"
+                [rpc]
+                endpoints = ["http://localhost:8899"]
+
+                [sink]
+                kind = "sqlite"
+                path = "indexer.sqlite"
+
+                {}
+            "#, aggregate).parse().unwrap()
+        };
+
+        assert!(config("").aggregator_config().unwrap().is_none());
+        let aggregator = config("[aggregate]\nwindow_secs = 60\nsums = [\"amount\"]")
+            .aggregator_config().unwrap().unwrap();
+        assert_eq!(aggregator.window, Duration::from_secs(60));
+        assert_eq!(aggregator.allowed_lateness, AggregatorConfig::default().allowed_lateness);
+        assert_eq!(aggregator.sums, vec![Symbol::intern("amount")]);
+        assert_eq!(aggregator.reorder_slots, None);
+        let reordered = config("[aggregate]\nreorder_slots = 8").aggregator_config().unwrap();
+        assert_eq!(reordered.unwrap().reorder_slots, Some(8));
+
+        assert_eq!(config("[aggregate]\nwindow_secs = 0").validate().unwrap_err().to_string(),
+                   "aggregate.window_secs: windows can't be empty");
+    }
+
+    #[test]
+    fn the_network_defaults_to_mainnet() {
+        let config = |network: &str| -> IndexerConfig {
+            format!(r#"
+                [rpc]
+                endpoints = ["http://localhost:8899"]
+                {}
+
+                [sink]
+                kind = "sqlite"
+                path = "indexer.sqlite"
+            "#, network).parse().unwrap()
+        };
+
+        assert_eq!(config("").network(), Network::Mainnet);
+        assert_eq!(config("").registry().unwrap().network(), Network::Mainnet);
+        assert_eq!(config("network = \"devnet\"").registry().unwrap().network(), Network::Devnet);
+        let local = config("network = \"localnet\"").network();
+        assert_eq!(local, Network::Custom(Symbol::intern("localnet")));
+        assert_eq!(local.genesis_hash(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "token")]
+    fn programs_are_decoded_under_their_ids_on_the_configured_network() {
+        const TOKEN: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+        const VANITY: &str = "Tok3n11111111111111111111111111111111111111";
+        let config = |network: &str, networks: &str| -> IndexerConfig {
+            format!(r#"
+                [rpc]
+                endpoints = ["http://localhost:8899"]
+                network = "{}"
+
+                [programs]
+                networks = {{ {} }}
+
+                [sink]
+                kind = "sqlite"
+                path = "indexer.sqlite"
+            "#, network, networks).parse().unwrap()
+        };
+        let networks = format!("localnet = {{ token = [\"{}\"] }}", VANITY);
+
+        let local = config("localnet", &networks).registry().unwrap();
+        assert_eq!(local.get(VANITY), Some(BuiltinProcessor::Token));
+        assert_eq!(local.get(TOKEN), None);
+        let mainnet = config("mainnet", &networks).registry().unwrap();
+        assert_eq!(mainnet.get(TOKEN), Some(BuiltinProcessor::Token));
+        assert_eq!(mainnet.get(VANITY), None);
+
+        // The ids of every network are checked, whichever is indexed.
+        let err = config("mainnet", "devnet = { token = [\"tokens\"] }").registry().unwrap_err();
+        assert_eq!(err.to_string(),
+                   "programs.networks.devnet.token[0]: `tokens` is not a valid address");
+    }
+
+    #[test]
+    fn raw_data_is_captured_on_failure_by_default() {
+        let config = |pipeline: &str| -> IndexerConfig {
+            format!(r#"
+                [rpc]
+                endpoints = ["http://localhost:8899"]
+
+                [pipeline]
+                {}
+
+                [sink]
+                kind = "sqlite"
+                path = "indexer.sqlite"
+            "#, pipeline).parse().unwrap()
+        };
+
+        assert_eq!(config("").registry().unwrap().raw_capture(), RawCapture::OnFailure);
+        let always = config("capture_raw = \"always\"").registry().unwrap();
+        assert_eq!(always.raw_capture(), RawCapture::Always);
+        assert_eq!(config("capture_raw = \"sometimes\"").validate().unwrap_err().to_string(),
+                   "pipeline.capture_raw: unknown capture `sometimes`, expected never, on-failure \
+                   or always");
+    }
+
+    #[test]
+    fn keys_are_namespaced_when_set() {
+        const COUNTER: &str = "Counter111111111111111111111111111111111111";
+        let config = |pipeline: &str| -> IndexerConfig {
+            format!(r#"
+                [rpc]
+                endpoints = ["http://localhost:8899"]
+
+                [pipeline]
+                {}
+
+                [sink]
+                kind = "sqlite"
+                path = "indexer.sqlite"
+            "#, pipeline).parse().unwrap()
+        };
+
+        assert!(!config("").registry().unwrap().key_namespacing());
+        let namespaced = config(&format!(
+            "namespace_keys = true\nnamespaces = {{ {} = \"counter\" }}", COUNTER));
+        let namespaced = namespaced.registry().unwrap();
+        assert!(namespaced.key_namespacing());
+        assert_eq!(namespaced.namespace(COUNTER), Some("counter"));
+        assert_eq!(config(&format!("namespaces = {{ {} = \"a.b\" }}", COUNTER)).validate()
+                       .unwrap_err().to_string(),
+                   format!("pipeline.namespaces.{}: `a.b` is not a namespace, which is a \
+                           non-empty name without dots", COUNTER));
+    }
+
+    #[test]
+    fn dispatch_budgets_are_read_in_milliseconds() {
+        let config = |pipeline: &str| -> IndexerConfig {
+            format!(r#"
+                [rpc]
+                endpoints = ["http://localhost:8899"]
+
+                [pipeline]
+                {}
+
+                [sink]
+                kind = "sqlite"
+                path = "indexer.sqlite"
+            "#, pipeline).parse().unwrap()
+        };
+
+        assert_eq!(config("").watchdog().unwrap(), WatchdogConfig::default());
+        let watchdog = config("soft_budget_ms = 2\nhard_budget_ms = 20\nmax_budget_violations = 0")
+            .watchdog()
+            .unwrap();
+        assert_eq!(watchdog, WatchdogConfig {
+            soft_budget: Duration::from_millis(2),
+            hard_budget: Duration::from_millis(20),
+            max_violations: 0,
+        });
+        assert_eq!(config("soft_budget_ms = 200").validate().unwrap_err().to_string(),
+                   "pipeline.soft_budget_ms: the soft budget (200ms) can't be over the hard one \
+                   (100ms)");
+    }
+
+    #[test]
+    fn notification_rules_are_read_with_the_configuration() {
+        let config = |rules_file: &str| -> IndexerConfig {
+            format!(r#"
+                [rpc]
+                endpoints = ["http://localhost:8899"]
+
+                [sink]
+                kind = "sqlite"
+                path = "indexer.sqlite"
+
+                [notify]
+                rules_file = "{}"
+            "#, rules_file).parse().unwrap()
+        };
+        let path = std::env::temp_dir()
+            .join(format!("spi-wrapper-config-rules-{}.toml", std::process::id()));
+        std::fs::write(&path, "[[rules]]\nname = \"all\"\nwebhooks = [\"http://localhost\"]\n")
+            .unwrap();
+        let config = config(&path.display().to_string());
+
+        #[cfg(feature = "notify")]
+        assert_eq!(config.notify_rules().unwrap().unwrap().rules()[0].name, "all");
+        #[cfg(not(feature = "notify"))]
+        assert_eq!(config.validate().unwrap_err().to_string(),
+                   "notify: notifications aren't supported by this build, rebuild with the \
+                   `notify` feature");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn redaction_is_configured() {
+        let config = |redaction: &str| -> IndexerConfig {
+            format!(r#"
+                [rpc]
+                endpoints = ["http://localhost:8899"]
+
+                [sink]
+                kind = "sqlite"
+                path = "indexer.sqlite"
+
+                {}
+            "#, redaction).parse().unwrap()
+        };
+
+        assert_eq!(config("").redaction().unwrap(), Redaction::default());
+        let unlimited = config("[redaction]\nmax_value_bytes = 0").redaction().unwrap();
+        assert_eq!(unlimited, Redaction::none());
+
+        let rules = "[[redaction.rules]]\nkey = \"memo\"\naction = \"truncate\"\nbytes = 256\n\
+            [[redaction.rules]]\nprogram = \"MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr\"\n\
+            action = \"drop\"";
+        let redaction = config(rules).redaction().unwrap();
+        assert_eq!(redaction.rules, vec![
+            RedactionRule {
+                program: None,
+                key: Some(Symbol::intern("memo")),
+                action: RedactAction::Truncate(256),
+            },
+            RedactionRule {
+                program: Some(Symbol::intern("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr")),
+                key: None,
+                action: RedactAction::Drop,
+            },
+        ]);
+
+        let error = |rule: &str| {
+            config(&format!("[[redaction.rules]]\n{}", rule)).validate().unwrap_err().to_string()
+        };
+        assert_eq!(error("key = \"memo\"\naction = \"truncate\""),
+                   "redaction.rules[0].bytes: truncating needs a length");
+        assert_eq!(error("key = \"memo\"\naction = \"hash\"\nbytes = 8"),
+                   "redaction.rules[0].bytes: only truncated values have a length");
+        assert_eq!(error("key = \"memo\"\naction = \"encrypt\""),
+                   "redaction.rules[0].action: unknown action `encrypt`, expected truncate, hash \
+                   or drop");
+    }
+
+    #[test]
+    #[cfg(feature = "system")]
+    fn projection_is_checked_against_the_schema() {
+        let config = |projection: &str| -> IndexerConfig {
+            format!(r#"
+                [rpc]
+                endpoints = ["http://localhost:8899"]
+
+                [sink]
+                kind = "sqlite"
+                path = "indexer.sqlite"
+
+                [projection]
+                max_properties = 4
+
+                [[projection.rules]]
+                program = "11111111111111111111111111111111"
+                {}
+            "#, projection).parse().unwrap()
+        };
+
+        let registry = config("function = \"transfer\"\nkeys = [\"lamports\"]")
+            .registry().unwrap();
+        assert_eq!(registry.projection(), &Projection {
+            rules: vec![ProjectionRule {
+                program: Symbol::intern("11111111111111111111111111111111"),
+                function: Some(Symbol::intern("transfer")),
+                keys: vec![Symbol::intern("lamports")],
+            }],
+            max_properties: Some(4),
+        });
+
+        let error = |rule: &str| config(rule).validate().unwrap_err().to_string();
+        assert_eq!(error("function = \"transfer\"\nkeys = [\"lamport\"]"),
+                   "projection.rules[0]: no property `lamport` is emitted by transfer of system");
+        assert_eq!(error("function = \"transfers\"\nkeys = [\"lamports\"]"),
+                   "projection.rules[0]: the processor of system has no function `transfers`");
+        assert_eq!(error("keys = []"),
+                   "projection.rules[0].keys: at least one key is kept, drop the properties with \
+                   a redaction rule");
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn sinks_are_routed() {
+        let config = |sink: &str| -> IndexerConfig {
+            format!(r#"
+                [rpc]
+                endpoints = ["http://localhost:8899"]
+
+                [sink]
+                kind = "router"
+                {}
+            "#, sink).parse().unwrap()
+        };
+
+        let routes = r#"
+            default = { kind = "sqlite", path = ":memory:" }
+
+            [[sink.routes]]
+            name = "tokens"
+            programs = ["TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"]
+            sink = { kind = "sqlite", path = ":memory:" }
+
+            [[sink.routes]]
+            name = "devnet"
+            networks = ["devnet"]
+            drop = true
+        "#;
+        let routed = config(routes);
+        routed.validate().unwrap();
+        let names: Vec<_> = routed.router().unwrap().stats().into_iter()
+            .map(|stats| stats.name)
+            .collect();
+        assert_eq!(names, vec!["default", "tokens"]);
+
+        let error = |route: &str| {
+            config(&format!("[[sink.routes]]\nname = \"route\"\n{}", route)).validate()
+                .unwrap_err().to_string()
+        };
+        assert_eq!(error("drop = true"),
+                   "sink.routes[0].programs: a route needs programs, functions or networks to \
+                   match, what no route matches going to sink.default");
+        assert_eq!(error("functions = [\"transfer\"]"),
+                   "sink.routes[0].sink: missing, unless drop is set");
+        assert_eq!(error("functions = [\"transfer\"]\nsink = { kind = \"router\" }"),
+                   "sink.routes[0].sink.kind: routers can't be nested");
+        assert_eq!(error("programs = [\"tokens\"]\ndrop = true"),
+                   "sink.routes[0].programs[0]: `tokens` is not a valid address");
+    }
+}
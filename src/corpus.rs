@@ -0,0 +1,433 @@
+//! Corpora of real instruction data, to check decoders against the breadth of what programs are
+//! invoked with rather than a few hand-picked fixtures.
+//!
+//! A corpus holds the instructions of a program sampled out of its recent transactions (see
+//! [`collect`]): the data of every instruction and how many accounts it was invoked with,
+//! grouped by discriminant (the first bytes of the data). Identical data is kept once, and every
+//! discriminant is capped, so that the few instructions a program is mostly invoked with don't
+//! crowd out the others. Entries are sorted, the same instructions making the same corpus.
+//!
+//! [`check`] decodes every entry with a registry, reporting the share of each discriminant that
+//! decoded. Compared with the report of a previous run (see [`CheckReport::diff`]), a decoding
+//! regression shows up as a drop in the rate of its discriminant.
+//!
+//! Corpus files start with the `SPIC` magic and the version of the format (a little-endian
+//! `u16`), followed by the bincode encoded [`Corpus`].
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use thiserror::Error;
+
+use crate::ingest::pool::RpcPool;
+use crate::ingest::{fetch_transactions, IngestError};
+use crate::interner::Symbol;
+use crate::registry::{DecodeStatus, ProcessorRegistry};
+use crate::transaction::RawTransaction;
+use crate::Instruction;
+
+const MAGIC: &[u8; 4] = b"SPIC";
+/// The version of the format corpora are written in.
+pub const CORPUS_VERSION: u16 = 1;
+// The most getSignaturesForAddress returns at once.
+const SIGNATURES_PER_PAGE: usize = 1000;
+
+/// Errors that may be returned while collecting, writing or reading corpora.
+#[derive(Debug, Error)]
+pub enum CorpusError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Not a corpus (bad magic header)")]
+    NotACorpus,
+    #[error("Corpus version {0} isn't supported, only {} is", CORPUS_VERSION)]
+    UnsupportedVersion(u16),
+    #[error("Invalid corpus: {0}")]
+    Encoding(#[from] bincode::Error),
+    #[error("Unable to sample transactions: {0}")]
+    Ingest(#[from] IngestError),
+}
+
+/// How instructions are sampled into a corpus.
+#[derive(Clone, Copy, Debug)]
+pub struct CorpusConfig {
+    // How many bytes of the data the instructions are grouped by: 1 for most native and SPL
+    // programs, 8 for Anchor ones.
+    pub discriminant_len: usize,
+    // How many distinct instructions are kept of every discriminant.
+    pub per_discriminant: usize,
+}
+
+impl Default for CorpusConfig {
+    fn default() -> Self {
+        CorpusConfig { discriminant_len: 1, per_discriminant: 100 }
+    }
+}
+
+/// The sampled instructions of a program.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Corpus {
+    pub program: String,
+    pub discriminant_len: usize,
+    // Sorted by discriminant, then data.
+    pub entries: Vec<CorpusEntry>,
+}
+
+/// An instruction of a corpus.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CorpusEntry {
+    // The first bytes of the data, hex encoded: the whole data when it's shorter.
+    pub discriminant: String,
+    pub data: Vec<u8>,
+    // How many accounts the instruction was invoked with.
+    pub accounts: u8,
+    // The transaction it was sampled from, to look it up.
+    pub signature: String,
+}
+
+impl Corpus {
+    /// Writes the corpus to `path`, replacing whatever is there.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), CorpusError> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&CORPUS_VERSION.to_le_bytes());
+        bytes.extend(bincode::serialize(self)?);
+        fs::write(path, bytes)?;
+
+        Ok(())
+    }
+
+    /// Reads the corpus written to `path`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Corpus, CorpusError> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < 6 || &bytes[..4] != MAGIC {
+            return Err(CorpusError::NotACorpus);
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if version != CORPUS_VERSION {
+            return Err(CorpusError::UnsupportedVersion(version));
+        }
+
+        Ok(bincode::deserialize(&bytes[6..])?)
+    }
+}
+
+/// Samples the instructions of a program out of transactions, into a [`Corpus`].
+pub struct CorpusBuilder {
+    program: Pubkey,
+    config: CorpusConfig,
+    entries: BTreeSet<CorpusEntry>,
+    // What's kept of every discriminant, and the data seen, not to keep it twice.
+    kept: HashMap<String, usize>,
+    seen: HashSet<Vec<u8>>,
+}
+
+impl CorpusBuilder {
+    pub fn new(program: Pubkey, config: CorpusConfig) -> Self {
+        CorpusBuilder {
+            program,
+            config,
+            entries: BTreeSet::new(),
+            kept: HashMap::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Samples the instructions of the program `transaction` holds, inner instructions included.
+    pub fn add_transaction(&mut self, transaction: &RawTransaction) {
+        let inner = transaction.inner_instructions.iter().flat_map(|ii| ii.instructions.iter());
+        for compiled in transaction.instructions.iter().chain(inner) {
+            if transaction.account_keys.get(compiled.program_id_index as usize)
+                == Some(&self.program)
+            {
+                self.add(compiled, &transaction.transaction_hash);
+            }
+        }
+    }
+
+    fn add(&mut self, compiled: &CompiledInstruction, signature: &str) {
+        let discriminant = hex::encode(
+            &compiled.data[..compiled.data.len().min(self.config.discriminant_len)]);
+        let kept = self.kept.entry(discriminant.clone()).or_default();
+        if *kept >= self.config.per_discriminant || !self.seen.insert(compiled.data.clone()) {
+            return;
+        }
+
+        *kept += 1;
+        self.entries.insert(CorpusEntry {
+            discriminant,
+            data: compiled.data.clone(),
+            accounts: compiled.accounts.len().min(u8::MAX as usize) as u8,
+            signature: signature.to_string(),
+        });
+    }
+
+    /// How many instructions were kept.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn build(self) -> Corpus {
+        Corpus {
+            program: self.program.to_string(),
+            discriminant_len: self.config.discriminant_len,
+            entries: self.entries.into_iter().collect(),
+        }
+    }
+}
+
+/// Samples the instructions of `program` out of its `sample` most recent transactions (or all of
+/// them when it has fewer), failed ones included.
+pub async fn collect(
+    rpc: &Arc<RpcPool>,
+    program: &Pubkey,
+    sample: usize,
+    config: CorpusConfig,
+    commitment: CommitmentConfig,
+) -> Result<Corpus, CorpusError> {
+    let mut builder = CorpusBuilder::new(*program, config);
+    let address = *program;
+    let mut before = None;
+    let mut sampled = 0;
+    while sampled < sample {
+        let limit = (sample - sampled).min(SIGNATURES_PER_PAGE);
+        let page = rpc.call(move |rpc| {
+            rpc.get_signatures_for_address_with_config(&address,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until: None,
+                    limit: Some(limit),
+                    commitment: Some(commitment),
+                })
+        }).await.map_err(IngestError::from)?;
+
+        let signatures: Vec<_> = page.iter().map(|status| status.signature.clone()).collect();
+        let transactions = fetch_transactions(rpc, &signatures, commitment).await?;
+        for transaction in transactions.iter().flatten() {
+            builder.add_transaction(transaction);
+        }
+        sampled += page.len();
+
+        before = page.last().and_then(|status| Signature::from_str(&status.signature).ok());
+        if page.len() < limit || before.is_none() {
+            break;
+        }
+    }
+
+    Ok(builder.build())
+}
+
+/// How the entries of a discriminant decoded.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiscriminantStats {
+    pub entries: usize,
+    pub decoded: usize,
+    // The functions the entries decoded to.
+    pub functions: BTreeSet<String>,
+}
+
+impl DiscriminantStats {
+    /// The share of the entries that decoded, between 0 and 1.
+    pub fn rate(&self) -> f64 {
+        if self.entries == 0 {
+            return 0.0;
+        }
+
+        self.decoded as f64 / self.entries as f64
+    }
+}
+
+/// How a corpus decoded, by discriminant.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CheckReport {
+    pub program: String,
+    pub discriminants: BTreeMap<String, DiscriminantStats>,
+}
+
+/// A discriminant whose rate differs between two reports.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RateChange {
+    pub discriminant: String,
+    // None when the discriminant isn't in one of the reports.
+    pub before: Option<f64>,
+    pub after: Option<f64>,
+}
+
+impl RateChange {
+    /// Whether fewer of the entries of the discriminant decode than before.
+    pub fn is_regression(&self) -> bool {
+        matches!((self.before, self.after), (Some(before), Some(after)) if after < before)
+    }
+}
+
+impl CheckReport {
+    /// The entries of every discriminant, and those that decoded.
+    pub fn totals(&self) -> (usize, usize) {
+        self.discriminants.values()
+            .fold((0, 0), |(entries, decoded), stats| {
+                (entries + stats.entries, decoded + stats.decoded)
+            })
+    }
+
+    /// The discriminants whose rate changed since `previous`, appeared or went away, in order.
+    pub fn diff(&self, previous: &CheckReport) -> Vec<RateChange> {
+        let discriminants: BTreeSet<_> = self.discriminants.keys()
+            .chain(previous.discriminants.keys())
+            .collect();
+
+        discriminants.into_iter()
+            .map(|discriminant| RateChange {
+                discriminant: discriminant.clone(),
+                before: previous.discriminants.get(discriminant).map(DiscriminantStats::rate),
+                after: self.discriminants.get(discriminant).map(DiscriminantStats::rate),
+            })
+            .filter(|change| change.before != change.after)
+            .collect()
+    }
+}
+
+/// Decodes every entry of `corpus` with `registry`, out of its transaction: as a top-level
+/// instruction, with as many accounts as it was invoked with, all of them the default address.
+pub fn check(registry: &ProcessorRegistry, corpus: &Corpus) -> CheckReport {
+    let program = Symbol::intern(&corpus.program);
+    let mut report = CheckReport { program: corpus.program.clone(), ..CheckReport::default() };
+    let mut decoded = Vec::new();
+    for entry in &corpus.entries {
+        let instruction = Instruction {
+            tx_instruction_id: 0,
+            transaction_hash: Arc::from(entry.signature.as_str()),
+            program,
+            data: entry.data.clone(),
+            parent_index: -1,
+            timestamp: 0,
+            network: registry.network(),
+        };
+        let accounts = vec![Pubkey::default(); entry.accounts as usize];
+
+        decoded.clear();
+        let status = registry.decode_with_accounts(instruction, &accounts, None, &mut decoded);
+        let stats = report.discriminants.entry(entry.discriminant.clone()).or_default();
+        stats.entries += 1;
+        if status == DecodeStatus::Decoded {
+            stats.decoded += 1;
+            stats.functions.extend(decoded.iter()
+                .map(|instruction_set| instruction_set.function.function_name.to_string()));
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_transaction_status::InnerInstructions;
+
+    use super::*;
+
+    const TOKEN: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+
+    fn transaction(signature: &str, instructions: Vec<Vec<u8>>) -> RawTransaction {
+        let token = Pubkey::from_str(TOKEN).unwrap();
+        let compiled = |data: Vec<u8>| CompiledInstruction {
+            program_id_index: 1,
+            accounts: vec![0, 0, 0],
+            data,
+        };
+        let mut instructions = instructions.into_iter().map(compiled);
+
+        RawTransaction {
+            slot: 1,
+            block_time: None,
+            transaction_hash: signature.to_string(),
+            account_keys: vec![Pubkey::new_unique(), token],
+            num_required_signatures: 1,
+            instructions: instructions.next().into_iter().collect(),
+            inner_instructions: vec![InnerInstructions {
+                index: 0,
+                instructions: instructions.collect(),
+            }],
+            token_mints: vec![],
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        }
+    }
+
+    fn transfer(amount: u64) -> Vec<u8> {
+        let mut data = vec![3];
+        data.extend(&amount.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn instructions_are_deduplicated_and_capped_by_discriminant() {
+        let config = CorpusConfig { discriminant_len: 1, per_discriminant: 2 };
+        let mut builder = CorpusBuilder::new(Pubkey::from_str(TOKEN).unwrap(), config);
+        builder.add_transaction(&transaction("b", vec![transfer(2), transfer(1), transfer(1)]));
+        builder.add_transaction(&transaction("a", vec![transfer(3), vec![9, 1]]));
+
+        let corpus = builder.build();
+        let entries: Vec<_> = corpus.entries.iter()
+            .map(|entry| {
+                (entry.discriminant.as_str(), entry.data.clone(), entry.signature.as_str())
+            })
+            .collect();
+        assert_eq!(entries, vec![
+            ("03", transfer(1), "b"),
+            ("03", transfer(2), "b"),
+            ("09", vec![9, 1], "a"),
+        ]);
+        assert!(corpus.entries.iter().all(|entry| entry.accounts == 3));
+
+        let path = std::env::temp_dir()
+            .join(format!("spi-corpus-{}.spic", std::process::id()));
+        corpus.write(&path).unwrap();
+        assert_eq!(Corpus::read(&path).unwrap(), corpus);
+        fs::write(&path, b"SPIS\x01\x00").unwrap();
+        assert!(matches!(Corpus::read(&path), Err(CorpusError::NotACorpus)));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[cfg(feature = "token")]
+    #[test]
+    fn checks_report_rates_by_discriminant_and_their_drops() {
+        let mut builder = CorpusBuilder::new(Pubkey::from_str(TOKEN).unwrap(),
+                                             CorpusConfig::default());
+        // A transfer, one cut short, and an instruction the token program doesn't have.
+        builder.add_transaction(&transaction("a", vec![transfer(5), vec![3, 1], vec![255]]));
+        let corpus = builder.build();
+
+        let report = check(&ProcessorRegistry::default(), &corpus);
+        assert_eq!(report.totals(), (3, 1));
+        let transfers = &report.discriminants["03"];
+        assert_eq!((transfers.entries, transfers.decoded), (2, 1));
+        assert_eq!(transfers.functions.iter().collect::<Vec<_>>(), vec!["transfer"]);
+        assert_eq!(report.discriminants["ff"].rate(), 0.0);
+
+        let mut previous = report.clone();
+        previous.discriminants.get_mut("03").unwrap().decoded = 2;
+        previous.discriminants.remove("ff");
+        let changes = report.diff(&previous);
+        assert_eq!(changes, vec![
+            RateChange { discriminant: "03".to_string(), before: Some(1.0), after: Some(0.5) },
+            RateChange { discriminant: "ff".to_string(), before: None, after: Some(0.0) },
+        ]);
+        assert_eq!(changes.iter().filter(|change| change.is_regression()).count(), 1);
+        assert!(report.diff(&report).is_empty());
+    }
+}
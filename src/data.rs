@@ -0,0 +1,211 @@
+//! Decoding instruction data out of the strings it comes as: base58 in the `data` of the
+//! instructions of json-encoded transactions (whatever its length), base64 in the binary
+//! encodings and wherever RPC refuses base58 (account data longer than 128 bytes).
+//!
+//! [`DataEncoding::Auto`] tells them apart by their alphabet: strings with a character base58
+//! doesn't have (`0`, `O`, `I`, `l`, `+`, `/`, `=`) are base64, the others base58. Only short
+//! payloads practically encode to base64 without any of them, so those strings being valid in
+//! both encodings are read as base58, the way RPC hands instruction data out.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+
+/// How instruction data is encoded into a string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataEncoding {
+    Base58,
+    Base64,
+    // Either of them, see the module documentation.
+    Auto,
+}
+
+impl Default for DataEncoding {
+    fn default() -> Self {
+        DataEncoding::Auto
+    }
+}
+
+impl fmt::Display for DataEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DataEncoding::Base58 => "base58",
+            DataEncoding::Base64 => "base64",
+            DataEncoding::Auto => "auto",
+        })
+    }
+}
+
+impl FromStr for DataEncoding {
+    type Err = DecodeError;
+
+    /// Parses the names RPC uses (base58, base64), or auto.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "base58" => Ok(DataEncoding::Base58),
+            "base64" => Ok(DataEncoding::Base64),
+            "auto" => Ok(DataEncoding::Auto),
+            name => Err(DecodeError::UnknownEncoding(name.to_string())),
+        }
+    }
+}
+
+/// Why an instruction couldn't be decoded out of strings.
+#[derive(Debug, Error, PartialEq)]
+pub enum DecodeError {
+    #[error("Unknown encoding `{0}`, expected base58, base64 or auto")]
+    UnknownEncoding(String),
+    #[error("Invalid program id `{0}`")]
+    InvalidProgramId(String),
+    #[error("Invalid {encoding} instruction data: {reason}")]
+    InvalidData { encoding: DataEncoding, reason: String },
+    #[error("No processor is registered for {0}")]
+    Unsupported(String),
+    #[error("The processor of {0} couldn't decode the instruction")]
+    Failed(String),
+    #[error("The instructions of {0} are sampled and this one wasn't part of the sample")]
+    SampledOut(String),
+    #[error("The processor of {0} is degraded for being too slow, until it's restored")]
+    Degraded(String),
+}
+
+/// Where an instruction decoded out of strings comes from, but for its program and data (see
+/// [`ProcessorRegistry::process_from_encoded`](crate::ProcessorRegistry::process_from_encoded)).
+#[derive(Clone, Debug)]
+pub struct EncodedContext {
+    // How the data is encoded.
+    pub encoding: DataEncoding,
+    // The accounts the program was invoked with, none when they aren't known.
+    pub accounts: Vec<Pubkey>,
+    pub tx_instruction_id: i16,
+    // The index of the parent instruction for inner instructions, -1 otherwise.
+    pub parent_index: i16,
+    pub transaction_hash: Arc<str>,
+    pub timestamp: i64,
+    pub slot: Option<u64>,
+}
+
+impl Default for EncodedContext {
+    fn default() -> Self {
+        EncodedContext {
+            encoding: DataEncoding::Auto,
+            accounts: Vec::new(),
+            tx_instruction_id: 0,
+            parent_index: -1,
+            transaction_hash: Arc::from(""),
+            timestamp: 0,
+            slot: None,
+        }
+    }
+}
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// The encoding `encoded` is in, as told by [`DataEncoding::Auto`].
+pub fn detect_encoding(encoded: &str) -> DataEncoding {
+    if encoded.bytes().all(|byte| BASE58_ALPHABET.contains(&byte)) {
+        DataEncoding::Base58
+    } else {
+        DataEncoding::Base64
+    }
+}
+
+/// Decodes instruction data out of `encoded`, in `encoding`.
+pub fn decode_instruction_data(
+    encoded: &str,
+    encoding: DataEncoding,
+) -> Result<Vec<u8>, DecodeError> {
+    match encoding {
+        DataEncoding::Base58 => bs58::decode(encoded).into_vec()
+            .map_err(|err| DecodeError::InvalidData { encoding, reason: err.to_string() }),
+        DataEncoding::Base64 => base64::decode(encoded)
+            .map_err(|err| DecodeError::InvalidData { encoding, reason: err.to_string() }),
+        DataEncoding::Auto => decode_instruction_data(encoded, detect_encoding(encoded)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every byte value, `len` bytes of them.
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|index| (index * 7 % 256) as u8).collect()
+    }
+
+    #[test]
+    fn base58_payloads_of_any_length_are_detected() {
+        // RPC only refuses base58 for account data over 128 bytes: instruction data comes as
+        // base58 in json-encoded transactions, up to the size of a transaction.
+        for len in &[0, 1, 32, 127, 128, 129, 200, 1_232] {
+            let encoded = bs58::encode(payload(*len)).into_string();
+            assert_eq!(detect_encoding(&encoded), DataEncoding::Base58, "{} bytes", len);
+            assert_eq!(decode_instruction_data(&encoded, DataEncoding::Auto).unwrap(),
+                       payload(*len), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn base64_payloads_over_a_few_bytes_are_detected() {
+        for len in &[3, 64, 128, 129, 1_232] {
+            let encoded = base64::encode(payload(*len));
+            assert_eq!(detect_encoding(&encoded), DataEncoding::Base64, "{} bytes", len);
+            assert_eq!(decode_instruction_data(&encoded, DataEncoding::Auto).unwrap(),
+                       payload(*len), "{} bytes", len);
+        }
+        // Padding gives base64 away whatever the payload.
+        assert_eq!(decode_instruction_data("AQ==", DataEncoding::Auto).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn ambiguous_strings_are_read_as_base58() {
+        // [1, 6, 155] in base64, which is valid base58 too.
+        assert_eq!(detect_encoding("AQab"), DataEncoding::Base58);
+        assert_eq!(decode_instruction_data("AQab", DataEncoding::Auto).unwrap(),
+                   bs58::decode("AQab").into_vec().unwrap());
+        assert_eq!(decode_instruction_data("AQab", DataEncoding::Base64).unwrap(),
+                   vec![1, 6, 155]);
+    }
+
+    #[test]
+    fn invalid_data_names_its_encoding() {
+        let err = decode_instruction_data("0OIl", DataEncoding::Base58).unwrap_err();
+        assert!(matches!(err, DecodeError::InvalidData { encoding: DataEncoding::Base58, .. }));
+        // Not base58, so base64, which it is.
+        assert_eq!(decode_instruction_data("0OIl", DataEncoding::Auto).unwrap(),
+                   vec![208, 226, 37]);
+
+        let err = decode_instruction_data("AQ=", DataEncoding::Auto).unwrap_err();
+        assert!(matches!(err, DecodeError::InvalidData { encoding: DataEncoding::Base64, .. }),
+                "{}", err);
+        assert_eq!("hex".parse::<DataEncoding>(),
+                   Err(DecodeError::UnknownEncoding("hex".to_string())));
+    }
+
+    #[cfg(feature = "runtime")]
+    #[test]
+    fn instruction_data_of_json_transactions_decodes() {
+        use solana_sdk::instruction::Instruction;
+        use solana_sdk::message::Message;
+        use solana_sdk::pubkey::Pubkey;
+        use solana_sdk::transaction::Transaction;
+        use solana_transaction_status::{EncodedTransaction, UiMessage, UiTransactionEncoding};
+
+        let data = payload(600);
+        let payer = Pubkey::new_unique();
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &data, vec![]);
+        let transaction = Transaction::new_unsigned(Message::new(&[instruction], Some(&payer)));
+
+        let encoded = match EncodedTransaction::encode(transaction, UiTransactionEncoding::Json) {
+            EncodedTransaction::Json(transaction) => match transaction.message {
+                UiMessage::Raw(message) => message.instructions[0].data.clone(),
+                UiMessage::Parsed(_) => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        assert_eq!(decode_instruction_data(&encoded, DataEncoding::Auto).unwrap(), data);
+    }
+}
@@ -0,0 +1,404 @@
+//! Decoding transactions and instructions straight out of the types RPC hands them out in (see
+//! `solana_transaction_status`), for callers already holding them.
+//!
+//! The raw data and account keys the processors work with are recovered from whichever encoding
+//! the transaction was fetched with: the binary ones (base58, base64 and the legacy binary) and
+//! `json`, whose instructions stay compiled. `jsonParsed` replaces the instructions RPC knows
+//! about with their parsed form, dropping their raw data, so transactions fetched with it are
+//! rejected with [`EncodingError::Parsed`].
+
+use std::iter;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use solana_client::rpc_response::{Response, RpcSimulateTransactionResult};
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::Transaction;
+use solana_transaction_status::{
+    EncodedTransaction, EncodedTransactionWithStatusMeta, UiCompiledInstruction, UiMessage,
+};
+use thiserror::Error;
+
+use crate::fees::compute_units_consumed;
+use crate::interner::Symbol;
+use crate::network::Network;
+use crate::registry::{InstructionContext, ProcessorRegistry};
+use crate::transaction::{
+    decode_transaction, decode_unconfirmed_transaction, RawTransaction, Simulation,
+    TransactionRecord,
+};
+use crate::{Instruction, InstructionSet};
+
+/// Why an encoded transaction or instruction couldn't be decoded.
+#[derive(Debug, Error, PartialEq)]
+pub enum EncodingError {
+    #[error("jsonParsed transactions don't carry the raw data of their instructions, fetch them \
+    with the base64 or json encoding instead")]
+    Parsed,
+    #[error("Unable to decode the transaction out of its binary encoding")]
+    Undecodable,
+    #[error("The transaction has no signature")]
+    Unsigned,
+    #[error("Invalid account key `{0}`")]
+    InvalidAccountKey(String),
+    #[error("Invalid base58 instruction data `{0}`")]
+    InvalidData(String),
+    #[error("Account index {index} is out of the {count} account keys of the transaction")]
+    AccountIndex { index: u8, count: usize },
+}
+
+/// The first signature, the account keys (and how many of them signed) and the top-level
+/// instructions of a message.
+pub(crate) struct DecodedMessage {
+    pub signature: String,
+    pub account_keys: Vec<Pubkey>,
+    pub num_required_signatures: u8,
+    pub instructions: Vec<CompiledInstruction>,
+}
+
+/// Decodes the message of a transaction, in any encoding but `jsonParsed`.
+pub(crate) fn decode_message(
+    transaction: &EncodedTransaction,
+) -> Result<DecodedMessage, EncodingError> {
+    let transaction = match transaction {
+        EncodedTransaction::Json(transaction) => transaction,
+        binary => {
+            let transaction = binary.decode().ok_or(EncodingError::Undecodable)?;
+            let signature = transaction.signatures.get(0).ok_or(EncodingError::Unsigned)?;
+
+            return Ok(DecodedMessage {
+                signature: signature.to_string(),
+                num_required_signatures: transaction.message.header.num_required_signatures,
+                account_keys: transaction.message.account_keys,
+                instructions: transaction.message.instructions,
+            });
+        }
+    };
+
+    let message = match &transaction.message {
+        UiMessage::Raw(message) => message,
+        UiMessage::Parsed(_) => return Err(EncodingError::Parsed),
+    };
+    let signature = transaction.signatures.get(0).ok_or(EncodingError::Unsigned)?;
+    let account_keys = message.account_keys.iter()
+        .map(|key| Pubkey::from_str(key).map_err(|_| EncodingError::InvalidAccountKey(key.clone())))
+        .collect::<Result<Vec<_>, _>>()?;
+    let instructions = message.instructions.iter()
+        .map(|instruction| compile_instruction(instruction, account_keys.len()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(DecodedMessage {
+        signature: signature.clone(),
+        account_keys,
+        num_required_signatures: message.header.num_required_signatures,
+        instructions,
+    })
+}
+
+/// Decodes the base58 data of a json-encoded instruction, checking its account indexes against
+/// the `count` account keys of its transaction.
+fn compile_instruction(
+    instruction: &UiCompiledInstruction,
+    count: usize,
+) -> Result<CompiledInstruction, EncodingError> {
+    let indexes = iter::once(&instruction.program_id_index).chain(&instruction.accounts);
+    if let Some(index) = indexes.copied().find(|index| *index as usize >= count) {
+        return Err(EncodingError::AccountIndex { index, count });
+    }
+    let data = bs58::decode(&instruction.data).into_vec()
+        .map_err(|_| EncodingError::InvalidData(instruction.data.clone()))?;
+
+    Ok(CompiledInstruction {
+        program_id_index: instruction.program_id_index,
+        accounts: instruction.accounts.clone(),
+        data,
+    })
+}
+
+/// Decodes every instruction (inner instructions included) of a transaction as handed out by
+/// RPC, e.g. by `get_transaction`, whatever its encoding but `jsonParsed`.
+pub fn process_encoded_transaction(
+    encoded: &EncodedTransactionWithStatusMeta,
+    slot: u64,
+    block_time: Option<i64>,
+    registry: &ProcessorRegistry,
+) -> Result<TransactionRecord, EncodingError> {
+    let transaction = RawTransaction::try_from_encoded(slot, block_time, encoded)?;
+
+    Ok(decode_transaction(registry, &transaction))
+}
+
+/// Decodes the instructions of a transaction that hasn't landed (yet), e.g. out of a feed of
+/// pending transactions, along with what simulating it returned when it was simulated.
+///
+/// Without a status meta, only the top-level instructions are decoded: the call tree holds them
+/// alone, and the fee, the balances and the token mints are unknown. The record isn't
+/// `confirmed`, its slot is the one the transaction was simulated at (0 when it wasn't) and its
+/// timestamp the time it was decoded at, as an estimate. It succeeded unless the simulation
+/// failed. Its `simulation` carries the logs (for Anchor events) and the compute units consumed.
+///
+/// The record goes by the first signature, like the one decoded once the transaction lands (see
+/// [`TransactionRecord::confirms`]); transactions simulated without being signed all go by the
+/// default signature.
+pub fn process_unconfirmed_transaction(
+    transaction: &Transaction,
+    simulation: Option<&Response<RpcSimulateTransactionResult>>,
+    registry: &ProcessorRegistry,
+) -> Result<TransactionRecord, EncodingError> {
+    let signature = transaction.signatures.get(0).ok_or(EncodingError::Unsigned)?;
+    let message = &transaction.message;
+    let count = message.account_keys.len();
+    for instruction in &message.instructions {
+        let indexes = iter::once(&instruction.program_id_index).chain(&instruction.accounts);
+        if let Some(index) = indexes.copied().find(|index| *index as usize >= count) {
+            return Err(EncodingError::AccountIndex { index, count });
+        }
+    }
+
+    let simulation = simulation.map(|response| {
+        let logs = response.value.logs.clone().unwrap_or_default();
+        Simulation {
+            slot: response.context.slot,
+            err: response.value.err.as_ref().map(|err| err.to_string()),
+            units_consumed: compute_units_consumed(&logs),
+            logs,
+        }
+    });
+    let decoded_at = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64);
+    let raw = RawTransaction {
+        slot: simulation.as_ref().map_or(0, |simulation| simulation.slot),
+        block_time: Some(decoded_at),
+        transaction_hash: signature.to_string(),
+        account_keys: message.account_keys.clone(),
+        num_required_signatures: message.header.num_required_signatures,
+        instructions: message.instructions.clone(),
+        inner_instructions: Vec::new(),
+        token_mints: Vec::new(),
+        fee: 0,
+        succeeded: simulation.as_ref().map_or(true, |simulation| simulation.err.is_none()),
+        stack_heights: Vec::new(),
+        log_messages: simulation.as_ref()
+            .map(|simulation| simulation.logs.clone())
+            .unwrap_or_default(),
+        pre_balances: Vec::new(),
+        post_balances: Vec::new(),
+    };
+
+    Ok(decode_unconfirmed_transaction(registry, &raw, simulation))
+}
+
+/// An instruction out of its RPC encoding, with its data decoded and its accounts resolved:
+/// everything an [`InstructionContext`] borrows.
+#[derive(Clone)]
+pub struct ResolvedInstruction {
+    pub instruction: Instruction,
+    // The accounts the program was invoked with.
+    pub accounts: Vec<Pubkey>,
+    // The slot of the transaction, when known.
+    pub slot: Option<u64>,
+}
+
+impl ResolvedInstruction {
+    /// What a closure processor gets to decode the instruction.
+    pub fn context(&self) -> InstructionContext<'_> {
+        InstructionContext {
+            program: self.instruction.program,
+            data: &self.instruction.data,
+            accounts: &self.accounts,
+            tx_instruction_id: self.instruction.tx_instruction_id,
+            parent_index: self.instruction.parent_index,
+            transaction_hash: &self.instruction.transaction_hash,
+            timestamp: self.instruction.timestamp,
+            network: self.instruction.network,
+            slot: self.slot,
+        }
+    }
+
+    /// Decodes the instruction with the processor registered for its program (see
+    /// [`ProcessorRegistry::decode_with_accounts`]).
+    pub fn decode(&self, registry: &ProcessorRegistry) -> Vec<InstructionSet> {
+        let mut decoded = Vec::new();
+        registry.decode_with_accounts(self.instruction.clone(), &self.accounts, self.slot,
+                                      &mut decoded);
+
+        decoded
+    }
+}
+
+/// Resolves a compiled instruction of a json-encoded transaction (inner instructions come this
+/// way in every encoding) against the `account_keys` of its message, numbered like in
+/// [`RawTransaction::instructions`]: `tx_instruction_id` is its position among the top-level
+/// instructions (or within its parent), `parent_index` the position of its parent, -1 for
+/// top-level instructions.
+///
+/// The instruction is timestamped 0 and tagged with the default network, with no slot: set them
+/// on the result when they're known.
+pub fn instruction_context_from_ui(
+    instruction: &UiCompiledInstruction,
+    account_keys: &[Pubkey],
+    tx_instruction_id: i16,
+    parent_index: i16,
+    transaction_hash: &Arc<str>,
+) -> Result<ResolvedInstruction, EncodingError> {
+    let compiled = compile_instruction(instruction, account_keys.len())?;
+    let program = account_keys[compiled.program_id_index as usize];
+
+    Ok(ResolvedInstruction {
+        instruction: Instruction {
+            tx_instruction_id,
+            transaction_hash: transaction_hash.clone(),
+            program: Symbol::intern(&program.to_string()),
+            data: compiled.data,
+            parent_index,
+            timestamp: 0,
+            network: Network::default(),
+        },
+        accounts: compiled.accounts.iter().map(|index| account_keys[*index as usize]).collect(),
+        slot: None,
+    })
+}
+
+// The fixture is a token transfer, which has to be decoded.
+#[cfg(all(test, feature = "token"))]
+mod tests {
+    use solana_sdk::hash::Hash;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::transaction::Transaction;
+    use solana_transaction_status::UiTransactionEncoding;
+    use spl_token::instruction::TokenInstruction;
+
+    use super::*;
+
+    fn transfer(amount: u64) -> Transaction {
+        let payer = Keypair::new();
+        let (source, destination) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let transfer = spl_token::instruction::transfer(
+            &spl_token::id(), &source, &destination, &payer.pubkey(), &[], amount).unwrap();
+
+        Transaction::new_signed_with_payer(&[transfer], Some(&payer.pubkey()), &[&payer],
+                                           Hash::default())
+    }
+
+    /// The transaction as `get_transaction` returns it with `encoding`.
+    fn encode(
+        transaction: &Transaction,
+        encoding: UiTransactionEncoding,
+    ) -> EncodedTransactionWithStatusMeta {
+        EncodedTransactionWithStatusMeta {
+            transaction: EncodedTransaction::encode(transaction.clone(), encoding),
+            meta: None,
+        }
+    }
+
+    #[test]
+    fn every_encoding_with_raw_data_decodes_alike() {
+        let transaction = transfer(42);
+        let registry = ProcessorRegistry::default();
+
+        let encodings = [
+            UiTransactionEncoding::Binary,
+            UiTransactionEncoding::Base58,
+            UiTransactionEncoding::Base64,
+            UiTransactionEncoding::Json,
+        ];
+        for encoding in &encodings {
+            let encoded = encode(&transaction, *encoding);
+            let record = process_encoded_transaction(&encoded, 42, Some(1_600_000_000),
+                                                     &registry)
+                .unwrap_or_else(|err| panic!("{:?}: {}", encoding, err));
+
+            assert_eq!(record.transaction_hash, transaction.signatures[0].to_string());
+            assert_eq!((record.slot, record.timestamp), (42, 1_600_000_000));
+            let decoded: Vec<_> = record.instruction_sets.iter()
+                .map(|set| (set.function.function_name.as_str(), set.properties[0].value.as_str()))
+                .collect();
+            assert_eq!(decoded, vec![("transfer", "42")], "{:?}", encoding);
+        }
+    }
+
+    #[test]
+    fn parsed_transactions_are_rejected() {
+        let encoded = encode(&transfer(42), UiTransactionEncoding::JsonParsed);
+
+        let result = process_encoded_transaction(&encoded, 42, None,
+                                                 &ProcessorRegistry::default());
+        assert_eq!(result.err(), Some(EncodingError::Parsed));
+    }
+
+    #[test]
+    fn unconfirmed_transactions_are_decoded_with_their_simulation() {
+        let transaction = transfer(42);
+        let registry = ProcessorRegistry::default();
+        let simulation: Response<RpcSimulateTransactionResult> =
+            serde_json::from_value(serde_json::json!({
+                "context": { "slot": 120_000_000 },
+                "value": {
+                    "err": null,
+                    "logs": [
+                        format!("Program {} invoke [1]", spl_token::id()),
+                        "Program log: Instruction: Transfer",
+                        format!("Program {} consumed 2712 of 200000 compute units",
+                                spl_token::id()),
+                        format!("Program {} success", spl_token::id()),
+                    ],
+                },
+            })).unwrap();
+
+        let pending = process_unconfirmed_transaction(&transaction, Some(&simulation), &registry)
+            .unwrap();
+        assert!(!pending.confirmed && pending.timestamp_estimated && pending.succeeded);
+        assert_eq!(pending.slot, 120_000_000);
+        let details = pending.simulation.as_ref().unwrap();
+        assert_eq!((details.units_consumed, details.logs.len()), (Some(2712), 4));
+        assert_eq!(pending.instruction_sets.len(), 1);
+        assert_eq!(pending.instruction_sets[0].function.parent_index, -1);
+        assert_eq!(pending.call_tree.nodes.len(), 1);
+
+        // Once it lands, the confirmed record supersedes the pending one.
+        let confirmed = process_encoded_transaction(
+            &encode(&transaction, UiTransactionEncoding::Base64), 120_000_002, None, &registry)
+            .unwrap();
+        assert!(confirmed.confirmed && confirmed.simulation.is_none());
+        assert!(confirmed.confirms(&pending));
+        assert!(!pending.confirms(&confirmed));
+
+        let unsimulated = process_unconfirmed_transaction(&transfer(7), None, &registry).unwrap();
+        assert_eq!((unsimulated.slot, unsimulated.succeeded), (0, true));
+        assert!(!confirmed.confirms(&unsimulated));
+    }
+
+    #[test]
+    fn ui_instructions_are_resolved_against_the_account_keys() {
+        let (payer, source, destination) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let account_keys = [payer, source, destination, spl_token::id()];
+        let transaction_hash: Arc<str> = Arc::from("fixture-signature");
+        let instruction = UiCompiledInstruction {
+            program_id_index: 3,
+            accounts: vec![1, 2, 0],
+            data: bs58::encode(TokenInstruction::Transfer { amount: 10 }.pack()).into_string(),
+        };
+
+        let resolved = instruction_context_from_ui(&instruction, &account_keys, 1, 0,
+                                                   &transaction_hash).unwrap();
+        assert_eq!(resolved.accounts, vec![source, destination, payer]);
+        let context = resolved.context();
+        assert_eq!(context.program.to_string(), spl_token::id().to_string());
+        assert_eq!((context.tx_instruction_id, context.parent_index), (1, 0));
+        let decoded = resolved.decode(&ProcessorRegistry::default());
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].function.function_name.as_str(), "transfer");
+
+        let out_of_range = UiCompiledInstruction { program_id_index: 4, ..instruction.clone() };
+        assert_eq!(instruction_context_from_ui(&out_of_range, &account_keys, 0, -1,
+                                               &transaction_hash).err(),
+                   Some(EncodingError::AccountIndex { index: 4, count: 4 }));
+        let garbled = UiCompiledInstruction { data: "0OIl".to_string(), ..instruction };
+        assert_eq!(instruction_context_from_ui(&garbled, &account_keys, 0, -1,
+                                               &transaction_hash).err(),
+                   Some(EncodingError::InvalidData("0OIl".to_string())));
+    }
+}
@@ -0,0 +1,294 @@
+//! Decoding a single instruction without allocating, for callers (wallets, bots) decoding one
+//! instruction at a time, for whom building an [`Instruction`] and its [`InstructionSet`] costs
+//! more than decoding it.
+//!
+//! [`ProcessorRegistry::decode_one`] decodes the data of an instruction into a
+//! [`FastInstruction`]: its function and properties as static names and values borrowing the data
+//! where they can, converted to strings only when displayed (see [`PropertyValueRef`]).
+//! [`FastInstruction::to_instruction_set`] builds what the processor of the program emits when
+//! the owned form is needed.
+//!
+//! Only the system, token and token-lending processors have a fast path; the instructions of
+//! other programs are decoded as usual.
+//!
+//! [`ProcessorRegistry::decode_one`]: crate::ProcessorRegistry::decode_one
+
+use std::fmt;
+
+use smallvec::SmallVec;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::registry::BuiltinProcessor;
+use crate::{Instruction, InstructionFunction, InstructionProperty, InstructionSet, PropertyType};
+
+/// The most properties an instruction decoded through a fast path carries.
+pub const MAX_FAST_PROPERTIES: usize = 10;
+
+/// What decoding an instruction through its fast path gave.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FastDecode<'a> {
+    Decoded(FastInstruction<'a>),
+    // No processor with a fast path is registered for the program: it has to be decoded as usual.
+    Unsupported,
+    // The data isn't that of an instruction of the program.
+    Failed,
+}
+
+impl<'a> FastDecode<'a> {
+    pub fn decoded(self) -> Option<FastInstruction<'a>> {
+        match self {
+            FastDecode::Decoded(instruction) => Some(instruction),
+            FastDecode::Unsupported | FastDecode::Failed => None,
+        }
+    }
+}
+
+/// The value of a property of a [`FastInstruction`], displayed as the processor emits it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropertyValueRef<'a> {
+    U64(u64),
+    Pubkey(Pubkey),
+    // An optional pubkey that isn't set, displayed as an empty string.
+    NoPubkey,
+    // Text borrowed from the data of the instruction.
+    Text(&'a str),
+}
+
+impl PropertyValueRef<'_> {
+    pub fn value_type(&self) -> PropertyType {
+        match self {
+            PropertyValueRef::U64(_) => PropertyType::U64,
+            PropertyValueRef::Pubkey(_) | PropertyValueRef::NoPubkey => PropertyType::Pubkey,
+            PropertyValueRef::Text(_) => PropertyType::Text,
+        }
+    }
+}
+
+impl fmt::Display for PropertyValueRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PropertyValueRef::U64(value) => write!(f, "{}", value),
+            PropertyValueRef::Pubkey(pubkey) => write!(f, "{}", pubkey),
+            PropertyValueRef::NoPubkey => Ok(()),
+            PropertyValueRef::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FastProperty<'a> {
+    key: &'static str,
+    parent_key: &'static str,
+    value: PropertyValueRef<'a>,
+}
+
+const NO_PROPERTY: FastProperty<'static> =
+    FastProperty { key: "", parent_key: "", value: PropertyValueRef::NoPubkey };
+
+/// An instruction decoded through the fast path of its processor, borrowing its data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FastInstruction<'a> {
+    function_name: &'static str,
+    properties: [FastProperty<'a>; MAX_FAST_PROPERTIES],
+    len: usize,
+    // The version of the processor, stamped by the registry.
+    pub(crate) decoder_version: u32,
+}
+
+impl<'a> FastInstruction<'a> {
+    pub(crate) fn new(function_name: &'static str) -> Self {
+        FastInstruction {
+            function_name,
+            properties: [NO_PROPERTY; MAX_FAST_PROPERTIES],
+            len: 0,
+            decoder_version: 0,
+        }
+    }
+
+    /// Adds a top-level property.
+    pub(crate) fn with(self, key: &'static str, value: PropertyValueRef<'a>) -> Self {
+        self.nested(key, "", value)
+    }
+
+    /// Adds a property nested under `parent_key`.
+    pub(crate) fn nested(
+        mut self,
+        key: &'static str,
+        parent_key: &'static str,
+        value: PropertyValueRef<'a>,
+    ) -> Self {
+        self.properties[self.len] = FastProperty { key, parent_key, value };
+        self.len += 1;
+        self
+    }
+
+    pub fn function_name(&self) -> &'static str {
+        self.function_name
+    }
+
+    /// The properties, as `(key, value)`, in the order the processor emits them.
+    pub fn properties(&self) -> impl Iterator<Item = (&'static str, PropertyValueRef<'a>)> + '_ {
+        self.properties[..self.len].iter().map(|property| (property.key, property.value))
+    }
+
+    /// The value of the top-level property `key`.
+    pub fn property(&self, key: &str) -> Option<PropertyValueRef<'a>> {
+        self.properties[..self.len].iter()
+            .find(|property| property.key == key && property.parent_key.is_empty())
+            .map(|property| property.value)
+    }
+
+    /// What the processor of the program emits for `instruction`, whose data was decoded. The
+    /// projection, redaction and key namespacing of the registry aren't applied.
+    pub fn to_instruction_set(&self, instruction: &Instruction) -> InstructionSet {
+        let properties: SmallVec<_> = self.properties[..self.len].iter()
+            .map(|property| InstructionProperty {
+                tx_instruction_id: instruction.tx_instruction_id,
+                transaction_hash: instruction.transaction_hash.clone(),
+                parent_index: instruction.parent_index,
+                key: property.key.into(),
+                value: property.value.to_string(),
+                value_type: property.value.value_type(),
+                parent_key: property.parent_key.into(),
+                timestamp: instruction.timestamp,
+            })
+            .collect();
+
+        InstructionSet {
+            function: InstructionFunction {
+                tx_instruction_id: instruction.tx_instruction_id,
+                transaction_hash: instruction.transaction_hash.clone(),
+                parent_index: instruction.parent_index,
+                program: instruction.program,
+                function_name: self.function_name.into(),
+                timestamp: instruction.timestamp,
+                network: instruction.network,
+                decoder_version: self.decoder_version,
+            },
+            properties,
+            raw_data: None,
+        }
+    }
+}
+
+/// Whether `processor` has a fast path (and is compiled in).
+pub(crate) fn has_fast_path(processor: BuiltinProcessor) -> bool {
+    match processor {
+        BuiltinProcessor::System => cfg!(feature = "system"),
+        BuiltinProcessor::Token => cfg!(feature = "token"),
+        BuiltinProcessor::TokenLending => cfg!(feature = "token-lending"),
+        _ => false,
+    }
+}
+
+/// Decodes `data` through the fast path of `processor`, `None` when it doesn't decode.
+#[cfg_attr(
+    not(any(feature = "system", feature = "token", feature = "token-lending")),
+    allow(unused_variables)
+)]
+pub(crate) fn decode(processor: BuiltinProcessor, data: &[u8]) -> Option<FastInstruction<'_>> {
+    match processor {
+        #[cfg(feature = "system")]
+        BuiltinProcessor::System => crate::programs::native_system::decode_fast(data),
+        #[cfg(feature = "token")]
+        BuiltinProcessor::Token => crate::programs::native_token::decode_fast(data),
+        #[cfg(feature = "token-lending")]
+        BuiltinProcessor::TokenLending => {
+            crate::programs::native_token_lending::decode_fast(data)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Network, ProcessorRegistry};
+
+    /// Decodes `data` through the fast path then as usual, checking both give the same.
+    #[allow(dead_code)]
+    fn assert_decodes_as_usual(registry: &ProcessorRegistry, program_id: &str, data: &[u8]) {
+        let instruction = Instruction {
+            tx_instruction_id: 1,
+            transaction_hash: "signature".into(),
+            program: program_id.into(),
+            data: data.to_vec(),
+            parent_index: -1,
+            timestamp: 1_700_000_000,
+            network: Network::Mainnet,
+        };
+        let fast = registry.decode_one(&program_id.parse().unwrap(), data).decoded().unwrap();
+        let owned = fast.to_instruction_set(&instruction);
+        let usual = registry.decode(instruction, None).unwrap();
+
+        assert_eq!(serde_json::to_value(&owned).unwrap(), serde_json::to_value(&usual).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "token")]
+    fn token_instructions_decode_as_they_usually_do() {
+        use spl_token::instruction::TokenInstruction;
+        use spl_token::solana_program::program_option::COption;
+
+        const TOKEN: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+        let mut registry = ProcessorRegistry::default();
+        let transfer = TokenInstruction::Transfer { amount: 42 }.pack();
+        let decoded = registry.decode_one(&TOKEN.parse().unwrap(), &transfer).decoded().unwrap();
+        assert_eq!(decoded.function_name(), "transfer");
+        assert_eq!(decoded.properties().collect::<Vec<_>>(),
+                   vec![("amount", PropertyValueRef::U64(42))]);
+
+        assert_decodes_as_usual(&registry, TOKEN, &transfer);
+        assert_decodes_as_usual(&registry, TOKEN, &TokenInstruction::InitializeMint {
+            decimals: 6,
+            mint_authority: Pubkey::new_unique(),
+            freeze_authority: COption::None,
+        }.pack());
+        assert_decodes_as_usual(&registry, TOKEN,
+                                &TokenInstruction::BurnChecked { amount: 7, decimals: 9 }.pack());
+        assert_eq!(registry.decode_one(&TOKEN.parse().unwrap(), &[200]), FastDecode::Failed);
+
+        // Shadowed by a processor without a fast path.
+        registry.register_fn(TOKEN, |_| Ok(vec![]));
+        assert_eq!(registry.decode_one(&TOKEN.parse().unwrap(), &transfer),
+                   FastDecode::Unsupported);
+        registry.unregister(TOKEN);
+        assert!(registry.decode_one(&TOKEN.parse().unwrap(), &transfer).decoded().is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "system")]
+    fn system_instructions_decode_as_they_usually_do() {
+        use solana_sdk::system_instruction;
+
+        const SYSTEM: &str = "11111111111111111111111111111111";
+        let registry = ProcessorRegistry::default();
+        let (from, base) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let owner = Pubkey::new_unique();
+        let create = system_instruction::create_account_with_seed(
+            &from, &Pubkey::new_unique(), &base, "vault", 1_000, 165, &owner).data;
+        let decoded = registry.decode_one(&SYSTEM.parse().unwrap(), &create).decoded().unwrap();
+        assert_eq!(decoded.property("seed"), Some(PropertyValueRef::Text("vault")));
+
+        assert_decodes_as_usual(&registry, SYSTEM, &create);
+        assert_decodes_as_usual(&registry, SYSTEM,
+                                &system_instruction::transfer(&from, &base, 5_000).data);
+        assert_decodes_as_usual(&registry, SYSTEM, &system_instruction::transfer_with_seed(
+            &from, &base, "vault".to_string(), &owner, &Pubkey::new_unique(), 5_000).data);
+        // A seed running past the end of the data.
+        assert_eq!(registry.decode_one(&SYSTEM.parse().unwrap(), &create[..50]),
+                   FastDecode::Failed);
+    }
+
+    #[test]
+    #[cfg(feature = "token-lending")]
+    fn token_lending_instructions_decode_as_they_usually_do() {
+        const LENDING: &str = "LendZqTs8gn5CTSJU1jWKhKuVpjJGom45nnwPb2AMTi";
+        let registry = ProcessorRegistry::default();
+        let mut borrow = vec![10];
+        borrow.extend_from_slice(&1_500u64.to_le_bytes());
+
+        assert_decodes_as_usual(&registry, LENDING, &borrow);
+        assert_decodes_as_usual(&registry, LENDING, &[3]);
+    }
+}
@@ -0,0 +1,325 @@
+//! Splitting the fee of transactions into the base fee, paid for every signature, and the
+//! priority fee, paid for the compute units requested at the price set through the ComputeBudget
+//! program.
+//!
+//! The priority fee is the compute unit price (in micro-lamports) times the compute unit limit,
+//! rounded up to the lamport: the limit set by `SetComputeUnitLimit`, or 200k units for every
+//! instruction but those of the ComputeBudget program (1.4M at most) when unset. What the
+//! transaction consumed doesn't change what it pays, but can't be over its limit. Fees that don't
+//! add up aren't broken down: those of transactions from before priority fees, when signatures
+//! weren't charged 5000 lamports, or charged by rules this module doesn't know about.
+
+use std::convert::TryInto;
+
+use solana_sdk::instruction::CompiledInstruction;
+
+use crate::transaction::RawTransaction;
+
+const COMPUTE_BUDGET_PROGRAM: &str = "ComputeBudget111111111111111111111111111111";
+// The precompiled programs verifying the signatures their instructions carry, each signature
+// being charged like those of the transaction from 1.10 on.
+const SECP256K1_PROGRAM: &str = "KeccakSecp256k11111111111111111111111111111";
+const ED25519_PROGRAM: &str = "Ed25519SigVerify111111111111111111111111111";
+const LAMPORTS_PER_SIGNATURE: u64 = 5000;
+const DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT: u64 = 200_000;
+const MAX_COMPUTE_UNIT_LIMIT: u64 = 1_400_000;
+const MICRO_LAMPORTS_PER_LAMPORT: u128 = 1_000_000;
+
+/// The fee of a transaction, broken down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeBreakdown {
+    // The fee paid for the signatures.
+    pub base_fee_lamports: u64,
+    // The fee paid for the compute units requested.
+    pub priority_fee_lamports: u64,
+    // What every compute unit requested was paid, 0 when no price was set.
+    pub compute_unit_price_micro_lamports: u64,
+}
+
+/// What the ComputeBudget instructions of a transaction request.
+#[derive(Default)]
+struct ComputeBudget {
+    unit_limit: Option<u32>,
+    unit_price: Option<u64>,
+    // The units and fee (in lamports) requested by the deprecated `RequestUnits`.
+    requested_units: Option<(u32, u32)>,
+    // How many instructions other programs have.
+    other_instructions: u64,
+    // How many signatures the instructions of the precompiled programs carry.
+    precompile_signatures: u64,
+}
+
+impl ComputeBudget {
+    fn of(transaction: &RawTransaction) -> Option<Self> {
+        let mut budget = ComputeBudget::default();
+        for instruction in &transaction.instructions {
+            let program = transaction.account_keys.get(instruction.program_id_index as usize)?
+                .to_string();
+            match program.as_str() {
+                COMPUTE_BUDGET_PROGRAM => budget.note(instruction)?,
+                SECP256K1_PROGRAM | ED25519_PROGRAM => {
+                    budget.other_instructions += 1;
+                    budget.precompile_signatures +=
+                        instruction.data.first().copied().unwrap_or(0) as u64;
+                }
+                _ => budget.other_instructions += 1,
+            }
+        }
+
+        Some(budget)
+    }
+
+    /// Notes what a ComputeBudget instruction requests. `None` if it can't be read, in which case
+    /// the transaction failed before paying anything but its base fee.
+    fn note(&mut self, instruction: &CompiledInstruction) -> Option<()> {
+        let data = &instruction.data;
+        let u32_at = |offset: usize| -> Option<u32> {
+            Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+        };
+        match data.first()? {
+            0 => self.requested_units = Some((u32_at(1)?, u32_at(5)?)),
+            // Requesting a larger heap frame, or limiting the size of the accounts loaded, is free.
+            1 | 4 => {}
+            2 => self.unit_limit = Some(u32_at(1)?),
+            3 => self.unit_price = Some(u64::from_le_bytes(data.get(1..9)?.try_into().ok()?)),
+            _ => return None,
+        }
+
+        Some(())
+    }
+
+    fn unit_limit(&self) -> u64 {
+        let limit = match (self.unit_limit, self.requested_units) {
+            (Some(limit), _) | (None, Some((limit, _))) => limit as u64,
+            (None, None) => self.other_instructions * DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT,
+        };
+
+        limit.min(MAX_COMPUTE_UNIT_LIMIT)
+    }
+
+    /// The priority fee and the compute unit price it was paid at.
+    fn priority_fee(&self) -> (u64, u64) {
+        let limit = self.unit_limit() as u128;
+        match (self.unit_price, self.requested_units) {
+            (None, Some((_, fee))) => {
+                let price = (fee as u128 * MICRO_LAMPORTS_PER_LAMPORT).checked_div(limit);
+                (fee as u64, price.unwrap_or(0) as u64)
+            }
+            (price, _) => {
+                let price = price.unwrap_or(0);
+                let fee = (price as u128 * limit + MICRO_LAMPORTS_PER_LAMPORT - 1)
+                    / MICRO_LAMPORTS_PER_LAMPORT;
+                (fee as u64, price)
+            }
+        }
+    }
+}
+
+/// Breaks the fee of `transaction` down, `None` when it doesn't add up (see the module
+/// documentation).
+pub fn breakdown(transaction: &RawTransaction) -> Option<FeeBreakdown> {
+    let budget = ComputeBudget::of(transaction)?;
+    if let Some(consumed) = compute_units_consumed(&transaction.log_messages) {
+        if consumed > budget.unit_limit() {
+            return None;
+        }
+    }
+    let (priority_fee_lamports, compute_unit_price_micro_lamports) = budget.priority_fee();
+
+    // Precompile signatures were only charged from 1.10 on.
+    let signatures = transaction.num_required_signatures as u64;
+    [signatures + budget.precompile_signatures, signatures].iter()
+        .map(|signatures| signatures * LAMPORTS_PER_SIGNATURE)
+        .find(|base_fee| base_fee.checked_add(priority_fee_lamports) == Some(transaction.fee))
+        .map(|base_fee_lamports| FeeBreakdown {
+            base_fee_lamports,
+            priority_fee_lamports,
+            compute_unit_price_micro_lamports,
+        })
+}
+
+/// The compute units the top-level instructions consumed, as logged (`Program <id> consumed <n>
+/// of <m> compute units`). `None` when there are no logs or they're truncated.
+pub fn compute_units_consumed(log_messages: &[String]) -> Option<u64> {
+    if log_messages.is_empty() {
+        return None;
+    }
+
+    let (mut consumed, mut height) = (0, 0);
+    for line in log_messages {
+        if line == "Log truncated" {
+            return None;
+        }
+        let line = match line.strip_prefix("Program ") {
+            // What programs log themselves.
+            Some(line) if line.starts_with("log: ") || line.starts_with("data: ")
+                || line.starts_with("return: ") => continue,
+            Some(line) => line,
+            None => continue,
+        };
+        if let Some((_, invoked)) = line.rsplit_once(" invoke [") {
+            height = invoked.strip_suffix(']')?.parse().ok()?;
+        } else if let Some((_, units)) = line.split_once(" consumed ") {
+            if height == 1 {
+                consumed += units.split(' ').next()?.parse::<u64>().ok()?;
+            }
+        } else if line.ends_with(" success") || line.contains(" failed: ") {
+            height -= 1;
+        }
+    }
+
+    Some(consumed)
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+
+    /// A transaction signed `signatures` times, paying `fee`, invoking a program with each of
+    /// `compute_budget` and `other` instructions (given by their program).
+    fn transaction(
+        signatures: u8,
+        fee: u64,
+        compute_budget: Vec<Vec<u8>>,
+        other: Vec<(&str, Vec<u8>)>,
+    ) -> RawTransaction {
+        let mut account_keys: Vec<Pubkey> = (0..signatures).map(|_| Pubkey::new_unique()).collect();
+        let mut program = |id: &str| {
+            account_keys.push(id.parse().unwrap());
+            (account_keys.len() - 1) as u8
+        };
+        let compute_budget_index = program(COMPUTE_BUDGET_PROGRAM);
+        let mut instructions: Vec<_> = compute_budget.into_iter()
+            .map(|data| CompiledInstruction {
+                program_id_index: compute_budget_index, accounts: vec![], data,
+            })
+            .collect();
+        for (id, data) in other {
+            let program_id_index = program(id);
+            instructions.push(CompiledInstruction { program_id_index, accounts: vec![0], data });
+        }
+
+        RawTransaction {
+            slot: 1,
+            block_time: None,
+            transaction_hash: "fees".to_string(),
+            account_keys,
+            num_required_signatures: signatures,
+            instructions,
+            inner_instructions: vec![],
+            token_mints: vec![],
+            fee,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        }
+    }
+
+    fn set_unit_limit(units: u32) -> Vec<u8> {
+        [&[2][..], &units.to_le_bytes()].concat()
+    }
+
+    fn set_unit_price(micro_lamports: u64) -> Vec<u8> {
+        [&[3][..], &micro_lamports.to_le_bytes()].concat()
+    }
+
+    const TOKEN: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+
+    fn fees(base: u64, priority: u64, price: u64) -> Option<FeeBreakdown> {
+        Some(FeeBreakdown {
+            base_fee_lamports: base,
+            priority_fee_lamports: priority,
+            compute_unit_price_micro_lamports: price,
+        })
+    }
+
+    #[test]
+    fn transactions_without_compute_budget_pay_their_signatures() {
+        let transfer = transaction(1, 5000, vec![], vec![(TOKEN, vec![3])]);
+        assert_eq!(breakdown(&transfer), fees(5000, 0, 0));
+        let cosigned = transaction(2, 10_000, vec![], vec![(TOKEN, vec![3])]);
+        assert_eq!(breakdown(&cosigned), fees(10_000, 0, 0));
+    }
+
+    #[test]
+    fn priority_fees_are_paid_for_the_units_requested() {
+        // A swap asking for 300k units at 0.05 lamports each.
+        let swap = transaction(1, 20_000, vec![set_unit_limit(300_000), set_unit_price(50_000)],
+                               vec![(TOKEN, vec![3])]);
+        assert_eq!(breakdown(&swap), fees(5000, 15_000, 50_000));
+
+        // No limit set: 200k units for each of the two other instructions.
+        let price_only = transaction(1, 5400, vec![set_unit_price(1_000)],
+                                     vec![(TOKEN, vec![3]), (TOKEN, vec![7])]);
+        assert_eq!(breakdown(&price_only), fees(5000, 400, 1_000));
+
+        // A limit alone costs nothing.
+        let limit_only = transaction(1, 5000, vec![set_unit_limit(50_000)], vec![(TOKEN, vec![3])]);
+        assert_eq!(breakdown(&limit_only), fees(5000, 0, 0));
+
+        // Fractions of a lamport are paid in full.
+        let rounded = transaction(1, 5001, vec![set_unit_limit(1_000), set_unit_price(1)],
+                                  vec![(TOKEN, vec![3])]);
+        assert_eq!(breakdown(&rounded), fees(5000, 1, 1));
+    }
+
+    #[test]
+    fn deprecated_unit_requests_pay_their_additional_fee() {
+        let request_units = [&[0][..], &400_000u32.to_le_bytes(), &2_000u32.to_le_bytes()].concat();
+        let transaction = transaction(1, 7000, vec![request_units], vec![(TOKEN, vec![3])]);
+        assert_eq!(breakdown(&transaction), fees(5000, 2000, 5_000));
+    }
+
+    #[test]
+    fn precompile_signatures_are_charged_when_they_add_up() {
+        let secp256k1 = vec![(SECP256K1_PROGRAM, vec![2]), (TOKEN, vec![3])];
+        let charged = transaction(1, 15_000, vec![], secp256k1.clone());
+        assert_eq!(breakdown(&charged), fees(15_000, 0, 0));
+        // Before 1.10.
+        let uncharged = transaction(1, 5000, vec![], secp256k1);
+        assert_eq!(breakdown(&uncharged), fees(5000, 0, 0));
+    }
+
+    #[test]
+    fn fees_that_dont_add_up_arent_broken_down() {
+        // From when signatures were charged 10000 lamports.
+        let early = transaction(1, 10_000, vec![], vec![(TOKEN, vec![3])]);
+        assert_eq!(breakdown(&early), None);
+
+        let mut over_limit = transaction(1, 5000, vec![set_unit_limit(1_000)],
+                                         vec![(TOKEN, vec![3])]);
+        over_limit.log_messages = vec![
+            format!("Program {} invoke [1]", COMPUTE_BUDGET_PROGRAM),
+            format!("Program {} success", COMPUTE_BUDGET_PROGRAM),
+            format!("Program {} invoke [1]", TOKEN),
+            format!("Program {} consumed 2000 of 1000 compute units", TOKEN),
+            format!("Program {} success", TOKEN),
+        ];
+        assert_eq!(breakdown(&over_limit), None);
+
+        let unreadable = transaction(1, 5000, vec![vec![2, 1]], vec![(TOKEN, vec![3])]);
+        assert_eq!(breakdown(&unreadable), None);
+    }
+
+    #[test]
+    fn only_top_level_units_are_counted() {
+        let logs: Vec<String> = vec![
+            "Program Router invoke [1]",
+            "Program Token invoke [2]",
+            "Program Token consumed 4000 of 190000 compute units",
+            "Program Token success",
+            "Program Router consumed 12000 of 200000 compute units",
+            "Program Router success",
+            "Program Token invoke [1]",
+            "Program Token consumed 3000 of 188000 compute units",
+            "Program Token failed: custom program error: 0x1",
+        ].into_iter().map(String::from).collect();
+        assert_eq!(compute_units_consumed(&logs), Some(15_000));
+        assert_eq!(compute_units_consumed(&[]), None);
+        assert_eq!(compute_units_consumed(&["Log truncated".to_string()]), None);
+    }
+}
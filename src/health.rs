@@ -0,0 +1,325 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+use crate::stats::StatsReporter;
+
+// Slot 0 is never indexed, so it stands for "unknown" in the atomics below.
+const UNKNOWN_SLOT: u64 = 0;
+
+/// The thresholds of the health checks.
+#[derive(Clone, Debug)]
+pub struct HealthConfig {
+    // How long a single write to the sink may take before the process is reported unhealthy.
+    pub sink_timeout: Duration,
+    // How many slots ingestion may be behind the tip of the chain and still be ready.
+    pub max_slot_lag: u64,
+    // How long ingestion may go without indexing a new slot and still be ready.
+    pub max_stall: Duration,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            sink_timeout: Duration::from_secs(30),
+            max_slot_lag: 150,
+            max_stall: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Where ingestion is, shared between the pipeline recording it and the health checks.
+pub struct IngestProgress {
+    // The last slot fully written to the sink.
+    current_slot: AtomicU64,
+    // The tip of the chain, as last reported by whoever feeds the pipeline.
+    tip_slot: AtomicU64,
+    // When `current_slot` last moved.
+    advanced_at: Mutex<Instant>,
+    // When the write to the sink in progress, if any, started.
+    write_started_at: Mutex<Option<Instant>>,
+    // The slots handed to the pipeline that haven't been fully written yet.
+    in_flight: Mutex<BTreeSet<u64>>,
+}
+
+impl Default for IngestProgress {
+    fn default() -> Self {
+        IngestProgress {
+            current_slot: AtomicU64::new(UNKNOWN_SLOT),
+            tip_slot: AtomicU64::new(UNKNOWN_SLOT),
+            advanced_at: Mutex::new(Instant::now()),
+            write_started_at: Mutex::new(None),
+            in_flight: Mutex::new(BTreeSet::new()),
+        }
+    }
+}
+
+impl IngestProgress {
+    /// The last slot fully written to the sink, if any.
+    pub fn current_slot(&self) -> Option<u64> {
+        known(self.current_slot.load(Ordering::Relaxed))
+    }
+
+    /// The tip of the chain, if it has been reported.
+    pub fn tip_slot(&self) -> Option<u64> {
+        known(self.tip_slot.load(Ordering::Relaxed))
+    }
+
+    /// How far behind the tip ingestion is, once both slots are known.
+    pub fn lag_slots(&self) -> Option<u64> {
+        Some(self.tip_slot()?.saturating_sub(self.current_slot()?))
+    }
+
+    /// The slots handed to the pipeline that haven't been fully written yet, in order.
+    pub fn unwritten_slots(&self) -> Vec<u64> {
+        self.in_flight.lock().unwrap().iter().copied().collect()
+    }
+
+    pub(crate) fn accept(&self, slot: u64) {
+        self.in_flight.lock().unwrap().insert(slot);
+    }
+
+    pub fn set_current_slot(&self, slot: u64) {
+        self.in_flight.lock().unwrap().remove(&slot);
+        if self.current_slot.swap(slot, Ordering::Relaxed) != slot {
+            *self.advanced_at.lock().unwrap() = Instant::now();
+        }
+    }
+
+    pub fn set_tip_slot(&self, slot: u64) {
+        self.tip_slot.store(slot, Ordering::Relaxed);
+    }
+
+    /// Runs a write to the sink, keeping track of how long it has been going on for.
+    pub(crate) async fn track_write<F: Future>(&self, write: F) -> F::Output {
+        *self.write_started_at.lock().unwrap() = Some(Instant::now());
+        let output = write.await;
+        *self.write_started_at.lock().unwrap() = None;
+
+        output
+    }
+
+    /// Whether the process is alive: no write to the sink has been hanging for longer than
+    /// `sink_timeout`. Returns why it isn't otherwise.
+    pub fn check_live(&self, config: &HealthConfig) -> Result<(), String> {
+        match *self.write_started_at.lock().unwrap() {
+            Some(started_at) if started_at.elapsed() > config.sink_timeout => {
+                Err(format!("the sink hasn't answered for {:?}", started_at.elapsed()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether ingestion is ready: within `max_slot_lag` slots of the tip, and still advancing.
+    /// Returns why it isn't otherwise.
+    pub fn check_ready(&self, config: &HealthConfig) -> Result<(), String> {
+        let current_slot = self.current_slot().ok_or("nothing has been indexed yet")?;
+        let tip_slot = self.tip_slot().ok_or("the tip of the chain isn't known yet")?;
+        let lag = tip_slot.saturating_sub(current_slot);
+        if lag > config.max_slot_lag {
+            return Err(format!("{} slots behind the tip", lag));
+        }
+        let stalled_for = self.advanced_at.lock().unwrap().elapsed();
+        if stalled_for > config.max_stall {
+            return Err(format!("stuck at slot {} for {:?}", current_slot, stalled_for));
+        }
+
+        Ok(())
+    }
+}
+
+fn known(slot: u64) -> Option<u64> {
+    if slot == UNKNOWN_SLOT { None } else { Some(slot) }
+}
+
+/// Serves `GET /healthz` (liveness) and `GET /readyz` (readiness) at `address` from a
+/// background thread, for as long as the process runs: 200 when the check passes, 503 with the
+/// reason otherwise. Returns the address actually bound (useful with port 0).
+pub fn serve<A: ToSocketAddrs>(
+    address: A,
+    progress: Arc<IngestProgress>,
+    config: HealthConfig,
+) -> io::Result<SocketAddr> {
+    spawn(address, progress, None, config)
+}
+
+/// Same as [`serve`], also serving the stats of `stats` as JSON on `GET /stats`.
+pub fn serve_with_stats<A: ToSocketAddrs>(
+    address: A,
+    stats: Arc<StatsReporter>,
+    config: HealthConfig,
+) -> io::Result<SocketAddr> {
+    spawn(address, stats.progress().clone(), Some(stats), config)
+}
+
+fn spawn<A: ToSocketAddrs>(
+    address: A,
+    progress: Arc<IngestProgress>,
+    stats: Option<Arc<StatsReporter>>,
+    config: HealthConfig,
+) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(address)?;
+    let address = listener.local_addr()?;
+
+    thread::Builder::new()
+        .name("spi-health".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.and_then(|stream| {
+                    respond(stream, &progress, stats.as_deref(), &config)
+                });
+                if let Err(err) = result {
+                    warn!("[spi-wrapper/health] Failed to serve a health check: {}", err);
+                }
+            }
+        })?;
+
+    Ok(address)
+}
+
+fn respond(
+    mut stream: TcpStream,
+    progress: &IngestProgress,
+    stats: Option<&StatsReporter>,
+    config: &HealthConfig,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers, we don't need any of them.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let check = match path {
+        "/healthz" => Some(progress.check_live(config)),
+        "/readyz" => Some(progress.check_ready(config)),
+        _ => None,
+    };
+    let (status, content_type, body) = match (check, path, stats) {
+        (Some(Ok(())), _, _) => ("200 OK", "text/plain", "ok\n".to_string()),
+        (Some(Err(reason)), _, _) => {
+            ("503 Service Unavailable", "text/plain", format!("{}\n", reason))
+        }
+        (None, "/stats", Some(stats)) => {
+            let body = serde_json::to_string(&stats.snapshot()).map_err(io::Error::from)?;
+            ("200 OK", "application/json", body)
+        }
+        (None, _, _) => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+    };
+
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+    Connection: close\r\n\r\n{}", status, content_type, body.len(), body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use async_trait::async_trait;
+    use serde_json::json;
+    use solana_transaction_status::UiConfirmedBlock;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::block::BlockRecord;
+    use crate::ingest::pipeline::{Pipeline, PipelineConfig};
+    use crate::registry::ProcessorRegistry;
+    use crate::sinks::{Sink, SinkError};
+    use crate::stats::StatsSnapshot;
+
+    /// Writes the header of the first block it gets, then hangs forever.
+    struct StallingSink;
+
+    #[async_trait]
+    impl Sink for StallingSink {
+        async fn write_block_header(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+            if block.slot > 1 {
+                futures::future::pending::<()>().await;
+            }
+            Ok(())
+        }
+    }
+
+    fn empty_block(slot: u64) -> UiConfirmedBlock {
+        serde_json::from_value(json!({
+            "blockhash": bs58::encode([slot as u8; 32]).into_string(),
+            "previousBlockhash": bs58::encode([slot as u8 - 1; 32]).into_string(),
+            "parentSlot": slot - 1,
+            "transactions": [],
+            "blockTime": null,
+        })).unwrap()
+    }
+
+    #[tokio::test]
+    async fn stalled_sink_flips_readiness() {
+        let config = HealthConfig {
+            sink_timeout: Duration::from_millis(200),
+            max_slot_lag: 10,
+            max_stall: Duration::from_millis(200),
+        };
+        let pipeline = Pipeline::new(Arc::new(ProcessorRegistry::default()),
+                                     PipelineConfig::default());
+        let progress = pipeline.progress();
+        pipeline.set_tip_slot(2);
+        let (sender, receiver) = mpsc::channel(4);
+        tokio::spawn(async move { pipeline.run(receiver, &mut StallingSink).await });
+
+        sender.send((1, empty_block(1))).await.unwrap();
+        while progress.current_slot() != Some(1) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(progress.check_ready(&config), Ok(()));
+        assert_eq!(progress.check_live(&config), Ok(()));
+
+        sender.send((2, empty_block(2))).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert_eq!(progress.current_slot(), Some(1));
+        assert!(progress.check_ready(&config).unwrap_err().starts_with("stuck at slot 1"));
+        assert!(progress.check_live(&config).unwrap_err().starts_with("the sink hasn't answered"));
+    }
+
+    #[test]
+    fn lagging_behind_the_tip_is_not_ready() {
+        let progress = IngestProgress::default();
+        assert!(progress.check_ready(&HealthConfig::default()).is_err());
+
+        progress.set_current_slot(100);
+        progress.set_tip_slot(1_000);
+        assert_eq!(progress.check_ready(&HealthConfig::default()),
+                   Err("900 slots behind the tip".to_string()));
+
+        progress.set_current_slot(950);
+        assert_eq!(progress.check_ready(&HealthConfig::default()), Ok(()));
+    }
+
+    #[test]
+    fn serves_the_stats() {
+        let stats = Arc::new(StatsReporter::new(Arc::new(IngestProgress::default())));
+        stats.progress().set_current_slot(90);
+        stats.progress().set_tip_slot(100);
+        let address = serve_with_stats("127.0.0.1:0", stats, HealthConfig::default()).unwrap();
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(b"GET /stats HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let stats: StatsSnapshot = serde_json::from_str(body).unwrap();
+        assert_eq!(stats.current_slot, Some(90));
+        assert_eq!(stats.lag_slots, Some(10));
+    }
+}
@@ -0,0 +1,165 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tracing::{info, warn};
+
+use crate::checkpoint::{Checkpoint, Checkpointing};
+use crate::ingest::pool::RpcPool;
+use crate::ingest::{fetch_transactions, IngestError};
+use crate::registry::ProcessorRegistry;
+use crate::sinks::{self, Sink};
+use crate::transaction::decode_transaction;
+
+#[derive(Clone, Debug)]
+pub struct BackfillConfig {
+    // Where the cursor (the oldest signature processed so far) is checkpointed. Without a
+    // checkpoint the backfill always starts from the newest signature.
+    pub checkpoint: Option<Checkpointing>,
+    // The maximum number of RPC requests issued per second.
+    pub requests_per_second: u32,
+    // How many signatures are asked for per `getSignaturesForAddress` page (at most 1000).
+    pub page_limit: usize,
+    pub commitment: CommitmentConfig,
+}
+
+impl Default for BackfillConfig {
+    fn default() -> Self {
+        BackfillConfig {
+            checkpoint: None,
+            requests_per_second: 10,
+            page_limit: 1000,
+            commitment: CommitmentConfig::confirmed(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BackfillProgress {
+    // Transactions decoded and written to the sink.
+    pub processed: usize,
+    // Signatures whose transaction the node no longer has.
+    pub missing: usize,
+    // The oldest slot reached so far.
+    pub earliest_slot: Option<u64>,
+}
+
+/// Spaces requests out evenly so we stay under the provider's rate limit.
+struct RateLimiter {
+    interval: Duration,
+    next: Instant,
+}
+
+impl RateLimiter {
+    fn new(requests_per_second: u32) -> Self {
+        RateLimiter {
+            interval: Duration::from_secs(1) / requests_per_second.max(1),
+            next: Instant::now(),
+        }
+    }
+
+    async fn wait(&mut self) {
+        let now = Instant::now();
+        if self.next > now {
+            tokio::time::sleep(self.next - now).await;
+        }
+        self.next = self.next.max(now) + self.interval;
+    }
+}
+
+/// Indexes every transaction that ever referenced `address`, newest first, stopping at
+/// `until_signature` (exclusive) or at the first transaction of the address.
+///
+/// The cursor is checkpointed after every page has been written to the sink, so an interrupted
+/// backfill picks up where it left off when it's started again with the same checkpoint.
+pub async fn backfill_address<S, F>(
+    rpc: &Arc<RpcPool>,
+    address: &Pubkey,
+    until_signature: Option<Signature>,
+    sink: &mut S,
+    registry: &Arc<ProcessorRegistry>,
+    config: &BackfillConfig,
+    mut on_progress: F,
+) -> Result<BackfillProgress, IngestError>
+where
+    S: Sink,
+    F: FnMut(&BackfillProgress),
+{
+    let mut rate_limiter = RateLimiter::new(config.requests_per_second);
+    let mut progress = BackfillProgress::default();
+    let mut before = match &config.checkpoint {
+        Some(checkpoint) => checkpoint.load().await?
+            .and_then(|checkpoint| checkpoint.last_signature)
+            .and_then(|signature| Signature::from_str(&signature).ok()),
+        None => None,
+    };
+
+    loop {
+        rate_limiter.wait().await;
+        let page_address = *address;
+        let page_limit = config.page_limit;
+        let commitment = config.commitment;
+        let page = rpc.call(move |rpc| {
+            rpc.get_signatures_for_address_with_config(&page_address,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until: until_signature,
+                    limit: Some(page_limit),
+                    commitment: Some(commitment),
+                })
+        }).await?;
+
+        if page.is_empty() {
+            break;
+        }
+
+        let page_len = page.len();
+        // A batch of transactions counts as one request against the rate limit.
+        for statuses in page.chunks(rpc.batch_size()) {
+            rate_limiter.wait().await;
+            let signatures: Vec<_> = statuses.iter()
+                .map(|status| status.signature.clone())
+                .collect();
+            let transactions = fetch_transactions(rpc, &signatures, config.commitment).await?;
+            for (status, transaction) in statuses.iter().zip(transactions) {
+                match transaction {
+                    Some(transaction) => {
+                        let mut record = decode_transaction(registry, &transaction);
+                        record.finalized = config.commitment.is_finalized();
+                        sinks::write_transactions(sink, &[record]).await?;
+                        progress.processed += 1;
+                    }
+                    None => {
+                        warn!("[spi-wrapper/ingest/backfill] Transaction {} is not available on \
+                        this node, skipping.", status.signature);
+                        progress.missing += 1;
+                    }
+                }
+                progress.earliest_slot = Some(progress.earliest_slot
+                    .map_or(status.slot, |slot| slot.min(status.slot)));
+            }
+        }
+        sinks::flush(sink).await?;
+
+        // Only move the cursor once the whole page is safely in the sink.
+        let oldest = &page[page_len - 1].signature;
+        if let Some(checkpoint) = &config.checkpoint {
+            checkpoint.save(&Checkpoint::new(progress.earliest_slot, Some(oldest.clone()))).await?;
+        }
+        before = Signature::from_str(oldest).ok();
+        on_progress(&progress);
+
+        if page_len < config.page_limit || before.is_none() {
+            break;
+        }
+    }
+
+    info!("[spi-wrapper/ingest/backfill] Backfill of {} done: {} transactions processed, {} \
+    missing.", address, progress.processed, progress.missing);
+
+    Ok(progress)
+}
@@ -0,0 +1,470 @@
+//! Coalescing RPC fetches: transactions are fetched through JSON-RPC batches of `getTransaction`
+//! requests, and accounts through `getMultipleAccounts` calls, [`RpcPoolConfig::batch_size`] at a
+//! time, rather than with a call each. Callers fetching what another one is already fetching
+//! (concurrent backfill workers, the mint resolver) wait for that fetch instead of issuing their
+//! own.
+//!
+//! Whatever a batch fails (the node erroring on some of its requests, or on the whole batch, e.g.
+//! when it doesn't serve batches) is fetched again on its own, through the retries of the pool.
+//!
+//! [`RpcPoolConfig::batch_size`]: crate::ingest::pool::RpcPoolConfig::batch_size
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde_json::{json, Value};
+use solana_client::client_error::Result as ClientResult;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_client::rpc_request::RpcRequest;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::UiTransactionEncoding;
+use tokio::sync::watch;
+use tracing::debug;
+
+use crate::ingest::pool::RpcPool;
+
+// The most accounts `getMultipleAccounts` is asked for at once.
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Where a fetch stands.
+#[derive(Clone)]
+enum Outcome<T> {
+    Pending,
+    Fetched(T),
+    // The batch failed it, those waiting for it fetch it on their own.
+    Failed,
+}
+
+/// The fetches in flight, by what they fetch.
+struct InFlight<K, T> {
+    fetches: Mutex<HashMap<K, watch::Receiver<Outcome<T>>>>,
+}
+
+impl<K, T> Default for InFlight<K, T> {
+    fn default() -> Self {
+        InFlight { fetches: Mutex::new(HashMap::new()) }
+    }
+}
+
+/// Forgets the fetches a caller made once it's done with them, whether it got to the end or not.
+struct Forget<'a, K: Eq + Hash, T> {
+    in_flight: &'a InFlight<K, T>,
+    keys: Vec<K>,
+}
+
+impl<K: Eq + Hash, T> Drop for Forget<'_, K, T> {
+    fn drop(&mut self) {
+        let mut fetches = self.in_flight.fetches.lock().unwrap();
+        for key in &self.keys {
+            fetches.remove(key);
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, T: Clone> InFlight<K, T> {
+    /// Fetches `keys`: those no one is fetching yet with `fetch`, `batch_size` at a time, then
+    /// waits for the others. `fetch` returns what it got of every key of a batch, in order, `None`
+    /// for what the batch failed. The outcome of every key, in order, `None` for those that
+    /// failed.
+    async fn fetch<F, Fut>(
+        &self,
+        keys: &[K],
+        batch_size: usize,
+        stats: &FetchStats,
+        fetch: F,
+    ) -> Vec<Option<T>>
+    where
+        F: Fn(Vec<K>) -> Fut,
+        Fut: Future<Output = ClientResult<Vec<Option<T>>>>,
+    {
+        let mut senders = HashMap::new();
+        let mut fetched = Vec::new();
+        let receivers: Vec<_> = {
+            let mut fetches = self.fetches.lock().unwrap();
+            keys.iter()
+                .map(|key| match fetches.get(key) {
+                    Some(receiver) => {
+                        stats.shared.fetch_add(1, Ordering::Relaxed);
+                        receiver.clone()
+                    }
+                    None => {
+                        let (sender, receiver) = watch::channel(Outcome::Pending);
+                        fetches.insert(key.clone(), receiver.clone());
+                        senders.insert(key.clone(), sender);
+                        fetched.push(key.clone());
+                        receiver
+                    }
+                })
+                .collect()
+        };
+        stats.requested.fetch_add(keys.len() as u64, Ordering::Relaxed);
+        let forget = Forget { in_flight: self, keys: fetched };
+
+        for batch in forget.keys.chunks(batch_size.max(1)) {
+            stats.sent.fetch_add(1, Ordering::Relaxed);
+            match fetch(batch.to_vec()).await {
+                Ok(values) => {
+                    for (key, value) in batch.iter().zip(values) {
+                        if let Some(value) = value {
+                            if let Some(sender) = senders.remove(key) {
+                                let _ = sender.send(Outcome::Fetched(value));
+                            }
+                        }
+                    }
+                }
+                Err(err) => debug!(error = %err, size = batch.len(),
+                                   "[spi-wrapper/ingest/batch] A batch failed."),
+            }
+        }
+        // What's left failed, waiters included.
+        for (_, sender) in senders {
+            let _ = sender.send(Outcome::Failed);
+        }
+
+        let mut outcomes = Vec::with_capacity(receivers.len());
+        for receiver in receivers {
+            outcomes.push(outcome(receiver).await);
+        }
+
+        outcomes
+    }
+}
+
+/// The outcome of a fetch, once known.
+async fn outcome<T: Clone>(mut receiver: watch::Receiver<Outcome<T>>) -> Option<T> {
+    loop {
+        let outcome = receiver.borrow().clone();
+        match outcome {
+            Outcome::Fetched(value) => return Some(value),
+            Outcome::Failed => return None,
+            // The fetch was given up when its sender went away, unless it just got its outcome.
+            Outcome::Pending => if receiver.changed().await.is_err() {
+                return match receiver.borrow().clone() {
+                    Outcome::Fetched(value) => Some(value),
+                    Outcome::Pending | Outcome::Failed => None,
+                };
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct FetchStats {
+    // What callers asked for.
+    requested: AtomicU64,
+    // The batches sent for it.
+    sent: AtomicU64,
+    // What was being fetched already when asked for.
+    shared: AtomicU64,
+    // What the batches failed, fetched again on its own.
+    refetched: AtomicU64,
+}
+
+impl FetchStats {
+    fn snapshot(&self) -> BatchStats {
+        let requested = self.requested.load(Ordering::Relaxed);
+        let sent = self.sent.load(Ordering::Relaxed) + self.refetched.load(Ordering::Relaxed);
+        BatchStats {
+            requested,
+            sent,
+            shared: self.shared.load(Ordering::Relaxed),
+            saved: requested.saturating_sub(sent),
+        }
+    }
+}
+
+/// The fetches in flight through a pool, and what batching them saved.
+#[derive(Default)]
+pub(crate) struct Batches {
+    // By signature and commitment.
+    transactions: InFlight<(String, String), Value>,
+    transaction_stats: FetchStats,
+    // By address and commitment.
+    accounts: InFlight<(Pubkey, String), Option<Account>>,
+    account_stats: FetchStats,
+}
+
+/// What batching the fetches of a kind saved.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    // What callers asked for.
+    pub requested: u64,
+    // The RPC calls made for it, batches and fetches made again on their own.
+    pub sent: u64,
+    // What was being fetched already when asked for.
+    pub shared: u64,
+    // The RPC calls spared, one call per request being made otherwise.
+    pub saved: u64,
+}
+
+impl RpcPool {
+    /// Fetches the transactions of `signatures` as `getTransaction` returns them (base64
+    /// encoded), in order, `Value::Null` for those the node doesn't have. Fetched in batches,
+    /// along with concurrent fetches, see the module documentation.
+    pub async fn get_transactions(
+        &self,
+        signatures: &[String],
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Vec<Value>> {
+        let keys: Vec<_> = signatures.iter()
+            .map(|signature| (signature.clone(), commitment_key(commitment)))
+            .collect();
+        let stats = &self.batches.transaction_stats;
+        let fetched = self.batches.transactions.fetch(&keys, self.batch_size(), stats, |batch| {
+            let signatures = batch.into_iter().map(|(signature, _)| signature).collect();
+            self.get_transaction_batch(signatures, commitment)
+        }).await;
+
+        let mut transactions = Vec::with_capacity(fetched.len());
+        for (signature, transaction) in signatures.iter().zip(fetched) {
+            let transaction = match transaction {
+                Some(transaction) => transaction,
+                None => {
+                    stats.refetched.fetch_add(1, Ordering::Relaxed);
+                    let params = transaction_params(signature, commitment);
+                    self.call(move |rpc| rpc.send::<Value>(RpcRequest::GetTransaction,
+                                                           params.clone()))
+                        .await?
+                }
+            };
+            transactions.push(transaction);
+        }
+
+        Ok(transactions)
+    }
+
+    /// Fetches the transactions of `signatures` in one batch, in order, `None` for those the
+    /// batch failed.
+    async fn get_transaction_batch(
+        &self,
+        signatures: Vec<String>,
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Vec<Option<Value>>> {
+        if let [signature] = signatures.as_slice() {
+            let params = transaction_params(signature, commitment);
+            let transaction = self.call(move |rpc| {
+                rpc.send::<Value>(RpcRequest::GetTransaction, params.clone())
+            }).await?;
+            return Ok(vec![Some(transaction)]);
+        }
+
+        let requests: Vec<_> = signatures.iter()
+            .map(|signature| {
+                (RpcRequest::GetTransaction, transaction_params(signature, commitment))
+            })
+            .collect();
+        let transactions = self.send_batch(&requests).await?;
+        record_saved("getTransaction", transactions.iter().filter(|t| t.is_some()).count());
+
+        Ok(transactions)
+    }
+
+    /// Fetches the accounts of `pubkeys`, in order, `None` for those that don't exist. Fetched
+    /// in batches, along with concurrent fetches, see the module documentation.
+    pub async fn get_multiple_accounts(
+        &self,
+        pubkeys: &[Pubkey],
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Vec<Option<Account>>> {
+        let keys: Vec<_> = pubkeys.iter()
+            .map(|pubkey| (*pubkey, commitment_key(commitment)))
+            .collect();
+        let batch_size = self.batch_size().min(MAX_MULTIPLE_ACCOUNTS);
+        let stats = &self.batches.account_stats;
+        let fetched = self.batches.accounts.fetch(&keys, batch_size, stats, |batch| {
+            let pubkeys = batch.into_iter().map(|(pubkey, _)| pubkey).collect();
+            self.get_account_batch(pubkeys, commitment)
+        }).await;
+
+        let mut accounts = Vec::with_capacity(fetched.len());
+        for (pubkey, account) in pubkeys.iter().zip(fetched) {
+            let account = match account {
+                Some(account) => account,
+                None => {
+                    stats.refetched.fetch_add(1, Ordering::Relaxed);
+                    let pubkey = *pubkey;
+                    self.call(move |rpc| rpc.get_account_with_commitment(&pubkey, commitment))
+                        .await?
+                        .value
+                }
+            };
+            accounts.push(account);
+        }
+
+        Ok(accounts)
+    }
+
+    /// Fetches the accounts of `pubkeys` in one call, in order. Those the node leaves out count
+    /// as failed.
+    async fn get_account_batch(
+        &self,
+        pubkeys: Vec<Pubkey>,
+        commitment: CommitmentConfig,
+    ) -> ClientResult<Vec<Option<Option<Account>>>> {
+        let count = pubkeys.len();
+        let accounts = self.call(move |rpc| {
+            rpc.get_multiple_accounts_with_commitment(&pubkeys, commitment)
+        }).await?.value;
+        if accounts.len() == count {
+            record_saved("getMultipleAccounts", count);
+        }
+
+        Ok(accounts.into_iter().map(Some).collect())
+    }
+
+    /// What batching the transactions fetched saved.
+    pub fn transaction_batch_stats(&self) -> BatchStats {
+        self.batches.transaction_stats.snapshot()
+    }
+
+    /// What batching the accounts fetched saved.
+    pub fn account_batch_stats(&self) -> BatchStats {
+        self.batches.account_stats.snapshot()
+    }
+}
+
+fn transaction_params(signature: &str, commitment: CommitmentConfig) -> Value {
+    json!([signature, RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(commitment),
+    }])
+}
+
+/// Tells the commitments fetches are made at apart.
+fn commitment_key(commitment: CommitmentConfig) -> String {
+    format!("{:?}", commitment.commitment)
+}
+
+/// Records the calls spared by a batch that resolved `resolved` requests.
+#[allow(unused_variables)]
+fn record_saved(method: &str, resolved: usize) {
+    #[cfg(feature = "metrics")]
+    crate::metrics::metrics().rpc_requests_saved
+        .with_label_values(&[method])
+        .inc_by(resolved.saturating_sub(1) as u64);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::ingest::pool::RpcPoolConfig;
+
+    /// A JSON-RPC server answering every request with a null result, but for the batched
+    /// requests of signatures starting with `fail`, which it errors on. Counts the HTTP requests.
+    fn mock_server() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let counted = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                        break;
+                    }
+                    let line = line.to_ascii_lowercase();
+                    if let Some(length) = line.strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                counted.fetch_add(1, Ordering::SeqCst);
+
+                let answer = |request: &Value| match request["params"][0].as_str() {
+                    Some(signature) if signature.starts_with("fail") => json!({
+                        "jsonrpc": "2.0", "id": request["id"],
+                        "error": { "code": -32603, "message": "Internal error" },
+                    }),
+                    _ => json!({ "jsonrpc": "2.0", "id": request["id"], "result": null }),
+                };
+                let body = match serde_json::from_slice::<Value>(&body).unwrap() {
+                    Value::Array(requests) => Value::Array(requests.iter().map(answer).collect()),
+                    request => json!({ "jsonrpc": "2.0", "id": request["id"], "result": null }),
+                }.to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                    Connection: close\r\n\r\n{}", body.len(), body);
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn pool(url: String, batch_size: usize) -> RpcPool {
+        RpcPool::new(vec![url], RpcPoolConfig {
+            requests_per_second: 1000.0,
+            burst: 1000.0,
+            batch_size,
+            ..RpcPoolConfig::default()
+        })
+    }
+
+    fn signatures(prefix: &str, count: usize) -> Vec<String> {
+        (0..count).map(|n| format!("{}{}", prefix, n)).collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn signatures_are_fetched_a_batch_at_a_time() {
+        for &(count, batch_size) in &[(10, 4), (8, 4), (3, 1), (250, 100)] {
+            let (url, requests) = mock_server();
+            let pool = pool(url, batch_size);
+            let transactions = pool.get_transactions(&signatures("ok", count),
+                                                     CommitmentConfig::confirmed()).await.unwrap();
+            assert_eq!(transactions, vec![Value::Null; count]);
+            let batches = (count + batch_size - 1) / batch_size;
+            assert_eq!(requests.load(Ordering::SeqCst), batches, "{} by {}", count, batch_size);
+            assert_eq!(pool.transaction_batch_stats().saved, (count - batches) as u64);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_fetches_of_a_signature_share_one_call() {
+        let (url, requests) = mock_server();
+        let pool = pool(url, 4);
+        let signatures = signatures("ok", 10);
+        let commitment = CommitmentConfig::confirmed();
+
+        let (first, second) = tokio::join!(pool.get_transactions(&signatures, commitment),
+                                           pool.get_transactions(&signatures, commitment));
+        assert_eq!(first.unwrap(), second.unwrap());
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        let stats = pool.transaction_batch_stats();
+        assert_eq!((stats.requested, stats.sent, stats.shared), (20, 3, 10));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn requests_a_batch_fails_are_retried_on_their_own() {
+        let (url, requests) = mock_server();
+        let pool = pool(url, 10);
+        let signatures = vec!["ok0".to_string(), "fail0".to_string(), "ok1".to_string(),
+                              "fail1".to_string()];
+
+        let transactions = pool.get_transactions(&signatures, CommitmentConfig::confirmed())
+            .await
+            .unwrap();
+        assert_eq!(transactions, vec![Value::Null; 4]);
+        // The batch, then each failed signature.
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert_eq!(pool.transaction_batch_stats().sent, 3);
+    }
+}
@@ -0,0 +1,228 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use solana_storage_bigtable::{Error as BigtableError, LedgerStorage};
+use solana_transaction_status::{TransactionDetails, UiTransactionEncoding};
+use tracing::{info, warn};
+
+use crate::block::decode_block;
+use crate::ingest::leaders::LeaderResolver;
+use crate::ingest::timestamps::{BlockTimeSource, TimestampResolver};
+use crate::ingest::{BlockSource, IngestError};
+use crate::registry::ProcessorRegistry;
+use crate::sinks::{self, Sink};
+
+// How many slots are listed per `get_confirmed_blocks` call.
+const SLOT_PAGE_LIMIT: usize = 1000;
+// How often (in blocks) throughput is reported.
+const REPORT_INTERVAL: usize = 100;
+
+#[derive(Clone, Debug)]
+pub struct BigtableSourceConfig {
+    // How many blocks are read from Bigtable at once. Blocks still reach the sink in slot order.
+    pub concurrency: usize,
+    // The timeout of individual Bigtable requests.
+    pub timeout: Option<Duration>,
+    // Where the last sunk slot is persisted, so an interrupted read resumes after it.
+    pub checkpoint_path: Option<PathBuf>,
+}
+
+impl Default for BigtableSourceConfig {
+    fn default() -> Self {
+        BigtableSourceConfig {
+            concurrency: 8,
+            timeout: Some(Duration::from_secs(30)),
+            checkpoint_path: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BigtableReport {
+    pub blocks: usize,
+    pub transactions: usize,
+    pub elapsed: Duration,
+    // Pairs of (last good slot, first slot after it) whose parent didn't chain to the previous
+    // block we read.
+    pub gaps: Vec<(u64, u64)>,
+}
+
+impl BigtableReport {
+    pub fn blocks_per_second(&self) -> f64 {
+        self.blocks as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Reads confirmed blocks straight from the Bigtable historical ledger.
+///
+/// Credentials are picked up from `GOOGLE_APPLICATION_CREDENTIALS`, like the validator does.
+pub struct BigtableSource {
+    storage: LedgerStorage,
+    registry: Arc<ProcessorRegistry>,
+    config: BigtableSourceConfig,
+    timestamps: Option<Arc<TimestampResolver>>,
+    leaders: Option<Arc<LeaderResolver>>,
+}
+
+impl BigtableSource {
+    pub async fn new(
+        registry: Arc<ProcessorRegistry>,
+        config: BigtableSourceConfig,
+    ) -> Result<Self, IngestError> {
+        let storage = LedgerStorage::new(true, config.timeout).await?;
+
+        Ok(BigtableSource {
+            storage,
+            registry,
+            config,
+            timestamps: None,
+            leaders: None,
+        })
+    }
+
+    /// Estimates the block time of blocks read without one with `resolver`, flagging their
+    /// records as estimated.
+    pub fn with_timestamp_resolver(mut self, resolver: Arc<TimestampResolver>) -> Self {
+        self.timestamps = Some(resolver);
+        self
+    }
+
+    /// Tags every block record with the epoch and leader of its slot with `resolver`. Nodes
+    /// don't serve the leader schedule of old epochs, whose blocks only get their epoch.
+    pub fn with_leader_resolver(mut self, resolver: Arc<LeaderResolver>) -> Self {
+        self.leaders = Some(resolver);
+        self
+    }
+
+    /// Where the block times of the blocks around those read without one can be looked up, for
+    /// a [`TimestampResolver`].
+    pub fn block_times(&self) -> Arc<dyn BlockTimeSource> {
+        Arc::new(self.storage.clone())
+    }
+
+    /// Indexes every confirmed block between `start_slot` and `end_slot` (both inclusive),
+    /// resuming after the checkpointed slot if there is one.
+    pub async fn index_range<S: Sink + ?Sized>(
+        &self,
+        start_slot: u64,
+        end_slot: u64,
+        sink: &mut S,
+    ) -> Result<BigtableReport, IngestError> {
+        let started = Instant::now();
+        let mut report = BigtableReport::default();
+        let mut next_slot = match self.load_checkpoint()? {
+            Some(checkpoint) if checkpoint >= start_slot => checkpoint + 1,
+            _ => start_slot,
+        };
+        let mut previous_slot: Option<u64> = None;
+
+        while next_slot <= end_slot {
+            let slots: Vec<u64> = self.storage.get_confirmed_blocks(next_slot, SLOT_PAGE_LIMIT)
+                .await?
+                .into_iter()
+                .filter(|slot| *slot <= end_slot)
+                .collect();
+            let last_slot = match slots.last() {
+                Some(last_slot) => *last_slot,
+                None => break,
+            };
+
+            let mut blocks = stream::iter(slots.into_iter().map(|slot| {
+                let storage = self.storage.clone();
+                async move { (slot, storage.get_confirmed_block(slot).await) }
+            })).buffered(self.config.concurrency.max(1));
+
+            while let Some((slot, block)) = blocks.next().await {
+                let block = block?;
+
+                if let Some(previous_slot) = previous_slot {
+                    if block.parent_slot != previous_slot {
+                        warn!("[spi-wrapper/ingest/bigtable] Block {} has parent {} but the \
+                        previous block read was {}, flagging a gap.",
+                            slot, block.parent_slot, previous_slot);
+                        report.gaps.push((previous_slot, slot));
+                    }
+                }
+                previous_slot = Some(slot);
+
+                let mut ui_block = block.configure(
+                    UiTransactionEncoding::Base64, TransactionDetails::Full, false);
+                let estimated = match &self.timestamps {
+                    Some(timestamps) => timestamps.resolve(slot, &mut ui_block).await?,
+                    None => false,
+                };
+                let mut record = decode_block(&self.registry, slot, &ui_block);
+                record.set_timestamp_estimated(estimated);
+                if let Some(leaders) = &self.leaders {
+                    leaders.enrich(&mut record).await;
+                }
+                // Only rooted blocks are uploaded to Bigtable.
+                record.set_finalized(true);
+                sinks::write_block(sink, &record).await?;
+
+                report.blocks += 1;
+                report.transactions += record.transactions.len();
+                if report.blocks % REPORT_INTERVAL == 0 {
+                    report.elapsed = started.elapsed();
+                    info!("[spi-wrapper/ingest/bigtable] {} blocks read ({:.1} blocks/s), at slot \
+                    {}.", report.blocks, report.blocks_per_second(), slot);
+                }
+            }
+
+            sinks::flush(sink).await?;
+            self.save_checkpoint(last_slot)?;
+            next_slot = last_slot + 1;
+        }
+
+        report.elapsed = started.elapsed();
+        info!("[spi-wrapper/ingest/bigtable] Read {} blocks ({} transactions) in {:?}, {:.1} \
+        blocks/s, {} gaps.", report.blocks, report.transactions, report.elapsed,
+            report.blocks_per_second(), report.gaps.len());
+
+        Ok(report)
+    }
+
+    fn load_checkpoint(&self) -> Result<Option<u64>, IngestError> {
+        match &self.config.checkpoint_path {
+            Some(path) if path.exists() => {
+                Ok(fs::read_to_string(path)?.trim().parse().ok())
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn save_checkpoint(&self, slot: u64) -> Result<(), IngestError> {
+        if let Some(path) = &self.config.checkpoint_path {
+            fs::write(path, slot.to_string())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BlockTimeSource for LedgerStorage {
+    async fn block_time(&self, slot: u64) -> Result<Option<i64>, IngestError> {
+        match self.get_confirmed_block(slot).await {
+            Ok(block) => Ok(block.block_time),
+            Err(BigtableError::BlockNotFound(_)) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl BlockSource for BigtableSource {
+    async fn index_range(
+        &self,
+        start_slot: u64,
+        end_slot: u64,
+        sink: &mut dyn Sink,
+    ) -> Result<usize, IngestError> {
+        Ok(BigtableSource::index_range(self, start_slot, end_slot, sink).await?.blocks)
+    }
+}
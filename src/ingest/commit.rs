@@ -0,0 +1,182 @@
+//! Agreeing on where batches end between a [`Pipeline`](crate::ingest::pipeline::Pipeline), its
+//! sink and its checkpoint, so that whoever feeds the pipeline resumes right after the last batch
+//! committed.
+//!
+//! In the default, at-least-once mode, the checkpoint of a batch is saved to the checkpoint store
+//! once the sink has flushed the batch: a crash in between has the batch written again on restart,
+//! so up to a batch of blocks may be written twice. In transactional mode, the sink writes a batch
+//! and its checkpoint in a single transaction (see [`Sink::begin`]): a crash before the commit
+//! leaves neither behind, so nothing is written twice. Only sinks supporting transactions (SQLite)
+//! can be committed to transactionally, and the checkpoint is then read back from the sink rather
+//! than from the checkpoint store.
+
+use std::sync::Arc;
+
+use crate::checkpoint::{Checkpoint, Checkpointing};
+use crate::health::IngestProgress;
+use crate::ingest::IngestError;
+use crate::sinks::{self, Sink};
+
+/// How batches and their checkpoints are committed, see the module documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommitMode {
+    AtLeastOnce,
+    Transactional,
+}
+
+impl Default for CommitMode {
+    fn default() -> Self {
+        CommitMode::AtLeastOnce
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CommitConfig {
+    pub mode: CommitMode,
+    // How many blocks make up a batch, a single batch committed once the pipeline stops when
+    // unset.
+    pub blocks_per_commit: Option<usize>,
+}
+
+/// Commits the batches a pipeline writes along with their checkpoint, see the module
+/// documentation.
+pub struct CommitCoordinator {
+    config: CommitConfig,
+    checkpoint: Option<Checkpointing>,
+    progress: Arc<IngestProgress>,
+    // How many blocks were written since the last commit.
+    pending: usize,
+    // Whether the sink has a transaction open.
+    open: bool,
+}
+
+impl CommitCoordinator {
+    pub fn new(
+        config: CommitConfig,
+        checkpoint: Option<Checkpointing>,
+        progress: Arc<IngestProgress>,
+    ) -> Self {
+        CommitCoordinator { config, checkpoint, progress, pending: 0, open: false }
+    }
+
+    pub fn mode(&self) -> CommitMode {
+        self.config.mode
+    }
+
+    /// The checkpoint of the last batch committed, to resume from: the one committed along with
+    /// it by the sink in transactional mode, the one saved to the checkpoint store otherwise.
+    pub async fn load<S: Sink + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> Result<Option<Checkpoint>, IngestError> {
+        let checkpoint = match &self.checkpoint {
+            Some(checkpoint) => checkpoint,
+            None => return Ok(None),
+        };
+
+        Ok(match self.config.mode {
+            CommitMode::AtLeastOnce => checkpoint.load().await?,
+            CommitMode::Transactional => sink.committed_checkpoint(&checkpoint.pipeline).await?,
+        })
+    }
+
+    /// Makes sure `sink` can be committed to as configured, before anything is written to it.
+    pub(crate) fn check<S: Sink + ?Sized>(&self, sink: &S) -> Result<(), IngestError> {
+        if self.config.mode == CommitMode::Transactional {
+            if !sink.supports_transactions() {
+                return Err(IngestError::Pipeline(
+                    "the sink can't commit checkpoints transactionally".to_string()));
+            }
+            if self.checkpoint.is_none() {
+                return Err(IngestError::Pipeline(
+                    "transactional commits need a checkpoint to commit".to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// To be called before a block is written, for it to be part of the batch being written.
+    pub(crate) async fn before_block<S: Sink + ?Sized>(
+        &mut self,
+        sink: &mut S,
+    ) -> Result<(), IngestError> {
+        if self.config.mode == CommitMode::Transactional && !self.open {
+            self.progress.track_write(sinks::begin(sink)).await?;
+            self.open = true;
+        }
+
+        Ok(())
+    }
+
+    /// To be called once a block has been written, everything up to `slot` having been written
+    /// then. Commits the batch once it's complete.
+    pub(crate) async fn block_written<S: Sink + ?Sized>(
+        &mut self,
+        sink: &mut S,
+        slot: Option<u64>,
+    ) -> Result<(), IngestError> {
+        self.pending += 1;
+        match self.config.blocks_per_commit {
+            Some(blocks) if self.pending >= blocks.max(1) => self.commit(sink, slot).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Commits whatever was written since the last commit, everything up to `slot` having been
+    /// written, and shuts `sink` down if `shutdown` is set (flushes it otherwise).
+    pub(crate) async fn finish<S: Sink + ?Sized>(
+        &mut self,
+        sink: &mut S,
+        slot: Option<u64>,
+        shutdown: bool,
+    ) -> Result<(), IngestError> {
+        if self.config.mode == CommitMode::Transactional {
+            self.commit(sink, slot).await?;
+        }
+        if shutdown {
+            self.progress.track_write(sinks::shutdown(sink)).await?;
+        } else {
+            self.progress.track_write(sinks::flush(sink)).await?;
+        }
+        if self.config.mode == CommitMode::AtLeastOnce {
+            self.save(slot).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn commit<S: Sink + ?Sized>(
+        &mut self,
+        sink: &mut S,
+        slot: Option<u64>,
+    ) -> Result<(), IngestError> {
+        self.pending = 0;
+        match self.config.mode {
+            CommitMode::AtLeastOnce => {
+                self.progress.track_write(sinks::flush(sink)).await?;
+                self.save(slot).await
+            }
+            CommitMode::Transactional if self.open => {
+                self.open = false;
+                let checkpoint = slot.map(|slot| Checkpoint::new(Some(slot), None));
+                let pipeline = self.checkpoint.as_ref().map_or("", |checkpoint| {
+                    checkpoint.pipeline.as_str()
+                });
+                self.progress.track_write(sinks::commit(sink, pipeline, checkpoint.as_ref()))
+                    .await?;
+
+                Ok(())
+            }
+            CommitMode::Transactional => Ok(()),
+        }
+    }
+
+    async fn save(&self, slot: Option<u64>) -> Result<(), IngestError> {
+        if let (Some(checkpoint), Some(slot)) = (&self.checkpoint, slot) {
+            checkpoint.save(&Checkpoint::new(Some(slot), None)).await?;
+        }
+
+        Ok(())
+    }
+}
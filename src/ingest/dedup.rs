@@ -0,0 +1,512 @@
+//! Skipping the transactions indexed before (after a restart, or when a gap is repaired) without
+//! decoding them again.
+//!
+//! The latest signatures sunk are remembered exactly, in an LRU. Older ones may be remembered by
+//! a rotating bloom filter: one filter per day, sized for the signatures expected in a day, the
+//! oldest day dropped as a new one starts. It can be saved next to the checkpoints to survive
+//! restarts.
+//!
+//! A bloom filter has false positives, so a transaction it claims to know may never have been
+//! indexed. Unless strict, such transactions are skipped anyway; in strict mode the sink is
+//! asked whether it has them instead, and only the ones it has are dropped (they're decoded
+//! either way, as decoding doesn't have access to the sink).
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::sinks::{Sink, SinkError};
+use crate::transaction::{RawTransaction, TransactionRecord};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Errors that may be returned while loading or saving the signatures seen.
+#[derive(Debug, Error)]
+pub enum DedupError {
+    #[error("Unable to access {path}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("{0} is corrupted")]
+    Corrupted(PathBuf),
+}
+
+#[derive(Clone, Debug)]
+pub struct DedupConfig {
+    // How many of the latest signatures are remembered exactly.
+    pub recent: usize,
+    // How many signatures a day the bloom filter is sized for. There's no bloom filter when unset.
+    pub expected_per_day: Option<u64>,
+    // The rate of false positives of the bloom filter, once it's full.
+    pub false_positive_rate: f64,
+    // How many days of signatures the bloom filter remembers.
+    pub days: usize,
+    // Whether the sink is asked about the signatures the bloom filter knows, instead of skipping
+    // their transactions.
+    pub strict: bool,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        DedupConfig {
+            recent: 100_000,
+            expected_per_day: None,
+            false_positive_rate: 0.001,
+            days: 2,
+            strict: false,
+        }
+    }
+}
+
+/// Whether a signature was seen before.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Seen {
+    No,
+    Yes,
+    // The bloom filter knows it, in strict mode.
+    Maybe,
+}
+
+/// The signatures sunk so far. Clones share them.
+#[derive(Clone)]
+pub struct DedupFilter {
+    config: Arc<DedupConfig>,
+    // Where the bloom filter is saved, if anywhere.
+    path: Option<PathBuf>,
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    recent: Recent,
+    bloom: Option<RotatingBloom>,
+}
+
+impl DedupFilter {
+    /// A filter remembering signatures for as long as the process runs.
+    pub fn new(config: DedupConfig) -> Self {
+        let bloom = config.expected_per_day
+            .map(|expected| RotatingBloom::new(expected, config.false_positive_rate, now()));
+
+        DedupFilter {
+            state: Arc::new(Mutex::new(State {
+                recent: Recent::new(config.recent),
+                bloom,
+            })),
+            config: Arc::new(config),
+            path: None,
+        }
+    }
+
+    /// A filter saving its bloom filter to `path` on `persist`, starting from the one saved
+    /// there, if any. A bloom filter sized differently is started over.
+    pub fn load<P: Into<PathBuf>>(config: DedupConfig, path: P) -> Result<Self, DedupError> {
+        let path = path.into();
+        let mut filter = DedupFilter::new(config);
+        let expected = match filter.config.expected_per_day {
+            Some(expected) => expected,
+            None => return Ok(filter),
+        };
+
+        if path.exists() {
+            let contents = fs::read(&path)
+                .map_err(|source| DedupError::Io { path: path.clone(), source })?;
+            let mut saved: RotatingBloom = bincode::deserialize(&contents)
+                .map_err(|_| DedupError::Corrupted(path.clone()))?;
+            let sized = RotatingBloom::new(expected, filter.config.false_positive_rate, now());
+            if saved.bits == sized.bits && saved.hashes == sized.hashes {
+                saved.rotate(now(), filter.config.days);
+                info!("[spi-wrapper/ingest/dedup] Loaded {} days of signatures from {}.",
+                    saved.generations.len(), path.display());
+                filter.state.lock().unwrap().bloom = Some(saved);
+            } else {
+                warn!("[spi-wrapper/ingest/dedup] {} was sized for other settings, starting \
+                    over.", path.display());
+            }
+        }
+        filter.path = Some(path);
+
+        Ok(filter)
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.config.strict
+    }
+
+    /// Whether `signature` was sunk before.
+    pub fn check(&self, signature: &str) -> Seen {
+        let state = self.state.lock().unwrap();
+        if state.recent.contains(signature) {
+            return Seen::Yes;
+        }
+        match &state.bloom {
+            Some(bloom) if bloom.contains(signature) && self.config.strict => Seen::Maybe,
+            Some(bloom) if bloom.contains(signature) => Seen::Yes,
+            _ => Seen::No,
+        }
+    }
+
+    /// Whether the transaction wasn't surely sunk before, counting the transactions skipped.
+    pub(crate) fn admits(&self, transaction: &RawTransaction) -> bool {
+        let admits = self.check(&transaction.transaction_hash) != Seen::Yes;
+        #[cfg(feature = "metrics")]
+        {
+            if !admits {
+                crate::metrics::metrics().skipped_by_filter.with_label_values(&["dedup"]).inc();
+            }
+        }
+
+        admits
+    }
+
+    /// Remembers `signature` as sunk.
+    pub fn insert(&self, signature: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.recent.insert(signature);
+        if let Some(bloom) = &mut state.bloom {
+            bloom.rotate(now(), self.config.days);
+            bloom.insert(signature);
+        }
+    }
+
+    /// Remembers every transaction as sunk, once written.
+    pub fn record(&self, transactions: &[TransactionRecord]) {
+        for transaction in transactions {
+            self.insert(&transaction.transaction_hash);
+        }
+    }
+
+    /// In strict mode, drops the transactions the bloom filter knows and the sink has. Returns
+    /// how many were dropped.
+    pub async fn confirm<S: Sink + ?Sized>(
+        &self,
+        transactions: &mut Vec<TransactionRecord>,
+        sink: &mut S,
+    ) -> Result<usize, SinkError> {
+        if !self.config.strict {
+            return Ok(0);
+        }
+
+        let mut kept = Vec::with_capacity(transactions.len());
+        let mut dropped = 0;
+        for transaction in transactions.drain(..) {
+            if self.check(&transaction.transaction_hash) == Seen::Maybe
+                && sink.has_transaction(&transaction.transaction_hash).await? == Some(true)
+            {
+                dropped += 1;
+                continue;
+            }
+            kept.push(transaction);
+        }
+        *transactions = kept;
+
+        Ok(dropped)
+    }
+
+    /// Saves the bloom filter, if the filter has both a bloom filter and a path.
+    pub fn persist(&self) -> Result<(), DedupError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let contents = match &self.state.lock().unwrap().bloom {
+            Some(bloom) => bincode::serialize(bloom)
+                .map_err(|_| DedupError::Corrupted(path.clone()))?,
+            None => return Ok(()),
+        };
+
+        write_atomically(path, &contents)
+            .map_err(|source| DedupError::Io { path: path.clone(), source })
+    }
+}
+
+impl fmt::Debug for DedupFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DedupFilter")
+            .field("config", &self.config)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let temporary = path.with_extension("dedup.tmp");
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// The latest signatures, the least recently inserted dropped first.
+struct Recent {
+    capacity: usize,
+    ticks: HashMap<String, u64>,
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl Recent {
+    fn new(capacity: usize) -> Self {
+        Recent { capacity, ticks: HashMap::new(), order: BTreeMap::new(), tick: 0 }
+    }
+
+    fn contains(&self, signature: &str) -> bool {
+        self.ticks.contains_key(signature)
+    }
+
+    fn insert(&mut self, signature: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.tick += 1;
+        if let Some(tick) = self.ticks.insert(signature.to_string(), self.tick) {
+            self.order.remove(&tick);
+        }
+        self.order.insert(self.tick, signature.to_string());
+        while self.ticks.len() > self.capacity {
+            let oldest = match self.order.keys().next() {
+                Some(tick) => *tick,
+                None => break,
+            };
+            if let Some(signature) = self.order.remove(&oldest) {
+                self.ticks.remove(&signature);
+            }
+        }
+    }
+}
+
+/// One bloom filter per day, every one of them sized the same.
+#[derive(Serialize, Deserialize)]
+struct RotatingBloom {
+    // The number of bits of every filter.
+    bits: u64,
+    hashes: u32,
+    // The newest last.
+    generations: VecDeque<Generation>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Generation {
+    // When the day started, in seconds since the Unix epoch.
+    started_at: u64,
+    words: Vec<u64>,
+}
+
+impl RotatingBloom {
+    fn new(expected: u64, false_positive_rate: f64, now: u64) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let expected = expected.max(1) as f64;
+        let bits = (-expected * false_positive_rate.ln() / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let hashes = ((bits as f64 / expected) * ln2).round().max(1.0) as u32;
+
+        let mut bloom = RotatingBloom { bits, hashes, generations: VecDeque::new() };
+        bloom.push(now);
+
+        bloom
+    }
+
+    fn push(&mut self, started_at: u64) {
+        let words = vec![0; ((self.bits + 63) / 64) as usize];
+        self.generations.push_back(Generation { started_at, words });
+    }
+
+    /// Starts a new day when the current one is over, keeping `days` of them.
+    fn rotate(&mut self, now: u64, days: usize) {
+        let current = self.generations.back().map_or(0, |generation| generation.started_at);
+        if now.saturating_sub(current) >= SECONDS_PER_DAY {
+            self.push(now);
+        }
+        while self.generations.len() > days.max(1) {
+            self.generations.pop_front();
+        }
+    }
+
+    /// The bits of `signature`, by double hashing.
+    fn positions(&self, signature: &str) -> impl Iterator<Item = usize> {
+        let digest = Sha3_256::digest(signature.as_bytes());
+        let mut first = [0; 8];
+        let mut second = [0; 8];
+        first.copy_from_slice(&digest[..8]);
+        second.copy_from_slice(&digest[8..16]);
+        let (first, second) = (u64::from_le_bytes(first), u64::from_le_bytes(second));
+        let bits = self.bits;
+
+        (0..self.hashes as u64)
+            .map(move |index| (first.wrapping_add(index.wrapping_mul(second)) % bits) as usize)
+    }
+
+    fn insert(&mut self, signature: &str) {
+        let positions: Vec<usize> = self.positions(signature).collect();
+        if let Some(generation) = self.generations.back_mut() {
+            for position in positions {
+                generation.words[position / 64] |= 1 << (position % 64);
+            }
+        }
+    }
+
+    fn contains(&self, signature: &str) -> bool {
+        let positions: Vec<usize> = self.positions(signature).collect();
+        self.generations.iter().any(|generation| {
+            positions.iter()
+                .all(|position| generation.words[position / 64] & (1 << (position % 64)) != 0)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::call_tree::CallTree;
+    use crate::network::Network;
+
+    fn signature(index: usize) -> String {
+        format!("signature-{}", index)
+    }
+
+    // Has the transactions it was given.
+    struct KnownSink(HashSet<String>);
+
+    #[async_trait]
+    impl Sink for KnownSink {
+        async fn write_transactions(
+            &mut self,
+            _transactions: &[TransactionRecord],
+        ) -> Result<(), SinkError> {
+            Ok(())
+        }
+
+        async fn has_transaction(&mut self, signature: &str) -> Result<Option<bool>, SinkError> {
+            Ok(Some(self.0.contains(signature)))
+        }
+    }
+
+    #[test]
+    fn recent_signatures_are_remembered_exactly() {
+        let filter = DedupFilter::new(DedupConfig { recent: 2, ..DedupConfig::default() });
+        filter.insert(&signature(0));
+        filter.insert(&signature(1));
+        filter.insert(&signature(0));
+        filter.insert(&signature(2));
+
+        assert_eq!(filter.check(&signature(0)), Seen::Yes);
+        assert_eq!(filter.check(&signature(1)), Seen::No);
+        assert_eq!(filter.check(&signature(2)), Seen::Yes);
+    }
+
+    #[test]
+    fn the_bloom_filter_is_strict_only_when_asked_to() {
+        let config = DedupConfig {
+            recent: 0,
+            expected_per_day: Some(1_000),
+            ..DedupConfig::default()
+        };
+        let lenient = DedupFilter::new(config.clone());
+        let strict = DedupFilter::new(DedupConfig { strict: true, ..config });
+        for filter in &[&lenient, &strict] {
+            (0..1_000).for_each(|index| filter.insert(&signature(index)));
+        }
+
+        assert!((0..1_000).all(|index| lenient.check(&signature(index)) == Seen::Yes));
+        assert!((0..1_000).all(|index| strict.check(&signature(index)) == Seen::Maybe));
+        let false_positives = (1_000..11_000)
+            .filter(|index| lenient.check(&signature(*index)) != Seen::No)
+            .count();
+        assert!(false_positives < 50, "{} false positives", false_positives);
+    }
+
+    #[tokio::test]
+    async fn strict_positives_are_checked_against_the_sink() {
+        let filter = DedupFilter::new(DedupConfig {
+            recent: 0,
+            expected_per_day: Some(1_000),
+            strict: true,
+            ..DedupConfig::default()
+        });
+        filter.insert("sunk");
+        filter.insert("rolled back");
+        let mut sink = KnownSink(Some("sunk".to_string()).into_iter().collect());
+        let mut transactions: Vec<_> = vec!["sunk", "rolled back", "new"].into_iter()
+            .map(|signature| TransactionRecord {
+                transaction_hash: signature.to_string(),
+                slot: 42,
+                fee: 5000,
+                base_fee_lamports: None,
+                priority_fee_lamports: None,
+                compute_unit_price_micro_lamports: None,
+                succeeded: true,
+                timestamp: 0,
+                timestamp_estimated: false,
+                finalized: false,
+                network: Network::Mainnet,
+                instruction_sets: vec![],
+                decode_failures: vec![],
+                call_tree: CallTree::default(),
+                signer_activity: Vec::new(),
+                account_lifecycle: Vec::new(),
+                confirmed: true,
+                simulation: None,
+            })
+            .collect();
+
+        assert_eq!(filter.confirm(&mut transactions, &mut sink).await.unwrap(), 1);
+        let kept: Vec<_> = transactions.iter()
+            .map(|transaction| transaction.transaction_hash.as_str())
+            .collect();
+        assert_eq!(kept, vec!["rolled back", "new"]);
+    }
+
+    #[test]
+    fn days_rotate_out() {
+        let mut bloom = RotatingBloom::new(100, 0.01, 0);
+        bloom.insert("old");
+        bloom.rotate(SECONDS_PER_DAY, 2);
+        bloom.insert("new");
+        assert!(bloom.contains("old") && bloom.contains("new"));
+
+        bloom.rotate(2 * SECONDS_PER_DAY, 2);
+        assert!(!bloom.contains("old"));
+        assert!(bloom.contains("new"));
+    }
+
+    #[test]
+    fn the_bloom_filter_survives_restarts() {
+        let path = std::env::temp_dir()
+            .join(format!("spi-wrapper-dedup-{}", std::process::id()))
+            .join("indexer.dedup");
+        let config = DedupConfig {
+            recent: 10,
+            expected_per_day: Some(1_000),
+            ..DedupConfig::default()
+        };
+
+        let filter = DedupFilter::load(config.clone(), &path).unwrap();
+        filter.insert("sunk");
+        filter.persist().unwrap();
+
+        let restarted = DedupFilter::load(config.clone(), &path).unwrap();
+        assert_eq!(restarted.check("sunk"), Seen::Yes);
+        assert_eq!(restarted.check("never sunk"), Seen::No);
+
+        let resized = DedupFilter::load(DedupConfig {
+            expected_per_day: Some(1_000_000),
+            ..config
+        }, &path).unwrap();
+        assert_eq!(resized.check("sunk"), Seen::No);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,378 @@
+//! Skipping the transactions that don't touch any account or mint of interest, and the
+//! instructions of programs of no interest, before they're decoded.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::ingest::dedup::DedupFilter;
+use crate::interner::Symbol;
+use crate::transaction::RawTransaction;
+
+/// How often `AccountFilter::watch` checks the account file for changes, by default.
+pub const DEFAULT_RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Whether the transactions only casting votes are skipped, by default: they're the bulk of every
+/// block, and including them is opt-in.
+pub const DEFAULT_SKIP_VOTES: bool = true;
+
+/// Errors that may be returned while loading an account file.
+#[derive(Debug, Error)]
+pub enum FilterError {
+    #[error("Unable to read {path}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("{path}:{line}: `{value}` is not a valid account")]
+    InvalidAccount { path: PathBuf, line: usize, value: String },
+}
+
+/// What's skipped while decoding, before reaching the registry. Votes are, by default.
+#[derive(Clone, Debug)]
+pub struct DecodeFilters {
+    // Whether the transactions only casting votes are skipped (see `RawTransaction::is_vote`),
+    // checked first. They are by default (see `DEFAULT_SKIP_VOTES`): including them is opt-in.
+    pub skip_votes: bool,
+    // Transactions it knows were sunk before are skipped.
+    pub dedup: Option<DedupFilter>,
+    // Transactions not matching it are skipped.
+    pub accounts: Option<AccountFilter>,
+    // Transactions involving none of its mints are skipped, checked after the accounts.
+    pub mints: Option<MintFilter>,
+    // Instructions of the programs it doesn't let through are skipped.
+    pub programs: Option<ProgramFilter>,
+}
+
+impl Default for DecodeFilters {
+    fn default() -> Self {
+        DecodeFilters { skip_votes: DEFAULT_SKIP_VOTES, ..DecodeFilters::none() }
+    }
+}
+
+impl DecodeFilters {
+    /// Skips nothing, votes included.
+    pub fn none() -> Self {
+        DecodeFilters {
+            skip_votes: false,
+            dedup: None,
+            accounts: None,
+            mints: None,
+            programs: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.skip_votes && self.dedup.is_none() && self.accounts.is_none()
+            && self.mints.is_none() && self.programs.is_none()
+    }
+}
+
+/// Which programs get their instructions decoded, applied to every instruction on its own (inner
+/// instructions included): denied programs never are, and when an allowlist is set only the
+/// programs on it are.
+#[derive(Clone, Debug, Default)]
+pub struct ProgramFilter {
+    // Every program is allowed when empty.
+    allow: HashSet<Symbol>,
+    deny: HashSet<Symbol>,
+}
+
+impl ProgramFilter {
+    /// Only lets the instructions of `programs` through.
+    pub fn allow<I: IntoIterator<Item = Symbol>>(mut self, programs: I) -> Self {
+        self.allow.extend(programs);
+        self
+    }
+
+    /// Skips the instructions of `programs`, even when they're allowed.
+    pub fn deny<I: IntoIterator<Item = Symbol>>(mut self, programs: I) -> Self {
+        self.deny.extend(programs);
+        self
+    }
+
+    pub fn matches(&self, program: Symbol) -> bool {
+        !self.deny.contains(&program) && (self.allow.is_empty() || self.allow.contains(&program))
+    }
+
+    /// Same as `matches`, counting the instructions skipped.
+    pub(crate) fn admits(&self, program: Symbol) -> bool {
+        let matches = self.matches(program);
+        #[cfg(feature = "metrics")]
+        {
+            if !matches {
+                crate::metrics::metrics().instructions_skipped_by_filter
+                    .with_label_values(&["programs"])
+                    .inc();
+            }
+        }
+
+        matches
+    }
+}
+
+/// An allowlist of accounts: transactions referencing none of them are skipped without being
+/// decoded.
+///
+/// The accounts are given up front, loaded from a file (one base58 account per line, `#` starting
+/// a comment), or both. The file can be reloaded while the filter is in use, clones of a filter
+/// sharing the reloaded accounts.
+#[derive(Clone, Debug, Default)]
+pub struct AccountFilter {
+    accounts: Arc<HashSet<Pubkey>>,
+    file: Option<PathBuf>,
+    // The accounts last loaded from the file.
+    loaded: Arc<RwLock<Arc<HashSet<Pubkey>>>>,
+}
+
+impl AccountFilter {
+    pub fn new<I: IntoIterator<Item = Pubkey>>(accounts: I) -> Self {
+        AccountFilter {
+            accounts: Arc::new(accounts.into_iter().collect()),
+            ..AccountFilter::default()
+        }
+    }
+
+    /// Also lets through the transactions referencing an account listed in `path`, which is read
+    /// right away.
+    pub fn with_file<P: Into<PathBuf>>(mut self, path: P) -> Result<Self, FilterError> {
+        self.file = Some(path.into());
+        self.reload()?;
+
+        Ok(self)
+    }
+
+    /// Reads the account file again, returning how many accounts it lists. The previous accounts
+    /// are kept when it can't be read.
+    pub fn reload(&self) -> Result<usize, FilterError> {
+        let path = match &self.file {
+            Some(path) => path,
+            None => return Ok(0),
+        };
+
+        let accounts = read_accounts(path)?;
+        let count = accounts.len();
+        *self.loaded.write().unwrap() = Arc::new(accounts);
+
+        Ok(count)
+    }
+
+    /// Reloads the account file whenever it's modified, checking every `interval`, until the
+    /// returned task is aborted.
+    pub fn watch(&self, interval: Duration) -> JoinHandle<()> {
+        let filter = self.clone();
+        tokio::spawn(async move {
+            let path = match &filter.file {
+                Some(path) => path.clone(),
+                None => return,
+            };
+            let mut modified = modified_at(&path);
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                let now_modified = modified_at(&path);
+                if now_modified == modified {
+                    continue;
+                }
+                modified = now_modified;
+
+                match filter.reload() {
+                    Ok(count) => info!("[spi-wrapper/ingest/filter] Reloaded {} accounts from {}.",
+                        count, path.display()),
+                    Err(err) => warn!("[spi-wrapper/ingest/filter] Keeping the previous accounts: \
+                        {}", err),
+                }
+            }
+        })
+    }
+
+    /// Whether the transaction references one of the accounts, the addresses loaded from lookup
+    /// tables by v0 transactions included.
+    pub fn matches(&self, transaction: &RawTransaction) -> bool {
+        let loaded = self.loaded.read().unwrap().clone();
+        transaction.account_keys.iter()
+            .any(|key| self.accounts.contains(key) || loaded.contains(key))
+    }
+
+    /// Same as `matches`, counting the transactions skipped.
+    pub(crate) fn admits(&self, transaction: &RawTransaction) -> bool {
+        let matches = self.matches(transaction);
+        #[cfg(feature = "metrics")]
+        {
+            if !matches {
+                crate::metrics::metrics().skipped_by_filter.with_label_values(&["accounts"]).inc();
+            }
+        }
+
+        matches
+    }
+}
+
+/// The token mints transactions have to involve, by moving a balance of one of them (as reported
+/// by their token balances) or by referencing one of the mint accounts.
+///
+/// In strict mode only the instructions involving one of the mints themselves are decoded, those
+/// invoked with one of the mint accounts or a token account of one of the mints; otherwise
+/// every instruction of the transactions let through is.
+#[derive(Clone, Debug, Default)]
+pub struct MintFilter {
+    mints: HashSet<Pubkey>,
+    strict: bool,
+}
+
+impl MintFilter {
+    pub fn new<I: IntoIterator<Item = Pubkey>>(mints: I, strict: bool) -> Self {
+        MintFilter { mints: mints.into_iter().collect(), strict }
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Whether the transaction involves one of the mints.
+    pub fn matches(&self, transaction: &RawTransaction) -> bool {
+        transaction.token_mints.iter().any(|token| self.mints.contains(&token.mint))
+            || transaction.account_keys.iter().any(|key| self.mints.contains(key))
+    }
+
+    /// Whether an instruction of `transaction` invoked with the accounts at `account_indexes`
+    /// involves one of the mints.
+    pub fn touches(&self, transaction: &RawTransaction, account_indexes: &[u8]) -> bool {
+        account_indexes.iter().any(|index| {
+            let is_mint = transaction.account_keys.get(*index as usize)
+                .map_or(false, |key| self.mints.contains(key));
+            is_mint || transaction.token_mints.iter()
+                .any(|token| token.account_index == *index && self.mints.contains(&token.mint))
+        })
+    }
+
+    /// Same as `matches`, counting the transactions skipped.
+    pub(crate) fn admits(&self, transaction: &RawTransaction) -> bool {
+        let matches = self.matches(transaction);
+        #[cfg(feature = "metrics")]
+        {
+            if !matches {
+                crate::metrics::metrics().skipped_by_filter.with_label_values(&["mints"]).inc();
+            }
+        }
+
+        matches
+    }
+
+    /// Same as `touches` when strict, counting the instructions skipped. Lets everything through
+    /// otherwise.
+    pub(crate) fn admits_instruction(
+        &self,
+        transaction: &RawTransaction,
+        account_indexes: &[u8],
+    ) -> bool {
+        if !self.strict {
+            return true;
+        }
+
+        let touches = self.touches(transaction, account_indexes);
+        #[cfg(feature = "metrics")]
+        {
+            if !touches {
+                crate::metrics::metrics().instructions_skipped_by_filter
+                    .with_label_values(&["mints"])
+                    .inc();
+            }
+        }
+
+        touches
+    }
+}
+
+fn read_accounts(path: &Path) -> Result<HashSet<Pubkey>, FilterError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|source| FilterError::Io { path: path.to_path_buf(), source })?;
+
+    let mut accounts = HashSet::new();
+    for (index, line) in contents.lines().enumerate() {
+        let value = line.split('#').next().unwrap_or_default().trim();
+        if value.is_empty() {
+            continue;
+        }
+        let account = value.parse().map_err(|_| FilterError::InvalidAccount {
+            path: path.to_path_buf(),
+            line: index + 1,
+            value: value.to_string(),
+        })?;
+        accounts.insert(account);
+    }
+
+    Ok(accounts)
+}
+
+pub(crate) fn modified_at(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::instruction::CompiledInstruction;
+
+    use super::*;
+
+    fn transaction(account_keys: Vec<Pubkey>) -> RawTransaction {
+        RawTransaction {
+            slot: 1,
+            block_time: None,
+            transaction_hash: "signature".to_string(),
+            account_keys,
+            num_required_signatures: 1,
+            instructions: vec![CompiledInstruction {
+                program_id_index: 1,
+                accounts: vec![0, 2],
+                data: vec![],
+            }],
+            inner_instructions: vec![],
+            token_mints: vec![],
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        }
+    }
+
+    #[test]
+    fn v0_loaded_addresses_are_considered() {
+        let (payer, program, loaded) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let filter = AccountFilter::new(vec![loaded]);
+
+        // The third key only exists through an address lookup table.
+        assert!(filter.matches(&transaction(vec![payer, program, loaded])));
+        assert!(!filter.matches(&transaction(vec![payer, program])));
+    }
+
+    #[test]
+    fn reloads_the_account_file() {
+        let path = std::env::temp_dir()
+            .join(format!("spi-wrapper-accounts-{}.txt", std::process::id()));
+        let (wallet, market) = (Pubkey::new_unique(), Pubkey::new_unique());
+        fs::write(&path, format!("# wallets\n{}\n", wallet)).unwrap();
+
+        let filter = AccountFilter::default().with_file(&path).unwrap();
+        let shared = filter.clone();
+        assert!(shared.matches(&transaction(vec![wallet])));
+        assert!(!shared.matches(&transaction(vec![market])));
+
+        fs::write(&path, format!("{}\n{}  # market\n", wallet, market)).unwrap();
+        assert_eq!(filter.reload().unwrap(), 2);
+        assert!(shared.matches(&transaction(vec![market])));
+
+        fs::write(&path, "not-an-account\n").unwrap();
+        assert!(matches!(filter.reload(), Err(FilterError::InvalidAccount { line: 1, .. })));
+        assert!(shared.matches(&transaction(vec![market])));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
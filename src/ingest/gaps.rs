@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tracing::info;
+
+use crate::checkpoint::{Checkpoint, Checkpointing};
+use crate::ingest::rpc::BlockPoller;
+use crate::ingest::IngestError;
+use crate::sinks::Sink;
+
+/// A set of slots, stored as disjoint inclusive ranges.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SlotRanges {
+    // Range start -> range end (inclusive). Ranges never overlap nor touch.
+    ranges: BTreeMap<u64, u64>,
+}
+
+impl SlotRanges {
+    pub fn from_ranges(ranges: &[(u64, u64)]) -> Self {
+        let mut slot_ranges = SlotRanges::default();
+        for (start, end) in ranges {
+            slot_ranges.insert(*start, *end);
+        }
+
+        slot_ranges
+    }
+
+    /// Adds every slot between `start` and `end` (both inclusive).
+    pub fn insert(&mut self, start: u64, end: u64) {
+        if start > end {
+            return;
+        }
+
+        let mut start = start;
+        let mut end = end;
+        // Swallow the range ending right before (or overlapping) the new one...
+        if let Some((&previous_start, &previous_end)) = self.ranges.range(..=start).next_back() {
+            if previous_end.saturating_add(1) >= start {
+                start = previous_start;
+                end = end.max(previous_end);
+            }
+        }
+        // ...and every range starting inside or right after it.
+        let following: Vec<(u64, u64)> = self.ranges
+            .range(start..=end.saturating_add(1))
+            .map(|(start, end)| (*start, *end))
+            .collect();
+        for (following_start, following_end) in following {
+            self.ranges.remove(&following_start);
+            end = end.max(following_end);
+        }
+
+        self.ranges.insert(start, end);
+    }
+
+    pub fn contains(&self, slot: u64) -> bool {
+        self.ranges.range(..=slot).next_back().map_or(false, |(_, end)| slot <= *end)
+    }
+
+    /// The lowest slot in the set.
+    pub fn first(&self) -> Option<u64> {
+        self.ranges.keys().next().cloned()
+    }
+
+    /// The highest slot in the set.
+    pub fn last(&self) -> Option<u64> {
+        self.ranges.values().next_back().cloned()
+    }
+
+    /// The ranges between `from` and `to` (both inclusive) that aren't in the set.
+    pub fn missing_ranges(&self, from: u64, to: u64) -> Vec<(u64, u64)> {
+        let mut missing = Vec::new();
+        let mut next = from;
+
+        for (start, end) in &self.ranges {
+            if next > to {
+                break;
+            }
+            if *end < next {
+                continue;
+            }
+            if *start > next {
+                missing.push((next, (*start - 1).min(to)));
+            }
+            next = end.saturating_add(1);
+        }
+        if next <= to {
+            missing.push((next, to));
+        }
+
+        missing
+    }
+
+    pub fn to_vec(&self) -> Vec<(u64, u64)> {
+        self.ranges.iter().map(|(start, end)| (*start, *end)).collect()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GapMetrics {
+    // Slots not processed yet between the first and last processed slot.
+    pub missing_slots: u64,
+    pub missing_ranges: usize,
+    // Slots refetched by the repair task so far.
+    pub repaired_slots: u64,
+}
+
+/// Records which slots have been processed, so holes left by outages or failed fetches can be
+/// found and refetched.
+///
+/// A slot counts as processed once the range it belongs to went through `getBlocks` and every
+/// block listed was sunk, so slots that never had a block (skipped slots) are processed too
+/// and never show up as gaps.
+#[derive(Debug)]
+pub struct GapTracker {
+    processed: Mutex<SlotRanges>,
+    checkpoint: Option<Checkpointing>,
+    repaired_slots: AtomicU64,
+}
+
+impl GapTracker {
+    /// A tracker persisting its state through `checkpoint`, starting from the state saved there.
+    /// The checkpoint needs a pipeline name of its own (e.g. `<pipeline>-gaps`), as the poller's
+    /// checkpoint would otherwise overwrite it.
+    pub async fn load(checkpoint: Option<Checkpointing>) -> Result<Self, IngestError> {
+        let processed = match &checkpoint {
+            Some(checkpoint) => checkpoint.load().await?
+                .map(|checkpoint| SlotRanges::from_ranges(&checkpoint.ranges))
+                .unwrap_or_default(),
+            None => SlotRanges::default(),
+        };
+
+        Ok(GapTracker {
+            processed: Mutex::new(processed),
+            checkpoint,
+            repaired_slots: AtomicU64::new(0),
+        })
+    }
+
+    pub fn mark_processed(&self, start_slot: u64, end_slot: u64) {
+        self.processed.lock().unwrap().insert(start_slot, end_slot);
+    }
+
+    pub fn missing_ranges(&self, from: u64, to: u64) -> Vec<(u64, u64)> {
+        self.processed.lock().unwrap().missing_ranges(from, to)
+    }
+
+    /// The first and last processed slots.
+    pub fn bounds(&self) -> Option<(u64, u64)> {
+        let processed = self.processed.lock().unwrap();
+        processed.first().zip(processed.last())
+    }
+
+    /// Gap counts between the first and last processed slots.
+    pub fn metrics(&self) -> GapMetrics {
+        let missing = match self.bounds() {
+            Some((first, last)) => self.missing_ranges(first, last),
+            None => Vec::new(),
+        };
+
+        GapMetrics {
+            missing_slots: missing.iter().map(|(start, end)| end - start + 1).sum(),
+            missing_ranges: missing.len(),
+            repaired_slots: self.repaired_slots.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Saves the processed ranges through the checkpoint store, if there is one.
+    pub async fn persist(&self) -> Result<(), IngestError> {
+        if let Some(checkpoint) = &self.checkpoint {
+            let processed = self.processed.lock().unwrap().clone();
+            let mut saved = Checkpoint::new(processed.last(), None);
+            saved.ranges = processed.to_vec();
+            checkpoint.save(&saved).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GapRepairConfig {
+    // The first slot gaps are looked for from. Defaults to the first slot processed.
+    pub from_slot: Option<u64>,
+    // The most slots refetched in one go, so live ingestion never waits long on a repair.
+    pub max_slots_per_round: u64,
+    // How long to wait between repair rounds, and while live ingestion is behind.
+    pub interval: Duration,
+}
+
+impl Default for GapRepairConfig {
+    fn default() -> Self {
+        GapRepairConfig {
+            from_slot: None,
+            max_slots_per_round: 50,
+            interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Refetches the slots the tracker is missing, forever. The poller must have been given the same
+/// tracker through `BlockPoller::with_gap_tracker`.
+///
+/// Repairs run at a lower priority than live ingestion: a round only starts while the poller is
+/// caught up with the tip, and covers at most `max_slots_per_round` slots.
+pub async fn repair_gaps<S: Sink + ?Sized>(
+    poller: &BlockPoller,
+    tracker: &GapTracker,
+    sink: &mut S,
+    config: &GapRepairConfig,
+) -> Result<(), IngestError> {
+    loop {
+        tokio::time::sleep(config.interval).await;
+        if !poller.is_caught_up() {
+            continue;
+        }
+
+        let (first_processed, last_processed) = match tracker.bounds() {
+            Some(bounds) => bounds,
+            None => continue,
+        };
+        let from_slot = config.from_slot.unwrap_or(first_processed);
+        let missing = tracker.missing_ranges(from_slot, last_processed);
+        let (start_slot, end_slot) = match missing.first() {
+            Some((start, end)) => (*start, (*end).min(start + config.max_slots_per_round - 1)),
+            None => continue,
+        };
+
+        // The poller marks the range processed once it's been sunk.
+        let written = poller.repair_range(start_slot, end_slot, sink).await?;
+        tracker.repaired_slots.fetch_add(end_slot - start_slot + 1, Ordering::Relaxed);
+        tracker.persist().await?;
+
+        let metrics = tracker.metrics();
+        info!("[spi-wrapper/ingest/gaps] Repaired slots {} to {} ({} blocks), {} slots in {} gaps \
+        left.", start_slot, end_slot, written, metrics.missing_slots, metrics.missing_ranges);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_merge_when_touching_or_overlapping() {
+        let mut ranges = SlotRanges::default();
+        ranges.insert(10, 20);
+        ranges.insert(30, 40);
+        ranges.insert(21, 25);
+        assert_eq!(ranges.to_vec(), vec![(10, 25), (30, 40)]);
+
+        ranges.insert(24, 35);
+        assert_eq!(ranges.to_vec(), vec![(10, 40)]);
+
+        ranges.insert(5, 50);
+        assert_eq!(ranges.to_vec(), vec![(5, 50)]);
+        assert!(ranges.contains(5) && ranges.contains(50) && !ranges.contains(51));
+    }
+
+    #[test]
+    fn missing_ranges_are_the_holes() {
+        let ranges = SlotRanges::from_ranges(&[(10, 20), (25, 30), (40, 45)]);
+
+        assert_eq!(ranges.missing_ranges(0, 50), vec![(0, 9), (21, 24), (31, 39), (46, 50)]);
+        assert_eq!(ranges.missing_ranges(15, 42), vec![(21, 24), (31, 39)]);
+        assert_eq!(ranges.missing_ranges(26, 29), vec![]);
+    }
+}
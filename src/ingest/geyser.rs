@@ -0,0 +1,446 @@
+//! A Geyser plugin forwarding validator notifications into the processing pipeline.
+//!
+//! The validator loads the plugin through `_create_plugin` with a JSON config such as:
+//!
+//! ```json
+//! {
+//!     "libpath": "/path/to/libspi_wrapper.so",
+//!     "sink": { "kind": "sqlite", "path": "/var/lib/spi-wrapper/index.sqlite" },
+//!     "program_filters": ["So1endDq2YkqhipRh3WViPa8hdiSpxWy6z3Z6tMCpAo"],
+//!     "channel_capacity": 100000,
+//!     "workers": 4,
+//!     "include_votes": false
+//! }
+//! ```
+//!
+//! Callbacks only convert the notification and push it onto a bounded lock-free channel, so the
+//! validator is never blocked on decoding or on the sink; the decoding happens on worker threads.
+
+use std::collections::HashSet;
+use std::fs;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
+use serde::Deserialize;
+use solana_geyser_plugin_interface::geyser_plugin_interface::{
+    GeyserPlugin, GeyserPluginError, ReplicaAccountInfoVersions, ReplicaBlockInfoVersions,
+    ReplicaTransactionInfoVersions, Result as PluginResult, SlotStatus,
+};
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::InnerInstructions;
+use tokio::runtime::{Handle, Runtime};
+use tokio::sync::Mutex;
+use tracing::{error, warn};
+
+use crate::account::{process_account_update, AccountUpdate};
+use crate::registry::ProcessorRegistry;
+use crate::sinks::sqlite::SqliteSink;
+use crate::sinks::{self, Sink};
+use crate::transaction::{decode_transaction, token_mints, RawTransaction};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GeyserSinkConfig {
+    Sqlite { path: String },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct GeyserConfig {
+    pub sink: GeyserSinkConfig,
+    // Only transactions referencing, and accounts owned by, these programs are forwarded.
+    // An empty list forwards everything.
+    #[serde(default)]
+    pub program_filters: Vec<String>,
+    #[serde(default = "default_channel_capacity")]
+    pub channel_capacity: usize,
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+    #[serde(default)]
+    pub include_votes: bool,
+}
+
+fn default_channel_capacity() -> usize {
+    100_000
+}
+
+fn default_workers() -> usize {
+    4
+}
+
+pub enum Notification {
+    Transaction(RawTransaction),
+    Account(AccountUpdate),
+}
+
+/// The worker threads draining the notification channel into a sink.
+pub struct Workers {
+    sender: Sender<Notification>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Workers {
+    /// Spawns `count` workers decoding notifications on the given runtime.
+    pub fn spawn(
+        capacity: usize,
+        count: usize,
+        runtime: Handle,
+        registry: Arc<ProcessorRegistry>,
+        sink: Arc<Mutex<Box<dyn Sink>>>,
+    ) -> Workers {
+        let (sender, receiver) = bounded(capacity);
+        let handles = (0..count.max(1))
+            .map(|_| {
+                let receiver = receiver.clone();
+                let runtime = runtime.clone();
+                let registry = registry.clone();
+                let sink = sink.clone();
+                std::thread::spawn(move || work(receiver, runtime, registry, sink))
+            })
+            .collect();
+
+        Workers { sender, handles }
+    }
+
+    /// Queues a notification without ever blocking. Returns the notification back if the
+    /// channel is full.
+    pub fn try_send(&self, notification: Notification) -> Result<(), Notification> {
+        self.sender.try_send(notification).map_err(|err| match err {
+            TrySendError::Full(notification) | TrySendError::Disconnected(notification) => {
+                notification
+            }
+        })
+    }
+
+    /// The number of notifications waiting to be decoded.
+    pub fn backlog(&self) -> usize {
+        self.sender.len()
+    }
+
+    /// Stops accepting notifications and waits for the queued ones to be decoded.
+    pub fn join(self) {
+        drop(self.sender);
+        for handle in self.handles {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn work(
+    receiver: Receiver<Notification>,
+    runtime: Handle,
+    registry: Arc<ProcessorRegistry>,
+    sink: Arc<Mutex<Box<dyn Sink>>>,
+) {
+    for notification in receiver.iter() {
+        let result = runtime.block_on(async {
+            match notification {
+                Notification::Transaction(transaction) => {
+                    let record = decode_transaction(&registry, &transaction);
+                    sinks::write_transactions(&mut **sink.lock().await, &[record]).await
+                }
+                Notification::Account(update) => {
+                    let record = process_account_update(&update);
+                    sinks::write_accounts(&mut **sink.lock().await, &[record]).await
+                }
+            }
+        });
+
+        if let Err(err) = result {
+            error!("[spi-wrapper/ingest/geyser] Unable to sink notification: {}", err);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct IndexerGeyserPlugin {
+    runtime: Option<Runtime>,
+    workers: Option<Workers>,
+    program_filters: HashSet<Pubkey>,
+    include_votes: bool,
+}
+
+impl std::fmt::Debug for IndexerGeyserPlugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IndexerGeyserPlugin")
+            .field("program_filters", &self.program_filters)
+            .field("include_votes", &self.include_votes)
+            .finish()
+    }
+}
+
+impl IndexerGeyserPlugin {
+    fn enqueue(&self, notification: Notification) {
+        if let Some(workers) = &self.workers {
+            if workers.try_send(notification).is_err() {
+                warn!("[spi-wrapper/ingest/geyser] Notification channel is full, dropping a \
+                notification.");
+            }
+        }
+    }
+
+    fn is_wanted(&self, key: &Pubkey) -> bool {
+        self.program_filters.is_empty() || self.program_filters.contains(key)
+    }
+}
+
+// The interface crate is built against a newer solana-sdk than the rest of this crate, so keys
+// are carried across through their bytes.
+fn to_pubkey(bytes: &[u8]) -> Pubkey {
+    Pubkey::new(bytes)
+}
+
+impl GeyserPlugin for IndexerGeyserPlugin {
+    fn name(&self) -> &'static str {
+        "spi-wrapper"
+    }
+
+    fn on_load(&mut self, config_file: &str) -> PluginResult<()> {
+        let contents = fs::read_to_string(config_file).map_err(|err| {
+            GeyserPluginError::ConfigFileReadError { msg: err.to_string() }
+        })?;
+        let config: GeyserConfig = serde_json::from_str(&contents).map_err(|err| {
+            GeyserPluginError::ConfigFileReadError { msg: err.to_string() }
+        })?;
+
+        let mut program_filters = HashSet::new();
+        for program in &config.program_filters {
+            let key = program.parse::<Pubkey>().map_err(|err| {
+                GeyserPluginError::ConfigFileReadError {
+                    msg: format!("Invalid program filter {}: {}", program, err),
+                }
+            })?;
+            program_filters.insert(key);
+        }
+
+        let sink: Box<dyn Sink> = match &config.sink {
+            GeyserSinkConfig::Sqlite { path } => Box::new(SqliteSink::open(path)
+                .map_err(|err| GeyserPluginError::Custom(Box::new(err)))?),
+        };
+
+        let runtime = Runtime::new().map_err(|err| GeyserPluginError::Custom(Box::new(err)))?;
+        self.workers = Some(Workers::spawn(
+            config.channel_capacity,
+            config.workers,
+            runtime.handle().clone(),
+            Arc::new(ProcessorRegistry::default()),
+            Arc::new(Mutex::new(sink)),
+        ));
+        self.runtime = Some(runtime);
+        self.program_filters = program_filters;
+        self.include_votes = config.include_votes;
+
+        Ok(())
+    }
+
+    fn on_unload(&mut self) {
+        if let Some(workers) = self.workers.take() {
+            workers.join();
+        }
+        self.runtime = None;
+    }
+
+    fn update_account(
+        &mut self,
+        account: ReplicaAccountInfoVersions,
+        slot: u64,
+        _is_startup: bool,
+    ) -> PluginResult<()> {
+        let ReplicaAccountInfoVersions::V0_0_1(info) = account;
+        let owner = to_pubkey(info.owner);
+        if !self.is_wanted(&owner) {
+            return Ok(());
+        }
+
+        self.enqueue(Notification::Account(AccountUpdate {
+            pubkey: to_pubkey(info.pubkey),
+            owner,
+            lamports: info.lamports,
+            executable: info.executable,
+            data: info.data.to_vec(),
+            slot,
+            write_version: info.write_version,
+        }));
+
+        Ok(())
+    }
+
+    fn notify_end_of_startup(&mut self) -> PluginResult<()> {
+        Ok(())
+    }
+
+    fn update_slot_status(
+        &mut self,
+        _slot: u64,
+        _parent: Option<u64>,
+        _status: SlotStatus,
+    ) -> PluginResult<()> {
+        Ok(())
+    }
+
+    fn notify_transaction(
+        &mut self,
+        transaction: ReplicaTransactionInfoVersions,
+        slot: u64,
+    ) -> PluginResult<()> {
+        let ReplicaTransactionInfoVersions::V0_0_1(info) = transaction;
+        if info.is_vote && !self.include_votes {
+            return Ok(());
+        }
+
+        let message = info.transaction.message();
+        let account_keys: Vec<Pubkey> = message.account_keys_iter()
+            .map(|key| to_pubkey(key.as_ref()))
+            .collect();
+        if !self.program_filters.is_empty()
+            && !account_keys.iter().any(|key| self.program_filters.contains(key)) {
+            return Ok(());
+        }
+
+        let meta = info.transaction_status_meta;
+        let balances = meta.pre_token_balances.iter().chain(&meta.post_token_balances).flatten();
+        let inner_instructions = meta.inner_instructions.as_ref()
+            .map(|iis| iis.iter()
+                .map(|ii| InnerInstructions {
+                    index: ii.index,
+                    instructions: ii.instructions.iter()
+                        .map(|ci| CompiledInstruction {
+                            program_id_index: ci.program_id_index,
+                            accounts: ci.accounts.clone(),
+                            data: ci.data.clone(),
+                        })
+                        .collect(),
+                })
+                .collect())
+            .unwrap_or_default();
+
+        self.enqueue(Notification::Transaction(RawTransaction {
+            slot,
+            // Block times are not known while the slot is being replayed.
+            block_time: None,
+            transaction_hash: info.signature.to_string(),
+            account_keys,
+            num_required_signatures: message.header().num_required_signatures,
+            instructions: message.instructions().iter()
+                .map(|ci| CompiledInstruction {
+                    program_id_index: ci.program_id_index,
+                    accounts: ci.accounts.clone(),
+                    data: ci.data.clone(),
+                })
+                .collect(),
+            inner_instructions,
+            token_mints: token_mints(
+                balances.map(|balance| (balance.account_index, balance.mint.as_str(),
+                    balance.ui_token_amount.decimals))),
+            fee: meta.fee,
+            succeeded: meta.status.is_ok(),
+            stack_heights: Vec::new(),
+            log_messages: meta.log_messages.clone().unwrap_or_default(),
+            pre_balances: meta.pre_balances.clone(),
+            post_balances: meta.post_balances.clone(),
+        }));
+
+        Ok(())
+    }
+
+    fn notify_block_metadata(&mut self, _blockinfo: ReplicaBlockInfoVersions) -> PluginResult<()> {
+        Ok(())
+    }
+
+    fn account_data_notifications_enabled(&self) -> bool {
+        true
+    }
+
+    fn transaction_notifications_enabled(&self) -> bool {
+        true
+    }
+}
+
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+/// # Safety
+///
+/// This function returns the plugin pointer as trait GeyserPlugin.
+pub unsafe extern "C" fn _create_plugin() -> *mut dyn GeyserPlugin {
+    let plugin: Box<dyn GeyserPlugin> = Box::new(IndexerGeyserPlugin::default());
+    Box::into_raw(plugin)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::sinks::SinkError;
+    use crate::transaction::TransactionRecord;
+
+    struct CountingSink(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Sink for CountingSink {
+        async fn write_transactions(
+            &mut self,
+            transactions: &[TransactionRecord],
+        ) -> Result<(), SinkError> {
+            self.0.fetch_add(transactions.len(), Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn synthetic_transaction(idx: usize) -> RawTransaction {
+        RawTransaction {
+            slot: idx as u64,
+            block_time: None,
+            transaction_hash: idx.to_string(),
+            account_keys: vec![Pubkey::new_unique()],
+            num_required_signatures: 1,
+            instructions: vec![],
+            inner_instructions: vec![],
+            token_mints: vec![],
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        }
+    }
+
+    #[test]
+    fn drains_50k_notifications_without_backing_up() {
+        let runtime = Runtime::new().unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+        let sink: Box<dyn Sink> = Box::new(CountingSink(count.clone()));
+        // Well below the notifications sent: the channel fills up unless they're decoded as they
+        // come.
+        let capacity = 1_024;
+        let workers = Workers::spawn(
+            capacity,
+            4,
+            runtime.handle().clone(),
+            Arc::new(ProcessorRegistry::default()),
+            Arc::new(Mutex::new(sink)),
+        );
+
+        let (mut dropped, mut max_backlog) = (0, 0);
+        for idx in 0..50_000 {
+            // Bursts of 100 notifications every millisecond.
+            if idx % 100 == 0 {
+                std::thread::sleep(Duration::from_millis(1));
+                max_backlog = max_backlog.max(workers.backlog());
+            }
+            if workers.try_send(Notification::Transaction(synthetic_transaction(idx))).is_err() {
+                dropped += 1;
+            }
+        }
+        workers.join();
+
+        assert_eq!(dropped, 0);
+        assert!(max_backlog < capacity / 2, "{} notifications were waiting", max_backlog);
+        assert_eq!(count.load(Ordering::SeqCst), 50_000);
+    }
+}
@@ -0,0 +1,269 @@
+//! Tagging block records with the epoch of their slot and the identity of the validator that was
+//! scheduled to lead it, for validator analytics.
+//!
+//! A [`LeaderResolver`] fetches the epoch schedule of the cluster once, and the leader schedule of
+//! every epoch it's asked about once (keeping the last few). Nodes only serve the leader schedule
+//! of the epochs around the current one, so the blocks of older epochs get their epoch but no
+//! leader: enrichment never fails a block, whatever goes wrong is left out of the record.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use solana_client::client_error::ClientErrorKind;
+use solana_client::rpc_request::RpcError;
+use solana_sdk::epoch_schedule::EpochSchedule;
+use solana_sdk::pubkey::Pubkey;
+use tracing::warn;
+
+use crate::block::BlockRecord;
+use crate::cache::LruCache;
+use crate::ingest::pool::RpcPool;
+use crate::ingest::IngestError;
+
+// How many epochs the leaders of are cached.
+const EPOCHS_CACHED: usize = 4;
+// Marks the slots of an epoch no leader is scheduled for.
+const NO_LEADER: u32 = u32::MAX;
+
+/// Where the epoch and leader schedules are looked up.
+#[async_trait]
+pub trait LeaderScheduleSource: Send + Sync {
+    /// The epoch schedule of the cluster.
+    async fn epoch_schedule(&self) -> Result<EpochSchedule, IngestError>;
+
+    /// The leader schedule of the epoch starting at `first_slot`: the slots every leader is
+    /// scheduled for, as offsets from `first_slot`. `None` if the schedule isn't served.
+    async fn leader_schedule(
+        &self,
+        first_slot: u64,
+    ) -> Result<Option<HashMap<Pubkey, Vec<usize>>>, IngestError>;
+}
+
+#[async_trait]
+impl LeaderScheduleSource for RpcPool {
+    async fn epoch_schedule(&self) -> Result<EpochSchedule, IngestError> {
+        Ok(self.call(|rpc| rpc.get_epoch_schedule()).await?)
+    }
+
+    async fn leader_schedule(
+        &self,
+        first_slot: u64,
+    ) -> Result<Option<HashMap<Pubkey, Vec<usize>>>, IngestError> {
+        let schedule = match self.call(move |rpc| rpc.get_leader_schedule(Some(first_slot))).await {
+            Ok(schedule) => schedule,
+            // Some nodes refuse the epochs they don't know rather than returning null.
+            Err(err) if matches!(err.kind(), ClientErrorKind::RpcError(
+                RpcError::RpcResponseError { .. })) => None,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(schedule.map(|schedule| {
+            schedule.into_iter()
+                .filter_map(|(identity, slots)| Some((identity.parse().ok()?, slots)))
+                .collect()
+        }))
+    }
+}
+
+/// The leader of every slot of an epoch.
+struct EpochLeaders {
+    first_slot: u64,
+    identities: Vec<Pubkey>,
+    // The index in `identities` of the leader of every slot, from the first.
+    slots: Vec<u32>,
+}
+
+impl EpochLeaders {
+    fn new(first_slot: u64, slots_in_epoch: u64, schedule: HashMap<Pubkey, Vec<usize>>) -> Self {
+        let mut leaders = EpochLeaders {
+            first_slot,
+            identities: Vec::with_capacity(schedule.len()),
+            slots: vec![NO_LEADER; slots_in_epoch as usize],
+        };
+        for (identity, slots) in schedule {
+            let index = leaders.identities.len() as u32;
+            leaders.identities.push(identity);
+            for slot in slots {
+                if let Some(leader) = leaders.slots.get_mut(slot) {
+                    *leader = index;
+                }
+            }
+        }
+
+        leaders
+    }
+
+    fn leader(&self, slot: u64) -> Option<Pubkey> {
+        let index = *self.slots.get(slot.checked_sub(self.first_slot)? as usize)?;
+        self.identities.get(index as usize).copied()
+    }
+}
+
+/// Resolves the epoch and leader of slots, see the module documentation.
+pub struct LeaderResolver {
+    source: Arc<dyn LeaderScheduleSource>,
+    // The epoch schedule, once fetched.
+    schedule: Mutex<Option<EpochSchedule>>,
+    // The leaders of the epochs looked up, by epoch, `None` for those whose leader schedule isn't
+    // served. Held while an epoch is looked up, for concurrent blocks not to look it up again.
+    leaders: tokio::sync::Mutex<LruCache<u64, Option<Arc<EpochLeaders>>>>,
+}
+
+impl LeaderResolver {
+    pub fn new(source: Arc<dyn LeaderScheduleSource>) -> Self {
+        LeaderResolver {
+            source,
+            schedule: Mutex::new(None),
+            leaders: tokio::sync::Mutex::new(LruCache::new("epoch_leaders", EPOCHS_CACHED, None)),
+        }
+    }
+
+    /// The epoch schedule, fetched on first use. `None` if it couldn't be, in which case it's
+    /// fetched again next time.
+    async fn schedule(&self) -> Option<EpochSchedule> {
+        if let Some(schedule) = *self.schedule.lock().unwrap() {
+            return Some(schedule);
+        }
+
+        match self.source.epoch_schedule().await {
+            Ok(schedule) => {
+                *self.schedule.lock().unwrap() = Some(schedule);
+                Some(schedule)
+            }
+            Err(err) => {
+                warn!("[spi-wrapper/ingest/leaders] Unable to fetch the epoch schedule: {}", err);
+                None
+            }
+        }
+    }
+
+    /// The epoch of `slot`, `None` if the epoch schedule couldn't be fetched.
+    pub async fn epoch(&self, slot: u64) -> Option<u64> {
+        Some(self.schedule().await?.get_epoch(slot))
+    }
+
+    /// The identity of the leader of `slot`, `None` if the leader schedule of its epoch isn't
+    /// served (or couldn't be fetched, in which case it's fetched again next time).
+    pub async fn leader(&self, slot: u64) -> Option<Pubkey> {
+        let schedule = self.schedule().await?;
+        let epoch = schedule.get_epoch(slot);
+
+        let mut cache = self.leaders.lock().await;
+        if let Some(leaders) = cache.get(&epoch) {
+            return leaders.as_ref()?.leader(slot);
+        }
+        let first_slot = schedule.get_first_slot_in_epoch(epoch);
+        let leaders = match self.source.leader_schedule(first_slot).await {
+            Ok(leaders) => leaders.map(|leaders| {
+                let slots_in_epoch = schedule.get_slots_in_epoch(epoch);
+                Arc::new(EpochLeaders::new(first_slot, slots_in_epoch, leaders))
+            }),
+            Err(err) => {
+                warn!("[spi-wrapper/ingest/leaders] Unable to fetch the leader schedule of epoch \
+                       {}: {}", epoch, err);
+                return None;
+            }
+        };
+        if leaders.is_none() {
+            warn!("[spi-wrapper/ingest/leaders] The leader schedule of epoch {} isn't served, its \
+                   blocks are left without a leader.", epoch);
+        }
+        cache.insert(epoch, leaders.clone());
+
+        leaders?.leader(slot)
+    }
+
+    /// Tags `record` with the epoch and leader of its slot, leaving out what isn't known.
+    pub async fn enrich(&self, record: &mut BlockRecord) {
+        record.epoch = self.epoch(record.slot).await;
+        record.leader = self.leader(record.slot).await.map(|leader| leader.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    const SLOTS_PER_EPOCH: u64 = 64;
+
+    /// Epochs of 64 slots, each led by one of two validators by runs of 4, the schedules of the
+    /// epochs before `first_served` not being served. Counts the schedules looked up.
+    struct Schedules {
+        validators: [Pubkey; 2],
+        first_served: u64,
+        lookups: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LeaderScheduleSource for Schedules {
+        async fn epoch_schedule(&self) -> Result<EpochSchedule, IngestError> {
+            Ok(EpochSchedule::custom(SLOTS_PER_EPOCH, SLOTS_PER_EPOCH, false))
+        }
+
+        async fn leader_schedule(
+            &self,
+            first_slot: u64,
+        ) -> Result<Option<HashMap<Pubkey, Vec<usize>>>, IngestError> {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            if first_slot / SLOTS_PER_EPOCH < self.first_served {
+                return Ok(None);
+            }
+            let mut schedule: HashMap<Pubkey, Vec<usize>> = HashMap::new();
+            for slot in 0..SLOTS_PER_EPOCH as usize {
+                schedule.entry(self.validators[slot / 4 % 2]).or_default().push(slot);
+            }
+
+            Ok(Some(schedule))
+        }
+    }
+
+    fn resolver(first_served: u64) -> (LeaderResolver, Arc<Schedules>) {
+        let source = Arc::new(Schedules {
+            validators: [Pubkey::new_unique(), Pubkey::new_unique()],
+            first_served,
+            lookups: AtomicUsize::new(0),
+        });
+        (LeaderResolver::new(source.clone()), source)
+    }
+
+    #[tokio::test]
+    async fn leaders_are_looked_up_once_per_epoch() {
+        let (resolver, source) = resolver(0);
+        let [first, second] = source.validators;
+        assert_eq!(resolver.epoch(3 * SLOTS_PER_EPOCH + 5).await, Some(3));
+        assert_eq!(resolver.leader(3 * SLOTS_PER_EPOCH).await, Some(first));
+        assert_eq!(resolver.leader(3 * SLOTS_PER_EPOCH + 5).await, Some(second));
+        assert_eq!(resolver.leader(4 * SLOTS_PER_EPOCH - 1).await, Some(second));
+        assert_eq!(source.lookups.load(Ordering::Relaxed), 1);
+
+        assert_eq!(resolver.leader(4 * SLOTS_PER_EPOCH).await, Some(first));
+        assert_eq!(source.lookups.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn historical_epochs_are_left_without_a_leader() {
+        let (resolver, source) = resolver(2);
+        let mut record: BlockRecord = serde_json::from_value(serde_json::json!({
+            "slot": SLOTS_PER_EPOCH + 1,
+            "parent_slot": SLOTS_PER_EPOCH,
+            "blockhash": "blockhash",
+            "previous_blockhash": "previous",
+            "block_time": null,
+            "finalized": true,
+            "transactions": [],
+        })).unwrap();
+        resolver.enrich(&mut record).await;
+        assert_eq!((record.epoch, record.leader.as_deref()), (Some(1), None));
+        // Not looked up again.
+        assert_eq!(resolver.leader(SLOTS_PER_EPOCH + 2).await, None);
+        assert_eq!(source.lookups.load(Ordering::Relaxed), 1);
+
+        record.slot = 2 * SLOTS_PER_EPOCH;
+        resolver.enrich(&mut record).await;
+        assert_eq!(record.epoch, Some(2));
+        assert_eq!(record.leader, Some(source.validators[0].to_string()));
+    }
+}
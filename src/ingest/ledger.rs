@@ -0,0 +1,104 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use solana_ledger::blockstore::Blockstore;
+use solana_ledger::blockstore_db::AccessType;
+use solana_transaction_status::{TransactionDetails, UiTransactionEncoding};
+use tracing::{info, warn};
+
+use crate::block::decode_block;
+use crate::ingest::{BlockSource, IngestError};
+use crate::registry::ProcessorRegistry;
+use crate::sinks::{self, Sink};
+
+/// Replays blocks out of a validator ledger directory (RocksDB), without touching RPC.
+///
+/// Only the rooted chain is followed, so blocks of abandoned forks are never indexed. Rooted
+/// slots whose block cannot be assembled (e.g. missing shreds) are recorded as gaps.
+pub struct LedgerSource {
+    blockstore: Arc<Blockstore>,
+    registry: Arc<ProcessorRegistry>,
+    // Rooted slots we couldn't read a complete block for.
+    gaps: Mutex<Vec<u64>>,
+}
+
+impl LedgerSource {
+    /// Opens the ledger at `ledger_path`. The ledger may be in use by a running validator, in
+    /// which case it is opened as a secondary instance.
+    pub fn open(ledger_path: &Path, registry: Arc<ProcessorRegistry>) -> Result<Self, IngestError> {
+        let blockstore = Blockstore::open_with_access_type(
+            ledger_path, AccessType::TryPrimaryThenSecondary, None, false)?;
+
+        Ok(LedgerSource {
+            blockstore: Arc::new(blockstore),
+            registry,
+            gaps: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// The rooted slots that could not be read so far.
+    pub fn gaps(&self) -> Vec<u64> {
+        self.gaps.lock().unwrap().clone()
+    }
+
+    /// Indexes every rooted block between `start_slot` and `end_slot` (both inclusive).
+    ///
+    /// Returns the number of blocks written to the sink.
+    pub async fn index_range<S: Sink + ?Sized>(
+        &self,
+        start_slot: u64,
+        end_slot: u64,
+        sink: &mut S,
+    ) -> Result<usize, IngestError> {
+        let blockstore = self.blockstore.clone();
+        let slots: Vec<u64> = tokio::task::spawn_blocking(move || {
+            blockstore.rooted_slot_iterator(start_slot)
+                .map(|slots| slots.take_while(|slot| *slot <= end_slot).collect())
+        }).await??;
+
+        let mut written = 0;
+        let mut gaps = 0;
+        for slot in slots {
+            let blockstore = self.blockstore.clone();
+            let block = tokio::task::spawn_blocking(move || {
+                blockstore.get_rooted_block(slot, false)
+            }).await?;
+
+            match block {
+                Ok(block) => {
+                    let ui_block = block.configure(
+                        UiTransactionEncoding::Base64, TransactionDetails::Full, false);
+                    let mut record = decode_block(&self.registry, slot, &ui_block);
+                    record.set_finalized(true);
+                    sinks::write_block(sink, &record).await?;
+                    written += 1;
+                }
+                Err(err) => {
+                    warn!("[spi-wrapper/ingest/ledger] Rooted slot {} cannot be read ({}), \
+                    recording a gap.", slot, err);
+                    self.gaps.lock().unwrap().push(slot);
+                    gaps += 1;
+                }
+            }
+        }
+
+        sinks::flush(sink).await?;
+        info!("[spi-wrapper/ingest/ledger] Replayed {} blocks between slots {} and {}, {} gaps.",
+            written, start_slot, end_slot, gaps);
+
+        Ok(written)
+    }
+}
+
+#[async_trait]
+impl BlockSource for LedgerSource {
+    async fn index_range(
+        &self,
+        start_slot: u64,
+        end_slot: u64,
+        sink: &mut dyn Sink,
+    ) -> Result<usize, IngestError> {
+        LedgerSource::index_range(self, start_slot, end_slot, sink).await
+    }
+}
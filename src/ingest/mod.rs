@@ -0,0 +1,106 @@
+pub mod backfill;
+pub mod batch;
+#[cfg(feature = "bigtable")]
+pub mod bigtable;
+pub mod commit;
+pub mod dedup;
+pub mod filter;
+pub mod gaps;
+pub mod leaders;
+#[cfg(feature = "geyser")]
+pub mod geyser;
+#[cfg(feature = "ledger")]
+pub mod ledger;
+pub mod pipeline;
+pub mod pool;
+pub mod rpc;
+pub mod scheduler;
+pub mod timestamps;
+pub mod websocket;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use solana_client::client_error::ClientError;
+use solana_sdk::commitment_config::CommitmentConfig;
+use thiserror::Error;
+
+use crate::checkpoint::CheckpointError;
+use crate::ingest::dedup::DedupError;
+use crate::ingest::pool::RpcPool;
+use crate::sinks::{Sink, SinkError};
+use crate::transaction::RawTransaction;
+
+/// Errors that may stop an ingestion source.
+#[derive(Debug, Error)]
+pub enum IngestError {
+    #[error("RPC request failed: {0}")]
+    Rpc(#[from] ClientError),
+    #[error("Sink failed: {0}")]
+    Sink(#[from] SinkError),
+    #[error("Background task failed: {0}")]
+    Join(#[from] tokio::task::JoinError),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Checkpoint failed: {0}")]
+    Checkpoint(#[from] CheckpointError),
+    #[error("Unable to save the signatures seen: {0}")]
+    Dedup(#[from] DedupError),
+    #[error("Pipeline failed: {0}")]
+    Pipeline(String),
+    #[cfg(feature = "bigtable")]
+    #[error("Bigtable request failed: {0}")]
+    Bigtable(#[from] solana_storage_bigtable::Error),
+    #[cfg(feature = "ledger")]
+    #[error("Blockstore error: {0}")]
+    Blockstore(#[from] solana_ledger::blockstore_db::BlockstoreError),
+}
+
+/// A source of confirmed blocks. Every block source pushes its blocks through the same sink
+/// interface, so switching between them is only a matter of configuration.
+#[async_trait]
+pub trait BlockSource: Send + Sync {
+    /// Indexes every block between `start_slot` and `end_slot` (both inclusive) into the sink,
+    /// in slot order. Returns the number of blocks written.
+    async fn index_range(
+        &self,
+        start_slot: u64,
+        end_slot: u64,
+        sink: &mut dyn Sink,
+    ) -> Result<usize, IngestError>;
+}
+
+/// Fetches a single transaction by signature.
+///
+/// Returns `None` when the node doesn't have the transaction (e.g. it was pruned) or when it
+/// cannot be turned into its raw form. Keeps the stack heights of the inner instructions, when
+/// the node reports them.
+pub async fn fetch_transaction(
+    rpc: &Arc<RpcPool>,
+    signature: &str,
+    commitment: CommitmentConfig,
+) -> Result<Option<RawTransaction>, IngestError> {
+    let mut transactions = fetch_transactions(rpc, &[signature.to_string()], commitment).await?;
+    Ok(transactions.pop().flatten())
+}
+
+/// Fetches transactions by signature, in order, batching the requests (see [`batch`]).
+///
+/// Every transaction is `None` when [`fetch_transaction`] would return `None` for it.
+pub async fn fetch_transactions(
+    rpc: &Arc<RpcPool>,
+    signatures: &[String],
+    commitment: CommitmentConfig,
+) -> Result<Vec<Option<RawTransaction>>, IngestError> {
+    let mut transactions = Vec::with_capacity(signatures.len());
+    for transaction in rpc.get_transactions(signatures, commitment).await? {
+        transactions.push(match transaction {
+            Value::Null => None,
+            confirmed => RawTransaction::from_confirmed_json(confirmed)
+                .map_err(ClientError::from)?,
+        });
+    }
+
+    Ok(transactions)
+}
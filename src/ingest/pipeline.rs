@@ -0,0 +1,982 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use solana_transaction_status::{EncodedTransactionWithStatusMeta, UiConfirmedBlock};
+use tokio::sync::{mpsc, Mutex, Semaphore};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::block::{decode_encoded_transaction_filtered, BlockRecord};
+use crate::checkpoint::Checkpointing;
+use crate::health::IngestProgress;
+use crate::ingest::commit::{CommitConfig, CommitCoordinator};
+use crate::ingest::filter::DecodeFilters;
+use crate::ingest::scheduler::{BlockScheduler, Lane, SchedulerState};
+use crate::ingest::IngestError;
+use crate::registry::ProcessorRegistry;
+use crate::sinks::{self, Sink};
+use crate::stats::{StatsLogger, StatsReporter, StatsSnapshot, DEFAULT_STATS_INTERVAL};
+use crate::transaction::TransactionRecord;
+
+// The name the queue depth of the sink is reported under.
+const SINK_QUEUE: &str = "sink";
+
+#[derive(Clone, Debug)]
+pub struct PipelineConfig {
+    // How many tasks decode transactions at once. Decoding is CPU bound, so more workers than
+    // runtime threads won't help.
+    pub workers: usize,
+    // The capacity of the channels between the pipeline stages.
+    pub queue_capacity: usize,
+    // The most transactions queued, being decoded or waiting for the rest of their block.
+    // Bounds memory use when the sink falls behind.
+    pub max_in_flight: usize,
+    // The most transactions handed to the sink in one `write_transactions` call.
+    pub batch_size: usize,
+    // Whether the blocks fed in were read at finalized commitment.
+    pub finalized: bool,
+    // Where the last slot written is checkpointed, as batches are committed.
+    pub checkpoint: Option<Checkpointing>,
+    // How batches and their checkpoint are committed, a single batch by default.
+    pub commit: CommitConfig,
+    // How often `run` logs the stats of the pipeline, never when unset.
+    pub stats_interval: Option<Duration>,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        PipelineConfig {
+            workers: 4,
+            queue_capacity: 64,
+            max_in_flight: 10_000,
+            batch_size: 500,
+            finalized: false,
+            checkpoint: None,
+            commit: CommitConfig::default(),
+            stats_interval: Some(DEFAULT_STATS_INTERVAL),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PipelineStats {
+    pub blocks: usize,
+    pub transactions: usize,
+    pub instructions: usize,
+    pub elapsed: Duration,
+}
+
+impl PipelineStats {
+    pub fn instructions_per_second(&self) -> f64 {
+        self.instructions as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+struct Job {
+    // The position of the block in the input stream.
+    block: u64,
+    index: usize,
+    slot: u64,
+    block_time: Option<i64>,
+    transaction: EncodedTransactionWithStatusMeta,
+}
+
+/// What a pipeline takes its blocks in from.
+enum Blocks {
+    // The blocks of a single source, all live.
+    Single(mpsc::Receiver<(u64, UiConfirmedBlock)>),
+    // The blocks a `BlockScheduler` hands over.
+    Scheduled(mpsc::Receiver<(Lane, u64, UiConfirmedBlock)>),
+}
+
+impl Blocks {
+    async fn recv(&mut self) -> Option<(Lane, u64, UiConfirmedBlock)> {
+        match self {
+            Blocks::Single(blocks) => blocks.recv().await
+                .map(|(slot, block)| (Lane::Live, slot, block)),
+            Blocks::Scheduled(blocks) => blocks.recv().await,
+        }
+    }
+}
+
+struct Decoded {
+    block: u64,
+    index: usize,
+    // `None` when the transaction couldn't be decoded into its raw form or was filtered out.
+    record: Option<TransactionRecord>,
+}
+
+/// Decodes blocks with a pool of workers, handing them to the sink in the order they came in.
+///
+/// Transactions are decoded independently (each one by a single worker, so its records keep
+/// their order) and streamed to the sink in block order, in batches of `batch_size`, right after
+/// their block's header. A block is never held in memory as a whole: every stage is connected
+/// through bounded channels and the number of transactions in flight is capped, so a slow sink
+/// eventually blocks whoever feeds the pipeline instead of growing memory.
+///
+/// The last slot written and the tip of the chain are kept in an [`IngestProgress`], for the
+/// health checks; whoever feeds the pipeline is expected to report the tip with
+/// [`Pipeline::set_tip_slot`]. Its throughput and lag are reported by [`Pipeline::stats`], and
+/// logged every `stats_interval`.
+///
+/// With [`Pipeline::run_scheduled`], live ingestion and a backfill share the pipeline (see
+/// [`crate::ingest::scheduler`]): only live blocks move the current slot and the checkpoint, and
+/// the state of the scheduler is reported in the stats.
+///
+/// Blocks are committed to the sink in batches of `commit.blocks_per_commit`, along with the
+/// checkpoint of the last slot written, by a [`CommitCoordinator`] (whose checkpoint whoever
+/// feeds the pipeline resumes from): at least once by default, or transactionally with sinks
+/// supporting it, so that a crash never has a block written twice.
+///
+/// Once [`Pipeline::shutdown`] is called (or its shutdown token cancelled), no new block is
+/// taken in; the ones already taken in are decoded and written, the sink is shut down and the
+/// last slot written is committed before `run` returns.
+pub struct Pipeline {
+    registry: Arc<ProcessorRegistry>,
+    config: PipelineConfig,
+    progress: Arc<IngestProgress>,
+    stats: Arc<StatsReporter>,
+    shutdown: CancellationToken,
+    // What's skipped before being decoded.
+    filters: DecodeFilters,
+}
+
+impl Pipeline {
+    pub fn new(registry: Arc<ProcessorRegistry>, config: PipelineConfig) -> Self {
+        let progress = Arc::new(IngestProgress::default());
+        Pipeline {
+            registry,
+            config,
+            stats: Arc::new(StatsReporter::new(progress.clone())),
+            progress,
+            shutdown: CancellationToken::new(),
+            filters: DecodeFilters::default(),
+        }
+    }
+
+    /// Skips the transactions and instructions `filters` don't let through, without decoding
+    /// them.
+    pub fn with_filters(mut self, filters: DecodeFilters) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// Stops the pipeline when `token` is cancelled, e.g. by a `ShutdownCoordinator`.
+    pub fn with_shutdown_token(mut self, token: CancellationToken) -> Self {
+        self.shutdown = token;
+        self
+    }
+
+    /// Stops taking in new blocks; `run` returns once the ones in flight have been written.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// The progress of the pipeline, to be shared with `health::serve`.
+    pub fn progress(&self) -> Arc<IngestProgress> {
+        self.progress.clone()
+    }
+
+    /// The last slot fully written to the sink, if any.
+    pub fn current_slot(&self) -> Option<u64> {
+        self.progress.current_slot()
+    }
+
+    /// The tip of the chain, as last reported with `set_tip_slot`.
+    pub fn tip_slot(&self) -> Option<u64> {
+        self.progress.tip_slot()
+    }
+
+    pub fn set_tip_slot(&self, slot: u64) {
+        self.progress.set_tip_slot(slot);
+    }
+
+    /// The throughput and lag of the pipeline, as of now.
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// What the stats are recorded into, to be shared with `health::serve_with_stats`.
+    pub fn stats_reporter(&self) -> Arc<StatsReporter> {
+        self.stats.clone()
+    }
+
+    /// What commits the batches written by the pipeline, e.g. to know which checkpoint to resume
+    /// from (see [`CommitCoordinator::load`]).
+    pub fn commit_coordinator(&self) -> CommitCoordinator {
+        CommitCoordinator::new(
+            self.config.commit.clone(), self.config.checkpoint.clone(), self.progress.clone())
+    }
+
+    /// Decodes every block received on `blocks` until the channel is closed or the pipeline is
+    /// shut down, writing them to the sink in order.
+    pub async fn run<S: Sink + ?Sized>(
+        &self,
+        blocks: mpsc::Receiver<(u64, UiConfirmedBlock)>,
+        sink: &mut S,
+    ) -> Result<PipelineStats, IngestError> {
+        self.run_blocks(Blocks::Single(blocks), None, sink).await
+    }
+
+    /// Same as [`Pipeline::run`], taking blocks in from both queues of `scheduler` until they're
+    /// both closed.
+    pub async fn run_scheduled<S: Sink + ?Sized>(
+        &self,
+        scheduler: BlockScheduler,
+        sink: &mut S,
+    ) -> Result<PipelineStats, IngestError> {
+        let state = scheduler.state();
+        let blocks = scheduler.spawn(self.config.queue_capacity);
+        self.run_blocks(Blocks::Scheduled(blocks), Some(state), sink).await
+    }
+
+    async fn run_blocks<S: Sink + ?Sized>(
+        &self,
+        mut blocks: Blocks,
+        scheduler: Option<Arc<SchedulerState>>,
+        sink: &mut S,
+    ) -> Result<PipelineStats, IngestError> {
+        let started = Instant::now();
+        let mut commits = self.commit_coordinator();
+        commits.check(sink)?;
+        let _logger: Option<StatsLogger> = self.config.stats_interval
+            .map(|interval| self.stats.clone().spawn_logger(interval));
+        let max_in_flight = self.config.max_in_flight.max(1);
+        let capacity = self.config.queue_capacity.max(1);
+        let batch_size = self.config.batch_size.max(1);
+        let (job_sender, job_receiver) = mpsc::channel::<Job>(capacity);
+        let (result_sender, mut results) = mpsc::channel::<Decoded>(capacity);
+        let (header_sender, mut headers) =
+            mpsc::channel::<(BlockRecord, usize, Lane)>(capacity);
+        let in_flight = Arc::new(Semaphore::new(max_in_flight));
+
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        for _ in 0..self.config.workers.max(1) {
+            let jobs = job_receiver.clone();
+            let results = result_sender.clone();
+            let registry = self.registry.clone();
+            let filters = self.filters.clone();
+
+            tokio::spawn(async move {
+                loop {
+                    let job = jobs.lock().await.recv().await;
+                    let job = match job {
+                        Some(job) => job,
+                        None => break,
+                    };
+
+                    let record = decode_encoded_transaction_filtered(
+                        &registry, &filters, job.slot, job.block_time, &job.transaction);
+                    let decoded = Decoded { block: job.block, index: job.index, record };
+                    if results.send(decoded).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(result_sender);
+
+        let dispatch_in_flight = in_flight.clone();
+        let progress = self.progress.clone();
+        let shutdown = self.shutdown.clone();
+        let network = self.registry.network();
+        tokio::spawn(async move {
+            let mut sequence = 0;
+            loop {
+                // Checked between blocks only, so a block is always written whole.
+                let (lane, slot, mut block) = tokio::select! {
+                    biased;
+                    _ = shutdown.cancelled() => break,
+                    received = blocks.recv() => match received {
+                        Some(received) => received,
+                        None => break,
+                    },
+                };
+                // Backfill blocks are behind the current slot, which only live blocks move.
+                if lane == Lane::Live {
+                    progress.accept(slot);
+                }
+                // The transactions are moved out one by one as they're dispatched, so the block
+                // shrinks while it's being decoded.
+                let transactions = block.transactions.take().unwrap_or_default();
+                let header = BlockRecord::header(network, slot, &block);
+                if header_sender.send((header, transactions.len(), lane)).await.is_err() {
+                    return;
+                }
+
+                for (index, transaction) in transactions.into_iter().enumerate() {
+                    if let Ok(permit) = dispatch_in_flight.acquire().await {
+                        permit.forget();
+                    }
+                    let job = Job {
+                        block: sequence,
+                        index,
+                        slot,
+                        block_time: block.block_time,
+                        transaction,
+                    };
+                    if job_sender.send(job).await.is_err() {
+                        return;
+                    }
+                }
+                sequence += 1;
+            }
+        });
+
+        let mut stats = PipelineStats::default();
+        // Results that came in before their turn.
+        let mut early: HashMap<(u64, usize), Option<TransactionRecord>> = HashMap::new();
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut sequence = 0;
+        while let Some((mut header, count, lane)) = headers.recv().await {
+            #[cfg(feature = "metrics")]
+            let timer = crate::metrics::metrics().block_processing_seconds.start_timer();
+            header.set_finalized(self.config.finalized);
+            let (mut transactions, mut instructions) = (0, 0);
+            commits.before_block(sink).await?;
+            self.progress.track_write(sinks::write_block_header(sink, &header)).await?;
+
+            for index in 0..count {
+                let record = match early.remove(&(sequence, index)) {
+                    Some(record) => record,
+                    None => loop {
+                        let decoded = results.recv().await.ok_or_else(|| {
+                            IngestError::Pipeline("decoding workers stopped".to_string())
+                        })?;
+                        if (decoded.block, decoded.index) == (sequence, index) {
+                            break decoded.record;
+                        }
+                        early.insert((decoded.block, decoded.index), decoded.record);
+                    },
+                };
+                // Transactions leave the in-flight count once they're next in line (results
+                // buffered early keep their permit), so the buffer never outgrows
+                // `max_in_flight`, and the transaction waited for has always been dispatched.
+                in_flight.add_permits(1);
+
+                if let Some(mut record) = record {
+                    record.finalized = self.config.finalized;
+                    transactions += 1;
+                    instructions += record.instruction_sets.len();
+                    batch.push(record);
+                }
+                if batch.len() >= batch_size {
+                    self.write_batch(sink, &mut batch).await?;
+                }
+            }
+            if !batch.is_empty() {
+                self.write_batch(sink, &mut batch).await?;
+            }
+
+            #[cfg(feature = "metrics")]
+            {
+                timer.observe_duration();
+                crate::metrics::metrics().blocks_processed.inc();
+            }
+            if lane == Lane::Live {
+                self.progress.set_current_slot(header.slot);
+            }
+            if let Some(scheduler) = &scheduler {
+                let live_lag = self.progress.lag_slots();
+                scheduler.block_written(lane, live_lag);
+                self.stats.set_scheduler(scheduler.stats(live_lag));
+            }
+            commits.block_written(sink, self.progress.current_slot()).await?;
+            // Whatever was dispatched but isn't next in line yet is waiting for the sink.
+            self.stats.set_queue_depth(
+                SINK_QUEUE, max_in_flight.saturating_sub(in_flight.available_permits()));
+            self.stats.record_block(transactions, instructions);
+            stats.blocks += 1;
+            stats.transactions += transactions;
+            stats.instructions += instructions;
+            sequence += 1;
+        }
+        // Everything up to the current slot has been written.
+        commits.finish(sink, self.progress.current_slot(), self.shutdown.is_cancelled()).await?;
+        if let Some(dedup) = &self.filters.dedup {
+            dedup.persist()?;
+        }
+
+        stats.elapsed = started.elapsed();
+        info!("[spi-wrapper/ingest/pipeline] Decoded {} blocks, {} instructions in {:?} ({:.0} \
+        instructions/s).", stats.blocks, stats.instructions, stats.elapsed,
+            stats.instructions_per_second());
+
+        Ok(stats)
+    }
+
+    /// Writes a batch of transactions, leaving out the ones the sink is known to have already
+    /// (see `DedupFilter`), and empties it.
+    async fn write_batch<S: Sink + ?Sized>(
+        &self,
+        sink: &mut S,
+        batch: &mut Vec<TransactionRecord>,
+    ) -> Result<(), IngestError> {
+        let dedup = self.filters.dedup.as_ref();
+        if let Some(dedup) = dedup {
+            dedup.confirm(batch, sink).await?;
+        }
+        self.progress.track_write(sinks::write_transactions(sink, batch)).await?;
+        if let Some(dedup) = dedup {
+            dedup.record(batch);
+        }
+        batch.clear();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use async_trait::async_trait;
+    use serde_json::json;
+    use solana_sdk::message::Message;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::transaction::Transaction;
+
+    use super::*;
+    use crate::checkpoint::file::FileCheckpointStore;
+    use crate::ingest::scheduler::SchedulerConfig;
+    use crate::shutdown::ShutdownCoordinator;
+    use crate::sinks::SinkError;
+
+    const BLOCKS: u64 = 20;
+    const TRANSACTIONS_PER_BLOCK: usize = 200;
+    const TRANSFERS_PER_TRANSACTION: u64 = 4;
+
+    #[derive(Default)]
+    struct CollectingSink {
+        slots: Vec<u64>,
+    }
+
+    #[async_trait]
+    impl Sink for CollectingSink {
+        async fn write_block_header(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+            self.slots.push(block.slot);
+            Ok(())
+        }
+
+        async fn write_transactions(&mut self, _: &[TransactionRecord]) -> Result<(), SinkError> {
+            Ok(())
+        }
+    }
+
+    /// A synthetic block of token transfers, as returned by `getBlock` with the base64 encoding.
+    fn fixture_block(slot: u64, transactions: usize) -> serde_json::Value {
+        let payer = Pubkey::new_unique();
+        let (source, destination) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let transactions: Vec<_> = (0..transactions)
+            .map(|index| {
+                let instructions: Vec<_> = (0..TRANSFERS_PER_TRANSACTION)
+                    .map(|amount| spl_token::instruction::transfer(
+                        &spl_token::id(), &source, &destination, &payer, &[],
+                        amount + index as u64).unwrap())
+                    .collect();
+                let transaction = Transaction::new_unsigned(
+                    Message::new(&instructions, Some(&payer)));
+
+                json!({
+                    "transaction": [
+                        base64::encode(bincode::serialize(&transaction).unwrap()), "base64"],
+                    "meta": {
+                        "err": null,
+                        "status": { "Ok": null },
+                        "fee": 5000,
+                        "preBalances": [],
+                        "postBalances": [],
+                    },
+                })
+            })
+            .collect();
+
+        json!({
+            "blockhash": bs58::encode([slot as u8; 32]).into_string(),
+            "previousBlockhash": bs58::encode([slot as u8 - 1; 32]).into_string(),
+            "parentSlot": slot - 1,
+            "transactions": transactions,
+            "blockTime": 1_600_000_000 + slot,
+        })
+    }
+
+    /// Writes synthetic blocks into a fixture directory, one JSON file per block.
+    fn write_fixture_blocks(directory: &Path) {
+        let _ = fs::remove_dir_all(directory);
+        fs::create_dir_all(directory).unwrap();
+
+        for slot in 1..=BLOCKS {
+            let block = fixture_block(slot, TRANSACTIONS_PER_BLOCK);
+            fs::write(directory.join(format!("{}.json", slot)), block.to_string()).unwrap();
+        }
+    }
+
+    fn read_fixture_blocks(directory: &Path) -> Vec<(u64, UiConfirmedBlock)> {
+        let mut blocks: Vec<(u64, UiConfirmedBlock)> = fs::read_dir(directory).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().map_or(false, |extension| extension == "json"))
+            .map(|path| {
+                let slot = path.file_stem().unwrap().to_str().unwrap().parse().unwrap();
+                (slot, serde_json::from_slice(&fs::read(&path).unwrap()).unwrap())
+            })
+            .collect();
+        blocks.sort_by_key(|(slot, _)| *slot);
+
+        blocks
+    }
+
+    /// Processes the fixture blocks (or the ones in `SPI_FIXTURE_BLOCKS`) with an increasing
+    /// number of workers, which only changes how fast they come out (see the
+    /// `pipeline_throughput` bench): they come out in order.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn blocks_come_out_in_order_whatever_the_workers() {
+        let blocks = match std::env::var("SPI_FIXTURE_BLOCKS") {
+            Ok(directory) => read_fixture_blocks(Path::new(&directory)),
+            Err(_) => {
+                let directory = std::env::temp_dir()
+                    .join(format!("spi-wrapper-fixture-blocks-{}", std::process::id()));
+                write_fixture_blocks(&directory);
+                let blocks = read_fixture_blocks(&directory);
+                fs::remove_dir_all(&directory).unwrap();
+                blocks
+            }
+        };
+        let expected_slots: Vec<u64> = blocks.iter().map(|(slot, _)| *slot).collect();
+
+        for workers in &[1, 2, 4, 8] {
+            let pipeline = Pipeline::new(Arc::new(ProcessorRegistry::default()), PipelineConfig {
+                workers: *workers,
+                max_in_flight: 500,
+                ..PipelineConfig::default()
+            });
+            let (sender, receiver) = mpsc::channel(4);
+            let feed = blocks.clone();
+            tokio::spawn(async move {
+                for block in feed {
+                    if sender.send(block).await.is_err() {
+                        break;
+                    }
+                }
+            });
+
+            let mut sink = CollectingSink::default();
+            pipeline.run(receiver, &mut sink).await.unwrap();
+
+            assert_eq!(sink.slots, expected_slots, "{} workers", workers);
+        }
+    }
+
+    /// Feeds fixture blocks at a steady pace and checks the rates reported against it.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stats_follow_the_pace_of_blocks() {
+        const PACED_BLOCKS: u64 = 10;
+        const PACED_TRANSACTIONS: usize = 20;
+        const PACE: Duration = Duration::from_millis(100);
+
+        let started = Instant::now();
+        let pipeline = Pipeline::new(Arc::new(ProcessorRegistry::default()),
+                                     PipelineConfig::default());
+        pipeline.set_tip_slot(PACED_BLOCKS + 5);
+        let (sender, receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            for slot in 1..=PACED_BLOCKS {
+                let block = serde_json::from_value(fixture_block(slot, PACED_TRANSACTIONS))
+                    .unwrap();
+                tokio::time::sleep(PACE).await;
+                if sender.send((slot, block)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut sink = CollectingSink::default();
+        let totals = pipeline.run(receiver, &mut sink).await.unwrap();
+        let stats = pipeline.stats();
+        let elapsed = started.elapsed();
+        assert!(elapsed >= PACE * PACED_BLOCKS as u32);
+        assert_eq!(totals.transactions, PACED_BLOCKS as usize * PACED_TRANSACTIONS);
+
+        // The run is shorter than a minute, so the rates are over all of it.
+        let expected = totals.transactions as f64 / elapsed.as_secs_f64();
+        let rate = stats.transactions_per_second.one_minute;
+        assert!(rate >= expected && rate < expected * 1.05,
+                "{:.1} transactions/s, expected {:.1}", rate, expected);
+        assert_eq!(stats.transactions_per_second.five_minutes, rate);
+        let instructions_per_transaction = totals.instructions as f64 / totals.transactions as f64;
+        assert!((stats.instructions_per_second.one_minute - rate * instructions_per_transaction)
+            .abs() < 1e-6);
+
+        assert_eq!(stats.current_slot, Some(PACED_BLOCKS));
+        assert_eq!(stats.lag_slots, Some(5));
+        assert_eq!(stats.queue_depths.get(SINK_QUEUE), Some(&0));
+    }
+
+    #[derive(Default)]
+    struct Recorded {
+        headers: Vec<u64>,
+        // Transactions written, by slot.
+        transactions: HashMap<u64, usize>,
+        shut_down: bool,
+    }
+
+    /// Records what reaches it, taking a little while over every batch.
+    struct SlowSink(Arc<std::sync::Mutex<Recorded>>);
+
+    #[async_trait]
+    impl Sink for SlowSink {
+        async fn write_block_header(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+            self.0.lock().unwrap().headers.push(block.slot);
+            Ok(())
+        }
+
+        async fn write_transactions(
+            &mut self,
+            transactions: &[TransactionRecord],
+        ) -> Result<(), SinkError> {
+            tokio::time::sleep(Duration::from_millis(2)).await;
+            let mut recorded = self.0.lock().unwrap();
+            for transaction in transactions {
+                *recorded.transactions.entry(transaction.slot).or_default() += 1;
+            }
+            Ok(())
+        }
+
+        async fn shutdown(&mut self) -> Result<(), SinkError> {
+            self.0.lock().unwrap().shut_down = true;
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shutdown_during_a_batch_loses_nothing() {
+        const TRANSACTIONS: usize = 50;
+        let directory = std::env::temp_dir()
+            .join(format!("spi-wrapper-shutdown-checkpoints-{}", std::process::id()));
+        let checkpoint = Checkpointing::new(Arc::new(FileCheckpointStore::new(&directory)),
+                                            "shutdown");
+        let coordinator = ShutdownCoordinator::default();
+        let pipeline = Pipeline::new(Arc::new(ProcessorRegistry::default()), PipelineConfig {
+            max_in_flight: 20,
+            batch_size: 7,
+            checkpoint: Some(checkpoint.clone()),
+            ..PipelineConfig::default()
+        }).with_shutdown_token(coordinator.token());
+        let progress = pipeline.progress();
+
+        let (sender, receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            for slot in 1..=BLOCKS {
+                let block = serde_json::from_value(fixture_block(slot, TRANSACTIONS)).unwrap();
+                if sender.send((slot, block)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        // Shut down as soon as the first batch has gone through, with more in flight.
+        let recorded = Arc::new(std::sync::Mutex::new(Recorded::default()));
+        let watched = recorded.clone();
+        let token = coordinator.token();
+        tokio::spawn(async move {
+            while watched.lock().unwrap().transactions.is_empty() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            token.cancel();
+        });
+
+        let mut sink = SlowSink(recorded.clone());
+        let stats = coordinator.run(pipeline.run(receiver, &mut sink), &progress).await
+            .unwrap()
+            .unwrap();
+
+        let saved = checkpoint.load().await.unwrap().unwrap();
+        let recorded = recorded.lock().unwrap();
+        assert!(recorded.shut_down);
+        assert!((recorded.headers.len() as u64) < BLOCKS, "shutdown didn't stop ingestion");
+        assert_eq!(stats.blocks, recorded.headers.len());
+        // Every block taken in was written whole.
+        for slot in &recorded.headers {
+            assert_eq!(recorded.transactions.get(slot), Some(&TRANSACTIONS));
+        }
+        assert!(progress.unwritten_slots().is_empty());
+        assert_eq!(saved.last_slot, recorded.headers.last().copied());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    /// Takes a while over every block, recording the slots written and how far behind the tip
+    /// live ingestion was whenever a block came in.
+    struct PacedSink {
+        delay: Duration,
+        progress: Arc<IngestProgress>,
+        slots: Vec<u64>,
+        max_live_lag: u64,
+    }
+
+    #[async_trait]
+    impl Sink for PacedSink {
+        async fn write_block_header(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+            tokio::time::sleep(self.delay).await;
+            self.slots.push(block.slot);
+            self.max_live_lag = self.max_live_lag.max(self.progress.lag_slots().unwrap_or(0));
+            Ok(())
+        }
+
+        async fn write_transactions(&mut self, _: &[TransactionRecord]) -> Result<(), SinkError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn live_lag_stays_bounded_while_backfilling() {
+        const BACKFILL_BLOCKS: u64 = 120;
+        const FIRST_LIVE_SLOT: u64 = 200;
+        const LIVE_BLOCKS: u64 = 40;
+        // The sink keeps up with live blocks alone, not with the backfill on top of them.
+        const LIVE_PACE: Duration = Duration::from_millis(10);
+        const SINK_DELAY: Duration = Duration::from_millis(6);
+
+        let pipeline = Pipeline::new(Arc::new(ProcessorRegistry::default()), PipelineConfig {
+            stats_interval: None,
+            ..PipelineConfig::default()
+        });
+        let (live_sender, live) = mpsc::channel(4);
+        let (backfill_sender, backfill) = mpsc::channel(BACKFILL_BLOCKS as usize);
+        let scheduler = BlockScheduler::new(SchedulerConfig {
+            backfill_concurrency: 4,
+            max_live_lag: 2,
+        }, live, backfill);
+        let state = scheduler.state();
+
+        // The whole backfill is waiting before the first live block comes in.
+        state.expect_backfill(BACKFILL_BLOCKS);
+        for slot in 1..=BACKFILL_BLOCKS {
+            let block = serde_json::from_value(fixture_block(slot, 2)).unwrap();
+            backfill_sender.send((slot, block)).await.unwrap();
+        }
+        drop(backfill_sender);
+        let progress = pipeline.progress();
+        tokio::spawn(async move {
+            for slot in FIRST_LIVE_SLOT..FIRST_LIVE_SLOT + LIVE_BLOCKS {
+                let block = serde_json::from_value(fixture_block(slot, 2)).unwrap();
+                progress.set_tip_slot(slot);
+                if live_sender.send((slot, block)).await.is_err() {
+                    break;
+                }
+                tokio::time::sleep(LIVE_PACE).await;
+            }
+        });
+
+        let mut sink = PacedSink {
+            delay: SINK_DELAY,
+            progress: pipeline.progress(),
+            slots: Vec::new(),
+            max_live_lag: 0,
+        };
+        let stats = pipeline.run_scheduled(scheduler, &mut sink).await.unwrap();
+        assert_eq!(stats.blocks as u64, BACKFILL_BLOCKS + LIVE_BLOCKS);
+
+        // Left alone, the backfill would hold the live blocks back by most of its 120 blocks.
+        assert!(sink.max_live_lag <= 8, "live ingestion lagged by {} slots", sink.max_live_lag);
+        // Each path still went in order, and the backfill went on while live blocks came in.
+        let live_slots: Vec<u64> = (FIRST_LIVE_SLOT..FIRST_LIVE_SLOT + LIVE_BLOCKS).collect();
+        let (written_live, written_backfill): (Vec<u64>, Vec<u64>) = sink.slots.iter()
+            .copied()
+            .partition(|slot| *slot >= FIRST_LIVE_SLOT);
+        assert_eq!(written_live, live_slots);
+        assert_eq!(written_backfill, (1..=BACKFILL_BLOCKS).collect::<Vec<_>>());
+        let position = |slot| sink.slots.iter().position(|written| *written == slot).unwrap();
+        let last_live_slot = FIRST_LIVE_SLOT + LIVE_BLOCKS - 1;
+        let (first_live, last_live) = (position(FIRST_LIVE_SLOT), position(last_live_slot));
+        assert!(sink.slots[first_live..last_live].iter().any(|slot| *slot < FIRST_LIVE_SLOT));
+
+        let scheduler = pipeline.stats().scheduler.unwrap();
+        assert_eq!(scheduler.backfill_remaining, 0);
+        assert_eq!(scheduler.backfill_in_flight, 0);
+        // Only live blocks move the current slot.
+        assert_eq!(pipeline.current_slot(), Some(last_live_slot));
+    }
+
+    /// Forwards to a SQLite sink, recording how many transactions of every slot were delivered
+    /// for good (once committed, in transactional mode), and crashes on the `crash_at`th commit.
+    #[cfg(feature = "sqlite")]
+    struct DeliverySink {
+        inner: crate::sinks::sqlite::SqliteSink,
+        // Transactions written since the sink's transaction was begun, if it was, by slot.
+        staged: Option<HashMap<u64, usize>>,
+        delivered: Arc<std::sync::Mutex<HashMap<u64, usize>>>,
+        commits: usize,
+        crash_at: Option<usize>,
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[async_trait]
+    impl Sink for DeliverySink {
+        async fn write_block_header(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+            self.inner.write_block_header(block).await
+        }
+
+        async fn write_transactions(
+            &mut self,
+            transactions: &[TransactionRecord],
+        ) -> Result<(), SinkError> {
+            self.inner.write_transactions(transactions).await?;
+            let mut delivered = self.delivered.lock().unwrap();
+            let counts = self.staged.as_mut().unwrap_or(&mut *delivered);
+            for transaction in transactions {
+                *counts.entry(transaction.slot).or_default() += 1;
+            }
+            Ok(())
+        }
+
+        fn supports_transactions(&self) -> bool {
+            true
+        }
+
+        async fn begin(&mut self) -> Result<(), SinkError> {
+            self.staged = Some(HashMap::new());
+            self.inner.begin().await
+        }
+
+        async fn commit(
+            &mut self,
+            pipeline: &str,
+            checkpoint: Option<&crate::checkpoint::Checkpoint>,
+        ) -> Result<(), SinkError> {
+            self.commits += 1;
+            if Some(self.commits) == self.crash_at {
+                return Err(SinkError::Other("crashed before committing".to_string()));
+            }
+            self.inner.commit(pipeline, checkpoint).await?;
+            let mut delivered = self.delivered.lock().unwrap();
+            for (slot, count) in self.staged.take().unwrap_or_default() {
+                *delivered.entry(slot).or_default() += count;
+            }
+            Ok(())
+        }
+
+        async fn committed_checkpoint(
+            &mut self,
+            pipeline: &str,
+        ) -> Result<Option<crate::checkpoint::Checkpoint>, SinkError> {
+            self.inner.committed_checkpoint(pipeline).await
+        }
+    }
+
+    /// A file checkpoint store crashing on the `crash_at`th save.
+    #[cfg(feature = "sqlite")]
+    struct CrashingStore {
+        inner: FileCheckpointStore,
+        saves: std::sync::atomic::AtomicUsize,
+        crash_at: Option<usize>,
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[async_trait]
+    impl crate::checkpoint::CheckpointStore for CrashingStore {
+        async fn load(
+            &self,
+            pipeline: &str,
+        ) -> Result<Option<crate::checkpoint::Checkpoint>, crate::checkpoint::CheckpointError> {
+            self.inner.load(pipeline).await
+        }
+
+        async fn save(
+            &self,
+            pipeline: &str,
+            checkpoint: &crate::checkpoint::Checkpoint,
+        ) -> Result<(), crate::checkpoint::CheckpointError> {
+            let saves = self.saves.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+            if Some(saves) == self.crash_at {
+                return Err(std::io::Error::new(std::io::ErrorKind::Other, "crashed").into());
+            }
+            self.inner.save(pipeline, checkpoint).await
+        }
+    }
+
+    /// Indexes the fixture blocks after the checkpoint committed in `directory`, committing every
+    /// other block in `mode`, and crashes on the `crash_at`th commit. The sink is dropped on the
+    /// way out, as it would be by a crash.
+    #[cfg(feature = "sqlite")]
+    async fn index_until_crash(
+        mode: crate::ingest::commit::CommitMode,
+        directory: &Path,
+        crash_at: Option<usize>,
+        delivered: Arc<std::sync::Mutex<HashMap<u64, usize>>>,
+    ) -> Result<PipelineStats, IngestError> {
+        use crate::ingest::commit::{CommitConfig, CommitMode};
+
+        let store = CrashingStore {
+            inner: FileCheckpointStore::new(directory),
+            saves: Default::default(),
+            crash_at: crash_at.filter(|_| mode == CommitMode::AtLeastOnce),
+        };
+        let pipeline = Pipeline::new(Arc::new(ProcessorRegistry::default()), PipelineConfig {
+            batch_size: 7,
+            checkpoint: Some(Checkpointing::new(Arc::new(store), "crash")),
+            commit: CommitConfig { mode, blocks_per_commit: Some(2) },
+            stats_interval: None,
+            ..PipelineConfig::default()
+        });
+        let mut sink = DeliverySink {
+            inner: crate::sinks::sqlite::SqliteSink::open(directory.join("indexer.sqlite"))?,
+            staged: None,
+            delivered,
+            commits: 0,
+            crash_at: crash_at.filter(|_| mode == CommitMode::Transactional),
+        };
+
+        let checkpoint = pipeline.commit_coordinator().load(&mut sink).await?;
+        let first_slot = checkpoint.and_then(|checkpoint| checkpoint.last_slot)
+            .map_or(1, |slot| slot + 1);
+        let (sender, receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            for slot in first_slot..=10 {
+                let block = serde_json::from_value(fixture_block(slot, 20)).unwrap();
+                if sender.send((slot, block)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        pipeline.run(receiver, &mut sink).await
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn crashing_between_write_and_checkpoint() {
+        use crate::ingest::commit::CommitMode;
+
+        for mode in &[CommitMode::Transactional, CommitMode::AtLeastOnce] {
+            let directory = std::env::temp_dir()
+                .join(format!("spi-wrapper-commit-{:?}-{}", mode, std::process::id()));
+            let _ = fs::remove_dir_all(&directory);
+            fs::create_dir_all(&directory).unwrap();
+            let delivered = Arc::new(std::sync::Mutex::new(HashMap::new()));
+
+            // Blocks 5 and 6 are written, but their checkpoint never is.
+            let crashed = index_until_crash(*mode, &directory, Some(3), delivered.clone()).await;
+            assert!(crashed.is_err());
+            let database = rusqlite::Connection::open(directory.join("indexer.sqlite")).unwrap();
+            let blocks: i64 = database
+                .query_row("SELECT COUNT(*) FROM blocks", rusqlite::params![], |row| row.get(0))
+                .unwrap();
+            assert_eq!(blocks, if *mode == CommitMode::Transactional { 4 } else { 6 });
+
+            let stats = index_until_crash(*mode, &directory, None, delivered.clone()).await
+                .unwrap();
+            assert_eq!(stats.blocks, 6);
+            let delivered = delivered.lock().unwrap();
+            assert_eq!(delivered.len(), 10);
+            let duplicates: usize = delivered.values().map(|count| count - 20).sum();
+            match mode {
+                CommitMode::Transactional => assert_eq!(duplicates, 0),
+                // At most a batch of blocks is written twice.
+                CommitMode::AtLeastOnce => assert_eq!(duplicates, 2 * 20),
+            }
+
+            let _ = fs::remove_dir_all(&directory);
+        }
+    }
+}
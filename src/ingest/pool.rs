@@ -0,0 +1,426 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::Value;
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_request::{RpcError, RpcRequest};
+use tracing::warn;
+
+use crate::ingest::batch::Batches;
+
+// Returned by nodes that are unhealthy, typically because they're behind the cluster.
+const JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY: i64 = -32005;
+
+#[derive(Clone, Debug)]
+pub struct RpcPoolConfig {
+    // The sustained request rate allowed per endpoint.
+    pub requests_per_second: f64,
+    // How many requests may be issued back to back before the rate limit kicks in.
+    pub burst: f64,
+    // How many times a call is retried (possibly on other endpoints) before giving up.
+    pub max_retries: u32,
+    // The first backoff delay; every retry doubles it, up to `max_backoff`.
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+    // How long it takes for a failing endpoint to be fully trusted again.
+    pub recovery_period: Duration,
+    // How many transactions are fetched in one JSON-RPC batch, and accounts in one
+    // `getMultipleAccounts` call (100 at most), 1 not to batch (see `ingest::batch`).
+    pub batch_size: usize,
+}
+
+impl Default for RpcPoolConfig {
+    fn default() -> Self {
+        RpcPoolConfig {
+            requests_per_second: 10.0,
+            burst: 10.0,
+            max_retries: 5,
+            base_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            recovery_period: Duration::from_secs(60),
+            batch_size: 100,
+        }
+    }
+}
+
+/// Per-endpoint call metrics.
+#[derive(Clone, Debug)]
+pub struct EndpointStats {
+    pub url: String,
+    pub calls: u64,
+    pub retries: u64,
+    pub errors: u64,
+    pub average_latency: Duration,
+    pub health: f64,
+}
+
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_second: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Takes a token, returning how long to wait first if none is available.
+    fn take(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        self.last_refill = now;
+
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.refill_per_second)
+        }
+    }
+}
+
+struct Health {
+    score: f64,
+    last_failure: Option<Instant>,
+}
+
+struct Endpoint {
+    url: String,
+    client: Arc<RpcClient>,
+    bucket: Mutex<TokenBucket>,
+    health: Mutex<Health>,
+    calls: AtomicU64,
+    retries: AtomicU64,
+    errors: AtomicU64,
+    latency_micros: AtomicU64,
+}
+
+impl Endpoint {
+    /// The health score in [0, 1], recovering linearly towards 1 after the last failure.
+    fn score(&self, recovery_period: Duration) -> f64 {
+        let health = self.health.lock().unwrap();
+        match health.last_failure {
+            Some(last_failure) => {
+                let recovered = last_failure.elapsed().as_secs_f64()
+                    / recovery_period.as_secs_f64().max(f64::EPSILON);
+                health.score + (1.0 - health.score) * recovered.min(1.0)
+            }
+            None => health.score,
+        }
+    }
+
+    fn record_success(&self, latency: Duration) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.latency_micros.fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+
+        let mut health = self.health.lock().unwrap();
+        health.score = health.score * 0.9 + 0.1;
+    }
+
+    fn record_failure(&self, retried: bool) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        if retried {
+            self.retries.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut health = self.health.lock().unwrap();
+        health.score *= 0.5;
+        health.last_failure = Some(Instant::now());
+    }
+}
+
+/// A set of RPC endpoints used as one: calls are rate limited per endpoint, retried with
+/// exponential backoff on transient errors (rate limiting, timeouts, unhealthy nodes), and sent
+/// to the healthiest endpoint, which moves traffic to the secondaries while the primary fails.
+///
+/// Transactions and accounts are fetched in batches through the pool, see `ingest::batch`.
+pub struct RpcPool {
+    endpoints: Vec<Endpoint>,
+    config: RpcPoolConfig,
+    // Sends JSON-RPC batches, which `RpcClient` doesn't.
+    http: reqwest::Client,
+    // The fetches in flight, shared by every caller.
+    pub(crate) batches: Batches,
+}
+
+impl RpcPool {
+    /// A pool over the given endpoint urls, in order of preference.
+    pub fn new(urls: Vec<String>, config: RpcPoolConfig) -> Self {
+        RpcPool::from_clients(
+            urls.into_iter().map(|url| (url.clone(), RpcClient::new(url))).collect(),
+            config,
+        )
+    }
+
+    /// A pool over already configured clients, in order of preference.
+    pub fn from_clients(clients: Vec<(String, RpcClient)>, config: RpcPoolConfig) -> Self {
+        let endpoints = clients.into_iter()
+            .map(|(url, client)| Endpoint {
+                url,
+                client: Arc::new(client),
+                bucket: Mutex::new(TokenBucket {
+                    capacity: config.burst.max(1.0),
+                    tokens: config.burst.max(1.0),
+                    refill_per_second: config.requests_per_second.max(f64::EPSILON),
+                    last_refill: Instant::now(),
+                }),
+                health: Mutex::new(Health { score: 1.0, last_failure: None }),
+                calls: AtomicU64::new(0),
+                retries: AtomicU64::new(0),
+                errors: AtomicU64::new(0),
+                latency_micros: AtomicU64::new(0),
+            })
+            .collect();
+
+        RpcPool { endpoints, config, http: reqwest::Client::new(), batches: Batches::default() }
+    }
+
+    /// A pool over a single endpoint with the default configuration.
+    pub fn single(url: String) -> Self {
+        RpcPool::new(vec![url], RpcPoolConfig::default())
+    }
+
+    /// Runs `f` against the healthiest endpoint, retrying transient failures.
+    pub async fn call<T, F>(&self, f: F) -> ClientResult<T>
+    where
+        T: Send + 'static,
+        F: Fn(&RpcClient) -> ClientResult<T> + Send + Sync + 'static,
+    {
+        let f = Arc::new(f);
+        self.retrying(|endpoint| {
+            let (client, call) = (endpoint.client.clone(), f.clone());
+            async move {
+                tokio::task::spawn_blocking(move || call(&client))
+                    .await
+                    .map_err(|err| ClientError::from(ClientErrorKind::Custom(err.to_string())))?
+            }
+        }).await
+    }
+
+    /// Sends `requests` to the healthiest endpoint as one JSON-RPC batch, retrying transient
+    /// failures of the whole batch. The result of every request, in order, `None` for those the
+    /// node answered with an error (or not at all).
+    pub(crate) async fn send_batch(
+        &self,
+        requests: &[(RpcRequest, Value)],
+    ) -> ClientResult<Vec<Option<Value>>> {
+        let body = Value::Array(requests.iter()
+            .enumerate()
+            .map(|(id, (request, params))| request.build_request_json(id as u64, params.clone()))
+            .collect())
+            .to_string();
+        let responses = self.retrying(|endpoint| {
+            let request = self.http.post(&endpoint.url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            async move {
+                let response = request.send().await?.error_for_status()?;
+                Ok::<_, ClientError>(serde_json::from_slice::<Value>(&response.bytes().await?)?)
+            }
+        }).await?;
+
+        // Nodes refusing batches answer with a single error.
+        let responses = match responses {
+            Value::Array(responses) => responses,
+            response => return Err(ClientErrorKind::Custom(
+                format!("Unexpected response to a batch: {}", response)).into()),
+        };
+        let mut results = vec![None; requests.len()];
+        for mut response in responses {
+            let id = response.get("id").and_then(Value::as_u64).map(|id| id as usize);
+            if let Some(result) = id.and_then(|id| results.get_mut(id)) {
+                if response.get("error").is_none() {
+                    *result = response.get_mut("result").map(Value::take);
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// How many requests are batched together, see [`RpcPoolConfig::batch_size`].
+    pub fn batch_size(&self) -> usize {
+        self.config.batch_size.max(1)
+    }
+
+    /// Runs `attempt` against the healthiest endpoint, retrying transient failures.
+    async fn retrying<T, F, Fut>(&self, attempt: F) -> ClientResult<T>
+    where
+        F: Fn(&Endpoint) -> Fut,
+        Fut: Future<Output = ClientResult<T>>,
+    {
+        if self.endpoints.is_empty() {
+            return Err(ClientErrorKind::Custom("The RPC pool has no endpoints".to_string()).into());
+        }
+
+        let mut retries = 0;
+        loop {
+            let endpoint = self.pick();
+            let wait = endpoint.bucket.lock().unwrap().take();
+            if wait > Duration::from_secs(0) {
+                tokio::time::sleep(wait).await;
+            }
+
+            let started = Instant::now();
+            let result = attempt(endpoint).await;
+            #[cfg(feature = "metrics")]
+            crate::metrics::metrics().rpc_call_seconds
+                .with_label_values(&[endpoint.url.as_str()])
+                .observe(started.elapsed().as_secs_f64());
+
+            match result {
+                Ok(value) => {
+                    endpoint.record_success(started.elapsed());
+                    return Ok(value);
+                }
+                Err(err) if is_transient(&err) && retries < self.config.max_retries => {
+                    endpoint.record_failure(true);
+                    retries += 1;
+
+                    let backoff = self.backoff(retries);
+                    warn!(endpoint = %endpoint.url, attempt = retries,
+                          "[spi-wrapper/ingest/pool] Transient error from {} ({}), retry {} in \
+                    {:?}.", endpoint.url, err, retries, backoff);
+                    tokio::time::sleep(backoff).await;
+                }
+                Err(err) => {
+                    endpoint.record_failure(false);
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Call metrics for every endpoint.
+    pub fn stats(&self) -> Vec<EndpointStats> {
+        self.endpoints.iter()
+            .map(|endpoint| {
+                let calls = endpoint.calls.load(Ordering::Relaxed);
+                let latency_micros = endpoint.latency_micros.load(Ordering::Relaxed);
+                let successes = calls
+                    - endpoint.retries.load(Ordering::Relaxed)
+                    - endpoint.errors.load(Ordering::Relaxed);
+
+                EndpointStats {
+                    url: endpoint.url.clone(),
+                    calls,
+                    retries: endpoint.retries.load(Ordering::Relaxed),
+                    errors: endpoint.errors.load(Ordering::Relaxed),
+                    average_latency: Duration::from_micros(
+                        latency_micros.checked_div(successes).unwrap_or(0)),
+                    health: endpoint.score(self.config.recovery_period),
+                }
+            })
+            .collect()
+    }
+
+    /// The endpoint with the best health score, preferring earlier endpoints on ties.
+    fn pick(&self) -> &Endpoint {
+        let mut best = &self.endpoints[0];
+        let mut best_score = best.score(self.config.recovery_period);
+        for endpoint in &self.endpoints[1..] {
+            let score = endpoint.score(self.config.recovery_period);
+            if score > best_score {
+                best = endpoint;
+                best_score = score;
+            }
+        }
+
+        best
+    }
+
+    /// Exponential backoff with full jitter.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.config.base_backoff
+            .checked_mul(1 << attempt.min(16))
+            .unwrap_or(self.config.max_backoff)
+            .min(self.config.max_backoff);
+
+        exponential.mul_f64(rand::thread_rng().gen_range(0.5, 1.0))
+    }
+}
+
+/// Whether an error is worth retrying: rate limiting, timeouts, server errors and nodes that
+/// report themselves unhealthy.
+fn is_transient(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::Reqwest(err) => {
+            err.is_timeout()
+                || err.is_connect()
+                || err.status().map_or(false, |status| {
+                    status.as_u16() == 429 || status.is_server_error()
+                })
+        }
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, message, .. }) => {
+            *code == JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY || message.contains("behind")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use solana_client::rpc_config::RpcBlockConfig;
+    use solana_transaction_status::UiTransactionEncoding;
+
+    use super::*;
+
+    /// A minimal JSON-RPC server answering every request with the given status line and body.
+    fn mock_server(status: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let mut buffer = [0u8; 8192];
+                let _ = stream.read(&mut buffer);
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                    Connection: close\r\n\r\n{}", status, body.len(), body);
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        url
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn block_fetch_fails_over_to_secondary() {
+        let primary = mock_server("503 Service Unavailable", "");
+        let secondary = mock_server("200 OK", r#"{"jsonrpc":"2.0","id":1,"result":{
+            "blockhash":"4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZAMdL4VZHirAn",
+            "previousBlockhash":"4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZAMdL4VZHirAn",
+            "parentSlot":9,"transactions":[],"blockTime":null}}"#);
+
+        let pool = RpcPool::new(vec![primary, secondary], RpcPoolConfig {
+            base_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+            ..RpcPoolConfig::default()
+        });
+
+        let block = pool.call(|client| client.get_block_with_config(10, RpcBlockConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            ..RpcBlockConfig::default()
+        })).await.unwrap();
+        assert_eq!(block.parent_slot, 9);
+
+        let stats = pool.stats();
+        assert!(stats[0].retries >= 1);
+        assert_eq!(stats[1].calls, 1);
+        assert!(stats[0].health < stats[1].health);
+    }
+}
@@ -0,0 +1,669 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use solana_client::client_error::ClientErrorKind;
+use solana_client::rpc_config::RpcBlockConfig;
+use solana_client::rpc_request::RpcError;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_transaction_status::{TransactionDetails, UiConfirmedBlock, UiTransactionEncoding};
+use tokio::sync::mpsc;
+use tracing::{info, info_span, warn, Instrument};
+
+use crate::block::{decode_block_filtered, BlockRecord};
+use crate::checkpoint::{Checkpoint, Checkpointing};
+use crate::ingest::filter::DecodeFilters;
+use crate::ingest::gaps::GapTracker;
+use crate::ingest::leaders::LeaderResolver;
+use crate::ingest::pool::RpcPool;
+use crate::ingest::timestamps::TimestampResolver;
+use crate::ingest::{BlockSource, IngestError};
+use crate::registry::ProcessorRegistry;
+use crate::sinks::{self, Sink};
+use crate::stats::StatsReporter;
+
+// JSON-RPC server errors returned for slots that will never have a block.
+const JSON_RPC_SERVER_ERROR_SLOT_SKIPPED: i64 = -32007;
+const JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_SLOT_SKIPPED: i64 = -32009;
+// How many recent blockhashes are kept to find where a fork diverged from what we indexed.
+const CHAIN_HISTORY: usize = 1024;
+// How many slots with an estimated timestamp are estimated again at once.
+const TIMESTAMP_REPAIR_BATCH: usize = 500;
+
+#[derive(Clone, Debug)]
+pub struct BlockPollerConfig {
+    // The commitment blocks are fetched at. `getBlock` doesn't serve processed blocks, so this
+    // should be confirmed or finalized.
+    pub commitment: CommitmentConfig,
+    // Whether blocks read below finalized commitment are re-checked once the cluster has
+    // finalized their slot, flagging them finalized or invalidating them if they were abandoned.
+    pub track_finality: bool,
+    // How many blocks may be fetched at once. Blocks still reach the sink in slot order.
+    pub concurrency: usize,
+    // The first slot to index. Defaults to the current tip.
+    pub start_slot: Option<u64>,
+    // How long to wait before asking for new blocks once we have caught up with the tip.
+    pub poll_interval: Duration,
+    // The maximum number of slots asked for in a single `get_blocks` call.
+    pub max_slots_per_poll: u64,
+    // Where `run` checkpoints the last slot sunk, and resumes from on restart.
+    pub checkpoint: Option<Checkpointing>,
+    // What's skipped before being decoded.
+    pub filters: DecodeFilters,
+    // Whether `run` estimates the timestamps estimated before again after every poll, with the
+    // timestamp resolver set (see `BlockPoller::with_timestamp_resolver`).
+    pub repair_timestamps: bool,
+}
+
+impl Default for BlockPollerConfig {
+    fn default() -> Self {
+        BlockPollerConfig {
+            commitment: CommitmentConfig::confirmed(),
+            track_finality: false,
+            concurrency: 4,
+            start_slot: None,
+            poll_interval: Duration::from_millis(400),
+            max_slots_per_poll: 100,
+            checkpoint: None,
+            filters: DecodeFilters::default(),
+            repair_timestamps: false,
+        }
+    }
+}
+
+/// Follows the chain tip over JSON-RPC, decoding every block and pushing the results into a sink.
+///
+/// With `track_finality` the poller runs in two phases: blocks are decoded as soon as they're
+/// confirmed and written with `finalized = false`, then a follow-up pass re-checks their slots at
+/// finalized commitment and either marks them finalized or invalidates them in the sink.
+///
+/// Note: the pinned solana-client predates versioned transactions, so only legacy transactions
+/// are fetched.
+pub struct BlockPoller {
+    rpc: Arc<RpcPool>,
+    registry: Arc<ProcessorRegistry>,
+    config: BlockPollerConfig,
+    // Slots written below finalized commitment that haven't been re-checked yet.
+    pending: Mutex<BTreeSet<u64>>,
+    // The blockhashes of the latest blocks indexed, used to detect forks.
+    chain: Mutex<ChainHistory>,
+    // Where the slot ranges indexed are recorded, if gaps are tracked.
+    gaps: Option<Arc<GapTracker>>,
+    // Whether `run` has reached the tip and is waiting for new blocks.
+    caught_up: AtomicBool,
+    // Where the blocks written and the tip are reported, if anywhere.
+    stats: Option<Arc<StatsReporter>>,
+    // What estimates the time of blocks read without one, if anything.
+    timestamps: Option<Arc<TimestampResolver>>,
+    // What tags blocks with their epoch and leader, if anything.
+    leaders: Option<Arc<LeaderResolver>>,
+}
+
+impl BlockPoller {
+    pub fn new(
+        rpc: Arc<RpcPool>,
+        registry: Arc<ProcessorRegistry>,
+        config: BlockPollerConfig,
+    ) -> Self {
+        BlockPoller {
+            rpc,
+            registry,
+            config,
+            pending: Mutex::new(BTreeSet::new()),
+            chain: Mutex::new(ChainHistory::new(CHAIN_HISTORY)),
+            gaps: None,
+            caught_up: AtomicBool::new(false),
+            stats: None,
+            timestamps: None,
+            leaders: None,
+        }
+    }
+
+    /// Records every slot range indexed into `tracker`, so gaps can be found and repaired.
+    pub fn with_gap_tracker(mut self, tracker: Arc<GapTracker>) -> Self {
+        self.gaps = Some(tracker);
+        self
+    }
+
+    /// Reports the blocks written, the slot of the chain they move to and the tip into `stats`.
+    pub fn with_stats(mut self, stats: Arc<StatsReporter>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Estimates the time of the blocks read without one with `resolver`, flagging their records
+    /// as estimated, instead of leaving them at the Unix epoch.
+    pub fn with_timestamp_resolver(mut self, resolver: Arc<TimestampResolver>) -> Self {
+        self.timestamps = Some(resolver);
+        self
+    }
+
+    /// Tags every block record with the epoch and leader of its slot with `resolver`.
+    pub fn with_leader_resolver(mut self, resolver: Arc<LeaderResolver>) -> Self {
+        self.leaders = Some(resolver);
+        self
+    }
+
+    /// Whether `run` has caught up with the tip.
+    pub fn is_caught_up(&self) -> bool {
+        self.caught_up.load(Ordering::Relaxed)
+    }
+
+    /// Decodes a block, estimating its time first if it has none and a resolver is set, and
+    /// tagging it with its epoch and leader if a resolver is set.
+    async fn decode_block(
+        &self,
+        slot: u64,
+        mut block: UiConfirmedBlock,
+    ) -> Result<BlockRecord, IngestError> {
+        let estimated = match &self.timestamps {
+            Some(timestamps) => timestamps.resolve(slot, &mut block).await?,
+            None => false,
+        };
+        let mut record = decode_block_filtered(&self.registry, &self.config.filters, slot, &block);
+        record.set_timestamp_estimated(estimated);
+        if let Some(leaders) = &self.leaders {
+            leaders.enrich(&mut record).await;
+        }
+
+        Ok(record)
+    }
+
+    /// Indexes blocks from the checkpointed slot (or the configured start slot) onwards, forever.
+    pub async fn run<S: Sink + ?Sized>(&self, sink: &mut S) -> Result<(), IngestError> {
+        let checkpointed = match &self.config.checkpoint {
+            Some(checkpoint) => checkpoint.load().await?
+                .and_then(|checkpoint| checkpoint.last_slot),
+            None => None,
+        };
+        let mut next_slot = match (checkpointed, self.config.start_slot) {
+            (Some(slot), _) => slot + 1,
+            (None, Some(slot)) => slot,
+            (None, None) => self.tip().await?,
+        };
+
+        loop {
+            let tip = self.tip().await?;
+            if let Some(stats) = &self.stats {
+                stats.progress().set_tip_slot(tip);
+            }
+            #[cfg(feature = "metrics")]
+            crate::metrics::metrics().slot_lag.set((tip + 1).saturating_sub(next_slot) as i64);
+            if tip < next_slot {
+                self.caught_up.store(true, Ordering::Relaxed);
+                tokio::time::sleep(self.config.poll_interval).await;
+                continue;
+            }
+            self.caught_up.store(false, Ordering::Relaxed);
+
+            let end_slot = tip.min(next_slot + self.config.max_slots_per_poll - 1);
+            self.index_range(next_slot, end_slot, sink).await?;
+            next_slot = end_slot + 1;
+
+            if self.config.track_finality {
+                self.check_finality(sink).await?;
+            }
+            if let (Some(timestamps), true) = (&self.timestamps, self.config.repair_timestamps) {
+                timestamps.repair(sink, TIMESTAMP_REPAIR_BATCH).await?;
+            }
+
+            // `index_range` flushed the sink, so everything up to `end_slot` is safe.
+            if let Some(checkpoint) = &self.config.checkpoint {
+                checkpoint.save(&Checkpoint::new(Some(end_slot), None)).await?;
+            }
+            if let Some(gaps) = &self.gaps {
+                gaps.persist().await?;
+            }
+            if let Some(dedup) = &self.config.filters.dedup {
+                dedup.persist()?;
+            }
+        }
+    }
+
+    /// Indexes every block between `start_slot` and `end_slot` (both inclusive).
+    ///
+    /// Every block is checked against the last one indexed: when its parent blockhash doesn't
+    /// match, the cluster forked under us, so the sink is rolled back to where the fork diverged
+    /// and ingestion resumes from there.
+    ///
+    /// Returns the number of blocks written to the sink.
+    pub async fn index_range<S: Sink + ?Sized>(
+        &self,
+        start_slot: u64,
+        end_slot: u64,
+        sink: &mut S,
+    ) -> Result<usize, IngestError> {
+        let span = info_span!("index_range", start_slot, end_slot);
+        self.index_slots(start_slot, end_slot, sink, true).instrument(span).await
+    }
+
+    /// Indexes the blocks of an older range that was missed, without treating them as the new
+    /// head of the chain. Returns the number of blocks written to the sink.
+    pub async fn repair_range<S: Sink + ?Sized>(
+        &self,
+        start_slot: u64,
+        end_slot: u64,
+        sink: &mut S,
+    ) -> Result<usize, IngestError> {
+        let span = info_span!("repair_range", start_slot, end_slot);
+        self.index_slots(start_slot, end_slot, sink, false).instrument(span).await
+    }
+
+    async fn index_slots<S: Sink + ?Sized>(
+        &self,
+        start_slot: u64,
+        end_slot: u64,
+        sink: &mut S,
+        follow_chain: bool,
+    ) -> Result<usize, IngestError> {
+        let finalized = self.config.commitment.is_finalized();
+        let mut written = 0;
+        let mut next_slot = start_slot;
+
+        'range: while next_slot <= end_slot {
+            let slots = self.blocks(next_slot, end_slot, self.config.commitment).await?;
+            let mut blocks = stream::iter(slots.into_iter().map(|slot| self.fetch_block(slot)))
+                .buffered(self.config.concurrency.max(1));
+
+            while let Some(fetched) = blocks.next().await {
+                let (slot, block) = fetched?;
+                let block = match block {
+                    Some(block) => block,
+                    None => continue,
+                };
+
+                if !follow_chain {
+                    let mut record = self.decode_block(slot, block).await?;
+                    record.set_finalized(finalized);
+                    self.write_block(sink, &mut record).await?;
+                    self.record_stats(&record, false);
+                    written += 1;
+                    continue;
+                }
+
+                if let Some(fork_slot) = self.detect_reorg(slot, &block).await? {
+                    warn!("[spi-wrapper/ingest/rpc] Block {} doesn't build on the chain we \
+                    indexed, rolling back from slot {}.", slot, fork_slot);
+                    sink.rollback_from_slot(fork_slot).await?;
+                    self.chain.lock().unwrap().truncate(fork_slot);
+                    self.pending.lock().unwrap().retain(|pending| *pending < fork_slot);
+                    next_slot = fork_slot;
+                    continue 'range;
+                }
+
+                let blockhash = block.blockhash.clone();
+                let mut record = self.decode_block(slot, block).await?;
+                record.set_finalized(finalized);
+                self.write_block(sink, &mut record).await?;
+                self.record_stats(&record, true);
+                self.chain.lock().unwrap().record(slot, blockhash);
+                written += 1;
+
+                if !finalized && self.config.track_finality {
+                    self.pending.lock().unwrap().insert(slot);
+                }
+            }
+
+            break;
+        }
+
+        sinks::flush(sink).await?;
+        if let Some(gaps) = &self.gaps {
+            gaps.mark_processed(start_slot, end_slot);
+        }
+        info!("[spi-wrapper/ingest/rpc] Indexed {} blocks between slots {} and {}.",
+            written, start_slot, end_slot);
+
+        Ok(written)
+    }
+
+    /// Writes a decoded block, leaving out the transactions the sink is known to have already
+    /// (see `DedupFilter`), and remembers the ones written.
+    async fn write_block<S: Sink + ?Sized>(
+        &self,
+        sink: &mut S,
+        record: &mut BlockRecord,
+    ) -> Result<(), IngestError> {
+        let dedup = self.config.filters.dedup.as_ref();
+        if let Some(dedup) = dedup {
+            dedup.confirm(&mut record.transactions, sink).await?;
+        }
+        sinks::write_block(sink, record).await?;
+        if let Some(dedup) = dedup {
+            dedup.record(&record.transactions);
+        }
+
+        Ok(())
+    }
+
+    /// Reports a block written into the stats, moving the current slot to it when it's the new
+    /// head of the chain.
+    fn record_stats(&self, record: &BlockRecord, head: bool) {
+        let stats = match &self.stats {
+            Some(stats) => stats,
+            None => return,
+        };
+
+        if head {
+            stats.progress().set_current_slot(record.slot);
+        }
+        let instructions = record.transactions.iter()
+            .map(|transaction| transaction.instruction_sets.len())
+            .sum();
+        stats.record_block(record.transactions.len(), instructions);
+    }
+
+    /// Checks that `block` builds on the last block indexed. If it doesn't, walks its ancestors
+    /// back until one matches a block we indexed and returns the first slot to roll back.
+    async fn detect_reorg(
+        &self,
+        slot: u64,
+        block: &UiConfirmedBlock,
+    ) -> Result<Option<u64>, IngestError> {
+        if self.chain.lock().unwrap().extends(slot, &block.previous_blockhash) {
+            return Ok(None);
+        }
+
+        let mut parent_slot = block.parent_slot;
+        let mut previous_blockhash = block.previous_blockhash.clone();
+        loop {
+            let check = self.chain.lock().unwrap().fork_point(parent_slot, &previous_blockhash);
+            match check {
+                ForkCheck::ForkedAt(fork_slot) => return Ok(Some(fork_slot)),
+                ForkCheck::UnknownParent(slot) => match self.fetch_block(slot).await? {
+                    (_, Some(parent)) => {
+                        parent_slot = parent.parent_slot;
+                        previous_blockhash = parent.previous_blockhash;
+                    }
+                    // The parent isn't available at our commitment, so roll back everything
+                    // from it onwards.
+                    (_, None) => return Ok(Some(slot)),
+                },
+            }
+        }
+    }
+
+    /// Fetches every block between `start_slot` and `end_slot` (both inclusive) into `blocks`,
+    /// in slot order, e.g. to feed a [`Pipeline`](crate::ingest::pipeline::Pipeline). Sending
+    /// waits while the channel is full, so fetching slows down to the pace of the consumer.
+    ///
+    /// Unlike `index_range` this doesn't check for forks, so it's meant for finalized history.
+    /// Returns the number of blocks sent.
+    pub async fn fetch_range(
+        &self,
+        start_slot: u64,
+        end_slot: u64,
+        blocks: &mpsc::Sender<(u64, UiConfirmedBlock)>,
+    ) -> Result<usize, IngestError> {
+        let slots = self.blocks(start_slot, end_slot, self.config.commitment).await?;
+        let mut fetched_blocks = stream::iter(slots.into_iter().map(|slot| self.fetch_block(slot)))
+            .buffered(self.config.concurrency.max(1));
+
+        let mut sent = 0;
+        while let Some(fetched) = fetched_blocks.next().await {
+            if let (slot, Some(block)) = fetched? {
+                blocks.send((slot, block)).await
+                    .map_err(|_| IngestError::Pipeline("block receiver dropped".to_string()))?;
+                sent += 1;
+            }
+        }
+
+        Ok(sent)
+    }
+
+    /// Re-checks the pending slots the cluster has finalized since they were written: slots that
+    /// still have a block at finalized commitment are marked finalized, the others are
+    /// invalidated. Returns the number of slots settled.
+    pub async fn check_finality<S: Sink + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> Result<usize, IngestError> {
+        let first_pending = match self.pending.lock().unwrap().iter().next() {
+            Some(slot) => *slot,
+            None => return Ok(0),
+        };
+
+        let finalized_tip = self.tip_at(CommitmentConfig::finalized()).await?;
+        if finalized_tip < first_pending {
+            return Ok(0);
+        }
+
+        let settled: Vec<u64> = self.pending.lock().unwrap()
+            .range(..=finalized_tip)
+            .cloned()
+            .collect();
+        let end_slot = settled[settled.len() - 1];
+        let finalized: HashSet<u64> = self
+            .blocks(first_pending, end_slot, CommitmentConfig::finalized())
+            .await?
+            .into_iter()
+            .collect();
+        let (finalized_slots, abandoned_slots): (Vec<u64>, Vec<u64>) = settled.iter()
+            .cloned()
+            .partition(|slot| finalized.contains(slot));
+
+        if !abandoned_slots.is_empty() {
+            warn!("[spi-wrapper/ingest/rpc] Slots {:?} never finalized, invalidating them.",
+                abandoned_slots);
+            sink.invalidate(&abandoned_slots).await?;
+        }
+        sink.mark_finalized(&finalized_slots).await?;
+        sinks::flush(sink).await?;
+
+        let mut pending = self.pending.lock().unwrap();
+        for slot in &settled {
+            pending.remove(slot);
+        }
+
+        Ok(settled.len())
+    }
+
+    /// The latest slot at the configured commitment.
+    pub async fn tip(&self) -> Result<u64, IngestError> {
+        self.tip_at(self.config.commitment).await
+    }
+
+    async fn tip_at(&self, commitment: CommitmentConfig) -> Result<u64, IngestError> {
+        Ok(self.rpc.call(move |rpc| rpc.get_slot_with_commitment(commitment)).await?)
+    }
+
+    /// The slots that have a block between `start_slot` and `end_slot`.
+    async fn blocks(
+        &self,
+        start_slot: u64,
+        end_slot: u64,
+        commitment: CommitmentConfig,
+    ) -> Result<Vec<u64>, IngestError> {
+        Ok(self.rpc.call(move |rpc| {
+            rpc.get_blocks_with_commitment(start_slot, Some(end_slot), commitment)
+        }).await?)
+    }
+
+    /// Fetches the block at `slot`, or `None` if the slot was skipped.
+    async fn fetch_block(&self, slot: u64) -> Result<(u64, Option<UiConfirmedBlock>), IngestError> {
+        let config = RpcBlockConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            transaction_details: Some(TransactionDetails::Full),
+            rewards: Some(false),
+            commitment: Some(self.config.commitment),
+        };
+
+        let result = self.rpc.call(move |rpc| rpc.get_block_with_config(slot, config)).await;
+
+        match result {
+            Ok(block) => Ok((slot, Some(block))),
+            Err(err) if is_skipped_slot(err.kind()) => {
+                warn!("[spi-wrapper/ingest/rpc] Slot {} was skipped, moving on.", slot);
+                Ok((slot, None))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl BlockSource for BlockPoller {
+    async fn index_range(
+        &self,
+        start_slot: u64,
+        end_slot: u64,
+        sink: &mut dyn Sink,
+    ) -> Result<usize, IngestError> {
+        BlockPoller::index_range(self, start_slot, end_slot, sink).await
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ForkCheck {
+    // The fork diverged right before this slot, so everything from it onwards is stale.
+    ForkedAt(u64),
+    // The block at this slot isn't one we indexed; its own parent has to be looked at.
+    UnknownParent(u64),
+}
+
+/// The blockhashes of the most recently indexed blocks, by slot.
+struct ChainHistory {
+    blockhashes: BTreeMap<u64, String>,
+    capacity: usize,
+}
+
+impl ChainHistory {
+    fn new(capacity: usize) -> Self {
+        ChainHistory {
+            blockhashes: BTreeMap::new(),
+            capacity,
+        }
+    }
+
+    fn record(&mut self, slot: u64, blockhash: String) {
+        self.blockhashes.insert(slot, blockhash);
+        while self.blockhashes.len() > self.capacity {
+            let oldest = *self.blockhashes.keys().next().unwrap();
+            self.blockhashes.remove(&oldest);
+        }
+    }
+
+    /// Forgets every block at or after `slot`.
+    fn truncate(&mut self, slot: u64) {
+        self.blockhashes.retain(|indexed, _| *indexed < slot);
+    }
+
+    /// Whether a block at `slot` with the given parent blockhash builds on the last block
+    /// indexed. Blocks at or before the last indexed slot are being re-indexed on purpose, and
+    /// replace what we knew from there.
+    fn extends(&mut self, slot: u64, previous_blockhash: &str) -> bool {
+        match self.blockhashes.iter().next_back() {
+            Some((last_slot, _)) if slot <= *last_slot => {
+                self.truncate(slot);
+                true
+            }
+            Some((_, last_blockhash)) => last_blockhash == previous_blockhash,
+            None => true,
+        }
+    }
+
+    /// Where a chain whose block at `parent_slot` has `parent_blockhash` as its hash diverges
+    /// from the indexed one.
+    fn fork_point(&self, parent_slot: u64, parent_blockhash: &str) -> ForkCheck {
+        let oldest = match self.blockhashes.keys().next() {
+            Some(oldest) => *oldest,
+            None => return ForkCheck::ForkedAt(parent_slot + 1),
+        };
+        if parent_slot < oldest {
+            // We can't tell how deep the fork goes, so everything we remember is suspect.
+            return ForkCheck::ForkedAt(oldest);
+        }
+
+        match self.blockhashes.get(&parent_slot) {
+            Some(blockhash) if blockhash == parent_blockhash => {
+                ForkCheck::ForkedAt(parent_slot + 1)
+            }
+            _ => ForkCheck::UnknownParent(parent_slot),
+        }
+    }
+}
+
+fn is_skipped_slot(kind: &ClientErrorKind) -> bool {
+    match kind {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => {
+            *code == JSON_RPC_SERVER_ERROR_SLOT_SKIPPED
+                || *code == JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_SLOT_SKIPPED
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (slot, parent slot, blockhash, previous blockhash)
+    type FixtureBlock = (u64, u64, &'static str, &'static str);
+
+    const CANONICAL: [FixtureBlock; 3] = [
+        (10, 9, "A", "genesis"),
+        (11, 10, "B", "A"),
+        (12, 11, "C", "B"),
+    ];
+    // A fork that replaced slot 12 and built on it.
+    const FORK: [FixtureBlock; 2] = [
+        (12, 11, "C'", "B"),
+        (13, 12, "D'", "C'"),
+    ];
+
+    fn indexed(blocks: &[FixtureBlock]) -> ChainHistory {
+        let mut chain = ChainHistory::new(CHAIN_HISTORY);
+        for (slot, _, blockhash, _) in blocks {
+            chain.record(*slot, blockhash.to_string());
+        }
+
+        chain
+    }
+
+    /// Walks the fork the way the poller does, looking parents up in the fixtures.
+    fn rollback_slot(
+        chain: &mut ChainHistory,
+        block: FixtureBlock,
+        fixtures: &[FixtureBlock],
+    ) -> Option<u64> {
+        let (slot, mut parent_slot, _, mut previous_blockhash) = block;
+        if chain.extends(slot, previous_blockhash) {
+            return None;
+        }
+
+        loop {
+            match chain.fork_point(parent_slot, previous_blockhash) {
+                ForkCheck::ForkedAt(fork_slot) => return Some(fork_slot),
+                ForkCheck::UnknownParent(slot) => {
+                    let parent = fixtures.iter().find(|fixture| fixture.0 == slot).unwrap();
+                    parent_slot = parent.1;
+                    previous_blockhash = parent.3;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_chain_does_not_roll_back() {
+        let mut chain = indexed(&CANONICAL[..2]);
+        assert_eq!(rollback_slot(&mut chain, CANONICAL[2], &CANONICAL), None);
+    }
+
+    #[test]
+    fn reorged_slot_is_rolled_back() {
+        let mut chain = indexed(&CANONICAL);
+        assert_eq!(rollback_slot(&mut chain, FORK[1], &FORK), Some(12));
+
+        chain.truncate(12);
+        assert_eq!(rollback_slot(&mut chain, FORK[0], &FORK), None);
+    }
+
+    #[test]
+    fn fork_older_than_history_rolls_back_everything() {
+        let mut chain = indexed(&CANONICAL[1..]);
+        assert_eq!(chain.fork_point(9, "A"), ForkCheck::ForkedAt(11));
+    }
+}
@@ -0,0 +1,191 @@
+//! Sharing a [`Pipeline`](crate::ingest::pipeline::Pipeline) between live ingestion and a
+//! backfill, without the backfill starving the live path.
+//!
+//! A [`BlockScheduler`] takes blocks from two queues, the live one always first. Blocks reach the
+//! pipeline whole, one after the other, so both paths share its sink and dedup filter without
+//! their blocks ever being interleaved; a live block can't overtake the backfill blocks already
+//! handed over, so how many of those there may be at once (the throttle) is what bounds the live
+//! lag. The throttle is halved every time a block is written while live ingestion lags the tip
+//! by more than `max_live_lag` slots, down to a single block so that the backfill still
+//! progresses, and grows back by one block per block written once live ingestion has caught up.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use solana_transaction_status::UiConfirmedBlock;
+use tokio::sync::{mpsc, Notify};
+
+use crate::stats::SchedulerStats;
+
+/// Which of the queues of a [`BlockScheduler`] a block came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lane {
+    Live,
+    Backfill,
+}
+
+#[derive(Clone, Debug)]
+pub struct SchedulerConfig {
+    // How many backfill blocks may be in the pipeline at once while live ingestion keeps up.
+    pub backfill_concurrency: usize,
+    // How many slots behind the tip live ingestion may be before the backfill is throttled.
+    pub max_live_lag: u64,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        SchedulerConfig {
+            backfill_concurrency: 8,
+            max_live_lag: 32,
+        }
+    }
+}
+
+/// Where the scheduling of the two queues is, shared between the scheduler, the pipeline writing
+/// their blocks and whoever feeds the backfill.
+pub struct SchedulerState {
+    config: SchedulerConfig,
+    // How many backfill blocks may be in the pipeline at once, as of now.
+    throttle: AtomicUsize,
+    // How many backfill blocks are in the pipeline.
+    backfill_in_flight: AtomicUsize,
+    // How many backfill blocks are still to be written, as announced by the backfill.
+    backfill_remaining: AtomicU64,
+    // Notified every time a block is written, for the scheduler to check the throttle again.
+    written: Notify,
+}
+
+impl SchedulerState {
+    fn new(config: SchedulerConfig) -> Self {
+        let backfill_concurrency = config.backfill_concurrency.max(1);
+        SchedulerState {
+            config: SchedulerConfig { backfill_concurrency, ..config },
+            throttle: AtomicUsize::new(backfill_concurrency),
+            backfill_in_flight: AtomicUsize::new(0),
+            backfill_remaining: AtomicU64::new(0),
+            written: Notify::new(),
+        }
+    }
+
+    /// Announces `blocks` more blocks to come on the backfill queue, e.g. once the slots of the
+    /// range being backfilled are known.
+    pub fn expect_backfill(&self, blocks: u64) {
+        self.backfill_remaining.fetch_add(blocks, Ordering::Relaxed);
+    }
+
+    /// How many of the backfill blocks announced are still to be written.
+    pub fn backfill_remaining(&self) -> u64 {
+        self.backfill_remaining.load(Ordering::Relaxed)
+    }
+
+    /// How many backfill blocks may be in the pipeline at once, as of now.
+    pub fn throttle(&self) -> usize {
+        self.throttle.load(Ordering::Relaxed)
+    }
+
+    fn has_backfill_room(&self) -> bool {
+        self.backfill_in_flight.load(Ordering::Relaxed) < self.throttle()
+    }
+
+    /// Records a block of `lane` written by the pipeline, live ingestion lagging the tip by
+    /// `live_lag` slots, and adjusts the throttle.
+    pub(crate) fn block_written(&self, lane: Lane, live_lag: Option<u64>) {
+        if lane == Lane::Backfill {
+            self.backfill_in_flight.fetch_sub(1, Ordering::Relaxed);
+            // Backfill blocks that weren't announced leave nothing to count down.
+            let _ = self.backfill_remaining.fetch_update(
+                Ordering::Relaxed, Ordering::Relaxed, |remaining| remaining.checked_sub(1));
+        }
+
+        let lagging = live_lag.map_or(false, |lag| lag > self.config.max_live_lag);
+        let _ = self.throttle.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |throttle| {
+            Some(if lagging {
+                (throttle / 2).max(1)
+            } else {
+                (throttle + 1).min(self.config.backfill_concurrency)
+            })
+        });
+        self.written.notify_one();
+    }
+
+    /// The state of the scheduler, live ingestion lagging the tip by `live_lag` slots.
+    pub fn stats(&self, live_lag: Option<u64>) -> SchedulerStats {
+        SchedulerStats {
+            live_lag_slots: live_lag,
+            backfill_remaining: self.backfill_remaining(),
+            backfill_in_flight: self.backfill_in_flight.load(Ordering::Relaxed),
+            backfill_throttle: self.throttle(),
+        }
+    }
+}
+
+/// Feeds a pipeline with the blocks of a live queue and of a backfill queue (see the module
+/// documentation), to be run with
+/// [`Pipeline::run_scheduled`](crate::ingest::pipeline::Pipeline::run_scheduled).
+///
+/// Blocks of each queue are handed over in the order they came in. Whoever feeds the live queue
+/// is expected to report the tip to the pipeline, which the live lag is measured against.
+pub struct BlockScheduler {
+    live: mpsc::Receiver<(u64, UiConfirmedBlock)>,
+    backfill: mpsc::Receiver<(u64, UiConfirmedBlock)>,
+    state: Arc<SchedulerState>,
+}
+
+impl BlockScheduler {
+    pub fn new(
+        config: SchedulerConfig,
+        live: mpsc::Receiver<(u64, UiConfirmedBlock)>,
+        backfill: mpsc::Receiver<(u64, UiConfirmedBlock)>,
+    ) -> Self {
+        BlockScheduler { live, backfill, state: Arc::new(SchedulerState::new(config)) }
+    }
+
+    /// The state of the scheduler, e.g. for the backfill to announce its blocks.
+    pub fn state(&self) -> Arc<SchedulerState> {
+        self.state.clone()
+    }
+
+    /// Hands the blocks of both queues over from a background task, through a channel of
+    /// `capacity` blocks, until both queues are closed or the receiver is dropped.
+    pub(crate) fn spawn(self, capacity: usize) -> mpsc::Receiver<(Lane, u64, UiConfirmedBlock)> {
+        let BlockScheduler { mut live, mut backfill, state } = self;
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+
+        tokio::spawn(async move {
+            let (mut live_open, mut backfill_open) = (true, true);
+            while live_open || backfill_open {
+                let room = state.has_backfill_room();
+                let scheduled = tokio::select! {
+                    biased;
+                    received = live.recv(), if live_open => match received {
+                        Some((slot, block)) => Some((Lane::Live, slot, block)),
+                        None => {
+                            live_open = false;
+                            None
+                        }
+                    },
+                    received = backfill.recv(), if backfill_open && room => match received {
+                        Some((slot, block)) => {
+                            state.backfill_in_flight.fetch_add(1, Ordering::Relaxed);
+                            Some((Lane::Backfill, slot, block))
+                        }
+                        None => {
+                            backfill_open = false;
+                            None
+                        }
+                    },
+                    // Throttled: wait for a block to be written before checking again.
+                    _ = state.written.notified(), if backfill_open && !room => None,
+                };
+
+                if let Some(scheduled) = scheduled {
+                    if sender.send(scheduled).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        receiver
+    }
+}
@@ -0,0 +1,378 @@
+//! Estimating the block time of blocks read without one (older blocks, and some RPC providers
+//! return `block_time: null`), rather than recording them at the Unix epoch.
+//!
+//! A [`TimestampResolver`] interpolates the missing time between the closest blocks on either side
+//! with a known time, fetched from a [`BlockTimeSource`] on demand (and cached), or extrapolates it
+//! from the closest block on one side at the nominal slot duration when the other side isn't
+//! known yet (e.g. at the tip). The records of such blocks are flagged `timestamp_estimated`, and
+//! [`TimestampResolver::repair`] estimates them again once more blocks around them are known.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use solana_client::client_error::ClientErrorKind;
+use solana_client::rpc_request::RpcError;
+use solana_transaction_status::UiConfirmedBlock;
+use tracing::info;
+
+use crate::cache::LruCache;
+use crate::ingest::pool::RpcPool;
+use crate::ingest::IngestError;
+use crate::sinks::Sink;
+
+/// Where the block time of a slot is looked up.
+#[async_trait]
+pub trait BlockTimeSource: Send + Sync {
+    /// The block time of the block at `slot`, `None` if it has none or there's no block there.
+    async fn block_time(&self, slot: u64) -> Result<Option<i64>, IngestError>;
+}
+
+#[async_trait]
+impl BlockTimeSource for RpcPool {
+    async fn block_time(&self, slot: u64) -> Result<Option<i64>, IngestError> {
+        match self.call(move |rpc| rpc.get_block_time(slot)).await {
+            Ok(block_time) => Ok(Some(block_time)),
+            // Skipped slots, and blocks the node has no time (or no longer anything) for.
+            Err(err) if matches!(err.kind(), ClientErrorKind::RpcError(
+                RpcError::RpcResponseError { .. })) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// The new timestamp of the records of a slot, see [`Sink::update_timestamps`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimestampUpdate {
+    pub slot: u64,
+    pub timestamp: i64,
+    // Whether the timestamp is still an estimate.
+    pub estimated: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct TimestampConfig {
+    // How many slots away from a block its neighbors with a known time are looked for, on either
+    // side.
+    pub max_distance: u64,
+    // The duration of a slot, to extrapolate from the neighbors on a single side.
+    pub slot_duration: Duration,
+    // How many block times (known or not) are cached, and for how long.
+    pub cache_capacity: usize,
+    pub cache_ttl: Duration,
+}
+
+impl Default for TimestampConfig {
+    fn default() -> Self {
+        TimestampConfig {
+            max_distance: 100_000,
+            slot_duration: Duration::from_millis(400),
+            cache_capacity: 10_000,
+            cache_ttl: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Estimates the block time of blocks read without one, see the module documentation.
+pub struct TimestampResolver {
+    source: Arc<dyn BlockTimeSource>,
+    config: TimestampConfig,
+    // The block times looked up, by slot, `None` for slots without one. Those expire, for the
+    // slots beyond the tip to be looked up again.
+    cache: Mutex<LruCache<u64, Option<i64>>>,
+}
+
+impl TimestampResolver {
+    pub fn new(source: Arc<dyn BlockTimeSource>, config: TimestampConfig) -> Self {
+        let cache = LruCache::new("block_times", config.cache_capacity, Some(config.cache_ttl));
+        TimestampResolver { source, config, cache: Mutex::new(cache) }
+    }
+
+    /// Records the block time of a block read, for the blocks around it to be estimated without
+    /// looking it up.
+    pub fn record(&self, slot: u64, block_time: Option<i64>) {
+        if block_time.is_some() {
+            self.cache.lock().unwrap().insert(slot, block_time);
+        }
+    }
+
+    /// The block time of `slot`, as cached or looked up.
+    pub async fn block_time(&self, slot: u64) -> Result<Option<i64>, IngestError> {
+        if let Some(block_time) = self.cache.lock().unwrap().get(&slot) {
+            return Ok(*block_time);
+        }
+
+        let block_time = self.source.block_time(slot).await?;
+        self.cache.lock().unwrap().insert(slot, block_time);
+
+        Ok(block_time)
+    }
+
+    /// An estimate of the block time of `slot` (see the module documentation), `None` when no
+    /// block within `max_distance` slots has a known time.
+    pub async fn estimate(&self, slot: u64) -> Result<Option<i64>, IngestError> {
+        let before = self.nearest(slot, Direction::Before).await?;
+        let after = self.nearest(slot, Direction::After).await?;
+
+        Ok(interpolate(slot, before, after, self.config.slot_duration))
+    }
+
+    /// Fills the block time of `block` in with an estimate if it has none. Returns whether it
+    /// did, for the records of the block to be flagged as estimated.
+    pub async fn resolve(
+        &self,
+        slot: u64,
+        block: &mut UiConfirmedBlock,
+    ) -> Result<bool, IngestError> {
+        if block.block_time.is_some() {
+            self.record(slot, block.block_time);
+            return Ok(false);
+        }
+        // Some providers only leave the time out of the block.
+        if let Some(block_time) = self.block_time(slot).await? {
+            block.block_time = Some(block_time);
+            return Ok(false);
+        }
+
+        block.block_time = self.estimate(slot).await?;
+        Ok(block.block_time.is_some())
+    }
+
+    /// Estimates the timestamps of the records `sink` holds as estimated again, now that more
+    /// blocks around them may be known, going through them by batches of `batch_size` slots.
+    /// Records whose block now has a time of its own get it, and aren't estimated anymore.
+    /// Returns how many slots were updated.
+    pub async fn repair<S: Sink + ?Sized>(
+        &self,
+        sink: &mut S,
+        batch_size: usize,
+    ) -> Result<usize, IngestError> {
+        let mut updated = 0;
+        let mut after = None;
+        loop {
+            let estimated = sink.estimated_timestamps(after, batch_size.max(1)).await?;
+            let last_slot = match estimated.last() {
+                Some((slot, _)) => *slot,
+                None => break,
+            };
+
+            let mut updates = Vec::new();
+            for (slot, timestamp) in estimated {
+                let update = match self.source.block_time(slot).await? {
+                    Some(block_time) => TimestampUpdate {
+                        slot,
+                        timestamp: block_time,
+                        estimated: false,
+                    },
+                    None => match self.estimate(slot).await? {
+                        Some(estimate) if estimate != timestamp => TimestampUpdate {
+                            slot,
+                            timestamp: estimate,
+                            estimated: true,
+                        },
+                        _ => continue,
+                    },
+                };
+                updates.push(update);
+            }
+            if !updates.is_empty() {
+                sink.update_timestamps(&updates).await?;
+                updated += updates.len();
+            }
+            after = Some(last_slot);
+        }
+        if updated > 0 {
+            info!("[spi-wrapper/ingest/timestamps] Repaired the timestamps of {} slots.", updated);
+        }
+
+        Ok(updated)
+    }
+
+    /// The closest slot to `slot` in `direction` with a known block time, along with it.
+    ///
+    /// Slots are probed at doubling distances until one has a time, then the distance is narrowed
+    /// down between the last two probes, so that a long run of blocks without a time only takes a
+    /// few lookups to get through.
+    async fn nearest(
+        &self,
+        slot: u64,
+        direction: Direction,
+    ) -> Result<Option<(u64, i64)>, IngestError> {
+        let at = |distance: u64| match direction {
+            Direction::Before => slot.checked_sub(distance),
+            Direction::After => slot.checked_add(distance),
+        };
+
+        let (mut missed, mut found) = (0, None);
+        let mut distance = 1;
+        while distance <= self.config.max_distance {
+            let probed = match at(distance) {
+                Some(probed) => probed,
+                None => break,
+            };
+            if let Some(block_time) = self.block_time(probed).await? {
+                found = Some((distance, (probed, block_time)));
+                break;
+            }
+            missed = distance;
+            distance = distance.saturating_mul(2).min(self.config.max_distance.max(distance + 1));
+        }
+
+        let (mut distance, mut nearest) = match found {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        while distance - missed > 1 {
+            let middle = missed + (distance - missed) / 2;
+            // Within the range probed, so never out of bounds.
+            let probed = at(middle).unwrap();
+            match self.block_time(probed).await? {
+                Some(block_time) => {
+                    distance = middle;
+                    nearest = (probed, block_time);
+                }
+                None => missed = middle,
+            }
+        }
+
+        Ok(Some(nearest))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Before,
+    After,
+}
+
+/// The block time of `slot`, interpolated between the closest slots with a known time `before`
+/// and `after` it, or extrapolated from the one known at `slot_duration` per slot. `None` when
+/// neither is known.
+pub fn interpolate(
+    slot: u64,
+    before: Option<(u64, i64)>,
+    after: Option<(u64, i64)>,
+    slot_duration: Duration,
+) -> Option<i64> {
+    let seconds_per_slot = slot_duration.as_secs_f64();
+    let estimate = match (before, after) {
+        (Some((before_slot, before_time)), Some((after_slot, after_time))) => {
+            if after_slot <= before_slot {
+                return Some(before_time);
+            }
+            let progress = slot.saturating_sub(before_slot) as f64
+                / (after_slot - before_slot) as f64;
+            let estimate = before_time as f64 + (after_time - before_time) as f64 * progress;
+            // Block times only ever move forward, but a skewed neighbor mustn't send the
+            // estimate outside of the two.
+            estimate.max(before_time.min(after_time) as f64)
+                .min(before_time.max(after_time) as f64)
+        }
+        (Some((before_slot, before_time)), None) => {
+            before_time as f64 + slot.saturating_sub(before_slot) as f64 * seconds_per_slot
+        }
+        (None, Some((after_slot, after_time))) => {
+            after_time as f64 - after_slot.saturating_sub(slot) as f64 * seconds_per_slot
+        }
+        (None, None) => return None,
+    };
+
+    Some(estimate.round() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    const SLOT: Duration = Duration::from_millis(400);
+    // The slots of an epoch.
+    const EPOCH: u64 = 432_000;
+
+    #[test]
+    fn interpolates_between_neighbors() {
+        let (before, after) = (Some((100, 1_000)), Some((110, 1_004)));
+        assert_eq!(interpolate(105, before, after, SLOT), Some(1_002));
+        // Rounded to the closest second.
+        assert_eq!(interpolate(101, before, after, SLOT), Some(1_000));
+        assert_eq!(interpolate(102, before, after, SLOT), Some(1_001));
+        assert_eq!(interpolate(109, before, after, SLOT), Some(1_004));
+    }
+
+    #[test]
+    fn neighbors_at_the_slot_itself_give_their_time() {
+        assert_eq!(interpolate(100, Some((100, 1_000)), Some((110, 1_004)), SLOT), Some(1_000));
+        assert_eq!(interpolate(110, Some((100, 1_000)), Some((110, 1_004)), SLOT), Some(1_004));
+        assert_eq!(interpolate(100, Some((100, 1_000)), Some((100, 1_000)), SLOT), Some(1_000));
+        assert_eq!(interpolate(0, None, Some((0, 1_000)), SLOT), Some(1_000));
+    }
+
+    #[test]
+    fn extrapolates_from_a_single_side() {
+        assert_eq!(interpolate(110, Some((100, 1_000)), None, SLOT), Some(1_004));
+        assert_eq!(interpolate(90, None, Some((100, 1_000)), SLOT), Some(996));
+        assert_eq!(interpolate(90, None, None, SLOT), None);
+    }
+
+    #[test]
+    fn interpolates_across_an_epoch_gap() {
+        // Slots run slower than nominal across the gap, which the neighbors account for.
+        let before = Some((EPOCH - 1, 1_600_000_000));
+        let after = Some((2 * EPOCH - 1, 1_600_000_000 + 216_000));
+        assert_eq!(interpolate(EPOCH + EPOCH / 2 - 1, before, after, SLOT),
+                   Some(1_600_000_000 + 108_000));
+        assert_eq!(interpolate(EPOCH + 1, before, after, SLOT), Some(1_600_000_001));
+        // A neighbor with a skewed clock still gives an estimate between the two.
+        assert_eq!(interpolate(EPOCH, Some((EPOCH - 1, 1_000)), Some((EPOCH + 1, 990)), SLOT),
+                   Some(995));
+        assert_eq!(interpolate(u64::MAX - 1, Some((u64::MAX - 2, 1_000)),
+                               Some((u64::MAX, 1_002)), SLOT), Some(1_001));
+    }
+
+    /// A block every slot, 400ms apart, but for the `missing` ones, counting the lookups.
+    struct NominalTimes {
+        missing: Range<u64>,
+        lookups: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl BlockTimeSource for NominalTimes {
+        async fn block_time(&self, slot: u64) -> Result<Option<i64>, IngestError> {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            Ok(Some(10_000 + (slot * 2 / 5) as i64).filter(|_| !self.missing.contains(&slot)))
+        }
+    }
+
+    fn resolver(missing: Range<u64>) -> (TimestampResolver, Arc<NominalTimes>) {
+        let source = Arc::new(NominalTimes { missing, lookups: AtomicUsize::new(0) });
+        let resolver = TimestampResolver::new(source.clone(), TimestampConfig {
+            cache_ttl: Duration::from_secs(0),
+            ..TimestampConfig::default()
+        });
+        (resolver, source)
+    }
+
+    #[tokio::test]
+    async fn estimates_from_the_nearest_blocks_with_a_time() {
+        let (resolver, source) = resolver(1_001..2_000);
+        assert_eq!(resolver.estimate(1_500).await.unwrap(), Some(10_600));
+        // Nowhere near one lookup per slot of the run.
+        assert!(source.lookups.load(Ordering::Relaxed) < 50,
+                "{} lookups", source.lookups.load(Ordering::Relaxed));
+
+        let mut block: UiConfirmedBlock = serde_json::from_value(serde_json::json!({
+            "blockhash": "11111111111111111111111111111111",
+            "previousBlockhash": "11111111111111111111111111111111",
+            "parentSlot": 1_749,
+        })).unwrap();
+        assert!(resolver.resolve(1_750, &mut block).await.unwrap());
+        assert_eq!(block.block_time, Some(10_700));
+        assert!(!resolver.resolve(1_750, &mut block).await.unwrap());
+
+        // Nothing to estimate from.
+        let (resolver, _) = resolver(0..u64::MAX);
+        assert_eq!(resolver.estimate(1_500).await.unwrap(), None);
+    }
+}
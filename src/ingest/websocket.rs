@@ -0,0 +1,288 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use solana_client::pubsub_client::PubsubClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tracing::{info, warn};
+
+use crate::ingest::pool::RpcPool;
+use crate::ingest::{fetch_transaction, IngestError};
+use crate::registry::ProcessorRegistry;
+use crate::sinks::{self, Sink};
+use crate::transaction::decode_transaction;
+
+// The page size used when backfilling the gap left by a dropped subscription.
+const GAP_PAGE_LIMIT: usize = 1000;
+
+#[derive(Clone, Debug)]
+pub struct LogsSubscriberConfig {
+    // The PubSub WebSocket endpoint, e.g. wss://api.mainnet-beta.solana.com
+    pub ws_url: String,
+    // The programs to subscribe to. Each program gets its own subscription.
+    pub program_ids: Vec<String>,
+    // The commitment for both the subscription and the transaction fetches.
+    pub commitment: CommitmentConfig,
+    // How long to wait before re-subscribing after a dropped connection.
+    pub reconnect_delay: Duration,
+    // How many recently processed signatures are remembered for deduplication.
+    pub dedup_capacity: usize,
+}
+
+impl Default for LogsSubscriberConfig {
+    fn default() -> Self {
+        LogsSubscriberConfig {
+            ws_url: "wss://api.mainnet-beta.solana.com".to_string(),
+            program_ids: Vec::new(),
+            commitment: CommitmentConfig::confirmed(),
+            reconnect_delay: Duration::from_secs(1),
+            dedup_capacity: 10_000,
+        }
+    }
+}
+
+enum SubscriptionEvent {
+    Logs { program: String, slot: u64, signature: String },
+    Disconnected { program: String, reason: String },
+}
+
+/// Near-real-time ingestion over the PubSub WebSocket API.
+///
+/// Every configured program gets a `logsSubscribe` subscription; each notified signature is then
+/// fetched with `getTransaction`, decoded and pushed into the sink. When a subscription drops,
+/// it is re-established and the signatures missed in between are backfilled through
+/// `getSignaturesForAddress`. A transaction seen through several subscriptions (or through both
+/// a subscription and a backfill) is only processed once.
+///
+/// Note: `blockSubscribe` is not available in the pinned solana-client, so logs are the only
+/// subscription used.
+pub struct LogsSubscriber {
+    rpc: Arc<RpcPool>,
+    registry: Arc<ProcessorRegistry>,
+    config: LogsSubscriberConfig,
+    // The last signature processed per program, used as the lower bound of gap backfills.
+    last_signatures: HashMap<String, String>,
+    // The slot of the last processed transaction.
+    last_processed_slot: Option<u64>,
+    recent_signatures: RecentSignatures,
+}
+
+impl LogsSubscriber {
+    pub fn new(
+        rpc: Arc<RpcPool>,
+        registry: Arc<ProcessorRegistry>,
+        config: LogsSubscriberConfig,
+    ) -> Self {
+        let recent_signatures = RecentSignatures::new(config.dedup_capacity);
+
+        LogsSubscriber {
+            rpc,
+            registry,
+            config,
+            last_signatures: HashMap::new(),
+            last_processed_slot: None,
+            recent_signatures,
+        }
+    }
+
+    /// The slot of the last transaction pushed into the sink.
+    pub fn last_processed_slot(&self) -> Option<u64> {
+        self.last_processed_slot
+    }
+
+    /// Subscribes to every configured program and indexes notified transactions, forever.
+    pub async fn run<S: Sink>(&mut self, sink: &mut S) -> Result<(), IngestError> {
+        let (sender, mut receiver) = unbounded_channel();
+        for program in &self.config.program_ids {
+            self.subscribe(program.clone(), sender.clone());
+        }
+
+        while let Some(event) = receiver.recv().await {
+            match event {
+                SubscriptionEvent::Logs { program, slot, signature } => {
+                    self.index_signature(&program, slot, signature, sink).await?;
+                }
+                SubscriptionEvent::Disconnected { program, reason } => {
+                    warn!("[spi-wrapper/ingest/websocket] Subscription for {} dropped ({}), \
+                    reconnecting.", program, reason);
+                    tokio::time::sleep(self.config.reconnect_delay).await;
+
+                    self.subscribe(program.clone(), sender.clone());
+                    self.backfill_gap(&program, sink).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Opens a logs subscription for `program` on a dedicated thread, forwarding every
+    /// notification to `sender` until the connection drops.
+    fn subscribe(&self, program: String, sender: UnboundedSender<SubscriptionEvent>) {
+        let url = self.config.ws_url.clone();
+        let commitment = self.config.commitment;
+
+        thread::spawn(move || {
+            let subscription = PubsubClient::logs_subscribe(
+                &url,
+                RpcTransactionLogsFilter::Mentions(vec![program.clone()]),
+                RpcTransactionLogsConfig { commitment: Some(commitment) },
+            );
+
+            let reason = match subscription {
+                Ok((_subscription, notifications)) => {
+                    while let Ok(notification) = notifications.recv() {
+                        let event = SubscriptionEvent::Logs {
+                            program: program.clone(),
+                            slot: notification.context.slot,
+                            signature: notification.value.signature,
+                        };
+                        if sender.send(event).is_err() {
+                            // Nobody is listening anymore.
+                            return;
+                        }
+                    }
+
+                    "connection closed".to_string()
+                }
+                Err(err) => err.to_string(),
+            };
+
+            let _ = sender.send(SubscriptionEvent::Disconnected { program, reason });
+        });
+    }
+
+    /// Fetches, decodes and sinks a single notified transaction, unless it was already seen.
+    async fn index_signature<S: Sink>(
+        &mut self,
+        program: &str,
+        slot: u64,
+        signature: String,
+        sink: &mut S,
+    ) -> Result<(), IngestError> {
+        if !self.recent_signatures.insert(&signature) {
+            return Ok(());
+        }
+
+        match fetch_transaction(&self.rpc, &signature, self.config.commitment).await? {
+            Some(transaction) => {
+                let mut record = decode_transaction(&self.registry, &transaction);
+                record.finalized = self.config.commitment.is_finalized();
+                sinks::write_transactions(sink, &[record]).await?;
+            }
+            None => {
+                warn!("[spi-wrapper/ingest/websocket] Transaction {} (slot {}) is not available, \
+                skipping.", signature, slot);
+            }
+        }
+
+        self.last_signatures.insert(program.to_string(), signature);
+        self.last_processed_slot = Some(self.last_processed_slot.map_or(slot, |s| s.max(slot)));
+
+        Ok(())
+    }
+
+    /// Indexes the transactions of `program` that were confirmed while its subscription was down,
+    /// oldest first.
+    async fn backfill_gap<S: Sink>(&mut self, program: &str, sink: &mut S) -> Result<(), IngestError> {
+        let until = match self.last_signatures.get(program)
+            .and_then(|signature| Signature::from_str(signature).ok()) {
+            Some(until) => until,
+            // Nothing was processed before the drop, so there is no gap to speak of.
+            None => return Ok(()),
+        };
+        let address = match Pubkey::from_str(program) {
+            Ok(address) => address,
+            Err(_) => return Ok(()),
+        };
+
+        let mut missed = Vec::new();
+        let mut before = None;
+        loop {
+            let commitment = self.config.commitment;
+            let page = self.rpc.call(move |rpc| {
+                rpc.get_signatures_for_address_with_config(&address,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until: Some(until),
+                        limit: Some(GAP_PAGE_LIMIT),
+                        commitment: Some(commitment),
+                    })
+            }).await?;
+
+            let page_len = page.len();
+            before = page.last().and_then(|status| Signature::from_str(&status.signature).ok());
+            missed.extend(page.into_iter().map(|status| (status.slot, status.signature)));
+
+            if page_len < GAP_PAGE_LIMIT || before.is_none() {
+                break;
+            }
+        }
+
+        info!("[spi-wrapper/ingest/websocket] Backfilling {} transactions missed by {}.",
+            missed.len(), program);
+        for (slot, signature) in missed.into_iter().rev() {
+            self.index_signature(program, slot, signature, sink).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A bounded set of the most recently seen signatures.
+struct RecentSignatures {
+    capacity: usize,
+    order: VecDeque<String>,
+    seen: HashSet<String>,
+}
+
+impl RecentSignatures {
+    fn new(capacity: usize) -> Self {
+        RecentSignatures {
+            capacity: capacity.max(1),
+            order: VecDeque::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Remembers `signature`, returning whether it was new.
+    fn insert(&mut self, signature: &str) -> bool {
+        if self.seen.contains(signature) {
+            return false;
+        }
+
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.order.push_back(signature.to_string());
+        self.seen.insert(signature.to_string());
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_signatures_dedup_and_evict() {
+        let mut recent = RecentSignatures::new(2);
+        assert!(recent.insert("a"));
+        assert!(!recent.insert("a"));
+        assert!(recent.insert("b"));
+        assert!(recent.insert("c"));
+        // "a" was evicted to make room for "c".
+        assert!(recent.insert("a"));
+        assert!(!recent.insert("c"));
+    }
+}
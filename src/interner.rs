@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, RwLock};
+
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+static INTERNER: Lazy<RwLock<Interner>> = Lazy::new(|| RwLock::new(Interner::default()));
+
+/// The interned strings by symbol, read without the lock. Bucket `b` holds the `2^b` symbols
+/// from `2^b - 1` on, allocated when the first of them is interned and never moved after.
+static STRINGS: [OnceCell<Box<[OnceCell<&'static str>]>>; BUCKETS] = [EMPTY_BUCKET; BUCKETS];
+const BUCKETS: usize = 32;
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_BUCKET: OnceCell<Box<[OnceCell<&'static str>]>> = OnceCell::new();
+
+/// The bucket of `STRINGS` holding the string of symbol `id`, and its position in the bucket.
+fn slot(id: u32) -> (usize, usize) {
+    let n = id as u64 + 1;
+    let bucket = 63 - n.leading_zeros() as usize;
+
+    (bucket, (n - (1 << bucket)) as usize)
+}
+
+/// A handle to an interned string.
+///
+/// The ids of registered programs, function names and property keys are drawn from a small set
+/// of strings that repeat across every record, so records hold a 4 byte handle instead of their
+/// own copy. A symbol derefs to its string and serializes as one, so the records' serde
+/// representation is unaffected.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// The symbol of `string`, interning it if it hasn't been seen before.
+    pub fn intern(string: &str) -> Self {
+        if let Some(symbol) = Symbol::lookup(string) {
+            return symbol;
+        }
+
+        INTERNER.write().unwrap().intern(string)
+    }
+
+    /// The symbol of `string` if it has been interned, without interning it.
+    pub fn lookup(string: &str) -> Option<Self> {
+        INTERNER.read().unwrap().symbols.get(string).copied()
+    }
+
+    /// The interned string, without taking the interner's lock.
+    pub fn as_str(self) -> &'static str {
+        let (bucket, index) = slot(self.0);
+        STRINGS[bucket].get()
+            .and_then(|strings| strings[index].get())
+            .expect("symbols are only handed out once their string is stored")
+    }
+
+    /// The position of the symbol in the intern table, usable as a dictionary index.
+    pub fn id(self) -> u32 {
+        self.0
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(string: &str) -> Self {
+        Symbol::intern(string)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(string: String) -> Self {
+        Symbol::intern(&string)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Symbol::intern(&String::deserialize(deserializer)?))
+    }
+}
+
+/// The id of a program a transaction invokes. Ids already interned, those of the programs
+/// processors are registered for, are kept as their symbol. Any other program keeps its own copy
+/// of its id, dropped along with it: the programs a transaction may invoke are unbounded, so
+/// interning them would grow the intern table for as long as the process runs.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum ProgramId {
+    Interned(Symbol),
+    Unknown(Arc<str>),
+}
+
+impl ProgramId {
+    /// The id of `program`, interned only if it already is.
+    pub fn new(program: &str) -> Self {
+        match Symbol::lookup(program) {
+            Some(symbol) => ProgramId::Interned(symbol),
+            None => ProgramId::Unknown(Arc::from(program)),
+        }
+    }
+
+    /// The symbol of the program, if its id is interned.
+    pub fn symbol(&self) -> Option<Symbol> {
+        match self {
+            ProgramId::Interned(symbol) => Some(*symbol),
+            ProgramId::Unknown(_) => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            ProgramId::Interned(symbol) => symbol.as_str(),
+            ProgramId::Unknown(program) => program,
+        }
+    }
+}
+
+impl Deref for ProgramId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<Symbol> for ProgramId {
+    fn from(symbol: Symbol) -> Self {
+        ProgramId::Interned(symbol)
+    }
+}
+
+impl From<&str> for ProgramId {
+    fn from(program: &str) -> Self {
+        ProgramId::new(program)
+    }
+}
+
+impl PartialEq<str> for ProgramId {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for ProgramId {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Display for ProgramId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for ProgramId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for ProgramId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ProgramId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(ProgramId::new(&String::deserialize(deserializer)?))
+    }
+}
+
+/// Interned strings live for the rest of the process, so only strings from a bounded set
+/// (the program ids processors are registered for, function names, property keys) should be
+/// interned, never values nor the ids of whatever program a transaction invokes (see
+/// [`ProgramId`]).
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+impl Interner {
+    fn intern(&mut self, string: &str) -> Symbol {
+        // Another thread may have interned it between our lookup and taking the write lock.
+        if let Some(symbol) = self.symbols.get(string) {
+            return *symbol;
+        }
+
+        let string: &'static str = Box::leak(string.to_string().into_boxed_str());
+        let symbol = Symbol(self.strings.len() as u32);
+        let (bucket, index) = slot(symbol.0);
+        let strings = STRINGS[bucket]
+            .get_or_init(|| (0..1usize << bucket).map(|_| OnceCell::new()).collect());
+        // Stored before the symbol is handed out, so `as_str` always finds it.
+        let _ = strings[index].set(string);
+        self.strings.push(string);
+        self.symbols.insert(string, symbol);
+
+        symbol
+    }
+}
+
+/// The intern table: every string interned so far, indexed by `Symbol::id`. Columnar sinks can
+/// write it out as the dictionary of dictionary-encoded columns.
+pub fn symbol_table() -> Vec<&'static str> {
+    INTERNER.read().unwrap().strings.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interning_is_idempotent() {
+        let transfer = Symbol::intern("interner-test-transfer");
+        assert_eq!(Symbol::intern("interner-test-transfer"), transfer);
+        assert_eq!(Symbol::lookup("interner-test-transfer"), Some(transfer));
+        assert_eq!(Symbol::lookup("interner-test-unknown"), None);
+        assert_eq!(symbol_table()[transfer.id() as usize], "interner-test-transfer");
+    }
+
+    #[test]
+    fn symbols_serialize_as_strings() {
+        let symbol = Symbol::intern("interner-test-owner");
+        assert_eq!(serde_json::to_string(&symbol).unwrap(), "\"interner-test-owner\"");
+        assert_eq!(serde_json::from_str::<Symbol>("\"interner-test-owner\"").unwrap(), symbol);
+    }
+
+    #[test]
+    fn symbols_resolve_across_buckets() {
+        let symbols: Vec<_> = (0..300)
+            .map(|n| (n, Symbol::intern(&format!("interner-test-bucket-{}", n))))
+            .collect();
+        for (n, symbol) in symbols {
+            assert_eq!(symbol.as_str(), format!("interner-test-bucket-{}", n));
+        }
+        assert_eq!(slot(0), (0, 0));
+        assert_eq!(slot(1), (1, 0));
+        assert_eq!(slot(2), (1, 1));
+        assert_eq!(slot(3), (2, 0));
+        assert_eq!(slot(u32::MAX - 1), (31, (1 << 31) - 1));
+    }
+
+    #[test]
+    fn unknown_programs_are_not_interned() {
+        let registered = Symbol::intern("interner-test-registered");
+        assert_eq!(ProgramId::new("interner-test-registered"), ProgramId::Interned(registered));
+
+        let unknown = ProgramId::new("interner-test-unregistered");
+        assert_eq!(unknown.symbol(), None);
+        assert_eq!(unknown, "interner-test-unregistered");
+        assert_eq!(Symbol::lookup("interner-test-unregistered"), None);
+        assert_eq!(serde_json::to_string(&unknown).unwrap(), "\"interner-test-unregistered\"");
+    }
+}
@@ -0,0 +1,4 @@
+//! What the instructions of the token lending programs (SPL token lending and its Solend fork)
+//! add up to, beyond the instructions themselves.
+
+pub mod positions;
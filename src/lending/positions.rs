@@ -0,0 +1,849 @@
+//! Running positions of lending obligations: what every obligation has deposited in and borrowed
+//! from every reserve, over time.
+//!
+//! The [`PositionTracker`] sums the collateral tokens deposited and the liquidity tokens borrowed
+//! as the instructions move them (interest accrued in between isn't accounted for). It relies on
+//! the `obligation` and reserve properties, which the lending processors only emit when the
+//! accounts of an instruction are known, and on the token transfers an instruction invoked for
+//! the amounts its arguments don't tell: what a liquidation repaid and seized, and what went
+//! when withdrawing or repaying everything.
+//!
+//! Slots are applied in order, each once the watermark (the latest slot seen) is past it by
+//! `reorder_slots` (see [`ReorderingBuffer`]), so that the instructions of a slot are applied by
+//! their position in it whatever the order they were delivered in. Every position keeps the slot
+//! it was last changed in: a change from an older slot, delivered too late to be put back in
+//! order, is skipped rather than applied over a newer one. A snapshot of a position is emitted
+//! after every instruction that changes it.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use smallvec::{smallvec, SmallVec};
+use tracing::{debug, warn};
+
+use crate::account::AccountRecord;
+use crate::aggregate::WindowAggregate;
+use crate::block::BlockRecord;
+use crate::call_tree::execution_order;
+use crate::checkpoint::Checkpoint;
+use crate::ingest::timestamps::TimestampUpdate;
+use crate::interner::Symbol;
+use crate::network::Network;
+use crate::ordering::{ReorderingBuffer, DEFAULT_MAX_PENDING};
+use crate::program_versions::ProgramUpgrade;
+use crate::reindex::{StaleFilter, StaleTransaction};
+use crate::reprocess::{RawFilter, RawInstruction};
+use crate::sinks::{Sink, SinkError};
+use crate::transaction::TransactionRecord;
+use crate::InstructionSet;
+
+// The token instructions whose `amount` moved the tokens of a lending instruction.
+const TRANSFERS: [&str; 2] = ["transfer", "transfer-checked"];
+
+#[derive(Clone, Debug)]
+pub struct PositionsConfig {
+    // How many slots past a slot the watermark has to be for the slot to be applied.
+    pub reorder_slots: u64,
+    // How many transactions are held at most, past which the oldest slots are applied early.
+    pub max_pending: usize,
+}
+
+impl Default for PositionsConfig {
+    fn default() -> Self {
+        PositionsConfig { reorder_slots: 32, max_pending: DEFAULT_MAX_PENDING }
+    }
+}
+
+/// The position of an obligation in a reserve, right after an instruction changed it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PositionSnapshot {
+    pub network: Network,
+    // The lending program.
+    pub program: Symbol,
+    pub obligation: String,
+    pub reserve: String,
+    // The collateral tokens of the reserve deposited.
+    pub deposited_amount: u64,
+    // The liquidity tokens of the reserve borrowed.
+    pub borrowed_amount: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+    // The instruction that changed the position.
+    pub transaction_hash: String,
+    pub tx_instruction_id: i16,
+    pub parent_index: i16,
+    pub function_name: Symbol,
+}
+
+// Network, program, obligation and reserve.
+type Key = (Network, Symbol, String, String);
+
+#[derive(Clone, Copy, Default)]
+struct Position {
+    deposited: u64,
+    borrowed: u64,
+    // The slot it was last changed in.
+    slot: u64,
+}
+
+/// What an instruction changes of the position of its obligation in a reserve.
+struct Effect {
+    reserve: String,
+    deposited: i128,
+    borrowed: i128,
+}
+
+impl Effect {
+    fn deposited(reserve: &str, amount: i128) -> Effect {
+        Effect { reserve: reserve.to_string(), deposited: amount, borrowed: 0 }
+    }
+
+    fn borrowed(reserve: &str, amount: i128) -> Effect {
+        Effect { reserve: reserve.to_string(), deposited: 0, borrowed: amount }
+    }
+}
+
+/// An instruction changing positions, waiting for its slot to be applied.
+struct Change {
+    // Where it is in the execution of its transaction.
+    order: (i16, i16),
+    network: Network,
+    program: Symbol,
+    function_name: Symbol,
+    obligation: String,
+    effects: SmallVec<[Effect; 2]>,
+    timestamp: i64,
+    transaction_hash: Arc<str>,
+    tx_instruction_id: i16,
+    parent_index: i16,
+}
+
+/// The changes of a transaction, waiting for its slot to be applied.
+struct PendingTransaction {
+    transaction_hash: String,
+    changes: Vec<Change>,
+}
+
+/// The positions of the obligations of the instructions it's fed.
+pub struct PositionTracker {
+    positions: HashMap<Key, Position>,
+    // The transactions of the slots not applied yet, in the order they arrived in.
+    pending: ReorderingBuffer<PendingTransaction>,
+    skipped_changes: u64,
+}
+
+impl PositionTracker {
+    pub fn new(config: PositionsConfig) -> Self {
+        PositionTracker {
+            pending: ReorderingBuffer::new("positions", config.reorder_slots, config.max_pending),
+            positions: HashMap::new(),
+            skipped_changes: 0,
+        }
+    }
+
+    /// The latest slot seen.
+    pub fn watermark(&self) -> Option<u64> {
+        self.pending.watermark()
+    }
+
+    /// How many transactions arrived after their slot was applied, since the start.
+    pub fn late_transactions(&self) -> u64 {
+        self.pending.late_records()
+    }
+
+    /// How many changes were skipped since the start, as they were older than the last change
+    /// of their position: delivered again (e.g. replayed after a restart) or too late.
+    pub fn skipped_changes(&self) -> u64 {
+        self.skipped_changes
+    }
+
+    /// Starts over from the latest snapshots written of every position, e.g. by a previous run.
+    /// Changes to a position from the slot of its snapshot or before are skipped.
+    pub fn restore(&mut self, snapshots: &[PositionSnapshot]) {
+        self.positions.clear();
+        for snapshot in snapshots {
+            let key = (snapshot.network, snapshot.program, snapshot.obligation.clone(),
+                       snapshot.reserve.clone());
+            let position = self.positions.entry(key).or_default();
+            if snapshot.slot >= position.slot {
+                *position = Position {
+                    deposited: snapshot.deposited_amount,
+                    borrowed: snapshot.borrowed_amount,
+                    slot: snapshot.slot,
+                };
+            }
+        }
+    }
+
+    /// Holds the changes of the instructions of a transaction until its slot is applied. The
+    /// instructions of failed transactions didn't happen, they're left out.
+    pub fn observe_transaction(&mut self, transaction: &TransactionRecord) {
+        self.pending.advance(transaction.slot);
+        if !transaction.succeeded {
+            return;
+        }
+
+        let changes: Vec<_> = transaction.instruction_sets.iter()
+            .filter_map(|instruction_set| change(transaction, instruction_set))
+            .collect();
+        if changes.is_empty() {
+            return;
+        }
+        self.pending.push(transaction.slot, PendingTransaction {
+            transaction_hash: transaction.transaction_hash.clone(),
+            changes,
+        });
+    }
+
+    /// Applies the slots the watermark is past by `reorder_slots`, returning the snapshots of
+    /// the positions they changed, in order.
+    pub fn drain_ready(&mut self) -> Vec<PositionSnapshot> {
+        let ready = self.pending.drain_ready();
+        self.apply(ready)
+    }
+
+    /// Applies every slot, e.g. before shutting down.
+    pub fn drain(&mut self) -> Vec<PositionSnapshot> {
+        let pending = self.pending.drain();
+        self.apply(pending)
+    }
+
+    /// Drops the slots at or after `slot` that weren't applied yet, their blocks having been
+    /// replaced by a fork. What was applied of them is left as it was, see
+    /// [`PositionTracker::restore`].
+    pub fn rollback_from_slot(&mut self, slot: u64) {
+        self.pending.rollback_from_slot(slot);
+    }
+
+    /// Drops the given slots if they weren't applied yet, their blocks having been abandoned.
+    pub fn invalidate(&mut self, slots: &[u64]) {
+        self.pending.invalidate(slots);
+    }
+
+    fn apply(&mut self, slots: Vec<(u64, Vec<PendingTransaction>)>) -> Vec<PositionSnapshot> {
+        let mut snapshots = Vec::new();
+        for (slot, transactions) in slots {
+            // A transaction delivered twice is only applied once. The changes of the slot are
+            // applied by the order their transaction arrived in, then by where they are in it.
+            let mut delivered = HashSet::new();
+            let mut changes: Vec<_> = transactions.into_iter()
+                .filter(|transaction| delivered.insert(transaction.transaction_hash.clone()))
+                .enumerate()
+                .flat_map(|(arrived, transaction)| {
+                    transaction.changes.into_iter()
+                        .map(move |change| ((arrived, change.order), change))
+                })
+                .collect();
+            changes.sort_by_key(|(order, _)| *order);
+            // The positions changed in this slot already, which it may change again.
+            let mut changed = HashSet::new();
+            for (_, change) in changes {
+                for effect in change.effects {
+                    let key = (change.network, change.program, change.obligation.clone(),
+                               effect.reserve);
+                    let again = changed.contains(&key);
+                    let position = self.positions.entry(key.clone()).or_default();
+                    if position.slot > slot || (position.slot == slot && !again) {
+                        debug!("[spi-wrapper/positions] Skipping {} of {}, older than the last \
+                            change of obligation {}.", change.function_name,
+                            change.transaction_hash, change.obligation);
+                        self.skipped_changes += 1;
+                        continue;
+                    }
+
+                    position.deposited = add(position.deposited, effect.deposited);
+                    position.borrowed = add(position.borrowed, effect.borrowed);
+                    position.slot = slot;
+                    snapshots.push(PositionSnapshot {
+                        network: change.network,
+                        program: change.program,
+                        obligation: change.obligation.clone(),
+                        reserve: key.3.clone(),
+                        deposited_amount: position.deposited,
+                        borrowed_amount: position.borrowed,
+                        slot,
+                        timestamp: change.timestamp,
+                        transaction_hash: change.transaction_hash.to_string(),
+                        tx_instruction_id: change.tx_instruction_id,
+                        parent_index: change.parent_index,
+                        function_name: change.function_name,
+                    });
+                    changed.insert(key);
+                }
+            }
+        }
+
+        snapshots
+    }
+}
+
+/// `amount` changed by `delta`, which can't take it below 0 (repaying or withdrawing everything
+/// is asked for as `u64::MAX`).
+fn add(amount: u64, delta: i128) -> u64 {
+    (amount as i128 + delta).clamp(0, u64::MAX as i128) as u64
+}
+
+/// What `instruction_set`, an instruction of `transaction`, changes of the positions of its
+/// obligation, if it's a lending instruction that changes any.
+fn change(transaction: &TransactionRecord, instruction_set: &InstructionSet) -> Option<Change> {
+    let function = &instruction_set.function;
+    let function_name = function.function_name.as_str();
+    let argument = |key: &str| property(instruction_set, key)?.parse::<u64>().ok();
+    let reserve = |key: &str| property(instruction_set, key);
+
+    let effects: SmallVec<[Effect; 2]> = match function_name {
+        "deposit-obligation-collateral" => {
+            let amount = argument("collateral_amount")?;
+            smallvec![Effect::deposited(reserve("reserve")?, amount as i128)]
+        }
+        // The liquidity deposited is exchanged for collateral, which is then transferred.
+        "deposit-reserve-liquidity-and-obligation-collateral" => {
+            let amount = match transfers(transaction, instruction_set).last() {
+                Some(amount) => *amount,
+                None => return unknown_amount(instruction_set),
+            };
+            smallvec![Effect::deposited(reserve("reserve")?, amount as i128)]
+        }
+        "withdraw-obligation-collateral"
+        | "withdraw-obligation-collateral-and-redeem-reserve-collateral" => {
+            let amount = transfers(transaction, instruction_set).first().copied()
+                .or_else(|| argument("collateral_amount"))?;
+            smallvec![Effect::deposited(reserve("reserve")?, -(amount as i128))]
+        }
+        // Fees are borrowed too: they're transferred along with what the borrower receives.
+        "borrow-obligation-liquidity" => {
+            let amount = match argument("liquidity_amount")? {
+                u64::MAX => {
+                    let transfers = transfers(transaction, instruction_set);
+                    if transfers.is_empty() {
+                        return unknown_amount(instruction_set);
+                    }
+                    transfers.iter().map(|amount| *amount as i128).sum::<i128>()
+                }
+                amount => amount as i128,
+            };
+            smallvec![Effect::borrowed(reserve("reserve")?, amount)]
+        }
+        "repay-obligation-liquidity" => {
+            let amount = transfers(transaction, instruction_set).first().copied()
+                .or_else(|| argument("liquidity_amount"))?;
+            smallvec![Effect::borrowed(reserve("reserve")?, -(amount as i128))]
+        }
+        // The liquidator repays liquidity, then seizes collateral.
+        "liquidate-obligation" => {
+            let transfers = transfers(transaction, instruction_set);
+            let repaid = transfers.first().copied().or_else(|| argument("liquidity_amount"))?;
+            let mut effects: SmallVec<[Effect; 2]> =
+                smallvec![Effect::borrowed(reserve("repay_reserve")?, -(repaid as i128))];
+            match transfers.get(1) {
+                Some(seized) => {
+                    effects.push(Effect::deposited(reserve("withdraw_reserve")?,
+                                                   -(*seized as i128)));
+                }
+                None => {
+                    warn!("[spi-wrapper/positions] The collateral liquidation {} seized isn't \
+                        known, only what it repaid is applied.", function.transaction_hash);
+                }
+            }
+            effects
+        }
+        _ => return None,
+    };
+
+    let obligation = match property(instruction_set, "obligation") {
+        Some(obligation) => obligation.to_string(),
+        None => {
+            debug!("[spi-wrapper/positions] The accounts of {} of {} aren't known, it's left \
+                out of the positions.", function_name, function.transaction_hash);
+
+            return None;
+        }
+    };
+
+    Some(Change {
+        order: execution_order(function.tx_instruction_id, function.parent_index),
+        network: transaction.network,
+        program: function.program,
+        function_name: function.function_name,
+        obligation,
+        effects,
+        timestamp: function.timestamp,
+        transaction_hash: function.transaction_hash.clone(),
+        tx_instruction_id: function.tx_instruction_id,
+        parent_index: function.parent_index,
+    })
+}
+
+fn unknown_amount(instruction_set: &InstructionSet) -> Option<Change> {
+    warn!("[spi-wrapper/positions] The amount {} of {} moved isn't known, as the token transfers \
+        it invoked weren't decoded.", instruction_set.function.function_name,
+        instruction_set.function.transaction_hash);
+
+    None
+}
+
+/// The top level property `key` of `instruction_set`.
+fn property<'a>(instruction_set: &'a InstructionSet, key: &str) -> Option<&'a str> {
+    instruction_set.properties.iter()
+        .find(|property| property.parent_key.is_empty() && property.key.as_str() == key)
+        .map(|property| property.value.as_str())
+}
+
+/// The amounts of the token transfers `instruction_set` invoked, in order.
+fn transfers(transaction: &TransactionRecord, instruction_set: &InstructionSet) -> Vec<u64> {
+    let function = &instruction_set.function;
+    let tree = &transaction.call_tree;
+    let node = match tree.node(function.tx_instruction_id, function.parent_index) {
+        Some(node) => node,
+        None => return Vec::new(),
+    };
+
+    tree.callees(node)
+        .filter_map(|callee| {
+            transaction.instruction_sets.iter().find(|instruction_set| {
+                instruction_set.function.tx_instruction_id == callee.tx_instruction_id
+                    && instruction_set.function.parent_index == callee.parent_index
+            })
+        })
+        .filter(|callee| TRANSFERS.contains(&callee.function.function_name.as_str()))
+        .filter_map(|callee| property(callee, "amount")?.parse().ok())
+        .collect()
+}
+
+/// Feeds the transactions written to the wrapped sink to a [`PositionTracker`], writing the
+/// snapshots of the slots it applies along with them. The pending slots are applied on shutdown.
+///
+/// Tracking starts from the latest positions the wrapped sink has (see
+/// [`Sink::latest_positions`]), and starts from them again after a rollback or an invalidation,
+/// which the wrapped sink removes the snapshots of.
+pub struct PositionTrackingSink<S> {
+    inner: S,
+    tracker: PositionTracker,
+    // Whether the tracker starts from what the wrapped sink has.
+    restored: bool,
+}
+
+impl<S: Sink> PositionTrackingSink<S> {
+    pub fn new(inner: S, config: PositionsConfig) -> Self {
+        PositionTrackingSink { inner, tracker: PositionTracker::new(config), restored: false }
+    }
+
+    pub fn tracker(&self) -> &PositionTracker {
+        &self.tracker
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    async fn restore(&mut self) -> Result<(), SinkError> {
+        if !self.restored {
+            let latest = self.inner.latest_positions().await?;
+            self.tracker.restore(&latest);
+            self.restored = true;
+        }
+
+        Ok(())
+    }
+
+    async fn track(&mut self, transactions: &[TransactionRecord]) -> Result<(), SinkError> {
+        self.restore().await?;
+        for transaction in transactions {
+            self.tracker.observe_transaction(transaction);
+        }
+        let snapshots = self.tracker.drain_ready();
+        if snapshots.is_empty() {
+            return Ok(());
+        }
+
+        crate::sinks::write_positions(&mut self.inner, &snapshots).await
+    }
+}
+
+#[async_trait]
+impl<S: Sink> Sink for PositionTrackingSink<S> {
+    async fn write_block(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        self.inner.write_block(block).await?;
+        self.track(&block.transactions).await
+    }
+
+    async fn write_block_header(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        self.inner.write_block_header(block).await
+    }
+
+    async fn write_transactions(
+        &mut self,
+        transactions: &[TransactionRecord],
+    ) -> Result<(), SinkError> {
+        self.inner.write_transactions(transactions).await?;
+        self.track(transactions).await
+    }
+
+    async fn write_accounts(&mut self, accounts: &[AccountRecord]) -> Result<(), SinkError> {
+        self.inner.write_accounts(accounts).await
+    }
+
+    async fn write_aggregates(&mut self, aggregates: &[WindowAggregate]) -> Result<(), SinkError> {
+        self.inner.write_aggregates(aggregates).await
+    }
+
+    async fn write_positions(&mut self, snapshots: &[PositionSnapshot]) -> Result<(), SinkError> {
+        self.inner.write_positions(snapshots).await
+    }
+
+    async fn latest_positions(&mut self) -> Result<Vec<PositionSnapshot>, SinkError> {
+        self.inner.latest_positions().await
+    }
+
+    async fn write_program_upgrades(
+        &mut self,
+        upgrades: &[ProgramUpgrade],
+    ) -> Result<(), SinkError> {
+        self.inner.write_program_upgrades(upgrades).await
+    }
+
+    async fn program_upgrades(&mut self) -> Result<Vec<ProgramUpgrade>, SinkError> {
+        self.inner.program_upgrades().await
+    }
+
+    async fn mark_finalized(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        self.inner.mark_finalized(slots).await
+    }
+
+    async fn invalidate(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        self.inner.invalidate(slots).await?;
+        self.tracker.invalidate(slots);
+        self.restored = false;
+
+        Ok(())
+    }
+
+    async fn rollback_from_slot(&mut self, slot: u64) -> Result<(), SinkError> {
+        self.inner.rollback_from_slot(slot).await?;
+        self.tracker.rollback_from_slot(slot);
+        self.restored = false;
+
+        Ok(())
+    }
+
+    async fn has_transaction(&mut self, signature: &str) -> Result<Option<bool>, SinkError> {
+        self.inner.has_transaction(signature).await
+    }
+
+    async fn raw_instructions(
+        &mut self,
+        filter: &RawFilter,
+    ) -> Result<Vec<RawInstruction>, SinkError> {
+        self.inner.raw_instructions(filter).await
+    }
+
+    async fn write_reprocessed(
+        &mut self,
+        instruction_sets: &[InstructionSet],
+    ) -> Result<(), SinkError> {
+        self.inner.write_reprocessed(instruction_sets).await
+    }
+
+    async fn stale_transactions(
+        &mut self,
+        filter: &StaleFilter,
+    ) -> Result<Vec<StaleTransaction>, SinkError> {
+        self.inner.stale_transactions(filter).await
+    }
+
+    async fn estimated_timestamps(
+        &mut self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, i64)>, SinkError> {
+        self.inner.estimated_timestamps(after, limit).await
+    }
+
+    async fn update_timestamps(&mut self, updates: &[TimestampUpdate]) -> Result<(), SinkError> {
+        self.inner.update_timestamps(updates).await
+    }
+
+    fn supports_transactions(&self) -> bool {
+        self.inner.supports_transactions()
+    }
+
+    async fn begin(&mut self) -> Result<(), SinkError> {
+        self.inner.begin().await
+    }
+
+    async fn commit(
+        &mut self,
+        pipeline: &str,
+        checkpoint: Option<&Checkpoint>,
+    ) -> Result<(), SinkError> {
+        self.inner.commit(pipeline, checkpoint).await
+    }
+
+    async fn committed_checkpoint(
+        &mut self,
+        pipeline: &str,
+    ) -> Result<Option<Checkpoint>, SinkError> {
+        self.inner.committed_checkpoint(pipeline).await
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        self.inner.flush().await
+    }
+
+    async fn shutdown(&mut self) -> Result<(), SinkError> {
+        self.restore().await?;
+        let snapshots = self.tracker.drain();
+        if !snapshots.is_empty() {
+            crate::sinks::write_positions(&mut self.inner, &snapshots).await?;
+        }
+
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::call_tree::{CallNode, CallTree, StackHeights};
+    use crate::{InstructionFunction, InstructionProperty, PropertyType};
+
+    const OBLIGATION: &str = "obligation";
+
+    fn instruction_set(
+        (tx_instruction_id, parent_index): (i16, i16),
+        function_name: &str,
+        properties: &[(&str, String)],
+    ) -> InstructionSet {
+        let transaction_hash: Arc<str> = Arc::from("signature");
+        InstructionSet {
+            function: InstructionFunction {
+                tx_instruction_id,
+                transaction_hash: transaction_hash.clone(),
+                parent_index,
+                program: "lending".into(),
+                function_name: function_name.into(),
+                timestamp: 0,
+                network: Network::Mainnet,
+                decoder_version: 0,
+            },
+            properties: properties.iter()
+                .map(|(key, value)| InstructionProperty {
+                    tx_instruction_id,
+                    transaction_hash: transaction_hash.clone(),
+                    parent_index,
+                    key: (*key).into(),
+                    value: value.clone(),
+                    value_type: PropertyType::Unknown,
+                    parent_key: "".into(),
+                    timestamp: 0,
+                })
+                .collect(),
+            raw_data: None,
+        }
+    }
+
+    /// A lending instruction of `obligation` with its accounts known, invoking a transfer of
+    /// each of `transfers`.
+    fn lending(
+        index: i16,
+        function_name: &str,
+        arguments: &[(&str, &str)],
+        transfers: &[u64],
+    ) -> Vec<InstructionSet> {
+        let mut properties: Vec<_> = arguments.iter()
+            .map(|(key, value)| (*key, value.to_string()))
+            .collect();
+        properties.push(("obligation", OBLIGATION.to_string()));
+        let mut instruction_sets = vec![instruction_set((index, -1), function_name, &properties)];
+        instruction_sets.extend(transfers.iter().enumerate().map(|(position, amount)| {
+            instruction_set((position as i16, index), "transfer",
+                            &[("amount", amount.to_string())])
+        }));
+
+        instruction_sets
+    }
+
+    /// A transaction of the given instruction sets, each top-level one invoking the others.
+    fn transaction(
+        slot: u64,
+        signature: &str,
+        instruction_sets: Vec<InstructionSet>,
+    ) -> TransactionRecord {
+        let mut call_tree = CallTree { nodes: Vec::new(), stack_heights: StackHeights::Reported };
+        let mut calls: Vec<_> = instruction_sets.iter()
+            .map(|instruction_set| {
+                (instruction_set.function.tx_instruction_id, instruction_set.function.parent_index)
+            })
+            .collect();
+        calls.sort_by_key(|(index, parent)| execution_order(*index, *parent));
+        for (tx_instruction_id, parent_index) in calls {
+            let caller = call_tree.nodes.iter()
+                .position(|node| node.parent_index < 0 && node.tx_instruction_id == parent_index);
+            let index = call_tree.nodes.len();
+            if let Some(caller) = caller {
+                call_tree.nodes[caller].callees.push(index);
+            }
+            call_tree.nodes.push(CallNode {
+                tx_instruction_id,
+                parent_index,
+                program: "lending".into(),
+                stack_height: if caller.is_some() { 2 } else { 1 },
+                call_path: String::new(),
+                caller,
+                callees: Vec::new(),
+            });
+        }
+
+        TransactionRecord {
+            transaction_hash: signature.to_string(),
+            slot,
+            fee: 5000,
+            base_fee_lamports: None,
+            priority_fee_lamports: None,
+            compute_unit_price_micro_lamports: None,
+            succeeded: true,
+            timestamp: 0,
+            timestamp_estimated: false,
+            finalized: false,
+            network: Network::Mainnet,
+            instruction_sets,
+            decode_failures: Vec::new(),
+            call_tree,
+            signer_activity: Vec::new(),
+            account_lifecycle: Vec::new(),
+            confirmed: true,
+            simulation: None,
+        }
+    }
+
+    fn balances(snapshots: &[PositionSnapshot]) -> Vec<(u64, &str, &str, u64, u64)> {
+        snapshots.iter()
+            .map(|snapshot| (snapshot.slot, snapshot.function_name.as_str(),
+                snapshot.reserve.as_str(), snapshot.deposited_amount, snapshot.borrowed_amount))
+            .collect()
+    }
+
+    fn tracker() -> PositionTracker {
+        PositionTracker::new(PositionsConfig { reorder_slots: 0, ..PositionsConfig::default() })
+    }
+
+    #[test]
+    fn liquidations_repay_the_debt_and_seize_the_collateral() {
+        let mut tracker = tracker();
+        tracker.observe_transaction(&transaction(10, "deposit", lending(
+            0, "deposit-obligation-collateral",
+            &[("collateral_amount", "1000"), ("reserve", "sol")], &[])));
+        tracker.observe_transaction(&transaction(11, "borrow", lending(
+            0, "borrow-obligation-liquidity",
+            &[("liquidity_amount", "500"), ("reserve", "usdc")], &[495, 5])));
+        // Repays at most 400, of which 200 are taken, seizing 300 collateral tokens.
+        tracker.observe_transaction(&transaction(12, "liquidate", lending(
+            0, "liquidate-obligation",
+            &[("liquidity_amount", "400"), ("repay_reserve", "usdc"),
+              ("withdraw_reserve", "sol")],
+            &[200, 300])));
+        assert_eq!(balances(&tracker.drain_ready()), vec![
+            (10, "deposit-obligation-collateral", "sol", 1000, 0),
+            (11, "borrow-obligation-liquidity", "usdc", 0, 500),
+        ]);
+
+        assert_eq!(balances(&tracker.drain()), vec![
+            (12, "liquidate-obligation", "usdc", 0, 300),
+            (12, "liquidate-obligation", "sol", 700, 0),
+        ]);
+    }
+
+    #[test]
+    fn instructions_of_a_slot_are_applied_in_order_and_once() {
+        let mut tracker = tracker();
+        // Withdrawing everything, delivered before the deposit preceding it.
+        let everything = u64::MAX.to_string();
+        let mut instruction_sets = lending(
+            1, "withdraw-obligation-collateral",
+            &[("collateral_amount", everything.as_str()), ("reserve", "sol")], &[]);
+        instruction_sets.extend(lending(
+            0, "deposit-obligation-collateral",
+            &[("collateral_amount", "100"), ("reserve", "sol")], &[]));
+        tracker.observe_transaction(&transaction(20, "deposit-and-withdraw", instruction_sets));
+        tracker.observe_transaction(&transaction(21, "other", Vec::new()));
+
+        assert_eq!(balances(&tracker.drain_ready()), vec![
+            (20, "deposit-obligation-collateral", "sol", 100, 0),
+            (20, "withdraw-obligation-collateral", "sol", 0, 0),
+        ]);
+
+        // Delivered again, e.g. after a restart.
+        tracker.observe_transaction(&transaction(20, "deposit-and-withdraw", lending(
+            0, "deposit-obligation-collateral",
+            &[("collateral_amount", "100"), ("reserve", "sol")], &[])));
+        assert!(tracker.drain().is_empty());
+        assert_eq!(tracker.skipped_changes(), 1);
+    }
+
+    #[test]
+    fn slots_delivered_out_of_order_end_up_as_delivered_in_order() {
+        use rand::seq::SliceRandom;
+        use rand::SeedableRng;
+
+        let transactions = vec![
+            transaction(40, "deposit", lending(
+                0, "deposit-obligation-collateral",
+                &[("collateral_amount", "1000"), ("reserve", "sol")], &[])),
+            transaction(41, "borrow", lending(
+                0, "borrow-obligation-liquidity",
+                &[("liquidity_amount", "500"), ("reserve", "usdc")], &[])),
+            transaction(43, "repay", lending(
+                0, "repay-obligation-liquidity",
+                &[("liquidity_amount", "200"), ("reserve", "usdc")], &[])),
+            transaction(44, "deposit-again", lending(
+                0, "deposit-obligation-collateral",
+                &[("collateral_amount", "50"), ("reserve", "sol")], &[])),
+            transaction(46, "withdraw", lending(
+                0, "withdraw-obligation-collateral",
+                &[("collateral_amount", "300"), ("reserve", "sol")], &[])),
+            transaction(47, "borrow-again", lending(
+                0, "borrow-obligation-liquidity",
+                &[("liquidity_amount", "100"), ("reserve", "usdc")], &[])),
+        ];
+        let run = |tracker: &mut PositionTracker, order: &[usize]| {
+            let mut snapshots = Vec::new();
+            for index in order {
+                tracker.observe_transaction(&transactions[*index]);
+                snapshots.extend(tracker.drain_ready());
+            }
+            snapshots.extend(tracker.drain());
+            snapshots
+        };
+
+        let mut order: Vec<_> = (0..transactions.len()).collect();
+        let in_order = run(&mut tracker(), &order);
+        assert_eq!(balances(&in_order).last(),
+                   Some(&(47, "borrow-obligation-liquidity", "usdc", 0, 400)));
+        let mut rng = rand::rngs::StdRng::seed_from_u64(674);
+        for _ in 0..10 {
+            order.shuffle(&mut rng);
+            let config = PositionsConfig { reorder_slots: 8, ..PositionsConfig::default() };
+            let mut shuffled = PositionTracker::new(config);
+            assert_eq!(run(&mut shuffled, &order), in_order, "delivered in order {:?}", order);
+            assert_eq!(shuffled.late_transactions(), 0);
+        }
+    }
+
+    #[test]
+    fn instructions_without_accounts_or_of_failed_transactions_are_left_out() {
+        let mut tracker = tracker();
+        let deposit = instruction_set((0, -1), "deposit-obligation-collateral",
+                                      &[("collateral_amount", "100".to_string())]);
+        tracker.observe_transaction(&transaction(30, "no-accounts", vec![deposit]));
+        let mut failed = transaction(30, "failed", lending(
+            0, "deposit-obligation-collateral",
+            &[("collateral_amount", "100"), ("reserve", "sol")], &[]));
+        failed.succeeded = false;
+        tracker.observe_transaction(&failed);
+
+        assert!(tracker.drain().is_empty());
+        assert_eq!(tracker.watermark(), Some(30));
+    }
+}
@@ -1,24 +1,138 @@
+pub mod account;
+#[cfg(feature = "runtime")]
+pub mod aggregate;
+#[cfg(feature = "runtime")]
+pub mod attribution;
+#[cfg(feature = "runtime")]
+pub mod block;
+#[cfg(feature = "runtime")]
+pub mod cache;
+#[cfg(feature = "runtime")]
+pub mod call_tree;
+#[cfg(feature = "runtime")]
+pub mod checkpoint;
+#[cfg(feature = "runtime")]
+pub mod config;
+#[cfg(feature = "runtime")]
+pub mod corpus;
+pub mod data;
+#[cfg(feature = "runtime")]
+pub mod encoding;
+pub mod fast;
+#[cfg(feature = "runtime")]
+pub mod fees;
+#[cfg(feature = "runtime")]
+pub mod health;
+#[cfg(feature = "runtime")]
+pub mod ingest;
+pub mod interner;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "snapshot")]
+pub mod migrate;
+#[cfg(feature = "runtime")]
+pub mod lending;
+#[cfg(feature = "runtime")]
+pub mod lifecycle;
+#[cfg(feature = "runtime")]
+pub mod mints;
+pub mod naming;
+pub mod network;
+#[cfg(feature = "runtime")]
+pub mod ordering;
+#[cfg(feature = "notify")]
+pub mod notify;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+#[cfg(feature = "runtime")]
+pub mod program_versions;
 mod programs;
+pub mod projection;
+pub mod redaction;
+pub mod registry;
+#[cfg(feature = "runtime")]
+pub mod reindex;
+#[cfg(feature = "runtime")]
+pub mod reprocess;
+pub mod schema;
+#[cfg(feature = "runtime")]
+pub mod shutdown;
+#[cfg(feature = "runtime")]
+pub mod sinks;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+#[cfg(feature = "runtime")]
+pub mod stats;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod testing;
+#[cfg(feature = "runtime")]
+pub mod transaction;
+#[cfg(feature = "runtime")]
+pub mod verify;
+#[cfg(feature = "runtime")]
+pub mod watchdog;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+use std::sync::Arc;
 
 use serde::{Serialize, Deserialize};
+use smallvec::SmallVec;
 use solana_sdk::instruction::CompiledInstruction;
-use tokio::spawn;
-use tracing::info;
+use solana_sdk::pubkey::Pubkey;
+
+#[cfg(feature = "runtime")]
+#[allow(deprecated)]
+pub use block::process_block;
+#[cfg(feature = "runtime")]
+pub use block::{decode_block, decode_block_streaming, BlockRecord};
+#[cfg(feature = "runtime")]
+pub use call_tree::CallTree;
+pub use data::{decode_instruction_data, DataEncoding, DecodeError, EncodedContext};
+pub use fast::{FastDecode, FastInstruction, PropertyValueRef};
+#[cfg(feature = "runtime")]
+pub use encoding::{
+    instruction_context_from_ui, process_encoded_transaction, process_unconfirmed_transaction,
+    EncodingError,
+};
+pub use interner::{ProgramId, Symbol};
+pub use network::Network;
+#[cfg(feature = "layout-decoder")]
+pub use programs::layout_decoder::{LayoutDescriptor, LayoutError, LayoutProcessor};
+#[cfg(feature = "layout-decoder")]
+pub use programs::layout_registry::LayoutRegistry;
+#[cfg(feature = "phoenix")]
+pub use programs::phoenix::PhoenixMarket;
+pub use registry::{
+    BuiltinProcessor, DecodeStatus, InstructionContext, ProcessError, Processor, ProcessorRegistry,
+    ProcessorRegistryBuilder, ProgramProcessor, RawCapture,
+};
+pub use schema::{key_collisions, FunctionSchema, KeyCollision, KeySchema, ProgramSchema};
+#[cfg(feature = "runtime")]
+#[allow(deprecated)]
+pub use transaction::process_transaction;
+#[cfg(feature = "runtime")]
+pub use transaction::{
+    decode_transaction, RawTransaction, Simulation, TokenMint, TransactionRecord,
+};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Instruction {
     // The local unique identifier of the instruction according to the transaction (not based on solana)
     pub tx_instruction_id: i16,
-    // The transaction this instruction belongs to.
-    pub transaction_hash: String,
+    // The transaction this instruction belongs to, shared by every record of the transaction.
+    pub transaction_hash: Arc<str>,
     // The name of the program invoking this instruction.
-    pub program: String,
+    pub program: Symbol,
     // The data contained from invoking this instruction.
     pub data: Vec<u8>,
     // If this is an inner instruction, we should depend on this
     pub parent_index: i16,
     // The time this log was created in our time
     pub timestamp: i64,
+    // The cluster the transaction was confirmed on.
+    #[serde(default)]
+    pub network: Network,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -26,15 +140,22 @@ pub struct InstructionFunction {
     // The local unique identifier of the instruction according to the transaction (not based on solana)
     pub tx_instruction_id: i16,
     // The transaction this instruction belongs to.
-    pub transaction_hash: String,
+    pub transaction_hash: Arc<str>,
     // If this is an inner instruction, we should depend on this
     pub parent_index: i16,
     // Which program does this function belong to?
-    pub program: String,
+    pub program: Symbol,
     // Which function is this function? (Well duh)
-    pub function_name: String,
+    pub function_name: Symbol,
     // Like what it means dude.
-    pub timestamp: i64
+    pub timestamp: i64,
+    // The cluster the transaction was confirmed on.
+    #[serde(default)]
+    pub network: Network,
+    // The version of the processor that decoded the instruction, stamped by the registry (see
+    // [`ProgramProcessor::decoder_version`]). 0 for records decoded before versions were kept.
+    #[serde(default)]
+    pub decoder_version: u32,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -42,129 +163,187 @@ pub struct InstructionProperty {
     // The local unique identifier of the instruction according to the transaction (not based on solana)
     pub tx_instruction_id: i16,
     // The local unique identifier of the instruction type (not based on solana)
-    pub transaction_hash: String,
+    pub transaction_hash: Arc<str>,
     // If this is an inner instruction, we should depend on this
     pub parent_index: i16,
-    pub key: String,
+    pub key: Symbol,
     pub value: String,
-    pub parent_key: String,
+    // What the value holds, for consumers that want it typed.
+    #[serde(default)]
+    pub value_type: PropertyType,
+    pub parent_key: Symbol,
     pub timestamp: i64,
 }
 
+/// What the string value of a property holds. Values are always strings, and empty when a
+/// processor has nothing to put in them (an optional authority that isn't set).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PropertyType {
+    U64,
+    I128,
+    // A number that may be fractional or not fit in 64 bits.
+    Decimal,
+    // A base58 encoded public key.
+    Pubkey,
+    // `1` or `0`.
+    Bool,
+    Text,
+    // Encoded bytes: base58, base64 or hex, depending on the processor.
+    Bytes,
+    // A Unix timestamp, in seconds.
+    Timestamp,
+    // Not stated by the processor, e.g. properties written before types were tracked.
+    Unknown,
+}
+
+impl Default for PropertyType {
+    fn default() -> Self {
+        PropertyType::Unknown
+    }
+}
+
+impl PropertyType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PropertyType::U64 => "u64",
+            PropertyType::I128 => "i128",
+            PropertyType::Decimal => "decimal",
+            PropertyType::Pubkey => "pubkey",
+            PropertyType::Bool => "bool",
+            PropertyType::Text => "text",
+            PropertyType::Bytes => "bytes",
+            PropertyType::Timestamp => "timestamp",
+            PropertyType::Unknown => "unknown",
+        }
+    }
+
+    /// The type named `name` (see [`as_str`](Self::as_str)), unknown for any other name.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "u64" => PropertyType::U64,
+            "i128" => PropertyType::I128,
+            "decimal" => PropertyType::Decimal,
+            "pubkey" => PropertyType::Pubkey,
+            "bool" => PropertyType::Bool,
+            "text" => PropertyType::Text,
+            "bytes" => PropertyType::Bytes,
+            "timestamp" => PropertyType::Timestamp,
+            _ => PropertyType::Unknown,
+        }
+    }
+
+    /// The narrowest type `value` fits in, for properties whose processor doesn't state it.
+    /// Timestamps and booleans pass for numbers, bytes for text.
+    pub fn infer(value: &str) -> Self {
+        if value.parse::<u64>().is_ok() {
+            PropertyType::U64
+        } else if value.parse::<i128>().is_ok() {
+            PropertyType::I128
+        } else if !value.is_empty() && value.parse::<f64>().is_ok()
+            && value.chars().all(|c| c.is_ascii_digit() || c == '.' || c == '-') {
+            PropertyType::Decimal
+        } else if (32..=44).contains(&value.len()) && value.parse::<Pubkey>().is_ok() {
+            PropertyType::Pubkey
+        } else {
+            PropertyType::Text
+        }
+    }
+}
+
+/// The properties of an instruction. Most instructions have four or fewer, which are stored
+/// inline; longer lists (built with `smallvec!`) are allocated once, at their exact size.
+/// Serializes as a plain sequence.
+pub type Properties = SmallVec<[InstructionProperty; 4]>;
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct InstructionSet {
     pub function: InstructionFunction,
-    pub properties: Vec<InstructionProperty>
+    pub properties: Properties,
+    // The data of the instruction, when captured (see [`RawCapture`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_data: Option<RawData>,
+}
+
+/// The data an instruction was invoked with, kept so that its properties can be derived again
+/// (after a decoder fix) without fetching the transaction again.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawData {
+    // The data, base64 encoded.
+    pub data: String,
+    // The length of the data, in bytes.
+    pub len: usize,
+}
+
+impl RawData {
+    pub fn new(data: &[u8]) -> Self {
+        RawData { data: base64::encode(data), len: data.len() }
+    }
+
+    /// The data, decoded back from base64.
+    pub fn bytes(&self) -> Result<Vec<u8>, base64::DecodeError> {
+        base64::decode(&self.data)
+    }
 }
 
 /// Derive a simple, singular function that 'decompiles' support program instruction invocations
 /// into a database and json-compatible format based on Solana FM's instruction properties.
+pub fn decode(
+    instructions: Vec<Instruction>,
+    og_instructions: Option<Vec<CompiledInstruction>>
+) -> Vec<InstructionSet> {
+    decode_with_registry(&ProcessorRegistry::default(), instructions, og_instructions)
+}
+
+/// Same as [`decode`], decoding with the processors of the given registry only.
+pub fn decode_with_registry(
+    registry: &ProcessorRegistry,
+    instructions: Vec<Instruction>,
+    og_instructions: Option<Vec<CompiledInstruction>>
+) -> Vec<InstructionSet> {
+    instructions.into_iter()
+        .filter_map(|instruction| registry.decode(instruction, og_instructions.as_deref()))
+        .collect()
+}
+
+/// Async version of [`decode`], kept for existing callers; decoding does no I/O.
+#[deprecated(note = "decoding is synchronous, use `decode` instead")]
 pub async fn process(
     instructions: Vec<Instruction>,
     og_instructions: Option<Vec<CompiledInstruction>>
 ) -> Vec<InstructionSet> {
-    let instruction_jobs: Vec<_> = instructions.into_iter()
-        .map(|instruction| {
-            let ogi = if let Some(res) = og_instructions.clone() {
-                Some(res)
-            } else {
-                None
-            };
-
-            spawn(async move {
-                match instruction.program.as_str() {
-                    programs::native_associated_token_account::PROGRAM_ADDRESS => {
-                        crate::programs::native_associated_token_account::fragment_instruction(
-                            instruction).await
-                    },
-                    programs::native_config::PROGRAM_ADDRESS => {
-                        crate::programs::native_config::fragment_instruction(instruction)
-                            .await
-                    },
-                    programs::native_loader::PROGRAM_ADDRESS => {
-                        crate::programs::native_loader::fragment_instruction(instruction)
-                            .await
-                    },
-                    programs::bpf_loader::PROGRAM_ADDRESS |
-                    programs::bpf_loader::PROGRAM_ADDRESS_2 => {
-                        crate::programs::bpf_loader::fragment_instruction(instruction)
-                            .await
-                    },
-                    programs::bpf_loader_upgradeable::PROGRAM_ADDRESS => {
-                        crate::programs::bpf_loader_upgradeable::fragment_instruction(instruction)
-                            .await
-                    }
-                    programs::native_secp256k1::PROGRAM_ADDRESS => {
-                        if let Some(og_instructs) = ogi {
-                            crate::programs::native_secp256k1::fragment_instruction(instruction,
-                                                                                    og_instructs.as_slice())
-                                .await
-                        } else {
-                            None
-                        }
-                    }
-                    programs::native_stake::PROGRAM_ADDRESS => {
-                        crate::programs::native_stake::fragment_instruction(instruction)
-                            .await
-                    }
-                    programs::native_system::PROGRAM_ADDRESS => {
-                        crate::programs::native_system::fragment_instruction(instruction)
-                            .await
-                    }
-                    programs::native_token::PROGRAM_ADDRESS => {
-                        crate::programs::native_token::fragment_instruction(instruction)
-                            .await
-                    }
-                    programs::native_token_lending::PROGRAM_ADDRESS => {
-                        crate::programs::native_token_lending::fragment_instruction(instruction)
-                            .await
-                    }
-                    programs::native_token_swap::PROGRAM_ADDRESS => {
-                        crate::programs::native_token_swap::fragment_instruction(instruction)
-                            .await
-                    }
-                    programs::serum_market::PROGRAM_ADDRESS_V1
-                        | programs::serum_market::PROGRAM_ADDRESS_V2
-                        | programs::serum_market::PROGRAM_ADDRESS_V3 => {
-                        crate::programs::serum_market::fragment_instruction(instruction)
-                            .await
-                    }
-                    programs::native_vote::PROGRAM_ADDRESS => {
-                        crate::programs::native_vote::fragment_instruction(instruction)
-                            .await
-                    }
-                    programs::solend_token_lending::PROGRAM_ADDRESS => {
-                        crate::programs::solend_token_lending::fragment_instruction(instruction)
-                            .await
-                    }
-                    _ => {
-                        info!("Looks like this program ({}) is an unsupported one.",
-                            instruction.program.to_string());
-
-                        None
-                    }
-                }
-            })
-        })
-        .collect();
-
-    let mut instruction_sets: Vec<InstructionSet> = Vec::new();
-    for job in instruction_jobs {
-        let res = job.await;
-        if let Ok(instruction_job_result) = res {
-            if let Some(instruction_set) = instruction_job_result {
-                instruction_sets.push(instruction_set);
-            }
-        }
-    }
+    decode(instructions, og_instructions)
+}
 
-    instruction_sets
+/// Async version of [`decode_with_registry`], kept for existing callers.
+#[deprecated(note = "decoding is synchronous, use `decode_with_registry` instead")]
+pub async fn process_with_registry(
+    registry: &Arc<ProcessorRegistry>,
+    instructions: Vec<Instruction>,
+    og_instructions: Option<Vec<CompiledInstruction>>
+) -> Vec<InstructionSet> {
+    decode_with_registry(registry, instructions, og_instructions)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn property_types_are_inferred_from_values() {
+        assert_eq!(PropertyType::infer("18446744073709551615"), PropertyType::U64);
+        assert_eq!(PropertyType::infer("-5"), PropertyType::I128);
+        assert_eq!(PropertyType::infer("340282366920938463463374607431768211455"),
+                   PropertyType::Decimal);
+        assert_eq!(PropertyType::infer("0.25"), PropertyType::Decimal);
+        assert_eq!(PropertyType::infer("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"),
+                   PropertyType::Pubkey);
+        assert_eq!(PropertyType::infer("staker"), PropertyType::Text);
+        assert_eq!(PropertyType::infer(""), PropertyType::Text);
+    }
 }
\ No newline at end of file
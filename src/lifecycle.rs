@@ -0,0 +1,300 @@
+//! Deriving when accounts are created and closed, which no single instruction tells: system
+//! `create-account` creates them, token `close-account` closes token accounts, and any account
+//! drained of its lamports is closed without an instruction saying so.
+//!
+//! [`LifecycleHints`] collects what the decoded instructions of a transaction tell about its
+//! accounts, and [`derive`] combines it with the balances of the transaction: an account going
+//! from no lamports (it didn't exist) to some is created, one going to none is closed. Accounts
+//! created and closed within the transaction (a token account opened for a swap and closed right
+//! after) end up where they started, and are told by their instructions alone.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+
+use crate::network::Network;
+use crate::transaction::RawTransaction;
+use crate::InstructionSet;
+
+const SYSTEM_PROGRAM: &str = "11111111111111111111111111111111";
+const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+// The size of the data of token accounts.
+const TOKEN_ACCOUNT_LEN: u64 = 165;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleEvent {
+    Created,
+    Closed,
+}
+
+impl LifecycleEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LifecycleEvent::Created => "created",
+            LifecycleEvent::Closed => "closed",
+        }
+    }
+}
+
+/// An account a transaction created or closed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountLifecycleEvent {
+    pub account: String,
+    pub event: LifecycleEvent,
+    // The program owning the account, when its instructions tell.
+    pub owner_program: Option<String>,
+    // The lamports the account was created with, or held when closed.
+    pub lamports: u64,
+    // Whether those lamports exempt the account from rent, for the size of its data when its
+    // instructions tell (accounts of unknown size are taken to hold no data).
+    pub rent_exempt: bool,
+    // The top-level instruction the account was created or closed by, none when only the
+    // balances tell.
+    pub source_instruction_index: Option<i16>,
+    pub transaction_hash: Arc<str>,
+    pub slot: u64,
+    pub timestamp: i64,
+    #[serde(default)]
+    pub network: Network,
+}
+
+/// What the instructions of a transaction tell about one of its accounts.
+#[derive(Default)]
+struct AccountHint {
+    owner: Option<Pubkey>,
+    space: Option<u64>,
+    // The lamports the account was funded with when created.
+    lamports: Option<u64>,
+    // The top-level instructions creating and closing the account.
+    created_by: Option<i16>,
+    closed_by: Option<i16>,
+}
+
+/// What the decoded instructions of a transaction tell about the accounts it creates and closes,
+/// see the module documentation.
+#[derive(Default)]
+pub struct LifecycleHints {
+    accounts: HashMap<Pubkey, AccountHint>,
+}
+
+impl LifecycleHints {
+    /// Notes what `instruction_set`, decoded out of an instruction invoked with `accounts`, tells
+    /// about them.
+    pub fn note(&mut self, instruction_set: &InstructionSet, accounts: &[Pubkey]) {
+        let function = &instruction_set.function;
+        let index = if function.parent_index < 0 {
+            function.tx_instruction_id
+        } else {
+            function.parent_index
+        };
+        let property = |key: &str| instruction_set.properties.iter()
+            .find(|property| property.key.as_str() == key)
+            .and_then(|property| property.value.parse::<u64>().ok());
+        let owner = instruction_set.properties.iter()
+            .find(|property| property.key.as_str() == "owner")
+            .and_then(|property| property.value.parse::<Pubkey>().ok());
+        let token_program = || TOKEN_PROGRAM.parse::<Pubkey>().ok();
+
+        match (function.program.as_str(), function.function_name.as_str()) {
+            (SYSTEM_PROGRAM, "create-account") | (SYSTEM_PROGRAM, "create-account-with-seed") => {
+                if let Some(hint) = self.hint(accounts, 1) {
+                    hint.created_by = hint.created_by.or(Some(index));
+                    hint.owner = owner.or(hint.owner);
+                    hint.space = property("space").or(hint.space);
+                    hint.lamports = property("lamports").or(hint.lamports);
+                }
+            }
+            (SYSTEM_PROGRAM, "allocate") | (SYSTEM_PROGRAM, "allocate-with-seed") => {
+                if let Some(hint) = self.hint(accounts, 0) {
+                    hint.owner = owner.or(hint.owner);
+                    hint.space = property("space").or(hint.space);
+                }
+            }
+            (SYSTEM_PROGRAM, "assign") | (SYSTEM_PROGRAM, "assign-with-seed") => {
+                if let Some(hint) = self.hint(accounts, 0) {
+                    hint.owner = owner.or(hint.owner);
+                }
+            }
+            (TOKEN_PROGRAM, "initialize-account") | (TOKEN_PROGRAM, "initialize-account-2") => {
+                if let Some(hint) = self.hint(accounts, 0) {
+                    hint.owner = token_program();
+                    hint.space = Some(TOKEN_ACCOUNT_LEN);
+                }
+            }
+            (TOKEN_PROGRAM, "close-account") => {
+                if let Some(hint) = self.hint(accounts, 0) {
+                    hint.closed_by = Some(index);
+                    hint.owner = token_program();
+                    hint.space = Some(TOKEN_ACCOUNT_LEN);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn hint(&mut self, accounts: &[Pubkey], position: usize) -> Option<&mut AccountHint> {
+        Some(self.accounts.entry(*accounts.get(position)?).or_default())
+    }
+}
+
+/// The accounts `transaction` created and closed, as told by its balances and `hints` (collected
+/// out of its decoded instructions), in account order.
+pub fn derive(
+    transaction: &RawTransaction,
+    hints: &LifecycleHints,
+    network: Network,
+) -> Vec<AccountLifecycleEvent> {
+    let rent = Rent::default();
+    let transaction_hash: Arc<str> = Arc::from(transaction.transaction_hash.as_str());
+    let mut events = Vec::new();
+    for (index, account) in transaction.account_keys.iter().enumerate() {
+        // The instructions of failed transactions had no effect.
+        let hint = hints.accounts.get(account).filter(|_| transaction.succeeded);
+        let mut push = |event, lamports, source_instruction_index| {
+            let space = hint.and_then(|hint| hint.space).unwrap_or(0);
+            events.push(AccountLifecycleEvent {
+                account: account.to_string(),
+                event,
+                owner_program: hint.and_then(|hint| hint.owner).map(|owner| owner.to_string()),
+                lamports,
+                rent_exempt: rent.is_exempt(lamports, space as usize),
+                source_instruction_index,
+                transaction_hash: transaction_hash.clone(),
+                slot: transaction.slot,
+                timestamp: transaction.block_time.unwrap_or_default(),
+                network,
+            });
+        };
+
+        let balances = (transaction.pre_balances.get(index), transaction.post_balances.get(index));
+        match balances {
+            (Some(0), Some(post)) if *post > 0 => {
+                push(LifecycleEvent::Created, *post, hint.and_then(|hint| hint.created_by));
+            }
+            (Some(pre), Some(0)) if *pre > 0 => {
+                push(LifecycleEvent::Closed, *pre, hint.and_then(|hint| hint.closed_by));
+            }
+            // No change the balances tell, or no balances at all.
+            _ => if let Some(hint) = hint {
+                let lamports = hint.lamports.unwrap_or(0);
+                if let Some(created_by) = hint.created_by {
+                    push(LifecycleEvent::Created, lamports, Some(created_by));
+                }
+                if let Some(closed_by) = hint.closed_by {
+                    push(LifecycleEvent::Closed, lamports, Some(closed_by));
+                }
+            },
+        }
+    }
+
+    events
+}
+
+// The fixtures are created by system and token instructions, which have to be decoded.
+#[cfg(all(test, feature = "system", feature = "token"))]
+mod tests {
+    use solana_sdk::instruction::CompiledInstruction;
+    use solana_sdk::{system_instruction, system_program};
+    use solana_transaction_status::InnerInstructions;
+    use spl_token::instruction::TokenInstruction;
+
+    use super::*;
+    use crate::registry::ProcessorRegistry;
+    use crate::transaction::decode_transaction;
+
+    const ATA_PROGRAM: &str = "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
+    // The rent exempt minimum of a token account.
+    const TOKEN_ACCOUNT_RENT: u64 = 2_039_280;
+
+    /// A wallet creating its associated token account of a mint, then closing it right away, the
+    /// rent going back to the wallet.
+    fn create_and_close_ata(succeeded: bool) -> (RawTransaction, Pubkey) {
+        let (wallet, ata, mint) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (system, token) = (system_program::id(), spl_token::id());
+        let create_account = system_instruction::create_account(
+            &wallet, &ata, TOKEN_ACCOUNT_RENT, TOKEN_ACCOUNT_LEN, &token);
+        let transaction = RawTransaction {
+            slot: 42,
+            block_time: Some(1_700_000_000),
+            transaction_hash: "ata".to_string(),
+            account_keys: vec![wallet, ata, mint, system, token, ATA_PROGRAM.parse().unwrap()],
+            num_required_signatures: 1,
+            instructions: vec![
+                // The associated token account program creates the account through CPI.
+                CompiledInstruction { program_id_index: 5, accounts: vec![0, 1, 0, 2, 3, 4],
+                                      data: vec![] },
+                CompiledInstruction { program_id_index: 4, accounts: vec![1, 0, 0],
+                                      data: TokenInstruction::CloseAccount.pack() },
+            ],
+            inner_instructions: vec![InnerInstructions {
+                index: 0,
+                instructions: vec![
+                    CompiledInstruction { program_id_index: 3, accounts: vec![0, 1],
+                                          data: create_account.data },
+                    CompiledInstruction { program_id_index: 4, accounts: vec![1, 2, 0],
+                                          data: TokenInstruction::InitializeAccount.pack() },
+                ],
+            }],
+            token_mints: vec![],
+            fee: 5000,
+            succeeded,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![10_000_000, 0, 1_461_600, 1, 1, 1],
+            post_balances: vec![10_000_000 - 5000, 0, 1_461_600, 1, 1, 1],
+        };
+
+        (transaction, ata)
+    }
+
+    #[test]
+    fn an_ata_created_and_closed_at_once_is_told_by_its_instructions() {
+        let (transaction, ata) = create_and_close_ata(true);
+        let record = decode_transaction(&ProcessorRegistry::default(), &transaction);
+
+        let events: Vec<_> = record.account_lifecycle.iter()
+            .map(|event| (event.account.clone(), event.event, event.lamports, event.rent_exempt,
+                          event.source_instruction_index))
+            .collect();
+        assert_eq!(events, vec![
+            (ata.to_string(), LifecycleEvent::Created, TOKEN_ACCOUNT_RENT, true, Some(0)),
+            (ata.to_string(), LifecycleEvent::Closed, TOKEN_ACCOUNT_RENT, true, Some(1)),
+        ]);
+        for event in &record.account_lifecycle {
+            assert_eq!(event.owner_program.as_deref(), Some(TOKEN_PROGRAM));
+            assert_eq!((event.slot, event.timestamp), (42, 1_700_000_000));
+        }
+
+        // Nothing happened to the account of a failed transaction.
+        let (transaction, _) = create_and_close_ata(false);
+        let record = decode_transaction(&ProcessorRegistry::default(), &transaction);
+        assert!(record.account_lifecycle.is_empty());
+    }
+
+    #[test]
+    fn balances_tell_what_instructions_dont() {
+        let (mut transaction, ata) = create_and_close_ata(true);
+        // The account outlives the transaction, and the wallet is drained without a word.
+        transaction.instructions.truncate(1);
+        transaction.post_balances = vec![0, TOKEN_ACCOUNT_RENT, 1_461_600, 1, 1, 1];
+        let record = decode_transaction(&ProcessorRegistry::default(), &transaction);
+
+        let events: Vec<_> = record.account_lifecycle.iter()
+            .map(|event| (event.account.clone(), event.event, event.lamports,
+                          event.source_instruction_index, event.owner_program.clone()))
+            .collect();
+        assert_eq!(events, vec![
+            (transaction.account_keys[0].to_string(), LifecycleEvent::Closed, 10_000_000, None,
+             None),
+            (ata.to_string(), LifecycleEvent::Created, TOKEN_ACCOUNT_RENT, Some(0),
+             Some(TOKEN_PROGRAM.to_string())),
+        ]);
+        // A wallet holding no data is exempt from rent with far less than a token account.
+        assert!(record.account_lifecycle[0].rent_exempt);
+    }
+}
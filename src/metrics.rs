@@ -0,0 +1,331 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use tracing::warn;
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+/// Every metric the crate records, registered in a registry of their own.
+///
+/// The processor registry, the ingestion sources (and their writes to the sink) and the RPC pool
+/// record into it as soon as the `metrics` feature is enabled; it only has to be exposed, through
+/// [`serve`] or by gathering [`registry`] into an existing endpoint.
+pub struct Metrics {
+    registry: Registry,
+    // Instructions decoded, by program and function.
+    pub instructions_decoded: IntCounterVec,
+    // Instructions of a supported program that couldn't be decoded, by program and first byte of
+    // their data.
+    pub decode_failures: IntCounterVec,
+    // Instructions not decoded as their transaction was sampled out, by program. Dividing by the
+    // sample rate re-scales the totals.
+    pub instructions_sampled_out: IntCounterVec,
+    // Properties decoded then dropped by the projection, by program and function.
+    pub properties_projected_out: IntCounterVec,
+    // Reloads of the layout descriptors, by result (reloaded, rejected).
+    pub layout_reloads: IntCounterVec,
+    // Dispatches to a processor over their budget, by program and budget (soft, hard), and
+    // whether the program is degraded for it (see `watchdog`).
+    pub dispatch_budget_violations: IntCounterVec,
+    pub processors_degraded: IntGaugeVec,
+    pub blocks_processed: IntCounter,
+    // Transactions skipped without being decoded, by filter (votes, accounts, mints).
+    pub skipped_by_filter: IntCounterVec,
+    // Instructions skipped without being decoded, by filter (programs, mints).
+    pub instructions_skipped_by_filter: IntCounterVec,
+    // Calls to the sink, by kind of write (block, header, transactions, accounts, flush).
+    pub sink_writes: IntCounterVec,
+    pub sink_write_errors: IntCounterVec,
+    // Instruction sets routed by a `sinks::router::SinkRouter`, by route (drop for those dropped).
+    pub route_records: IntCounterVec,
+    // Writes to a route of the router that failed, by route and kind of write.
+    pub route_write_errors: IntCounterVec,
+    // Writes a route holds on to, its sink having failed them, by route.
+    pub route_pending_writes: IntGaugeVec,
+    pub block_processing_seconds: Histogram,
+    pub sink_flush_seconds: Histogram,
+    // RPC calls, by endpoint. Retried calls are observed once per attempt.
+    pub rpc_call_seconds: HistogramVec,
+    // RPC calls spared by batching and sharing fetches in flight, by method (see
+    // `ingest::batch`).
+    pub rpc_requests_saved: IntCounterVec,
+    // How many slots the block poller is behind the tip of the chain.
+    pub slot_lag: IntGauge,
+    // The rest are published from the stats of the pipeline (see `stats::StatsReporter`).
+    pub current_slot: IntGauge,
+    pub tip_slot: IntGauge,
+    pub lag_seconds: Gauge,
+    // Rolling rates, by window (1m, 5m).
+    pub instructions_per_second: GaugeVec,
+    pub transactions_per_second: GaugeVec,
+    // Transactions waiting to be written, by sink.
+    pub sink_queue_depth: IntGaugeVec,
+    // Lookups, evictions and entries of the bounded caches, by cache (see `cache::LruCache`).
+    pub cache_hits: IntCounterVec,
+    pub cache_misses: IntCounterVec,
+    pub cache_evictions: IntCounterVec,
+    pub cache_entries: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let metrics = Metrics {
+            instructions_decoded: IntCounterVec::new(
+                Opts::new("spi_instructions_decoded_total", "Instructions decoded."),
+                &["program", "function"],
+            ).unwrap(),
+            decode_failures: IntCounterVec::new(
+                Opts::new("spi_decode_failures_total",
+                          "Instructions of a supported program that couldn't be decoded."),
+                &["program", "discriminant"],
+            ).unwrap(),
+            instructions_sampled_out: IntCounterVec::new(
+                Opts::new("spi_instructions_sampled_out_total",
+                          "Instructions not decoded as their transaction was sampled out."),
+                &["program"],
+            ).unwrap(),
+            properties_projected_out: IntCounterVec::new(
+                Opts::new("spi_properties_projected_out_total",
+                          "Properties decoded then dropped by the projection."),
+                &["program", "function"],
+            ).unwrap(),
+            layout_reloads: IntCounterVec::new(
+                Opts::new("spi_layout_reloads_total", "Reloads of the layout descriptors."),
+                &["result"],
+            ).unwrap(),
+            dispatch_budget_violations: IntCounterVec::new(
+                Opts::new("spi_dispatch_budget_violations_total",
+                          "Dispatches to a processor over their budget."),
+                &["program", "budget"],
+            ).unwrap(),
+            processors_degraded: IntGaugeVec::new(
+                Opts::new("spi_processor_degraded",
+                          "Whether the instructions of a program are kept raw, its processor \
+                          being too slow."),
+                &["program"],
+            ).unwrap(),
+            blocks_processed: IntCounter::new("spi_blocks_processed_total", "Blocks decoded.")
+                .unwrap(),
+            skipped_by_filter: IntCounterVec::new(
+                Opts::new("spi_transactions_skipped_by_filter_total",
+                          "Transactions skipped without being decoded."),
+                &["filter"],
+            ).unwrap(),
+            instructions_skipped_by_filter: IntCounterVec::new(
+                Opts::new("spi_instructions_skipped_by_filter_total",
+                          "Instructions skipped without being decoded."),
+                &["filter"],
+            ).unwrap(),
+            sink_writes: IntCounterVec::new(
+                Opts::new("spi_sink_writes_total", "Writes made to the sink."),
+                &["kind"],
+            ).unwrap(),
+            sink_write_errors: IntCounterVec::new(
+                Opts::new("spi_sink_write_errors_total", "Writes to the sink that failed."),
+                &["kind"],
+            ).unwrap(),
+            route_records: IntCounterVec::new(
+                Opts::new("spi_route_records_total", "Instruction sets routed to a sink."),
+                &["route"],
+            ).unwrap(),
+            route_write_errors: IntCounterVec::new(
+                Opts::new("spi_route_write_errors_total", "Writes to a route that failed."),
+                &["route", "kind"],
+            ).unwrap(),
+            route_pending_writes: IntGaugeVec::new(
+                Opts::new("spi_route_pending_writes",
+                          "Writes a route holds on to until its sink takes them."),
+                &["route"],
+            ).unwrap(),
+            block_processing_seconds: Histogram::with_opts(HistogramOpts::new(
+                "spi_block_processing_seconds", "Time taken to decode a block.",
+            )).unwrap(),
+            sink_flush_seconds: Histogram::with_opts(HistogramOpts::new(
+                "spi_sink_flush_seconds", "Time taken to flush the sink.",
+            )).unwrap(),
+            rpc_call_seconds: HistogramVec::new(
+                HistogramOpts::new("spi_rpc_call_seconds", "Time taken by RPC calls."),
+                &["endpoint"],
+            ).unwrap(),
+            rpc_requests_saved: IntCounterVec::new(
+                Opts::new("spi_rpc_requests_saved_total",
+                          "RPC calls spared by batching and sharing fetches in flight."),
+                &["method"],
+            ).unwrap(),
+            slot_lag: IntGauge::new("spi_slot_lag",
+                                    "Slots between the tip of the chain and the last one indexed.")
+                .unwrap(),
+            current_slot: IntGauge::new("spi_current_slot",
+                                        "The last slot fully written to the sink.").unwrap(),
+            tip_slot: IntGauge::new("spi_tip_slot", "The tip of the chain.").unwrap(),
+            lag_seconds: Gauge::new("spi_lag_seconds",
+                                    "How far behind the tip of the chain ingestion is, in time.")
+                .unwrap(),
+            instructions_per_second: GaugeVec::new(
+                Opts::new("spi_instructions_per_second", "Instructions written per second."),
+                &["window"],
+            ).unwrap(),
+            transactions_per_second: GaugeVec::new(
+                Opts::new("spi_transactions_per_second", "Transactions written per second."),
+                &["window"],
+            ).unwrap(),
+            sink_queue_depth: IntGaugeVec::new(
+                Opts::new("spi_sink_queue_depth", "Transactions waiting to be written."),
+                &["sink"],
+            ).unwrap(),
+            cache_hits: IntCounterVec::new(
+                Opts::new("spi_cache_hits_total", "Lookups answered by a cache."),
+                &["cache"],
+            ).unwrap(),
+            cache_misses: IntCounterVec::new(
+                Opts::new("spi_cache_misses_total", "Lookups a cache couldn't answer."),
+                &["cache"],
+            ).unwrap(),
+            cache_evictions: IntCounterVec::new(
+                Opts::new("spi_cache_evictions_total",
+                          "Entries dropped from a cache to make room or for being stale."),
+                &["cache"],
+            ).unwrap(),
+            cache_entries: IntGaugeVec::new(
+                Opts::new("spi_cache_entries", "Entries held by a cache."),
+                &["cache"],
+            ).unwrap(),
+            registry,
+        };
+
+        let collectors: Vec<Box<dyn prometheus::core::Collector>> = vec![
+            Box::new(metrics.instructions_decoded.clone()),
+            Box::new(metrics.decode_failures.clone()),
+            Box::new(metrics.instructions_sampled_out.clone()),
+            Box::new(metrics.properties_projected_out.clone()),
+            Box::new(metrics.layout_reloads.clone()),
+            Box::new(metrics.dispatch_budget_violations.clone()),
+            Box::new(metrics.processors_degraded.clone()),
+            Box::new(metrics.blocks_processed.clone()),
+            Box::new(metrics.skipped_by_filter.clone()),
+            Box::new(metrics.instructions_skipped_by_filter.clone()),
+            Box::new(metrics.sink_writes.clone()),
+            Box::new(metrics.sink_write_errors.clone()),
+            Box::new(metrics.route_records.clone()),
+            Box::new(metrics.route_write_errors.clone()),
+            Box::new(metrics.route_pending_writes.clone()),
+            Box::new(metrics.block_processing_seconds.clone()),
+            Box::new(metrics.sink_flush_seconds.clone()),
+            Box::new(metrics.rpc_call_seconds.clone()),
+            Box::new(metrics.rpc_requests_saved.clone()),
+            Box::new(metrics.slot_lag.clone()),
+            Box::new(metrics.current_slot.clone()),
+            Box::new(metrics.tip_slot.clone()),
+            Box::new(metrics.lag_seconds.clone()),
+            Box::new(metrics.instructions_per_second.clone()),
+            Box::new(metrics.transactions_per_second.clone()),
+            Box::new(metrics.sink_queue_depth.clone()),
+            Box::new(metrics.cache_hits.clone()),
+            Box::new(metrics.cache_misses.clone()),
+            Box::new(metrics.cache_evictions.clone()),
+            Box::new(metrics.cache_entries.clone()),
+        ];
+        for collector in collectors {
+            metrics.registry.register(collector).unwrap();
+        }
+
+        metrics
+    }
+}
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// The registry holding every metric of the crate.
+pub fn registry() -> &'static Registry {
+    &METRICS.registry
+}
+
+/// Every metric in the Prometheus text format.
+pub fn gather() -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&registry().gather(), &mut buffer).unwrap();
+
+    String::from_utf8(buffer).unwrap()
+}
+
+/// Serves the metrics on `GET /metrics` at `address` from a background thread, for as long as
+/// the process runs. Returns the address actually bound (useful with port 0).
+pub fn serve<A: ToSocketAddrs>(address: A) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(address)?;
+    let address = listener.local_addr()?;
+
+    thread::Builder::new()
+        .name("spi-metrics".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.and_then(respond);
+                if let Err(err) = result {
+                    warn!("[spi-wrapper/metrics] Failed to serve a metrics request: {}", err);
+                }
+            }
+        })?;
+
+    Ok(address)
+}
+
+fn respond(mut stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers, we don't need any of them.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let (status, content_type, body) = if path == "/metrics" {
+        ("200 OK", TextEncoder::new().format_type().to_string(), gather())
+    } else {
+        ("404 Not Found", "text/plain".to_string(), "Not found\n".to_string())
+    };
+
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+    Connection: close\r\n\r\n{}", status, content_type, body.len(), body)?;
+    stream.flush()
+}
+
+pub(crate) fn record_sink_write<T, E>(kind: &str, result: &Result<T, E>) {
+    METRICS.sink_writes.with_label_values(&[kind]).inc();
+    if result.is_err() {
+        METRICS.sink_write_errors.with_label_values(&[kind]).inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn serves_the_metrics() {
+        metrics().instructions_decoded.with_label_values(&["test-program", "transfer"]).inc();
+        let address = serve("127.0.0.1:0").unwrap();
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains(
+            "spi_instructions_decoded_total{function=\"transfer\",program=\"test-program\"}"));
+    }
+}
@@ -0,0 +1,372 @@
+//! Moves what was indexed from one sink to another without decoding it again, through snapshots
+//! (see [`snapshot`](crate::snapshot)).
+//!
+//! [`export`] reads the transactions of a slot range back from a sink, along with the functions
+//! and properties of their instructions (see [`Sink::read_transactions`]), and writes them to a
+//! directory: one snapshot per chunk of slots, named after them (`<first slot>-<last slot>.spis`).
+//! Chunks are read a page at a time and streamed to their snapshot, which is only renamed into
+//! place once complete, so that an interrupted export resumes at the first chunk missing (given
+//! the same chunk size).
+//!
+//! [`import`] writes the snapshots of a directory to a sink, a page at a time. Records keep their
+//! natural keys (network, signature and instruction indexes), so importing into a sink holding
+//! some of them already, or importing twice, replaces rows rather than duplicating them.
+//!
+//! Both then compare, chunk by chunk, the rows of the snapshot with those the sink holds of the
+//! chunk's slots, when it can tell (see [`Sink::row_counts`]). Only what sinks read back moves:
+//! what was derived from transactions while indexing them (blocks, signer activity, account
+//! lifecycle, positions, aggregates) is derived again by indexing, not migrated.
+
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+use tracing::info;
+
+use crate::sinks::{self, ReadFilter, RowCounts, Sink, SinkError};
+use crate::snapshot::{SnapshotError, SnapshotReader, SnapshotWriter};
+use crate::transaction::TransactionRecord;
+
+/// Errors that may be returned while exporting or importing records.
+#[derive(Debug, Error)]
+pub enum MigrateError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Sink failed: {0}")]
+    Sink(#[from] SinkError),
+    #[error("Snapshot error: {0}")]
+    Snapshot(#[from] SnapshotError),
+    #[error("{0} isn't named after the slots it holds (<first slot>-<last slot>.spis)")]
+    UnnamedSnapshot(PathBuf),
+}
+
+#[derive(Clone, Debug)]
+pub struct MigrateConfig {
+    // How many slots the snapshot of each chunk spans.
+    pub chunk_slots: u64,
+    // How many transactions are read from or written to the sink at a time.
+    pub batch_size: usize,
+}
+
+impl Default for MigrateConfig {
+    fn default() -> Self {
+        MigrateConfig {
+            chunk_slots: 10_000,
+            batch_size: 500,
+        }
+    }
+}
+
+/// A chunk of slots exported or imported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkSummary {
+    // The slots of the chunk, both inclusive.
+    pub first_slot: u64,
+    pub last_slot: u64,
+    // The rows of its snapshot.
+    pub rows: RowCounts,
+    // The rows the sink holds of its slots, when it can tell.
+    pub sink_rows: Option<RowCounts>,
+    // Whether the chunk was exported by an earlier run already.
+    pub resumed: bool,
+}
+
+impl ChunkSummary {
+    /// Whether the sink holds other rows of the chunk's slots than its snapshot.
+    pub fn mismatched(&self) -> bool {
+        self.sink_rows.map_or(false, |sink_rows| sink_rows != self.rows)
+    }
+}
+
+/// What came of an export or an import, chunk by chunk in slot order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MigrateSummary {
+    pub chunks: Vec<ChunkSummary>,
+}
+
+impl MigrateSummary {
+    /// The rows of every chunk.
+    pub fn rows(&self) -> RowCounts {
+        self.chunks.iter().fold(RowCounts::default(), |mut total, chunk| {
+            total.transactions += chunk.rows.transactions;
+            total.functions += chunk.rows.functions;
+            total.properties += chunk.rows.properties;
+            total
+        })
+    }
+
+    /// The chunks whose rows differ between their snapshot and the sink.
+    pub fn mismatches(&self) -> impl Iterator<Item = &ChunkSummary> {
+        self.chunks.iter().filter(|chunk| chunk.mismatched())
+    }
+
+    /// How many chunks the sink couldn't count the rows of.
+    pub fn unverified(&self) -> usize {
+        self.chunks.iter().filter(|chunk| chunk.sink_rows.is_none()).count()
+    }
+}
+
+/// Exports the transactions `sink` holds of `slots` to snapshots in `dir` (created if needed),
+/// skipping the chunks exported there already.
+pub async fn export<S: Sink + ?Sized>(
+    sink: &mut S,
+    slots: RangeInclusive<u64>,
+    dir: &Path,
+    config: &MigrateConfig,
+) -> Result<MigrateSummary, MigrateError> {
+    fs::create_dir_all(dir)?;
+    let chunk_slots = config.chunk_slots.max(1);
+
+    let mut summary = MigrateSummary::default();
+    let mut first_slot = *slots.start();
+    while first_slot <= *slots.end() {
+        let last_slot = first_slot.saturating_add(chunk_slots - 1).min(*slots.end());
+        let path = dir.join(chunk_name(first_slot, last_slot));
+        let resumed = path.exists();
+        let rows = if resumed {
+            count_rows(&path)?
+        } else {
+            export_chunk(sink, first_slot, last_slot, &path, config.batch_size.max(1)).await?
+        };
+        let sink_rows = sink.row_counts(first_slot, last_slot).await?;
+        info!("[spi-wrapper/migrate] Exported {} transactions of slots {} to {}{}.",
+            rows.transactions, first_slot, last_slot, if resumed { " (resumed)" } else { "" });
+        summary.chunks.push(ChunkSummary { first_slot, last_slot, rows, sink_rows, resumed });
+
+        if last_slot == u64::MAX {
+            break;
+        }
+        first_slot = last_slot + 1;
+    }
+
+    Ok(summary)
+}
+
+/// Streams the transactions `sink` holds of the slots of a chunk to a snapshot at `path`.
+async fn export_chunk<S: Sink + ?Sized>(
+    sink: &mut S,
+    first_slot: u64,
+    last_slot: u64,
+    path: &Path,
+    batch_size: usize,
+) -> Result<RowCounts, MigrateError> {
+    let partial = path.with_extension("spis.partial");
+    let mut writer = SnapshotWriter::<TransactionRecord>::create(&partial)?;
+    let mut rows = RowCounts::default();
+    let mut filter = ReadFilter { first_slot, last_slot, after: None, limit: batch_size };
+    loop {
+        let transactions = sink.read_transactions(&filter).await?;
+        for transaction in &transactions {
+            writer.write(transaction)?;
+            rows.add(transaction);
+        }
+
+        match transactions.last() {
+            Some(last) if transactions.len() >= filter.limit => {
+                filter.after = Some((last.slot, last.transaction_hash.clone()));
+            }
+            _ => break,
+        }
+    }
+    writer.finish()?;
+    // Only complete snapshots are taken for exported chunks on resume.
+    fs::rename(&partial, path)?;
+
+    Ok(rows)
+}
+
+/// Imports the snapshots exported to `dir` into `sink`, in slot order.
+pub async fn import<S: Sink + ?Sized>(
+    dir: &Path,
+    sink: &mut S,
+    config: &MigrateConfig,
+) -> Result<MigrateSummary, MigrateError> {
+    let mut chunks = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        // Leaves out the snapshots of interrupted exports.
+        if path.extension().map_or(true, |extension| extension != "spis") {
+            continue;
+        }
+        let (first_slot, last_slot) = chunk_slots(&path)
+            .ok_or_else(|| MigrateError::UnnamedSnapshot(path.clone()))?;
+        chunks.push((first_slot, last_slot, path));
+    }
+    chunks.sort();
+
+    let batch_size = config.batch_size.max(1);
+    let mut summary = MigrateSummary::default();
+    for (first_slot, last_slot, path) in chunks {
+        let mut rows = RowCounts::default();
+        let mut batch = Vec::with_capacity(batch_size);
+        for transaction in SnapshotReader::<TransactionRecord>::open(&path)? {
+            let transaction = transaction?;
+            rows.add(&transaction);
+            batch.push(transaction);
+            if batch.len() >= batch_size {
+                sinks::write_transactions(sink, &batch).await?;
+                batch.clear();
+            }
+        }
+        if !batch.is_empty() {
+            sinks::write_transactions(sink, &batch).await?;
+        }
+        sinks::flush(sink).await?;
+
+        let sink_rows = sink.row_counts(first_slot, last_slot).await?;
+        info!("[spi-wrapper/migrate] Imported {} transactions of slots {} to {}.",
+            rows.transactions, first_slot, last_slot);
+        summary.chunks.push(ChunkSummary { first_slot, last_slot, rows, sink_rows,
+                                           resumed: false });
+    }
+
+    Ok(summary)
+}
+
+/// The name of the snapshot of a chunk.
+fn chunk_name(first_slot: u64, last_slot: u64) -> String {
+    format!("{}-{}.spis", first_slot, last_slot)
+}
+
+/// The slots of the chunk whose snapshot is at `path`, as told by its name.
+fn chunk_slots(path: &Path) -> Option<(u64, u64)> {
+    let name = path.file_stem()?.to_str()?;
+    let (first_slot, last_slot) = name.split_once('-')?;
+    let (first_slot, last_slot) = (first_slot.parse().ok()?, last_slot.parse().ok()?);
+
+    Some((first_slot, last_slot)).filter(|(first_slot, last_slot)| first_slot <= last_slot)
+}
+
+/// The rows of the snapshot at `path`.
+fn count_rows(path: &Path) -> Result<RowCounts, MigrateError> {
+    let mut rows = RowCounts::default();
+    for transaction in SnapshotReader::<TransactionRecord>::open(path)? {
+        rows.add(&transaction?);
+    }
+
+    Ok(rows)
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use smallvec::smallvec;
+
+    use super::*;
+    use crate::call_tree::CallTree;
+    use crate::sinks::sqlite::SqliteSink;
+    use crate::{InstructionFunction, InstructionProperty, InstructionSet, Network, PropertyType};
+
+    fn transaction(n: u64) -> TransactionRecord {
+        let transaction_hash = format!("{:0>88}", n);
+        let function = InstructionFunction {
+            tx_instruction_id: 0,
+            transaction_hash: transaction_hash.as_str().into(),
+            parent_index: -1,
+            program: "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA".into(),
+            function_name: "transfer".into(),
+            timestamp: 1_700_000_000,
+            network: Network::Mainnet,
+            decoder_version: 1,
+        };
+        let property = |key: &str, value: String| InstructionProperty {
+            tx_instruction_id: 0,
+            transaction_hash: function.transaction_hash.clone(),
+            parent_index: -1,
+            key: key.into(),
+            value,
+            value_type: PropertyType::U64,
+            parent_key: "".into(),
+            timestamp: 1_700_000_000,
+        };
+        let properties = smallvec![property("amount", (n * 1_000).to_string()),
+                                   property("decimals", "6".to_string())];
+
+        TransactionRecord {
+            transaction_hash,
+            // Three transactions a slot.
+            slot: 100 + n / 3,
+            fee: 5000,
+            base_fee_lamports: Some(5000),
+            priority_fee_lamports: Some(0),
+            compute_unit_price_micro_lamports: None,
+            succeeded: true,
+            timestamp: 1_700_000_000,
+            timestamp_estimated: false,
+            finalized: true,
+            network: Network::Mainnet,
+            instruction_sets: vec![InstructionSet { function, properties, raw_data: None }],
+            decode_failures: Vec::new(),
+            call_tree: CallTree::default(),
+            signer_activity: Vec::new(),
+            account_lifecycle: Vec::new(),
+            confirmed: true,
+            simulation: None,
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("spi-migrate-{}-{}", name,
+                                                     std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        let _ = fs::remove_file(&path);
+
+        path
+    }
+
+    fn as_json(transactions: &[TransactionRecord]) -> Vec<serde_json::Value> {
+        transactions.iter()
+            .map(|transaction| serde_json::to_value(transaction).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn records_move_between_sinks_chunk_by_chunk() {
+        let (source_path, target_path) = (temp_path("source"), temp_path("target"));
+        let dir = temp_path("snapshots");
+        let transactions: Vec<_> = (0..30).map(transaction).collect();
+        let mut source = SqliteSink::open(&source_path).unwrap();
+        source.write_transactions(&transactions).await.unwrap();
+        let config = MigrateConfig { chunk_slots: 4, batch_size: 5 };
+
+        // Slots 100 to 109, one of the chunks being exported by an interrupted run already.
+        export_chunk(&mut source, 104, 107, &dir.join("104-107.spis"), 5).await.unwrap();
+        let exported = export(&mut source, 100..=109, &dir, &config).await.unwrap();
+        let chunks: Vec<_> = exported.chunks.iter()
+            .map(|chunk| (chunk.first_slot, chunk.last_slot, chunk.rows.transactions,
+                          chunk.resumed))
+            .collect();
+        assert_eq!(chunks, vec![(100, 103, 12, false), (104, 107, 12, true), (108, 109, 6, false)]);
+        assert_eq!(exported.rows(), RowCounts { transactions: 30, functions: 30, properties: 60 });
+        assert_eq!(exported.mismatches().count(), 0);
+
+        // Importing twice leaves a single copy of every row.
+        let mut target = SqliteSink::open(&target_path).unwrap();
+        import(&dir, &mut target, &config).await.unwrap();
+        let imported = import(&dir, &mut target, &config).await.unwrap();
+        assert_eq!(imported.rows(), exported.rows());
+        assert_eq!(imported.mismatches().count(), 0);
+        assert_eq!(imported.unverified(), 0);
+
+        let read = target.read_transactions(&ReadFilter {
+            first_slot: 0,
+            last_slot: u64::MAX,
+            after: None,
+            limit: 100,
+        }).await.unwrap();
+        assert_eq!(as_json(&read), as_json(&transactions));
+
+        // The target holding more of a chunk's slots than its snapshot shows.
+        let mut extra = transaction(30);
+        extra.slot = 109;
+        target.write_transactions(&[extra]).await.unwrap();
+        let imported = import(&dir, &mut target, &config).await.unwrap();
+        let mismatches: Vec<_> = imported.mismatches().map(|chunk| chunk.first_slot).collect();
+        assert_eq!(mismatches, vec![108]);
+
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_file(&source_path);
+        let _ = fs::remove_file(&target_path);
+    }
+}
@@ -0,0 +1,406 @@
+//! The decimals of token mints, to turn the raw amounts of instructions into UI amounts.
+//!
+//! Decimals are taken from the token balances of the transaction being decoded whenever they
+//! cover the mint, then from a [`MintCache`]: seeded from the configuration, filled from token
+//! balances as transactions go by and, when a resolver is running, from the mint accounts
+//! themselves. Decoding never waits on the resolver: an amount whose mint isn't known yet is only
+//! emitted raw.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+use crate::cache::{CacheStats, LruCache};
+use crate::ingest::pool::RpcPool;
+use crate::interner::Symbol;
+use crate::transaction::RawTransaction;
+use crate::{InstructionProperty, InstructionSet, PropertyType};
+
+const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+// The size of a mint account, and the offset of its decimals and initialized flag.
+const MINT_LEN: usize = 82;
+const DECIMALS_OFFSET: usize = 44;
+// How many unresolved mints may wait for the resolver before new ones are dropped.
+const PENDING_MINTS: usize = 1024;
+
+/// Bounds of a [`MintCache`].
+#[derive(Clone, Debug)]
+pub struct MintCacheConfig {
+    // How many mints are cached (seeded ones aside), the least recently used being evicted.
+    pub capacity: usize,
+    // How long cached decimals are trusted before being resolved again.
+    pub ttl: Duration,
+}
+
+impl Default for MintCacheConfig {
+    fn default() -> Self {
+        MintCacheConfig {
+            capacity: 10_000,
+            ttl: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// The decimals of mints, shared by its clones.
+#[derive(Clone, Debug)]
+pub struct MintCache {
+    inner: Arc<Mutex<Inner>>,
+    config: MintCacheConfig,
+}
+
+#[derive(Debug)]
+struct Inner {
+    // Seeded from the configuration, never evicted.
+    seeded: HashMap<Pubkey, u8>,
+    entries: LruCache<Pubkey, u8>,
+    // Where misses go when a resolver is running, and the mints it hasn't resolved yet.
+    misses: Option<mpsc::Sender<Pubkey>>,
+    pending: HashSet<Pubkey>,
+}
+
+impl MintCache {
+    pub fn new(config: MintCacheConfig) -> Self {
+        let inner = Inner {
+            seeded: HashMap::new(),
+            entries: LruCache::new("mints", config.capacity, Some(config.ttl)),
+            misses: None,
+            pending: HashSet::new(),
+        };
+
+        MintCache { inner: Arc::new(Mutex::new(inner)), config }
+    }
+
+    /// Pins the decimals of `mint`, which are never evicted nor resolved.
+    pub fn seed(&self, mint: Pubkey, decimals: u8) {
+        self.inner.lock().unwrap().seeded.insert(mint, decimals);
+    }
+
+    /// The decimals of `mint` if cached and fresh.
+    pub fn get(&self, mint: &Pubkey) -> Option<u8> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(decimals) = inner.seeded.get(mint) {
+            return Some(*decimals);
+        }
+
+        inner.entries.get(mint).copied()
+    }
+
+    pub fn insert(&self, mint: Pubkey, decimals: u8) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.seeded.contains_key(&mint) {
+            return;
+        }
+
+        inner.entries.insert(mint, decimals);
+        inner.pending.remove(&mint);
+    }
+
+    /// How many mints are cached, seeded ones included.
+    pub fn len(&self) -> usize {
+        let inner = self.inner.lock().unwrap();
+        inner.seeded.len() + inner.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The hits, misses and evictions of the mints that aren't seeded.
+    pub fn stats(&self) -> CacheStats {
+        self.inner.lock().unwrap().entries.stats()
+    }
+
+    /// The decimals of `mint` in `transaction`: from its token balances, then from the cache.
+    /// Misses are handed to the resolver, if any, without waiting for it.
+    pub fn decimals(&self, mint: &Pubkey, transaction: &RawTransaction) -> Option<u8> {
+        let balance = transaction.token_mints.iter().find(|token| token.mint == *mint);
+        if let Some(token) = balance {
+            self.insert(token.mint, token.decimals);
+            return Some(token.decimals);
+        }
+
+        let decimals = self.get(mint);
+        if decimals.is_none() {
+            self.request(*mint);
+        }
+
+        decimals
+    }
+
+    fn request(&self, mint: Pubkey) {
+        let mut inner = self.inner.lock().unwrap();
+        let misses = match &inner.misses {
+            Some(misses) => misses.clone(),
+            None => return,
+        };
+        if inner.pending.contains(&mint) {
+            return;
+        }
+        // A full queue drops the miss, it's requested again by the next instruction of the mint.
+        if misses.try_send(mint).is_ok() {
+            inner.pending.insert(mint);
+        }
+    }
+
+    /// Resolves the mints missing from the cache by fetching their accounts through `rpc`, in the
+    /// background, until the returned task is aborted.
+    pub fn resolve_with(&self, rpc: Arc<RpcPool>) -> JoinHandle<()> {
+        let (sender, mut misses) = mpsc::channel(PENDING_MINTS);
+        self.inner.lock().unwrap().misses = Some(sender);
+
+        let cache = self.clone();
+        tokio::spawn(async move {
+            while let Some(mint) = misses.recv().await {
+                // Whatever else is waiting is resolved along, a `getMultipleAccounts` call at a
+                // time.
+                let mut mints = vec![mint];
+                while mints.len() < rpc.batch_size() {
+                    match misses.try_recv() {
+                        Ok(mint) => mints.push(mint),
+                        Err(_) => break,
+                    }
+                }
+
+                for (mint, decimals) in mints.iter().zip(fetch_decimals(&rpc, &mints).await) {
+                    let mint = *mint;
+                    match decimals {
+                        Some(decimals) => cache.insert(mint, decimals),
+                        None => {
+                            debug!(%mint, "[spi-wrapper/mints] Unable to resolve the decimals.");
+                            // Requested again (and most likely failing again) after the TTL.
+                            let cache = cache.clone();
+                            let ttl = cache.config.ttl;
+                            tokio::spawn(async move {
+                                tokio::time::sleep(ttl).await;
+                                cache.inner.lock().unwrap().pending.remove(&mint);
+                            });
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Adds the UI amount of every amount property of `instruction_set` whose mint is known, as
+    /// `<key>_ui`. Amount properties are `amount` and the ones ending with `_amount`.
+    pub fn add_ui_amounts(
+        &self,
+        instruction_set: &mut InstructionSet,
+        transaction: &RawTransaction,
+        account_indexes: &[u8],
+    ) {
+        let has_amounts = instruction_set.properties.iter()
+            .any(|property| is_amount(property.key.as_str()));
+        if !has_amounts {
+            return;
+        }
+        let decimals = match instruction_mint(instruction_set, transaction, account_indexes)
+            .and_then(|mint| self.decimals(&mint, transaction)) {
+            Some(decimals) => decimals,
+            None => return,
+        };
+
+        let ui_amounts: Vec<InstructionProperty> = instruction_set.properties.iter()
+            .filter(|property| is_amount(property.key.as_str()))
+            .filter_map(|property| Some(InstructionProperty {
+                key: Symbol::from(format!("{}_ui", property.key).as_str()),
+                value: ui_amount(&property.value, decimals)?,
+                value_type: PropertyType::Decimal,
+                ..property.clone()
+            }))
+            .collect();
+        instruction_set.properties.extend(ui_amounts);
+    }
+}
+
+impl Default for MintCache {
+    fn default() -> Self {
+        MintCache::new(MintCacheConfig::default())
+    }
+}
+
+fn is_amount(key: &str) -> bool {
+    key == "amount" || key.ends_with("_amount")
+}
+
+/// The mint the amounts of an instruction are in. Token program instructions name it by
+/// position; otherwise it's the one mint of the token accounts the instruction was invoked with,
+/// when they're all of the same mint.
+fn instruction_mint(
+    instruction_set: &InstructionSet,
+    transaction: &RawTransaction,
+    account_indexes: &[u8],
+) -> Option<Pubkey> {
+    if instruction_set.function.program.as_str() == TOKEN_PROGRAM {
+        let position = match instruction_set.function.function_name.as_str() {
+            "mint-to" | "mint-to-checked" => Some(0),
+            "transfer-checked" | "approve-checked" | "burn" | "burn-checked" => Some(1),
+            _ => None,
+        };
+        if let Some(position) = position {
+            let index = *account_indexes.get(position)?;
+            return transaction.account_keys.get(index as usize).copied();
+        }
+    }
+
+    let mut mints = account_indexes.iter()
+        .filter_map(|index| transaction.token_mints.iter()
+            .find(|token| token.account_index == *index)
+            .map(|token| token.mint));
+    let mint = mints.next()?;
+    if mints.all(|other| other == mint) {
+        Some(mint)
+    } else {
+        None
+    }
+}
+
+/// `amount` shifted by `decimals`, without trailing zeros: 1500000 with 6 decimals is `1.5`.
+pub fn ui_amount(amount: &str, decimals: u8) -> Option<String> {
+    let amount: u128 = amount.parse().ok()?;
+    let decimals = decimals as usize;
+    if decimals == 0 {
+        return Some(amount.to_string());
+    }
+
+    let digits = format!("{:0>width$}", amount, width = decimals + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        Some(integer.to_string())
+    } else {
+        Some(format!("{}.{}", integer, fraction))
+    }
+}
+
+/// The decimals of `mints`, in order, `None` for those that couldn't be fetched or aren't mints.
+async fn fetch_decimals(rpc: &RpcPool, mints: &[Pubkey]) -> Vec<Option<u8>> {
+    let accounts = match rpc.get_multiple_accounts(mints, CommitmentConfig::confirmed()).await {
+        Ok(accounts) => accounts,
+        Err(_) => return vec![None; mints.len()],
+    };
+
+    accounts.into_iter()
+        .map(|account| {
+            let account = account?;
+            let is_mint = account.owner.to_string() == TOKEN_PROGRAM
+                && account.data.len() == MINT_LEN
+                && account.data[DECIMALS_OFFSET + 1] == 1;
+            if is_mint {
+                Some(account.data[DECIMALS_OFFSET])
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::instruction::CompiledInstruction;
+
+    use super::*;
+    use crate::transaction::TokenMint;
+    use crate::{InstructionFunction, Network};
+
+    #[test]
+    fn ui_amounts_are_trimmed() {
+        assert_eq!(ui_amount("1500000", 6).as_deref(), Some("1.5"));
+        assert_eq!(ui_amount("1", 6).as_deref(), Some("0.000001"));
+        assert_eq!(ui_amount("2000000", 6).as_deref(), Some("2"));
+        assert_eq!(ui_amount("42", 0).as_deref(), Some("42"));
+        assert_eq!(ui_amount("-1", 6), None);
+    }
+
+    #[test]
+    fn least_recently_used_mints_are_evicted() {
+        let cache = MintCache::new(MintCacheConfig { capacity: 2, ..MintCacheConfig::default() });
+        let (usdc, wsol, bonk) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        cache.seed(Pubkey::new_unique(), 9);
+        cache.insert(usdc, 6);
+        cache.insert(wsol, 9);
+        assert_eq!(cache.get(&usdc), Some(6));
+
+        cache.insert(bonk, 5);
+        assert_eq!(cache.get(&wsol), None);
+        assert_eq!(cache.get(&usdc), Some(6));
+        assert_eq!(cache.len(), 3);
+
+        let expiring = MintCache::new(MintCacheConfig { ttl: Duration::from_secs(0), capacity: 2 });
+        expiring.insert(usdc, 6);
+        assert_eq!(expiring.get(&usdc), None);
+    }
+
+    #[test]
+    fn amounts_of_a_known_mint_get_a_ui_amount() {
+        let (source, destination, usdc) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let transaction = RawTransaction {
+            slot: 1,
+            block_time: None,
+            transaction_hash: "signature".to_string(),
+            account_keys: vec![Pubkey::new_unique(), source, destination],
+            num_required_signatures: 1,
+            instructions: vec![CompiledInstruction {
+                program_id_index: 0,
+                accounts: vec![1, 2],
+                data: vec![],
+            }],
+            inner_instructions: vec![],
+            token_mints: vec![
+                TokenMint { account_index: 1, mint: usdc, decimals: 6 },
+                TokenMint { account_index: 2, mint: usdc, decimals: 6 },
+            ],
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        };
+        let transaction_hash: Arc<str> = Arc::from("signature");
+        let property = |key: &str, value: &str| InstructionProperty {
+            tx_instruction_id: 0,
+            transaction_hash: transaction_hash.clone(),
+            parent_index: -1,
+            key: Symbol::from(key),
+            value: value.to_string(),
+            value_type: PropertyType::U64,
+            parent_key: Symbol::from(""),
+            timestamp: 0,
+        };
+        let mut instruction_set = InstructionSet {
+            function: InstructionFunction {
+                tx_instruction_id: 0,
+                transaction_hash: transaction_hash.clone(),
+                parent_index: -1,
+                program: Symbol::from("Lending111111111111111111111111111111111111"),
+                function_name: Symbol::from("deposit"),
+                timestamp: 0,
+                network: Network::Mainnet,
+                decoder_version: 0,
+            },
+            properties: vec![property("liquidity_amount", "2500000"), property("fee", "30")].into(),
+            raw_data: None,
+        };
+
+        let cache = MintCache::default();
+        cache.add_ui_amounts(&mut instruction_set, &transaction, &[1, 2]);
+        let ui: Vec<_> = instruction_set.properties.iter()
+            .map(|property| (property.key.as_str(), property.value.as_str()))
+            .collect();
+        assert_eq!(ui, vec![
+            ("liquidity_amount", "2500000"),
+            ("fee", "30"),
+            ("liquidity_amount_ui", "2.5"),
+        ]);
+        // Learnt from the token balances.
+        assert_eq!(cache.get(&usdc), Some(6));
+    }
+}
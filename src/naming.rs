@@ -0,0 +1,258 @@
+//! The names of what processors emit, for downstream filters to match them whatever the program.
+//!
+//! Function names are kebab-case (`init-lending-market`) and the keys of properties (their parent
+//! keys included) snake_case (`liquidity_amount`), nested keys joining snake_case segments and
+//! indexes with `/` (`config_keys/0/pubkey`). Names are made of lowercase ASCII letters and
+//! digits only, and function names can't be empty.
+//!
+//! The registry puts whatever its processors emit in that form (see [`canonicalize`]), closures
+//! and processors of other crates included. Renaming breaks what filters on the names as they
+//! were, so the registry can also emit the legacy names of what it renames while consumers
+//! migrate (see [`ProcessorRegistry::set_legacy_names`]).
+//!
+//! [`ProcessorRegistry::set_legacy_names`]: crate::registry::ProcessorRegistry::set_legacy_names
+
+use std::borrow::Cow;
+
+use crate::schema::INDEX_PLACEHOLDER;
+use crate::{InstructionProperty, InstructionSet, PropertyType};
+
+/// The key of the property carrying the name a function went by before it was renamed, when
+/// legacy names are emitted.
+pub const LEGACY_FUNCTION_NAME_KEY: &str = "legacy_function_name";
+
+/// The functions of built-in processors renamed for following the policy, as `(processor, name,
+/// legacy name)`.
+pub const RENAMED_FUNCTIONS: &[(&str, &str, &str)] = &[
+    ("associated-token-account", "create", ""),
+    ("config", "store", ""),
+    ("secp256k1", "verify", ""),
+];
+
+/// The name of a function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FunctionName<'a>(pub &'a str);
+
+impl<'a> FunctionName<'a> {
+    /// The name in kebab-case, e.g. `initLendingMarket` and `init_lending_market` become
+    /// `init-lending-market`. Characters other than ASCII letters and digits separate words. An
+    /// empty name stays empty: there's no telling what it should have been.
+    pub fn canonical(&self) -> Cow<'a, str> {
+        if self.is_canonical() {
+            return Cow::Borrowed(self.0);
+        }
+
+        Cow::Owned(words(self.0).join("-"))
+    }
+
+    /// Whether the name follows the policy.
+    pub fn is_canonical(&self) -> bool {
+        is_joined(self.0, '-')
+    }
+}
+
+/// The key (or parent key) of a property.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PropertyKey<'a>(pub &'a str);
+
+impl<'a> PropertyKey<'a> {
+    /// The key in snake_case, segment by segment, e.g. `configKeys/0/Pubkey` becomes
+    /// `config_keys/0/pubkey`. Empty segments are dropped.
+    pub fn canonical(&self) -> Cow<'a, str> {
+        if self.is_canonical() {
+            return Cow::Borrowed(self.0);
+        }
+
+        let segments: Vec<String> = self.0.split('/')
+            .map(|segment| words(segment).join("_"))
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        Cow::Owned(segments.join("/"))
+    }
+
+    /// Whether the key follows the policy. The empty key, the parent key of top-level properties,
+    /// does, and so do the index placeholders of schemas.
+    pub fn is_canonical(&self) -> bool {
+        self.0.is_empty() || self.0.split('/')
+            .all(|segment| segment == INDEX_PLACEHOLDER || is_joined(segment, '_'))
+    }
+}
+
+/// Puts the function name and the keys of `instruction_set`, emitted by `processor`, in their
+/// canonical form. With `legacy_names`, what was renamed is also emitted as it was: the legacy
+/// function name as a [`LEGACY_FUNCTION_NAME_KEY`] property, and the properties whose keys
+/// changed a second time, under their legacy keys.
+pub fn canonicalize(instruction_set: &mut InstructionSet, processor: &str, legacy_names: bool) {
+    let function = &mut instruction_set.function;
+    let name = function.function_name.as_str();
+    let canonical = FunctionName(name).canonical();
+    let legacy_name = if canonical != name {
+        function.function_name = canonical.as_ref().into();
+        Some(name)
+    } else {
+        RENAMED_FUNCTIONS.iter()
+            .find(|(renamed_in, renamed_to, _)| *renamed_in == processor && *renamed_to == name)
+            .map(|(_, _, legacy)| *legacy)
+    };
+
+    let mut legacy_properties = Vec::new();
+    for property in &mut instruction_set.properties {
+        let key = PropertyKey(property.key.as_str()).canonical();
+        let parent_key = PropertyKey(property.parent_key.as_str()).canonical();
+        if key == property.key.as_str() && parent_key == property.parent_key.as_str() {
+            continue;
+        }
+
+        let legacy = property.clone();
+        property.key = key.as_ref().into();
+        property.parent_key = parent_key.as_ref().into();
+        if legacy_names {
+            legacy_properties.push(legacy);
+        }
+    }
+
+    if !legacy_names {
+        return;
+    }
+    instruction_set.properties.extend(legacy_properties);
+    if let Some(legacy_name) = legacy_name {
+        let function = &instruction_set.function;
+        instruction_set.properties.push(InstructionProperty {
+            tx_instruction_id: function.tx_instruction_id,
+            transaction_hash: function.transaction_hash.clone(),
+            parent_index: function.parent_index,
+            key: LEGACY_FUNCTION_NAME_KEY.into(),
+            value: legacy_name.to_string(),
+            value_type: PropertyType::Text,
+            parent_key: "".into(),
+            timestamp: function.timestamp,
+        });
+    }
+}
+
+/// Whether `name` is made of words of lowercase ASCII letters and digits joined by `separator`.
+fn is_joined(name: &str, separator: char) -> bool {
+    !name.is_empty() && name.split(separator).all(|word| {
+        !word.is_empty() && word.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+    })
+}
+
+/// The words of `name`, lowercased: separated by anything but ASCII letters and digits, and where
+/// the case changes (`initLendingMarket`, `HTTPServer`).
+fn words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (index, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+
+        let starts_word = c.is_ascii_uppercase() && index > 0 && {
+            let previous = chars[index - 1];
+            let next = chars.get(index + 1).copied();
+            let acronym_ends = previous.is_ascii_uppercase()
+                && next.map_or(false, |next| next.is_ascii_lowercase());
+            previous.is_ascii_lowercase() || previous.is_ascii_digit() || acronym_ends
+        };
+        if starts_word && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.push(c.to_ascii_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InstructionFunction;
+
+    #[test]
+    fn names_are_put_in_their_canonical_form() {
+        assert_eq!(FunctionName("init-lending-market").canonical(), "init-lending-market");
+        assert_eq!(FunctionName("initLendingMarket").canonical(), "init-lending-market");
+        assert_eq!(FunctionName("init_lending market").canonical(), "init-lending-market");
+        assert_eq!(FunctionName("SetHTTPAuthority2").canonical(), "set-http-authority2");
+        assert_eq!(FunctionName("").canonical(), "");
+        assert!(!FunctionName("").is_canonical());
+        assert!(!FunctionName("init--market").is_canonical());
+
+        assert_eq!(PropertyKey("liquidity_amount").canonical(), "liquidity_amount");
+        assert_eq!(PropertyKey("configKeys/0/Pubkey").canonical(), "config_keys/0/pubkey");
+        assert_eq!(PropertyKey("orders//price-in-ticks").canonical(), "orders/price_in_ticks");
+        assert!(PropertyKey("").is_canonical());
+        assert!(PropertyKey("config_keys/{index}/pubkey").is_canonical());
+        assert!(!PropertyKey("config_keys/{id}/pubkey").is_canonical());
+    }
+
+    fn instruction_set(function_name: &str, keys: &[(&str, &str)]) -> InstructionSet {
+        InstructionSet {
+            function: InstructionFunction {
+                tx_instruction_id: 0,
+                transaction_hash: "signature".into(),
+                parent_index: -1,
+                program: "program".into(),
+                function_name: function_name.into(),
+                timestamp: 0,
+                network: Default::default(),
+                decoder_version: 0,
+            },
+            properties: keys.iter()
+                .map(|(key, parent_key)| InstructionProperty {
+                    tx_instruction_id: 0,
+                    transaction_hash: "signature".into(),
+                    parent_index: -1,
+                    key: (*key).into(),
+                    value: "1".to_string(),
+                    value_type: PropertyType::U64,
+                    parent_key: (*parent_key).into(),
+                    timestamp: 0,
+                })
+                .collect(),
+            raw_data: None,
+        }
+    }
+
+    fn keys(instruction_set: &InstructionSet) -> Vec<(&str, &str, &str)> {
+        instruction_set.properties.iter()
+            .map(|property| (property.key.as_str(), property.parent_key.as_str(),
+                             property.value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn legacy_names_are_emitted_along_with_the_canonical_ones() {
+        let drifted = instruction_set("closeAccount", &[("amount", ""), ("feeAmount", "fees")]);
+
+        let mut canonical = drifted.clone();
+        canonicalize(&mut canonical, "custom", false);
+        assert_eq!(canonical.function.function_name.as_str(), "close-account");
+        assert_eq!(keys(&canonical), vec![("amount", "", "1"), ("fee_amount", "fees", "1")]);
+
+        let mut aliased = drifted;
+        canonicalize(&mut aliased, "custom", true);
+        assert_eq!(aliased.function.function_name.as_str(), "close-account");
+        assert_eq!(keys(&aliased), vec![
+            ("amount", "", "1"),
+            ("fee_amount", "fees", "1"),
+            ("feeAmount", "fees", "1"),
+            (LEGACY_FUNCTION_NAME_KEY, "", "closeAccount"),
+        ]);
+
+        // Built-in functions renamed by hand keep their legacy name too.
+        let mut renamed = instruction_set("store", &[]);
+        canonicalize(&mut renamed, "config", true);
+        assert_eq!(keys(&renamed), vec![(LEGACY_FUNCTION_NAME_KEY, "", "")]);
+        let mut unrelated = instruction_set("store", &[]);
+        canonicalize(&mut unrelated, "custom", true);
+        assert!(unrelated.properties.is_empty());
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::interner::Symbol;
+
+/// The cluster records were indexed from, so that the records of several clusters can share a
+/// sink. Serializes as its name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Network {
+    Mainnet,
+    Devnet,
+    Testnet,
+    // Any other cluster, e.g. a local test validator.
+    Custom(Symbol),
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Network::Mainnet
+    }
+}
+
+impl Network {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Devnet => "devnet",
+            Network::Testnet => "testnet",
+            Network::Custom(name) => name.as_str(),
+        }
+    }
+
+    /// The genesis hash of the public clusters.
+    pub fn genesis_hash(&self) -> Option<&'static str> {
+        match self {
+            Network::Mainnet => Some("5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d"),
+            Network::Devnet => Some("EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG"),
+            Network::Testnet => Some("4uhcVJyU9pJkvQyS88uRDiswHXSCkY3zQawwpjk2NsNY"),
+            Network::Custom(_) => None,
+        }
+    }
+}
+
+impl From<&str> for Network {
+    fn from(name: &str) -> Self {
+        match name {
+            "mainnet" | "mainnet-beta" => Network::Mainnet,
+            "devnet" => Network::Devnet,
+            "testnet" => Network::Testnet,
+            name => Network::Custom(Symbol::intern(name)),
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = std::convert::Infallible;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(Network::from(name))
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for Network {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Network {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Network::from(String::deserialize(deserializer)?.as_str()))
+    }
+}
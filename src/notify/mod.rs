@@ -0,0 +1,352 @@
+//! Notifying webhooks of the instruction sets matching rules (see [`rules`]), as they're written
+//! to the sink: the JSON of every matching instruction set is POSTed to the webhooks of the rule,
+//! signed (see [`webhook`]).
+
+pub mod rules;
+pub mod webhook;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::account::AccountRecord;
+use crate::aggregate::WindowAggregate;
+use crate::block::BlockRecord;
+use crate::checkpoint::Checkpoint;
+use crate::ingest::timestamps::TimestampUpdate;
+use crate::lending::positions::PositionSnapshot;
+use crate::program_versions::ProgramUpgrade;
+use crate::reindex::{StaleFilter, StaleTransaction};
+use crate::reprocess::{RawFilter, RawInstruction};
+use crate::sinks::{ReadFilter, RowCounts, Sink, SinkError};
+use crate::transaction::TransactionRecord;
+use crate::InstructionSet;
+
+pub use rules::{Rule, RuleSet};
+pub use webhook::{Delivery, WebhookConfig, WebhookDispatcher};
+
+/// Errors that may be returned while loading rules.
+#[derive(Debug, Error)]
+pub enum NotifyError {
+    #[error("Unable to read {path}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("Invalid TOML: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("{key}: {message}")]
+    Invalid { key: String, message: String },
+}
+
+impl NotifyError {
+    fn invalid<K: Into<String>, M: Into<String>>(key: K, message: M) -> Self {
+        NotifyError::Invalid { key: key.into(), message: message.into() }
+    }
+}
+
+/// Counts the notifications of every rule per minute, so that a burst of matching instructions
+/// doesn't turn into a storm of notifications.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    // When the current window of each rule started, and how many notifications it let through.
+    windows: HashMap<String, (Instant, u32)>,
+}
+
+impl RateLimiter {
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    /// Whether `rule` may notify once more at `now`, `limit` times per minute at most.
+    pub fn admit(&mut self, rule: &str, limit: Option<u32>, now: Instant) -> bool {
+        let limit = match limit {
+            Some(limit) => limit,
+            None => return true,
+        };
+
+        let window = self.windows.entry(rule.to_string()).or_insert((now, 0));
+        if now.duration_since(window.0) >= RateLimiter::WINDOW {
+            *window = (now, 0);
+        }
+        if window.1 >= limit {
+            if window.1 == limit {
+                warn!("[spi-wrapper/notify] Rule {} notified {} times within a minute, dropping \
+                    its notifications until the minute is over.", rule, limit);
+                window.1 += 1;
+            }
+            return false;
+        }
+
+        window.1 += 1;
+        true
+    }
+}
+
+/// Evaluates rules against the instruction sets of transactions and dispatches the matching ones
+/// to their webhooks.
+pub struct Notifier {
+    rules: RuleSet,
+    dispatcher: WebhookDispatcher,
+    limiter: RateLimiter,
+}
+
+impl Notifier {
+    /// Has to be created within a tokio runtime, which delivers the notifications.
+    pub fn new(rules: RuleSet, config: WebhookConfig) -> Self {
+        Notifier {
+            rules,
+            dispatcher: WebhookDispatcher::spawn(config),
+            limiter: RateLimiter::default(),
+        }
+    }
+
+    pub fn rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
+    /// Dispatches every instruction set of `transactions` matching a rule to the webhooks of the
+    /// rule, returning how many deliveries were queued.
+    pub fn notify(&mut self, transactions: &[TransactionRecord]) -> usize {
+        let rules = self.rules.rules();
+        if rules.is_empty() {
+            return 0;
+        }
+
+        let mut queued = 0;
+        let instruction_sets = transactions.iter()
+            .flat_map(|transaction| transaction.instruction_sets.iter());
+        for instruction_set in instruction_sets {
+            for rule in rules.iter().filter(|rule| rule.matches(instruction_set)) {
+                if !self.limiter.admit(&rule.name, rule.max_per_minute, Instant::now()) {
+                    continue;
+                }
+                queued += self.dispatch(rule, instruction_set);
+            }
+        }
+
+        queued
+    }
+
+    fn dispatch(&self, rule: &Rule, instruction_set: &InstructionSet) -> usize {
+        let body: Arc<[u8]> = match serde_json::to_vec(instruction_set) {
+            Ok(body) => body.into(),
+            Err(err) => {
+                warn!("[spi-wrapper/notify] Unable to serialize an instruction set of {} \
+                    matching rule {}: {}", instruction_set.function.transaction_hash, rule.name,
+                    err);
+                return 0;
+            }
+        };
+
+        let mut queued = 0;
+        for url in &rule.webhooks {
+            let delivery = Delivery {
+                rule: rule.name.clone(),
+                url: url.clone(),
+                body: body.clone(),
+            };
+            if self.dispatcher.dispatch(delivery) {
+                queued += 1;
+            }
+        }
+
+        queued
+    }
+
+    /// Waits for the notifications queued to be delivered (or given up on).
+    pub async fn shutdown(&mut self) {
+        self.dispatcher.shutdown().await
+    }
+}
+
+/// Notifies the transactions written to the wrapped sink (see [`Notifier`]), once they're
+/// written. The notifications queued are delivered on shutdown.
+pub struct NotifyingSink<S> {
+    inner: S,
+    notifier: Notifier,
+}
+
+impl<S: Sink> NotifyingSink<S> {
+    pub fn new(inner: S, notifier: Notifier) -> Self {
+        NotifyingSink { inner, notifier }
+    }
+
+    pub fn notifier(&self) -> &Notifier {
+        &self.notifier
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<S: Sink> Sink for NotifyingSink<S> {
+    async fn write_block(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        self.inner.write_block(block).await?;
+        self.notifier.notify(&block.transactions);
+
+        Ok(())
+    }
+
+    async fn write_block_header(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        self.inner.write_block_header(block).await
+    }
+
+    async fn write_transactions(
+        &mut self,
+        transactions: &[TransactionRecord],
+    ) -> Result<(), SinkError> {
+        self.inner.write_transactions(transactions).await?;
+        self.notifier.notify(transactions);
+
+        Ok(())
+    }
+
+    async fn write_accounts(&mut self, accounts: &[AccountRecord]) -> Result<(), SinkError> {
+        self.inner.write_accounts(accounts).await
+    }
+
+    async fn write_aggregates(&mut self, aggregates: &[WindowAggregate]) -> Result<(), SinkError> {
+        self.inner.write_aggregates(aggregates).await
+    }
+
+    async fn write_positions(&mut self, snapshots: &[PositionSnapshot]) -> Result<(), SinkError> {
+        self.inner.write_positions(snapshots).await
+    }
+
+    async fn latest_positions(&mut self) -> Result<Vec<PositionSnapshot>, SinkError> {
+        self.inner.latest_positions().await
+    }
+
+    async fn write_program_upgrades(
+        &mut self,
+        upgrades: &[ProgramUpgrade],
+    ) -> Result<(), SinkError> {
+        self.inner.write_program_upgrades(upgrades).await
+    }
+
+    async fn program_upgrades(&mut self) -> Result<Vec<ProgramUpgrade>, SinkError> {
+        self.inner.program_upgrades().await
+    }
+
+    async fn mark_finalized(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        self.inner.mark_finalized(slots).await
+    }
+
+    async fn invalidate(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        self.inner.invalidate(slots).await
+    }
+
+    async fn rollback_from_slot(&mut self, slot: u64) -> Result<(), SinkError> {
+        self.inner.rollback_from_slot(slot).await
+    }
+
+    async fn has_transaction(&mut self, signature: &str) -> Result<Option<bool>, SinkError> {
+        self.inner.has_transaction(signature).await
+    }
+
+    async fn raw_instructions(
+        &mut self,
+        filter: &RawFilter,
+    ) -> Result<Vec<RawInstruction>, SinkError> {
+        self.inner.raw_instructions(filter).await
+    }
+
+    async fn write_reprocessed(
+        &mut self,
+        instruction_sets: &[InstructionSet],
+    ) -> Result<(), SinkError> {
+        self.inner.write_reprocessed(instruction_sets).await
+    }
+
+    async fn stale_transactions(
+        &mut self,
+        filter: &StaleFilter,
+    ) -> Result<Vec<StaleTransaction>, SinkError> {
+        self.inner.stale_transactions(filter).await
+    }
+
+    async fn read_transactions(
+        &mut self,
+        filter: &ReadFilter,
+    ) -> Result<Vec<TransactionRecord>, SinkError> {
+        self.inner.read_transactions(filter).await
+    }
+
+    async fn row_counts(
+        &mut self,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Option<RowCounts>, SinkError> {
+        self.inner.row_counts(first_slot, last_slot).await
+    }
+
+    async fn estimated_timestamps(
+        &mut self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, i64)>, SinkError> {
+        self.inner.estimated_timestamps(after, limit).await
+    }
+
+    async fn update_timestamps(&mut self, updates: &[TimestampUpdate]) -> Result<(), SinkError> {
+        self.inner.update_timestamps(updates).await
+    }
+
+    fn supports_transactions(&self) -> bool {
+        self.inner.supports_transactions()
+    }
+
+    async fn begin(&mut self) -> Result<(), SinkError> {
+        self.inner.begin().await
+    }
+
+    async fn commit(
+        &mut self,
+        pipeline: &str,
+        checkpoint: Option<&Checkpoint>,
+    ) -> Result<(), SinkError> {
+        self.inner.commit(pipeline, checkpoint).await
+    }
+
+    async fn committed_checkpoint(
+        &mut self,
+        pipeline: &str,
+    ) -> Result<Option<Checkpoint>, SinkError> {
+        self.inner.committed_checkpoint(pipeline).await
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        self.inner.flush().await
+    }
+
+    async fn shutdown(&mut self) -> Result<(), SinkError> {
+        let result = self.inner.shutdown().await;
+        self.notifier.shutdown().await;
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_are_rate_limited_per_minute() {
+        let mut limiter = RateLimiter::default();
+        let start = Instant::now();
+
+        assert!(limiter.admit("liquidations", Some(2), start));
+        assert!(limiter.admit("liquidations", Some(2), start + Duration::from_secs(10)));
+        assert!(!limiter.admit("liquidations", Some(2), start + Duration::from_secs(20)));
+        assert!(!limiter.admit("liquidations", Some(2), start + Duration::from_secs(30)));
+        // Other rules have limits of their own.
+        assert!(limiter.admit("treasury", Some(1), start + Duration::from_secs(30)));
+        assert!((0..100).all(|_| limiter.admit("unlimited", None, start)));
+
+        assert!(limiter.admit("liquidations", Some(2), start + Duration::from_secs(60)));
+    }
+}
@@ -0,0 +1,556 @@
+//! Which instruction sets are worth a notification, as described by a rule file:
+//!
+//! ```toml
+//! [[rules]]
+//! name = "large-liquidations"
+//! program = "LendZqTs8gn5CTSJU1jWKhKuVpjJGom45nnwPb2AMTi"
+//! function = "liquidate-obligation"
+//! conditions = [{ key = "liquidity_amount", op = ">=", value = "1000000000000" }]
+//! webhooks = ["https://hooks.example.com/liquidations"]
+//! max_per_minute = 10
+//!
+//! [[rules]]
+//! name = "treasury"
+//! accounts = ["<treasury address>"]
+//! webhooks = ["${TREASURY_WEBHOOK}"]
+//! ```
+//!
+//! Every criterion set has to match. Conditions compare the value of a property with `==`, `!=`,
+//! `>`, `>=`, `<` or `<=`: numerically when both are numbers (exactly, however large), as strings
+//! otherwise. Accounts match the instruction sets with a property set to one of them, which only
+//! covers the accounts the processors decode (owners, authorities...), not every account the
+//! instructions are invoked with.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use reqwest::Url;
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::config::{interpolate, ConfigError};
+use crate::ingest::filter::modified_at;
+use crate::interner::Symbol;
+use crate::notify::NotifyError;
+use crate::InstructionSet;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    #[serde(default)]
+    rules: Vec<RuleSettings>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSettings {
+    name: String,
+    program: Option<String>,
+    function: Option<String>,
+    #[serde(default)]
+    conditions: Vec<ConditionSettings>,
+    #[serde(default)]
+    accounts: Vec<String>,
+    webhooks: Vec<String>,
+    max_per_minute: Option<u32>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConditionSettings {
+    key: String,
+    // Any parent key when unset.
+    parent_key: Option<String>,
+    op: String,
+    value: String,
+}
+
+/// How a condition compares the value of a property with its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Operator {
+    fn accepts(self, ordering: Ordering) -> bool {
+        match self {
+            Operator::Eq => ordering == Ordering::Equal,
+            Operator::Ne => ordering != Ordering::Equal,
+            Operator::Gt => ordering == Ordering::Greater,
+            Operator::Ge => ordering != Ordering::Less,
+            Operator::Lt => ordering == Ordering::Less,
+            Operator::Le => ordering != Ordering::Greater,
+        }
+    }
+}
+
+impl FromStr for Operator {
+    type Err = String;
+
+    fn from_str(op: &str) -> Result<Self, String> {
+        match op {
+            "==" => Ok(Operator::Eq),
+            "!=" => Ok(Operator::Ne),
+            ">" => Ok(Operator::Gt),
+            ">=" => Ok(Operator::Ge),
+            "<" => Ok(Operator::Lt),
+            "<=" => Ok(Operator::Le),
+            op => Err(format!("unknown operator `{}`, expected ==, !=, >, >=, < or <=", op)),
+        }
+    }
+}
+
+/// A comparison a property of the instruction sets has to pass.
+#[derive(Clone, Debug)]
+pub struct Condition {
+    pub key: String,
+    // Any parent key when unset.
+    pub parent_key: Option<String>,
+    pub op: Operator,
+    pub value: String,
+}
+
+impl Condition {
+    /// Whether `value` compares to the value of the condition as expected.
+    pub fn holds(&self, value: &str) -> bool {
+        match compare_numbers(value, &self.value) {
+            Some(ordering) => self.op.accepts(ordering),
+            None => match self.op {
+                Operator::Eq => value == self.value,
+                Operator::Ne => value != self.value,
+                _ => false,
+            },
+        }
+    }
+
+    fn matches(&self, instruction_set: &InstructionSet) -> bool {
+        instruction_set.properties.iter()
+            .filter(|property| property.key.as_str() == self.key)
+            .filter(|property| self.parent_key.as_deref()
+                .map_or(true, |parent_key| property.parent_key.as_str() == parent_key))
+            .any(|property| self.holds(&property.value))
+    }
+}
+
+/// What an instruction set has to look like to be notified, and where it's notified to.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub name: String,
+    // Any program when unset.
+    pub program: Option<Symbol>,
+    // Any function when unset.
+    pub function: Option<String>,
+    pub conditions: Vec<Condition>,
+    // One of them has to be the value of a property, when any is set.
+    pub accounts: HashSet<String>,
+    pub webhooks: Vec<Url>,
+    // How many instruction sets are notified per minute, at most. No limit when unset.
+    pub max_per_minute: Option<u32>,
+}
+
+impl Rule {
+    pub fn matches(&self, instruction_set: &InstructionSet) -> bool {
+        let function = &instruction_set.function;
+        if self.program.map_or(false, |program| program != function.program) {
+            return false;
+        }
+        if self.function.as_deref().map_or(false, |name| name != function.function_name.as_str()) {
+            return false;
+        }
+        if !self.accounts.is_empty() && !instruction_set.properties.iter()
+            .any(|property| self.accounts.contains(property.value.as_str()))
+        {
+            return false;
+        }
+
+        self.conditions.iter().all(|condition| condition.matches(instruction_set))
+    }
+}
+
+/// Parses the rules of a rule file (see the [module](self) documentation), `${NAME}` in any
+/// string being replaced by the `NAME` environment variable.
+pub fn parse_rules(toml: &str) -> Result<Vec<Rule>, NotifyError> {
+    let mut value: toml::Value = toml::from_str(toml)?;
+    interpolate(&mut value, &mut Vec::new()).map_err(|err| match err {
+        ConfigError::Invalid { key, message } => NotifyError::Invalid { key, message },
+        err => NotifyError::Invalid { key: String::new(), message: err.to_string() },
+    })?;
+    let file: RuleFile = value.try_into()?;
+
+    let mut rules: Vec<Rule> = Vec::with_capacity(file.rules.len());
+    for (index, settings) in file.rules.into_iter().enumerate() {
+        let rule = compile(index, settings)?;
+        if rules.iter().any(|other| other.name == rule.name) {
+            return Err(NotifyError::invalid(format!("rules[{}].name", index),
+                format!("another rule is named `{}`", rule.name)));
+        }
+        rules.push(rule);
+    }
+
+    Ok(rules)
+}
+
+fn compile(index: usize, settings: RuleSettings) -> Result<Rule, NotifyError> {
+    let key = |field: &str| format!("rules[{}].{}", index, field);
+
+    let program = match &settings.program {
+        Some(program) => {
+            Pubkey::from_str(program).map_err(|_| NotifyError::invalid(
+                key("program"), format!("`{}` is not a valid address", program)))?;
+            Some(Symbol::intern(program))
+        }
+        None => None,
+    };
+
+    let mut conditions = Vec::with_capacity(settings.conditions.len());
+    for (position, condition) in settings.conditions.into_iter().enumerate() {
+        let key = |field: &str| key(&format!("conditions[{}].{}", position, field));
+        let op: Operator = condition.op.parse()
+            .map_err(|message| NotifyError::invalid(key("op"), message))?;
+        let ordered = !matches!(op, Operator::Eq | Operator::Ne);
+        if ordered && Number::parse(&condition.value).is_none() {
+            return Err(NotifyError::invalid(key("value"), format!(
+                "`{}` is not a number, which {} compares to", condition.value, condition.op)));
+        }
+        conditions.push(Condition {
+            key: condition.key,
+            parent_key: condition.parent_key,
+            op,
+            value: condition.value,
+        });
+    }
+
+    for (position, account) in settings.accounts.iter().enumerate() {
+        Pubkey::from_str(account).map_err(|_| NotifyError::invalid(
+            key(&format!("accounts[{}]", position)),
+            format!("`{}` is not a valid address", account)))?;
+    }
+
+    if settings.webhooks.is_empty() {
+        return Err(NotifyError::invalid(key("webhooks"), "at least one webhook is needed"));
+    }
+    let mut webhooks = Vec::with_capacity(settings.webhooks.len());
+    for (position, webhook) in settings.webhooks.iter().enumerate() {
+        let key = || key(&format!("webhooks[{}]", position));
+        let url = Url::parse(webhook)
+            .map_err(|err| NotifyError::invalid(key(), format!("invalid url: {}", err)))?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(NotifyError::invalid(key(), "webhooks are http or https urls"));
+        }
+        webhooks.push(url);
+    }
+
+    if settings.max_per_minute == Some(0) {
+        return Err(NotifyError::invalid(
+            key("max_per_minute"), "at least one notification per minute is needed"));
+    }
+
+    Ok(Rule {
+        name: settings.name,
+        program,
+        function: settings.function,
+        conditions,
+        accounts: settings.accounts.into_iter().collect(),
+        webhooks,
+        max_per_minute: settings.max_per_minute,
+    })
+}
+
+/// The rules in use, given up front or loaded from a rule file. The file can be reloaded while
+/// the rules are in use, clones sharing the reloaded rules.
+#[derive(Clone, Debug, Default)]
+pub struct RuleSet {
+    file: Option<PathBuf>,
+    rules: Arc<RwLock<Arc<Vec<Rule>>>>,
+}
+
+impl RuleSet {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        RuleSet { file: None, rules: Arc::new(RwLock::new(Arc::new(rules))) }
+    }
+
+    /// The rules of the file at `path`, which is read right away.
+    pub fn from_file<P: Into<PathBuf>>(path: P) -> Result<Self, NotifyError> {
+        let rules = RuleSet { file: Some(path.into()), ..RuleSet::default() };
+        rules.reload()?;
+
+        Ok(rules)
+    }
+
+    /// Reads the rule file again, returning how many rules it holds. The previous rules are kept
+    /// when it can't be read or holds an invalid rule.
+    pub fn reload(&self) -> Result<usize, NotifyError> {
+        let path = match &self.file {
+            Some(path) => path,
+            None => return Ok(0),
+        };
+
+        let rules = read_rules(path)?;
+        let count = rules.len();
+        *self.rules.write().unwrap() = Arc::new(rules);
+
+        Ok(count)
+    }
+
+    /// Reloads the rule file whenever it's modified, checking every `interval`, until the
+    /// returned task is aborted.
+    pub fn watch(&self, interval: Duration) -> JoinHandle<()> {
+        let rules = self.clone();
+        tokio::spawn(async move {
+            let path = match &rules.file {
+                Some(path) => path.clone(),
+                None => return,
+            };
+            let mut modified = modified_at(&path);
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                let now_modified = modified_at(&path);
+                if now_modified == modified {
+                    continue;
+                }
+                modified = now_modified;
+
+                match rules.reload() {
+                    Ok(count) => info!("[spi-wrapper/notify] Reloaded {} rules from {}.",
+                        count, path.display()),
+                    Err(err) => warn!("[spi-wrapper/notify] Keeping the previous rules: {}", err),
+                }
+            }
+        })
+    }
+
+    /// The rules currently in use.
+    pub fn rules(&self) -> Arc<Vec<Rule>> {
+        self.rules.read().unwrap().clone()
+    }
+}
+
+fn read_rules(path: &Path) -> Result<Vec<Rule>, NotifyError> {
+    let toml = fs::read_to_string(path)
+        .map_err(|source| NotifyError::Io { path: path.to_path_buf(), source })?;
+
+    parse_rules(&toml)
+}
+
+/// A decimal number as written, e.g. `-12.50`, compared without losing precision.
+#[derive(Debug, PartialEq, Eq)]
+struct Number<'a> {
+    negative: bool,
+    // The digits before the point, without leading zeros.
+    integer: &'a str,
+    // The digits after the point, without trailing zeros.
+    fraction: &'a str,
+}
+
+impl<'a> Number<'a> {
+    fn parse(value: &'a str) -> Option<Self> {
+        let (negative, digits) = match value.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, value.strip_prefix('+').unwrap_or(value)),
+        };
+        let (integer, fraction) = match digits.find('.') {
+            Some(point) => (&digits[..point], &digits[point + 1..]),
+            None => (digits, ""),
+        };
+        let is_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        let is_empty = integer.is_empty() && fraction.is_empty();
+        if is_empty || !is_digits(integer) || !is_digits(fraction) {
+            return None;
+        }
+
+        let integer = integer.trim_start_matches('0');
+        let fraction = fraction.trim_end_matches('0');
+        Some(Number {
+            // Negative zero is zero.
+            negative: negative && !(integer.is_empty() && fraction.is_empty()),
+            integer,
+            fraction,
+        })
+    }
+
+    fn cmp_magnitude(&self, other: &Number<'_>) -> Ordering {
+        self.integer.len().cmp(&other.integer.len())
+            .then_with(|| self.integer.cmp(other.integer))
+            .then_with(|| self.fraction.cmp(other.fraction))
+    }
+}
+
+/// Compares two values as numbers, or returns `None` when either isn't one. Amounts are stored
+/// as strings, and may not fit a float (or even a u64) without losing precision.
+pub fn compare_numbers(left: &str, right: &str) -> Option<Ordering> {
+    let (left, right) = (Number::parse(left)?, Number::parse(right)?);
+
+    Some(match (left.negative, right.negative) {
+        (false, true) => Ordering::Greater,
+        (true, false) => Ordering::Less,
+        (false, false) => left.cmp_magnitude(&right),
+        (true, true) => right.cmp_magnitude(&left),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use smallvec::smallvec;
+
+    use super::*;
+    use crate::{InstructionFunction, InstructionProperty, Network, PropertyType};
+
+    const LENDING: &str = "LendZqTs8gn5CTSJU1jWKhKuVpjJGom45nnwPb2AMTi";
+
+    fn liquidation(amount: &str) -> InstructionSet {
+        let transaction_hash: Arc<str> = Arc::from("signature");
+        InstructionSet {
+            function: InstructionFunction {
+                tx_instruction_id: 0,
+                transaction_hash: transaction_hash.clone(),
+                parent_index: -1,
+                program: LENDING.into(),
+                function_name: "liquidate-obligation".into(),
+                timestamp: 0,
+                network: Network::Mainnet,
+                decoder_version: 0,
+            },
+            properties: smallvec![InstructionProperty {
+                tx_instruction_id: 0,
+                transaction_hash,
+                parent_index: -1,
+                key: "liquidity_amount".into(),
+                value: amount.to_string(),
+                value_type: PropertyType::U64,
+                parent_key: "".into(),
+                timestamp: 0,
+            }],
+            raw_data: None,
+        }
+    }
+
+    #[test]
+    fn numbers_compare_exactly_whatever_their_size() {
+        assert_eq!(compare_numbers("10", "9"), Some(Ordering::Greater));
+        assert_eq!(compare_numbers("0009", "9.000"), Some(Ordering::Equal));
+        assert_eq!(compare_numbers("1.5", "1.45"), Some(Ordering::Greater));
+        assert_eq!(compare_numbers("-2", "1"), Some(Ordering::Less));
+        assert_eq!(compare_numbers("-2.5", "-2.25"), Some(Ordering::Less));
+        assert_eq!(compare_numbers("-0", "0"), Some(Ordering::Equal));
+        assert_eq!(compare_numbers(".5", "0.5"), Some(Ordering::Equal));
+        // Beyond what a f64 (and a u64) represents exactly.
+        assert_eq!(compare_numbers("18446744073709551617", "18446744073709551616"),
+            Some(Ordering::Greater));
+        assert_eq!(compare_numbers("9007199254740993", "9007199254740992"),
+            Some(Ordering::Greater));
+
+        assert_eq!(compare_numbers("1e9", "1"), None);
+        assert_eq!(compare_numbers("", "1"), None);
+        assert_eq!(compare_numbers(".", "1"), None);
+        assert_eq!(compare_numbers("So11111111111111111111111111111111111111112", "1"), None);
+    }
+
+    #[test]
+    fn conditions_compare_stringly_stored_amounts() {
+        let rules = parse_rules(&format!(r#"
+            [[rules]]
+            name = "large-liquidations"
+            program = "{}"
+            function = "liquidate-obligation"
+            conditions = [
+                {{ key = "liquidity_amount", op = ">=", value = "1000000000" }},
+                {{ key = "liquidity_amount", op = "!=", value = "5000000000" }},
+            ]
+            webhooks = ["https://hooks.example.com/liquidations"]
+        "#, LENDING)).unwrap();
+        let rule = &rules[0];
+
+        // A string comparison would have 999999999 above 1000000000.
+        assert!(!rule.matches(&liquidation("999999999")));
+        assert!(rule.matches(&liquidation("1000000000")));
+        assert!(rule.matches(&liquidation("18446744073709551615")));
+        assert!(!rule.matches(&liquidation("5000000000")));
+        assert!(!rule.matches(&liquidation("not a number")));
+
+        let mut other_function = liquidation("2000000000");
+        other_function.function.function_name = "borrow-obligation-liquidity".into();
+        assert!(!rule.matches(&other_function));
+    }
+
+    #[test]
+    fn accounts_match_any_property() {
+        let treasury = Pubkey::new_unique();
+        let rules = parse_rules(&format!(r#"
+            [[rules]]
+            name = "treasury"
+            accounts = ["{}"]
+            webhooks = ["http://localhost:8080/treasury"]
+        "#, treasury)).unwrap();
+
+        assert!(!rules[0].matches(&liquidation("1")));
+        assert!(rules[0].matches(&liquidation(&treasury.to_string())));
+    }
+
+    #[test]
+    fn invalid_rules_are_reported() {
+        let error = |toml: &str| parse_rules(toml).unwrap_err().to_string();
+
+        assert_eq!(error(r#"
+            [[rules]]
+            name = "large"
+            conditions = [{ key = "amount", op = ">", value = "a lot" }]
+            webhooks = ["https://hooks.example.com"]
+        "#), "rules[0].conditions[0].value: `a lot` is not a number, which > compares to");
+        assert_eq!(error(r#"
+            [[rules]]
+            name = "large"
+            conditions = [{ key = "amount", op = "=>", value = "1" }]
+            webhooks = ["https://hooks.example.com"]
+        "#), "rules[0].conditions[0].op: unknown operator `=>`, expected ==, !=, >, >=, < or <=");
+        assert_eq!(error(r#"
+            [[rules]]
+            name = "nowhere"
+            webhooks = []
+        "#), "rules[0].webhooks: at least one webhook is needed");
+        assert_eq!(error(r#"
+            [[rules]]
+            name = "https"
+            webhooks = ["https://hooks.example.com"]
+
+            [[rules]]
+            name = "ftp"
+            webhooks = ["ftp://hooks.example.com"]
+        "#), "rules[1].webhooks[0]: webhooks are http or https urls");
+    }
+
+    #[test]
+    fn reloads_the_rule_file() {
+        let path = std::env::temp_dir()
+            .join(format!("spi-wrapper-rules-{}.toml", std::process::id()));
+        let rule = |name: &str| format!(
+            "[[rules]]\nname = \"{}\"\nwebhooks = [\"https://hooks.example.com\"]\n", name);
+        fs::write(&path, rule("first")).unwrap();
+
+        let rules = RuleSet::from_file(&path).unwrap();
+        let shared = rules.clone();
+        assert_eq!(shared.rules()[0].name, "first");
+
+        fs::write(&path, rule("first") + &rule("second")).unwrap();
+        assert_eq!(rules.reload().unwrap(), 2);
+        assert_eq!(shared.rules()[1].name, "second");
+
+        fs::write(&path, rule("first") + &rule("first")).unwrap();
+        assert!(matches!(rules.reload(), Err(NotifyError::Invalid { .. })));
+        assert_eq!(shared.rules().len(), 2);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,244 @@
+//! Delivering notifications to webhooks in the background, so that a slow or unreachable
+//! endpoint never holds up the pipeline.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac, NewMac};
+use reqwest::{Client, StatusCode, Url};
+use sha2::Sha256;
+use thiserror::Error;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, warn};
+
+/// The header carrying the HMAC-SHA256 of the body, as `sha256=<hex digest>`, when a secret is
+/// set.
+pub const SIGNATURE_HEADER: &str = "X-SPI-Signature";
+/// The header carrying the name of the rule the body matched.
+pub const RULE_HEADER: &str = "X-SPI-Rule";
+
+#[derive(Clone, Debug)]
+pub struct WebhookConfig {
+    // The key the bodies are signed with, unsigned when unset.
+    pub secret: Option<String>,
+    // How many times a failed delivery is retried.
+    pub max_retries: u32,
+    // How long the first retry waits, doubling with every retry up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    // How long a delivery may take.
+    pub timeout: Duration,
+    // How many deliveries may wait before new ones are dropped.
+    pub queue_size: usize,
+    // How many deliveries are in flight at once.
+    pub concurrency: usize,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            secret: None,
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+            queue_size: 1024,
+            concurrency: 8,
+        }
+    }
+}
+
+impl WebhookConfig {
+    /// How long to wait before the `retry`th retry (starting at 0).
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry);
+        self.initial_backoff.checked_mul(factor).unwrap_or(self.max_backoff).min(self.max_backoff)
+    }
+}
+
+/// A body to POST to a webhook.
+#[derive(Clone, Debug)]
+pub struct Delivery {
+    // The rule the body matched.
+    pub rule: String,
+    pub url: Url,
+    pub body: Arc<[u8]>,
+}
+
+#[derive(Debug, Error)]
+enum DeliveryError {
+    #[error("{0}")]
+    Http(#[from] reqwest::Error),
+    #[error("the webhook answered {0}")]
+    Status(StatusCode),
+}
+
+impl DeliveryError {
+    // Client errors other than rate limiting won't go away by retrying.
+    fn is_retryable(&self) -> bool {
+        match self {
+            DeliveryError::Http(_) => true,
+            DeliveryError::Status(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+        }
+    }
+}
+
+/// The signature of `body` with `secret`, as sent in the [`SIGNATURE_HEADER`].
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC takes keys of any size");
+    mac.update(body);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// POSTs deliveries to their webhook from a background task, retrying with backoff.
+pub struct WebhookDispatcher {
+    sender: Option<mpsc::Sender<Delivery>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl WebhookDispatcher {
+    /// Starts delivering in the background. Has to be called within a tokio runtime.
+    pub fn spawn(config: WebhookConfig) -> Self {
+        let (sender, receiver) = mpsc::channel(config.queue_size.max(1));
+        let worker = tokio::spawn(deliver_all(config, receiver));
+
+        WebhookDispatcher { sender: Some(sender), worker: Some(worker) }
+    }
+
+    /// Queues a delivery, or drops it (returning false) when the queue is full or the dispatcher
+    /// shut down.
+    pub fn dispatch(&self, delivery: Delivery) -> bool {
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => return false,
+        };
+
+        match sender.try_send(delivery) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(delivery)) => {
+                warn!("[spi-wrapper/notify] The webhook queue is full, dropping a notification of \
+                    rule {} to {}.", delivery.rule, delivery.url);
+                false
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        }
+    }
+
+    /// Stops taking deliveries and waits for the queued ones to be delivered (or given up on).
+    pub async fn shutdown(&mut self) {
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            if let Err(err) = worker.await {
+                error!("[spi-wrapper/notify] The webhook dispatcher failed: {}", err);
+            }
+        }
+    }
+}
+
+async fn deliver_all(config: WebhookConfig, mut receiver: mpsc::Receiver<Delivery>) {
+    let client = match Client::builder().timeout(config.timeout).build() {
+        Ok(client) => client,
+        Err(err) => {
+            error!("[spi-wrapper/notify] Unable to build the webhook client, no notification \
+                will be delivered: {}", err);
+            return;
+        }
+    };
+    let config = Arc::new(config);
+    let concurrency = config.concurrency.max(1);
+    let in_flight = Arc::new(Semaphore::new(concurrency));
+
+    while let Some(delivery) = receiver.recv().await {
+        let permit = in_flight.clone().acquire_owned().await.unwrap();
+        let (client, config) = (client.clone(), config.clone());
+        tokio::spawn(async move {
+            deliver(&client, &config, &delivery).await;
+            drop(permit);
+        });
+    }
+
+    // Every permit is back once the deliveries in flight are done.
+    let _ = in_flight.acquire_many(concurrency as u32).await;
+}
+
+async fn deliver(client: &Client, config: &WebhookConfig, delivery: &Delivery) {
+    let mut retry = 0;
+    loop {
+        let err = match post(client, config, delivery).await {
+            Ok(()) => {
+                debug!("[spi-wrapper/notify] Notified {} of rule {}.", delivery.url,
+                    delivery.rule);
+                return;
+            }
+            Err(err) => err,
+        };
+
+        if retry >= config.max_retries || !err.is_retryable() {
+            error!("[spi-wrapper/notify] Giving up on notifying {} of rule {} after {} attempts: \
+                {}", delivery.url, delivery.rule, retry + 1, err);
+            return;
+        }
+        let backoff = config.backoff(retry);
+        warn!("[spi-wrapper/notify] Unable to notify {} of rule {}, retrying in {:?}: {}",
+            delivery.url, delivery.rule, backoff, err);
+        tokio::time::sleep(backoff).await;
+        retry += 1;
+    }
+}
+
+async fn post(
+    client: &Client,
+    config: &WebhookConfig,
+    delivery: &Delivery,
+) -> Result<(), DeliveryError> {
+    let mut request = client.post(delivery.url.clone())
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(RULE_HEADER, &delivery.rule);
+    if let Some(secret) = &config.secret {
+        request = request.header(SIGNATURE_HEADER, sign(secret, &delivery.body));
+    }
+
+    let response = request.body(delivery.body.to_vec()).send().await?;
+    if !response.status().is_success() {
+        return Err(DeliveryError::Status(response.status()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies_are_signed_with_hmac_sha256() {
+        // RFC 4231, test case 2.
+        assert_eq!(sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let config = WebhookConfig {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            ..WebhookConfig::default()
+        };
+
+        let backoffs: Vec<_> = (0..5).map(|retry| config.backoff(retry).as_secs()).collect();
+        assert_eq!(backoffs, vec![1, 2, 4, 8, 10]);
+        assert_eq!(config.backoff(40), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn only_transient_failures_are_retried() {
+        assert!(DeliveryError::Status(StatusCode::BAD_GATEWAY).is_retryable());
+        assert!(DeliveryError::Status(StatusCode::TOO_MANY_REQUESTS).is_retryable());
+        assert!(!DeliveryError::Status(StatusCode::NOT_FOUND).is_retryable());
+    }
+}
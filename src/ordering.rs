@@ -0,0 +1,197 @@
+//! Putting records back in slot order before they reach stateful consumers.
+//!
+//! Blocks aren't always delivered in order: the poller fetches several at once, backfills and
+//! replays overlap with the tip. Raw rows don't mind, each being keyed on its own, but what's
+//! derived from a running state (lending positions, aggregates) does: applied out of order, an
+//! older block would overwrite what a newer one derived.
+//!
+//! A [`ReorderingBuffer`] holds records by slot until the watermark (the latest slot seen) is more
+//! than its window past their slot, then releases them in slot order. It holds at most `capacity`
+//! records: past it, the oldest slots are released early, out of order if need be. Records of
+//! slots released already are released on the next drain, out of order too. Both are logged and
+//! counted; consumers keep guarding against applying what's older than their state.
+
+use std::collections::BTreeMap;
+
+use tracing::warn;
+
+/// How many records a buffer holds at most by default.
+pub const DEFAULT_MAX_PENDING: usize = 100_000;
+
+/// Records by slot, released in slot order once the watermark is more than `window` slots past.
+pub struct ReorderingBuffer<T> {
+    // What holds the buffer, for the logs.
+    name: &'static str,
+    window: u64,
+    capacity: usize,
+    pending: BTreeMap<u64, Vec<T>>,
+    held: usize,
+    watermark: Option<u64>,
+    // The latest slot released, whose records (or older ones) arrive too late to be put in order.
+    released: Option<u64>,
+    late_records: u64,
+    overflowed_records: u64,
+}
+
+impl<T> ReorderingBuffer<T> {
+    pub fn new(name: &'static str, window: u64, capacity: usize) -> Self {
+        ReorderingBuffer {
+            name,
+            window,
+            capacity,
+            pending: BTreeMap::new(),
+            held: 0,
+            watermark: None,
+            released: None,
+            late_records: 0,
+            overflowed_records: 0,
+        }
+    }
+
+    /// The latest slot seen.
+    pub fn watermark(&self) -> Option<u64> {
+        self.watermark
+    }
+
+    /// How many records are held.
+    pub fn len(&self) -> usize {
+        self.held
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held == 0
+    }
+
+    /// How many records arrived after their slot was released, since the start.
+    pub fn late_records(&self) -> u64 {
+        self.late_records
+    }
+
+    /// How many records were released early as the buffer was full, since the start.
+    pub fn overflowed_records(&self) -> u64 {
+        self.overflowed_records
+    }
+
+    /// Moves the watermark to `slot` if it's past it, e.g. for a block without any record.
+    pub fn advance(&mut self, slot: u64) {
+        self.watermark = Some(self.watermark.map_or(slot, |mark| mark.max(slot)));
+    }
+
+    /// Holds `record` until `slot` is released.
+    pub fn push(&mut self, slot: u64, record: T) {
+        self.advance(slot);
+        if self.released.map_or(false, |released| slot <= released) {
+            self.late_records += 1;
+            warn!("[spi-wrapper/{}] A record of slot {} arrived after slot {} was applied, it's \
+                applied out of order.", self.name, slot, self.released.unwrap_or_default());
+        }
+        self.pending.entry(slot).or_default().push(record);
+        self.held += 1;
+    }
+
+    /// Releases the slots the watermark is more than `window` slots past, along with the late
+    /// records and the oldest slots over the capacity, in slot order.
+    pub fn drain_ready(&mut self) -> Vec<(u64, Vec<T>)> {
+        let ready_before = match self.watermark {
+            Some(mark) => mark.saturating_sub(self.window),
+            None => return Vec::new(),
+        };
+        let still_pending = self.pending.split_off(&ready_before);
+        let ready = std::mem::replace(&mut self.pending, still_pending);
+        let mut released = self.release(ready);
+
+        let mut overflowed = 0;
+        while self.held > self.capacity {
+            let slot = match self.pending.keys().next() {
+                Some(slot) => *slot,
+                None => break,
+            };
+            let records = self.pending.remove(&slot).unwrap_or_default();
+            overflowed += records.len();
+            let mut early = BTreeMap::new();
+            early.insert(slot, records);
+            released.extend(self.release(early));
+        }
+        if overflowed > 0 {
+            self.overflowed_records += overflowed as u64;
+            warn!("[spi-wrapper/{}] More than {} records pending, {} were applied before the \
+                watermark was past their slot.", self.name, self.capacity, overflowed);
+        }
+
+        released
+    }
+
+    /// Releases every slot, e.g. before shutting down.
+    pub fn drain(&mut self) -> Vec<(u64, Vec<T>)> {
+        let pending = std::mem::take(&mut self.pending);
+        self.release(pending)
+    }
+
+    /// Drops the slots at or after `slot`, their blocks having been replaced by a fork, and
+    /// moves the watermark back before it.
+    pub fn rollback_from_slot(&mut self, slot: u64) {
+        let dropped = self.pending.split_off(&slot);
+        self.held -= dropped.values().map(Vec::len).sum::<usize>();
+        self.watermark = self.watermark.map(|mark| mark.min(slot.saturating_sub(1)));
+        self.released = self.released.map(|released| released.min(slot.saturating_sub(1)));
+    }
+
+    /// Drops the given slots, their blocks having been abandoned.
+    pub fn invalidate(&mut self, slots: &[u64]) {
+        for slot in slots {
+            if let Some(records) = self.pending.remove(slot) {
+                self.held -= records.len();
+            }
+        }
+    }
+
+    fn release(&mut self, slots: BTreeMap<u64, Vec<T>>) -> Vec<(u64, Vec<T>)> {
+        self.held -= slots.values().map(Vec::len).sum::<usize>();
+        if let Some(last) = slots.keys().next_back() {
+            self.released = Some(self.released.map_or(*last, |released| released.max(*last)));
+        }
+
+        slots.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slots<T>(released: &[(u64, Vec<T>)]) -> Vec<u64> {
+        released.iter().map(|(slot, _)| *slot).collect()
+    }
+
+    #[test]
+    fn slots_are_released_in_order_once_the_watermark_is_past_them() {
+        let mut buffer = ReorderingBuffer::new("test", 2, DEFAULT_MAX_PENDING);
+        for slot in &[12, 10, 11, 13] {
+            buffer.push(*slot, *slot);
+        }
+        assert_eq!(buffer.drain_ready(), vec![(10, vec![10])]);
+
+        buffer.advance(20);
+        assert_eq!(slots(&buffer.drain_ready()), vec![11, 12, 13]);
+        assert!(buffer.is_empty());
+
+        // Too late to be put in order, but not lost.
+        buffer.push(11, 11);
+        assert_eq!(buffer.late_records(), 1);
+        assert_eq!(buffer.drain_ready(), vec![(11, vec![11])]);
+    }
+
+    #[test]
+    fn the_oldest_slots_are_released_early_past_the_capacity() {
+        let mut buffer = ReorderingBuffer::new("test", 100, 3);
+        for slot in &[5, 1, 2, 2, 3] {
+            buffer.push(*slot, *slot);
+        }
+
+        assert_eq!(slots(&buffer.drain_ready()), vec![1, 2]);
+        assert_eq!((buffer.len(), buffer.overflowed_records()), (2, 3));
+        buffer.rollback_from_slot(4);
+        assert_eq!((buffer.len(), buffer.watermark()), (1, Some(3)));
+        assert_eq!(buffer.drain(), vec![(3, vec![3])]);
+    }
+}
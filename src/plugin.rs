@@ -0,0 +1,243 @@
+//! Processors loaded at runtime from shared libraries.
+//!
+//! A plugin is a `cdylib` depending on this crate with the `plugins` feature, implementing
+//! [`PluginProcessor`] and exporting it with [`declare_processor!`]:
+//!
+//! ```ignore
+//! struct MyProcessor;
+//!
+//! impl PluginProcessor for MyProcessor { ... }
+//!
+//! spi_wrapper::declare_processor!(MyProcessor);
+//! ```
+//!
+//! Plugins don't share the host's symbol table, so they hand out plain strings which the host
+//! interns. Rust has no stable ABI: a plugin has to be built against the same version of this
+//! crate (which is checked when it's loaded) with the same compiler as the host, and both have
+//! to use the system allocator.
+
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use libloading::Library;
+use thiserror::Error;
+use tracing::info;
+
+use crate::interner::Symbol;
+use crate::registry::{ProcessorRegistry, ProgramProcessor};
+use crate::{
+    Instruction, InstructionFunction, InstructionProperty, InstructionSet, Properties, PropertyType,
+};
+
+/// Bumped whenever the types crossing the plugin boundary change.
+pub const ABI_VERSION: u32 = 1;
+/// The version of this crate, NUL terminated so it can be handed out as a C string.
+pub const CORE_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+/// Errors that may be returned while loading a plugin.
+#[derive(Debug, Error)]
+pub enum PluginError {
+    #[error("Unable to load the plugin {path}: {source}")]
+    Load { path: PathBuf, source: libloading::Error },
+    #[error("{path} is not a processor plugin (missing `{symbol}`)")]
+    MissingSymbol { path: PathBuf, symbol: &'static str },
+    #[error("{path} was built for plugin ABI {plugin}, this build expects {host}")]
+    AbiMismatch { path: PathBuf, plugin: u32, host: u32 },
+    #[error("{path} was built against spi-wrapper {plugin}, this build is {host}")]
+    VersionMismatch { path: PathBuf, plugin: String, host: String },
+}
+
+/// A decoded instruction, as handed out by a plugin.
+#[derive(Clone, Debug, Default)]
+pub struct DecodedInstruction {
+    pub function_name: String,
+    pub properties: Vec<DecodedProperty>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DecodedProperty {
+    pub key: String,
+    pub value: String,
+    // Empty for top-level properties.
+    pub parent_key: String,
+}
+
+/// The processor a plugin exports.
+pub trait PluginProcessor: Send + Sync {
+    /// The name the processor goes by in logs.
+    fn name(&self) -> &str;
+
+    /// The program ids the processor decodes.
+    fn program_ids(&self) -> Vec<String>;
+
+    /// Decodes the data of an instruction of one of the programs, or returns `None` when it
+    /// can't.
+    fn decode(&self, data: &[u8]) -> Option<DecodedInstruction>;
+}
+
+/// Exports a [`PluginProcessor`] from a plugin, built with `Default::default()` or the given
+/// constructor.
+#[macro_export]
+macro_rules! declare_processor {
+    ($processor:ty) => {
+        $crate::declare_processor!($processor, <$processor as Default>::default);
+    };
+    ($processor:ty, $constructor:expr) => {
+        #[no_mangle]
+        pub extern "C" fn _spi_plugin_abi_version() -> u32 {
+            $crate::plugin::ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub extern "C" fn _spi_plugin_core_version() -> *const std::os::raw::c_char {
+            $crate::plugin::CORE_VERSION.as_ptr() as *const std::os::raw::c_char
+        }
+
+        #[no_mangle]
+        #[allow(improper_ctypes_definitions)]
+        /// # Safety
+        ///
+        /// This function returns the processor pointer as trait PluginProcessor.
+        pub unsafe extern "C" fn _create_processor() -> *mut dyn $crate::plugin::PluginProcessor {
+            let processor: Box<$processor> = Box::new(($constructor)());
+            let processor: Box<dyn $crate::plugin::PluginProcessor> = processor;
+            Box::into_raw(processor)
+        }
+    };
+}
+
+type AbiVersion = extern "C" fn() -> u32;
+type CoreVersion = extern "C" fn() -> *const c_char;
+#[allow(improper_ctypes_definitions)]
+type CreateProcessor = unsafe extern "C" fn() -> *mut dyn PluginProcessor;
+
+/// A processor loaded from a plugin, along with the library it lives in.
+pub struct LoadedPlugin {
+    // Dropped before the library unloads.
+    processor: Box<dyn PluginProcessor>,
+    path: PathBuf,
+    _library: Library,
+}
+
+impl LoadedPlugin {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl ProgramProcessor for LoadedPlugin {
+    fn name(&self) -> &str {
+        self.processor.name()
+    }
+
+    fn program_ids(&self) -> Vec<String> {
+        self.processor.program_ids()
+    }
+
+    fn decode(&self, instruction: Instruction) -> Option<InstructionSet> {
+        let decoded = self.processor.decode(&instruction.data)?;
+        let properties: Properties = decoded.properties.into_iter()
+            .map(|property| InstructionProperty {
+                tx_instruction_id: instruction.tx_instruction_id,
+                transaction_hash: instruction.transaction_hash.clone(),
+                parent_index: instruction.parent_index,
+                key: Symbol::intern(&property.key),
+                value_type: PropertyType::infer(&property.value),
+                value: property.value,
+                parent_key: Symbol::intern(&property.parent_key),
+                timestamp: instruction.timestamp,
+            })
+            .collect();
+
+        Some(InstructionSet {
+            function: InstructionFunction {
+                tx_instruction_id: instruction.tx_instruction_id,
+                transaction_hash: instruction.transaction_hash,
+                parent_index: instruction.parent_index,
+                program: instruction.program,
+                function_name: Symbol::intern(&decoded.function_name),
+                timestamp: instruction.timestamp,
+                network: instruction.network,
+                decoder_version: 0,
+            },
+            properties,
+            raw_data: None,
+        })
+    }
+}
+
+/// Loads processor plugins and registers them into a registry.
+#[derive(Default)]
+pub struct PluginLoader {
+    plugins: Vec<Arc<LoadedPlugin>>,
+}
+
+impl PluginLoader {
+    pub fn new() -> Self {
+        PluginLoader::default()
+    }
+
+    /// Loads the plugin at `path`, checking it was built against this version of the crate.
+    ///
+    /// Loading a library runs its initialization code: only load plugins you trust.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<Arc<LoadedPlugin>, PluginError> {
+        let path = path.as_ref().to_path_buf();
+        let library = unsafe { Library::new(&path) }
+            .map_err(|source| PluginError::Load { path: path.clone(), source })?;
+
+        let processor = unsafe {
+            let abi_version = symbol::<AbiVersion>(&library, &path, "_spi_plugin_abi_version")?;
+            if abi_version() != ABI_VERSION {
+                return Err(PluginError::AbiMismatch {
+                    path,
+                    plugin: abi_version(),
+                    host: ABI_VERSION,
+                });
+            }
+            let core_version = symbol::<CoreVersion>(&library, &path, "_spi_plugin_core_version")?;
+            let plugin_version = CStr::from_ptr(core_version()).to_string_lossy().into_owned();
+            let host_version = CORE_VERSION.trim_end_matches('\0');
+            if plugin_version != host_version {
+                return Err(PluginError::VersionMismatch {
+                    path,
+                    plugin: plugin_version,
+                    host: host_version.to_string(),
+                });
+            }
+
+            let create = symbol::<CreateProcessor>(&library, &path, "_create_processor")?;
+            Box::from_raw(create())
+        };
+
+        info!("[spi-wrapper/plugin] Loaded the {} processor from {}.", processor.name(),
+            path.display());
+        let plugin = Arc::new(LoadedPlugin { processor, path, _library: library });
+        self.plugins.push(plugin.clone());
+
+        Ok(plugin)
+    }
+
+    /// Registers every plugin loaded so far under the program ids it declares.
+    pub fn register(&self, registry: &mut ProcessorRegistry) {
+        for plugin in &self.plugins {
+            for program_id in plugin.program_ids() {
+                registry.register_processor(&program_id, plugin.clone());
+            }
+        }
+    }
+}
+
+unsafe fn symbol<T: Copy>(
+    library: &Library,
+    path: &Path,
+    name: &'static str,
+) -> Result<T, PluginError> {
+    let mut symbol_name = name.as_bytes().to_vec();
+    symbol_name.push(0);
+
+    library.get::<T>(&symbol_name)
+        .map(|symbol| *symbol)
+        .map_err(|_| PluginError::MissingSymbol { path: path.to_path_buf(), symbol: name })
+}
@@ -0,0 +1,685 @@
+//! The version of the program each instruction was invoked on, as the slot the program was last
+//! deployed or upgraded at.
+//!
+//! Programs of the upgradeable BPF loader are deployed and upgraded through its
+//! `DeployWithMaxDataLen` and `Upgrade` instructions, which [`ProgramVersions`] learns of from the
+//! successful transactions decoded. Programs it knows no upgrade of are, when a resolver is
+//! running, looked up through their ProgramData account, which holds the slot of their latest
+//! deployment only; [`ProgramVersions::backfill`] goes through the history of that account for
+//! every earlier one. Decoding never waits on the resolver.
+//!
+//! An upgrade takes effect from the slot after it, so instructions are stamped with the latest
+//! upgrade strictly before the slot of their transaction, as `program_version_slot`. Instructions
+//! of programs with no upgrade known before their slot aren't stamped.
+//!
+//! The upgrades learnt are persisted through the sink by [`ProgramVersionSink`], and restored from
+//! it when a pipeline is built (see [`Sink::program_upgrades`]).
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_sdk::account::Account;
+use solana_sdk::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::loader_upgradeable_instruction::UpgradeableLoaderInstruction;
+use solana_sdk::program_utils::limited_deserialize;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+use crate::account::AccountRecord;
+use crate::aggregate::WindowAggregate;
+use crate::block::BlockRecord;
+use crate::checkpoint::Checkpoint;
+use crate::ingest::pool::RpcPool;
+use crate::ingest::timestamps::TimestampUpdate;
+use crate::ingest::{fetch_transactions, IngestError};
+use crate::interner::Symbol;
+use crate::lending::positions::PositionSnapshot;
+use crate::network::Network;
+use crate::reindex::{StaleFilter, StaleTransaction};
+use crate::reprocess::{RawFilter, RawInstruction};
+use crate::sinks::{ReadFilter, RowCounts, Sink, SinkError};
+use crate::transaction::{RawTransaction, TransactionRecord};
+use crate::{InstructionProperty, InstructionSet, PropertyType};
+
+/// The key of the property instructions are stamped with.
+pub const VERSION_KEY: &str = "program_version_slot";
+// How many programs may wait for the resolver before new ones are dropped.
+const PENDING_PROGRAMS: usize = 1024;
+// How long before the programs whose ProgramData couldn't be fetched are requested again.
+const RETRY_AFTER: Duration = Duration::from_secs(60);
+// How many signatures are asked for per `getSignaturesForAddress` page by backfills.
+const SIGNATURES_PER_PAGE: usize = 1000;
+
+/// A deployment or an upgrade of a program.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProgramUpgrade {
+    pub network: Network,
+    pub program: Symbol,
+    // The slot the upgrade landed in; it takes effect from the next one.
+    pub slot: u64,
+    // The transaction that upgraded the program, unknown when the slot was read off its
+    // ProgramData account.
+    pub transaction_hash: Option<String>,
+}
+
+/// The upgrades of programs, shared by its clones.
+#[derive(Clone, Debug, Default)]
+pub struct ProgramVersions {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    // The slots of the upgrades of every program, by network.
+    upgrades: HashMap<(Network, Symbol), BTreeSet<u64>>,
+    // The upgrades learnt since they were last taken, to be persisted.
+    learnt: Vec<ProgramUpgrade>,
+    // Where programs without upgrades go when a resolver is running, and those handed to it.
+    misses: Option<mpsc::Sender<(Network, Symbol)>>,
+    requested: HashSet<(Network, Symbol)>,
+}
+
+impl Inner {
+    fn insert(&mut self, upgrade: &ProgramUpgrade) -> bool {
+        self.upgrades.entry((upgrade.network, upgrade.program)).or_default().insert(upgrade.slot)
+    }
+
+    fn request(&mut self, program: (Network, Symbol)) {
+        let misses = match &self.misses {
+            Some(misses) => misses,
+            None => return,
+        };
+        if self.requested.contains(&program) {
+            return;
+        }
+        // A full queue drops the miss, it's requested again by the next instruction of the
+        // program.
+        if misses.try_send(program).is_ok() {
+            self.requested.insert(program);
+        }
+    }
+}
+
+impl ProgramVersions {
+    pub fn new() -> Self {
+        ProgramVersions::default()
+    }
+
+    /// Records an upgrade, to be persisted (see [`take_learnt`](Self::take_learnt)) unless it
+    /// was known already. Returns whether it wasn't.
+    pub fn record(&self, upgrade: ProgramUpgrade) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let new = inner.insert(&upgrade);
+        if new {
+            inner.learnt.push(upgrade);
+        }
+
+        new
+    }
+
+    /// Adds upgrades persisted before, which aren't persisted again.
+    pub fn restore(&self, upgrades: &[ProgramUpgrade]) {
+        let mut inner = self.inner.lock().unwrap();
+        for upgrade in upgrades {
+            inner.insert(upgrade);
+        }
+    }
+
+    /// The slot `program` was last upgraded at before `slot`, if known.
+    pub fn version_at(&self, network: Network, program: Symbol, slot: u64) -> Option<u64> {
+        let inner = self.inner.lock().unwrap();
+        inner.upgrades.get(&(network, program))?.range(..slot).next_back().copied()
+    }
+
+    /// The slots of the upgrades of `program` known, oldest first.
+    pub fn upgrades(&self, network: Network, program: Symbol) -> Vec<u64> {
+        let inner = self.inner.lock().unwrap();
+        inner.upgrades.get(&(network, program))
+            .map_or_else(Vec::new, |slots| slots.iter().copied().collect())
+    }
+
+    /// The upgrades learnt since they were last taken.
+    pub fn take_learnt(&self) -> Vec<ProgramUpgrade> {
+        std::mem::take(&mut self.inner.lock().unwrap().learnt)
+    }
+
+    /// Hands upgrades taken back, when they couldn't be persisted.
+    fn untake(&self, upgrades: Vec<ProgramUpgrade>) {
+        let mut inner = self.inner.lock().unwrap();
+        let learnt = std::mem::replace(&mut inner.learnt, upgrades);
+        inner.learnt.extend(learnt);
+    }
+
+    /// Forgets the upgrades of `network` at or after `slot`, whose blocks were replaced by a fork.
+    pub fn rollback_from_slot(&self, network: Network, slot: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        for ((upgrade_network, _), slots) in inner.upgrades.iter_mut() {
+            if *upgrade_network == network {
+                slots.split_off(&slot);
+            }
+        }
+        inner.learnt.retain(|upgrade| upgrade.network != network || upgrade.slot < slot);
+    }
+
+    /// Forgets the upgrades of `network` in `slots`, whose blocks were abandoned.
+    pub fn invalidate(&self, network: Network, slots: &[u64]) {
+        let mut inner = self.inner.lock().unwrap();
+        for ((upgrade_network, _), upgrade_slots) in inner.upgrades.iter_mut() {
+            if *upgrade_network == network {
+                for slot in slots {
+                    upgrade_slots.remove(slot);
+                }
+            }
+        }
+        inner.learnt.retain(|upgrade| upgrade.network != network || !slots.contains(&upgrade.slot));
+    }
+
+    /// Learns the programs `transaction` deployed or upgraded, if it succeeded.
+    pub fn observe(&self, network: Network, transaction: &RawTransaction) {
+        if !transaction.succeeded {
+            return;
+        }
+
+        let loader = bpf_loader_upgradeable::id();
+        let compiled = transaction.instructions.iter()
+            .chain(transaction.inner_instructions.iter().flat_map(|inner| &inner.instructions));
+        for instruction in compiled {
+            let program_id = transaction.account_keys.get(instruction.program_id_index as usize);
+            if program_id != Some(&loader) {
+                continue;
+            }
+            let decoded = limited_deserialize::<UpgradeableLoaderInstruction>(&instruction.data);
+            // The position of the program among the accounts of the instruction.
+            let position = match decoded {
+                Ok(UpgradeableLoaderInstruction::DeployWithMaxDataLen { .. }) => 2,
+                Ok(UpgradeableLoaderInstruction::Upgrade) => 1,
+                _ => continue,
+            };
+            let program = instruction.accounts.get(position)
+                .and_then(|index| transaction.account_keys.get(*index as usize));
+            if let Some(program) = program {
+                self.record(ProgramUpgrade {
+                    network,
+                    program: Symbol::intern(&program.to_string()),
+                    slot: transaction.slot,
+                    transaction_hash: Some(transaction.transaction_hash.clone()),
+                });
+            }
+        }
+    }
+
+    /// Adds the `program_version_slot` of `instruction_set`, of a transaction at `slot`, when an
+    /// upgrade of its program before `slot` is known. Programs no upgrade is known of are handed
+    /// to the resolver, if any, without waiting for it.
+    pub fn stamp(&self, instruction_set: &mut InstructionSet, slot: u64) {
+        let function = &instruction_set.function;
+        let version = {
+            let mut inner = self.inner.lock().unwrap();
+            let program = (function.network, function.program);
+            match inner.upgrades.get(&program) {
+                Some(slots) => slots.range(..slot).next_back().copied(),
+                None => {
+                    inner.request(program);
+                    None
+                }
+            }
+        };
+
+        if let Some(version) = version {
+            instruction_set.properties.push(InstructionProperty {
+                tx_instruction_id: function.tx_instruction_id,
+                transaction_hash: function.transaction_hash.clone(),
+                parent_index: function.parent_index,
+                key: Symbol::from(VERSION_KEY),
+                value: version.to_string(),
+                value_type: PropertyType::U64,
+                parent_key: Symbol::from(""),
+                timestamp: function.timestamp,
+            });
+        }
+    }
+
+    /// Resolves the latest deployment of the programs no upgrade is known of by fetching their
+    /// ProgramData accounts through `rpc`, in the background, until the returned task is aborted.
+    /// Programs that aren't upgradeable are only looked up once.
+    pub fn resolve_with(&self, rpc: Arc<RpcPool>) -> JoinHandle<()> {
+        let (sender, mut misses) = mpsc::channel(PENDING_PROGRAMS);
+        self.inner.lock().unwrap().misses = Some(sender);
+
+        let versions = self.clone();
+        tokio::spawn(async move {
+            while let Some(program) = misses.recv().await {
+                let mut programs = vec![program];
+                while programs.len() < rpc.batch_size() {
+                    match misses.try_recv() {
+                        Ok(program) => programs.push(program),
+                        Err(_) => break,
+                    }
+                }
+                let (programs, addresses): (Vec<_>, Vec<_>) = programs.into_iter()
+                    .filter_map(|(network, program)| {
+                        let address = program_data_address(&program.parse().ok()?);
+                        Some(((network, program), address))
+                    })
+                    .unzip();
+
+                match rpc.get_multiple_accounts(&addresses, CommitmentConfig::confirmed()).await {
+                    Ok(accounts) => {
+                        for ((network, program), account) in programs.into_iter().zip(accounts) {
+                            match account.as_ref().and_then(deployed_slot) {
+                                Some(slot) => {
+                                    versions.record(ProgramUpgrade {
+                                        network,
+                                        program,
+                                        slot,
+                                        transaction_hash: None,
+                                    });
+                                }
+                                None => debug!(%program,
+                                    "[spi-wrapper/program-versions] Not an upgradeable program."),
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        debug!(%err, "[spi-wrapper/program-versions] Unable to fetch ProgramData \
+                            accounts.");
+                        let versions = versions.clone();
+                        tokio::spawn(async move {
+                            tokio::time::sleep(RETRY_AFTER).await;
+                            let mut inner = versions.inner.lock().unwrap();
+                            for program in &programs {
+                                inner.requested.remove(program);
+                            }
+                        });
+                    }
+                }
+            }
+        })
+    }
+
+    /// Learns every deployment and upgrade of `program` from the history of its ProgramData
+    /// account, fetching its transactions through `rpc`. Returns the slots of the upgrades known
+    /// of the program, oldest first. What's learnt is persisted along with the next writes of a
+    /// [`ProgramVersionSink`], or taken with [`take_learnt`](Self::take_learnt).
+    pub async fn backfill(
+        &self,
+        rpc: &Arc<RpcPool>,
+        network: Network,
+        program: &Pubkey,
+        commitment: CommitmentConfig,
+    ) -> Result<Vec<u64>, IngestError> {
+        let address = program_data_address(program);
+        let mut before = None;
+        loop {
+            let page = rpc.call(move |rpc| {
+                rpc.get_signatures_for_address_with_config(&address,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until: None,
+                        limit: Some(SIGNATURES_PER_PAGE),
+                        commitment: Some(commitment),
+                    })
+            }).await?;
+
+            let signatures: Vec<_> = page.iter()
+                .filter(|status| status.err.is_none())
+                .map(|status| status.signature.clone())
+                .collect();
+            let transactions = fetch_transactions(rpc, &signatures, commitment).await?;
+            for transaction in transactions.iter().flatten() {
+                self.observe(network, transaction);
+            }
+
+            before = page.last().and_then(|status| Signature::from_str(&status.signature).ok());
+            if page.len() < SIGNATURES_PER_PAGE || before.is_none() {
+                break;
+            }
+        }
+
+        Ok(self.upgrades(network, Symbol::intern(&program.to_string())))
+    }
+}
+
+/// The ProgramData account of an upgradeable program, which holds its code and the slot it was
+/// last deployed at.
+pub fn program_data_address(program: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[program.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+/// The slot the ProgramData `account` was last deployed at, if it is one.
+fn deployed_slot(account: &Account) -> Option<u64> {
+    if account.owner != bpf_loader_upgradeable::id() {
+        return None;
+    }
+
+    match limited_deserialize::<UpgradeableLoaderState>(&account.data) {
+        Ok(UpgradeableLoaderState::ProgramData { slot, .. }) => Some(slot),
+        _ => None,
+    }
+}
+
+/// Persists the upgrades [`ProgramVersions`] learns to the wrapped sink, after the transactions
+/// they were learnt from, and forgets those rolled back or invalidated along with their slots.
+/// Upgrades learnt by the resolver alone are persisted on the next write or flush.
+pub struct ProgramVersionSink<S> {
+    inner: S,
+    versions: ProgramVersions,
+    // The network whose slots are rolled back and invalidated.
+    network: Network,
+}
+
+impl<S: Sink> ProgramVersionSink<S> {
+    pub fn new(inner: S, versions: ProgramVersions, network: Network) -> Self {
+        ProgramVersionSink { inner, versions, network }
+    }
+
+    pub fn versions(&self) -> &ProgramVersions {
+        &self.versions
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    async fn persist(&mut self) -> Result<(), SinkError> {
+        let learnt = self.versions.take_learnt();
+        if learnt.is_empty() {
+            return Ok(());
+        }
+
+        let result = crate::sinks::write_program_upgrades(&mut self.inner, &learnt).await;
+        if result.is_err() {
+            self.versions.untake(learnt);
+        }
+
+        result
+    }
+}
+
+#[async_trait]
+impl<S: Sink> Sink for ProgramVersionSink<S> {
+    async fn write_block(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        self.inner.write_block(block).await?;
+        self.persist().await
+    }
+
+    async fn write_block_header(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        self.inner.write_block_header(block).await
+    }
+
+    async fn write_transactions(
+        &mut self,
+        transactions: &[TransactionRecord],
+    ) -> Result<(), SinkError> {
+        self.inner.write_transactions(transactions).await?;
+        self.persist().await
+    }
+
+    async fn write_accounts(&mut self, accounts: &[AccountRecord]) -> Result<(), SinkError> {
+        self.inner.write_accounts(accounts).await
+    }
+
+    async fn write_aggregates(&mut self, aggregates: &[WindowAggregate]) -> Result<(), SinkError> {
+        self.inner.write_aggregates(aggregates).await
+    }
+
+    async fn write_positions(&mut self, snapshots: &[PositionSnapshot]) -> Result<(), SinkError> {
+        self.inner.write_positions(snapshots).await
+    }
+
+    async fn latest_positions(&mut self) -> Result<Vec<PositionSnapshot>, SinkError> {
+        self.inner.latest_positions().await
+    }
+
+    async fn write_program_upgrades(
+        &mut self,
+        upgrades: &[ProgramUpgrade],
+    ) -> Result<(), SinkError> {
+        self.inner.write_program_upgrades(upgrades).await
+    }
+
+    async fn program_upgrades(&mut self) -> Result<Vec<ProgramUpgrade>, SinkError> {
+        self.inner.program_upgrades().await
+    }
+
+    async fn mark_finalized(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        self.inner.mark_finalized(slots).await
+    }
+
+    async fn invalidate(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        self.inner.invalidate(slots).await?;
+        self.versions.invalidate(self.network, slots);
+
+        Ok(())
+    }
+
+    async fn rollback_from_slot(&mut self, slot: u64) -> Result<(), SinkError> {
+        self.inner.rollback_from_slot(slot).await?;
+        self.versions.rollback_from_slot(self.network, slot);
+
+        Ok(())
+    }
+
+    async fn has_transaction(&mut self, signature: &str) -> Result<Option<bool>, SinkError> {
+        self.inner.has_transaction(signature).await
+    }
+
+    async fn raw_instructions(
+        &mut self,
+        filter: &RawFilter,
+    ) -> Result<Vec<RawInstruction>, SinkError> {
+        self.inner.raw_instructions(filter).await
+    }
+
+    async fn write_reprocessed(
+        &mut self,
+        instruction_sets: &[InstructionSet],
+    ) -> Result<(), SinkError> {
+        self.inner.write_reprocessed(instruction_sets).await
+    }
+
+    async fn stale_transactions(
+        &mut self,
+        filter: &StaleFilter,
+    ) -> Result<Vec<StaleTransaction>, SinkError> {
+        self.inner.stale_transactions(filter).await
+    }
+
+    async fn read_transactions(
+        &mut self,
+        filter: &ReadFilter,
+    ) -> Result<Vec<TransactionRecord>, SinkError> {
+        self.inner.read_transactions(filter).await
+    }
+
+    async fn row_counts(
+        &mut self,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Option<RowCounts>, SinkError> {
+        self.inner.row_counts(first_slot, last_slot).await
+    }
+
+    async fn estimated_timestamps(
+        &mut self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, i64)>, SinkError> {
+        self.inner.estimated_timestamps(after, limit).await
+    }
+
+    async fn update_timestamps(&mut self, updates: &[TimestampUpdate]) -> Result<(), SinkError> {
+        self.inner.update_timestamps(updates).await
+    }
+
+    fn supports_transactions(&self) -> bool {
+        self.inner.supports_transactions()
+    }
+
+    async fn begin(&mut self) -> Result<(), SinkError> {
+        self.inner.begin().await
+    }
+
+    async fn commit(
+        &mut self,
+        pipeline: &str,
+        checkpoint: Option<&Checkpoint>,
+    ) -> Result<(), SinkError> {
+        // What the resolver learnt since the last write is committed along.
+        self.persist().await?;
+        self.inner.commit(pipeline, checkpoint).await
+    }
+
+    async fn committed_checkpoint(
+        &mut self,
+        pipeline: &str,
+    ) -> Result<Option<Checkpoint>, SinkError> {
+        self.inner.committed_checkpoint(pipeline).await
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        self.persist().await?;
+        self.inner.flush().await
+    }
+
+    async fn shutdown(&mut self) -> Result<(), SinkError> {
+        self.persist().await?;
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::instruction::CompiledInstruction;
+
+    use super::*;
+    use crate::registry::ProcessorRegistry;
+    use crate::transaction::decode_transaction;
+
+    // The accounts of the transactions: the payer, the program, the loader, the ProgramData
+    // account and the buffer.
+    fn transaction(
+        program: &Pubkey,
+        slot: u64,
+        signature: &str,
+        instructions: Vec<CompiledInstruction>,
+    ) -> RawTransaction {
+        RawTransaction {
+            slot,
+            block_time: Some(1_000 + slot as i64),
+            transaction_hash: signature.to_string(),
+            account_keys: vec![
+                Pubkey::new_unique(),
+                *program,
+                bpf_loader_upgradeable::id(),
+                program_data_address(program),
+                Pubkey::new_unique(),
+            ],
+            num_required_signatures: 1,
+            instructions,
+            inner_instructions: vec![],
+            token_mints: vec![],
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        }
+    }
+
+    fn invoke() -> CompiledInstruction {
+        CompiledInstruction { program_id_index: 1, accounts: vec![0], data: vec![1] }
+    }
+
+    fn upgrade() -> CompiledInstruction {
+        CompiledInstruction {
+            program_id_index: 2,
+            accounts: vec![3, 1, 4, 0],
+            data: bincode::serialize(&UpgradeableLoaderInstruction::Upgrade).unwrap(),
+        }
+    }
+
+    fn stamp(record: &TransactionRecord) -> Option<&str> {
+        let call = record.instruction_sets.iter()
+            .find(|instruction_set| instruction_set.function.function_name.as_str() == "call")
+            .unwrap();
+        call.properties.iter()
+            .find(|property| property.key.as_str() == VERSION_KEY)
+            .map(|property| property.value.as_str())
+    }
+
+    #[test]
+    fn instructions_are_stamped_with_the_upgrade_before_their_slot() {
+        let program = Pubkey::new_unique();
+        let versions = ProgramVersions::new();
+        let registry = ProcessorRegistry::builder()
+            .register_fn(&program.to_string(), |ctx| Ok(vec![ctx.instruction_set("call", vec![])]))
+            .program_versions(versions.clone())
+            .build();
+        let decode = |slot, signature, instructions| {
+            decode_transaction(&registry, &transaction(&program, slot, signature, instructions))
+        };
+        let symbol = Symbol::intern(&program.to_string());
+        versions.restore(&[ProgramUpgrade {
+            network: Network::Mainnet,
+            program: symbol,
+            slot: 10,
+            transaction_hash: None,
+        }]);
+
+        assert_eq!(stamp(&decode(90, "before", vec![invoke()])), Some("10"));
+        // The upgrade takes effect from the next slot, not for what's invoked along with it.
+        assert_eq!(stamp(&decode(100, "upgrade", vec![upgrade(), invoke()])), Some("10"));
+        assert_eq!(stamp(&decode(100, "same-slot", vec![invoke()])), Some("10"));
+        assert_eq!(stamp(&decode(101, "after", vec![invoke()])), Some("100"));
+        // Transactions decoded out of order are stamped with the version of their own slot.
+        assert_eq!(stamp(&decode(95, "late", vec![invoke()])), Some("10"));
+        assert_eq!(stamp(&decode(5, "first", vec![invoke()])), None);
+
+        assert_eq!(versions.take_learnt(), vec![ProgramUpgrade {
+            network: Network::Mainnet,
+            program: symbol,
+            slot: 100,
+            transaction_hash: Some("upgrade".to_string()),
+        }]);
+        versions.rollback_from_slot(Network::Mainnet, 100);
+        assert_eq!(stamp(&decode(101, "forked", vec![invoke()])), Some("10"));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn upgrades_are_persisted_through_the_sink() {
+        use crate::sinks::sqlite::SqliteSink;
+
+        let program = Pubkey::new_unique();
+        let versions = ProgramVersions::new();
+        let registry = ProcessorRegistry::builder()
+            .register_fn(&program.to_string(), |ctx| Ok(vec![ctx.instruction_set("call", vec![])]))
+            .program_versions(versions.clone())
+            .build();
+        let mut sink = ProgramVersionSink::new(
+            SqliteSink::open(":memory:").unwrap(), versions.clone(), Network::Mainnet);
+        for (slot, signature) in &[(100, "upgrade"), (200, "upgrade-again")] {
+            let upgraded = transaction(&program, *slot, signature, vec![upgrade()]);
+            sink.write_transactions(&[decode_transaction(&registry, &upgraded)]).await.unwrap();
+        }
+        assert!(versions.take_learnt().is_empty());
+
+        sink.rollback_from_slot(200).await.unwrap();
+        let restarted = ProgramVersions::new();
+        restarted.restore(&sink.program_upgrades().await.unwrap());
+        let symbol = Symbol::intern(&program.to_string());
+        assert_eq!(restarted.upgrades(Network::Mainnet, symbol), vec![100]);
+        assert_eq!(versions.version_at(Network::Mainnet, symbol, 300), Some(100));
+    }
+}
@@ -0,0 +1,234 @@
+//! The Aldrin AMMs (the constant product pools and the v2 ones with curves), Anchor programs:
+//! every instruction starts with the first 8 bytes of `sha256("global:<instruction name>")`,
+//! followed by its borsh encoded arguments.
+//!
+//! Liquidity goes in and out of a pool as baskets, of its base and quote tokens in proportion,
+//! sized in pool tokens.
+
+use std::convert::TryFrom;
+
+use smallvec::{smallvec, SmallVec};
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS_V1: &str = "AMM55ShdkoGRB5jVYPjWziwk8m5MpwyDgsMWHaMSQWH6";
+pub const PROGRAM_ADDRESS_V2: &str = "CURVGoZn8zycx6FXwwevgBTB2gVvdbGTEpvMJDbgs2t4";
+
+const SWAP: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+const CREATE_BASKET: [u8; 8] = [47, 105, 155, 148, 15, 169, 202, 211];
+const REDEEM_BASKET: [u8; 8] = [37, 133, 222, 57, 189, 160, 151, 41];
+
+// The side of the book a swap takes: bids pay quote tokens for base tokens.
+const SIDES: &[&str] = &["bid", "ask"];
+
+// Every instruction of a pool starts with the pool, its signer and its mint.
+const POOL_ACCOUNT: usize = 0;
+const POOL_MINT_ACCOUNT: usize = 2;
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let pool = [
+        ("pool", "", PropertyType::Pubkey,
+         "Pool the instruction is for, when the accounts of the instruction are known"),
+        ("pool_mint", "", PropertyType::Pubkey,
+         "Mint of the pool tokens, when the accounts of the instruction are known"),
+    ];
+
+    ProgramSchema::new("aldrin")
+        .function("swap", &[
+            ("amount_in", "", PropertyType::U64, "Amount of source tokens swapped"),
+            ("minimum_amount_out", "", PropertyType::U64,
+             "Minimum amount of destination tokens received"),
+            ("side", "", PropertyType::Text,
+             "Side of the swap (bid, paying quote tokens, or ask, paying base tokens)"),
+            pool[0],
+            pool[1],
+        ])
+        .function("create-basket", &[
+            ("pool_token_amount", "", PropertyType::U64, "Amount of pool tokens minted"),
+            ("maximum_base_token_amount", "", PropertyType::U64,
+             "Maximum amount of base tokens deposited"),
+            ("maximum_quote_token_amount", "", PropertyType::U64,
+             "Maximum amount of quote tokens deposited"),
+            pool[0],
+            pool[1],
+        ])
+        .function("redeem-basket", &[
+            ("pool_token_amount", "", PropertyType::U64, "Amount of pool tokens burnt"),
+            ("minimum_base_token_amount", "", PropertyType::U64,
+             "Minimum amount of base tokens withdrawn"),
+            ("minimum_quote_token_amount", "", PropertyType::U64,
+             "Minimum amount of quote tokens withdrawn"),
+            pool[0],
+            pool[1],
+        ])
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    if instruction.data.len() < 8 {
+        error!("[spi-wrapper/aldrin] Attempt to parse instruction from program {} failed as it \
+            is shorter than a discriminator.", instruction.program);
+
+        return None;
+    }
+    let (discriminator, arguments) = instruction.data.split_at(8);
+    let mut arguments = Reader::new(arguments);
+
+    let decoded = match <[u8; 8]>::try_from(discriminator).ok()? {
+        SWAP => swap(&mut arguments).map(|properties| ("swap", properties)),
+        CREATE_BASKET => {
+            basket(&mut arguments, ["maximum_base_token_amount", "maximum_quote_token_amount"])
+                .map(|properties| ("create-basket", properties))
+        }
+        REDEEM_BASKET => {
+            basket(&mut arguments, ["minimum_base_token_amount", "minimum_quote_token_amount"])
+                .map(|properties| ("redeem-basket", properties))
+        }
+        _ => {
+            debug!("[spi-wrapper/aldrin] Instruction {} of {} isn't one the processor decodes.",
+                hex::encode(discriminator), instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let (function_name, fields) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/aldrin] Attempt to parse instruction from program {} failed due \
+                to truncated or invalid arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, value, value_type)| layout::property(&instruction, key, "", value, value_type))
+        .collect();
+    for (key, index) in &[("pool", POOL_ACCOUNT), ("pool_mint", POOL_MINT_ACCOUNT)] {
+        if let Some(account) = accounts.get(*index) {
+            instruction_set.properties.push(layout::property(
+                &instruction, key, "", account.to_string(), PropertyType::Pubkey));
+        }
+    }
+
+    Some(instruction_set)
+}
+
+// The properties of an instruction: key, value and type.
+type Fields = SmallVec<[(&'static str, String, PropertyType); 4]>;
+
+fn swap(arguments: &mut Reader) -> Option<Fields> {
+    let tokens = arguments.u64()?;
+    let min_tokens = arguments.u64()?;
+    let side = arguments.name(SIDES)?;
+
+    Some(smallvec![
+        ("amount_in", tokens.to_string(), PropertyType::U64),
+        ("minimum_amount_out", min_tokens.to_string(), PropertyType::U64),
+        ("side", side.to_string(), PropertyType::Text),
+    ])
+}
+
+/// The arguments of `create_basket` and `redeem_basket`: the size of the basket, then the bounds
+/// of its base and quote token amounts (maximums for the former, minimums for the latter), emitted
+/// as `keys`.
+fn basket(arguments: &mut Reader, keys: [&'static str; 2]) -> Option<Fields> {
+    let size = arguments.u64()?;
+    let base_token_amount = arguments.u64()?;
+    let quote_token_amount = arguments.u64()?;
+    let [base_key, quote_key] = keys;
+
+    Some(smallvec![
+        ("pool_token_amount", size.to_string(), PropertyType::U64),
+        (base_key, base_token_amount.to_string(), PropertyType::U64),
+        (quote_key, quote_token_amount.to_string(), PropertyType::U64),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use solana_sdk::hash::hash;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    // Instruction data as sent to the program, discriminator included.
+    // Selling 2 RIN for 1.5 USDC at least.
+    const SWAP_ASK: &str = "f8c69e91e17587c8009435770000000060e316000000000001";
+    // Minting 1000 pool tokens (of 6 decimals) for 10 RIN and 7.5 USDC at most.
+    const CREATE_BASKET_1000: &str = "2f699b940fa9cad300ca9a3b0000000000e40b5402000000e0707200\
+        00000000";
+    // Burning them for 9.9 RIN and 7.4 USDC at least.
+    const REDEEM_BASKET_1000: &str = "2585de39bda0972900ca9a3b000000000003164e0200000040ea7000\
+        00000000";
+
+    fn decode(data: &str, accounts: &[Pubkey]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS_V2, hex::decode(data).unwrap()), accounts)
+    }
+
+    #[test]
+    fn discriminators_are_the_anchor_ones() {
+        for (name, discriminator) in &[
+            ("swap", SWAP),
+            ("create_basket", CREATE_BASKET),
+            ("redeem_basket", REDEEM_BASKET),
+        ] {
+            let preimage = format!("global:{}", name);
+            let expected: [u8; 8] = hash(preimage.as_bytes()).to_bytes()[..8].try_into().unwrap();
+            assert_eq!(*discriminator, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn swaps_and_baskets_decode_with_their_pool() {
+        let accounts: Vec<_> = (0..11).map(|_| Pubkey::new_unique()).collect();
+
+        let swap = decode(SWAP_ASK, &accounts).unwrap();
+        assert_eq!(swap.function.function_name.as_str(), "swap");
+        assert_eq!(properties(&swap), vec![
+            ("amount_in", "2000000000".to_string()),
+            ("minimum_amount_out", "1500000".to_string()),
+            ("side", "ask".to_string()),
+            ("pool", accounts[0].to_string()),
+            ("pool_mint", accounts[2].to_string()),
+        ]);
+
+        let create = decode(CREATE_BASKET_1000, &[]).unwrap();
+        assert_eq!(create.function.function_name.as_str(), "create-basket");
+        assert_eq!(properties(&create), vec![
+            ("pool_token_amount", "1000000000".to_string()),
+            ("maximum_base_token_amount", "10000000000".to_string()),
+            ("maximum_quote_token_amount", "7500000".to_string()),
+        ]);
+        let redeem = decode(REDEEM_BASKET_1000, &accounts).unwrap();
+        assert_eq!(&properties(&redeem)[1..], &[
+            ("minimum_base_token_amount", "9900000000".to_string()),
+            ("minimum_quote_token_amount", "7400000".to_string()),
+            ("pool", accounts[0].to_string()),
+            ("pool_mint", accounts[2].to_string()),
+        ]);
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        // Cut before the side.
+        assert!(decode(&SWAP_ASK[..2 * 24], &[]).is_none());
+        // An out of range side.
+        assert!(decode("f8c69e91e17587c8009435770000000060e316000000000002", &[]).is_none());
+        assert!(decode("0001020304050607", &[]).is_none());
+        assert!(decode("2f699b", &[]).is_none());
+    }
+}
@@ -0,0 +1,294 @@
+//! The Metaplex Auction House, the marketplace program many NFT marketplaces deploy their own
+//! auction houses of. An Anchor program: every instruction starts with the first 8 bytes of
+//! `sha256("global:<instruction name>")`, followed by its borsh encoded arguments.
+//!
+//! Listings (`sell`), bids (`buy` and `public-buy`) and their cancellations leave trade states
+//! behind, which `execute-sale` matches. Prices are in the treasury mint of the auction house,
+//! which isn't always SOL, so they're emitted as they are. The accounts (the wallets, the trade
+//! states, the auction house) are only emitted when they're known.
+
+use std::convert::TryFrom;
+
+use smallvec::{smallvec, SmallVec};
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS: &str = "hausS13jsjafwWwGqZTUQRmWyvyxn9EQpqMwV1PBBmk";
+
+const BUY: [u8; 8] = [102, 6, 61, 18, 1, 218, 235, 234];
+const PUBLIC_BUY: [u8; 8] = [169, 84, 218, 35, 42, 206, 16, 171];
+const SELL: [u8; 8] = [51, 230, 133, 164, 1, 127, 131, 173];
+const EXECUTE_SALE: [u8; 8] = [37, 74, 217, 157, 79, 49, 35, 6];
+const DEPOSIT: [u8; 8] = [242, 35, 198, 137, 82, 225, 242, 182];
+const WITHDRAW: [u8; 8] = [183, 18, 70, 156, 148, 109, 161, 34];
+const CANCEL: [u8; 8] = [232, 219, 223, 41, 219, 236, 220, 190];
+
+// The accounts emitted for each instruction: key, then where it is in its accounts.
+type Accounts = &'static [(&'static str, usize)];
+
+const BUY_ACCOUNTS: Accounts = &[
+    ("wallet", 0),
+    ("treasury_mint", 3),
+    ("metadata", 5),
+    ("auction_house", 8),
+    ("buyer_trade_state", 10),
+];
+const SELL_ACCOUNTS: Accounts =
+    &[("wallet", 0), ("metadata", 2), ("auction_house", 4), ("seller_trade_state", 6)];
+const EXECUTE_SALE_ACCOUNTS: Accounts = &[
+    ("buyer", 0),
+    ("seller", 1),
+    ("token_mint", 3),
+    ("metadata", 4),
+    ("treasury_mint", 5),
+    ("auction_house", 10),
+    ("buyer_trade_state", 13),
+    ("seller_trade_state", 14),
+];
+const DEPOSIT_ACCOUNTS: Accounts = &[("wallet", 0), ("treasury_mint", 4), ("auction_house", 6)];
+const WITHDRAW_ACCOUNTS: Accounts = &[("wallet", 0), ("treasury_mint", 3), ("auction_house", 5)];
+const CANCEL_ACCOUNTS: Accounts =
+    &[("wallet", 0), ("token_mint", 2), ("auction_house", 4), ("trade_state", 6)];
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let price = [
+        ("price", "", PropertyType::U64,
+         "Price, in the smallest unit of the treasury mint (lamports for SOL)"),
+        ("token_size", "", PropertyType::U64, "Amount of tokens traded, 1 for NFTs"),
+    ];
+    let amount = ("amount", "", PropertyType::U64,
+                  "Amount moved in or out of the escrow of the wallet, in the smallest unit of \
+                  the treasury mint");
+    let account = |key: &'static str, description: &'static str| {
+        (key, "", PropertyType::Pubkey, description)
+    };
+    let wallet = account("wallet", "Wallet of the instruction, when its accounts are known");
+    let treasury_mint = account("treasury_mint",
+                                "Mint prices are paid in, when the accounts of the instruction \
+                                are known");
+    let metadata = account("metadata",
+                           "Metadata account of the NFT, when the accounts of the instruction \
+                           are known");
+    let token_mint = account("token_mint",
+                             "Mint of the NFT, when the accounts of the instruction are known");
+    let auction_house = account("auction_house",
+                                "Auction house, when the accounts of the instruction are known");
+    let buyer_trade_state = account("buyer_trade_state",
+                                    "Trade state of the bid, when the accounts of the \
+                                    instruction are known");
+    let seller_trade_state = account("seller_trade_state",
+                                     "Trade state of the listing, when the accounts of the \
+                                     instruction are known");
+    let buy = [price[0], price[1], wallet, treasury_mint, metadata, auction_house,
+               buyer_trade_state];
+
+    ProgramSchema::new("auction-house")
+        .function("buy", &buy)
+        .function("public-buy", &buy)
+        .function("sell", &[price[0], price[1], wallet, metadata, auction_house,
+                            seller_trade_state])
+        .function("execute-sale", &[
+            price[0],
+            price[1],
+            account("buyer", "Buyer, when the accounts of the instruction are known"),
+            account("seller", "Seller, when the accounts of the instruction are known"),
+            token_mint,
+            metadata,
+            treasury_mint,
+            auction_house,
+            buyer_trade_state,
+            seller_trade_state,
+        ])
+        .function("deposit", &[amount, wallet, treasury_mint, auction_house])
+        .function("withdraw", &[amount, wallet, treasury_mint, auction_house])
+        .function("cancel", &[
+            price[0],
+            price[1],
+            wallet,
+            token_mint,
+            auction_house,
+            account("trade_state",
+                    "Trade state of the bid or listing cancelled, when the accounts of the \
+                    instruction are known"),
+        ])
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    if instruction.data.len() < 8 {
+        error!("[spi-wrapper/auction-house] Attempt to parse instruction from program {} failed \
+            as it is shorter than a discriminator.", instruction.program);
+
+        return None;
+    }
+    let (discriminator, arguments) = instruction.data.split_at(8);
+    let mut arguments = Reader::new(arguments);
+
+    let decoded = match <[u8; 8]>::try_from(discriminator).ok()? {
+        // The bumps of the trade state and of the escrow.
+        BUY => price_fields(&mut arguments, 2).map(|fields| ("buy", fields, BUY_ACCOUNTS)),
+        PUBLIC_BUY => price_fields(&mut arguments, 2)
+            .map(|fields| ("public-buy", fields, BUY_ACCOUNTS)),
+        // The bumps of the trade state, of the free trade state and of the program signer.
+        SELL => price_fields(&mut arguments, 3).map(|fields| ("sell", fields, SELL_ACCOUNTS)),
+        // The bumps of the escrow, of the free trade state and of the program signer.
+        EXECUTE_SALE => price_fields(&mut arguments, 3)
+            .map(|fields| ("execute-sale", fields, EXECUTE_SALE_ACCOUNTS)),
+        DEPOSIT => escrow_amount(&mut arguments)
+            .map(|fields| ("deposit", fields, DEPOSIT_ACCOUNTS)),
+        WITHDRAW => escrow_amount(&mut arguments)
+            .map(|fields| ("withdraw", fields, WITHDRAW_ACCOUNTS)),
+        CANCEL => price_fields(&mut arguments, 0).map(|fields| ("cancel", fields, CANCEL_ACCOUNTS)),
+        _ => {
+            debug!("[spi-wrapper/auction-house] Instruction {} of {} isn't one the processor \
+                decodes.", hex::encode(discriminator), instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let (function_name, fields, known_accounts) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/auction-house] Attempt to parse instruction from program {} \
+                failed due to truncated arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, value)| {
+            layout::property(&instruction, key, "", value.to_string(), PropertyType::U64)
+        })
+        .collect();
+    for (key, index) in known_accounts {
+        if let Some(account) = accounts.get(*index) {
+            instruction_set.properties.push(layout::property(
+                &instruction, key, "", account.to_string(), PropertyType::Pubkey));
+        }
+    }
+
+    Some(instruction_set)
+}
+
+// The properties of an instruction, all of them amounts: key and value.
+type Fields = SmallVec<[(&'static str, u64); 2]>;
+
+/// The price and size the trading instructions end with, past their `bumps`.
+fn price_fields(arguments: &mut Reader, bumps: usize) -> Option<Fields> {
+    arguments.bytes(bumps)?;
+
+    Some(smallvec![("price", arguments.u64()?), ("token_size", arguments.u64()?)])
+}
+
+/// The arguments of `deposit` and `withdraw`: the bump of the escrow, then the amount moved.
+fn escrow_amount(arguments: &mut Reader) -> Option<Fields> {
+    arguments.u8()?;
+
+    Some(smallvec![("amount", arguments.u64()?)])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use solana_sdk::hash::hash;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(data: &[u8], accounts: &[Pubkey]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, data.to_vec()), accounts)
+    }
+
+    /// The discriminator, the `bumps`, then the price and size.
+    fn data(discriminator: [u8; 8], bumps: usize, price: u64, token_size: u64) -> Vec<u8> {
+        [&discriminator[..], &vec![255; bumps], &price.to_le_bytes(), &token_size.to_le_bytes()]
+            .concat()
+    }
+
+    #[test]
+    fn discriminators_are_the_anchor_ones() {
+        for (name, discriminator) in &[
+            ("buy", BUY),
+            ("public_buy", PUBLIC_BUY),
+            ("sell", SELL),
+            ("execute_sale", EXECUTE_SALE),
+            ("deposit", DEPOSIT),
+            ("withdraw", WITHDRAW),
+            ("cancel", CANCEL),
+        ] {
+            let preimage = format!("global:{}", name);
+            let expected: [u8; 8] = hash(preimage.as_bytes()).to_bytes()[..8].try_into().unwrap();
+            assert_eq!(*discriminator, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn trades_carry_their_price_and_trade_states() {
+        let accounts: Vec<_> = (0..20).map(|_| Pubkey::new_unique()).collect();
+
+        let sell = decode(&data(SELL, 3, 2_500_000_000, 1), &accounts).unwrap();
+        assert_eq!(sell.function.function_name.as_str(), "sell");
+        assert_eq!(properties(&sell), vec![
+            ("price", "2500000000".to_string()),
+            ("token_size", "1".to_string()),
+            ("wallet", accounts[0].to_string()),
+            ("metadata", accounts[2].to_string()),
+            ("auction_house", accounts[4].to_string()),
+            ("seller_trade_state", accounts[6].to_string()),
+        ]);
+
+        let sale = decode(&data(EXECUTE_SALE, 3, 2_500_000_000, 1), &accounts).unwrap();
+        assert_eq!(sale.function.function_name.as_str(), "execute-sale");
+        assert_eq!(properties(&sale)[2..], [
+            ("buyer", accounts[0].to_string()),
+            ("seller", accounts[1].to_string()),
+            ("token_mint", accounts[3].to_string()),
+            ("metadata", accounts[4].to_string()),
+            ("treasury_mint", accounts[5].to_string()),
+            ("auction_house", accounts[10].to_string()),
+            ("buyer_trade_state", accounts[13].to_string()),
+            ("seller_trade_state", accounts[14].to_string()),
+        ]);
+
+        let public_buy = decode(&data(PUBLIC_BUY, 2, 1_200_000_000, 1), &[]).unwrap();
+        assert_eq!(public_buy.function.function_name.as_str(), "public-buy");
+        assert_eq!(properties(&public_buy), vec![
+            ("price", "1200000000".to_string()),
+            ("token_size", "1".to_string()),
+        ]);
+        let cancel = decode(&data(CANCEL, 0, 1_200_000_000, 1), &accounts).unwrap();
+        assert_eq!(properties(&cancel)[5], ("trade_state", accounts[6].to_string()));
+    }
+
+    #[test]
+    fn escrow_movements_carry_their_amount() {
+        let deposit = [&DEPOSIT[..], &[254], &5_000_000_000u64.to_le_bytes()].concat();
+        let deposit = decode(&deposit, &[]).unwrap();
+        assert_eq!(deposit.function.function_name.as_str(), "deposit");
+        assert_eq!(properties(&deposit), vec![("amount", "5000000000".to_string())]);
+        let withdraw = [&WITHDRAW[..], &[254], &1u64.to_le_bytes()].concat();
+        assert_eq!(decode(&withdraw, &[]).unwrap().function.function_name.as_str(), "withdraw");
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        // Cut before the token size.
+        assert!(decode(&data(BUY, 2, 1, 1)[..18], &[]).is_none());
+        assert!(decode(&[&DEPOSIT[..], &[254]].concat(), &[]).is_none());
+        assert!(decode(&[0, 1, 2, 3, 4, 5, 6, 7], &[]).is_none());
+        assert!(decode(&SELL[..3], &[]).is_none());
+    }
+}
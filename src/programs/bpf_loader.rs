@@ -1,21 +1,33 @@
-use bincode::deserialize;
+use smallvec::smallvec;
 use solana_sdk::loader_instruction::LoaderInstruction;
+use solana_sdk::program_utils::limited_deserialize;
 use tracing::error;
 
-use crate::{InstructionProperty, Instruction, InstructionSet, InstructionFunction};
+use crate::{InstructionProperty, Instruction, InstructionSet, InstructionFunction, PropertyType};
+use crate::schema::ProgramSchema;
 
 pub const PROGRAM_ADDRESS: &str = "BPFLoader1111111111111111111111111111111111";
 pub const PROGRAM_ADDRESS_2: &str = "BPFLoader2111111111111111111111111111111111";
 
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    ProgramSchema::new("bpf-loader")
+        .function("write", &[
+            ("offset", "", PropertyType::U64, "Offset the bytes are written at"),
+            ("bytes", "info", PropertyType::Bytes, "Bytes written"),
+        ])
+        .function("finalize", &[])
+}
+
 /// Extracts the contents of an instruction into small bits and pieces, or what we would call,
 /// instruction_properties.
 ///
 /// The function should return a list of instruction properties extracted from an instruction.
-pub async fn fragment_instruction(
+pub fn fragment_instruction(
     // The instruction
     _instruction: Instruction,
 ) -> Option<InstructionSet> {
-    let bpf_loader_dr = deserialize::<LoaderInstruction>(
+    let bpf_loader_dr = limited_deserialize::<LoaderInstruction>(
         &_instruction.data);
 
     return match bpf_loader_dr {
@@ -25,46 +37,54 @@ pub async fn fragment_instruction(
                 LoaderInstruction::Write { offset, bytes } => {
                     Option::from(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: _instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: _instruction.tx_instruction_id,
                             transaction_hash: _instruction.transaction_hash.clone(),
-                            parent_index: _instruction.parent_index.clone(),
-                            program: _instruction.program.clone(),
-                            function_name: "write".to_string(),
-                            timestamp: _instruction.timestamp.clone(),
+                            parent_index: _instruction.parent_index,
+                            program: _instruction.program,
+                            function_name: "write".into(),
+                            timestamp: _instruction.timestamp,
+                            network: _instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: _instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: _instruction.tx_instruction_id,
                                 transaction_hash: _instruction.transaction_hash.clone(),
-                                parent_index: _instruction.parent_index.clone(),
-                                key: "offset".to_string(),
+                                parent_index: _instruction.parent_index,
+                                key: "offset".into(),
                                 value: offset.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: _instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: _instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: _instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: _instruction.tx_instruction_id,
                                 transaction_hash: _instruction.transaction_hash.clone(),
-                                parent_index: _instruction.parent_index.clone(),
-                                key: "bytes".to_string(),
+                                parent_index: _instruction.parent_index,
+                                key: "bytes".into(),
                                 value: base64::encode(&bytes),
-                                parent_key: "info".to_string(),
-                                timestamp: _instruction.timestamp.clone(),
+                                value_type: PropertyType::Bytes,
+                                parent_key: "info".into(),
+                                timestamp: _instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 LoaderInstruction::Finalize => {
                     Option::from(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: _instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: _instruction.tx_instruction_id,
                             transaction_hash: _instruction.transaction_hash.clone(),
-                            parent_index: _instruction.parent_index.clone(),
-                            program: _instruction.program.clone(),
-                            function_name: "finalize".to_string(),
-                            timestamp: _instruction.timestamp.clone(),
+                            parent_index: _instruction.parent_index,
+                            program: _instruction.program,
+                            function_name: "finalize".into(),
+                            timestamp: _instruction.timestamp,
+                            network: _instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![],
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
             }
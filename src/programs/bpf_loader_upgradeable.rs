@@ -1,21 +1,52 @@
+use smallvec::smallvec;
+use solana_sdk::bpf_loader_upgradeable::UpgradeableLoaderState;
+use solana_sdk::program_utils::limited_deserialize;
 use solana_account_decoder::parse_bpf_loader::{
     parse_bpf_upgradeable_loader, BpfUpgradeableLoaderAccountType,
 };
 use tracing::error;
 
-use crate::{Instruction, InstructionFunction, InstructionProperty, InstructionSet};
+use crate::{Instruction, InstructionFunction, InstructionProperty, InstructionSet, PropertyType};
+use crate::schema::ProgramSchema;
 use solana_account_decoder::parse_account_data::{ParseAccountError, ParsableAccount};
 
 pub const PROGRAM_ADDRESS: &str = "BPFLoaderUpgradeab1e11111111111111111111111";
 
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    ProgramSchema::new("bpf-loader-upgradeable")
+        .function("uninitialized", &[])
+        .function("buffer", &[
+            ("authority", "buffer", PropertyType::Pubkey,
+             "Authority allowed to write or upgrade, empty when immutable"),
+            ("data", "buffer", PropertyType::Text, "Account data, as JSON"),
+        ])
+        .function("program", &[
+            ("program_data", "program", PropertyType::Text, "Account holding the program data"),
+        ])
+        .function("program-data", &[
+            ("authority", "program_data", PropertyType::Pubkey,
+             "Authority allowed to write or upgrade, empty when immutable"),
+            ("data", "program_data", PropertyType::Text, "Account data, as JSON"),
+            ("slot", "program_data", PropertyType::U64, "Slot the program was last deployed at"),
+        ])
+}
+
 /// Extracts the contents of an instruction into small bits and pieces, or what we would call,
 /// instruction_properties.
 ///
 /// The function should return a list of instruction properties extracted from an instruction.
-pub async fn fragment_instruction(
+pub fn fragment_instruction(
     // The instruction
     instruction: Instruction,
 ) -> Option<InstructionSet> {
+    if is_truncated(&instruction.data) {
+        error!("[spi-wrapper/bpf_loader_upgradeable] Attempt to parse instruction from program {} \
+            failed as its data is shorter than its metadata.", instruction.program);
+
+        return None;
+    }
+
     let bpf_loader_upgradeable_dr =
         parse_bpf_upgradeable_loader(instruction.data.as_slice());
 
@@ -27,118 +58,136 @@ pub async fn fragment_instruction(
                 BpfUpgradeableLoaderAccountType::Uninitialized => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "uninitialized".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "uninitialized".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![]
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 BpfUpgradeableLoaderAccountType::Buffer(buffer) => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "buffer".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "buffer".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "authority".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "authority".into(),
                                 value: if let Some(ba) = buffer.authority.clone() {
                                     ba
                                 } else {
                                     "".to_string()
                                 },
-                                parent_key: "buffer".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "buffer".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "data".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "data".into(),
                                 value: serde_json::to_string(&buffer.data).unwrap().to_string(),
-                                parent_key: "buffer".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "buffer".into(),
+                                timestamp: instruction.timestamp,
                             },
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 BpfUpgradeableLoaderAccountType::Program(program) => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "program".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "program".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "program_data".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "program_data".into(),
                                 value: serde_json::to_string(&program.program_data).unwrap().to_string(),
-                                parent_key: "program".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "program".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 BpfUpgradeableLoaderAccountType::ProgramData(program_data) => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "program-data".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "program-data".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "authority".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "authority".into(),
                                 value: if let Some(auth) = program_data.authority.clone() {
                                     auth
                                 } else {
                                     "".to_string()
                                 },
-                                parent_key: "program_data".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "program_data".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "data".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "data".into(),
                                 value: serde_json::to_string(&program_data.data).unwrap().to_string(),
-                                parent_key: "program_data".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "program_data".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "slot".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "slot".into(),
                                 value: program_data.slot.to_string(),
-                                parent_key: "program_data".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "program_data".into(),
+                                timestamp: instruction.timestamp,
                             },
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
             }
@@ -183,4 +232,18 @@ pub async fn fragment_instruction(
             None
         }
     }
-}
\ No newline at end of file
+}
+
+/// Whether the data is a buffer or program data shorter than its metadata, which
+/// `parse_bpf_upgradeable_loader` slices past without checking.
+fn is_truncated(data: &[u8]) -> bool {
+    let offset = match limited_deserialize::<UpgradeableLoaderState>(data) {
+        Ok(UpgradeableLoaderState::Buffer { .. }) => UpgradeableLoaderState::buffer_data_offset(),
+        Ok(UpgradeableLoaderState::ProgramData { .. }) => {
+            UpgradeableLoaderState::programdata_data_offset()
+        }
+        _ => return false,
+    };
+
+    offset.map_or(true, |offset| data.len() < offset)
+}
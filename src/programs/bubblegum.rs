@@ -0,0 +1,456 @@
+//! The Metaplex Bubblegum program, minting compressed NFTs as the leaves of a concurrent merkle
+//! tree. An Anchor program: every instruction starts with the first 8 bytes of
+//! `sha256("global:<instruction name>")`, followed by its borsh encoded arguments.
+//!
+//! Compressed NFTs have no accounts of their own to scan, so their leaves are rebuilt out of the
+//! instructions, nested under `leaf`: those changing a leaf carry its nonce and hashes, from
+//! which the asset id is derived along with the tree, and mints carry the metadata the hashes of
+//! their leaf are computed from. A mint to a collection verifies it, and its leaf is hashed (and
+//! the collection emitted) verified. The owners, delegates and trees are only emitted when the
+//! accounts of the instructions are known.
+
+use std::convert::TryFrom;
+
+use sha3::{Digest, Keccak256};
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, flag, top_level, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS: &str = "BGUMAp9Gq7iTEuizy4pqaxsTyUCBK68MDfK752saRPUY";
+
+const MINT_V1: [u8; 8] = [145, 98, 192, 118, 184, 147, 118, 104];
+const MINT_TO_COLLECTION_V1: [u8; 8] = [153, 18, 178, 47, 197, 158, 86, 15];
+const TRANSFER: [u8; 8] = [163, 52, 200, 231, 140, 3, 69, 186];
+const BURN: [u8; 8] = [116, 110, 29, 56, 107, 219, 42, 93];
+const DELEGATE: [u8; 8] = [90, 147, 75, 178, 85, 88, 4, 137];
+const REDEEM: [u8; 8] = [184, 12, 86, 149, 70, 196, 97, 225];
+
+const TOKEN_STANDARDS: &[&str] =
+    &["non-fungible", "fungible-asset", "fungible", "non-fungible-edition"];
+const TOKEN_PROGRAM_VERSIONS: &[&str] = &["original", "token-2022"];
+const USE_METHODS: &[&str] = &["burn", "multiple", "single"];
+
+// The accounts emitted for each instruction: key, parent key, then where it is in its accounts.
+type Accounts = &'static [(&'static str, &'static str, usize)];
+
+const MINT_TO_COLLECTION_ACCOUNTS: Accounts = &[
+    ("owner", "leaf", 1),
+    ("delegate", "leaf", 2),
+    ("merkle_tree", "", 3),
+    ("collection_mint", "", 8),
+];
+const TRANSFER_ACCOUNTS: Accounts = &[
+    ("owner", "leaf", 1),
+    ("delegate", "leaf", 2),
+    ("new_leaf_owner", "", 3),
+    ("merkle_tree", "", 4),
+];
+const DELEGATE_ACCOUNTS: Accounts = &[
+    ("owner", "leaf", 1),
+    ("delegate", "leaf", 2),
+    ("new_leaf_delegate", "", 3),
+    ("merkle_tree", "", 4),
+];
+const LEAF_ACCOUNTS: Accounts =
+    &[("owner", "leaf", 1), ("delegate", "leaf", 2), ("merkle_tree", "", 3)];
+
+// The properties of an instruction: key, parent key, value and type.
+type Fields = Vec<(&'static str, String, String, PropertyType)>;
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let metadata = [
+        ("name", "", PropertyType::Text, "Name of the NFT"),
+        ("symbol", "", PropertyType::Text, "Symbol of the NFT"),
+        ("uri", "", PropertyType::Text, "URI of the JSON metadata of the NFT"),
+        ("seller_fee_basis_points", "", PropertyType::U64,
+         "Royalties paid to the creators on secondary sales, in basis points"),
+        ("primary_sale_happened", "", PropertyType::Bool, "Whether the NFT was sold already"),
+        ("is_mutable", "", PropertyType::Bool, "Whether the metadata can be updated"),
+        ("edition_nonce", "", PropertyType::U64, "Nonce of the edition of the NFT, if any"),
+        ("token_standard", "", PropertyType::Text, "Token standard of the NFT, if given"),
+        ("collection", "", PropertyType::Pubkey, "Mint of the collection of the NFT, if any"),
+        ("collection_verified", "", PropertyType::Bool,
+         "Whether the collection verified the NFT, if any"),
+        ("use_method", "uses", PropertyType::Text,
+         "How the NFT is used up (burn, multiple or single), if it is"),
+        ("remaining", "uses", PropertyType::U64, "Uses left"),
+        ("total", "uses", PropertyType::U64, "Uses in all"),
+        ("token_program_version", "", PropertyType::Text,
+         "Token program the NFT is decompressed with (original or token-2022)"),
+        ("address", "creators/{index}", PropertyType::Pubkey, "Address of a creator"),
+        ("verified", "creators/{index}", PropertyType::Bool,
+         "Whether the creator signed the metadata"),
+        ("share", "creators/{index}", PropertyType::U64,
+         "Share of the royalties of the creator, in percent"),
+    ];
+    let leaf = [
+        ("id", "leaf", PropertyType::Pubkey,
+         "Asset id of the NFT, when the accounts of the instruction are known"),
+        ("owner", "leaf", PropertyType::Pubkey,
+         "Owner of the NFT, when the accounts of the instruction are known"),
+        ("delegate", "leaf", PropertyType::Pubkey,
+         "Delegate of the NFT (its owner when it has none), when the accounts of the instruction \
+         are known"),
+        ("nonce", "leaf", PropertyType::U64, "Nonce of the NFT in its tree"),
+        ("data_hash", "leaf", PropertyType::Bytes,
+         "Hash of the metadata of the NFT, hex encoded"),
+        ("creator_hash", "leaf", PropertyType::Bytes,
+         "Hash of the creators of the NFT, hex encoded"),
+    ];
+    let merkle_tree = ("merkle_tree", "", PropertyType::Pubkey,
+                       "Tree of the NFT, when the accounts of the instruction are known");
+
+    let mut mint = metadata.to_vec();
+    mint.extend_from_slice(&[leaf[1], leaf[2], leaf[4], leaf[5], merkle_tree]);
+    let mut mint_to_collection = mint.clone();
+    mint_to_collection.push(("collection_mint", "", PropertyType::Pubkey,
+                             "Mint of the collection verified, when the accounts of the \
+                             instruction are known"));
+    let mut change = vec![
+        ("root", "", PropertyType::Bytes,
+         "Root of the tree the proof of the leaf is for, hex encoded"),
+        ("index", "", PropertyType::U64, "Index of the leaf in the tree"),
+        merkle_tree,
+    ];
+    change.extend_from_slice(&leaf);
+    let mut transfer = change.clone();
+    transfer.push(("new_leaf_owner", "", PropertyType::Pubkey,
+                   "Owner the NFT is transferred to, when the accounts of the instruction are \
+                   known"));
+    let mut delegate = change.clone();
+    delegate.push(("new_leaf_delegate", "", PropertyType::Pubkey,
+                   "Delegate of the NFT from now on, when the accounts of the instruction are \
+                   known"));
+
+    ProgramSchema::new("bubblegum")
+        .function("mint-v1", &mint)
+        .function("mint-to-collection-v1", &mint_to_collection)
+        .function("transfer", &transfer)
+        .function("burn", &change)
+        .function("delegate", &delegate)
+        .function("redeem", &change)
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    if instruction.data.len() < 8 {
+        error!("[spi-wrapper/bubblegum] Attempt to parse instruction from program {} failed as \
+            it is shorter than a discriminator.", instruction.program);
+
+        return None;
+    }
+    let (discriminator, arguments) = instruction.data.split_at(8);
+
+    // The fields, and the nonce of the leaf changed, from which its asset id is derived.
+    let decoded = match <[u8; 8]>::try_from(discriminator).ok()? {
+        MINT_V1 => mint(arguments, false).map(|fields| ("mint-v1", fields, None, LEAF_ACCOUNTS)),
+        MINT_TO_COLLECTION_V1 => mint(arguments, true)
+            .map(|fields| ("mint-to-collection-v1", fields, None, MINT_TO_COLLECTION_ACCOUNTS)),
+        TRANSFER => leaf(arguments)
+            .map(|(fields, nonce)| ("transfer", fields, Some(nonce), TRANSFER_ACCOUNTS)),
+        BURN => leaf(arguments).map(|(fields, nonce)| ("burn", fields, Some(nonce), LEAF_ACCOUNTS)),
+        DELEGATE => leaf(arguments)
+            .map(|(fields, nonce)| ("delegate", fields, Some(nonce), DELEGATE_ACCOUNTS)),
+        REDEEM => leaf(arguments)
+            .map(|(fields, nonce)| ("redeem", fields, Some(nonce), LEAF_ACCOUNTS)),
+        _ => {
+            debug!("[spi-wrapper/bubblegum] Instruction {} of {} isn't one the processor decodes.",
+                hex::encode(discriminator), instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let (function_name, fields, nonce, known_accounts) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/bubblegum] Attempt to parse instruction from program {} failed \
+                due to truncated or invalid arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, parent_key, value, value_type)| {
+            layout::property(&instruction, key, &parent_key, value, value_type)
+        })
+        .collect();
+    for (key, parent_key, index) in known_accounts {
+        if let Some(account) = accounts.get(*index) {
+            instruction_set.properties.push(layout::property(
+                &instruction, key, parent_key, account.to_string(), PropertyType::Pubkey));
+            if let Some(nonce) = nonce.filter(|_| *key == "merkle_tree") {
+                instruction_set.properties.push(layout::property(
+                    &instruction, "id", "leaf", asset_id(account, nonce).to_string(),
+                    PropertyType::Pubkey));
+            }
+        }
+    }
+
+    Some(instruction_set)
+}
+
+/// The arguments of `mint_v1` and `mint_to_collection_v1`: `MetadataArgs`, flattened, followed by
+/// the hashes of the leaf minted. Minting to a collection verifies it before hashing.
+fn mint(data: &[u8], verify_collection: bool) -> Option<Fields> {
+    let mut arguments = Reader::new(data);
+    let mut fields = vec![
+        top_level("name", string(&mut arguments)?, PropertyType::Text),
+        top_level("symbol", string(&mut arguments)?, PropertyType::Text),
+        top_level("uri", string(&mut arguments)?, PropertyType::Text),
+    ];
+    let seller_fee_basis_points = arguments.u16()?;
+    fields.push(top_level("seller_fee_basis_points", seller_fee_basis_points.to_string(),
+                          PropertyType::U64));
+    fields.push(top_level("primary_sale_happened", flag(arguments.bool()?), PropertyType::Bool));
+    fields.push(top_level("is_mutable", flag(arguments.bool()?), PropertyType::Bool));
+    if let Some(edition_nonce) = arguments.option(Reader::u8)? {
+        fields.push(top_level("edition_nonce", edition_nonce.to_string(), PropertyType::U64));
+    }
+    if let Some(token_standard) = arguments.option(|arguments| arguments.name(TOKEN_STANDARDS))? {
+        fields.push(top_level("token_standard", token_standard.to_string(), PropertyType::Text));
+    }
+    // Where the collection starts, to verify it before hashing.
+    let collection_offset = data.len() - arguments.remaining();
+    let collection = arguments.option(|arguments| Some((arguments.bool()?, arguments.pubkey()?)))?;
+    if let Some((verified, key)) = collection {
+        fields.push(top_level("collection", key.to_string(), PropertyType::Pubkey));
+        fields.push(top_level("collection_verified", flag(verified || verify_collection),
+                              PropertyType::Bool));
+    }
+    let uses = arguments.option(|arguments| {
+        Some((arguments.name(USE_METHODS)?, arguments.u64()?, arguments.u64()?))
+    })?;
+    if let Some((use_method, remaining, total)) = uses {
+        let uses = || "uses".to_string();
+        fields.push(("use_method", uses(), use_method.to_string(), PropertyType::Text));
+        fields.push(("remaining", uses(), remaining.to_string(), PropertyType::U64));
+        fields.push(("total", uses(), total.to_string(), PropertyType::U64));
+    }
+    let token_program_version = arguments.name(TOKEN_PROGRAM_VERSIONS)?;
+    fields.push(top_level("token_program_version", token_program_version.to_string(),
+                          PropertyType::Text));
+    // Creators: an address, whether it's verified and a share, hashed as they're encoded.
+    let creators_offset = data.len() - arguments.remaining() + 4;
+    let creators = arguments.vec(34, |arguments| {
+        Some((arguments.pubkey()?, arguments.bool()?, arguments.u8()?))
+    })?;
+    for (index, (address, verified, share)) in creators.iter().enumerate() {
+        let parent_key = format!("creators/{}", index);
+        fields.push(("address", parent_key.clone(), address.to_string(), PropertyType::Pubkey));
+        fields.push(("verified", parent_key.clone(), flag(*verified), PropertyType::Bool));
+        fields.push(("share", parent_key, share.to_string(), PropertyType::U64));
+    }
+
+    let mut metadata = data[..data.len() - arguments.remaining()].to_vec();
+    if verify_collection && collection.is_some() {
+        metadata[collection_offset + 1] = 1;
+    }
+    let data_hash = Keccak256::digest(&[
+        &Keccak256::digest(&metadata)[..],
+        &seller_fee_basis_points.to_le_bytes(),
+    ].concat());
+    let creator_hash = Keccak256::digest(&metadata[creators_offset..]);
+    fields.push(("data_hash", "leaf".to_string(), hex::encode(data_hash), PropertyType::Bytes));
+    fields.push(("creator_hash", "leaf".to_string(), hex::encode(creator_hash),
+                 PropertyType::Bytes));
+
+    Some(fields)
+}
+
+/// A string of the metadata, without the NULs it may be padded with.
+fn string(arguments: &mut Reader) -> Option<String> {
+    arguments.string().map(|string| string.trim_end_matches('\0').to_string())
+}
+
+/// The arguments of the instructions changing a leaf: the root of the tree its proof is for, the
+/// hashes of the leaf, its nonce and its index, and the nonce.
+fn leaf(data: &[u8]) -> Option<(Fields, u64)> {
+    let mut arguments = Reader::new(data);
+    let root = arguments.bytes(32)?;
+    let data_hash = arguments.bytes(32)?;
+    let creator_hash = arguments.bytes(32)?;
+    let nonce = arguments.u64()?;
+    let index = arguments.u32()?;
+
+    let leaf = || "leaf".to_string();
+    Some((vec![
+        top_level("root", hex::encode(root), PropertyType::Bytes),
+        top_level("index", index.to_string(), PropertyType::U64),
+        ("nonce", leaf(), nonce.to_string(), PropertyType::U64),
+        ("data_hash", leaf(), hex::encode(data_hash), PropertyType::Bytes),
+        ("creator_hash", leaf(), hex::encode(creator_hash), PropertyType::Bytes),
+    ], nonce))
+}
+
+/// The asset id of the compressed NFT of `nonce` in `tree`.
+fn asset_id(tree: &Pubkey, nonce: u64) -> Pubkey {
+    let seeds: &[&[u8]] = &[b"asset", tree.as_ref(), &nonce.to_le_bytes()];
+
+    Pubkey::find_program_address(seeds, &PROGRAM_ADDRESS.parse().unwrap()).0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use solana_sdk::hash::hash;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(data: &[u8], accounts: &[Pubkey]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, data.to_vec()), accounts)
+    }
+
+    fn property(instruction_set: &InstructionSet, key: &str) -> String {
+        properties(instruction_set).into_iter()
+            .find(|(found, _)| *found == key)
+            .map(|(_, value)| value)
+            .unwrap()
+    }
+
+    fn string(data: &mut Vec<u8>, string: &str) {
+        data.extend_from_slice(&(string.len() as u32).to_le_bytes());
+        data.extend_from_slice(string.as_bytes());
+    }
+
+    /// `MetadataArgs` of an unverified collection and a creator.
+    fn metadata_args(collection: &Pubkey, creator: &Pubkey) -> Vec<u8> {
+        let mut data = Vec::new();
+        string(&mut data, "Drip #7");
+        string(&mut data, "DRIP");
+        string(&mut data, "https://arweave.net/drip7");
+        data.extend_from_slice(&500u16.to_le_bytes());
+        // Not sold, mutable, edition nonce 255, non-fungible, then the collection.
+        data.extend_from_slice(&[0, 1, 1, 255, 1, 0, 1, 0]);
+        data.extend_from_slice(collection.as_ref());
+        // No uses, the original token program, then the creator.
+        data.extend_from_slice(&[0, 0, 1, 0, 0, 0]);
+        data.extend_from_slice(creator.as_ref());
+        data.extend_from_slice(&[1, 100]);
+        data
+    }
+
+    /// The arguments of the instructions changing the leaf of `nonce`.
+    fn leaf_data(discriminator: [u8; 8], nonce: u64) -> Vec<u8> {
+        [&discriminator[..], &[1; 32], &[2; 32], &[3; 32], &nonce.to_le_bytes(),
+         &(nonce as u32).to_le_bytes()].concat()
+    }
+
+    #[test]
+    fn discriminators_are_the_anchor_ones() {
+        for (name, discriminator) in &[
+            ("mint_v1", MINT_V1),
+            ("mint_to_collection_v1", MINT_TO_COLLECTION_V1),
+            ("transfer", TRANSFER),
+            ("burn", BURN),
+            ("delegate", DELEGATE),
+            ("redeem", REDEEM),
+        ] {
+            let preimage = format!("global:{}", name);
+            let expected: [u8; 8] = hash(preimage.as_bytes()).to_bytes()[..8].try_into().unwrap();
+            assert_eq!(*discriminator, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn mints_carry_their_metadata_and_the_hashes_of_their_leaf() {
+        let (collection, creator) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let accounts: Vec<_> = (0..16).map(|_| Pubkey::new_unique()).collect();
+        let metadata = metadata_args(&collection, &creator);
+
+        let mint = decode(&[&MINT_V1[..], &metadata].concat(), &accounts).unwrap();
+        assert_eq!(mint.function.function_name.as_str(), "mint-v1");
+        let expected = vec![
+            ("name", "Drip #7".to_string()),
+            ("symbol", "DRIP".to_string()),
+            ("uri", "https://arweave.net/drip7".to_string()),
+            ("seller_fee_basis_points", "500".to_string()),
+            ("primary_sale_happened", "0".to_string()),
+            ("is_mutable", "1".to_string()),
+            ("edition_nonce", "255".to_string()),
+            ("token_standard", "non-fungible".to_string()),
+            ("collection", collection.to_string()),
+            ("collection_verified", "0".to_string()),
+            ("token_program_version", "original".to_string()),
+            ("creators/0.address", creator.to_string()),
+            ("creators/0.verified", "1".to_string()),
+            ("creators/0.share", "100".to_string()),
+        ];
+        assert_eq!(properties(&mint)[..expected.len()], expected[..]);
+
+        // The hashes Bubblegum puts in the leaf.
+        let data_hash = Keccak256::digest(&[
+            &Keccak256::digest(&metadata)[..],
+            &500u16.to_le_bytes(),
+        ].concat());
+        let creator_hash = Keccak256::digest(&[creator.as_ref(), &[1, 100]].concat());
+        assert_eq!(property(&mint, "leaf.data_hash"), hex::encode(data_hash));
+        assert_eq!(property(&mint, "leaf.creator_hash"), hex::encode(creator_hash));
+        assert_eq!(property(&mint, "leaf.owner"), accounts[1].to_string());
+        assert_eq!(property(&mint, "merkle_tree"), accounts[3].to_string());
+
+        // Minted to the collection, which is verified before the leaf is hashed.
+        let to_collection = [&MINT_TO_COLLECTION_V1[..], &metadata].concat();
+        let to_collection = decode(&to_collection, &accounts).unwrap();
+        assert_eq!(property(&to_collection, "collection_verified"), "1");
+        assert_eq!(property(&to_collection, "collection_mint"), accounts[8].to_string());
+        let mut verified = metadata.clone();
+        let collection_offset = metadata.len() - (32 + 6 + 32 + 2) - 2;
+        assert_eq!(verified[collection_offset..collection_offset + 2], [1, 0]);
+        verified[collection_offset + 1] = 1;
+        let data_hash = Keccak256::digest(&[
+            &Keccak256::digest(&verified)[..],
+            &500u16.to_le_bytes(),
+        ].concat());
+        assert_eq!(property(&to_collection, "leaf.data_hash"), hex::encode(data_hash));
+    }
+
+    #[test]
+    fn leaves_changed_carry_their_asset_id() {
+        let accounts: Vec<_> = (0..8).map(|_| Pubkey::new_unique()).collect();
+
+        let transfer = decode(&leaf_data(TRANSFER, 42), &accounts).unwrap();
+        assert_eq!(transfer.function.function_name.as_str(), "transfer");
+        assert_eq!(properties(&transfer), vec![
+            ("root", hex::encode([1; 32])),
+            ("index", "42".to_string()),
+            ("leaf.nonce", "42".to_string()),
+            ("leaf.data_hash", hex::encode([2; 32])),
+            ("leaf.creator_hash", hex::encode([3; 32])),
+            ("leaf.owner", accounts[1].to_string()),
+            ("leaf.delegate", accounts[2].to_string()),
+            ("new_leaf_owner", accounts[3].to_string()),
+            ("merkle_tree", accounts[4].to_string()),
+            ("leaf.id", asset_id(&accounts[4], 42).to_string()),
+        ]);
+
+        let burn = decode(&leaf_data(BURN, 42), &accounts).unwrap();
+        assert_eq!(property(&burn, "leaf.id"), asset_id(&accounts[3], 42).to_string());
+        // Without accounts, there's no tree to derive the asset id from.
+        let redeem = decode(&leaf_data(REDEEM, 42), &[]).unwrap();
+        assert_eq!(redeem.properties.len(), 5);
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        let metadata = metadata_args(&Pubkey::new_unique(), &Pubkey::new_unique());
+        // Cut within the creator.
+        let mint = [&MINT_V1[..], &metadata[..metadata.len() - 10]].concat();
+        assert!(decode(&mint, &[]).is_none());
+        assert!(decode(&leaf_data(DELEGATE, 1)[..8 + 100], &[]).is_none());
+        assert!(decode(&[0, 1, 2, 3, 4, 5, 6, 7], &[]).is_none());
+        assert!(decode(&BURN[..5], &[]).is_none());
+    }
+}
@@ -0,0 +1,481 @@
+//! The Metaplex Candy Machine (v3), minting the NFTs of a collection out of its config lines, and
+//! the Candy Guard wrapping it, which checks the guards of a mint (payments, dates, allow lists)
+//! before minting through it. Both are Anchor programs: every instruction starts with the first
+//! 8 bytes of `sha256("global:<instruction name>")`, followed by its borsh encoded arguments. The
+//! two programs share instruction names, so the discriminators are told apart by program, and the
+//! functions of the Candy Guard are prefixed with `guard-`.
+//!
+//! The guards of a Candy Guard are emitted by name, out of the flags of its default guard set:
+//! those of its groups follow the settings of the default guards, whose sizes differ per guard,
+//! and aren't decoded.
+
+use std::convert::TryFrom;
+
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, flag, top_level, Reader};
+use crate::schema::ProgramSchema;
+
+pub const CANDY_MACHINE_PROGRAM_ADDRESS: &str = "CndyV3LdqHUfDLmE5naZjVN8rBZz4tqhdefbAnjHG3JR";
+pub const CANDY_GUARD_PROGRAM_ADDRESS: &str = "Guard1JwRhJkVH6XZhzoYxeBVQe872VH6QggF4BWmS9g";
+
+const INITIALIZE: [u8; 8] = [175, 175, 109, 31, 13, 152, 155, 237];
+const INITIALIZE_V2: [u8; 8] = [67, 153, 175, 39, 218, 16, 38, 32];
+const MINT: [u8; 8] = [51, 57, 225, 47, 182, 146, 137, 166];
+const MINT_V2: [u8; 8] = [120, 121, 23, 146, 173, 110, 199, 205];
+const UPDATE: [u8; 8] = [219, 200, 88, 176, 158, 63, 253, 127];
+const WITHDRAW: [u8; 8] = [183, 18, 70, 156, 148, 109, 161, 34];
+const ROUTE: [u8; 8] = [229, 23, 203, 151, 122, 227, 173, 42];
+
+// The guards, in the order of `GuardType`, which is also the order of their flags.
+const GUARDS: &[&str] = &[
+    "bot-tax",
+    "sol-payment",
+    "token-payment",
+    "start-date",
+    "third-party-signer",
+    "token-gate",
+    "gatekeeper",
+    "end-date",
+    "allow-list",
+    "mint-limit",
+    "nft-payment",
+    "redeemed-amount",
+    "address-gate",
+    "nft-gate",
+    "nft-burn",
+    "token-burn",
+    "freeze-sol-payment",
+    "freeze-token-payment",
+    "program-gate",
+    "allocation",
+    "token-2022-payment",
+];
+
+const TOKEN_STANDARDS: &[&str] = &[
+    "non-fungible",
+    "fungible-asset",
+    "fungible",
+    "non-fungible-edition",
+    "programmable-non-fungible",
+    "programmable-non-fungible-edition",
+];
+
+// The accounts emitted for each instruction: key, then where it is in its accounts.
+type Accounts = &'static [(&'static str, usize)];
+
+const INITIALIZE_ACCOUNTS: Accounts = &[("candy_machine", 0), ("collection_mint", 5)];
+const INITIALIZE_V2_ACCOUNTS: Accounts = &[("candy_machine", 0), ("collection_mint", 6)];
+const MINT_ACCOUNTS: Accounts = &[("candy_machine", 0), ("nft_mint", 4)];
+const MINT_V2_ACCOUNTS: Accounts = &[("candy_machine", 0), ("nft_owner", 4), ("nft_mint", 5)];
+const CANDY_MACHINE_ACCOUNTS: Accounts = &[("candy_machine", 0)];
+const GUARD_ACCOUNTS: Accounts = &[("candy_guard", 0)];
+const GUARD_MINT_ACCOUNTS: Accounts =
+    &[("candy_guard", 0), ("candy_machine", 2), ("minter", 4), ("nft_mint", 5)];
+const GUARD_MINT_V2_ACCOUNTS: Accounts =
+    &[("candy_guard", 0), ("candy_machine", 2), ("minter", 5), ("nft_mint", 6)];
+const GUARD_ROUTE_ACCOUNTS: Accounts = &[("candy_guard", 0), ("candy_machine", 1), ("minter", 2)];
+
+// The properties of an instruction: key, parent key, value and type.
+type Fields = Vec<(&'static str, String, String, PropertyType)>;
+
+/// What the instructions of the programs decode to.
+pub fn describe() -> ProgramSchema {
+    let data = [
+        ("items_available", "", PropertyType::U64, "Number of NFTs the candy machine mints"),
+        ("symbol", "", PropertyType::Text, "Symbol of the NFTs"),
+        ("seller_fee_basis_points", "", PropertyType::U64,
+         "Royalties paid to the creators on secondary sales, in basis points"),
+        ("max_supply", "", PropertyType::U64, "Maximum number of editions of each NFT"),
+        ("is_mutable", "", PropertyType::Bool, "Whether the metadata of the NFTs can be updated"),
+        ("address", "creators/{index}", PropertyType::Pubkey, "Address of a creator"),
+        ("verified", "creators/{index}", PropertyType::Bool,
+         "Whether the creator signed the metadata"),
+        ("share", "creators/{index}", PropertyType::U64,
+         "Share of the royalties of the creator, in percent"),
+        ("prefix_name", "config_line_settings", PropertyType::Text,
+         "Prefix of the names of the NFTs, when they're read from config lines"),
+        ("name_length", "config_line_settings", PropertyType::U64,
+         "Length of the names in the config lines"),
+        ("prefix_uri", "config_line_settings", PropertyType::Text,
+         "Prefix of the URIs of the NFTs, when they're read from config lines"),
+        ("uri_length", "config_line_settings", PropertyType::U64,
+         "Length of the URIs in the config lines"),
+        ("is_sequential", "config_line_settings", PropertyType::Bool,
+         "Whether the NFTs are minted in the order of the config lines"),
+        ("name", "hidden_settings", PropertyType::Text,
+         "Name of every NFT, until they're revealed"),
+        ("uri", "hidden_settings", PropertyType::Text, "URI of every NFT, until they're revealed"),
+        ("hash", "hidden_settings", PropertyType::Bytes,
+         "Hash of the config lines revealed later, hex encoded"),
+    ];
+    let candy_machine = ("candy_machine", "", PropertyType::Pubkey,
+                         "Candy machine, when the accounts of the instruction are known");
+    let collection_mint = ("collection_mint", "", PropertyType::Pubkey,
+                           "Mint of the collection of the NFTs, when the accounts of the \
+                           instruction are known");
+    let nft_mint = ("nft_mint", "", PropertyType::Pubkey,
+                    "Mint of the NFT minted, when the accounts of the instruction are known");
+    let candy_guard = ("candy_guard", "", PropertyType::Pubkey,
+                       "Candy guard, when the accounts of the instruction are known");
+    let minter = ("minter", "", PropertyType::Pubkey,
+                  "Account minting, when the accounts of the instruction are known");
+    let group = ("group", "", PropertyType::Text,
+                 "Label of the guard group minted with, when not the default guards");
+    let guard = ("guard", "guards/{index}", PropertyType::Text,
+                 "Name of a guard of the default guard set");
+
+    let mut initialize = data.to_vec();
+    initialize.extend_from_slice(&[candy_machine, collection_mint]);
+    let mut initialize_v2 = initialize.clone();
+    initialize_v2.push(("token_standard", "", PropertyType::Text,
+                        "Token standard of the NFTs (e.g. programmable-non-fungible)"));
+    let mut update = data.to_vec();
+    update.push(candy_machine);
+
+    ProgramSchema::new("candy-machine")
+        .function("initialize", &initialize)
+        .function("initialize-v2", &initialize_v2)
+        .function("mint", &[candy_machine, nft_mint])
+        .function("mint-v2", &[
+            candy_machine,
+            ("nft_owner", "", PropertyType::Pubkey,
+             "Owner of the NFT minted, when the accounts of the instruction are known"),
+            nft_mint,
+        ])
+        .function("update", &update)
+        .function("withdraw", &[candy_machine])
+        .function("guard-initialize", &[guard, candy_guard])
+        .function("guard-update", &[guard, candy_guard])
+        .function("guard-withdraw", &[candy_guard])
+        .function("guard-mint", &[group, candy_guard, candy_machine, minter, nft_mint])
+        .function("guard-mint-v2", &[group, candy_guard, candy_machine, minter, nft_mint])
+        .function("guard-route", &[
+            ("guard", "", PropertyType::Text, "Name of the guard routed to"),
+            group,
+            candy_guard,
+            candy_machine,
+            minter,
+        ])
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    if instruction.data.len() < 8 {
+        error!("[spi-wrapper/candy-machine] Attempt to parse instruction from program {} failed \
+            as it is shorter than a discriminator.", instruction.program);
+
+        return None;
+    }
+    let (discriminator, arguments) = instruction.data.split_at(8);
+    let mut arguments = Reader::new(arguments);
+    let guard = instruction.program.as_str() == CANDY_GUARD_PROGRAM_ADDRESS;
+
+    let decoded = match (guard, <[u8; 8]>::try_from(discriminator).ok()?) {
+        (false, INITIALIZE) => candy_machine_data(&mut arguments)
+            .map(|fields| ("initialize", fields, INITIALIZE_ACCOUNTS)),
+        (false, INITIALIZE_V2) => initialize_v2(&mut arguments)
+            .map(|fields| ("initialize-v2", fields, INITIALIZE_V2_ACCOUNTS)),
+        (false, MINT) => Some(("mint", Fields::new(), MINT_ACCOUNTS)),
+        (false, MINT_V2) => Some(("mint-v2", Fields::new(), MINT_V2_ACCOUNTS)),
+        (false, UPDATE) => candy_machine_data(&mut arguments)
+            .map(|fields| ("update", fields, CANDY_MACHINE_ACCOUNTS)),
+        (false, WITHDRAW) => Some(("withdraw", Fields::new(), CANDY_MACHINE_ACCOUNTS)),
+        (true, INITIALIZE) => guard_set(&mut arguments)
+            .map(|fields| ("guard-initialize", fields, GUARD_ACCOUNTS)),
+        (true, UPDATE) => guard_set(&mut arguments)
+            .map(|fields| ("guard-update", fields, GUARD_ACCOUNTS)),
+        (true, WITHDRAW) => Some(("guard-withdraw", Fields::new(), GUARD_ACCOUNTS)),
+        (true, MINT) => guard_mint(&mut arguments)
+            .map(|fields| ("guard-mint", fields, GUARD_MINT_ACCOUNTS)),
+        (true, MINT_V2) => guard_mint(&mut arguments)
+            .map(|fields| ("guard-mint-v2", fields, GUARD_MINT_V2_ACCOUNTS)),
+        (true, ROUTE) => route(&mut arguments)
+            .map(|fields| ("guard-route", fields, GUARD_ROUTE_ACCOUNTS)),
+        _ => {
+            debug!("[spi-wrapper/candy-machine] Instruction {} of {} isn't one the processor \
+                decodes.", hex::encode(discriminator), instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let (function_name, fields, known_accounts) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/candy-machine] Attempt to parse instruction from program {} \
+                failed due to truncated or invalid arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, parent_key, value, value_type)| {
+            layout::property(&instruction, key, &parent_key, value, value_type)
+        })
+        .collect();
+    for (key, index) in known_accounts {
+        if let Some(account) = accounts.get(*index) {
+            instruction_set.properties.push(layout::property(
+                &instruction, key, "", account.to_string(), PropertyType::Pubkey));
+        }
+    }
+
+    Some(instruction_set)
+}
+
+/// A `Vec<u8>`, the arguments the Candy Guard reads itself.
+fn byte_vec<'a>(arguments: &mut Reader<'a>) -> Option<&'a [u8]> {
+    let len = arguments.u32()? as usize;
+    arguments.bytes(len)
+}
+
+/// `CandyMachineData`: the number of NFTs, what their metadata shares, then where their names
+/// and URIs come from: config lines, or the same for all of them until they're revealed.
+fn candy_machine_data(arguments: &mut Reader) -> Option<Fields> {
+    let mut fields = vec![
+        top_level("items_available", arguments.u64()?.to_string(), PropertyType::U64),
+        top_level("symbol", arguments.string()?.trim_end_matches('\0').to_string(),
+                  PropertyType::Text),
+        top_level("seller_fee_basis_points", arguments.u16()?.to_string(), PropertyType::U64),
+        top_level("max_supply", arguments.u64()?.to_string(), PropertyType::U64),
+        top_level("is_mutable", flag(arguments.bool()?), PropertyType::Bool),
+    ];
+    // Creators: an address, whether it's verified and a share.
+    let creators = arguments.vec(34, |arguments| {
+        Some((arguments.pubkey()?, arguments.bool()?, arguments.u8()?))
+    })?;
+    for (index, (address, verified, share)) in creators.into_iter().enumerate() {
+        let parent_key = format!("creators/{}", index);
+        fields.push(("address", parent_key.clone(), address.to_string(), PropertyType::Pubkey));
+        fields.push(("verified", parent_key.clone(), flag(verified), PropertyType::Bool));
+        fields.push(("share", parent_key, share.to_string(), PropertyType::U64));
+    }
+
+    let config_line_settings = arguments.option(|arguments| {
+        Some((arguments.string()?, arguments.u32()?, arguments.string()?, arguments.u32()?,
+              arguments.bool()?))
+    })?;
+    if let Some((prefix_name, name_length, prefix_uri, uri_length, is_sequential)) =
+        config_line_settings {
+        let parent_key = || "config_line_settings".to_string();
+        fields.push(("prefix_name", parent_key(), prefix_name.to_string(), PropertyType::Text));
+        fields.push(("name_length", parent_key(), name_length.to_string(), PropertyType::U64));
+        fields.push(("prefix_uri", parent_key(), prefix_uri.to_string(), PropertyType::Text));
+        fields.push(("uri_length", parent_key(), uri_length.to_string(), PropertyType::U64));
+        fields.push(("is_sequential", parent_key(), flag(is_sequential), PropertyType::Bool));
+    }
+    let hidden_settings = arguments.option(|arguments| {
+        Some((arguments.string()?, arguments.string()?, arguments.bytes(32)?))
+    })?;
+    if let Some((name, uri, hash)) = hidden_settings {
+        let parent_key = || "hidden_settings".to_string();
+        fields.push(("name", parent_key(), name.to_string(), PropertyType::Text));
+        fields.push(("uri", parent_key(), uri.to_string(), PropertyType::Text));
+        fields.push(("hash", parent_key(), hex::encode(hash), PropertyType::Bytes));
+    }
+
+    Some(fields)
+}
+
+/// The arguments of `initialize_v2`: the data of the candy machine, then the token standard of
+/// its NFTs.
+fn initialize_v2(arguments: &mut Reader) -> Option<Fields> {
+    let mut fields = candy_machine_data(arguments)?;
+    let token_standard = arguments.name(TOKEN_STANDARDS)?;
+    fields.push(top_level("token_standard", token_standard.to_string(), PropertyType::Text));
+
+    Some(fields)
+}
+
+/// The guards of the default guard set of `CandyGuardData`, which starts with their flags.
+/// Guards newer than the processor are left out.
+fn guard_set(arguments: &mut Reader) -> Option<Fields> {
+    let features = Reader::new(byte_vec(arguments)?).u64()?;
+
+    Some(GUARDS.iter().enumerate()
+        .filter(|(bit, _)| features & (1 << bit) != 0)
+        .enumerate()
+        .map(|(index, (_, guard))| {
+            ("guard", format!("guards/{}", index), guard.to_string(), PropertyType::Text)
+        })
+        .collect())
+}
+
+/// The label of the group minted with, if any.
+fn group(arguments: &mut Reader) -> Option<Fields> {
+    let label = arguments.option(Reader::string)?;
+    Some(label.map(|label| top_level("group", label.to_string(), PropertyType::Text))
+        .into_iter()
+        .collect())
+}
+
+/// The arguments of the Candy Guard `mint` and `mint_v2`: those of the guards, then the group.
+fn guard_mint(arguments: &mut Reader) -> Option<Fields> {
+    byte_vec(arguments)?;
+    group(arguments)
+}
+
+/// The arguments of `route`: the guard routed to and its arguments, then the group.
+fn route(arguments: &mut Reader) -> Option<Fields> {
+    let guard = arguments.name(GUARDS)?;
+    byte_vec(arguments)?;
+    let mut fields = vec![top_level("guard", guard.to_string(), PropertyType::Text)];
+    fields.extend(group(arguments)?);
+
+    Some(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use solana_sdk::hash::hash;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(program: &str, data: &[u8], accounts: &[Pubkey]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(program, data.to_vec()), accounts)
+    }
+
+    fn string(data: &mut Vec<u8>, string: &str) {
+        data.extend_from_slice(&(string.len() as u32).to_le_bytes());
+        data.extend_from_slice(string.as_bytes());
+    }
+
+    /// `CandyMachineData` of 5000 NFTs, a creator and config lines.
+    fn candy_machine_data(creator: &Pubkey) -> Vec<u8> {
+        let mut data = 5000u64.to_le_bytes().to_vec();
+        string(&mut data, "APE");
+        data.extend_from_slice(&500u16.to_le_bytes());
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&[1, 1, 0, 0, 0]);
+        data.extend_from_slice(creator.as_ref());
+        data.extend_from_slice(&[1, 100, 1]);
+        string(&mut data, "Ape #$ID+1$");
+        data.extend_from_slice(&4u32.to_le_bytes());
+        string(&mut data, "https://arweave.net/");
+        data.extend_from_slice(&43u32.to_le_bytes());
+        data.extend_from_slice(&[0, 0]);
+        data
+    }
+
+    /// `CandyGuardData` of a default guard set of `features`, and the settings of its guards.
+    fn candy_guard_data(features: u64, settings: &[u8]) -> Vec<u8> {
+        let guard_data = [&features.to_le_bytes()[..], settings].concat();
+        [&(guard_data.len() as u32).to_le_bytes()[..], &guard_data].concat()
+    }
+
+    #[test]
+    fn discriminators_are_the_anchor_ones() {
+        for (name, discriminator) in &[
+            ("initialize", INITIALIZE),
+            ("initialize_v2", INITIALIZE_V2),
+            ("mint", MINT),
+            ("mint_v2", MINT_V2),
+            ("update", UPDATE),
+            ("withdraw", WITHDRAW),
+            ("route", ROUTE),
+        ] {
+            let preimage = format!("global:{}", name);
+            let expected: [u8; 8] = hash(preimage.as_bytes()).to_bytes()[..8].try_into().unwrap();
+            assert_eq!(*discriminator, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn candy_machines_are_initialized_and_minted_from() {
+        let creator = Pubkey::new_unique();
+        let accounts: Vec<_> = (0..20).map(|_| Pubkey::new_unique()).collect();
+
+        // Of programmable NFTs.
+        let initialize = [&INITIALIZE_V2[..], &candy_machine_data(&creator), &[4]].concat();
+        let initialize = decode(CANDY_MACHINE_PROGRAM_ADDRESS, &initialize, &accounts).unwrap();
+        assert_eq!(initialize.function.function_name.as_str(), "initialize-v2");
+        let expected: Vec<(&str, String)> = vec![
+            ("items_available", "5000"),
+            ("symbol", "APE"),
+            ("seller_fee_basis_points", "500"),
+            ("max_supply", "0"),
+            ("is_mutable", "1"),
+            ("creators/0.address", creator.to_string().as_str()),
+            ("creators/0.verified", "1"),
+            ("creators/0.share", "100"),
+            ("config_line_settings.prefix_name", "Ape #$ID+1$"),
+            ("config_line_settings.name_length", "4"),
+            ("config_line_settings.prefix_uri", "https://arweave.net/"),
+            ("config_line_settings.uri_length", "43"),
+            ("config_line_settings.is_sequential", "0"),
+            ("token_standard", "programmable-non-fungible"),
+            ("candy_machine", accounts[0].to_string().as_str()),
+            ("collection_mint", accounts[6].to_string().as_str()),
+        ].into_iter().map(|(key, value)| (key, value.to_string())).collect();
+        assert_eq!(properties(&initialize), expected);
+
+        let mint = decode(CANDY_MACHINE_PROGRAM_ADDRESS, &MINT_V2, &accounts).unwrap();
+        assert_eq!(mint.function.function_name.as_str(), "mint-v2");
+        assert_eq!(properties(&mint), vec![
+            ("candy_machine", accounts[0].to_string()),
+            ("nft_owner", accounts[4].to_string()),
+            ("nft_mint", accounts[5].to_string()),
+        ]);
+    }
+
+    #[test]
+    fn guards_are_named() {
+        let accounts: Vec<_> = (0..20).map(|_| Pubkey::new_unique()).collect();
+
+        // A bot tax, a SOL payment and a start date, whose settings aren't read.
+        let initialize = [&INITIALIZE[..], &candy_guard_data(0b1011, &[0; 57])].concat();
+        let initialize = decode(CANDY_GUARD_PROGRAM_ADDRESS, &initialize, &accounts[..1]).unwrap();
+        assert_eq!(initialize.function.function_name.as_str(), "guard-initialize");
+        assert_eq!(properties(&initialize), vec![
+            ("guards/0.guard", "bot-tax".to_string()),
+            ("guards/1.guard", "sol-payment".to_string()),
+            ("guards/2.guard", "start-date".to_string()),
+            ("candy_guard", accounts[0].to_string()),
+        ]);
+
+        // Minting with the `OG` group, and routing to its allow list.
+        let mut mint = [&MINT_V2[..], &[0; 4], &[1]].concat();
+        string(&mut mint, "OG");
+        let mint = decode(CANDY_GUARD_PROGRAM_ADDRESS, &mint, &accounts).unwrap();
+        assert_eq!(properties(&mint), vec![
+            ("group", "OG".to_string()),
+            ("candy_guard", accounts[0].to_string()),
+            ("candy_machine", accounts[2].to_string()),
+            ("minter", accounts[5].to_string()),
+            ("nft_mint", accounts[6].to_string()),
+        ]);
+        let mut route = [&ROUTE[..], &[8, 33, 0, 0, 0, 0], &[0; 32], &[1]].concat();
+        string(&mut route, "OG");
+        assert_eq!(properties(&decode(CANDY_GUARD_PROGRAM_ADDRESS, &route, &[]).unwrap()), vec![
+            ("guard", "allow-list".to_string()),
+            ("group", "OG".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        let data = candy_machine_data(&Pubkey::new_unique());
+        let initialize = [&INITIALIZE[..], &data[..20]].concat();
+        assert!(decode(CANDY_MACHINE_PROGRAM_ADDRESS, &initialize, &[]).is_none());
+        // Guards of the Candy Guard sent to the Candy Machine, an unknown guard and an unknown
+        // instruction.
+        let guards = [&INITIALIZE[..], &candy_guard_data(1, &[0; 9])].concat();
+        assert!(decode(CANDY_MACHINE_PROGRAM_ADDRESS, &guards, &[]).is_none());
+        let route = [&ROUTE[..], &[200, 0, 0, 0, 0, 0]].concat();
+        assert!(decode(CANDY_GUARD_PROGRAM_ADDRESS, &route, &[]).is_none());
+        assert!(decode(CANDY_GUARD_PROGRAM_ADDRESS, &[0; 8], &[]).is_none());
+        assert!(decode(CANDY_GUARD_PROGRAM_ADDRESS, &MINT[..4], &[]).is_none());
+    }
+}
@@ -0,0 +1,315 @@
+//! The Drift v2 perpetual futures exchange, an Anchor program: every instruction starts with the
+//! first 8 bytes of `sha256("global:<instruction name>")`, followed by its borsh encoded
+//! arguments.
+//!
+//! `OrderParams` gained fields (and `post_only` went from a bool to an enum) across versions of
+//! the program, always at the end or with the same encoding, so only the fields every version
+//! starts with are decoded and whatever follows is ignored.
+
+use std::convert::TryFrom;
+
+use smallvec::{smallvec, SmallVec};
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS: &str = "dRiftyHA39MWEi3m9aunc5MzRF1JYuBsbn6VPcn33UH";
+
+const PLACE_PERP_ORDER: [u8; 8] = [69, 161, 93, 202, 120, 126, 76, 185];
+const PLACE_AND_TAKE_PERP_ORDER: [u8; 8] = [213, 51, 1, 187, 108, 220, 230, 224];
+const CANCEL_ORDER: [u8; 8] = [95, 129, 237, 240, 8, 49, 223, 132];
+const SETTLE_PNL: [u8; 8] = [43, 61, 234, 45, 15, 95, 152, 153];
+const DEPOSIT: [u8; 8] = [242, 35, 198, 137, 82, 225, 242, 182];
+const WITHDRAW: [u8; 8] = [183, 18, 70, 156, 148, 109, 161, 34];
+const LIQUIDATE_PERP: [u8; 8] = [75, 35, 119, 247, 191, 18, 139, 2];
+
+// The names of the variants of the enums of `OrderParams`, by discriminant.
+const ORDER_TYPES: &[&str] = &["market", "limit", "trigger-market", "trigger-limit", "oracle"];
+const DIRECTIONS: &[&str] = &["long", "short"];
+// Older versions encode a bool, which lines up with the first two.
+const POST_ONLY: &[&str] = &["none", "must-post-only", "try-post-only", "slide"];
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let order_params = [
+        ("order_type", "params", PropertyType::Text,
+         "Order type (market, limit, trigger-market, trigger-limit or oracle)"),
+        ("direction", "params", PropertyType::Text, "Direction of the order (long or short)"),
+        ("base_asset_amount", "params", PropertyType::U64,
+         "Size of the order, in base asset precision (1e9)"),
+        ("price", "params", PropertyType::U64,
+         "Limit price, in price precision (1e6), 0 for market orders"),
+        ("market_index", "params", PropertyType::U64, "Index of the perp market"),
+        ("reduce_only", "params", PropertyType::Bool,
+         "Whether the order may only reduce the position"),
+        ("post_only", "params", PropertyType::Text,
+         "Whether the order may only be a maker (none, must-post-only, try-post-only or slide)"),
+    ];
+    let spot_transfer = [
+        ("market_index", "", PropertyType::U64, "Index of the spot market"),
+        ("amount", "", PropertyType::U64, "Amount of tokens, in base units"),
+        ("reduce_only", "", PropertyType::Bool,
+         "Whether the transfer may only reduce a borrow or deposit"),
+    ];
+
+    ProgramSchema::new("drift")
+        .function("place-perp-order", &order_params)
+        .function("place-and-take-perp-order", &order_params)
+        .function("cancel-order", &[
+            ("order_id", "", PropertyType::U64,
+             "Id of the order cancelled, absent for the last order placed"),
+        ])
+        .function("settle-pnl", &[
+            ("market_index", "", PropertyType::U64, "Index of the perp market"),
+        ])
+        .function("deposit", &spot_transfer)
+        .function("withdraw", &spot_transfer)
+        .function("liquidate-perp", &[
+            ("market_index", "", PropertyType::U64, "Index of the perp market"),
+            ("liquidator_max_base_asset_amount", "", PropertyType::U64,
+             "Largest share of the position the liquidator takes over, in base asset precision"),
+            ("limit_price", "", PropertyType::U64,
+             "Worst price the liquidator takes the position over at, in price precision"),
+        ])
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction
+) -> Option<InstructionSet> {
+    if instruction.data.len() < 8 {
+        error!("[spi-wrapper/drift] Attempt to parse instruction from program {} failed as it \
+            is shorter than a discriminator.", instruction.program);
+
+        return None;
+    }
+    let (discriminator, arguments) = instruction.data.split_at(8);
+    let mut arguments = Reader::new(arguments);
+
+    let decoded = match <[u8; 8]>::try_from(discriminator).ok()? {
+        PLACE_PERP_ORDER => order_params(&mut arguments)
+            .map(|properties| ("place-perp-order", properties)),
+        // Followed by the maker order id, or a success condition in newer versions.
+        PLACE_AND_TAKE_PERP_ORDER => order_params(&mut arguments)
+            .map(|properties| ("place-and-take-perp-order", properties)),
+        CANCEL_ORDER => arguments.option(Reader::u32).map(|order_id| {
+            let properties: Fields = order_id.into_iter()
+                .map(|order_id| ("order_id", "", order_id.to_string(), PropertyType::U64))
+                .collect();
+            ("cancel-order", properties)
+        }),
+        SETTLE_PNL => arguments.u16().map(|market_index| {
+            let properties: Fields = smallvec![
+                ("market_index", "", market_index.to_string(), PropertyType::U64),
+            ];
+            ("settle-pnl", properties)
+        }),
+        DEPOSIT => spot_transfer(&mut arguments).map(|properties| ("deposit", properties)),
+        WITHDRAW => spot_transfer(&mut arguments).map(|properties| ("withdraw", properties)),
+        LIQUIDATE_PERP => liquidate_perp(&mut arguments)
+            .map(|properties| ("liquidate-perp", properties)),
+        _ => {
+            debug!("[spi-wrapper/drift] Instruction {} of {} isn't one the processor decodes.",
+                hex::encode(discriminator), instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let (function_name, fields) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/drift] Attempt to parse instruction from program {} failed due \
+                to truncated or invalid arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, parent_key, value, value_type)| {
+            layout::property(&instruction, key, parent_key, value, value_type)
+        })
+        .collect();
+
+    Some(instruction_set)
+}
+
+// The properties of an instruction: key, parent key, value and type.
+type Fields = SmallVec<[(&'static str, &'static str, String, PropertyType); 8]>;
+
+/// The fields of `OrderParams` every version of the program starts with.
+fn order_params(arguments: &mut Reader) -> Option<Fields> {
+    let order_type = arguments.name(ORDER_TYPES)?;
+    // Always perp for perp orders.
+    let _market_type = arguments.u8()?;
+    let direction = arguments.name(DIRECTIONS)?;
+    let _user_order_id = arguments.u8()?;
+    let base_asset_amount = arguments.u64()?;
+    let price = arguments.u64()?;
+    let market_index = arguments.u16()?;
+    let reduce_only = arguments.bool()?;
+    let post_only = arguments.name(POST_ONLY)?;
+
+    Some(smallvec![
+        ("order_type", "params", order_type.to_string(), PropertyType::Text),
+        ("direction", "params", direction.to_string(), PropertyType::Text),
+        ("base_asset_amount", "params", base_asset_amount.to_string(), PropertyType::U64),
+        ("price", "params", price.to_string(), PropertyType::U64),
+        ("market_index", "params", market_index.to_string(), PropertyType::U64),
+        ("reduce_only", "params", (reduce_only as i32).to_string(), PropertyType::Bool),
+        ("post_only", "params", post_only.to_string(), PropertyType::Text),
+    ])
+}
+
+/// The arguments of `deposit` and `withdraw`.
+fn spot_transfer(arguments: &mut Reader) -> Option<Fields> {
+    let market_index = arguments.u16()?;
+    let amount = arguments.u64()?;
+    let reduce_only = arguments.bool()?;
+
+    Some(smallvec![
+        ("market_index", "", market_index.to_string(), PropertyType::U64),
+        ("amount", "", amount.to_string(), PropertyType::U64),
+        ("reduce_only", "", (reduce_only as i32).to_string(), PropertyType::Bool),
+    ])
+}
+
+fn liquidate_perp(arguments: &mut Reader) -> Option<Fields> {
+    let market_index = arguments.u16()?;
+    let liquidator_max_base_asset_amount = arguments.u64()?;
+    let limit_price = arguments.option(Reader::u64)?;
+
+    let mut properties: Fields = smallvec![
+        ("market_index", "", market_index.to_string(), PropertyType::U64),
+        ("liquidator_max_base_asset_amount", "", liquidator_max_base_asset_amount.to_string(),
+         PropertyType::U64),
+    ];
+    if let Some(limit_price) = limit_price {
+        properties.push(("limit_price", "", limit_price.to_string(), PropertyType::U64));
+    }
+
+    Some(properties)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use solana_sdk::hash::hash;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    // Instruction data as sent to the program, discriminator included.
+    // `OrderParams` of the first v2 releases: a short post only limit order, with `post_only` a
+    // bool and a `position_limit`.
+    const PLACE_PERP_ORDER_V2_0: &str = "45a15dca787e4cb90101010700ca9a3b00000000a052570100000000\
+        00000001000000000000000000000000000000000000000000000000";
+    // The current layout: a long reduce only limit order expiring at `max_ts`, with an auction.
+    const PLACE_PERP_ORDER_CURRENT: &str = "45a15dca787e4cb901010003809698000000000000ac23fc060000\
+        000100010200013cf1536500000000000000010a0000";
+    // A short market order, followed by its success condition.
+    const PLACE_AND_TAKE_PERP_ORDER_CURRENT: &str = "d53301bb6cdce6e00001010000f9029500000000e02a\
+        4e010000000000000000000000000001000180b14f010000000001e02a4e01000000000101000000";
+    const CANCEL_ORDER_42: &str = "5f81edf00831df84012a000000";
+    const SETTLE_PNL_SOL_PERP: &str = "2b3dea2d0f5f98990000";
+    const DEPOSIT_100_USDC: &str = "f223c68952e1f2b6000000e1f5050000000000";
+    const WITHDRAW_25_USDC: &str = "b712469c946da122000040787d010000000001";
+    const LIQUIDATE_PERP_5_SOL: &str = "4b2377f7bf128b02000000f2052a0100000001002d310100000000";
+
+    fn decode(data: &str) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, hex::decode(data).unwrap()))
+    }
+
+    #[test]
+    fn discriminators_are_the_anchor_ones() {
+        for (name, discriminator) in &[
+            ("place_perp_order", PLACE_PERP_ORDER),
+            ("place_and_take_perp_order", PLACE_AND_TAKE_PERP_ORDER),
+            ("cancel_order", CANCEL_ORDER),
+            ("settle_pnl", SETTLE_PNL),
+            ("deposit", DEPOSIT),
+            ("withdraw", WITHDRAW),
+            ("liquidate_perp", LIQUIDATE_PERP),
+        ] {
+            let preimage = format!("global:{}", name);
+            let expected: [u8; 8] = hash(preimage.as_bytes()).to_bytes()[..8].try_into().unwrap();
+            assert_eq!(*discriminator, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn order_params_of_every_layout_decode() {
+        let early = decode(PLACE_PERP_ORDER_V2_0).unwrap();
+        assert_eq!(early.function.function_name.as_str(), "place-perp-order");
+        assert_eq!(properties(&early), vec![
+            ("order_type", "limit".to_string()),
+            ("direction", "short".to_string()),
+            ("base_asset_amount", "1000000000".to_string()),
+            ("price", "22500000".to_string()),
+            ("market_index", "0".to_string()),
+            ("reduce_only", "0".to_string()),
+            ("post_only", "must-post-only".to_string()),
+        ]);
+
+        let current = decode(PLACE_PERP_ORDER_CURRENT).unwrap();
+        assert_eq!(properties(&current), vec![
+            ("order_type", "limit".to_string()),
+            ("direction", "long".to_string()),
+            ("base_asset_amount", "10000000".to_string()),
+            ("price", "30000000000".to_string()),
+            ("market_index", "1".to_string()),
+            ("reduce_only", "1".to_string()),
+            ("post_only", "try-post-only".to_string()),
+        ]);
+
+        let take = decode(PLACE_AND_TAKE_PERP_ORDER_CURRENT).unwrap();
+        assert_eq!(take.function.function_name.as_str(), "place-and-take-perp-order");
+        assert_eq!(&properties(&take)[..3], &[
+            ("order_type", "market".to_string()),
+            ("direction", "short".to_string()),
+            ("base_asset_amount", "2500000000".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn account_and_liquidation_instructions_decode() {
+        let decoded: Vec<_> = [
+            CANCEL_ORDER_42,
+            SETTLE_PNL_SOL_PERP,
+            DEPOSIT_100_USDC,
+            WITHDRAW_25_USDC,
+            LIQUIDATE_PERP_5_SOL,
+        ].iter().map(|data| decode(data).unwrap()).collect();
+
+        assert_eq!(properties(&decoded[0]), vec![("order_id", "42".to_string())]);
+        assert_eq!(properties(&decoded[1]), vec![("market_index", "0".to_string())]);
+        assert_eq!(properties(&decoded[2]), vec![
+            ("market_index", "0".to_string()),
+            ("amount", "100000000".to_string()),
+            ("reduce_only", "0".to_string()),
+        ]);
+        assert_eq!(decoded[3].function.function_name.as_str(), "withdraw");
+        assert_eq!(properties(&decoded[3])[2], ("reduce_only", "1".to_string()));
+        assert_eq!(properties(&decoded[4]), vec![
+            ("market_index", "0".to_string()),
+            ("liquidator_max_base_asset_amount", "5000000000".to_string()),
+            ("limit_price", "20000000".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        // Cut before `post_only`.
+        assert!(decode(&PLACE_PERP_ORDER_CURRENT[..2 * (8 + 23)]).is_none());
+        // An out of range direction.
+        assert!(decode("45a15dca787e4cb9010102").is_none());
+        assert!(decode("5f81edf00831df8402").is_none());
+        assert!(decode("0001020304050607").is_none());
+        assert!(decode("45a15d").is_none());
+    }
+}
@@ -0,0 +1,445 @@
+//! The SPL governance program (Realms), running the DAOs of Solana: realms, their governances
+//! and treasuries, proposals and the votes cast on them. Every instruction starts with a one byte
+//! tag, followed by its borsh encoded arguments.
+//!
+//! The arguments changed along the versions of the program: only those all the versions share
+//! are decoded, e.g. the name of a realm but not the rest of its config. The realms, governances
+//! and proposals are read from the accounts of the instructions, and are only emitted when
+//! they're known. Vote weights aren't part of the instructions (they're those of the token owner
+//! records voting), so tallies are of the votes cast.
+
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, flag, top_level, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS: &str = "GovER5Lthms3bLBqWub97yVrMmEogzX7xNjdXpPPCVZw";
+
+const CREATE_REALM: u8 = 0;
+const DEPOSIT_GOVERNING_TOKENS: u8 = 1;
+const WITHDRAW_GOVERNING_TOKENS: u8 = 2;
+const SET_GOVERNANCE_DELEGATE: u8 = 3;
+const CREATE_GOVERNANCE: u8 = 4;
+const CREATE_PROPOSAL: u8 = 6;
+const INSERT_TRANSACTION: u8 = 9;
+const CANCEL_PROPOSAL: u8 = 11;
+const SIGN_OFF_PROPOSAL: u8 = 12;
+const CAST_VOTE: u8 = 13;
+const FINALIZE_VOTE: u8 = 14;
+const RELINQUISH_VOTE: u8 = 15;
+const EXECUTE_TRANSACTION: u8 = 16;
+const CREATE_TOKEN_OWNER_RECORD: u8 = 23;
+const CREATE_NATIVE_TREASURY: u8 = 25;
+const REVOKE_GOVERNING_TOKENS: u8 = 26;
+
+const VOTE_TYPES: &[&str] = &["single-choice", "multi-choice"];
+const VOTES: &[&str] = &["approve", "deny", "abstain", "veto"];
+
+// The accounts emitted for each instruction: key, then where it is in its accounts.
+type Accounts = &'static [(&'static str, usize)];
+
+const CREATE_REALM_ACCOUNTS: Accounts = &[("realm", 0), ("community_token_mint", 2)];
+const DEPOSIT_ACCOUNTS: Accounts =
+    &[("realm", 0), ("governing_token_owner", 3), ("token_owner_record", 5)];
+const WITHDRAW_ACCOUNTS: Accounts =
+    &[("realm", 0), ("governing_token_owner", 3), ("token_owner_record", 4)];
+const SET_GOVERNANCE_DELEGATE_ACCOUNTS: Accounts = &[("token_owner_record", 1)];
+const CREATE_GOVERNANCE_ACCOUNTS: Accounts =
+    &[("realm", 0), ("governance", 1), ("governed_account", 2)];
+const CREATE_PROPOSAL_ACCOUNTS: Accounts = &[
+    ("realm", 0),
+    ("proposal", 1),
+    ("governance", 2),
+    ("token_owner_record", 3),
+    ("governing_token_mint", 4),
+];
+const INSERT_TRANSACTION_ACCOUNTS: Accounts =
+    &[("governance", 0), ("proposal", 1), ("proposal_transaction", 4)];
+const PROPOSAL_ACCOUNTS: Accounts = &[("realm", 0), ("governance", 1), ("proposal", 2)];
+const CAST_VOTE_ACCOUNTS: Accounts = &[
+    ("realm", 0),
+    ("governance", 1),
+    ("proposal", 2),
+    ("token_owner_record", 4),
+    ("governing_token_mint", 7),
+];
+const EXECUTE_TRANSACTION_ACCOUNTS: Accounts =
+    &[("governance", 0), ("proposal", 1), ("proposal_transaction", 2)];
+const TOKEN_OWNER_RECORD_ACCOUNTS: Accounts = &[
+    ("realm", 0),
+    ("governing_token_owner", 1),
+    ("token_owner_record", 2),
+    ("governing_token_mint", 3),
+];
+const CREATE_NATIVE_TREASURY_ACCOUNTS: Accounts = &[("governance", 0), ("native_treasury", 1)];
+const REVOKE_ACCOUNTS: Accounts =
+    &[("realm", 0), ("token_owner_record", 2), ("governing_token_mint", 3)];
+
+// The properties of an instruction: key, parent key, value and type.
+type Fields = Vec<(&'static str, String, String, PropertyType)>;
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let account = |key: &'static str, description: &'static str| {
+        (key, "", PropertyType::Pubkey, description)
+    };
+    let realm = account("realm", "Realm, when the accounts of the instruction are known");
+    let governance = account("governance",
+                             "Governance, when the accounts of the instruction are known");
+    let proposal = account("proposal", "Proposal, when the accounts of the instruction are known");
+    let token_owner_record = account("token_owner_record",
+                                     "Token owner record, when the accounts of the instruction \
+                                     are known");
+    let governing_token_owner = account("governing_token_owner",
+                                        "Owner of the governing tokens, when the accounts of the \
+                                        instruction are known");
+    let governing_token_mint = account("governing_token_mint",
+                                       "Mint of the governing tokens (community or council), \
+                                       when the accounts of the instruction are known");
+    let proposal_transaction = account("proposal_transaction",
+                                       "Transaction of the proposal, when the accounts of the \
+                                       instruction are known");
+    let amount = ("amount", "", PropertyType::U64, "Amount of governing tokens");
+
+    ProgramSchema::new("governance")
+        .function("create-realm", &[
+            ("name", "", PropertyType::Text, "Name of the realm"),
+            ("use_council_mint", "", PropertyType::Bool, "Whether the realm has a council"),
+            ("min_community_weight_to_create_governance", "", PropertyType::U64,
+             "Community tokens needed to create a governance"),
+            realm,
+            account("community_token_mint",
+                    "Mint of the community tokens, when the accounts of the instruction are \
+                    known"),
+        ])
+        .function("deposit-governing-tokens", &[
+            amount, realm, governing_token_owner, token_owner_record,
+        ])
+        .function("withdraw-governing-tokens", &[realm, governing_token_owner, token_owner_record])
+        .function("set-governance-delegate", &[
+            ("new_governance_delegate", "", PropertyType::Pubkey,
+             "Delegate voting with the token owner record, empty when removed"),
+            token_owner_record,
+        ])
+        .function("create-governance", &[
+            realm,
+            governance,
+            account("governed_account",
+                    "Account governed, when the accounts of the instruction are known"),
+        ])
+        .function("create-proposal", &[
+            ("name", "", PropertyType::Text, "Name of the proposal"),
+            ("description_link", "", PropertyType::Text, "Link to the description of the proposal"),
+            ("vote_type", "", PropertyType::Text,
+             "How the options are voted on (single-choice or multi-choice)"),
+            ("label", "options/{index}", PropertyType::Text, "Label of an option"),
+            ("use_deny_option", "", PropertyType::Bool,
+             "Whether the proposal can be denied, rather than only choosing among its options"),
+            realm,
+            proposal,
+            governance,
+            token_owner_record,
+            governing_token_mint,
+        ])
+        .function("insert-transaction", &[
+            ("option_index", "", PropertyType::U64, "Option the transaction executes for"),
+            ("index", "", PropertyType::U64, "Index of the transaction in the option"),
+            ("hold_up_time", "", PropertyType::U64,
+             "Seconds the transaction waits for after the proposal passes"),
+            governance,
+            proposal,
+            proposal_transaction,
+        ])
+        .function("cancel-proposal", &[realm, governance, proposal])
+        .function("sign-off-proposal", &[realm, governance, proposal])
+        .function("cast-vote", &[
+            ("vote", "", PropertyType::Text, "Vote cast (approve, deny, abstain or veto)"),
+            ("rank", "choices/{index}", PropertyType::U64,
+             "Rank of an option approved, for ranked votes"),
+            ("weight_percentage", "choices/{index}", PropertyType::U64,
+             "Share of the vote weight given to an option, in percent"),
+            realm,
+            governance,
+            proposal,
+            token_owner_record,
+            governing_token_mint,
+        ])
+        .function("finalize-vote", &[realm, governance, proposal])
+        .function("relinquish-vote", &[realm, governance, proposal])
+        .function("execute-transaction", &[governance, proposal, proposal_transaction])
+        .function("create-token-owner-record", &[
+            realm, governing_token_owner, token_owner_record, governing_token_mint,
+        ])
+        .function("create-native-treasury", &[
+            governance,
+            account("native_treasury",
+                    "Treasury of the governance holding SOL, when the accounts of the instruction \
+                    are known"),
+        ])
+        .function("revoke-governing-tokens", &[
+            amount, realm, token_owner_record, governing_token_mint,
+        ])
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    let (tag, arguments) = match instruction.data.split_first() {
+        Some(split) => split,
+        None => {
+            error!("[spi-wrapper/governance] Attempt to parse instruction from program {} failed \
+                as it is empty.", instruction.program);
+
+            return None;
+        }
+    };
+    let mut arguments = Reader::new(arguments);
+    let none = || Some(Fields::new());
+
+    let decoded = match *tag {
+        CREATE_REALM => create_realm(&mut arguments)
+            .map(|fields| ("create-realm", fields, CREATE_REALM_ACCOUNTS)),
+        DEPOSIT_GOVERNING_TOKENS => amount(&mut arguments)
+            .map(|fields| ("deposit-governing-tokens", fields, DEPOSIT_ACCOUNTS)),
+        WITHDRAW_GOVERNING_TOKENS => none()
+            .map(|fields| ("withdraw-governing-tokens", fields, WITHDRAW_ACCOUNTS)),
+        SET_GOVERNANCE_DELEGATE => set_governance_delegate(&mut arguments)
+            .map(|fields| ("set-governance-delegate", fields, SET_GOVERNANCE_DELEGATE_ACCOUNTS)),
+        CREATE_GOVERNANCE => none()
+            .map(|fields| ("create-governance", fields, CREATE_GOVERNANCE_ACCOUNTS)),
+        CREATE_PROPOSAL => create_proposal(&mut arguments)
+            .map(|fields| ("create-proposal", fields, CREATE_PROPOSAL_ACCOUNTS)),
+        INSERT_TRANSACTION => insert_transaction(&mut arguments)
+            .map(|fields| ("insert-transaction", fields, INSERT_TRANSACTION_ACCOUNTS)),
+        CANCEL_PROPOSAL => none().map(|fields| ("cancel-proposal", fields, PROPOSAL_ACCOUNTS)),
+        SIGN_OFF_PROPOSAL => none().map(|fields| ("sign-off-proposal", fields, PROPOSAL_ACCOUNTS)),
+        CAST_VOTE => cast_vote(&mut arguments)
+            .map(|fields| ("cast-vote", fields, CAST_VOTE_ACCOUNTS)),
+        FINALIZE_VOTE => none().map(|fields| ("finalize-vote", fields, PROPOSAL_ACCOUNTS)),
+        RELINQUISH_VOTE => none().map(|fields| ("relinquish-vote", fields, PROPOSAL_ACCOUNTS)),
+        EXECUTE_TRANSACTION => none()
+            .map(|fields| ("execute-transaction", fields, EXECUTE_TRANSACTION_ACCOUNTS)),
+        CREATE_TOKEN_OWNER_RECORD => none()
+            .map(|fields| ("create-token-owner-record", fields, TOKEN_OWNER_RECORD_ACCOUNTS)),
+        CREATE_NATIVE_TREASURY => none()
+            .map(|fields| ("create-native-treasury", fields, CREATE_NATIVE_TREASURY_ACCOUNTS)),
+        REVOKE_GOVERNING_TOKENS => amount(&mut arguments)
+            .map(|fields| ("revoke-governing-tokens", fields, REVOKE_ACCOUNTS)),
+        _ => {
+            debug!("[spi-wrapper/governance] Instruction {} of {} isn't one the processor \
+                decodes.", tag, instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let (function_name, fields, known_accounts) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/governance] Attempt to parse instruction from program {} failed \
+                due to truncated or invalid arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, parent_key, value, value_type)| {
+            layout::property(&instruction, key, &parent_key, value, value_type)
+        })
+        .collect();
+    for (key, index) in known_accounts {
+        if let Some(account) = accounts.get(*index) {
+            instruction_set.properties.push(layout::property(
+                &instruction, key, "", account.to_string(), PropertyType::Pubkey));
+        }
+    }
+
+    Some(instruction_set)
+}
+
+/// The arguments of `CreateRealm`: its name, then the start of its config.
+fn create_realm(arguments: &mut Reader) -> Option<Fields> {
+    Some(vec![
+        top_level("name", arguments.string()?.to_string(), PropertyType::Text),
+        top_level("use_council_mint", flag(arguments.bool()?), PropertyType::Bool),
+        top_level("min_community_weight_to_create_governance", arguments.u64()?.to_string(),
+                  PropertyType::U64),
+    ])
+}
+
+/// The amount of `DepositGoverningTokens` and `RevokeGoverningTokens`.
+fn amount(arguments: &mut Reader) -> Option<Fields> {
+    Some(vec![top_level("amount", arguments.u64()?.to_string(), PropertyType::U64)])
+}
+
+fn set_governance_delegate(arguments: &mut Reader) -> Option<Fields> {
+    let delegate = arguments.option(Reader::pubkey)?;
+    let delegate = delegate.map(|delegate| delegate.to_string()).unwrap_or_default();
+
+    Some(vec![top_level("new_governance_delegate", delegate, PropertyType::Pubkey)])
+}
+
+/// The arguments of `CreateProposal`: its name and description, how it's voted on, its options,
+/// then whether it can be denied.
+fn create_proposal(arguments: &mut Reader) -> Option<Fields> {
+    let mut fields = vec![
+        top_level("name", arguments.string()?.to_string(), PropertyType::Text),
+        top_level("description_link", arguments.string()?.to_string(), PropertyType::Text),
+    ];
+    let vote_type = arguments.name(VOTE_TYPES)?;
+    // How many options voters choose and win, 4 bytes in every version.
+    if vote_type == "multi-choice" {
+        arguments.bytes(4)?;
+    }
+    fields.push(top_level("vote_type", vote_type.to_string(), PropertyType::Text));
+    let options = arguments.vec(4, |arguments| arguments.string())?;
+    for (index, label) in options.into_iter().enumerate() {
+        fields.push(("label", format!("options/{}", index), label.to_string(), PropertyType::Text));
+    }
+    fields.push(top_level("use_deny_option", flag(arguments.bool()?), PropertyType::Bool));
+
+    Some(fields)
+}
+
+/// The arguments of `InsertTransaction`: where the transaction goes, and how long it waits, then
+/// its instructions.
+fn insert_transaction(arguments: &mut Reader) -> Option<Fields> {
+    Some(vec![
+        top_level("option_index", arguments.u8()?.to_string(), PropertyType::U64),
+        top_level("index", arguments.u16()?.to_string(), PropertyType::U64),
+        top_level("hold_up_time", arguments.u32()?.to_string(), PropertyType::U64),
+    ])
+}
+
+/// The `Vote` of `CastVote`, along with the options approved.
+fn cast_vote(arguments: &mut Reader) -> Option<Fields> {
+    let vote = arguments.name(VOTES)?;
+    let mut fields = vec![top_level("vote", vote.to_string(), PropertyType::Text)];
+    if vote == "approve" {
+        let choices = arguments.vec(2, |arguments| Some((arguments.u8()?, arguments.u8()?)))?;
+        for (index, (rank, weight_percentage)) in choices.into_iter().enumerate() {
+            let parent_key = format!("choices/{}", index);
+            fields.push(("rank", parent_key.clone(), rank.to_string(), PropertyType::U64));
+            fields.push(("weight_percentage", parent_key, weight_percentage.to_string(),
+                         PropertyType::U64));
+        }
+    }
+
+    Some(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(data: &[u8], accounts: &[Pubkey]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, data.to_vec()), accounts)
+    }
+
+    fn string(data: &mut Vec<u8>, string: &str) {
+        data.extend_from_slice(&(string.len() as u32).to_le_bytes());
+        data.extend_from_slice(string.as_bytes());
+    }
+
+    fn expected(properties: &[(&'static str, &str)]) -> Vec<(&'static str, String)> {
+        properties.iter().map(|(key, value)| (*key, value.to_string())).collect()
+    }
+
+    #[test]
+    fn proposals_are_created_and_voted_on() {
+        let accounts: Vec<_> = (0..12).map(|_| Pubkey::new_unique()).collect();
+
+        // A multiple choice proposal (of the layout of the third version) of two options.
+        let mut create = vec![CREATE_PROPOSAL];
+        string(&mut create, "Fund the grants");
+        string(&mut create, "https://forum.example/t/42");
+        create.extend_from_slice(&[1, 0, 1, 2, 2, 2, 0, 0, 0]);
+        string(&mut create, "Yes");
+        string(&mut create, "Later");
+        create.push(1);
+        create.extend_from_slice(Pubkey::new_unique().as_ref());
+        let create = decode(&create, &[]).unwrap();
+        assert_eq!(create.function.function_name.as_str(), "create-proposal");
+        assert_eq!(properties(&create), expected(&[
+            ("name", "Fund the grants"),
+            ("description_link", "https://forum.example/t/42"),
+            ("vote_type", "multi-choice"),
+            ("options/0.label", "Yes"),
+            ("options/1.label", "Later"),
+            ("use_deny_option", "1"),
+        ]));
+
+        let approve = decode(&[CAST_VOTE, 0, 2, 0, 0, 0, 0, 100, 1, 0], &accounts).unwrap();
+        assert_eq!(approve.function.function_name.as_str(), "cast-vote");
+        assert_eq!(properties(&approve)[..5], expected(&[
+            ("vote", "approve"),
+            ("choices/0.rank", "0"),
+            ("choices/0.weight_percentage", "100"),
+            ("choices/1.rank", "1"),
+            ("choices/1.weight_percentage", "0"),
+        ])[..]);
+        assert_eq!(properties(&approve)[5..], [
+            ("realm", accounts[0].to_string()),
+            ("governance", accounts[1].to_string()),
+            ("proposal", accounts[2].to_string()),
+            ("token_owner_record", accounts[4].to_string()),
+            ("governing_token_mint", accounts[7].to_string()),
+        ]);
+        let veto = decode(&[CAST_VOTE, 3], &[]).unwrap();
+        assert_eq!(properties(&veto), expected(&[("vote", "veto")]));
+
+        let execute = decode(&[EXECUTE_TRANSACTION], &accounts).unwrap();
+        assert_eq!(properties(&execute), vec![
+            ("governance", accounts[0].to_string()),
+            ("proposal", accounts[1].to_string()),
+            ("proposal_transaction", accounts[2].to_string()),
+        ]);
+    }
+
+    #[test]
+    fn realms_and_their_members_decode() {
+        let accounts: Vec<_> = (0..12).map(|_| Pubkey::new_unique()).collect();
+
+        let mut create = vec![CREATE_REALM];
+        string(&mut create, "Grape");
+        create.push(1);
+        create.extend_from_slice(&1_000_000u64.to_le_bytes());
+        // The rest of the config, which isn't read.
+        create.extend_from_slice(&[0; 11]);
+        let create = decode(&create, &accounts).unwrap();
+        assert_eq!(properties(&create), vec![
+            ("name", "Grape".to_string()),
+            ("use_council_mint", "1".to_string()),
+            ("min_community_weight_to_create_governance", "1000000".to_string()),
+            ("realm", accounts[0].to_string()),
+            ("community_token_mint", accounts[2].to_string()),
+        ]);
+
+        let deposit = [&[DEPOSIT_GOVERNING_TOKENS][..], &250u64.to_le_bytes()].concat();
+        let deposit = decode(&deposit, &accounts).unwrap();
+        assert_eq!(properties(&deposit)[..2], [
+            ("amount", "250".to_string()),
+            ("realm", accounts[0].to_string()),
+        ]);
+        let undelegate = decode(&[SET_GOVERNANCE_DELEGATE, 0], &[]).unwrap();
+        assert_eq!(properties(&undelegate), expected(&[("new_governance_delegate", "")]));
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        assert!(decode(&[DEPOSIT_GOVERNING_TOKENS, 1, 0], &[]).is_none());
+        // An approval cut within its choices, and an unknown vote.
+        assert!(decode(&[CAST_VOTE, 0, 2, 0, 0, 0, 0, 100], &[]).is_none());
+        assert!(decode(&[CAST_VOTE, 4], &[]).is_none());
+        assert!(decode(&[200], &[]).is_none());
+        assert!(decode(&[], &[]).is_none());
+    }
+}
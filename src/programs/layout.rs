@@ -0,0 +1,179 @@
+//! Reading the borsh encoded arguments of the programs that don't publish a crate to unpack their
+//! instructions with, and building instruction sets out of them.
+
+use std::convert::TryInto;
+
+use smallvec::smallvec;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{Instruction, InstructionFunction, InstructionProperty, InstructionSet, PropertyType};
+
+/// Reads borsh encoded arguments, returning `None` past their end.
+pub(crate) struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader(data)
+    }
+
+    /// How many bytes are left to read.
+    pub fn remaining(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The next `len` bytes, e.g. of a fixed size array.
+    pub fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+
+        Some(taken)
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        let mut le_bytes = [0; 4];
+        le_bytes.copy_from_slice(self.bytes(4)?);
+        Some(u32::from_le_bytes(le_bytes))
+    }
+
+    pub fn u64(&mut self) -> Option<u64> {
+        let mut le_bytes = [0; 8];
+        le_bytes.copy_from_slice(self.bytes(8)?);
+        Some(u64::from_le_bytes(le_bytes))
+    }
+
+    pub fn i16(&mut self) -> Option<i16> {
+        self.u16().map(|value| value as i16)
+    }
+
+    pub fn i32(&mut self) -> Option<i32> {
+        self.u32().map(|value| value as i32)
+    }
+
+    pub fn i64(&mut self) -> Option<i64> {
+        self.u64().map(|value| value as i64)
+    }
+
+    pub fn u128(&mut self) -> Option<u128> {
+        let mut le_bytes = [0; 16];
+        le_bytes.copy_from_slice(self.bytes(16)?);
+        Some(u128::from_le_bytes(le_bytes))
+    }
+
+    pub fn pubkey(&mut self) -> Option<Pubkey> {
+        Some(Pubkey::new_from_array(self.bytes(32)?.try_into().ok()?))
+    }
+
+    pub fn bool(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    /// A `String`, which has to be UTF-8.
+    pub fn string(&mut self) -> Option<&'a str> {
+        let len = self.u32()? as usize;
+        std::str::from_utf8(self.bytes(len)?).ok()
+    }
+
+    /// The name of the variant of a fieldless enum, out of `names`.
+    pub fn name(&mut self, names: &[&'static str]) -> Option<&'static str> {
+        names.get(self.u8()? as usize).copied()
+    }
+
+    /// An `Option` of what `read` reads: `Some(None)` when absent, `None` when invalid.
+    pub fn option<T>(&mut self, read: fn(&mut Self) -> Option<T>) -> Option<Option<T>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => read(self).map(Some),
+            _ => None,
+        }
+    }
+
+    /// A `Vec` of what `read` reads, whose items take `item_len` bytes at least: its length is
+    /// checked against what's left before anything is allocated.
+    pub fn vec<T>(
+        &mut self,
+        item_len: usize,
+        read: fn(&mut Self) -> Option<T>,
+    ) -> Option<Vec<T>> {
+        let len = self.u32()? as usize;
+        if len.checked_mul(item_len)? > self.remaining() {
+            return None;
+        }
+
+        (0..len).map(|_| read(self)).collect()
+    }
+}
+
+/// `lamports` in SOL, without trailing zeros: 1500000000 is `1.5`.
+pub(crate) fn sol(lamports: u64) -> String {
+    let fraction = format!("{:09}", lamports % 1_000_000_000);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        (lamports / 1_000_000_000).to_string()
+    } else {
+        format!("{}.{}", lamports / 1_000_000_000, fraction)
+    }
+}
+
+/// An instruction set of `instruction` decoded to `function_name`, without properties yet.
+pub(crate) fn instruction_set(instruction: &Instruction, function_name: &str) -> InstructionSet {
+    InstructionSet {
+        function: InstructionFunction {
+            tx_instruction_id: instruction.tx_instruction_id,
+            transaction_hash: instruction.transaction_hash.clone(),
+            parent_index: instruction.parent_index,
+            program: instruction.program,
+            function_name: function_name.into(),
+            timestamp: instruction.timestamp,
+            network: instruction.network,
+            decoder_version: 0,
+        },
+        properties: smallvec![],
+        raw_data: None,
+    }
+}
+
+pub(crate) fn property(
+    instruction: &Instruction,
+    key: &str,
+    parent_key: &str,
+    value: String,
+    value_type: PropertyType,
+) -> InstructionProperty {
+    InstructionProperty {
+        tx_instruction_id: instruction.tx_instruction_id,
+        transaction_hash: instruction.transaction_hash.clone(),
+        parent_index: instruction.parent_index,
+        key: key.into(),
+        value,
+        value_type,
+        parent_key: parent_key.into(),
+        timestamp: instruction.timestamp,
+    }
+}
+
+/// A field that isn't nested, in the `(key, parent_key, value, value_type)` form of the
+/// processors reading nested ones.
+pub(crate) fn top_level(key: &'static str, value: String, value_type: PropertyType)
+    -> (&'static str, String, String, PropertyType) {
+    (key, String::new(), value, value_type)
+}
+
+/// A `bool` as the `0` or `1` it's stored as.
+pub(crate) fn flag(value: bool) -> String {
+    (value as u8).to_string()
+}
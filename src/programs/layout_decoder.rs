@@ -0,0 +1,723 @@
+//! Decodes the instructions of programs that publish neither a crate nor an IDL, out of layouts
+//! declared by users: for each discriminant, the borsh encoded fields following it.
+//!
+//! ```toml
+//! name = "name-service"
+//! program_ids = ["namesLPneVptA9Z5rqUDD9tMTWEJwofgaYwp8cawRkX"]
+//!
+//! [[instructions]]
+//! name = "create"
+//! discriminant = 0
+//! fields = [
+//!     { name = "hashed_name", type = "bytes" },
+//!     { name = "lamports", type = "u64" },
+//!     { name = "space", type = "u32" },
+//! ]
+//! ```
+//!
+//! Discriminants are either a `u8` tag or an array of bytes (e.g. the 8 bytes of Anchor
+//! instructions). Fields are of the types `u8`, `u16`, `u32`, `u64`, `u128`, `i8`, `i16`, `i32`,
+//! `i64`, `i128`, `bool`, `pubkey`, `string`, `bytes` (a `Vec<u8>`, emitted whole), `bytes[n]`, or
+//! `vec<T>` of any of them, or of the structs declared under `types` by name. Bytes are emitted
+//! hex encoded.
+//!
+//! Fields are emitted under their declared name. The fields of structs and the items of vecs are
+//! flattened: a field `schedules` of type `vec<schedule>` emits `schedules/0/amount`, whose parent
+//! key is `schedules/0` (see [`crate::naming`]).
+//!
+//! Descriptors are checked when the processor is built (see [`LayoutProcessor::new`]). The data
+//! of every instruction is checked against the fixed-size part of its layout before anything is
+//! read: an instruction too short for it is reported as such rather than as some truncated field.
+//! Bytes left past the declared fields are ignored, programs appending fields over time.
+//!
+//! A program whose layouts changed has a descriptor per version, each stating the slot its
+//! layouts are in use from (`activation_slot`), which a [`LayoutRegistry`] picks from by the slot
+//! of every instruction.
+//!
+//! [`LayoutRegistry`]: crate::LayoutRegistry
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, error};
+
+use crate::programs::layout::{self, Reader};
+use crate::schema::{ProgramSchema, INDEX_PLACEHOLDER};
+use crate::{Instruction, InstructionSet, ProgramProcessor, PropertyType};
+
+/// The layouts of the instructions of a program.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayoutDescriptor {
+    // The name the processor goes by in logs and schemas.
+    pub name: String,
+    pub program_ids: Vec<String>,
+    // To be bumped whenever the layouts change, for what they decoded before to be re-indexed.
+    #[serde(default = "default_version")]
+    pub version: u32,
+    // The first slot the layouts are in use at, e.g. that of the upgrade introducing them.
+    #[serde(default)]
+    pub activation_slot: u64,
+    pub instructions: Vec<InstructionLayout>,
+    // The structs fields may be of, by name.
+    #[serde(default)]
+    pub types: BTreeMap<String, Vec<FieldLayout>>,
+}
+
+fn default_version() -> u32 {
+    1
+}
+
+/// The layout of the instructions starting with `discriminant`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InstructionLayout {
+    // The function the instructions decode to.
+    pub name: String,
+    pub discriminant: Discriminant,
+    #[serde(default)]
+    pub fields: Vec<FieldLayout>,
+}
+
+/// What the data of an instruction starts with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Discriminant {
+    Tag(u8),
+    Bytes(Vec<u8>),
+}
+
+impl Discriminant {
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Discriminant::Tag(tag) => vec![*tag],
+            Discriminant::Bytes(bytes) => bytes.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldLayout {
+    pub name: String,
+    // As declared, e.g. `u64`, `bytes[32]` or `vec<schedule>`.
+    #[serde(rename = "type")]
+    pub field_type: String,
+}
+
+/// Errors in descriptors, and in the instructions decoded with them.
+#[derive(Debug, Error)]
+pub enum LayoutError {
+    #[error("{context}: unknown type `{name}`")]
+    UnknownType { context: String, name: String },
+    #[error("{context}: the type `{name}` contains itself")]
+    RecursiveType { context: String, name: String },
+    #[error("{context}: the type `{name}` has no fields")]
+    EmptyType { context: String, name: String },
+    #[error("{context}: the field `{name}` is declared twice")]
+    DuplicateField { context: String, name: String },
+    #[error("{context}: {message}")]
+    Invalid { context: String, message: String },
+    #[error("the discriminants of `{0}` and `{1}` are ambiguous")]
+    AmbiguousDiscriminants(String, String),
+    #[error("no instruction starts with {0}")]
+    UnknownDiscriminant(String),
+    #[error("`{function}` takes {needed} bytes of arguments at least, not {actual}")]
+    TooShort { function: String, needed: usize, actual: usize },
+    #[error("`{function}`: the field `{field}` is truncated or invalid")]
+    InvalidField { function: String, field: String },
+    #[error("Unable to read {}: {source}", .path.display())]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("{}: {message}", .path.display())]
+    Parse { path: PathBuf, message: String },
+    #[error("{}: {source}", .path.display())]
+    InFile { path: PathBuf, source: Box<LayoutError> },
+}
+
+#[derive(Clone, Debug)]
+enum FieldType {
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    Bool,
+    Pubkey,
+    String,
+    Bytes,
+    FixedBytes(usize),
+    Vec(Box<FieldType>),
+    Struct(Vec<Field>),
+}
+
+impl FieldType {
+    /// How many bytes a value takes at least: the length prefix of variable-length ones.
+    fn min_len(&self) -> usize {
+        match self {
+            FieldType::U8 | FieldType::I8 | FieldType::Bool => 1,
+            FieldType::U16 | FieldType::I16 => 2,
+            FieldType::U32 | FieldType::I32 => 4,
+            FieldType::U64 | FieldType::I64 => 8,
+            FieldType::U128 | FieldType::I128 => 16,
+            FieldType::Pubkey => 32,
+            FieldType::String | FieldType::Bytes | FieldType::Vec(_) => 4,
+            FieldType::FixedBytes(len) => *len,
+            FieldType::Struct(fields) => min_len(fields),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Field {
+    name: String,
+    declared: String,
+    field_type: FieldType,
+}
+
+fn min_len(fields: &[Field]) -> usize {
+    fields.iter().map(|field| field.field_type.min_len()).sum()
+}
+
+#[derive(Debug)]
+struct Function {
+    name: String,
+    discriminant: Vec<u8>,
+    fields: Vec<Field>,
+    // The fixed-size part of the arguments.
+    min_len: usize,
+}
+
+/// A processor decoding instructions out of a [`LayoutDescriptor`].
+#[derive(Debug)]
+pub struct LayoutProcessor {
+    name: String,
+    program_ids: Vec<String>,
+    version: u32,
+    activation_slot: u64,
+    functions: Vec<Function>,
+}
+
+/// A value decoded, before it's made into a property.
+#[derive(Debug, PartialEq)]
+struct Decoded {
+    key: String,
+    parent_key: String,
+    value: String,
+    value_type: PropertyType,
+}
+
+impl LayoutProcessor {
+    /// Checks `descriptor`: every type is known, structs don't contain themselves, fields are
+    /// declared once, and no discriminant is a prefix of another.
+    pub fn new(descriptor: LayoutDescriptor) -> Result<Self, LayoutError> {
+        let mut functions: Vec<Function> = Vec::new();
+        for instruction in &descriptor.instructions {
+            let context = format!("{}.{}", descriptor.name, instruction.name);
+            let discriminant = instruction.discriminant.bytes();
+            if discriminant.is_empty() {
+                return Err(LayoutError::Invalid {
+                    context,
+                    message: "the discriminant is empty".to_string(),
+                });
+            }
+            if let Some(other) = functions.iter().find(|function| {
+                function.discriminant.starts_with(&discriminant)
+                    || discriminant.starts_with(&function.discriminant)
+            }) {
+                return Err(LayoutError::AmbiguousDiscriminants(
+                    other.name.clone(), instruction.name.clone()));
+            }
+
+            let fields = resolve_fields(&instruction.fields, &descriptor.types, &context,
+                                        &mut Vec::new())?;
+            functions.push(Function {
+                name: instruction.name.clone(),
+                discriminant,
+                min_len: min_len(&fields),
+                fields,
+            });
+        }
+
+        Ok(LayoutProcessor {
+            name: descriptor.name,
+            program_ids: descriptor.program_ids,
+            version: descriptor.version,
+            activation_slot: descriptor.activation_slot,
+            functions,
+        })
+    }
+
+    /// The first slot the layouts are in use at.
+    pub fn activation_slot(&self) -> u64 {
+        self.activation_slot
+    }
+
+    /// The function `data` decodes to, with its fields as `(key, parent_key, value, type)`.
+    fn decode_data(&self, data: &[u8]) -> Result<(&str, Vec<Decoded>), LayoutError> {
+        let function = self.functions.iter()
+            .find(|function| data.starts_with(&function.discriminant))
+            .ok_or_else(|| {
+                LayoutError::UnknownDiscriminant(hex::encode(&data[..data.len().min(8)]))
+            })?;
+        let arguments = &data[function.discriminant.len()..];
+        if arguments.len() < function.min_len {
+            return Err(LayoutError::TooShort {
+                function: function.name.clone(),
+                needed: function.min_len,
+                actual: arguments.len(),
+            });
+        }
+
+        let mut reader = Reader::new(arguments);
+        let mut decoded = Vec::new();
+        for field in &function.fields {
+            read(&mut reader, &field.field_type, field.name.clone(), "", &mut decoded)
+                .ok_or_else(|| LayoutError::InvalidField {
+                    function: function.name.clone(),
+                    field: field.name.clone(),
+                })?;
+        }
+
+        Ok((&function.name, decoded))
+    }
+}
+
+impl ProgramProcessor for LayoutProcessor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn program_ids(&self) -> Vec<String> {
+        self.program_ids.clone()
+    }
+
+    fn decode(&self, instruction: Instruction) -> Option<InstructionSet> {
+        let (function_name, decoded) = match self.decode_data(&instruction.data) {
+            Ok(decoded) => decoded,
+            Err(err @ LayoutError::UnknownDiscriminant(_)) => {
+                debug!("[spi-wrapper/layout-decoder] {} of {}: {}.", self.name,
+                    instruction.transaction_hash, err);
+                return None;
+            }
+            Err(err) => {
+                error!("[spi-wrapper/layout-decoder] Attempt to parse instruction from program {} \
+                    with the {} layouts failed: {}.", instruction.program, self.name, err);
+                return None;
+            }
+        };
+
+        let mut instruction_set = layout::instruction_set(&instruction, function_name);
+        instruction_set.properties = decoded.into_iter()
+            .map(|decoded| layout::property(&instruction, &decoded.key, &decoded.parent_key,
+                                            decoded.value, decoded.value_type))
+            .collect();
+
+        Some(instruction_set)
+    }
+
+    fn decoder_version(&self) -> u32 {
+        self.version
+    }
+
+    fn describe(&self) -> ProgramSchema {
+        let mut schema = ProgramSchema::new(&self.name);
+        for function in &self.functions {
+            let mut keys = Vec::new();
+            for field in &function.fields {
+                describe(field, field.name.clone(), String::new(), &mut keys);
+            }
+            let keys: Vec<_> = keys.iter()
+                .map(|(key, parent_key, value_type, declared)| {
+                    (key.as_str(), parent_key.as_str(), *value_type, declared.as_str())
+                })
+                .collect();
+            schema = schema.function(&function.name, &keys);
+        }
+
+        schema
+    }
+}
+
+/// Resolves the types of `fields`, declared in `context`. `within` lists the structs being
+/// resolved, which can't be resolved again.
+fn resolve_fields(
+    fields: &[FieldLayout],
+    types: &BTreeMap<String, Vec<FieldLayout>>,
+    context: &str,
+    within: &mut Vec<String>,
+) -> Result<Vec<Field>, LayoutError> {
+    let mut names = HashSet::new();
+    let mut resolved = Vec::with_capacity(fields.len());
+    for field in fields {
+        if !names.insert(field.name.as_str()) {
+            return Err(LayoutError::DuplicateField {
+                context: context.to_string(),
+                name: field.name.clone(),
+            });
+        }
+        let context = format!("{}.{}", context, field.name);
+        resolved.push(Field {
+            name: field.name.clone(),
+            declared: field.field_type.clone(),
+            field_type: resolve_type(field.field_type.trim(), types, &context, within)?,
+        });
+    }
+
+    Ok(resolved)
+}
+
+fn resolve_type(
+    name: &str,
+    types: &BTreeMap<String, Vec<FieldLayout>>,
+    context: &str,
+    within: &mut Vec<String>,
+) -> Result<FieldType, LayoutError> {
+    let field_type = match name {
+        "u8" => FieldType::U8,
+        "u16" => FieldType::U16,
+        "u32" => FieldType::U32,
+        "u64" => FieldType::U64,
+        "u128" => FieldType::U128,
+        "i8" => FieldType::I8,
+        "i16" => FieldType::I16,
+        "i32" => FieldType::I32,
+        "i64" => FieldType::I64,
+        "i128" => FieldType::I128,
+        "bool" => FieldType::Bool,
+        "pubkey" => FieldType::Pubkey,
+        "string" => FieldType::String,
+        "bytes" => FieldType::Bytes,
+        _ => {
+            if let Some(len) = name.strip_prefix("bytes[").and_then(|len| len.strip_suffix(']')) {
+                let len = len.trim().parse().map_err(|_| LayoutError::Invalid {
+                    context: context.to_string(),
+                    message: format!("`{}` isn't a length", len),
+                })?;
+                return Ok(FieldType::FixedBytes(len));
+            }
+            if let Some(item) = name.strip_prefix("vec<").and_then(|item| item.strip_suffix('>')) {
+                let item = resolve_type(item.trim(), types, context, within)?;
+                return Ok(FieldType::Vec(Box::new(item)));
+            }
+
+            let fields = types.get(name).ok_or_else(|| LayoutError::UnknownType {
+                context: context.to_string(),
+                name: name.to_string(),
+            })?;
+            if within.iter().any(|resolving| resolving == name) {
+                return Err(LayoutError::RecursiveType {
+                    context: context.to_string(),
+                    name: name.to_string(),
+                });
+            }
+            // Vecs of them would be read forever out of nothing.
+            if fields.is_empty() {
+                return Err(LayoutError::EmptyType {
+                    context: context.to_string(),
+                    name: name.to_string(),
+                });
+            }
+            within.push(name.to_string());
+            let fields = resolve_fields(fields, types, context, within);
+            within.pop();
+            FieldType::Struct(fields?)
+        }
+    };
+
+    Ok(field_type)
+}
+
+/// Reads a value of `field_type` into `decoded`, under `key`, returning `None` when it's
+/// truncated or invalid.
+fn read(
+    reader: &mut Reader<'_>,
+    field_type: &FieldType,
+    key: String,
+    parent_key: &str,
+    decoded: &mut Vec<Decoded>,
+) -> Option<()> {
+    let (value, value_type) = match field_type {
+        FieldType::U8 => (reader.u8()?.to_string(), PropertyType::U64),
+        FieldType::U16 => (reader.u16()?.to_string(), PropertyType::U64),
+        FieldType::U32 => (reader.u32()?.to_string(), PropertyType::U64),
+        FieldType::U64 => (reader.u64()?.to_string(), PropertyType::U64),
+        FieldType::U128 => (reader.u128()?.to_string(), PropertyType::Decimal),
+        FieldType::I8 => ((reader.u8()? as i8).to_string(), PropertyType::I128),
+        FieldType::I16 => (reader.i16()?.to_string(), PropertyType::I128),
+        FieldType::I32 => ((reader.u32()? as i32).to_string(), PropertyType::I128),
+        FieldType::I64 => (reader.i64()?.to_string(), PropertyType::I128),
+        FieldType::I128 => ((reader.u128()? as i128).to_string(), PropertyType::I128),
+        FieldType::Bool => ((reader.bool()? as u8).to_string(), PropertyType::Bool),
+        FieldType::Pubkey => {
+            (bs58::encode(reader.bytes(32)?).into_string(), PropertyType::Pubkey)
+        }
+        FieldType::String => {
+            let len = reader.u32()? as usize;
+            let string = std::str::from_utf8(reader.bytes(len)?).ok()?;
+            (string.to_string(), PropertyType::Text)
+        }
+        FieldType::Bytes => {
+            let len = reader.u32()? as usize;
+            (hex::encode(reader.bytes(len)?), PropertyType::Bytes)
+        }
+        FieldType::FixedBytes(len) => (hex::encode(reader.bytes(*len)?), PropertyType::Bytes),
+        FieldType::Vec(item) => {
+            // Checked against what's left before reading anything; items take a byte at least.
+            let len = reader.u32()? as usize;
+            if len.checked_mul(item.min_len().max(1))? > reader.remaining() {
+                return None;
+            }
+            for index in 0..len {
+                read(reader, item, format!("{}/{}", key, index), &key, decoded)?;
+            }
+            return Some(());
+        }
+        FieldType::Struct(fields) => {
+            for field in fields {
+                read(reader, &field.field_type, format!("{}/{}", key, field.name), &key,
+                     decoded)?;
+            }
+            return Some(());
+        }
+    };
+
+    decoded.push(Decoded { key, parent_key: parent_key.to_string(), value, value_type });
+    Some(())
+}
+
+/// Adds the keys `field` emits under `key` to `keys`, as `(key, parent_key, type, declared)`.
+fn describe(
+    field: &Field,
+    key: String,
+    parent_key: String,
+    keys: &mut Vec<(String, String, PropertyType, String)>,
+) {
+    describe_type(&field.field_type, &field.declared, key, parent_key, keys);
+}
+
+fn describe_type(
+    field_type: &FieldType,
+    declared: &str,
+    key: String,
+    parent_key: String,
+    keys: &mut Vec<(String, String, PropertyType, String)>,
+) {
+    let value_type = match field_type {
+        FieldType::U8 | FieldType::U16 | FieldType::U32 | FieldType::U64 => PropertyType::U64,
+        FieldType::U128 => PropertyType::Decimal,
+        FieldType::I8 | FieldType::I16 | FieldType::I32 | FieldType::I64 | FieldType::I128 => {
+            PropertyType::I128
+        }
+        FieldType::Bool => PropertyType::Bool,
+        FieldType::Pubkey => PropertyType::Pubkey,
+        FieldType::String => PropertyType::Text,
+        FieldType::Bytes | FieldType::FixedBytes(_) => PropertyType::Bytes,
+        FieldType::Vec(item) => {
+            let item_key = format!("{}/{}", key, INDEX_PLACEHOLDER);
+            describe_type(item, declared, item_key, key, keys);
+            return;
+        }
+        FieldType::Struct(fields) => {
+            for field in fields {
+                describe(field, format!("{}/{}", key, field.name), key.clone(), keys);
+            }
+            return;
+        }
+    };
+
+    keys.push((key, parent_key, value_type, declared.to_string()));
+}
+
+#[cfg(test)]
+mod tests {
+    use borsh::BorshSerialize;
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    const VESTING_PROGRAM: &str = "Vest111111111111111111111111111111111111111";
+
+    #[derive(BorshSerialize)]
+    struct Schedule {
+        release_time: i64,
+        amount: u64,
+    }
+
+    #[derive(BorshSerialize)]
+    struct Create {
+        seeds: [u8; 4],
+        destination: [u8; 32],
+        schedules: Vec<Schedule>,
+        memo: String,
+    }
+
+    const VESTING: &str = r#"{
+        "name": "vesting",
+        "program_ids": ["Vest111111111111111111111111111111111111111"],
+        "instructions": [
+            {
+                "name": "create",
+                "discriminant": 1,
+                "fields": [
+                    { "name": "seeds", "type": "bytes[4]" },
+                    { "name": "destination", "type": "pubkey" },
+                    { "name": "schedules", "type": "vec<schedule>" },
+                    { "name": "memo", "type": "string" }
+                ]
+            },
+            { "name": "unlock", "discriminant": [2, 0], "fields": [] }
+        ],
+        "types": {
+            "schedule": [
+                { "name": "release_time", "type": "i64" },
+                { "name": "amount", "type": "u64" }
+            ]
+        }
+    }"#;
+
+    fn vesting() -> LayoutProcessor {
+        LayoutProcessor::new(serde_json::from_str(VESTING).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn borsh_encoded_instructions_round_trip() {
+        let destination = Pubkey::new_unique();
+        let create = Create {
+            seeds: [1, 2, 3, 4],
+            destination: destination.to_bytes(),
+            schedules: vec![
+                Schedule { release_time: -5, amount: 100 },
+                Schedule { release_time: 1_700_000_000, amount: 250 },
+            ],
+            memo: "cliff".to_string(),
+        };
+        let mut data = vec![1];
+        data.extend(create.try_to_vec().unwrap());
+
+        let decoded = vesting().decode(instruction(VESTING_PROGRAM, data)).unwrap();
+        assert_eq!(decoded.function.function_name.as_str(), "create");
+        assert_eq!(properties(&decoded), vec![
+            ("seeds", "01020304".to_string()),
+            ("destination", destination.to_string()),
+            ("schedules/0/release_time", "-5".to_string()),
+            ("schedules/0/amount", "100".to_string()),
+            ("schedules/1/release_time", "1700000000".to_string()),
+            ("schedules/1/amount", "250".to_string()),
+            ("memo", "cliff".to_string()),
+        ]);
+        assert_eq!(decoded.properties[3].parent_key.as_str(), "schedules/0");
+        let unlock = vesting().decode(instruction(VESTING_PROGRAM, vec![2, 0])).unwrap();
+        assert_eq!(unlock.function.function_name.as_str(), "unlock");
+        assert!(vesting().decode(instruction(VESTING_PROGRAM, vec![2, 1])).is_none());
+    }
+
+    #[test]
+    fn instructions_shorter_than_their_layout_are_reported() {
+        // The tag, the seeds and half of the destination.
+        match vesting().decode_data(&[1; 21]) {
+            Err(LayoutError::TooShort { function, needed, actual }) => {
+                assert_eq!((function.as_str(), needed, actual), ("create", 44, 20));
+            }
+            other => panic!("unexpected {:?}", other.map(|(name, _)| name)),
+        }
+
+        // Long enough for the fixed-size part, but a vec longer than what's left.
+        let mut data = vec![1];
+        data.extend(&[0; 36]);
+        data.extend(&1000u32.to_le_bytes());
+        data.extend(&0u32.to_le_bytes());
+        assert!(matches!(vesting().decode_data(&data),
+                         Err(LayoutError::InvalidField { field, .. }) if field == "schedules"));
+    }
+
+    #[test]
+    fn invalid_descriptors_are_rejected() {
+        let descriptor = |instructions: &str, types: &str| -> Result<LayoutProcessor, LayoutError> {
+            LayoutProcessor::new(serde_json::from_str(&format!(
+                r#"{{ "name": "test", "program_ids": [], "instructions": {}, "types": {} }}"#,
+                instructions, types)).unwrap())
+        };
+
+        assert!(matches!(descriptor(
+            r#"[{ "name": "a", "discriminant": 0, "fields": [{ "name": "x", "type": "u256" }] }]"#,
+            "{}"), Err(LayoutError::UnknownType { .. })));
+        assert!(matches!(descriptor(
+            r#"[{ "name": "a", "discriminant": 0, "fields": [{ "name": "x", "type": "node" }] }]"#,
+            r#"{ "node": [{ "name": "children", "type": "vec<node>" }] }"#),
+            Err(LayoutError::RecursiveType { .. })));
+        assert!(matches!(descriptor(
+            r#"[{ "name": "a", "discriminant": 0 }, { "name": "b", "discriminant": [0, 1] }]"#,
+            "{}"), Err(LayoutError::AmbiguousDiscriminants(..))));
+        assert!(matches!(descriptor(
+            r#"[{ "name": "a", "discriminant": 0, "fields": [
+                { "name": "x", "type": "u8" }, { "name": "x", "type": "u16" }] }]"#,
+            "{}"), Err(LayoutError::DuplicateField { .. })));
+    }
+
+    #[test]
+    fn nested_keys_are_described_with_index_placeholders() {
+        let schema = vesting().describe();
+        let create = schema.get("create").unwrap();
+        let keys: Vec<_> = create.keys.iter()
+            .map(|key| (key.key.as_str(), key.parent_key.as_str(), key.value_type))
+            .collect();
+        assert_eq!(keys, vec![
+            ("seeds", "", PropertyType::Bytes),
+            ("destination", "", PropertyType::Pubkey),
+            ("schedules/{index}/release_time", "schedules/{index}", PropertyType::I128),
+            ("schedules/{index}/amount", "schedules/{index}", PropertyType::U64),
+            ("memo", "", PropertyType::Text),
+        ]);
+    }
+
+    #[cfg(feature = "runtime")]
+    #[test]
+    fn the_name_service_example_decodes_its_instructions() {
+        #[derive(BorshSerialize)]
+        enum NameRegistryInstruction {
+            Create { hashed_name: Vec<u8>, lamports: u64, space: u32 },
+            Update { offset: u32, data: Vec<u8> },
+            Transfer { new_owner: [u8; 32] },
+            Delete,
+        }
+
+        let descriptor: LayoutDescriptor = toml::from_str(include_str!(
+            "../../examples/layouts/name_service.toml")).unwrap();
+        let processor = LayoutProcessor::new(descriptor).unwrap();
+        let decode = |instruction: NameRegistryInstruction| {
+            processor.decode(self::instruction(VESTING_PROGRAM, instruction.try_to_vec().unwrap()))
+                .unwrap()
+        };
+
+        let create = decode(NameRegistryInstruction::Create {
+            hashed_name: vec![0xab; 32],
+            lamports: 2_039_280,
+            space: 96,
+        });
+        assert_eq!(create.function.function_name.as_str(), "create");
+        assert_eq!(properties(&create), vec![
+            ("hashed_name", "ab".repeat(32)),
+            ("lamports", "2039280".to_string()),
+            ("space", "96".to_string()),
+        ]);
+        let update = decode(NameRegistryInstruction::Update { offset: 8, data: vec![1, 2] });
+        assert_eq!(properties(&update), vec![
+            ("offset", "8".to_string()),
+            ("data", "0102".to_string()),
+        ]);
+        let new_owner = Pubkey::new_unique();
+        let transfer = decode(NameRegistryInstruction::Transfer {
+            new_owner: new_owner.to_bytes(),
+        });
+        assert_eq!(properties(&transfer), vec![("new_owner", new_owner.to_string())]);
+        assert!(decode(NameRegistryInstruction::Delete).properties.is_empty());
+    }
+}
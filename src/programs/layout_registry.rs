@@ -0,0 +1,400 @@
+//! Every version of the layout descriptors of programs, loaded from files and directories and
+//! reloaded as they change.
+//!
+//! Programs get upgraded and their layouts change: decoding all of their history with the latest
+//! layouts mis-decodes what came before. Every descriptor states the slot its layouts are in use
+//! from (`activation_slot`, 0 by default), and an instruction is decoded with the descriptor of its
+//! program activated last at or before its slot, the latest one when its slot isn't known.
+//! Instructions from before the first activation of their program aren't decoded.
+//!
+//! Descriptors are read from files (TOML, or JSON when named `*.json`) and directories (each of
+//! their `*.toml` and `*.json` files). [`LayoutRegistry::reload`] reads them all again, e.g. once
+//! a new descriptor was dropped into a directory, and [`LayoutRegistry::watch`] does so whenever
+//! they change. Reloads are all or nothing: when a descriptor can't be read or is invalid, the
+//! previous descriptors are all kept.
+//!
+//! Processors are registered for the programs the registry knows when they're registered (see
+//! [`LayoutRegistry::processors`]), and new versions of their layouts are picked up as they're
+//! reloaded. The programs whose descriptors appear later are decoded without a restart too, by
+//! the processor registry the layouts are set on (see
+//! [`ProcessorRegistry::set_layouts`](crate::ProcessorRegistry::set_layouts)).
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::programs::layout_decoder::{LayoutDescriptor, LayoutError, LayoutProcessor};
+use crate::schema::ProgramSchema;
+use crate::{Instruction, InstructionSet, ProgramProcessor};
+
+// The versions of the layouts of every program, by program id then activation slot.
+type Versions = HashMap<String, BTreeMap<u64, Arc<LayoutProcessor>>>;
+
+/// The layout descriptors given up front and those of the files and directories given, by
+/// program and activation slot.
+#[derive(Debug, Default)]
+pub struct LayoutRegistry {
+    descriptors: Vec<LayoutDescriptor>,
+    sources: Vec<PathBuf>,
+    // The versions last loaded.
+    versions: RwLock<Arc<Versions>>,
+}
+
+impl LayoutRegistry {
+    /// The registry of `descriptors` and of the descriptors read from `sources`, files or
+    /// directories, which are read right away.
+    pub fn new(
+        descriptors: Vec<LayoutDescriptor>,
+        sources: Vec<PathBuf>,
+    ) -> Result<Self, LayoutError> {
+        let registry = LayoutRegistry { descriptors, sources, ..LayoutRegistry::default() };
+        registry.reload()?;
+
+        Ok(registry)
+    }
+
+    /// Reads the sources again, returning how many descriptors are in use. The previous ones are
+    /// kept when any can't be read or is invalid.
+    pub fn reload(&self) -> Result<usize, LayoutError> {
+        let reloaded = self.load();
+        #[cfg(feature = "metrics")]
+        {
+            let result = if reloaded.is_ok() { "reloaded" } else { "rejected" };
+            crate::metrics::metrics().layout_reloads.with_label_values(&[result]).inc();
+        }
+
+        let (versions, count) = reloaded?;
+        *self.versions.write().unwrap() = Arc::new(versions);
+
+        Ok(count)
+    }
+
+    fn load(&self) -> Result<(Versions, usize), LayoutError> {
+        let mut versions = Versions::new();
+        let mut count = 0;
+        for descriptor in &self.descriptors {
+            insert(&mut versions, descriptor.clone())?;
+            count += 1;
+        }
+        for path in descriptor_files(&self.sources)? {
+            let descriptor = read_descriptor(&path)?;
+            insert(&mut versions, descriptor)
+                .map_err(|source| LayoutError::InFile { path, source: Box::new(source) })?;
+            count += 1;
+        }
+
+        Ok((versions, count))
+    }
+
+    /// Reloads the sources whenever a descriptor is added, modified or removed, checking every
+    /// `interval`, until the returned task is aborted.
+    #[cfg(feature = "runtime")]
+    pub fn watch(self: &Arc<Self>, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        use tracing::{info, warn};
+
+        let registry = self.clone();
+        tokio::spawn(async move {
+            if registry.sources.is_empty() {
+                return;
+            }
+            let mut modified = registry.modified_at();
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                let now_modified = registry.modified_at();
+                if now_modified == modified {
+                    continue;
+                }
+                modified = now_modified;
+
+                match registry.reload() {
+                    Ok(count) => info!("[spi-wrapper/layout-decoder] Reloaded {} layout \
+                        descriptors.", count),
+                    Err(err) => warn!("[spi-wrapper/layout-decoder] Keeping the previous layout \
+                        descriptors: {}", err),
+                }
+            }
+        })
+    }
+
+    /// When every descriptor file was last modified, to tell when one changed, appeared or went.
+    #[cfg(feature = "runtime")]
+    fn modified_at(&self) -> Vec<(PathBuf, Option<std::time::SystemTime>)> {
+        descriptor_files(&self.sources).unwrap_or_default().into_iter()
+            .map(|path| {
+                let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+                (path, modified)
+            })
+            .collect()
+    }
+
+    /// The programs there are layouts of.
+    pub fn program_ids(&self) -> Vec<String> {
+        let mut program_ids: Vec<_> = self.versions.read().unwrap().keys().cloned().collect();
+        program_ids.sort();
+
+        program_ids
+    }
+
+    /// The layouts of `program_id` in use at `slot`: those activated last at or before it, or
+    /// the latest when `slot` isn't known.
+    pub fn processor(&self, program_id: &str, slot: Option<u64>) -> Option<Arc<LayoutProcessor>> {
+        let versions = self.versions.read().unwrap().clone();
+        let program = versions.get(program_id)?;
+        let version = match slot {
+            Some(slot) => program.range(..=slot).next_back(),
+            None => program.iter().next_back(),
+        };
+
+        version.map(|(_, processor)| processor.clone())
+    }
+
+    /// A processor for each program there are layouts of, by program id, decoding with the
+    /// layouts of the registry as they're reloaded.
+    pub fn processors(self: &Arc<Self>) -> Vec<(String, Arc<dyn ProgramProcessor>)> {
+        self.program_ids().into_iter()
+            .filter_map(|program_id| Some((program_id.clone(), self.versioned(&program_id)?)))
+            .collect()
+    }
+
+    /// A processor of `program_id`, decoding with the layouts of the registry as they're
+    /// reloaded, if there are layouts of it.
+    pub fn versioned(self: &Arc<Self>, program_id: &str) -> Option<Arc<dyn ProgramProcessor>> {
+        let name = self.processor(program_id, None)?.name().to_string();
+
+        Some(Arc::new(VersionedLayouts {
+            program_id: program_id.to_string(),
+            name,
+            registry: self.clone(),
+        }))
+    }
+}
+
+/// Adds the version of the layouts of `descriptor` for each of its programs.
+fn insert(versions: &mut Versions, descriptor: LayoutDescriptor) -> Result<(), LayoutError> {
+    let invalid = |message: String| LayoutError::Invalid {
+        context: descriptor.name.clone(),
+        message,
+    };
+    for program_id in &descriptor.program_ids {
+        if Pubkey::from_str(program_id).is_err() {
+            return Err(invalid(format!("`{}` is not a valid address", program_id)));
+        }
+        let activated = versions.get(program_id)
+            .and_then(|program| program.get(&descriptor.activation_slot));
+        if let Some(other) = activated {
+            return Err(invalid(format!("the layouts of {} activated at slot {} are those of {} \
+                already", program_id, descriptor.activation_slot, other.name())));
+        }
+    }
+
+    let program_ids = descriptor.program_ids.clone();
+    let processor = Arc::new(LayoutProcessor::new(descriptor)?);
+    for program_id in program_ids {
+        versions.entry(program_id).or_default()
+            .insert(processor.activation_slot(), processor.clone());
+    }
+
+    Ok(())
+}
+
+/// The descriptor files of `sources`: those given, and those in the directories given, sorted.
+fn descriptor_files(sources: &[PathBuf]) -> Result<Vec<PathBuf>, LayoutError> {
+    let mut files = Vec::new();
+    for source in sources {
+        if !source.is_dir() {
+            files.push(source.clone());
+            continue;
+        }
+
+        let entries = fs::read_dir(source)
+            .map_err(|err| LayoutError::Io { path: source.clone(), source: err })?;
+        let mut directory = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|err| LayoutError::Io { path: source.clone(), source: err })?
+                .path();
+            let is_descriptor = path.extension()
+                .map_or(false, |extension| extension == "toml" || extension == "json");
+            if is_descriptor && path.is_file() {
+                directory.push(path);
+            }
+        }
+        directory.sort();
+        files.extend(directory);
+    }
+
+    Ok(files)
+}
+
+fn read_descriptor(path: &Path) -> Result<LayoutDescriptor, LayoutError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|source| LayoutError::Io { path: path.to_path_buf(), source })?;
+    let parse = |message: String| LayoutError::Parse { path: path.to_path_buf(), message };
+    if path.extension().map_or(false, |extension| extension == "json") {
+        serde_json::from_str(&contents).map_err(|err| parse(err.to_string()))
+    } else {
+        toml::from_str(&contents).map_err(|err| parse(err.to_string()))
+    }
+}
+
+/// The layouts of a program, picked among those of a [`LayoutRegistry`] by slot.
+struct VersionedLayouts {
+    program_id: String,
+    // The name of the processor of the latest layouts when it was registered.
+    name: String,
+    registry: Arc<LayoutRegistry>,
+}
+
+impl ProgramProcessor for VersionedLayouts {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn program_ids(&self) -> Vec<String> {
+        vec![self.program_id.clone()]
+    }
+
+    fn decode(&self, instruction: Instruction) -> Option<InstructionSet> {
+        self.decode_at(instruction, None)
+    }
+
+    fn decode_at(&self, instruction: Instruction, slot: Option<u64>) -> Option<InstructionSet> {
+        self.registry.processor(&self.program_id, slot)?.decode(instruction)
+    }
+
+    /// The version of the latest layouts.
+    fn decoder_version(&self) -> u32 {
+        self.registry.processor(&self.program_id, None)
+            .map_or(0, |processor| processor.decoder_version())
+    }
+
+    fn describe(&self) -> ProgramSchema {
+        self.registry.processor(&self.program_id, None)
+            .map_or_else(|| ProgramSchema::new(&self.name), |processor| processor.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+    use crate::testing::instruction;
+    use crate::ProcessorRegistry;
+
+    const PROGRAM: &str = "Vest111111111111111111111111111111111111111";
+
+    /// The layouts of the vesting program from `activation_slot`, its instruction 0 being
+    /// `function_name` of an `amount` of the given type.
+    fn descriptor(activation_slot: u64, function_name: &str, amount_type: &str) -> String {
+        format!(r#"{{
+            "name": "vesting",
+            "program_ids": ["{}"],
+            "activation_slot": {},
+            "instructions": [{{
+                "name": "{}",
+                "discriminant": 0,
+                "fields": [{{ "name": "amount", "type": "{}" }}]
+            }}]
+        }}"#, PROGRAM, activation_slot, function_name, amount_type)
+    }
+
+    fn decode(registry: &ProcessorRegistry, program: &str, slot: u64) -> Vec<(String, String)> {
+        let mut data = vec![0];
+        data.extend_from_slice(&5_000_000_007u64.to_le_bytes());
+        let mut decoded = Vec::new();
+        registry.decode_with_accounts(instruction(program, data), &[Pubkey::default()], Some(slot),
+                                      &mut decoded);
+
+        decoded.iter()
+            .flat_map(|instruction_set| instruction_set.properties.iter().map(move |property| {
+                (instruction_set.function.function_name.to_string(), property.value.clone())
+            }))
+            .collect()
+    }
+
+    #[test]
+    fn instructions_are_decoded_with_the_layouts_of_their_slot() {
+        let versions = [(100, "create", "u32"), (5_000, "create-v2", "u64")].iter()
+            .map(|(slot, name, amount)| serde_json::from_str(&descriptor(*slot, name, amount)))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let layouts = Arc::new(LayoutRegistry::new(versions, Vec::new()).unwrap());
+        let mut registry = ProcessorRegistry::new();
+        for (program_id, processor) in layouts.processors() {
+            registry.register_processor(&program_id, processor);
+        }
+
+        // The same bytes: the low half of the amount, then the whole of it.
+        assert!(decode(&registry, PROGRAM, 99).is_empty());
+        assert_eq!(decode(&registry, PROGRAM, 100),
+                   vec![("create".to_string(), "705032711".to_string())]);
+        assert_eq!(decode(&registry, PROGRAM, 4_999)[0].0, "create");
+        assert_eq!(decode(&registry, PROGRAM, 5_000),
+                   vec![("create-v2".to_string(), "5000000007".to_string())]);
+        assert_eq!(registry.schema()[PROGRAM].functions[0].name, "create-v2");
+
+        let versions = vec![
+            serde_json::from_str(&descriptor(100, "create", "u64")).unwrap(),
+            serde_json::from_str(&descriptor(100, "mint", "u8")).unwrap(),
+        ];
+        assert!(matches!(LayoutRegistry::new(versions, Vec::new()),
+                         Err(LayoutError::Invalid { .. })));
+    }
+
+    #[test]
+    fn descriptors_dropped_into_a_directory_are_picked_up() {
+        let directory = std::env::temp_dir()
+            .join(format!("spi-wrapper-layouts-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("vesting-v1.json"), descriptor(0, "create", "u32")).unwrap();
+        let layouts = Arc::new(LayoutRegistry::new(Vec::new(), vec![directory.clone()]).unwrap());
+        let mut registry = ProcessorRegistry::new();
+        for (program_id, processor) in layouts.processors() {
+            registry.register_processor(&program_id, processor);
+        }
+        assert_eq!(decode(&registry, PROGRAM, 9_000)[0].0, "create");
+
+        fs::write(directory.join("vesting-v2.json"), descriptor(8_000, "create-v2", "u64"))
+            .unwrap();
+        assert_eq!(layouts.reload().unwrap(), 2);
+        assert_eq!(decode(&registry, PROGRAM, 7_999)[0].0, "create");
+        assert_eq!(decode(&registry, PROGRAM, 9_000)[0].0, "create-v2");
+
+        // Invalid JSON is rejected, the previous layouts kept.
+        fs::write(directory.join("vesting-v3.json"), "{ \"name\": ").unwrap();
+        assert!(matches!(layouts.reload(), Err(LayoutError::Parse { .. })));
+        assert_eq!(decode(&registry, PROGRAM, 9_000)[0].0, "create-v2");
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn descriptors_of_new_programs_are_picked_up() {
+        let directory = std::env::temp_dir()
+            .join(format!("spi-wrapper-new-layouts-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("vesting.json"), descriptor(0, "create", "u32")).unwrap();
+        let layouts = Arc::new(LayoutRegistry::new(Vec::new(), vec![directory.clone()]).unwrap());
+        let mut registry = ProcessorRegistry::new();
+        registry.set_layouts(layouts.clone());
+        let locker = Pubkey::new_unique().to_string();
+        assert!(decode(&registry, &locker, 9_000).is_empty());
+
+        // A program no processor was registered for when the layouts were set.
+        let locker_descriptor = descriptor(0, "lock", "u64")
+            .replace("vesting", "locker")
+            .replace(PROGRAM, &locker);
+        fs::write(directory.join("locker.json"), locker_descriptor).unwrap();
+        assert_eq!(layouts.reload().unwrap(), 2);
+        assert_eq!(decode(&registry, &locker, 9_000),
+                   vec![("lock".to_string(), "5000000007".to_string())]);
+        assert!(registry.registered_symbol(&locker).is_some());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
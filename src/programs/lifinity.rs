@@ -0,0 +1,129 @@
+//! The Lifinity AMMs (v1 and v2), Anchor programs: every instruction starts with the first 8
+//! bytes of `sha256("global:<instruction name>")`, followed by its borsh encoded arguments. Only
+//! swaps are decoded, the pools being managed by Lifinity itself.
+
+use std::convert::TryFrom;
+
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS_V1: &str = "EewxydAPCCVuNEyrVN68PuSYdQ7wKn27V9Gjeoi8dy3S";
+pub const PROGRAM_ADDRESS_V2: &str = "2wT8Yq49kHgDzXuPxZSaeLaH1qbmGXtEyPy64bL7aD3c";
+
+const SWAP: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+
+// Where the pool accounts are in the accounts of a swap, after the authority of the pool.
+const AMM_ACCOUNT: usize = 1;
+const POOL_MINT_ACCOUNT: usize = 7;
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    ProgramSchema::new("lifinity")
+        .function("swap", &[
+            ("amount_in", "", PropertyType::U64, "Amount of source tokens swapped"),
+            ("minimum_amount_out", "", PropertyType::U64,
+             "Minimum amount of destination tokens received"),
+            ("amm", "", PropertyType::Pubkey,
+             "Pool swapped with, when the accounts of the instruction are known"),
+            ("pool_mint", "", PropertyType::Pubkey,
+             "Mint of the pool tokens, when the accounts of the instruction are known"),
+        ])
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    if instruction.data.len() < 8 {
+        error!("[spi-wrapper/lifinity] Attempt to parse instruction from program {} failed as \
+            it is shorter than a discriminator.", instruction.program);
+
+        return None;
+    }
+    let (discriminator, arguments) = instruction.data.split_at(8);
+    if <[u8; 8]>::try_from(discriminator).ok()? != SWAP {
+        debug!("[spi-wrapper/lifinity] Instruction {} of {} isn't one the processor decodes.",
+            hex::encode(discriminator), instruction.transaction_hash);
+
+        return None;
+    }
+
+    let mut arguments = Reader::new(arguments);
+    let (amount_in, minimum_amount_out) = match (arguments.u64(), arguments.u64()) {
+        (Some(amount_in), Some(minimum_amount_out)) => (amount_in, minimum_amount_out),
+        _ => {
+            error!("[spi-wrapper/lifinity] Attempt to parse instruction from program {} failed \
+                due to truncated arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, "swap");
+    instruction_set.properties.push(layout::property(
+        &instruction, "amount_in", "", amount_in.to_string(), PropertyType::U64));
+    instruction_set.properties.push(layout::property(
+        &instruction, "minimum_amount_out", "", minimum_amount_out.to_string(),
+        PropertyType::U64));
+    for (key, index) in &[("amm", AMM_ACCOUNT), ("pool_mint", POOL_MINT_ACCOUNT)] {
+        if let Some(account) = accounts.get(*index) {
+            instruction_set.properties.push(layout::property(
+                &instruction, key, "", account.to_string(), PropertyType::Pubkey));
+        }
+    }
+
+    Some(instruction_set)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use solana_sdk::hash::hash;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    // Swapping 1 SOL for 20 USDC at least, discriminator included.
+    const SWAP_1_SOL: &str = "f8c69e91e17587c800ca9a3b00000000002d310100000000";
+
+    fn decode(data: &str, accounts: &[Pubkey]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS_V2, hex::decode(data).unwrap()), accounts)
+    }
+
+    #[test]
+    fn discriminator_is_the_anchor_one() {
+        let expected: [u8; 8] = hash(b"global:swap").to_bytes()[..8].try_into().unwrap();
+        assert_eq!(SWAP, expected);
+    }
+
+    #[test]
+    fn swaps_decode_with_their_pool() {
+        let accounts: Vec<_> = (0..13).map(|_| Pubkey::new_unique()).collect();
+
+        let swap = decode(SWAP_1_SOL, &accounts).unwrap();
+        assert_eq!(swap.function.function_name.as_str(), "swap");
+        assert_eq!(properties(&swap), vec![
+            ("amount_in", "1000000000".to_string()),
+            ("minimum_amount_out", "20000000".to_string()),
+            ("amm", accounts[1].to_string()),
+            ("pool_mint", accounts[7].to_string()),
+        ]);
+
+        // Decoded outside of its transaction.
+        assert_eq!(decode(SWAP_1_SOL, &[]).unwrap().properties.len(), 2);
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        assert!(decode(&SWAP_1_SOL[..2 * 12], &[]).is_none());
+        assert!(decode("0001020304050607", &[]).is_none());
+        assert!(decode("f8c69e", &[]).is_none());
+    }
+}
@@ -0,0 +1,270 @@
+//! The Magic Eden NFT marketplace (M2), an Anchor program: every instruction starts with the
+//! first 8 bytes of `sha256("global:<instruction name>")`, followed by its borsh encoded
+//! arguments.
+//!
+//! As for Tensor, every trade decodes to `sale` (the instruction it was executed with being its
+//! `instruction` property) and listings to `list` and `delist`; bids, which sellers can sell
+//! into, decode to `bid`.
+
+use std::convert::TryFrom;
+
+use smallvec::{smallvec, SmallVec};
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS: &str = "M2mx93ekt1fmXSVkTrUL9xVFHkmME8HTUi5Cyc5aF7K";
+
+const SELL: [u8; 8] = [51, 230, 133, 164, 1, 127, 131, 173];
+const CANCEL_SELL: [u8; 8] = [198, 198, 130, 203, 163, 95, 175, 75];
+const BUY_V2: [u8; 8] = [184, 23, 238, 97, 103, 197, 211, 61];
+const EXECUTE_SALE: [u8; 8] = [37, 74, 217, 157, 79, 49, 35, 6];
+const EXECUTE_SALE_V2: [u8; 8] = [91, 220, 49, 223, 204, 129, 53, 193];
+
+// Where the mint of the NFT is in the accounts of the instructions.
+const LISTING_MINT_ACCOUNT: usize = 4;
+const BID_MINT_ACCOUNT: usize = 2;
+const SALE_MINT_ACCOUNT: usize = 7;
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let price = [
+        ("price", "", PropertyType::U64, "Price, in lamports"),
+        ("price_sol", "", PropertyType::Decimal, "Price, in SOL"),
+        ("token_size", "", PropertyType::U64, "Amount of tokens traded, 1 for NFTs"),
+    ];
+    let mint = ("mint", "", PropertyType::Pubkey,
+                "Mint of the NFT, when the accounts of the instruction are known");
+
+    ProgramSchema::new("magic-eden")
+        .function("sale", &[
+            ("instruction", "", PropertyType::Text,
+             "Instruction the sale was executed with (execute-sale or execute-sale-v2)"),
+            price[0],
+            price[1],
+            price[2],
+            ("maker_fee_basis_points", "", PropertyType::I128,
+             "Fee of the maker, in basis points, negative for rebates"),
+            ("taker_fee_basis_points", "", PropertyType::U64, "Fee of the taker, in basis points"),
+            mint,
+        ])
+        .function("list", &[price[0], price[1], price[2], mint])
+        .function("delist", &[price[0], price[1], price[2], mint])
+        .function("bid", &[
+            price[0],
+            price[1],
+            price[2],
+            ("royalty_basis_points", "", PropertyType::U64,
+             "Royalties the buyer pays to the creators, in basis points"),
+            mint,
+        ])
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    if instruction.data.len() < 8 {
+        error!("[spi-wrapper/magic-eden] Attempt to parse instruction from program {} failed as \
+            it is shorter than a discriminator.", instruction.program);
+
+        return None;
+    }
+    let (discriminator, arguments) = instruction.data.split_at(8);
+    let mut arguments = Reader::new(arguments);
+
+    let decoded = match <[u8; 8]>::try_from(discriminator).ok()? {
+        SELL => sell(&mut arguments).map(|fields| ("list", fields, LISTING_MINT_ACCOUNT)),
+        CANCEL_SELL => listing(&mut arguments)
+            .map(|fields| ("delist", fields, LISTING_MINT_ACCOUNT)),
+        BUY_V2 => bid(&mut arguments).map(|fields| ("bid", fields, BID_MINT_ACCOUNT)),
+        EXECUTE_SALE => sale(&mut arguments, "execute-sale")
+            .map(|fields| ("sale", fields, SALE_MINT_ACCOUNT)),
+        EXECUTE_SALE_V2 => sale(&mut arguments, "execute-sale-v2")
+            .map(|fields| ("sale", fields, SALE_MINT_ACCOUNT)),
+        _ => {
+            debug!("[spi-wrapper/magic-eden] Instruction {} of {} isn't one the processor \
+                decodes.", hex::encode(discriminator), instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let (function_name, fields, mint_account) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/magic-eden] Attempt to parse instruction from program {} failed \
+                due to truncated arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, value, value_type)| layout::property(&instruction, key, "", value, value_type))
+        .collect();
+    if let Some(mint) = accounts.get(mint_account) {
+        instruction_set.properties.push(layout::property(
+            &instruction, "mint", "", mint.to_string(), PropertyType::Pubkey));
+    }
+
+    Some(instruction_set)
+}
+
+// The properties of an instruction: key, value and type.
+type Fields = SmallVec<[(&'static str, String, PropertyType); 8]>;
+
+/// The price and size all the instructions start with (past their bumps).
+fn price_fields(arguments: &mut Reader) -> Option<Fields> {
+    let buyer_price = arguments.u64()?;
+    let token_size = arguments.u64()?;
+
+    Some(smallvec![
+        ("price", buyer_price.to_string(), PropertyType::U64),
+        ("price_sol", layout::sol(buyer_price), PropertyType::Decimal),
+        ("token_size", token_size.to_string(), PropertyType::U64),
+    ])
+}
+
+/// The arguments of `sell`: the bumps of the seller state and of the program signer, then those
+/// of `cancel_sell`.
+fn sell(arguments: &mut Reader) -> Option<Fields> {
+    arguments.bytes(2)?;
+
+    listing(arguments)
+}
+
+/// The arguments of `cancel_sell`: the price and size of the listing, and when it expires.
+fn listing(arguments: &mut Reader) -> Option<Fields> {
+    let fields = price_fields(arguments)?;
+    arguments.i64()?;
+
+    Some(fields)
+}
+
+/// The arguments of `buy_v2`: the price and size of the bid, when it expires, the royalties the
+/// buyer pays, then extra arguments of later versions.
+fn bid(arguments: &mut Reader) -> Option<Fields> {
+    let mut fields = price_fields(arguments)?;
+    arguments.i64()?;
+    let royalty_basis_points = arguments.u16()?;
+
+    fields.push(("royalty_basis_points", royalty_basis_points.to_string(), PropertyType::U64));
+    Some(fields)
+}
+
+/// The arguments of `execute_sale` and `execute_sale_v2`: the bumps of the escrow and of the
+/// program signer, the price and size of the sale, when the bid and the listing expire, then the
+/// fees of the maker and the taker.
+fn sale(arguments: &mut Reader, instruction: &str) -> Option<Fields> {
+    arguments.bytes(2)?;
+    let mut fields: Fields =
+        smallvec![("instruction", instruction.to_string(), PropertyType::Text)];
+    fields.extend(price_fields(arguments)?);
+    arguments.i64()?;
+    arguments.i64()?;
+    let maker_fee_basis_points = arguments.i16()?;
+    let taker_fee_basis_points = arguments.u16()?;
+
+    fields.push(("maker_fee_basis_points", maker_fee_basis_points.to_string(),
+                 PropertyType::I128));
+    fields.push(("taker_fee_basis_points", taker_fee_basis_points.to_string(),
+                 PropertyType::U64));
+    Some(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use solana_sdk::hash::hash;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    // Instruction data as sent to the program, discriminator included.
+    // Listing an NFT for 2.5 SOL, without expiry.
+    const SELL_2_5_SOL: &str = "33e685a4017f83adfefd00f90295000000000100000000000000ffffffffffff\
+        ffff";
+    const CANCEL_SELL_2_5_SOL: &str = "c6c682cba35faf4b00f90295000000000100000000000000ffffffff\
+        ffffffff";
+    // Bidding 1.2 SOL, paying 2.5% of royalties.
+    const BUY_V2_1_2_SOL: &str = "b817ee6167c5d33d008c864700000000010000000000000000f15365000000\
+        00fa0000000000";
+    // Selling for 2.5 SOL, with a 0.5% rebate to the maker and a 2% fee for the taker.
+    const EXECUTE_SALE_V2_2_5_SOL: &str = "5bdc31dfcc8135c1fffe00f902950000000001000000000000000\
+        0f1536500000000ffffffffffffffffceffc800";
+
+    fn decode(data: &str, accounts: &[Pubkey]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, hex::decode(data).unwrap()), accounts)
+    }
+
+    #[test]
+    fn discriminators_are_the_anchor_ones() {
+        for (name, discriminator) in &[
+            ("sell", SELL),
+            ("cancel_sell", CANCEL_SELL),
+            ("buy_v2", BUY_V2),
+            ("execute_sale", EXECUTE_SALE),
+            ("execute_sale_v2", EXECUTE_SALE_V2),
+        ] {
+            let preimage = format!("global:{}", name);
+            let expected: [u8; 8] = hash(preimage.as_bytes()).to_bytes()[..8].try_into().unwrap();
+            assert_eq!(*discriminator, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn listings_bids_and_sales_decode() {
+        let accounts: Vec<_> = (0..20).map(|_| Pubkey::new_unique()).collect();
+
+        let list = decode(SELL_2_5_SOL, &accounts).unwrap();
+        assert_eq!(list.function.function_name.as_str(), "list");
+        assert_eq!(properties(&list), vec![
+            ("price", "2500000000".to_string()),
+            ("price_sol", "2.5".to_string()),
+            ("token_size", "1".to_string()),
+            ("mint", accounts[4].to_string()),
+        ]);
+        let delist = decode(CANCEL_SELL_2_5_SOL, &accounts).unwrap();
+        assert_eq!(delist.function.function_name.as_str(), "delist");
+        assert_eq!(properties(&delist), properties(&list));
+
+        let bid = decode(BUY_V2_1_2_SOL, &accounts).unwrap();
+        assert_eq!(bid.function.function_name.as_str(), "bid");
+        assert_eq!(properties(&bid), vec![
+            ("price", "1200000000".to_string()),
+            ("price_sol", "1.2".to_string()),
+            ("token_size", "1".to_string()),
+            ("royalty_basis_points", "250".to_string()),
+            ("mint", accounts[2].to_string()),
+        ]);
+
+        let sale = decode(EXECUTE_SALE_V2_2_5_SOL, &[]).unwrap();
+        assert_eq!(sale.function.function_name.as_str(), "sale");
+        assert_eq!(properties(&sale), vec![
+            ("instruction", "execute-sale-v2".to_string()),
+            ("price", "2500000000".to_string()),
+            ("price_sol", "2.5".to_string()),
+            ("token_size", "1".to_string()),
+            ("maker_fee_basis_points", "-50".to_string()),
+            ("taker_fee_basis_points", "200".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        // Cut before the expiry.
+        assert!(decode(&SELL_2_5_SOL[..2 * 26], &[]).is_none());
+        // Cut before the taker fee.
+        assert!(decode(&EXECUTE_SALE_V2_2_5_SOL[..2 * 44], &[]).is_none());
+        assert!(decode("0001020304050607", &[]).is_none());
+        assert!(decode("33e685", &[]).is_none());
+    }
+}
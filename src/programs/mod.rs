@@ -1,15 +1,94 @@
+#[cfg(feature = "bpf-loader")]
 pub mod bpf_loader;
+#[cfg(feature = "aldrin")]
+pub mod aldrin;
+#[cfg(feature = "auction-house")]
+pub mod auction_house;
+#[cfg(any(
+    feature = "aldrin",
+    feature = "associated-token-account",
+    feature = "auction-house",
+    feature = "bubblegum",
+    feature = "candy-machine",
+    feature = "compute-budget",
+    feature = "drift",
+    feature = "governance",
+    feature = "layout-decoder",
+    feature = "lifinity",
+    feature = "magic-eden",
+    feature = "memo",
+    feature = "name-service",
+    feature = "openbook-v2",
+    feature = "phoenix",
+    feature = "raydium-amm",
+    feature = "tensor",
+    feature = "token-2022",
+    feature = "token-metadata",
+    feature = "vote",
+    feature = "whirlpool",
+))]
+mod layout;
+#[cfg(feature = "layout-decoder")]
+pub mod layout_decoder;
+#[cfg(feature = "layout-decoder")]
+pub mod layout_registry;
+#[cfg(feature = "lifinity")]
+pub mod lifinity;
+#[cfg(feature = "bpf-loader-upgradeable")]
 pub mod bpf_loader_upgradeable;
+#[cfg(feature = "bubblegum")]
+pub mod bubblegum;
+#[cfg(feature = "candy-machine")]
+pub mod candy_machine;
+#[cfg(feature = "associated-token-account")]
 pub mod native_associated_token_account;
+#[cfg(feature = "drift")]
+pub mod drift;
+#[cfg(feature = "governance")]
+pub mod governance;
+#[cfg(feature = "compute-budget")]
+pub mod native_compute_budget;
+#[cfg(feature = "config")]
 pub mod native_config;
+#[cfg(feature = "loader")]
 pub mod native_loader;
+#[cfg(feature = "magic-eden")]
+pub mod magic_eden;
+#[cfg(feature = "memo")]
+pub mod native_memo;
+#[cfg(feature = "name-service")]
+pub mod name_service;
+#[cfg(feature = "openbook-v2")]
+pub mod openbook_v2;
+#[cfg(feature = "phoenix")]
+pub mod phoenix;
+#[cfg(feature = "raydium-amm")]
+pub mod raydium_amm;
+#[cfg(feature = "secp256k1")]
 pub mod native_secp256k1;
+#[cfg(feature = "stake")]
 pub mod native_stake;
+#[cfg(feature = "system")]
 pub mod native_system;
+#[cfg(feature = "token")]
 pub mod native_token;
+#[cfg(feature = "token-2022")]
+pub mod native_token_2022;
+#[cfg(feature = "token-swap")]
 pub mod native_token_swap;
+#[cfg(feature = "token-lending")]
 pub mod native_token_lending;
+#[cfg(feature = "vote")]
 pub mod native_vote;
+#[cfg(feature = "serum-market")]
 pub mod serum_market;
+#[cfg(feature = "solend-token-lending")]
 pub mod solend;
-pub mod solend_token_lending;
\ No newline at end of file
+#[cfg(feature = "solend-token-lending")]
+pub mod solend_token_lending;
+#[cfg(feature = "tensor")]
+pub mod tensor;
+#[cfg(feature = "token-metadata")]
+pub mod token_metadata;
+#[cfg(feature = "whirlpool")]
+pub mod whirlpool;
@@ -0,0 +1,225 @@
+//! The SPL name service, which holds the names of the Solana Name Service: .sol domains, their
+//! subdomains and records. Every instruction starts with a one byte tag, followed by its borsh
+//! encoded arguments.
+//!
+//! A name account is found from the hash of its name, its class and its parent (the .sol domain
+//! for subdomains, the .sol TLD for domains), and the name itself never reaches the program: the
+//! hash is what's emitted. The class and parent are only emitted when the name has them, the
+//! program being given the default public key otherwise.
+
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS: &str = "namesLPneVptA9Z5rqUDD9tMTWEJwofgaYwp8cawRkX";
+
+const CREATE: u8 = 0;
+const UPDATE: u8 = 1;
+const TRANSFER: u8 = 2;
+const DELETE: u8 = 3;
+
+// The accounts emitted for each instruction: key, then where it is in its accounts.
+type Accounts = &'static [(&'static str, usize)];
+
+const CREATE_ACCOUNTS: Accounts =
+    &[("name_account", 2), ("owner", 3), ("name_class", 4), ("parent_name", 5)];
+const UPDATE_ACCOUNTS: Accounts = &[("name_account", 0)];
+const TRANSFER_ACCOUNTS: Accounts = &[("name_account", 0), ("owner", 1)];
+const DELETE_ACCOUNTS: Accounts = &[("name_account", 0), ("owner", 1), ("refund_target", 2)];
+
+// The properties of an instruction: key, value and type.
+type Fields = Vec<(&'static str, String, PropertyType)>;
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let account = |key: &'static str, description: &'static str| {
+        (key, "", PropertyType::Pubkey, description)
+    };
+    let name_account = account("name_account",
+                               "Account of the name, when the accounts of the instruction are \
+                               known");
+    let owner = |description| account("owner", description);
+
+    ProgramSchema::new("name-service")
+        .function("create", &[
+            ("name_hash", "", PropertyType::Bytes,
+             "Hash of the name, hex encoded (sha256 of the name and the name service prefix)"),
+            ("lamports", "", PropertyType::U64, "Lamports funding the name account"),
+            ("space", "", PropertyType::U64, "Bytes of data the name account holds"),
+            name_account,
+            owner("Owner of the name, when the accounts of the instruction are known"),
+            account("name_class",
+                    "Class of the name, when it has one and the accounts of the instruction are \
+                    known"),
+            account("parent_name",
+                    "Parent of the name (the .sol TLD for domains), when it has one and the \
+                    accounts of the instruction are known"),
+        ])
+        .function("update", &[
+            ("offset", "", PropertyType::U64, "Where the data is written, past the header"),
+            ("data", "", PropertyType::Bytes, "Data written, hex encoded"),
+            name_account,
+        ])
+        .function("transfer", &[
+            ("new_owner", "", PropertyType::Pubkey, "Owner the name is transferred to"),
+            name_account,
+            owner("Owner the name is transferred from, when the accounts of the instruction are \
+                  known"),
+        ])
+        .function("delete", &[
+            name_account,
+            owner("Owner of the name, when the accounts of the instruction are known"),
+            account("refund_target",
+                    "Account refunded the lamports of the name account, when the accounts of \
+                    the instruction are known"),
+        ])
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    let (tag, arguments) = match instruction.data.split_first() {
+        Some(split) => split,
+        None => {
+            error!("[spi-wrapper/name-service] Attempt to parse instruction from program {} \
+                failed as it is empty.", instruction.program);
+
+            return None;
+        }
+    };
+    let mut arguments = Reader::new(arguments);
+
+    let decoded = match *tag {
+        CREATE => create(&mut arguments).map(|fields| ("create", fields, CREATE_ACCOUNTS)),
+        UPDATE => update(&mut arguments).map(|fields| ("update", fields, UPDATE_ACCOUNTS)),
+        TRANSFER => arguments.pubkey().map(|new_owner| {
+            ("transfer", vec![("new_owner", new_owner.to_string(), PropertyType::Pubkey)],
+             TRANSFER_ACCOUNTS)
+        }),
+        DELETE => Some(("delete", Fields::new(), DELETE_ACCOUNTS)),
+        _ => {
+            debug!("[spi-wrapper/name-service] Instruction {} of {} isn't one the processor \
+                decodes.", tag, instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let (function_name, fields, known_accounts) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/name-service] Attempt to parse instruction from program {} \
+                failed due to truncated arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, value, value_type)| layout::property(&instruction, key, "", value, value_type))
+        .collect();
+    for (key, index) in known_accounts {
+        // The default public key is what the program is given for the class or parent of names
+        // without one.
+        let account = accounts.get(*index).filter(|account| **account != Pubkey::default());
+        if let Some(account) = account {
+            instruction_set.properties.push(layout::property(
+                &instruction, key, "", account.to_string(), PropertyType::Pubkey));
+        }
+    }
+
+    Some(instruction_set)
+}
+
+/// The arguments of `Create`: the hash of the name, then the lamports and space of its account.
+fn create(arguments: &mut Reader) -> Option<Fields> {
+    let name_hash = arguments.vec(1, Reader::u8)?;
+
+    Some(vec![
+        ("name_hash", hex::encode(name_hash), PropertyType::Bytes),
+        ("lamports", arguments.u64()?.to_string(), PropertyType::U64),
+        ("space", arguments.u32()?.to_string(), PropertyType::U64),
+    ])
+}
+
+/// The arguments of `Update`: where the data is written, then the data.
+fn update(arguments: &mut Reader) -> Option<Fields> {
+    let offset = arguments.u32()?;
+    let data = arguments.vec(1, Reader::u8)?;
+
+    Some(vec![
+        ("offset", offset.to_string(), PropertyType::U64),
+        ("data", hex::encode(data), PropertyType::Bytes),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(data: &[u8], accounts: &[Pubkey]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, data.to_vec()), accounts)
+    }
+
+    #[test]
+    fn domains_are_created_with_their_hash_and_owner() {
+        let mut accounts: Vec<_> = (0..7).map(|_| Pubkey::new_unique()).collect();
+        // A domain, of no class.
+        accounts[4] = Pubkey::default();
+        let create = [&[CREATE][..], &[32, 0, 0, 0], &[7; 32], &2_000_000u64.to_le_bytes(),
+                      &[0, 4, 0, 0]].concat();
+
+        let create = decode(&create, &accounts).unwrap();
+        assert_eq!(create.function.function_name.as_str(), "create");
+        assert_eq!(properties(&create), vec![
+            ("name_hash", "07".repeat(32)),
+            ("lamports", "2000000".to_string()),
+            ("space", "1024".to_string()),
+            ("name_account", accounts[2].to_string()),
+            ("owner", accounts[3].to_string()),
+            ("parent_name", accounts[5].to_string()),
+        ]);
+    }
+
+    #[test]
+    fn updates_transfers_and_deletions_decode() {
+        let accounts: Vec<_> = (0..3).map(|_| Pubkey::new_unique()).collect();
+
+        let update = decode(&[UPDATE, 0, 1, 0, 0, 2, 0, 0, 0, 0xca, 0xfe], &[]).unwrap();
+        assert_eq!(properties(&update), vec![
+            ("offset", "256".to_string()),
+            ("data", "cafe".to_string()),
+        ]);
+
+        let new_owner = Pubkey::new_unique();
+        let transfer = [&[TRANSFER][..], new_owner.as_ref()].concat();
+        let transfer = decode(&transfer, &accounts).unwrap();
+        assert_eq!(transfer.function.function_name.as_str(), "transfer");
+        assert_eq!(properties(&transfer), vec![
+            ("new_owner", new_owner.to_string()),
+            ("name_account", accounts[0].to_string()),
+            ("owner", accounts[1].to_string()),
+        ]);
+
+        let delete = decode(&[DELETE], &accounts).unwrap();
+        assert_eq!(properties(&delete)[2], ("refund_target", accounts[2].to_string()));
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        // Cut within the name hash, and before the space.
+        assert!(decode(&[CREATE, 32, 0, 0, 0, 7], &[]).is_none());
+        assert!(decode(&[&[CREATE, 0, 0, 0, 0][..], &[0; 8]].concat(), &[]).is_none());
+        assert!(decode(&[TRANSFER, 1, 2], &[]).is_none());
+        assert!(decode(&[9], &[]).is_none());
+        assert!(decode(&[], &[]).is_none());
+    }
+}
@@ -1,119 +1,116 @@
-use bincode::deserialize;
-use tracing::error;
+//! The associated token account program, creating the token account of a wallet for a mint at
+//! the address derived from the wallet, the token program and the mint.
+//!
+//! `Create` used to carry no data at all, and still decodes from empty data; `CreateIdempotent`
+//! does the same without failing when the account exists already. The wallet, the mint and the
+//! account created are read from the accounts of the instruction, and are only emitted when
+//! they're known.
 
-use crate::{InstructionProperty, Instruction, InstructionSet, InstructionFunction};
+use solana_sdk::pubkey::Pubkey;
+use tracing::debug;
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout;
+use crate::schema::ProgramSchema;
 
 pub const PROGRAM_ADDRESS: &str = "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
 
-/// Extracts the contents of an instruction into small bits and pieces, or what we would call,
-/// instruction_properties.
-///
-/// The function should return a list of instruction properties extracted from an instruction.
-pub async fn fragment_instruction(
-    // The instruction
+const CREATE: u8 = 0;
+const CREATE_IDEMPOTENT: u8 = 1;
+
+// The accounts of `Create` and `CreateIdempotent`, by key, in the order they're invoked with.
+const ACCOUNTS: [(&str, &str); 6] = [
+    ("funder", "Account paying for the rent of the account created"),
+    ("associated_account", "Associated token account, derived from the wallet, the token \
+        program and the mint"),
+    ("wallet", "Wallet owning the associated token account"),
+    ("mint", "Mint of the associated token account"),
+    ("system_program", "System program"),
+    ("token_program", "Token program owning the associated token account"),
+];
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let accounts: Vec<_> = ACCOUNTS.iter()
+        .map(|(key, description)| (*key, "", PropertyType::Pubkey, *description))
+        .collect();
+
+    ProgramSchema::new("associated-token-account")
+        .function("create", &accounts)
+        .function("create-idempotent", &accounts)
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
     instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
 ) -> Option<InstructionSet> {
-    let atadr = deserialize::<solana_program::instruction::Instruction>(
-        &instruction.data.as_slice());
-
-    return match atadr {
-        Ok(ref ati) => {
-            let associated_token_instruction = ati.clone();
-            // Create an associated token account for the given wallet address and token mint
-            //
-            // Accounts expected by this instruction:
-            //
-            //   0. `[writeable,signer]` Funding account (must be a system account)
-            //   1. `[writeable]` Associated token account address to be created
-            //   2. `[]` Wallet address for the new associated token account
-            //   3. `[]` The token mint for the new associated token account
-            //   4. `[]` System program
-            //   5. `[]` SPL Token program
-            //   6. `[]` Rent sysvar
-            let account_sets: Vec<Vec<InstructionProperty>> = associated_token_instruction.accounts
-                .into_iter().map(|am| {
-                vec![
-                    InstructionProperty {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
-                        transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        key: "pubkey".to_string(),
-                        value: am.pubkey.to_string(),
-                        parent_key: "".to_string(),
-                        timestamp: instruction.timestamp.clone(),
-                    },
-                    InstructionProperty {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
-                        transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        key: "is_signer".to_string(),
-                        value: if am.is_signer {
-                            "1".to_string()
-                        } else {
-                            "0".to_string()
-                        },
-                        parent_key: "".to_string(),
-                        timestamp: instruction.timestamp.clone(),
-                    },
-                    InstructionProperty {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
-                        transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        key: "is_writable".to_string(),
-                        value: if am.is_writable {
-                            "1".to_string()
-                        } else {
-                            "0".to_string()
-                        },
-                        parent_key: "".to_string(),
-                        timestamp: instruction.timestamp.clone(),
-                    }
-                ]
-            }).collect();
-
-            let mut properties = vec![
-                InstructionProperty {
-                    tx_instruction_id: instruction.tx_instruction_id.clone(),
-                    transaction_hash: instruction.transaction_hash.clone(),
-                    parent_index: instruction.parent_index.clone(),
-                    key: "data".to_string(),
-                    value: bs58::encode(associated_token_instruction.data).into_string(),
-                    parent_key: "".to_string(),
-                    timestamp: instruction.timestamp.clone(),
-                },
-                InstructionProperty {
-                    tx_instruction_id: instruction.tx_instruction_id.clone(),
-                    transaction_hash: instruction.transaction_hash.clone(),
-                    parent_index: instruction.parent_index.clone(),
-                    key: "program_id".to_string(),
-                    value: associated_token_instruction.program_id.to_string(),
-                    parent_key: "".to_string(),
-                    timestamp: instruction.timestamp.clone(),
-                }
-            ];
-
-            for ac in account_sets {
-                properties.extend(ac);
-            }
-
-            Some(InstructionSet {
-                function: InstructionFunction {
-                    tx_instruction_id: instruction.tx_instruction_id.clone(),
-                    transaction_hash: instruction.transaction_hash.clone(),
-                    parent_index: instruction.parent_index.clone(),
-                    program: instruction.program.clone(),
-                    function_name: "".to_string(),
-                    timestamp: instruction.timestamp
-                },
-                properties
-            })
-        }
-        Err(err) => {
-            // If the instruction parsing is failing, bail out
-            error!("[spi-wrapper/bpf_loader] Attempt to parse instruction from program {} failed due to \
-        {}.", instruction.program, err);
+    let function_name = match instruction.data.as_slice() {
+        [] | [CREATE] => "create",
+        [CREATE_IDEMPOTENT] => "create-idempotent",
+        _ => {
+            debug!("[spi-wrapper/associated-token-account] Instruction {} of {} isn't one the \
+                processor decodes.", hex::encode(&instruction.data), instruction.transaction_hash);
 
-            None
+            return None;
         }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = ACCOUNTS.iter()
+        .zip(accounts)
+        .map(|((key, _), account)| {
+            layout::property(&instruction, key, "", account.to_string(), PropertyType::Pubkey)
+        })
+        .collect();
+
+    Some(instruction_set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(data: &[u8], accounts: &[Pubkey]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, data.to_vec()), accounts)
+    }
+
+    #[test]
+    fn creations_carry_the_wallet_the_mint_and_the_account_created() {
+        let funder = Pubkey::new_unique();
+        let (wallet, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let token_program: Pubkey = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA".parse().unwrap();
+        let (associated_account, _) = Pubkey::find_program_address(
+            &[wallet.as_ref(), token_program.as_ref(), mint.as_ref()],
+            &PROGRAM_ADDRESS.parse().unwrap(),
+        );
+        let accounts = [funder, associated_account, wallet, mint, Pubkey::default(), token_program,
+                        Pubkey::new_unique()];
+
+        let create = decode(&[], &accounts).unwrap();
+        assert_eq!(create.function.function_name.as_str(), "create");
+        assert_eq!(properties(&create), vec![
+            ("funder", funder.to_string()),
+            ("associated_account", associated_account.to_string()),
+            ("wallet", wallet.to_string()),
+            ("mint", mint.to_string()),
+            ("system_program", Pubkey::default().to_string()),
+            ("token_program", token_program.to_string()),
+        ]);
+        assert_eq!(decode(&[0], &accounts).unwrap().function.function_name.as_str(), "create");
+
+        let idempotent = decode(&[1], &accounts[..4]).unwrap();
+        assert_eq!(idempotent.function.function_name.as_str(), "create-idempotent");
+        assert_eq!(properties(&idempotent).len(), 4);
+    }
+
+    #[test]
+    fn instructions_without_known_accounts_carry_no_properties() {
+        assert!(decode(&[1], &[]).unwrap().properties.is_empty());
+        // Recovering a nested account, and garbage.
+        assert!(decode(&[2], &[]).is_none());
+        assert!(decode(&[0, 0], &[]).is_none());
     }
-}
\ No newline at end of file
+}
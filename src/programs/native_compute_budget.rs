@@ -0,0 +1,170 @@
+//! The compute budget program, whose instructions set how many compute units a transaction may
+//! consume, what it pays for each of them (its priority fee), how large its heap is and how many
+//! bytes of accounts it may load. Every instruction is a one byte tag followed by its little
+//! endian arguments.
+//!
+//! The deprecated `RequestUnits`, which paid an additional fee (in lamports) rather than a price
+//! per unit, is still emitted for the transactions using it. What a transaction actually
+//! pays is worked out in [`crate::fees`].
+
+use smallvec::{smallvec, SmallVec};
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS: &str = "ComputeBudget111111111111111111111111111111";
+
+const REQUEST_UNITS: u8 = 0;
+const REQUEST_HEAP_FRAME: u8 = 1;
+const SET_COMPUTE_UNIT_LIMIT: u8 = 2;
+const SET_COMPUTE_UNIT_PRICE: u8 = 3;
+const SET_LOADED_ACCOUNTS_DATA_SIZE_LIMIT: u8 = 4;
+
+// The properties of an instruction, all of them amounts: key and value.
+type Fields = SmallVec<[(&'static str, u64); 2]>;
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let units = ("units", "", PropertyType::U64, "Compute units the transaction may consume");
+
+    ProgramSchema::new("compute-budget")
+        .function("request-units", &[
+            units,
+            ("additional_fee", "", PropertyType::U64,
+             "Lamports paid on top of the base fee, for the units"),
+        ])
+        .function("request-heap-frame", &[
+            ("bytes", "", PropertyType::U64, "Size of the heap of the transaction, in bytes"),
+        ])
+        .function("set-compute-unit-limit", &[units])
+        .function("set-compute-unit-price", &[
+            ("micro_lamports", "", PropertyType::U64,
+             "Price of a compute unit, in micro-lamports (millionths of a lamport)"),
+        ])
+        .function("set-loaded-accounts-data-size-limit", &[
+            ("bytes", "", PropertyType::U64,
+             "Size of the accounts the transaction may load, in bytes"),
+        ])
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+) -> Option<InstructionSet> {
+    let (tag, arguments) = match instruction.data.split_first() {
+        Some(split) => split,
+        None => {
+            error!("[spi-wrapper/compute-budget] Attempt to parse instruction from program {} \
+                failed as it is empty.", instruction.program);
+
+            return None;
+        }
+    };
+    let mut arguments = Reader::new(arguments);
+
+    let decoded = match *tag {
+        REQUEST_UNITS => request_units(&mut arguments).map(|fields| ("request-units", fields)),
+        REQUEST_HEAP_FRAME => arguments.u32()
+            .map(|bytes| ("request-heap-frame", smallvec![("bytes", bytes as u64)])),
+        SET_COMPUTE_UNIT_LIMIT => arguments.u32()
+            .map(|units| ("set-compute-unit-limit", smallvec![("units", units as u64)])),
+        SET_COMPUTE_UNIT_PRICE => arguments.u64()
+            .map(|price| ("set-compute-unit-price", smallvec![("micro_lamports", price)])),
+        SET_LOADED_ACCOUNTS_DATA_SIZE_LIMIT => arguments.u32().map(|bytes| {
+            ("set-loaded-accounts-data-size-limit", smallvec![("bytes", bytes as u64)])
+        }),
+        _ => {
+            debug!("[spi-wrapper/compute-budget] Instruction {} of {} isn't one the processor \
+                decodes.", tag, instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let (function_name, fields) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/compute-budget] Attempt to parse instruction from program {} \
+                failed due to truncated arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, value)| {
+            layout::property(&instruction, key, "", value.to_string(), PropertyType::U64)
+        })
+        .collect();
+
+    Some(instruction_set)
+}
+
+/// The arguments of the deprecated `RequestUnits`: the units, then the additional fee.
+fn request_units(arguments: &mut Reader) -> Option<Fields> {
+    Some(smallvec![
+        ("units", arguments.u32()? as u64),
+        ("additional_fee", arguments.u32()? as u64),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(data: &[u8]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, data.to_vec()))
+    }
+
+    #[test]
+    fn limits_and_prices_carry_their_amount() {
+        let limit = decode(&[&[SET_COMPUTE_UNIT_LIMIT][..], &300_000u32.to_le_bytes()].concat())
+            .unwrap();
+        assert_eq!(limit.function.function_name.as_str(), "set-compute-unit-limit");
+        assert_eq!(properties(&limit), vec![("units", "300000".to_string())]);
+
+        let price = decode(&[&[SET_COMPUTE_UNIT_PRICE][..], &50_000u64.to_le_bytes()].concat())
+            .unwrap();
+        assert_eq!(price.function.function_name.as_str(), "set-compute-unit-price");
+        assert_eq!(properties(&price), vec![("micro_lamports", "50000".to_string())]);
+
+        let heap = decode(&[&[REQUEST_HEAP_FRAME][..], &(256u32 * 1024).to_le_bytes()].concat())
+            .unwrap();
+        assert_eq!(properties(&heap), vec![("bytes", "262144".to_string())]);
+    }
+
+    #[test]
+    fn loaded_accounts_data_size_limits_carry_their_size() {
+        let data = [&[SET_LOADED_ACCOUNTS_DATA_SIZE_LIMIT][..], &(64u32 * 1024).to_le_bytes()]
+            .concat();
+        let limit = decode(&data).unwrap();
+        assert_eq!(limit.function.function_name.as_str(), "set-loaded-accounts-data-size-limit");
+        assert_eq!(properties(&limit), vec![("bytes", "65536".to_string())]);
+        assert!(describe().key("set-loaded-accounts-data-size-limit", "bytes", "").is_some());
+        assert!(decode(&[SET_LOADED_ACCOUNTS_DATA_SIZE_LIMIT, 0, 1]).is_none());
+    }
+
+    #[test]
+    fn deprecated_unit_requests_carry_their_fee() {
+        let data = [&[REQUEST_UNITS][..], &400_000u32.to_le_bytes(), &2_000u32.to_le_bytes()]
+            .concat();
+        let request = decode(&data).unwrap();
+        assert_eq!(request.function.function_name.as_str(), "request-units");
+        assert_eq!(properties(&request), vec![
+            ("units", "400000".to_string()),
+            ("additional_fee", "2000".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        assert!(decode(&[SET_COMPUTE_UNIT_PRICE, 1, 0, 0, 0]).is_none());
+        assert!(decode(&[REQUEST_UNITS, 1, 0, 0, 0]).is_none());
+        assert!(decode(&[9]).is_none());
+        assert!(decode(&[]).is_none());
+    }
+}
@@ -1,17 +1,33 @@
+use smallvec::smallvec;
 use solana_config_program::ConfigKeys;
 use solana_program::instruction::InstructionError;
 use solana_sdk::program_utils::limited_deserialize;
 use tracing::error;
 
-use crate::{InstructionProperty, Instruction, InstructionSet, InstructionFunction};
+use crate::{
+    InstructionProperty, Instruction, InstructionSet, InstructionFunction, Properties,
+    PropertyType,
+};
+use crate::schema::ProgramSchema;
 
 pub const PROGRAM_ADDRESS: &str = "Config1111111111111111111111111111111111111";
 
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    ProgramSchema::new("config")
+        .function("store", &[
+            ("config_keys/{index}/pubkey", "config_keys/{index}", PropertyType::Pubkey,
+             "Account of the configuration"),
+            ("config_keys/{index}/signer", "config_keys/{index}", PropertyType::Bool,
+             "Whether the account has to sign updates"),
+        ])
+}
+
 /// Extracts the contents of an instruction into small bits and pieces, or what we would call,
 /// instruction_properties.
 ///
 /// The function should return a list of instruction properties extracted from an instruction.
-pub async fn fragment_instruction(
+pub fn fragment_instruction(
     // The instruction
     instruction: Instruction,
 ) -> Option<InstructionSet> {
@@ -24,14 +40,17 @@ pub async fn fragment_instruction(
 
             let mut instruction_set = InstructionSet {
                 function: InstructionFunction {
-                    tx_instruction_id: instruction.tx_instruction_id.clone(),
+                    tx_instruction_id: instruction.tx_instruction_id,
                     transaction_hash: instruction.transaction_hash.clone(),
-                    parent_index: instruction.parent_index.clone(),
-                    program: instruction.program.clone(),
-                    function_name: "".to_string(),
-                    timestamp: instruction.timestamp.clone(),
+                    parent_index: instruction.parent_index,
+                    program: instruction.program,
+                    function_name: "store".into(),
+                    timestamp: instruction.timestamp,
+                    network: instruction.network,
+                    decoder_version: 0,
                 },
-                properties: vec![],
+                properties: smallvec![],
+                raw_data: None,
             };
 
             let config_keys: Vec<Vec<InstructionProperty>> = (0..key_list.keys.len()).into_iter()
@@ -43,30 +62,32 @@ pub async fn fragment_instruction(
                     let key_name = "config_keys/".to_owned() + &*idx.to_string();
                     let pubkey_name = key_name.clone() + &"/pubkey".to_owned();
                     properties.push(InstructionProperty {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        key: pubkey_name,
+                        parent_index: instruction.parent_index,
+                        key: pubkey_name.into(),
                         value: pk.to_string(),
-                        parent_key: key_name.clone(),
-                        timestamp: instruction.timestamp.clone(),
+                        value_type: PropertyType::Pubkey,
+                        parent_key: key_name.as_str().into(),
+                        timestamp: instruction.timestamp,
                     });
 
                     let signer_name = key_name.clone() + &"/signer".to_owned();
                     properties.push(InstructionProperty {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        key: signer_name.clone(),
+                        parent_index: instruction.parent_index,
+                        key: signer_name.into(),
                         value: (is_signer as i32).to_string(),
-                        parent_key: key_name,
-                        timestamp: instruction.timestamp.clone(),
+                        value_type: PropertyType::Bool,
+                        parent_key: key_name.into(),
+                        timestamp: instruction.timestamp,
                     });
 
                     properties
                 }).collect();
 
-            let mut properties = Vec::new();
+            let mut properties = Properties::with_capacity(key_list.keys.len() * 2);
             for ck in config_keys {
                 properties.extend(ck);
             }
@@ -1,12 +1,19 @@
 use crate::{Instruction, InstructionSet};
+use crate::schema::ProgramSchema;
 
 pub const PROGRAM_ADDRESS: &str = "NativeLoader1111111111111111111111111111111";
 
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    // Nothing is decoded out of the instructions of the program.
+    ProgramSchema::new("loader")
+}
+
 /// Extracts the contents of an instruction into small bits and pieces, or what we would call,
 /// instruction_properties.
 ///
 /// The function should return a list of instruction properties extracted from an instruction.
-pub async fn fragment_instruction(
+pub fn fragment_instruction(
     // The instruction
     _instruction: Instruction,
 ) -> Option<InstructionSet> {
@@ -0,0 +1,69 @@
+//! The SPL memo program, both versions, attaching a memo (often the reference of a payment) to a
+//! transaction: the whole data of the instruction is the memo.
+//!
+//! Memos are meant to be UTF-8, but the first version of the program didn't check: those that
+//! aren't are emitted base64 encoded, along with an `encoding` property saying so.
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout;
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS_V1: &str = "Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo";
+pub const PROGRAM_ADDRESS_V2: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    ProgramSchema::new("memo")
+        .function("memo", &[
+            ("memo", "", PropertyType::Text, "The memo, base64 encoded when it isn't UTF-8"),
+            ("encoding", "", PropertyType::Text,
+             "How the memo is encoded (base64), only when it isn't UTF-8"),
+        ])
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+) -> Option<InstructionSet> {
+    let mut instruction_set = layout::instruction_set(&instruction, "memo");
+    match std::str::from_utf8(&instruction.data) {
+        Ok(memo) => {
+            instruction_set.properties.push(layout::property(
+                &instruction, "memo", "", memo.to_string(), PropertyType::Text));
+        }
+        Err(_) => {
+            instruction_set.properties.push(layout::property(
+                &instruction, "memo", "", base64::encode(&instruction.data), PropertyType::Text));
+            instruction_set.properties.push(layout::property(
+                &instruction, "encoding", "", "base64".to_string(), PropertyType::Text));
+        }
+    }
+
+    Some(instruction_set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(data: &[u8]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS_V2, data.to_vec()))
+    }
+
+    #[test]
+    fn utf8_memos_are_kept_as_they_are() {
+        let memo = decode("invoice #4521 ✓".as_bytes()).unwrap();
+        assert_eq!(memo.function.function_name.as_str(), "memo");
+        assert_eq!(properties(&memo), vec![("memo", "invoice #4521 ✓".to_string())]);
+        assert_eq!(properties(&decode(b"").unwrap()), vec![("memo", String::new())]);
+    }
+
+    #[test]
+    fn other_memos_are_base64_encoded() {
+        assert_eq!(properties(&decode(&[0xff, 0x00, 0x9f]).unwrap()), vec![
+            ("memo", "/wCf".to_string()),
+            ("encoding", "base64".to_string()),
+        ]);
+    }
+}
@@ -1,5 +1,6 @@
 use sha3::Digest;
 use libsecp256k1::PublicKey;
+use smallvec::smallvec;
 use solana_program::instruction::CompiledInstruction;
 use solana_sdk::secp256k1_instruction::{
     construct_eth_pubkey, Secp256k1Error, SecpSignatureOffsets, HASHED_PUBKEY_SERIALIZED_SIZE,
@@ -7,35 +8,56 @@ use solana_sdk::secp256k1_instruction::{
 };
 use tracing::{error, info};
 
-use crate::{InstructionProperty, Instruction, InstructionSet, InstructionFunction};
+use crate::{InstructionProperty, Instruction, InstructionSet, InstructionFunction, PropertyType};
+use crate::schema::ProgramSchema;
 
 pub const PROGRAM_ADDRESS: &str = "KeccakSecp256k11111111111111111111111111111";
 
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    ProgramSchema::new("secp256k1")
+        .function("verify", &[
+            ("eth_address", "", PropertyType::Bytes,
+             "Ethereum address recovered from the signature, hex encoded"),
+        ])
+}
+
 /// Extracts the contents of an instruction into small bits and pieces, or what we would call,
 /// instruction_properties.
 ///
 /// The function should return a list of instruction properties extracted from an instruction.
-pub async fn fragment_instruction(
+pub fn fragment_instruction(
     // The instruction
     instruction: Instruction,
     // The instructions that were part of the transaction, in order.
     instructions: &[CompiledInstruction]
 ) -> Option<InstructionSet> {
     // The first element within data slice tells us the number of signatures.
-    let count = instruction.data[0] as usize;
+    let count = match instruction.data.first() {
+        Some(count) => *count as usize,
+        None => {
+            info!("[spi-wrapper/programs/native_secp256k1] INFO: On-chain failed instruction \
+        found -> Secp256k1Error::InvalidInstructionDataSize");
+
+            return None;
+        }
+    };
     let expected_data_size = 1 + count * SIGNATURE_OFFSETS_SERIALIZED_SIZE;
 
     // Create the instruction
     let mut interpreted_instruction_set = InstructionSet {
         function: InstructionFunction {
-            tx_instruction_id: instruction.tx_instruction_id.clone(),
+            tx_instruction_id: instruction.tx_instruction_id,
             transaction_hash: instruction.transaction_hash.clone(),
-            parent_index: instruction.parent_index.clone(),
-            program: instruction.program.clone(),
-            function_name: "".to_string(),
-            timestamp: instruction.timestamp.clone(),
+            parent_index: instruction.parent_index,
+            program: instruction.program,
+            function_name: "verify".into(),
+            timestamp: instruction.timestamp,
+            network: instruction.network,
+            decoder_version: 0,
         },
-        properties: vec![],
+        properties: smallvec![],
+        raw_data: None,
     };
 
     // On-chain failed instruction detected, safe anyways.
@@ -164,13 +186,14 @@ pub async fn fragment_instruction(
             let eth_address_str = "0x".to_string() + &*hex::encode(eth_address);
             interpreted_instruction_set.properties
                 .push(InstructionProperty {
-                    tx_instruction_id: instruction.tx_instruction_id.clone(),
+                    tx_instruction_id: instruction.tx_instruction_id,
                     transaction_hash: instruction.transaction_hash.clone(),
-                    parent_index: instruction.parent_index.clone(),
-                    key: "eth_address".to_string(),
+                    parent_index: instruction.parent_index,
+                    key: "eth_address".into(),
                     value: eth_address_str.to_string(),
-                    parent_key: "".to_string(),
-                    timestamp: instruction.timestamp.clone(),
+                    value_type: PropertyType::Bytes,
+                    parent_key: "".into(),
+                    timestamp: instruction.timestamp,
                 });
 
             if eth_address_slice != eth_address {
@@ -1,18 +1,103 @@
+use std::convert::TryInto;
+
+use smallvec::smallvec;
 use solana_program::instruction::InstructionError;
 use solana_sdk::program_utils::limited_deserialize;
 use solana_program::stake::instruction::StakeInstruction;
 use solana_program::stake::state::StakeAuthorize;
 use tracing::error;
 
-use crate::{InstructionProperty, Instruction, InstructionSet, InstructionFunction};
+use crate::{InstructionProperty, Instruction, InstructionSet, InstructionFunction, PropertyType};
+use crate::schema::ProgramSchema;
 
 pub const PROGRAM_ADDRESS: &str = "Stake11111111111111111111111111111111111111";
 
+// The instructions that came after the solana version this crate is on, which its
+// `StakeInstruction` doesn't deserialize, by tag: their function and whether a u64 amount of
+// lamports follows.
+const NEWER_INSTRUCTIONS: [(u32, &str, bool); 5] = [
+    (13, "get-minimum-delegation", false),
+    (14, "deactivate-delinquent", false),
+    (15, "redelegate", false),
+    (16, "move-stake", true),
+    (17, "move-lamports", true),
+];
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    ProgramSchema::new("stake")
+        .function("initialize", &[
+            ("staker", "authorized", PropertyType::Pubkey,
+             "Authority allowed to delegate the stake"),
+            ("withdrawer", "authorized", PropertyType::Pubkey,
+             "Authority allowed to withdraw the stake"),
+            ("epoch", "lockup", PropertyType::U64, "Epoch the lockup ends at"),
+            ("custodian", "lockup", PropertyType::Pubkey,
+             "Custodian allowed to lift the lockup early"),
+            ("unix_timestamp", "lockup", PropertyType::Timestamp, "Time the lockup ends at"),
+        ])
+        .function("initialize-checked", &[])
+        .function("authorize", &[
+            ("authorized_pubkey", "", PropertyType::Pubkey, "New authority"),
+            ("stake_authorize", "", PropertyType::Text, "Authority changed (Staker or Withdrawer)"),
+        ])
+        .function("authorize-checked", &[
+            ("stake_authorize", "", PropertyType::Text, "Authority changed (Staker or Withdrawer)"),
+        ])
+        .function("authorize-checked-with-seed", &[
+            ("authority_seed", "authorize_checked_with_seed_args", PropertyType::Text,
+             "Seed the authority address is derived with"),
+            ("authority_owner", "authorize_checked_with_seed_args", PropertyType::Pubkey,
+             "Program owning the derived authority"),
+            ("stake_authorize", "authorize_checked_with_seed_args", PropertyType::Text,
+             "Authority changed (Staker or Withdrawer)"),
+        ])
+        .function("authorize-with-seed", &[
+            ("authority_seed", "authorize_with_seed_args", PropertyType::Text,
+             "Seed the authority address is derived with"),
+            ("authority_owner", "authorize_with_seed_args", PropertyType::Pubkey,
+             "Program owning the derived authority"),
+            ("stake_authorize", "authorize_checked_with_seed_args", PropertyType::Text,
+             "Authority changed (Staker or Withdrawer)"),
+            ("new_authorized_pubkey", "authorize_checked_with_seed_args", PropertyType::Pubkey,
+             "New authority"),
+        ])
+        .function("delegate-stake", &[])
+        .function("split", &[
+            ("lamports", "", PropertyType::U64, "Amount of lamports"),
+        ])
+        .function("merge", &[])
+        .function("withdraw", &[
+            ("lamports", "", PropertyType::U64, "Amount of lamports"),
+        ])
+        .function("deactivate", &[])
+        .function("set-lockup", &[
+            ("epoch", "lockup_args", PropertyType::U64, "Epoch the lockup ends at"),
+            ("unix_timestamp", "lockup_args", PropertyType::Timestamp, "Time the lockup ends at"),
+            ("custodian", "lockup_args", PropertyType::Pubkey,
+             "Custodian allowed to lift the lockup early"),
+        ])
+        .function("set-lockup-checked", &[
+            ("epoch", "lockup_checked_args", PropertyType::U64, "Epoch the lockup ends at"),
+            ("unix_timestamp", "lockup_checked_args", PropertyType::Timestamp,
+             "Time the lockup ends at"),
+        ])
+        .function("get-minimum-delegation", &[])
+        .function("deactivate-delinquent", &[])
+        .function("redelegate", &[])
+        .function("move-stake", &[
+            ("lamports", "", PropertyType::U64, "Amount of lamports"),
+        ])
+        .function("move-lamports", &[
+            ("lamports", "", PropertyType::U64, "Amount of lamports"),
+        ])
+}
+
 /// Extracts the contents of an instruction into small bits and pieces, or what we would call,
 /// instruction_properties.
 ///
 /// The function should return a list of instruction properties extracted from an instruction.
-pub async fn fragment_instruction(
+pub fn fragment_instruction(
     // The instruction
     instruction: Instruction,
 ) -> Option<InstructionSet> {
@@ -31,73 +116,84 @@ pub async fn fragment_instruction(
                     // )
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "initialize".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "initialize".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "staker".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "staker".into(),
                                 value: authorized.staker.to_string(),
-                                parent_key: "authorized".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "authorized".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "withdrawer".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "withdrawer".into(),
                                 value: authorized.withdrawer.to_string(),
-                                parent_key: "authorized".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "authorized".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "epoch".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "epoch".into(),
                                 value: lockup.epoch.to_string(),
-                                parent_key: "lockup".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "lockup".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "custodian".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "custodian".into(),
                                 value: lockup.custodian.to_string(),
-                                parent_key: "lockup".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "lockup".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "unix_timestamp".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "unix_timestamp".into(),
                                 value: lockup.unix_timestamp.to_string(),
-                                parent_key: "lockup".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Timestamp,
+                                parent_key: "lockup".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 StakeInstruction::InitializeChecked => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "initialize-checked".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "initialize-checked".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![],
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 StakeInstruction::Authorize(authorized_pubkey, stake_authorize) => {
@@ -134,108 +230,123 @@ pub async fn fragment_instruction(
                     // }
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "authorize".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "authorize".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "authorized_pubkey".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "authorized_pubkey".into(),
                                 value: authorized_pubkey.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "stake_authorize".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "stake_authorize".into(),
                                 value: match stake_authorize {
                                     StakeAuthorize::Staker => "staker".to_string(),
                                     StakeAuthorize::Withdrawer => "withdrawer".to_string()
                                 },
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 StakeInstruction::AuthorizeChecked(stake_authorize) => {
                     // stake_authorize
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "authorize-checked".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "authorize-checked".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "stake_authorize".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "stake_authorize".into(),
                                 value: match stake_authorize {
                                     StakeAuthorize::Staker => "staker".to_string(),
                                     StakeAuthorize::Withdrawer => "withdrawer".to_string()
                                 },
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 StakeInstruction::AuthorizeCheckedWithSeed(authorize_checked_with_seed_args) => {
                     // stake_authorize
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "authorize-checked-with-seed".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "authorize-checked-with-seed".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "authority_seed".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "authority_seed".into(),
                                 value: authorize_checked_with_seed_args.authority_seed.to_string(),
-                                parent_key: "authorize_checked_with_seed_args".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "authorize_checked_with_seed_args".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "authority_owner".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "authority_owner".into(),
                                 value: authorize_checked_with_seed_args.authority_owner.to_string(),
-                                parent_key: "authorize_checked_with_seed_args".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "authorize_checked_with_seed_args".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "stake_authorize".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "stake_authorize".into(),
                                 value: match authorize_checked_with_seed_args.stake_authorize {
                                     StakeAuthorize::Staker => "staker".to_string(),
                                     StakeAuthorize::Withdrawer => "withdrawer".to_string()
                                 },
-                                parent_key: "authorize_checked_with_seed_args".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "authorize_checked_with_seed_args".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 StakeInstruction::AuthorizeWithSeed(authorize_with_seed_args) => {
@@ -276,54 +387,61 @@ pub async fn fragment_instruction(
                     // }
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "authorize-with-seed".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "authorize-with-seed".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "authority_seed".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "authority_seed".into(),
                                 value: authorize_with_seed_args.authority_seed.to_string(),
-                                parent_key: "authorize_with_seed_args".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "authorize_with_seed_args".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "authority_owner".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "authority_owner".into(),
                                 value: authorize_with_seed_args.authority_owner.to_string(),
-                                parent_key: "authorize_with_seed_args".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "authorize_with_seed_args".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "stake_authorize".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "stake_authorize".into(),
                                 value: match authorize_with_seed_args.stake_authorize {
                                     StakeAuthorize::Staker => "staker".to_string(),
                                     StakeAuthorize::Withdrawer => "withdrawer".to_string()
                                 },
-                                parent_key: "authorize_checked_with_seed_args".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "authorize_checked_with_seed_args".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "new_authorized_pubkey".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "new_authorized_pubkey".into(),
                                 value: authorize_with_seed_args.new_authorized_pubkey.to_string(),
-                                parent_key: "authorize_checked_with_seed_args".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "authorize_checked_with_seed_args".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 StakeInstruction::DelegateStake => {
@@ -343,14 +461,17 @@ pub async fn fragment_instruction(
                     // )
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "delegate-stake".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "delegate-stake".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![],
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 StakeInstruction::Split(lamports) => {
@@ -358,24 +479,28 @@ pub async fn fragment_instruction(
                     // me.split(lamports, split_stake, &signers)
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "split".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "split".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "lamports".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "lamports".into(),
                                 value: lamports.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 StakeInstruction::Merge => {
@@ -394,14 +519,17 @@ pub async fn fragment_instruction(
                     // )
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "merge".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "merge".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![],
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 StakeInstruction::Withdraw(lamports) => {
@@ -420,24 +548,28 @@ pub async fn fragment_instruction(
                     // )
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "withdraw".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "withdraw".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "lamports".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "lamports".into(),
                                 value: lamports.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 StakeInstruction::Deactivate => {
@@ -447,14 +579,17 @@ pub async fn fragment_instruction(
                     // )
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "deactivate".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "deactivate".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![],
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 StakeInstruction::SetLockup(lockup_args) => {
@@ -468,101 +603,115 @@ pub async fn fragment_instruction(
                     // me.set_lockup(&lockup, &signers, clock.as_ref())
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "set-lockup".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "set-lockup".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "epoch".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "epoch".into(),
                                 value: if let Some(epoch) = lockup_args.epoch {
                                     epoch.to_string()
                                 } else {
                                     "".to_string()
                                 },
-                                parent_key: "lockup_args".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "lockup_args".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "unix_timestamp".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "unix_timestamp".into(),
                                 value: if let Some(unix_timestamp) = lockup_args.unix_timestamp {
                                     unix_timestamp.to_string()
                                 } else {
                                     "".to_string()
                                 },
-                                parent_key: "lockup_args".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Timestamp,
+                                parent_key: "lockup_args".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "custodian".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "custodian".into(),
                                 value: if let Some(custodian) = lockup_args.custodian {
                                     custodian.to_string()
                                 } else {
                                     "".to_string()
                                 },
-                                parent_key: "lockup_args".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "lockup_args".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 StakeInstruction::SetLockupChecked(lockup_checked_args) => {
                     // lockup_checked
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "set-lockup-checked".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "set-lockup-checked".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "epoch".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "epoch".into(),
                                 value: if let Some(epoch) = lockup_checked_args.epoch {
                                     epoch.to_string()
                                 } else {
                                     "".to_string()
                                 },
-                                parent_key: "lockup_checked_args".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "lockup_checked_args".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "unix_timestamp".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "unix_timestamp".into(),
                                 value: if let Some(unix_timestamp) =
                                 lockup_checked_args.unix_timestamp {
                                     unix_timestamp.to_string()
                                 } else {
                                     "".to_string()
                                 },
-                                parent_key: "lockup_checked_args".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Timestamp,
+                                parent_key: "lockup_checked_args".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
             }
         }
         Err(err) => {
+            if let Some(instruction_set) = newer_instruction(&instruction) {
+                return Some(instruction_set);
+            }
             let err_msg = match err {
                 InstructionError::GenericError => "GenericError".to_string(),
                 InstructionError::InvalidArgument => "InvalidArgument".to_string(),
@@ -622,4 +771,123 @@ pub async fn fragment_instruction(
             None
         }
     }
-}
\ No newline at end of file
+}
+
+/// Decodes the instructions of [`NEWER_INSTRUCTIONS`].
+fn newer_instruction(instruction: &Instruction) -> Option<InstructionSet> {
+    let tag = u32::from_le_bytes(instruction.data.get(..4)?.try_into().ok()?);
+    let (_, function_name, with_lamports) = NEWER_INSTRUCTIONS.iter()
+        .find(|(newer_tag, _, _)| *newer_tag == tag)?;
+    let lamports = if *with_lamports {
+        Some(u64::from_le_bytes(instruction.data.get(4..12)?.try_into().ok()?))
+    } else {
+        None
+    };
+
+    Some(InstructionSet {
+        function: InstructionFunction {
+            tx_instruction_id: instruction.tx_instruction_id,
+            transaction_hash: instruction.transaction_hash.clone(),
+            parent_index: instruction.parent_index,
+            program: instruction.program,
+            function_name: (*function_name).into(),
+            timestamp: instruction.timestamp,
+            network: instruction.network,
+            decoder_version: 0,
+        },
+        properties: lamports.into_iter()
+            .map(|lamports| InstructionProperty {
+                tx_instruction_id: instruction.tx_instruction_id,
+                transaction_hash: instruction.transaction_hash.clone(),
+                parent_index: instruction.parent_index,
+                key: "lamports".into(),
+                value: lamports.to_string(),
+                value_type: PropertyType::U64,
+                parent_key: "".into(),
+                timestamp: instruction.timestamp,
+            })
+            .collect(),
+        raw_data: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_program::stake::state::{Authorized, Lockup};
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(data: Vec<u8>) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, data))
+    }
+
+    fn decode_stake(instruction: StakeInstruction) -> InstructionSet {
+        decode(bincode::serialize(&instruction).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn delegations_and_withdrawals_decode() {
+        for (instruction, function_name) in &[
+            (StakeInstruction::DelegateStake, "delegate-stake"),
+            (StakeInstruction::Deactivate, "deactivate"),
+            (StakeInstruction::Merge, "merge"),
+        ] {
+            let instruction_set = decode_stake(instruction.clone());
+            assert_eq!(instruction_set.function.function_name.as_str(), *function_name);
+            assert!(instruction_set.properties.is_empty());
+        }
+
+        let split = decode_stake(StakeInstruction::Split(1_000));
+        assert_eq!(split.function.function_name.as_str(), "split");
+        assert_eq!(properties(&split), vec![("lamports", "1000".to_string())]);
+        let withdraw = decode_stake(StakeInstruction::Withdraw(7));
+        assert_eq!(properties(&withdraw), vec![("lamports", "7".to_string())]);
+    }
+
+    #[test]
+    fn authorities_and_lockups_decode() {
+        let (staker, withdrawer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let authorize = decode_stake(StakeInstruction::Authorize(staker, StakeAuthorize::Staker));
+        assert_eq!(properties(&authorize), vec![
+            ("authorized_pubkey", staker.to_string()),
+            ("stake_authorize", "staker".to_string()),
+        ]);
+
+        let initialize = decode_stake(StakeInstruction::Initialize(
+            Authorized { staker, withdrawer },
+            Lockup { unix_timestamp: 0, epoch: 10, custodian: withdrawer },
+        ));
+        assert_eq!(initialize.properties.len(), 5);
+
+        // The parts of the lockup left unset are empty.
+        let set_lockup = decode_stake(StakeInstruction::SetLockup(
+            solana_program::stake::instruction::LockupArgs {
+                unix_timestamp: None,
+                epoch: Some(12),
+                custodian: None,
+            },
+        ));
+        assert_eq!(properties(&set_lockup), vec![
+            ("epoch", "12".to_string()),
+            ("unix_timestamp", "".to_string()),
+            ("custodian", "".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn instructions_newer_than_the_sdk_decode() {
+        let mut data = 16u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&5_000u64.to_le_bytes());
+        let move_stake = decode(data).unwrap();
+        assert_eq!(move_stake.function.function_name.as_str(), "move-stake");
+        assert_eq!(properties(&move_stake), vec![("lamports", "5000".to_string())]);
+
+        let delinquent = decode(14u32.to_le_bytes().to_vec()).unwrap();
+        assert_eq!(delinquent.function.function_name.as_str(), "deactivate-delinquent");
+        // A move of lamports cut short, and an unknown instruction.
+        assert!(decode(vec![17, 0, 0, 0, 1]).is_none());
+        assert!(decode(18u32.to_le_bytes().to_vec()).is_none());
+    }
+}
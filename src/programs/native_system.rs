@@ -1,20 +1,84 @@
-use bincode::{deserialize};
+use std::convert::{TryFrom, TryInto};
+
+use smallvec::smallvec;
 use solana_program::system_instruction::SystemInstruction;
+use solana_sdk::program_utils::limited_deserialize;
+use solana_sdk::pubkey::Pubkey;
 use tracing::error;
 
-use crate::{InstructionProperty, Instruction, InstructionSet, InstructionFunction};
+use crate::{InstructionProperty, Instruction, InstructionSet, InstructionFunction, PropertyType};
+use crate::fast::{FastInstruction, PropertyValueRef};
+use crate::schema::ProgramSchema;
 
 pub const PROGRAM_ADDRESS: &str = "11111111111111111111111111111111";
 
+// The tag of `UpgradeNonceAccount`, which came after the solana version this crate is on: its
+// `SystemInstruction` doesn't deserialize it.
+const UPGRADE_NONCE_ACCOUNT: [u8; 4] = [12, 0, 0, 0];
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    ProgramSchema::new("system")
+        .function("create-account", &[
+            ("lamports", "", PropertyType::U64, "Amount of lamports"),
+            ("owner", "", PropertyType::Pubkey, "Program owning the account"),
+            ("space", "", PropertyType::U64, "Size of the account data, in bytes"),
+        ])
+        .function("assign", &[
+            ("owner", "", PropertyType::Pubkey, "Program owning the account"),
+        ])
+        .function("transfer", &[
+            ("lamports", "", PropertyType::U64, "Amount of lamports"),
+        ])
+        .function("create-account-with-seed", &[
+            ("base", "", PropertyType::Pubkey, "Base address the account address is derived from"),
+            ("seed", "", PropertyType::Text, "Seed the account address is derived with"),
+            ("lamports", "", PropertyType::U64, "Amount of lamports"),
+            ("space", "", PropertyType::U64, "Size of the account data, in bytes"),
+            ("owner", "", PropertyType::Pubkey, "Program owning the account"),
+        ])
+        .function("advance-nonce-account", &[])
+        .function("withdraw-nonce-account", &[
+            ("lamports", "", PropertyType::U64, "Amount of lamports"),
+        ])
+        .function("initialize-nonce-account", &[
+            ("authority", "", PropertyType::Pubkey, "Authority of the nonce account"),
+        ])
+        .function("authorize-nonce-account", &[
+            ("authority", "", PropertyType::Pubkey, "Authority of the nonce account"),
+        ])
+        .function("allocate", &[
+            ("space", "", PropertyType::U64, "Size of the account data, in bytes"),
+        ])
+        .function("allocate-with-seed", &[
+            ("base", "", PropertyType::Pubkey, "Base address the account address is derived from"),
+            ("seed", "", PropertyType::Text, "Seed the account address is derived with"),
+            ("space", "", PropertyType::U64, "Size of the account data, in bytes"),
+            ("owner", "", PropertyType::Pubkey, "Program owning the account"),
+        ])
+        .function("assign-with-seed", &[
+            ("base", "", PropertyType::Pubkey, "Base address the account address is derived from"),
+            ("seed", "", PropertyType::Text, "Seed the account address is derived with"),
+            ("owner", "", PropertyType::Pubkey, "Program owning the account"),
+        ])
+        .function("transfer-with-seed", &[
+            ("lamports", "", PropertyType::U64, "Amount of lamports"),
+            ("from_seed", "", PropertyType::Text,
+             "Seed the source account address is derived with"),
+            ("from_owner", "", PropertyType::Pubkey, "Program owning the derived source account"),
+        ])
+        .function("upgrade-nonce-account", &[])
+}
+
 /// Extracts the contents of an instruction into small bits and pieces, or what we would call,
 /// instruction_properties.
 ///
 /// The function should return a list of instruction properties extracted from an instruction.
-pub async fn fragment_instruction(
+pub fn fragment_instruction(
     // The instruction
     instruction: Instruction
 ) -> Option<InstructionSet> {
-    let sdr = deserialize::<SystemInstruction>(
+    let sdr = limited_deserialize::<SystemInstruction>(
         &instruction.data.as_slice());
 
     return match sdr {
@@ -39,42 +103,48 @@ pub async fn fragment_instruction(
                     // })
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "create-account".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "create-account".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "lamports".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "lamports".into(),
                                 value: lamports.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "owner".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "owner".into(),
                                 value: owner.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "space".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "space".into(),
                                 value: space.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 SystemInstruction::Assign { owner } => {
@@ -88,24 +158,28 @@ pub async fn fragment_instruction(
                     // })
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "assign".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "assign".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "owner".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "owner".into(),
                                 value: owner.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 SystemInstruction::Transfer { lamports } => {
@@ -121,24 +195,28 @@ pub async fn fragment_instruction(
                     // check_num_system_accounts(&instruction.accounts, 2)?;
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "transfer".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "transfer".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "lamports".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "lamports".into(),
                                 value: lamports.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 SystemInstruction::CreateAccountWithSeed {
@@ -163,60 +241,68 @@ pub async fn fragment_instruction(
                     // })
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "create-account-with-seed".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "create-account-with-seed".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "base".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "base".into(),
                                 value: base.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "seed".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "seed".into(),
                                 value: seed.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "lamports".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "lamports".into(),
                                 value: lamports.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "space".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "space".into(),
                                 value: space.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "owner".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "owner".into(),
                                 value: owner.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 SystemInstruction::AdvanceNonceAccount => {
@@ -231,14 +317,17 @@ pub async fn fragment_instruction(
                     // })
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "advance-nonce-account".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "advance-nonce-account".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![],
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 SystemInstruction::WithdrawNonceAccount(lamports) => {
@@ -256,24 +345,28 @@ pub async fn fragment_instruction(
                     // })
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "withdraw-nonce-account".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "withdraw-nonce-account".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "lamports".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "lamports".into(),
                                 value: lamports.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 SystemInstruction::InitializeNonceAccount(authority) => {
@@ -289,24 +382,28 @@ pub async fn fragment_instruction(
                     // })
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "initialize-nonce-account".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "initialize-nonce-account".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "authority".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "authority".into(),
                                 value: authority.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 SystemInstruction::AuthorizeNonceAccount(authority) => {
@@ -321,24 +418,28 @@ pub async fn fragment_instruction(
                     // })
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "authorize-nonce-account".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "authorize-nonce-account".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "authority".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "authority".into(),
                                 value: authority.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 SystemInstruction::Allocate { space } => {
@@ -352,24 +453,28 @@ pub async fn fragment_instruction(
                     // })
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "allocate".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "allocate".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "space".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "space".into(),
                                 value: space.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 SystemInstruction::AllocateWithSeed {
@@ -391,51 +496,58 @@ pub async fn fragment_instruction(
                     // })
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "allocate-with-seed".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "allocate-with-seed".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "base".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "base".into(),
                                 value: base.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "seed".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "seed".into(),
                                 value: seed.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "space".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "space".into(),
                                 value: space.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "owner".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "owner".into(),
                                 value: owner.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 SystemInstruction::AssignWithSeed { base, seed, owner } => {
@@ -451,42 +563,48 @@ pub async fn fragment_instruction(
                     // })
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "assign-with-seed".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "assign-with-seed".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "base".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "base".into(),
                                 value: base.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "seed".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "seed".into(),
                                 value: seed.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "owner".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "owner".into(),
                                 value: owner.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 SystemInstruction::TransferWithSeed {
@@ -508,46 +626,68 @@ pub async fn fragment_instruction(
                     // })
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "transfer-with-seed".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "transfer-with-seed".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "lamports".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "lamports".into(),
                                 value: lamports.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "from_seed".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "from_seed".into(),
                                 value: from_seed.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "from_owner".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "from_owner".into(),
                                 value: from_owner.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
             }
         }
+        Err(_) if instruction.data.starts_with(&UPGRADE_NONCE_ACCOUNT) => {
+            Some(InstructionSet {
+                function: InstructionFunction {
+                    tx_instruction_id: instruction.tx_instruction_id,
+                    transaction_hash: instruction.transaction_hash.clone(),
+                    parent_index: instruction.parent_index,
+                    program: instruction.program,
+                    function_name: "upgrade-nonce-account".into(),
+                    timestamp: instruction.timestamp,
+                    network: instruction.network,
+                    decoder_version: 0,
+                },
+                properties: smallvec![],
+                raw_data: None,
+            })
+        }
         Err(_) => { // Error provided has no utility at the moment.
             error!("{}", "[spi-wrapper/programs/native_system] Error deserializing this system \
         instruction!".to_string());
@@ -555,4 +695,158 @@ pub async fn fragment_instruction(
             None
         }
     }
-}
\ No newline at end of file
+}
+
+/// Decodes the data of an instruction into what [`fragment_instruction`] emits for it, without
+/// allocating: the bincode encoding of system instructions is read field by field, seeds borrowed
+/// from the data.
+pub fn decode_fast(data: &[u8]) -> Option<FastInstruction<'_>> {
+    use PropertyValueRef::{Pubkey as Address, Text, U64};
+
+    let mut fields = FieldReader { data };
+    let instruction = match fields.u32()? {
+        0 => {
+            let (lamports, space, owner) = (fields.u64()?, fields.u64()?, fields.pubkey()?);
+            FastInstruction::new("create-account")
+                .with("lamports", U64(lamports))
+                .with("owner", Address(owner))
+                .with("space", U64(space))
+        }
+        1 => FastInstruction::new("assign").with("owner", Address(fields.pubkey()?)),
+        2 => FastInstruction::new("transfer").with("lamports", U64(fields.u64()?)),
+        3 => FastInstruction::new("create-account-with-seed")
+            .with("base", Address(fields.pubkey()?))
+            .with("seed", Text(fields.string()?))
+            .with("lamports", U64(fields.u64()?))
+            .with("space", U64(fields.u64()?))
+            .with("owner", Address(fields.pubkey()?)),
+        4 => FastInstruction::new("advance-nonce-account"),
+        5 => FastInstruction::new("withdraw-nonce-account").with("lamports", U64(fields.u64()?)),
+        6 => FastInstruction::new("initialize-nonce-account")
+            .with("authority", Address(fields.pubkey()?)),
+        7 => FastInstruction::new("authorize-nonce-account")
+            .with("authority", Address(fields.pubkey()?)),
+        8 => FastInstruction::new("allocate").with("space", U64(fields.u64()?)),
+        9 => FastInstruction::new("allocate-with-seed")
+            .with("base", Address(fields.pubkey()?))
+            .with("seed", Text(fields.string()?))
+            .with("space", U64(fields.u64()?))
+            .with("owner", Address(fields.pubkey()?)),
+        10 => FastInstruction::new("assign-with-seed")
+            .with("base", Address(fields.pubkey()?))
+            .with("seed", Text(fields.string()?))
+            .with("owner", Address(fields.pubkey()?)),
+        11 => FastInstruction::new("transfer-with-seed")
+            .with("lamports", U64(fields.u64()?))
+            .with("from_seed", Text(fields.string()?))
+            .with("from_owner", Address(fields.pubkey()?)),
+        12 => FastInstruction::new("upgrade-nonce-account"),
+        _ => return None,
+    };
+
+    Some(instruction)
+}
+
+/// Reads bincode encoded fields off the data of an instruction.
+struct FieldReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> FieldReader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn pubkey(&mut self) -> Option<Pubkey> {
+        Some(Pubkey::new_from_array(self.bytes(32)?.try_into().ok()?))
+    }
+
+    /// A string, prefixed with its length as a u64.
+    fn string(&mut self) -> Option<&'a str> {
+        let len = usize::try_from(self.u64()?).ok()?;
+
+        std::str::from_utf8(self.bytes(len)?).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::system_instruction;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(data: Vec<u8>) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, data))
+    }
+
+    #[test]
+    fn transfers_and_account_creations_decode() {
+        let (from, to, owner) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+
+        let transfer = decode(system_instruction::transfer(&from, &to, 5_000).data).unwrap();
+        assert_eq!(transfer.function.function_name.as_str(), "transfer");
+        assert_eq!(properties(&transfer), vec![("lamports", "5000".to_string())]);
+
+        let create = system_instruction::create_account(&from, &to, 1_000, 165, &owner).data;
+        let create = decode(create).unwrap();
+        assert_eq!(create.function.function_name.as_str(), "create-account");
+        assert_eq!(properties(&create), vec![
+            ("lamports", "1000".to_string()),
+            ("owner", owner.to_string()),
+            ("space", "165".to_string()),
+        ]);
+
+        let with_seed = system_instruction::create_account_with_seed(
+            &from, &to, &from, "vault", 1_000, 165, &owner).data;
+        assert_eq!(properties(&decode(with_seed).unwrap())[..2],
+                   [("base", from.to_string()), ("seed", "vault".to_string())]);
+        let allocate = decode(system_instruction::allocate(&to, 64).data).unwrap();
+        assert_eq!(properties(&allocate), vec![("space", "64".to_string())]);
+        let assign = decode(system_instruction::assign(&to, &owner).data).unwrap();
+        assert_eq!(properties(&assign), vec![("owner", owner.to_string())]);
+    }
+
+    #[test]
+    fn nonce_instructions_decode() {
+        let (nonce, authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let advance = system_instruction::advance_nonce_account(&nonce, &authority).data;
+        assert_eq!(decode(advance).unwrap().function.function_name.as_str(),
+                   "advance-nonce-account");
+        let withdraw = system_instruction::withdraw_nonce_account(
+            &nonce, &authority, &authority, 42).data;
+        assert_eq!(properties(&decode(withdraw).unwrap()), vec![("lamports", "42".to_string())]);
+        let authorize = system_instruction::authorize_nonce_account(
+            &nonce, &authority, &Pubkey::new_unique()).data;
+        assert_eq!(decode(authorize).unwrap().function.function_name.as_str(),
+                   "authorize-nonce-account");
+
+        // Not known to the solana version this crate is on.
+        let upgrade = decode(UPGRADE_NONCE_ACCOUNT.to_vec()).unwrap();
+        assert_eq!(upgrade.function.function_name.as_str(), "upgrade-nonce-account");
+        assert_eq!(decode_fast(&UPGRADE_NONCE_ACCOUNT).unwrap().function_name(),
+                   "upgrade-nonce-account");
+    }
+
+    #[test]
+    fn unknown_instructions_are_skipped() {
+        assert!(decode(vec![13, 0, 0, 0]).is_none());
+        // A transfer cut short.
+        assert!(decode(vec![2, 0, 0, 0, 1]).is_none());
+    }
+}
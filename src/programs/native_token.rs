@@ -1,17 +1,78 @@
+use smallvec::smallvec;
 use solana_program::program_error::ProgramError;
 use spl_token::instruction::TokenInstruction;
 use spl_token::solana_program::program_option::COption;
 use tracing::error;
 
-use crate::{Instruction, InstructionFunction, InstructionProperty, InstructionSet};
+use crate::{Instruction, InstructionFunction, InstructionProperty, InstructionSet, PropertyType};
+use crate::fast::{FastInstruction, PropertyValueRef};
+use crate::schema::ProgramSchema;
 
 pub const PROGRAM_ADDRESS: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
 
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    ProgramSchema::new("token")
+        .function("initialize-mint", &[
+            ("decimals", "", PropertyType::U64, "Decimals of the mint"),
+            ("mint_authority", "", PropertyType::Pubkey, "Authority allowed to mint tokens"),
+            ("freeze_authority", "", PropertyType::Pubkey,
+             "Authority allowed to freeze token accounts"),
+        ])
+        .function("initialize-account", &[])
+        .function("initialize-account-2", &[
+            ("owner", "", PropertyType::Pubkey, "Owner of the token account"),
+        ])
+        .function("initialize-multisig", &[
+            ("m", "", PropertyType::U64, "Number of signers required"),
+        ])
+        .function("transfer", &[
+            ("amount", "", PropertyType::U64, "Amount of tokens, in base units"),
+        ])
+        .function("approve", &[
+            ("amount", "", PropertyType::U64, "Amount of tokens, in base units"),
+        ])
+        .function("revoke", &[])
+        .function("set-authority", &[
+            ("authority_type", "", PropertyType::U64,
+             "Kind of authority changed (0: mint tokens, 1: freeze account, 2: account owner, 3: \
+             close account)"),
+            ("new_authority", "", PropertyType::Pubkey,
+             "New authority, empty when the authority is removed"),
+        ])
+        .function("mint-to", &[
+            ("amount", "", PropertyType::U64, "Amount of tokens, in base units"),
+        ])
+        .function("burn", &[
+            ("amount", "", PropertyType::U64, "Amount of tokens, in base units"),
+        ])
+        .function("close-account", &[])
+        .function("freeze-account", &[])
+        .function("thaw-account", &[])
+        .function("transfer-checked", &[
+            ("amount", "", PropertyType::U64, "Amount of tokens, in base units"),
+            ("decimals", "", PropertyType::U64, "Decimals of the mint"),
+        ])
+        .function("approve-checked", &[
+            ("amount", "", PropertyType::U64, "Amount of tokens, in base units"),
+            ("decimals", "", PropertyType::U64, "Decimals of the mint"),
+        ])
+        .function("mint-to-checked", &[
+            ("amount", "", PropertyType::U64, "Amount of tokens, in base units"),
+            ("decimals", "", PropertyType::U64, "Decimals of the mint"),
+        ])
+        .function("burn-checked", &[
+            ("amount", "", PropertyType::U64, "Amount of tokens, in base units"),
+            ("decimals", "", PropertyType::U64, "Decimals of the mint"),
+        ])
+        .function("sync-native", &[])
+}
+
 /// Extracts the contents of an instruction into small bits and pieces, or what we would call,
 /// instruction_properties.
 ///
 /// The function should return a list of instruction properties extracted from an instruction.
-pub async fn fragment_instruction(
+pub fn fragment_instruction(
     // The instruction
     instruction: Instruction,
 ) -> Option<InstructionSet> {
@@ -37,46 +98,52 @@ pub async fn fragment_instruction(
                     // )
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "initialize-mint".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "initialize-mint".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "decimals".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "decimals".into(),
                                 value: decimals.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "mint_authority".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "mint_authority".into(),
                                 value: mint_authority.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "freeze_authority".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "freeze_authority".into(),
                                 value: if let COption::Some(fa) = freeze_authority {
                                     fa.to_string()
                                 } else {
                                     "".to_string()
                                 },
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::InitializeAccount => {
@@ -84,14 +151,17 @@ pub async fn fragment_instruction(
                     // Self::process_initialize_account(accounts)
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "initialize-account".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "initialize-account".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![]
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::InitializeAccount2 { owner } => {
@@ -99,24 +169,28 @@ pub async fn fragment_instruction(
                     // Self::process_initialize_account2(accounts, owner)
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "initialize-account-2".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "initialize-account-2".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "owner".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "owner".into(),
                                 value: owner.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::InitializeMultisig { m } => {
@@ -124,24 +198,28 @@ pub async fn fragment_instruction(
                     // Self::process_initialize_multisig(accounts, m)
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "initialize-multisig".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "initialize-multisig".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "m".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "m".into(),
                                 value: m.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::Transfer { amount } => {
@@ -149,24 +227,28 @@ pub async fn fragment_instruction(
                     // Self::process_transfer(program_id, accounts, amount, None)
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "transfer".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "transfer".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "amount".into(),
                                 value: amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::Approve { amount } => {
@@ -174,24 +256,28 @@ pub async fn fragment_instruction(
                     // Self::process_approve(program_id, accounts, amount, None)
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "approve".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "approve".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "amount".into(),
                                 value: amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::Revoke => {
@@ -199,14 +285,17 @@ pub async fn fragment_instruction(
                     // Self::process_revoke(program_id, accounts)
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "revoke".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "revoke".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![]
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::SetAuthority {
@@ -222,37 +311,42 @@ pub async fn fragment_instruction(
                     // )
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "set-authority".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "set-authority".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "authority_type".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "authority_type".into(),
                                 value: (authority_type as u8).to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "new_authority".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "new_authority".into(),
                                 value: if let COption::Some(na) = new_authority {
                                     na.to_string()
                                 } else {
                                     "".to_string()
                                 },
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::MintTo { amount } => {
@@ -260,24 +354,28 @@ pub async fn fragment_instruction(
                     // Self::process_mint_to(program_id, accounts, amount, None)
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "mint-to".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "mint-to".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "amount".into(),
                                 value: amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::Burn { amount } => {
@@ -285,24 +383,28 @@ pub async fn fragment_instruction(
                     // Self::process_burn(program_id, accounts, amount, None)
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "burn".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "burn".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "amount".into(),
                                 value: amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::CloseAccount => {
@@ -310,14 +412,17 @@ pub async fn fragment_instruction(
                     // Self::process_close_account(program_id, accounts)
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "close-account".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "close-account".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![]
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::FreezeAccount => {
@@ -325,14 +430,17 @@ pub async fn fragment_instruction(
                     // Self::process_toggle_freeze_account(program_id, accounts, true)
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "freeze-account".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "freeze-account".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![]
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::ThawAccount => {
@@ -340,14 +448,17 @@ pub async fn fragment_instruction(
                     // Self::process_toggle_freeze_account(program_id, accounts, false)
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "thaw-account".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "thaw-account".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![]
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::TransferChecked { amount, decimals } => {
@@ -355,33 +466,38 @@ pub async fn fragment_instruction(
                     // Self::process_transfer(program_id, accounts, amount, Some(decimals))
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "transfer-checked".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "transfer-checked".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "amount".into(),
                                 value: amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "decimals".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "decimals".into(),
                                 value: decimals.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::ApproveChecked { amount, decimals } => {
@@ -389,33 +505,38 @@ pub async fn fragment_instruction(
                     // Self::process_approve(program_id, accounts, amount, Some(decimals))
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "approve-checked".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "approve-checked".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "amount".into(),
                                 value: amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "decimals".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "decimals".into(),
                                 value: decimals.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::MintToChecked { amount, decimals } => {
@@ -423,33 +544,38 @@ pub async fn fragment_instruction(
                     // Self::process_mint_to(program_id, accounts, amount, Some(decimals))
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "mint-to-checked".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "mint-to-checked".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "amount".into(),
                                 value: amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "decimals".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "decimals".into(),
                                 value: decimals.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::BurnChecked { amount, decimals } => {
@@ -457,47 +583,55 @@ pub async fn fragment_instruction(
                     // Self::process_burn(program_id, accounts, amount, Some(decimals))
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "burn-checked".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "burn-checked".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "amount".into(),
                                 value: amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "decimals".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "decimals".into(),
                                 value: decimals.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 TokenInstruction::SyncNative => {
                     // msg!("Instruction: SyncNative");
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "sync-native".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "sync-native".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![]
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
             }
@@ -528,4 +662,152 @@ pub async fn fragment_instruction(
             None
         }
     }
-}
\ No newline at end of file
+}
+
+/// Decodes the data of an instruction into what [`fragment_instruction`] emits for it, without
+/// allocating.
+pub fn decode_fast(data: &[u8]) -> Option<FastInstruction<'_>> {
+    use PropertyValueRef::{Pubkey as Address, U64};
+
+    let optional = |pubkey: COption<_>| match pubkey {
+        COption::Some(pubkey) => Address(pubkey),
+        COption::None => PropertyValueRef::NoPubkey,
+    };
+    let checked = |function_name, amount, decimals: u8| {
+        FastInstruction::new(function_name)
+            .with("amount", U64(amount))
+            .with("decimals", U64(decimals.into()))
+    };
+    let instruction = match TokenInstruction::unpack(data).ok()? {
+        TokenInstruction::InitializeMint { decimals, mint_authority, freeze_authority } => {
+            FastInstruction::new("initialize-mint")
+                .with("decimals", U64(decimals.into()))
+                .with("mint_authority", Address(mint_authority))
+                .with("freeze_authority", optional(freeze_authority))
+        }
+        TokenInstruction::InitializeAccount => FastInstruction::new("initialize-account"),
+        TokenInstruction::InitializeAccount2 { owner } => {
+            FastInstruction::new("initialize-account-2").with("owner", Address(owner))
+        }
+        TokenInstruction::InitializeMultisig { m } => {
+            FastInstruction::new("initialize-multisig").with("m", U64(m.into()))
+        }
+        TokenInstruction::Transfer { amount } => {
+            FastInstruction::new("transfer").with("amount", U64(amount))
+        }
+        TokenInstruction::Approve { amount } => {
+            FastInstruction::new("approve").with("amount", U64(amount))
+        }
+        TokenInstruction::Revoke => FastInstruction::new("revoke"),
+        TokenInstruction::SetAuthority { authority_type, new_authority } => {
+            FastInstruction::new("set-authority")
+                .with("authority_type", U64(authority_type as u64))
+                .with("new_authority", optional(new_authority))
+        }
+        TokenInstruction::MintTo { amount } => {
+            FastInstruction::new("mint-to").with("amount", U64(amount))
+        }
+        TokenInstruction::Burn { amount } => {
+            FastInstruction::new("burn").with("amount", U64(amount))
+        }
+        TokenInstruction::CloseAccount => FastInstruction::new("close-account"),
+        TokenInstruction::FreezeAccount => FastInstruction::new("freeze-account"),
+        TokenInstruction::ThawAccount => FastInstruction::new("thaw-account"),
+        TokenInstruction::TransferChecked { amount, decimals } => {
+            checked("transfer-checked", amount, decimals)
+        }
+        TokenInstruction::ApproveChecked { amount, decimals } => {
+            checked("approve-checked", amount, decimals)
+        }
+        TokenInstruction::MintToChecked { amount, decimals } => {
+            checked("mint-to-checked", amount, decimals)
+        }
+        TokenInstruction::BurnChecked { amount, decimals } => {
+            checked("burn-checked", amount, decimals)
+        }
+        TokenInstruction::SyncNative => FastInstruction::new("sync-native"),
+    };
+
+    Some(instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(token_instruction: TokenInstruction) -> InstructionSet {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, token_instruction.pack())).unwrap()
+    }
+
+    #[test]
+    fn token_movements_decode_with_their_amounts() {
+        let transfer = decode(TokenInstruction::Transfer { amount: 42 });
+        assert_eq!(transfer.function.function_name.as_str(), "transfer");
+        assert_eq!(properties(&transfer), vec![("amount", "42".to_string())]);
+
+        let checked = decode(TokenInstruction::TransferChecked { amount: 1_500, decimals: 6 });
+        assert_eq!(checked.function.function_name.as_str(), "transfer-checked");
+        assert_eq!(properties(&checked),
+                   vec![("amount", "1500".to_string()), ("decimals", "6".to_string())]);
+
+        for (instruction, function_name) in &[
+            (TokenInstruction::MintTo { amount: 7 }, "mint-to"),
+            (TokenInstruction::Burn { amount: 7 }, "burn"),
+            (TokenInstruction::Approve { amount: 7 }, "approve"),
+        ] {
+            let instruction_set = decode(instruction.clone());
+            assert_eq!(instruction_set.function.function_name.as_str(), *function_name);
+            assert_eq!(properties(&instruction_set), vec![("amount", "7".to_string())]);
+        }
+        for (instruction, function_name) in &[
+            (TokenInstruction::Revoke, "revoke"),
+            (TokenInstruction::InitializeAccount, "initialize-account"),
+            (TokenInstruction::CloseAccount, "close-account"),
+        ] {
+            let instruction_set = decode(instruction.clone());
+            assert_eq!(instruction_set.function.function_name.as_str(), *function_name);
+            assert!(instruction_set.properties.is_empty());
+        }
+    }
+
+    #[test]
+    fn unset_authorities_decode_as_empty_pubkeys() {
+        let mint_authority = Pubkey::new_unique();
+        let mint = decode(TokenInstruction::InitializeMint {
+            decimals: 9,
+            mint_authority,
+            freeze_authority: COption::None,
+        });
+        assert_eq!(properties(&mint), vec![
+            ("decimals", "9".to_string()),
+            ("mint_authority", mint_authority.to_string()),
+            ("freeze_authority", "".to_string()),
+        ]);
+        assert!(mint.properties.iter().skip(1).all(|p| p.value_type == PropertyType::Pubkey));
+    }
+
+    #[test]
+    fn decoded_properties_are_described() {
+        let schema = describe();
+        let owner = Pubkey::new_unique();
+        for instruction in &[
+            TokenInstruction::InitializeAccount2 { owner },
+            TokenInstruction::MintToChecked { amount: 1, decimals: 0 },
+            TokenInstruction::SyncNative,
+        ] {
+            let instruction_set = decode(instruction.clone());
+            let function = schema.get(instruction_set.function.function_name.as_str()).unwrap();
+            for property in &instruction_set.properties {
+                assert!(function.keys.iter().any(|key| key.key == property.key.as_str()));
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_instructions_are_skipped() {
+        assert!(fragment_instruction(instruction(PROGRAM_ADDRESS, vec![200, 1, 2])).is_none());
+    }
+}
@@ -0,0 +1,771 @@
+//! The Token-2022 program (SPL token extensions), a superset of the token program (see
+//! `native_token`) whose mints and accounts carry extensions: transfer fees, confidential
+//! transfers, interest, required memos and so on. The crate unpacking its instructions doesn't
+//! build against the solana version this crate is on, so they're read here.
+//!
+//! The instructions it shares with the token program decode to the same functions and properties.
+//! Those of the extensions (the tag of the extension, then that of the instruction) decode to
+//! functions of their own, their properties nested under the name of the extension, e.g.
+//! `transfer_fee_basis_points` under `transfer_fee`. Confidential amounts and their proofs are
+//! encrypted: only what's in the clear is emitted. The confidential mint and burn extension, and
+//! the token metadata and token group interfaces the program also takes, aren't decoded.
+
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+
+// The parent keys of the properties of the extensions.
+const TRANSFER_FEE: &str = "transfer_fee";
+const CONFIDENTIAL_TRANSFER: &str = "confidential_transfer";
+const CONFIDENTIAL_TRANSFER_FEE: &str = "confidential_transfer_fee";
+const DEFAULT_ACCOUNT_STATE: &str = "default_account_state";
+const MINT_CLOSE_AUTHORITY: &str = "mint_close_authority";
+const INTEREST_BEARING_MINT: &str = "interest_bearing_mint";
+const PERMANENT_DELEGATE: &str = "permanent_delegate";
+const TRANSFER_HOOK: &str = "transfer_hook";
+const METADATA_POINTER: &str = "metadata_pointer";
+const GROUP_POINTER: &str = "group_pointer";
+const GROUP_MEMBER_POINTER: &str = "group_member_pointer";
+const SCALED_UI_AMOUNT: &str = "scaled_ui_amount";
+const PAUSABLE: &str = "pausable";
+
+// The names of the variants of the enums of the arguments, by discriminant.
+const EXTENSION_TYPES: &[&str] = &[
+    "uninitialized", "transfer-fee-config", "transfer-fee-amount", "mint-close-authority",
+    "confidential-transfer-mint", "confidential-transfer-account", "default-account-state",
+    "immutable-owner", "memo-transfer", "non-transferable", "interest-bearing-config",
+    "cpi-guard", "permanent-delegate", "non-transferable-account", "transfer-hook",
+    "transfer-hook-account", "confidential-transfer-fee-config",
+    "confidential-transfer-fee-amount", "metadata-pointer", "token-metadata", "group-pointer",
+    "token-group", "group-member-pointer", "token-group-member", "confidential-mint-burn",
+    "scaled-ui-amount", "pausable", "pausable-account",
+];
+const ACCOUNT_STATES: &[&str] = &["uninitialized", "initialized", "frozen"];
+
+// The instructions of the confidential transfer extensions, by discriminant.
+const CONFIDENTIAL_TRANSFER_INSTRUCTIONS: &[&str] = &[
+    "initialize-confidential-transfer-mint",
+    "update-confidential-transfer-mint",
+    "configure-confidential-account",
+    "approve-confidential-account",
+    "empty-confidential-account",
+    "confidential-deposit",
+    "confidential-withdraw",
+    "confidential-transfer",
+    "apply-pending-balance",
+    "enable-confidential-credits",
+    "disable-confidential-credits",
+    "enable-non-confidential-credits",
+    "disable-non-confidential-credits",
+];
+const CONFIDENTIAL_TRANSFER_FEE_INSTRUCTIONS: &[&str] = &[
+    "initialize-confidential-transfer-fee-config",
+    "withdraw-withheld-confidential-tokens-from-mint",
+    "withdraw-withheld-confidential-tokens-from-accounts",
+    "harvest-withheld-confidential-tokens-to-mint",
+    "enable-harvest-to-mint",
+    "disable-harvest-to-mint",
+];
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let amount = ("amount", "", PropertyType::U64, "Amount of tokens, in base units");
+    let decimals = ("decimals", "", PropertyType::U64, "Decimals of the mint");
+    let owner = ("owner", "", PropertyType::Pubkey, "Owner of the token account");
+    let m = ("m", "", PropertyType::U64, "Number of signers required");
+    let mint = [
+        decimals,
+        ("mint_authority", "", PropertyType::Pubkey, "Authority allowed to mint tokens"),
+        ("freeze_authority", "", PropertyType::Pubkey,
+         "Authority allowed to freeze token accounts"),
+    ];
+    let extension_type = ("extension_type", "extension_types/{index}", PropertyType::Text,
+                          "Extension the account is sized or reallocated for");
+    let fee = [
+        ("transfer_fee_basis_points", TRANSFER_FEE, PropertyType::U64,
+         "Fee of transfers, in basis points of the amount transferred"),
+        ("maximum_fee", TRANSFER_FEE, PropertyType::U64,
+         "Maximum fee of a transfer, in base units"),
+    ];
+    let confidential_amount = [
+        ("amount", CONFIDENTIAL_TRANSFER, PropertyType::U64,
+         "Amount of tokens moved between the public and the confidential balances, in base \
+         units"),
+        ("decimals", CONFIDENTIAL_TRANSFER, PropertyType::U64, "Decimals of the mint"),
+    ];
+    let auto_approve = ("auto_approve_new_accounts", CONFIDENTIAL_TRANSFER, PropertyType::Bool,
+                        "Whether accounts can transfer confidentially without being approved");
+    let state = ("state", DEFAULT_ACCOUNT_STATE, PropertyType::Text,
+                 "State new accounts start in (uninitialized, initialized or frozen)");
+    let rate = ("rate", INTEREST_BEARING_MINT, PropertyType::I128,
+                "Interest rate, in basis points per year");
+    let hook_program = ("program_id", TRANSFER_HOOK, PropertyType::Pubkey,
+                        "Program invoked on every transfer, empty when unset");
+    let multiplier = ("multiplier", SCALED_UI_AMOUNT, PropertyType::Decimal,
+                      "Multiplier of the amounts displayed");
+
+    let metadata_pointer = pointer_keys(METADATA_POINTER, "metadata_address");
+    let group_pointer = pointer_keys(GROUP_POINTER, "group_address");
+    let group_member_pointer = pointer_keys(GROUP_MEMBER_POINTER, "member_address");
+
+    ProgramSchema::new("token-2022")
+        .function("initialize-mint", &mint)
+        .function("initialize-account", &[])
+        .function("initialize-multisig", &[m])
+        .function("transfer", &[amount])
+        .function("approve", &[amount])
+        .function("revoke", &[])
+        .function("set-authority", &[
+            ("authority_type", "", PropertyType::U64,
+             "Kind of authority changed (0: mint tokens, 1: freeze account, 2: account owner, 3: \
+             close account, 4 and up: the authorities of the extensions)"),
+            ("new_authority", "", PropertyType::Pubkey,
+             "New authority, empty when the authority is removed"),
+        ])
+        .function("mint-to", &[amount])
+        .function("burn", &[amount])
+        .function("close-account", &[])
+        .function("freeze-account", &[])
+        .function("thaw-account", &[])
+        .function("transfer-checked", &[amount, decimals])
+        .function("approve-checked", &[amount, decimals])
+        .function("mint-to-checked", &[amount, decimals])
+        .function("burn-checked", &[amount, decimals])
+        .function("initialize-account-2", &[owner])
+        .function("sync-native", &[])
+        .function("initialize-account-3", &[owner])
+        .function("initialize-multisig-2", &[m])
+        .function("initialize-mint-2", &mint)
+        .function("get-account-data-size", &[extension_type])
+        .function("initialize-immutable-owner", &[])
+        .function("amount-to-ui-amount", &[amount])
+        .function("ui-amount-to-amount", &[
+            ("ui_amount", "", PropertyType::Text, "Amount of tokens, as displayed"),
+        ])
+        .function("initialize-mint-close-authority", &[
+            ("close_authority", MINT_CLOSE_AUTHORITY, PropertyType::Pubkey,
+             "Authority allowed to close the mint, empty when unset"),
+        ])
+        .function("initialize-transfer-fee-config", &[
+            ("transfer_fee_config_authority", TRANSFER_FEE, PropertyType::Pubkey,
+             "Authority allowed to set the fee, empty when unset"),
+            ("withdraw_withheld_authority", TRANSFER_FEE, PropertyType::Pubkey,
+             "Authority allowed to withdraw the fees withheld, empty when unset"),
+            fee[0], fee[1],
+        ])
+        .function("transfer-checked-with-fee", &[
+            amount, decimals,
+            ("fee", TRANSFER_FEE, PropertyType::U64, "Fee withheld, in base units"),
+        ])
+        .function("withdraw-withheld-tokens-from-mint", &[])
+        .function("withdraw-withheld-tokens-from-accounts", &[
+            ("num_token_accounts", TRANSFER_FEE, PropertyType::U64,
+             "Number of accounts the fees are withdrawn from"),
+        ])
+        .function("harvest-withheld-tokens-to-mint", &[])
+        .function("set-transfer-fee", &fee)
+        .function("initialize-confidential-transfer-mint", &[
+            ("authority", CONFIDENTIAL_TRANSFER, PropertyType::Pubkey,
+             "Authority allowed to approve accounts, empty when unset"),
+            auto_approve,
+        ])
+        .function("update-confidential-transfer-mint", &[auto_approve])
+        .function("confidential-deposit", &confidential_amount)
+        .function("confidential-withdraw", &confidential_amount)
+        .function("configure-confidential-account", &[])
+        .function("approve-confidential-account", &[])
+        .function("empty-confidential-account", &[])
+        .function("confidential-transfer", &[])
+        .function("apply-pending-balance", &[])
+        .function("enable-confidential-credits", &[])
+        .function("disable-confidential-credits", &[])
+        .function("enable-non-confidential-credits", &[])
+        .function("disable-non-confidential-credits", &[])
+        .function("initialize-confidential-transfer-fee-config", &[
+            ("authority", CONFIDENTIAL_TRANSFER_FEE, PropertyType::Pubkey,
+             "Authority allowed to withdraw the confidential fees withheld, empty when unset"),
+        ])
+        .function("withdraw-withheld-confidential-tokens-from-mint", &[])
+        .function("withdraw-withheld-confidential-tokens-from-accounts", &[])
+        .function("harvest-withheld-confidential-tokens-to-mint", &[])
+        .function("enable-harvest-to-mint", &[])
+        .function("disable-harvest-to-mint", &[])
+        .function("initialize-default-account-state", &[state])
+        .function("update-default-account-state", &[state])
+        .function("reallocate", &[extension_type])
+        .function("enable-required-memo-transfers", &[])
+        .function("disable-required-memo-transfers", &[])
+        .function("create-native-mint", &[])
+        .function("initialize-non-transferable-mint", &[])
+        .function("initialize-interest-bearing-mint", &[
+            ("rate_authority", INTEREST_BEARING_MINT, PropertyType::Pubkey,
+             "Authority allowed to update the rate, empty when unset"),
+            rate,
+        ])
+        .function("update-interest-rate", &[rate])
+        .function("enable-cpi-guard", &[])
+        .function("disable-cpi-guard", &[])
+        .function("initialize-permanent-delegate", &[
+            ("delegate", PERMANENT_DELEGATE, PropertyType::Pubkey,
+             "Delegate allowed to transfer or burn the tokens of any account of the mint"),
+        ])
+        .function("initialize-transfer-hook", &[
+            ("authority", TRANSFER_HOOK, PropertyType::Pubkey,
+             "Authority allowed to set the program, empty when unset"),
+            hook_program,
+        ])
+        .function("update-transfer-hook", &[hook_program])
+        .function("withdraw-excess-lamports", &[])
+        .function("initialize-metadata-pointer", &metadata_pointer)
+        .function("update-metadata-pointer", &metadata_pointer[1..])
+        .function("initialize-group-pointer", &group_pointer)
+        .function("update-group-pointer", &group_pointer[1..])
+        .function("initialize-group-member-pointer", &group_member_pointer)
+        .function("update-group-member-pointer", &group_member_pointer[1..])
+        .function("initialize-scaled-ui-amount", &[
+            ("authority", SCALED_UI_AMOUNT, PropertyType::Pubkey,
+             "Authority allowed to update the multiplier, empty when unset"),
+            multiplier,
+        ])
+        .function("update-multiplier", &[
+            multiplier,
+            ("effective_timestamp", SCALED_UI_AMOUNT, PropertyType::Timestamp,
+             "When the multiplier takes effect"),
+        ])
+        .function("initialize-pausable", &[
+            ("authority", PAUSABLE, PropertyType::Pubkey,
+             "Authority allowed to pause and resume the mint"),
+        ])
+        .function("pause", &[])
+        .function("resume", &[])
+}
+
+/// The keys of the instructions of a pointer extension: its authority, then the address pointed
+/// to.
+fn pointer_keys(
+    parent_key: &'static str,
+    address_key: &'static str,
+) -> [(&'static str, &'static str, PropertyType, &'static str); 2] {
+    [
+        ("authority", parent_key, PropertyType::Pubkey,
+         "Authority allowed to set the address, empty when unset"),
+        (address_key, parent_key, PropertyType::Pubkey, "Address pointed to, empty when unset"),
+    ]
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+) -> Option<InstructionSet> {
+    let (tag, arguments) = match instruction.data.split_first() {
+        Some(split) => split,
+        None => {
+            error!("[spi-wrapper/token-2022] Attempt to parse instruction from program {} failed \
+                as it has no data.", instruction.program);
+
+            return None;
+        }
+    };
+    let mut arguments = Reader::new(arguments);
+    let arguments = &mut arguments;
+
+    let decoded = match *tag {
+        0 => initialize_mint(arguments, "initialize-mint"),
+        1 => Some(Decoded::new("initialize-account")),
+        2 => multisig(arguments, "initialize-multisig"),
+        3 => amount(arguments, "transfer"),
+        4 => amount(arguments, "approve"),
+        5 => Some(Decoded::new("revoke")),
+        6 => set_authority(arguments),
+        7 => amount(arguments, "mint-to"),
+        8 => amount(arguments, "burn"),
+        9 => Some(Decoded::new("close-account")),
+        10 => Some(Decoded::new("freeze-account")),
+        11 => Some(Decoded::new("thaw-account")),
+        12 => checked(arguments, "transfer-checked"),
+        13 => checked(arguments, "approve-checked"),
+        14 => checked(arguments, "mint-to-checked"),
+        15 => checked(arguments, "burn-checked"),
+        16 => account_owner(arguments, "initialize-account-2"),
+        17 => Some(Decoded::new("sync-native")),
+        18 => account_owner(arguments, "initialize-account-3"),
+        19 => multisig(arguments, "initialize-multisig-2"),
+        20 => initialize_mint(arguments, "initialize-mint-2"),
+        21 => extension_types(arguments, "get-account-data-size"),
+        22 => Some(Decoded::new("initialize-immutable-owner")),
+        23 => amount(arguments, "amount-to-ui-amount"),
+        24 => ui_amount(arguments),
+        25 => arguments.option(Reader::pubkey).map(|close_authority| {
+            Decoded::new("initialize-mint-close-authority")
+                .nested(MINT_CLOSE_AUTHORITY, "close_authority", optional(close_authority),
+                        PropertyType::Pubkey)
+        }),
+        26 => transfer_fee(arguments),
+        27 => confidential_transfer(arguments),
+        28 => default_account_state(arguments),
+        29 => extension_types(arguments, "reallocate"),
+        30 => toggle(arguments, "enable-required-memo-transfers",
+                     "disable-required-memo-transfers"),
+        31 => Some(Decoded::new("create-native-mint")),
+        32 => Some(Decoded::new("initialize-non-transferable-mint")),
+        33 => interest_bearing_mint(arguments),
+        34 => toggle(arguments, "enable-cpi-guard", "disable-cpi-guard"),
+        35 => arguments.pubkey().map(|delegate| {
+            Decoded::new("initialize-permanent-delegate")
+                .nested(PERMANENT_DELEGATE, "delegate", delegate.to_string(), PropertyType::Pubkey)
+        }),
+        36 => transfer_hook(arguments),
+        37 => confidential_transfer_fee(arguments),
+        38 => Some(Decoded::new("withdraw-excess-lamports")),
+        39 => pointer(arguments, METADATA_POINTER, "metadata_address",
+                      ["initialize-metadata-pointer", "update-metadata-pointer"]),
+        40 => pointer(arguments, GROUP_POINTER, "group_address",
+                      ["initialize-group-pointer", "update-group-pointer"]),
+        41 => pointer(arguments, GROUP_MEMBER_POINTER, "member_address",
+                      ["initialize-group-member-pointer", "update-group-member-pointer"]),
+        43 => scaled_ui_amount(arguments),
+        44 => pausable(arguments),
+        _ => {
+            debug!("[spi-wrapper/token-2022] Instruction {} of {} isn't one the processor \
+                decodes.", tag, instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let decoded = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/token-2022] Attempt to parse instruction from program {} \
+                failed due to truncated or invalid arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, decoded.function_name);
+    instruction_set.properties = decoded.properties.into_iter()
+        .map(|(key, parent_key, value, value_type)| {
+            layout::property(&instruction, key, &parent_key, value, value_type)
+        })
+        .collect();
+
+    Some(instruction_set)
+}
+
+/// A function and its properties: key, parent key, value and type.
+struct Decoded {
+    function_name: &'static str,
+    properties: Vec<(&'static str, String, String, PropertyType)>,
+}
+
+impl Decoded {
+    fn new(function_name: &'static str) -> Self {
+        Decoded { function_name, properties: Vec::new() }
+    }
+
+    fn with(self, key: &'static str, value: String, value_type: PropertyType) -> Self {
+        self.nested("", key, value, value_type)
+    }
+
+    fn nested(
+        mut self,
+        parent_key: &str,
+        key: &'static str,
+        value: String,
+        value_type: PropertyType,
+    ) -> Self {
+        self.properties.push((key, parent_key.to_string(), value, value_type));
+        self
+    }
+}
+
+/// An optional pubkey, empty when unset.
+fn optional(pubkey: Option<Pubkey>) -> String {
+    pubkey.map_or_else(String::new, |pubkey| pubkey.to_string())
+}
+
+/// A pubkey of an extension, the default pubkey standing for an unset one.
+fn non_zero_pubkey(arguments: &mut Reader) -> Option<Option<Pubkey>> {
+    arguments.pubkey().map(|pubkey| Some(pubkey).filter(|pubkey| *pubkey != Pubkey::default()))
+}
+
+fn initialize_mint(arguments: &mut Reader, function_name: &'static str) -> Option<Decoded> {
+    let decimals = arguments.u8()?;
+    let mint_authority = arguments.pubkey()?;
+    let freeze_authority = arguments.option(Reader::pubkey)?;
+
+    Some(Decoded::new(function_name)
+        .with("decimals", decimals.to_string(), PropertyType::U64)
+        .with("mint_authority", mint_authority.to_string(), PropertyType::Pubkey)
+        .with("freeze_authority", optional(freeze_authority), PropertyType::Pubkey))
+}
+
+fn multisig(arguments: &mut Reader, function_name: &'static str) -> Option<Decoded> {
+    let m = arguments.u8()?;
+    Some(Decoded::new(function_name).with("m", m.to_string(), PropertyType::U64))
+}
+
+fn account_owner(arguments: &mut Reader, function_name: &'static str) -> Option<Decoded> {
+    let owner = arguments.pubkey()?;
+    Some(Decoded::new(function_name).with("owner", owner.to_string(), PropertyType::Pubkey))
+}
+
+fn amount(arguments: &mut Reader, function_name: &'static str) -> Option<Decoded> {
+    let amount = arguments.u64()?;
+    Some(Decoded::new(function_name).with("amount", amount.to_string(), PropertyType::U64))
+}
+
+/// An amount, checked against the decimals of the mint.
+fn checked(arguments: &mut Reader, function_name: &'static str) -> Option<Decoded> {
+    let decoded = amount(arguments, function_name)?;
+    let decimals = arguments.u8()?;
+    Some(decoded.with("decimals", decimals.to_string(), PropertyType::U64))
+}
+
+fn set_authority(arguments: &mut Reader) -> Option<Decoded> {
+    let authority_type = arguments.u8()?;
+    let new_authority = arguments.option(Reader::pubkey)?;
+
+    Some(Decoded::new("set-authority")
+        .with("authority_type", authority_type.to_string(), PropertyType::U64)
+        .with("new_authority", optional(new_authority), PropertyType::Pubkey))
+}
+
+/// The rest of the data, a string.
+fn ui_amount(arguments: &mut Reader) -> Option<Decoded> {
+    let len = arguments.remaining();
+    let ui_amount = std::str::from_utf8(arguments.bytes(len)?).ok()?;
+    Some(Decoded::new("ui-amount-to-amount")
+        .with("ui_amount", ui_amount.to_string(), PropertyType::Text))
+}
+
+/// The rest of the data, extension types as u16s.
+fn extension_types(arguments: &mut Reader, function_name: &'static str) -> Option<Decoded> {
+    if arguments.remaining() % 2 != 0 {
+        return None;
+    }
+
+    let mut decoded = Decoded::new(function_name);
+    for index in 0..arguments.remaining() / 2 {
+        let extension_type = arguments.u16()?;
+        let name = EXTENSION_TYPES.get(extension_type as usize)
+            .map_or_else(|| extension_type.to_string(), |name| name.to_string());
+        decoded = decoded.nested(&format!("extension_types/{}", index), "extension_type", name,
+                                 PropertyType::Text);
+    }
+
+    Some(decoded)
+}
+
+/// The instructions of extensions that are either turned on or off.
+fn toggle(
+    arguments: &mut Reader,
+    enable: &'static str,
+    disable: &'static str,
+) -> Option<Decoded> {
+    arguments.name(&[enable, disable]).map(Decoded::new)
+}
+
+fn transfer_fee(arguments: &mut Reader) -> Option<Decoded> {
+    match arguments.u8()? {
+        0 => {
+            let config_authority = arguments.option(Reader::pubkey)?;
+            let withdraw_authority = arguments.option(Reader::pubkey)?;
+            let decoded = Decoded::new("initialize-transfer-fee-config")
+                .nested(TRANSFER_FEE, "transfer_fee_config_authority",
+                        optional(config_authority), PropertyType::Pubkey)
+                .nested(TRANSFER_FEE, "withdraw_withheld_authority",
+                        optional(withdraw_authority), PropertyType::Pubkey);
+            fee(arguments, decoded)
+        }
+        1 => {
+            let decoded = checked(arguments, "transfer-checked-with-fee")?;
+            let fee = arguments.u64()?;
+            Some(decoded.nested(TRANSFER_FEE, "fee", fee.to_string(), PropertyType::U64))
+        }
+        2 => Some(Decoded::new("withdraw-withheld-tokens-from-mint")),
+        3 => {
+            let num_token_accounts = arguments.u8()?;
+            Some(Decoded::new("withdraw-withheld-tokens-from-accounts")
+                .nested(TRANSFER_FEE, "num_token_accounts", num_token_accounts.to_string(),
+                        PropertyType::U64))
+        }
+        4 => Some(Decoded::new("harvest-withheld-tokens-to-mint")),
+        5 => fee(arguments, Decoded::new("set-transfer-fee")),
+        _ => None,
+    }
+}
+
+/// The basis points and maximum of a transfer fee.
+fn fee(arguments: &mut Reader, decoded: Decoded) -> Option<Decoded> {
+    let basis_points = arguments.u16()?;
+    let maximum_fee = arguments.u64()?;
+
+    Some(decoded
+        .nested(TRANSFER_FEE, "transfer_fee_basis_points", basis_points.to_string(),
+                PropertyType::U64)
+        .nested(TRANSFER_FEE, "maximum_fee", maximum_fee.to_string(), PropertyType::U64))
+}
+
+fn confidential_transfer(arguments: &mut Reader) -> Option<Decoded> {
+    let index = arguments.u8()?;
+    let decoded = Decoded::new(*CONFIDENTIAL_TRANSFER_INSTRUCTIONS.get(index as usize)?);
+    let auto_approve = |decoded: Decoded, auto_approve: bool| {
+        decoded.nested(CONFIDENTIAL_TRANSFER, "auto_approve_new_accounts",
+                       (auto_approve as u8).to_string(), PropertyType::Bool)
+    };
+
+    // The auditor keys, the ciphertexts and the proofs that follow are left out.
+    match index {
+        0 => {
+            let authority = non_zero_pubkey(arguments)?;
+            let decoded = decoded.nested(CONFIDENTIAL_TRANSFER, "authority", optional(authority),
+                                         PropertyType::Pubkey);
+            Some(auto_approve(decoded, arguments.bool()?))
+        }
+        1 => Some(auto_approve(decoded, arguments.bool()?)),
+        // Deposits and withdrawals.
+        5 | 6 => {
+            let amount = arguments.u64()?;
+            let decimals = arguments.u8()?;
+            Some(decoded
+                .nested(CONFIDENTIAL_TRANSFER, "amount", amount.to_string(), PropertyType::U64)
+                .nested(CONFIDENTIAL_TRANSFER, "decimals", decimals.to_string(),
+                        PropertyType::U64))
+        }
+        _ => Some(decoded),
+    }
+}
+
+fn confidential_transfer_fee(arguments: &mut Reader) -> Option<Decoded> {
+    let index = arguments.u8()?;
+    let decoded = Decoded::new(*CONFIDENTIAL_TRANSFER_FEE_INSTRUCTIONS.get(index as usize)?);
+    if index != 0 {
+        return Some(decoded);
+    }
+
+    let authority = non_zero_pubkey(arguments)?;
+    Some(decoded.nested(CONFIDENTIAL_TRANSFER_FEE, "authority", optional(authority),
+                        PropertyType::Pubkey))
+}
+
+fn default_account_state(arguments: &mut Reader) -> Option<Decoded> {
+    let function_name = arguments.name(&[
+        "initialize-default-account-state",
+        "update-default-account-state",
+    ])?;
+    let state = arguments.name(ACCOUNT_STATES)?;
+
+    Some(Decoded::new(function_name)
+        .nested(DEFAULT_ACCOUNT_STATE, "state", state.to_string(), PropertyType::Text))
+}
+
+fn interest_bearing_mint(arguments: &mut Reader) -> Option<Decoded> {
+    let decoded = match arguments.u8()? {
+        0 => {
+            let rate_authority = non_zero_pubkey(arguments)?;
+            Decoded::new("initialize-interest-bearing-mint")
+                .nested(INTEREST_BEARING_MINT, "rate_authority", optional(rate_authority),
+                        PropertyType::Pubkey)
+        }
+        1 => Decoded::new("update-interest-rate"),
+        _ => return None,
+    };
+    let rate = arguments.i16()?;
+
+    Some(decoded.nested(INTEREST_BEARING_MINT, "rate", rate.to_string(), PropertyType::I128))
+}
+
+fn transfer_hook(arguments: &mut Reader) -> Option<Decoded> {
+    let decoded = match arguments.u8()? {
+        0 => {
+            let authority = non_zero_pubkey(arguments)?;
+            Decoded::new("initialize-transfer-hook")
+                .nested(TRANSFER_HOOK, "authority", optional(authority), PropertyType::Pubkey)
+        }
+        1 => Decoded::new("update-transfer-hook"),
+        _ => return None,
+    };
+    let program_id = non_zero_pubkey(arguments)?;
+
+    Some(decoded.nested(TRANSFER_HOOK, "program_id", optional(program_id), PropertyType::Pubkey))
+}
+
+/// The instructions of the extensions pointing to an address (of the metadata of the mint, of
+/// its group, or of its membership of a group).
+fn pointer(
+    arguments: &mut Reader,
+    parent_key: &'static str,
+    address_key: &'static str,
+    function_names: [&'static str; 2],
+) -> Option<Decoded> {
+    let decoded = match arguments.u8()? {
+        0 => {
+            let authority = non_zero_pubkey(arguments)?;
+            Decoded::new(function_names[0])
+                .nested(parent_key, "authority", optional(authority), PropertyType::Pubkey)
+        }
+        1 => Decoded::new(function_names[1]),
+        _ => return None,
+    };
+    let address = non_zero_pubkey(arguments)?;
+
+    Some(decoded.nested(parent_key, address_key, optional(address), PropertyType::Pubkey))
+}
+
+fn scaled_ui_amount(arguments: &mut Reader) -> Option<Decoded> {
+    let multiplier = |decoded: Decoded, arguments: &mut Reader| {
+        let multiplier = f64::from_bits(arguments.u64()?);
+        Some(decoded.nested(SCALED_UI_AMOUNT, "multiplier", multiplier.to_string(),
+                            PropertyType::Decimal))
+    };
+
+    match arguments.u8()? {
+        0 => {
+            let authority = non_zero_pubkey(arguments)?;
+            let decoded = Decoded::new("initialize-scaled-ui-amount")
+                .nested(SCALED_UI_AMOUNT, "authority", optional(authority), PropertyType::Pubkey);
+            multiplier(decoded, arguments)
+        }
+        1 => {
+            let decoded = multiplier(Decoded::new("update-multiplier"), arguments)?;
+            let effective_timestamp = arguments.i64()?;
+            Some(decoded.nested(SCALED_UI_AMOUNT, "effective_timestamp",
+                                effective_timestamp.to_string(), PropertyType::Timestamp))
+        }
+        _ => None,
+    }
+}
+
+fn pausable(arguments: &mut Reader) -> Option<Decoded> {
+    match arguments.u8()? {
+        0 => {
+            let authority = arguments.pubkey()?;
+            Some(Decoded::new("initialize-pausable")
+                .nested(PAUSABLE, "authority", authority.to_string(), PropertyType::Pubkey))
+        }
+        1 => Some(Decoded::new("pause")),
+        2 => Some(Decoded::new("resume")),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(data: Vec<u8>) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, data))
+    }
+
+    fn data(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    #[test]
+    fn instructions_of_the_token_program_decode_as_they_do_there() {
+        let authority = Pubkey::new_unique();
+        let mint = decode(data(&[&[20, 9], authority.as_ref(), &[0]])).unwrap();
+        assert_eq!(mint.function.function_name.as_str(), "initialize-mint-2");
+        assert_eq!(properties(&mint), vec![
+            ("decimals", "9".to_string()),
+            ("mint_authority", authority.to_string()),
+            ("freeze_authority", "".to_string()),
+        ]);
+
+        let transfer = decode(data(&[&[12], &1_500u64.to_le_bytes(), &[6]])).unwrap();
+        assert_eq!(transfer.function.function_name.as_str(), "transfer-checked");
+        assert_eq!(properties(&transfer), vec![
+            ("amount", "1500".to_string()),
+            ("decimals", "6".to_string()),
+        ]);
+        assert_eq!(decode(data(&[&[24], b"1.5"])).unwrap().properties[0].value, "1.5");
+    }
+
+    #[test]
+    fn extension_properties_are_nested_under_their_extension() {
+        let authority = Pubkey::new_unique();
+        let config = decode(data(&[
+            &[26, 0, 1], authority.as_ref(), &[0], &50u16.to_le_bytes(), &5_000u64.to_le_bytes(),
+        ])).unwrap();
+        assert_eq!(config.function.function_name.as_str(), "initialize-transfer-fee-config");
+        assert_eq!(properties(&config), vec![
+            ("transfer_fee.transfer_fee_config_authority", authority.to_string()),
+            ("transfer_fee.withdraw_withheld_authority", "".to_string()),
+            ("transfer_fee.transfer_fee_basis_points", "50".to_string()),
+            ("transfer_fee.maximum_fee", "5000".to_string()),
+        ]);
+
+        let with_fee = decode(data(&[
+            &[26, 1], &1_000u64.to_le_bytes(), &[6], &5u64.to_le_bytes(),
+        ])).unwrap();
+        assert_eq!(properties(&with_fee), vec![
+            ("amount", "1000".to_string()),
+            ("decimals", "6".to_string()),
+            ("transfer_fee.fee", "5".to_string()),
+        ]);
+
+        // A negative rate, its authority unset.
+        let interest = decode(data(&[&[33, 0], &[0; 32], &(-25i16).to_le_bytes()])).unwrap();
+        assert_eq!(properties(&interest), vec![
+            ("interest_bearing_mint.rate_authority", "".to_string()),
+            ("interest_bearing_mint.rate", "-25".to_string()),
+        ]);
+
+        let memos = decode(vec![30, 0]).unwrap();
+        assert_eq!(memos.function.function_name.as_str(), "enable-required-memo-transfers");
+        let sizes = decode(data(&[&[21], &1u16.to_le_bytes(), &14u16.to_le_bytes()])).unwrap();
+        assert_eq!(properties(&sizes), vec![
+            ("extension_types/0.extension_type", "transfer-fee-config".to_string()),
+            ("extension_types/1.extension_type", "transfer-hook".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn decoded_properties_are_described() {
+        let schema = describe();
+        let key = Pubkey::new_unique();
+        for data in &[
+            data(&[&[0, 6], key.as_ref(), &[1], key.as_ref()]),
+            data(&[&[26, 5], &10u16.to_le_bytes(), &1u64.to_le_bytes()]),
+            data(&[&[27, 0], key.as_ref(), &[1], &[0; 32]]),
+            data(&[&[27, 5], &7u64.to_le_bytes(), &[9]]),
+            vec![28, 1, 2],
+            data(&[&[36, 1], key.as_ref()]),
+            data(&[&[39, 0], key.as_ref(), key.as_ref()]),
+            data(&[&[43, 1], &1.5f64.to_bits().to_le_bytes(), &1_700_000_000i64.to_le_bytes()]),
+            data(&[&[44, 0], key.as_ref()]),
+        ] {
+            let instruction_set = decode(data.clone()).unwrap();
+            let function_name = instruction_set.function.function_name.as_str();
+            assert!(!instruction_set.properties.is_empty(), "{}", function_name);
+            for property in &instruction_set.properties {
+                let declared = schema.key(function_name, &property.key, &property.parent_key);
+                assert_eq!(declared.map(|declared| declared.value_type),
+                           Some(property.value_type), "{} {}", function_name, property.key);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        // Cut in the middle of the authorities of the fee.
+        assert!(decode(vec![26, 0, 1, 7]).is_none());
+        // An unknown instruction of the transfer fee extension, and an unknown account state.
+        assert!(decode(vec![26, 9]).is_none());
+        assert!(decode(vec![28, 0, 3]).is_none());
+        // An odd number of bytes of extension types.
+        assert!(decode(vec![21, 1, 0, 14]).is_none());
+        // Confidential mint and burn, and a token metadata interface discriminator.
+        assert!(decode(vec![42, 0]).is_none());
+        assert!(decode(vec![210, 225, 30, 162, 88, 184, 77, 141]).is_none());
+        assert!(decode(vec![]).is_none());
+    }
+}
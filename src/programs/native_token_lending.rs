@@ -1,14 +1,139 @@
+use smallvec::smallvec;
 use solana_program::program_error::ProgramError;
 use solana_sdk::pubkey::Pubkey;
 use spl_token_lending::instruction::LendingInstruction;
 use tracing::error;
 
-use crate::{Instruction, InstructionFunction, InstructionProperty, InstructionSet};
+use crate::{Instruction, InstructionFunction, InstructionProperty, InstructionSet, PropertyType};
+use crate::fast::{FastInstruction, PropertyValueRef};
+use crate::schema::ProgramSchema;
 
 pub const PROGRAM_ADDRESS: &str = "LendZqTs8gn5CTSJU1jWKhKuVpjJGom45nnwPb2AMTi";
+pub const DEVNET_PROGRAM_ADDRESS: &str = "6TvznH3B2e3p2mbhufNBpgSrLx6UkgvxtVQvopEZ2kuH";
 
-pub async fn fragment_instruction(
-    instruction: Instruction
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let account = |key, description| (key, "", PropertyType::Pubkey, description);
+    let obligation = account("obligation",
+                             "Obligation account, when the accounts of the instruction are known");
+    let reserve = account("reserve",
+                          "Reserve account, when the accounts of the instruction are known");
+    let repay_reserve = account("repay_reserve", "Reserve the liquidator repays liquidity to, \
+        when the accounts of the instruction are known");
+    let withdraw_reserve = account("withdraw_reserve", "Reserve the liquidator withdraws \
+        collateral from, when the accounts of the instruction are known");
+
+    ProgramSchema::new("token-lending")
+        .function("init-lending-market", &[
+            ("owner", "", PropertyType::Pubkey, "Owner of the lending market"),
+            ("quote_currency", "", PropertyType::Pubkey,
+             "Currency the market values are quoted in"),
+        ])
+        .function("set-lending-market-owner", &[
+            ("new_owner", "", PropertyType::Pubkey, "New owner of the lending market"),
+        ])
+        .function("init-reserve", &[
+            ("liquidity_amount", "", PropertyType::U64, "Amount of liquidity tokens"),
+            ("flash_loan_fee_wad", "fees", PropertyType::U64,
+             "Fee on flash loans, as a wad (1e18 = 100%)"),
+            ("borrow_fee_wad", "config/fees", PropertyType::U64,
+             "Fee on borrowed liquidity, as a wad (1e18 = 100%)"),
+            ("host_fee_percentage", "config/fees", PropertyType::U64,
+             "Share of the fees paid to the host, in percent"),
+            ("liquidation_threshold", "config", PropertyType::U64,
+             "Loan to value ratio at which obligations get liquidated, in percent"),
+            ("loan_to_value_ratio", "config", PropertyType::U64,
+             "Maximum loan to value ratio, in percent"),
+            ("max_borrow_rate", "config", PropertyType::U64,
+             "Borrow rate at full utilization, in percent"),
+            ("min_borrow_rate", "config", PropertyType::U64,
+             "Borrow rate at no utilization, in percent"),
+            ("optimal_borrow_rate", "config", PropertyType::U64,
+             "Borrow rate at optimal utilization, in percent"),
+            ("optimal_utilization_rate", "config", PropertyType::U64,
+             "Optimal utilization of the reserve, in percent"),
+        ])
+        .function("refresh-reserve", &[])
+        .function("deposit-reserve-liquidity", &[
+            ("liquidity_amount", "", PropertyType::U64, "Amount of liquidity tokens"),
+        ])
+        .function("redeem-reserve-collateral", &[
+            ("collateral_amount", "", PropertyType::U64, "Amount of collateral tokens"),
+        ])
+        .function("init-obligation", &[])
+        .function("refresh-obligation", &[])
+        .function("deposit-obligation-collateral", &[
+            ("collateral_amount", "", PropertyType::U64, "Amount of collateral tokens"),
+            reserve,
+            obligation,
+        ])
+        .function("withdraw-obligation-collateral", &[
+            ("collateral_amount", "", PropertyType::U64, "Amount of collateral tokens"),
+            reserve,
+            obligation,
+        ])
+        .function("borrow-obligation-liquidity", &[
+            ("liquidity_amount", "", PropertyType::U64, "Amount of liquidity tokens"),
+            reserve,
+            obligation,
+        ])
+        .function("repay-obligation-liquidity", &[
+            ("liquidity_amount", "", PropertyType::U64, "Amount of liquidity tokens"),
+            reserve,
+            obligation,
+        ])
+        .function("liquidate-obligation", &[
+            ("liquidity_amount", "", PropertyType::U64, "Amount of liquidity tokens"),
+            repay_reserve,
+            withdraw_reserve,
+            obligation,
+        ])
+        .function("flash-loan", &[
+            ("amount", "", PropertyType::U64, "Amount of tokens, in base units"),
+        ])
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    let mut instruction_set = unpack(&instruction)?;
+    for (key, index) in account_roles(instruction_set.function.function_name.as_str()) {
+        if let Some(account) = accounts.get(*index) {
+            instruction_set.properties.push(InstructionProperty {
+                tx_instruction_id: instruction.tx_instruction_id,
+                transaction_hash: instruction.transaction_hash.clone(),
+                parent_index: instruction.parent_index,
+                key: (*key).into(),
+                value: account.to_string(),
+                value_type: PropertyType::Pubkey,
+                parent_key: "".into(),
+                timestamp: instruction.timestamp,
+            });
+        }
+    }
+
+    Some(instruction_set)
+}
+
+/// Where the obligation and the reserves an instruction changes are in its accounts, by function.
+fn account_roles(function_name: &str) -> &'static [(&'static str, usize)] {
+    match function_name {
+        "deposit-obligation-collateral" => &[("reserve", 2), ("obligation", 3)],
+        "withdraw-obligation-collateral" => &[("reserve", 2), ("obligation", 3)],
+        "borrow-obligation-liquidity" => &[("reserve", 2), ("obligation", 4)],
+        "repay-obligation-liquidity" => &[("reserve", 2), ("obligation", 3)],
+        "liquidate-obligation" => {
+            &[("repay_reserve", 2), ("withdraw_reserve", 4), ("obligation", 6)]
+        }
+        _ => &[],
+    }
+}
+
+fn unpack(
+    instruction: &Instruction
 ) -> Option<InstructionSet> {
     // Unpack the instruction via the spl_token_swap library
     let unpack_result = LendingInstruction::unpack(
@@ -24,56 +149,65 @@ pub async fn fragment_instruction(
                 } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "init-lending-market".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "init-lending-market".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "owner".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "owner".into(),
                                 value: owner.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "quote_currency".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "quote_currency".into(),
                                 value: Pubkey::new_from_array(quote_currency).to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::SetLendingMarketOwner { new_owner } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "set-lending-market-owner".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "set-lending-market-owner".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "new_owner".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "new_owner".into(),
                                 value: new_owner.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::InitReserve {
@@ -82,328 +216,382 @@ pub async fn fragment_instruction(
                 } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "init-reserve".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "init-reserve".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidity_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidity_amount".into(),
                                 value: liquidity_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "flash_loan_fee_wad".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "flash_loan_fee_wad".into(),
                                 value: config.fees.flash_loan_fee_wad.to_string(),
-                                parent_key: "fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "borrow_fee_wad".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "borrow_fee_wad".into(),
                                 value: config.fees.borrow_fee_wad.to_string(),
-                                parent_key: "config/fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config/fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "host_fee_percentage".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "host_fee_percentage".into(),
                                 value: config.fees.host_fee_percentage.to_string(),
-                                parent_key: "config/fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config/fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidation_threshold".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidation_threshold".into(),
                                 value: config.liquidation_threshold.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "loan_to_value_ratio".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "loan_to_value_ratio".into(),
                                 value: config.loan_to_value_ratio.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "max_borrow_rate".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "max_borrow_rate".into(),
                                 value: config.max_borrow_rate.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "min_borrow_rate".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "min_borrow_rate".into(),
                                 value: config.min_borrow_rate.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "optimal_borrow_rate".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "optimal_borrow_rate".into(),
                                 value: config.optimal_borrow_rate.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "optimal_utilization_rate".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "optimal_utilization_rate".into(),
                                 value: config.optimal_utilization_rate.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::RefreshReserve => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "refresh-reserve".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "refresh-reserve".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![]
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::DepositReserveLiquidity { liquidity_amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "deposit-reserve-liquidity".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "deposit-reserve-liquidity".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidity_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidity_amount".into(),
                                 value: liquidity_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::RedeemReserveCollateral { collateral_amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "redeem-reserve-collateral".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "redeem-reserve-collateral".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "collateral_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "collateral_amount".into(),
                                 value: collateral_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::InitObligation => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "init-obligation".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "init-obligation".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![]
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::RefreshObligation => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "refresh-obligation".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "refresh-obligation".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![]
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::DepositObligationCollateral { collateral_amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "deposit-obligation-collateral".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "deposit-obligation-collateral".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "collateral_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "collateral_amount".into(),
                                 value: collateral_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::WithdrawObligationCollateral { collateral_amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "withdraw-obligation-collateral".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "withdraw-obligation-collateral".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "collateral_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "collateral_amount".into(),
                                 value: collateral_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::BorrowObligationLiquidity { liquidity_amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "borrow-obligation-liquidity".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "borrow-obligation-liquidity".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidity_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidity_amount".into(),
                                 value: liquidity_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::RepayObligationLiquidity { liquidity_amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "repay-obligation-liquidity".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "repay-obligation-liquidity".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidity_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidity_amount".into(),
                                 value: liquidity_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::LiquidateObligation { liquidity_amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "liquidate-obligation".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "liquidate-obligation".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidity_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidity_amount".into(),
                                 value: liquidity_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::FlashLoan { amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "flash-loan".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "flash-loan".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "amount".into(),
                                 value: amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
-                        ]
+                        ],
+                        raw_data: None,
                     })
                 }
             }
@@ -437,3 +625,72 @@ pub async fn fragment_instruction(
         }
     }
 }
+
+/// Decodes the data of an instruction into what [`fragment_instruction`] emits for it when its
+/// accounts aren't known, without allocating.
+pub fn decode_fast(data: &[u8]) -> Option<FastInstruction<'_>> {
+    use PropertyValueRef::{Pubkey as Address, U64};
+
+    let instruction = match LendingInstruction::unpack(data).ok()? {
+        LendingInstruction::InitLendingMarket { owner, quote_currency } => {
+            FastInstruction::new("init-lending-market")
+                .with("owner", Address(owner))
+                .with("quote_currency", Address(Pubkey::new_from_array(quote_currency)))
+        }
+        LendingInstruction::SetLendingMarketOwner { new_owner } => {
+            FastInstruction::new("set-lending-market-owner").with("new_owner", Address(new_owner))
+        }
+        LendingInstruction::InitReserve { liquidity_amount, config } => {
+            FastInstruction::new("init-reserve")
+                .with("liquidity_amount", U64(liquidity_amount))
+                .nested("flash_loan_fee_wad", "fees", U64(config.fees.flash_loan_fee_wad))
+                .nested("borrow_fee_wad", "config/fees", U64(config.fees.borrow_fee_wad))
+                .nested("host_fee_percentage", "config/fees",
+                        U64(config.fees.host_fee_percentage.into()))
+                .nested("liquidation_threshold", "config",
+                        U64(config.liquidation_threshold.into()))
+                .nested("loan_to_value_ratio", "config", U64(config.loan_to_value_ratio.into()))
+                .nested("max_borrow_rate", "config", U64(config.max_borrow_rate.into()))
+                .nested("min_borrow_rate", "config", U64(config.min_borrow_rate.into()))
+                .nested("optimal_borrow_rate", "config", U64(config.optimal_borrow_rate.into()))
+                .nested("optimal_utilization_rate", "config",
+                        U64(config.optimal_utilization_rate.into()))
+        }
+        LendingInstruction::RefreshReserve => FastInstruction::new("refresh-reserve"),
+        LendingInstruction::DepositReserveLiquidity { liquidity_amount } => {
+            FastInstruction::new("deposit-reserve-liquidity")
+                .with("liquidity_amount", U64(liquidity_amount))
+        }
+        LendingInstruction::RedeemReserveCollateral { collateral_amount } => {
+            FastInstruction::new("redeem-reserve-collateral")
+                .with("collateral_amount", U64(collateral_amount))
+        }
+        LendingInstruction::InitObligation => FastInstruction::new("init-obligation"),
+        LendingInstruction::RefreshObligation => FastInstruction::new("refresh-obligation"),
+        LendingInstruction::DepositObligationCollateral { collateral_amount } => {
+            FastInstruction::new("deposit-obligation-collateral")
+                .with("collateral_amount", U64(collateral_amount))
+        }
+        LendingInstruction::WithdrawObligationCollateral { collateral_amount } => {
+            FastInstruction::new("withdraw-obligation-collateral")
+                .with("collateral_amount", U64(collateral_amount))
+        }
+        LendingInstruction::BorrowObligationLiquidity { liquidity_amount } => {
+            FastInstruction::new("borrow-obligation-liquidity")
+                .with("liquidity_amount", U64(liquidity_amount))
+        }
+        LendingInstruction::RepayObligationLiquidity { liquidity_amount } => {
+            FastInstruction::new("repay-obligation-liquidity")
+                .with("liquidity_amount", U64(liquidity_amount))
+        }
+        LendingInstruction::LiquidateObligation { liquidity_amount } => {
+            FastInstruction::new("liquidate-obligation")
+                .with("liquidity_amount", U64(liquidity_amount))
+        }
+        LendingInstruction::FlashLoan { amount } => {
+            FastInstruction::new("flash-loan").with("amount", U64(amount))
+        }
+    };
+
+    Some(instruction)
+}
@@ -1,13 +1,65 @@
+use smallvec::smallvec;
 use spl_token_swap::curve::base::CurveType;
 use spl_token_swap::instruction::{unpack, SwapInstruction};
 use spl_token_swap::solana_program::program_error::ProgramError;
 use tracing::error;
 
-use crate::{Instruction, InstructionFunction, InstructionProperty, InstructionSet};
+use crate::{Instruction, InstructionFunction, InstructionProperty, InstructionSet, PropertyType};
+use crate::schema::ProgramSchema;
 
 pub const PROGRAM_ADDRESS: &str = "SwaPpA9LAaLfeLi3a68M4DjnLqgtticKg6CnyNwgAC8";
 
-pub async fn fragment_instruction(
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    ProgramSchema::new("token-swap")
+        .function("initialize", &[
+            ("host_fee_numerator", "fees", PropertyType::U64, "Numerator of the host fee"),
+            ("owner_trade_fee_numerator", "fees", PropertyType::U64,
+             "Numerator of the owner trade fee"),
+            ("owner_trade_fee_denominator", "fees", PropertyType::U64,
+             "Denominator of the owner trade fee"),
+            ("owner_withdraw_fee_numerator", "fees", PropertyType::U64,
+             "Numerator of the owner withdraw fee"),
+            ("owner_withdraw_fee_denominator", "fees", PropertyType::U64,
+             "Denominator of the owner withdraw fee"),
+            ("trade_fee_numerator", "fees", PropertyType::U64, "Numerator of the trade fee"),
+            ("nonce", "initialize_instruction", PropertyType::U64,
+             "Nonce the swap authority is derived with"),
+            ("trade_fee_denominator", "fees", PropertyType::U64, "Denominator of the trade fee"),
+            ("curve_type", "swap_curve", PropertyType::Text, "Curve pricing the swap"),
+        ])
+        .function("swap", &[
+            ("amount_in", "", PropertyType::U64, "Amount of source tokens swapped"),
+            ("minimum_amount_out", "", PropertyType::U64,
+             "Minimum amount of destination tokens received"),
+        ])
+        .function("deposit-all-token-types", &[
+            ("pool_token_amount", "", PropertyType::U64, "Amount of pool tokens"),
+            ("maximum_token_a_amount", "", PropertyType::U64,
+             "Maximum amount of token A deposited"),
+            ("maximum_token_b_amount", "", PropertyType::U64,
+             "Maximum amount of token B deposited"),
+        ])
+        .function("withdraw-all-token-types", &[
+            ("pool_token_amount", "", PropertyType::U64, "Amount of pool tokens"),
+            ("minimum_token_a_amount", "", PropertyType::U64,
+             "Minimum amount of token A withdrawn"),
+            ("minimum_token_b_amount", "", PropertyType::U64,
+             "Minimum amount of token B withdrawn"),
+        ])
+        .function("deposit-single-token-type-exact-amount-in", &[
+            ("minimum_pool_token_amount", "", PropertyType::U64,
+             "Minimum amount of pool tokens minted"),
+            ("source_token_amount", "", PropertyType::U64, "Amount of tokens deposited"),
+        ])
+        .function("withdraw-single-token-type-exact-amount-out", &[
+            ("maximum_pool_token_amount", "", PropertyType::U64,
+             "Maximum amount of pool tokens burnt"),
+            ("destination_token_amount", "", PropertyType::U64, "Amount of tokens withdrawn"),
+        ])
+}
+
+pub fn fragment_instruction(
     // The instruction in question.
     instruction: Instruction
 ) -> Option<InstructionSet> {
@@ -25,99 +77,109 @@ pub async fn fragment_instruction(
 
                     Option::from(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "initialize".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "initialize".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "host_fee_numerator".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "host_fee_numerator".into(),
                                 value: (&initialize_instruction.fees.host_fee_numerator).to_string(),
-                                parent_key: "fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "owner_trade_fee_numerator".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "owner_trade_fee_numerator".into(),
                                 value: (&initialize_instruction.fees.owner_trade_fee_numerator).to_string(),
-                                parent_key: "fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "owner_trade_fee_denominator".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "owner_trade_fee_denominator".into(),
                                 value:
                                 (&initialize_instruction.fees.owner_trade_fee_denominator)
                                     .to_string(),
-                                parent_key: "fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "owner_withdraw_fee_numerator".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "owner_withdraw_fee_numerator".into(),
                                 value:
                                 (&initialize_instruction.fees.owner_withdraw_fee_numerator)
                                     .to_string(),
-                                parent_key: "fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "owner_withdraw_fee_denominator".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "owner_withdraw_fee_denominator".into(),
                                 value:
                                 (&initialize_instruction.fees.owner_withdraw_fee_denominator)
                                     .to_string(),
-                                parent_key: "fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "trade_fee_numerator".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "trade_fee_numerator".into(),
                                 value:
                                 (&initialize_instruction.fees.trade_fee_numerator).to_string(),
-                                parent_key: "fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "nonce".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "nonce".into(),
                                 value: (&initialize_instruction.nonce).to_string(),
-                                parent_key: "initialize_instruction".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "initialize_instruction".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "trade_fee_denominator".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "trade_fee_denominator".into(),
                                 value:
                                 (&initialize_instruction.fees.trade_fee_denominator).to_string(),
-                                parent_key: "fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "curve_type".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "curve_type".into(),
                                 value: match initialize_instruction.swap_curve.curve_type {
                                     CurveType::ConstantProduct => "ConstantProduct".to_string(),
                                     // Flat line, always providing 1:1 from one token to another
@@ -127,197 +189,226 @@ pub async fn fragment_instruction(
                                     // Offset curve, like Uniswap, but the token B side has a faked offset
                                     CurveType::Offset => "Offset".to_string(),
                                 },
-                                parent_key: "swap_curve".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "swap_curve".into(),
+                                timestamp: instruction.timestamp,
                             },
                             // InstructionProperty {
-                            //     tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            //     tx_instruction_id: instruction.tx_instruction_id,
                             //     transaction_hash: instruction.transaction_hash.clone(),
-                            //     parent_index: instruction.parent_index.clone(),
-                            //     key: "calculator".to_string(),
+                            //     parent_index: instruction.parent_index,
+                            //     key: "calculator".into(),
                             //     value: initialize_instruction.swap_curve.calculator.to_string(),
-                            //     parent_key: "swap_curve".to_string(),
-                            //     timestamp: instruction.timestamp.clone(),
+                            //     parent_key: "swap_curve".into(),
+                            //     timestamp: instruction.timestamp,
                             // },
                         ],
+                        raw_data: None,
                     })
                 }
                 SwapInstruction::Swap(swap) => {
                     Option::from(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "swap".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "swap".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "amount_in".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "amount_in".into(),
                                 value: swap.amount_in.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "minimum_amount_out".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "minimum_amount_out".into(),
                                 value: swap.minimum_amount_out.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 SwapInstruction::DepositAllTokenTypes(datt) => {
                     Option::from(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "deposit-all-token-types".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "deposit-all-token-types".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "pool_token_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "pool_token_amount".into(),
                                 value: datt.pool_token_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "maximum_token_a_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "maximum_token_a_amount".into(),
                                 value: datt.maximum_token_a_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "maximum_token_b_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "maximum_token_b_amount".into(),
                                 value: datt.maximum_token_b_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 SwapInstruction::WithdrawAllTokenTypes(watt) => {
                     Option::from(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "withdraw-all-token-types".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "withdraw-all-token-types".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "pool_token_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "pool_token_amount".into(),
                                 value: watt.pool_token_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "minimum_token_a_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "minimum_token_a_amount".into(),
                                 value: watt.minimum_token_a_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "minimum_token_b_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "minimum_token_b_amount".into(),
                                 value: watt.minimum_token_b_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 SwapInstruction::DepositSingleTokenTypeExactAmountIn(dstteai) => {
                     Option::from(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "deposit-single-token-type-exact-amount-in".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "deposit-single-token-type-exact-amount-in".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "minimum_pool_token_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "minimum_pool_token_amount".into(),
                                 value: dstteai.minimum_pool_token_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "source_token_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "source_token_amount".into(),
                                 value: dstteai.source_token_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 SwapInstruction::WithdrawSingleTokenTypeExactAmountOut(wstteao) => {
                     Option::from(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "withdraw-single-token-type-exact-amount-out".to_string(),
-                            timestamp: instruction.timestamp.clone()
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "withdraw-single-token-type-exact-amount-out".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "maximum_pool_token_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "maximum_pool_token_amount".into(),
                                 value: wstteao.maximum_pool_token_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "destination_token_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "destination_token_amount".into(),
                                 value: wstteao.destination_token_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
             }
@@ -1,18 +1,109 @@
+use smallvec::smallvec;
 use solana_program::instruction::InstructionError;
 use solana_sdk::program_utils::limited_deserialize;
 use solana_vote_program::vote_instruction::VoteInstruction;
 use solana_vote_program::vote_state::VoteAuthorize;
 use tracing::error;
 
-use crate::{Instruction, InstructionFunction, InstructionProperty, InstructionSet};
+use crate::{Instruction, InstructionFunction, InstructionProperty, InstructionSet, PropertyType};
+use crate::programs::layout::{self, Reader};
+use crate::schema::ProgramSchema;
 
 pub const PROGRAM_ADDRESS: &str = "Vote111111111111111111111111111111111111111";
 
+// The instructions that came after the solana version this crate is on, which its
+// `VoteInstruction` doesn't deserialize, by tag. Validators vote with the compact ones.
+const UPDATE_VOTE_STATE: u32 = 8;
+const UPDATE_VOTE_STATE_SWITCH: u32 = 9;
+const AUTHORIZE_WITH_SEED: u32 = 10;
+const AUTHORIZE_CHECKED_WITH_SEED: u32 = 11;
+const COMPACT_UPDATE_VOTE_STATE: u32 = 12;
+const COMPACT_UPDATE_VOTE_STATE_SWITCH: u32 = 13;
+const TOWER_SYNC: u32 = 14;
+const TOWER_SYNC_SWITCH: u32 = 15;
+
+// The properties of an instruction: key, parent key, value and type.
+type Fields = Vec<(&'static str, &'static str, String, PropertyType)>;
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let vote_state_update = tower_keys("vote_state_update");
+    let tower_sync = [&tower_keys("tower_sync")[..], &[
+        ("block_id", "tower_sync", PropertyType::Bytes, "Id of the block voted on"),
+    ]].concat();
+    let switch = ("hash", "", PropertyType::Bytes, "Hash of the proof of the fork switched to");
+    let authorize_with_seed = [
+        ("vote_authorize", "", PropertyType::Text, "Authority changed (Voter or Withdrawer)"),
+        ("current_authority_derived_key_owner", "", PropertyType::Pubkey,
+         "Owner of the program the current authority is derived for"),
+        ("current_authority_derived_key_seed", "", PropertyType::Text,
+         "Seed the current authority is derived with"),
+    ];
+    let new_authority = ("new_authority", "", PropertyType::Pubkey, "New authority");
+
+    ProgramSchema::new("vote")
+        .function("initialize-account", &[
+            ("node_pubkey", "vote_init", PropertyType::Pubkey, "Identity of the validator"),
+            ("commission", "vote_init", PropertyType::U64,
+             "Share of the rewards kept by the validator, in percent"),
+            ("authorized_withdrawer", "vote_init", PropertyType::Pubkey,
+             "Authority allowed to withdraw from the vote account"),
+            ("authorized_voter", "vote_init", PropertyType::Pubkey,
+             "Authority voting on behalf of the validator"),
+        ])
+        .function("authorize", &[
+            ("voter_pubkey", "", PropertyType::Pubkey, "New authority"),
+            ("vote_authorize", "", PropertyType::Text, "Authority changed (Voter or Withdrawer)"),
+        ])
+        .function("vote-authorize", &[
+            ("vote_authorize", "", PropertyType::Text, "Authority changed (Voter or Withdrawer)"),
+        ])
+        .function("update-validator-identity", &[])
+        .function("update-commission", &[
+            ("commission", "", PropertyType::U64,
+             "Share of the rewards kept by the validator, in percent"),
+        ])
+        .function("vote-switch", &[
+            ("hash", "vote", PropertyType::Bytes, "Hash of the bank voted on"),
+            ("slots", "vote", PropertyType::Text, "Slots voted on"),
+            ("hash", "", PropertyType::Bytes, "Hash of the proof of the fork switched to"),
+        ])
+        .function("vote", &[
+            ("hash", "vote", PropertyType::Bytes, "Hash of the bank voted on"),
+            ("slots", "vote", PropertyType::Text, "Slots voted on"),
+        ])
+        .function("withdraw", &[
+            ("lamports", "", PropertyType::U64, "Amount of lamports withdrawn"),
+        ])
+        .function("update-vote-state", &vote_state_update)
+        .function("update-vote-state-switch", &[&vote_state_update[..], &[switch]].concat())
+        .function("compact-update-vote-state", &vote_state_update)
+        .function("compact-update-vote-state-switch",
+                  &[&vote_state_update[..], &[switch]].concat())
+        .function("tower-sync", &tower_sync)
+        .function("tower-sync-switch", &[&tower_sync[..], &[switch]].concat())
+        .function("authorize-with-seed", &[&authorize_with_seed[..], &[new_authority]].concat())
+        .function("authorize-checked-with-seed", &authorize_with_seed)
+}
+
+/// The keys of the vote state updates and tower syncs, nested under `parent_key`.
+fn tower_keys(parent_key: &'static str)
+    -> Vec<(&'static str, &'static str, PropertyType, &'static str)> {
+    vec![
+        ("slots", parent_key, PropertyType::Text, "Slots voted on"),
+        ("root", parent_key, PropertyType::U64, "Root slot of the tower, empty if none"),
+        ("hash", parent_key, PropertyType::Bytes, "Hash of the bank voted on"),
+        ("timestamp", parent_key, PropertyType::Timestamp,
+         "Time of the last slot voted on, empty if none"),
+    ]
+}
+
+
 /// Extracts the contents of an instruction into small bits and pieces, or what we would call,
 /// instruction_properties.
 ///
 /// The function should return a list of instruction properties extracted from an instruction.
-pub async fn fragment_instruction(
+pub fn fragment_instruction(
     // The instruction
     instruction: Instruction,
 ) -> Option<InstructionSet> {
@@ -37,51 +128,58 @@ pub async fn fragment_instruction(
                     // );
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "initialize-account".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "initialize-account".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "node_pubkey".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "node_pubkey".into(),
                                 value: vote_init.node_pubkey.to_string(),
-                                parent_key: "vote_init".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "vote_init".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "commission".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "commission".into(),
                                 value: vote_init.commission.to_string(),
-                                parent_key: "vote_init".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "vote_init".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "authorized_withdrawer".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "authorized_withdrawer".into(),
                                 value: vote_init.authorized_withdrawer.to_string(),
-                                parent_key: "vote_init".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "vote_init".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "authorized_voter".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "authorized_voter".into(),
                                 value: vote_init.authorized_voter.to_string(),
-                                parent_key: "vote_init".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "vote_init".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 VoteInstruction::Authorize(voter_pubkey, vote_authorize) => {
@@ -94,62 +192,71 @@ pub async fn fragment_instruction(
                     // )
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "authorize".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "authorize".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "voter_pubkey".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "voter_pubkey".into(),
                                 value: voter_pubkey.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "vote_authorize".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "vote_authorize".into(),
                                 value: match vote_authorize {
                                     VoteAuthorize::Voter => "voter".to_string(),
                                     VoteAuthorize::Withdrawer => "withdrawer".to_string()
                                 },
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 VoteInstruction::AuthorizeChecked(vote_authorize) => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "vote-authorize".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "vote-authorize".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "vote_authorize".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "vote_authorize".into(),
                                 value: match vote_authorize {
                                     VoteAuthorize::Voter => "voter".to_string(),
                                     VoteAuthorize::Withdrawer => "withdrawer".to_string()
                                 },
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 VoteInstruction::UpdateValidatorIdentity => {
@@ -160,92 +267,105 @@ pub async fn fragment_instruction(
                     // )
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "update-validator-identity".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "update-validator-identity".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![],
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 VoteInstruction::UpdateCommission(commission) => {
                     // vote_state::update_commission(me, commission, &signers)
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "update-commission".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "update-commission".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "commission".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "commission".into(),
                                 value: commission.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 VoteInstruction::VoteSwitch(vote, hash) => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "vote-switch".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "vote-switch".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "hash".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "hash".into(),
                                 value: bs58::encode(vote.hash.0).into_string(),
-                                parent_key: "vote".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Bytes,
+                                parent_key: "vote".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "slots".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "slots".into(),
                                 value: serde_json::to_string(vote.slots.as_slice()).unwrap(),
-                                parent_key: "vote".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "vote".into(),
+                                timestamp: instruction.timestamp,
                             },
                             // InstructionProperty {
-                            //     tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            //     tx_instruction_id: instruction.tx_instruction_id,
                             //     transaction_hash: instruction.transaction_hash.clone(),
-                            //     parent_index: instruction.parent_index.clone(),
-                            //     key: "timestamp".to_string(),
+                            //     parent_index: instruction.parent_index,
+                            //     key: "timestamp".into(),
                             //     value: if let Some(ts) = vote.timestamp {
                             //         ts.to_string()
                             //     } else {
                             //         "".to_string()
                             //     },
-                            //     parent_key: "vote".to_string(),
-                            //     timestamp: instruction.timestamp.clone(),
+                            //     parent_key: "vote".into(),
+                            //     timestamp: instruction.timestamp,
                             // },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "hash".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "hash".into(),
                                 value: bs58::encode(hash.0).into_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Bytes,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 VoteInstruction::Vote(vote) => {
@@ -259,46 +379,51 @@ pub async fn fragment_instruction(
                     // )
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "vote".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "vote".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "hash".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "hash".into(),
                                 value: bs58::encode(vote.hash.0).into_string(),
-                                parent_key: "vote".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Bytes,
+                                parent_key: "vote".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "slots".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "slots".into(),
                                 value: serde_json::to_string(vote.slots.as_slice()).unwrap(),
-                                parent_key: "vote".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Text,
+                                parent_key: "vote".into(),
+                                timestamp: instruction.timestamp,
                             },
                             // InstructionProperty {
-                            //     tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            //     tx_instruction_id: instruction.tx_instruction_id,
                             //     transaction_hash: instruction.transaction_hash.clone(),
-                            //     parent_index: instruction.parent_index.clone(),
-                            //     key: "timestamp".to_string(),
+                            //     parent_index: instruction.parent_index,
+                            //     key: "timestamp".into(),
                             //     value: if let Some(ts) = vote.timestamp {
                             //         ts.to_string()
                             //     } else {
                             //         "".to_string()
                             //     },
-                            //     parent_key: "vote".to_string(),
-                            //     timestamp: instruction.timestamp.clone(),
+                            //     parent_key: "vote".into(),
+                            //     timestamp: instruction.timestamp,
                             // }
                         ],
+                        raw_data: None,
                     })
                 }
                 VoteInstruction::Withdraw(lamports) => {
@@ -307,29 +432,36 @@ pub async fn fragment_instruction(
                     // vote_state::update_commission(me, commission, &signers)
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "withdraw".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "withdraw".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "lamports".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "lamports".into(),
                                 value: lamports.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
             }
         }
         Err(err) => {
+            if let Some(instruction_set) = newer_instruction(&instruction) {
+                return Some(instruction_set);
+            }
             let err_msg = match err {
                 InstructionError::GenericError => "GenericError".to_string(),
                 InstructionError::InvalidArgument => "InvalidArgument".to_string(),
@@ -387,4 +519,289 @@ pub async fn fragment_instruction(
             None
         }
     }
-}
\ No newline at end of file
+}
+
+/// Decodes the instructions newer than this version's `VoteInstruction`, by tag.
+fn newer_instruction(instruction: &Instruction) -> Option<InstructionSet> {
+    let mut arguments = Reader::new(&instruction.data);
+    let (function_name, fields) = match arguments.u32()? {
+        UPDATE_VOTE_STATE => ("update-vote-state", vote_state_update(&mut arguments)?),
+        UPDATE_VOTE_STATE_SWITCH => {
+            let fields = vote_state_update(&mut arguments)?;
+            ("update-vote-state-switch", switch(fields, &mut arguments)?)
+        }
+        COMPACT_UPDATE_VOTE_STATE => {
+            ("compact-update-vote-state", compact_tower(&mut arguments, "vote_state_update")?)
+        }
+        COMPACT_UPDATE_VOTE_STATE_SWITCH => {
+            let fields = compact_tower(&mut arguments, "vote_state_update")?;
+            ("compact-update-vote-state-switch", switch(fields, &mut arguments)?)
+        }
+        TOWER_SYNC => ("tower-sync", compact_tower(&mut arguments, "tower_sync")?),
+        TOWER_SYNC_SWITCH => {
+            let fields = compact_tower(&mut arguments, "tower_sync")?;
+            ("tower-sync-switch", switch(fields, &mut arguments)?)
+        }
+        AUTHORIZE_WITH_SEED => ("authorize-with-seed", authorize_with_seed(&mut arguments, true)?),
+        AUTHORIZE_CHECKED_WITH_SEED => {
+            ("authorize-checked-with-seed", authorize_with_seed(&mut arguments, false)?)
+        }
+        _ => return None,
+    };
+
+    let mut instruction_set = layout::instruction_set(instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, parent_key, value, value_type)| {
+            layout::property(instruction, key, parent_key, value, value_type)
+        })
+        .collect();
+
+    Some(instruction_set)
+}
+
+/// A `VoteStateUpdate`, as bincode serializes it: its lockouts (a slot and a confirmation count),
+/// root, hash and timestamp.
+fn vote_state_update(arguments: &mut Reader) -> Option<Fields> {
+    let len = arguments.u64()? as usize;
+    if len.checked_mul(12)? > arguments.remaining() {
+        return None;
+    }
+    let slots = (0..len)
+        .map(|_| {
+            let slot = arguments.u64()?;
+            arguments.u32()?;
+            Some(slot)
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let root = arguments.option(Reader::u64)?;
+
+    tower(arguments, "vote_state_update", slots, root)
+}
+
+/// A vote state update or a tower sync (nested under `parent_key`) in its compact form: the root,
+/// `u64::MAX` when there's none, then every slot voted on as an offset from the previous one,
+/// with its confirmation count. Tower syncs end with the id of the block voted on.
+fn compact_tower(arguments: &mut Reader, parent_key: &'static str) -> Option<Fields> {
+    let root = Some(arguments.u64()?).filter(|root| *root != u64::MAX);
+    let len = short_vec_len(arguments)?;
+    // An offset and a confirmation count take two bytes at least.
+    if len.checked_mul(2)? > arguments.remaining() {
+        return None;
+    }
+    let mut slot = root.unwrap_or_default();
+    let mut slots = Vec::with_capacity(len);
+    for _ in 0..len {
+        slot = slot.checked_add(varint(arguments)?)?;
+        arguments.u8()?;
+        slots.push(slot);
+    }
+
+    let mut fields = tower(arguments, parent_key, slots, root)?;
+    if parent_key == "tower_sync" {
+        let block_id = bs58::encode(arguments.bytes(32)?).into_string();
+        fields.push(("block_id", parent_key, block_id, PropertyType::Bytes));
+    }
+    Some(fields)
+}
+
+/// The slots voted on and the root of a tower, followed by its hash and timestamp.
+fn tower(arguments: &mut Reader, parent_key: &'static str, slots: Vec<u64>, root: Option<u64>)
+    -> Option<Fields> {
+    let hash = bs58::encode(arguments.bytes(32)?).into_string();
+    let timestamp = arguments.option(Reader::i64)?
+        .map(|timestamp| timestamp.to_string())
+        .unwrap_or_default();
+
+    Some(vec![
+        ("slots", parent_key, serde_json::to_string(&slots).unwrap(), PropertyType::Text),
+        ("root", parent_key, root.map(|root| root.to_string()).unwrap_or_default(),
+         PropertyType::U64),
+        ("hash", parent_key, hash, PropertyType::Bytes),
+        ("timestamp", parent_key, timestamp, PropertyType::Timestamp),
+    ])
+}
+
+/// `fields`, followed by the hash of the proof of the fork switched to.
+fn switch(mut fields: Fields, arguments: &mut Reader) -> Option<Fields> {
+    let hash = bs58::encode(arguments.bytes(32)?).into_string();
+    fields.push(("hash", "", hash, PropertyType::Bytes));
+    Some(fields)
+}
+
+/// `VoteAuthorizeWithSeedArgs`, or `VoteAuthorizeCheckedWithSeedArgs` when the new authority
+/// signs (and isn't in the arguments).
+fn authorize_with_seed(arguments: &mut Reader, with_new_authority: bool) -> Option<Fields> {
+    let vote_authorize = match arguments.u32()? {
+        0 => "voter",
+        1 => "withdrawer",
+        _ => return None,
+    };
+    let owner = arguments.pubkey()?;
+    let seed_len = arguments.u64()? as usize;
+    let seed = std::str::from_utf8(arguments.bytes(seed_len)?).ok()?;
+
+    let mut fields = vec![
+        ("vote_authorize", "", vote_authorize.to_string(), PropertyType::Text),
+        ("current_authority_derived_key_owner", "", owner.to_string(), PropertyType::Pubkey),
+        ("current_authority_derived_key_seed", "", seed.to_string(), PropertyType::Text),
+    ];
+    if with_new_authority {
+        fields.push(("new_authority", "", arguments.pubkey()?.to_string(), PropertyType::Pubkey));
+    }
+    Some(fields)
+}
+
+/// The length of a `short_vec`: a u16 in groups of 7 bits, the high bit set when one follows.
+fn short_vec_len(arguments: &mut Reader) -> Option<usize> {
+    let mut len = 0;
+    for shift in &[0, 7, 14] {
+        let byte = arguments.u8()?;
+        len |= ((byte & 0x7f) as usize) << *shift;
+        if byte & 0x80 == 0 {
+            return Some(len);
+        }
+    }
+    None
+}
+
+/// A `serde_varint` u64: groups of 7 bits, least significant first, the high bit set when one
+/// follows.
+fn varint(arguments: &mut Reader) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = arguments.u8()?;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::hash::Hash;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_vote_program::vote_state::Vote;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(data: Vec<u8>) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, data))
+    }
+
+    fn decode_vote(vote_instruction: VoteInstruction) -> InstructionSet {
+        decode(bincode::serialize(&vote_instruction).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn votes_decode_with_their_slots_and_hash() {
+        let (hash, proof) = (Hash::new(&[7; 32]), Hash::new(&[8; 32]));
+        let vote = decode_vote(VoteInstruction::Vote(Vote::new(vec![41, 42], hash)));
+        assert_eq!(vote.function.function_name.as_str(), "vote");
+        assert_eq!(properties(&vote), vec![
+            ("vote.hash", hash.to_string()),
+            ("vote.slots", "[41,42]".to_string()),
+        ]);
+
+        let switch = decode_vote(VoteInstruction::VoteSwitch(Vote::new(vec![43], hash), proof));
+        assert_eq!(switch.function.function_name.as_str(), "vote-switch");
+        assert_eq!(properties(&switch), vec![
+            ("vote.hash", hash.to_string()),
+            ("vote.slots", "[43]".to_string()),
+            ("hash", proof.to_string()),
+        ]);
+    }
+
+    #[test]
+    fn authorities_and_withdrawals_decode() {
+        let voter = Pubkey::new_unique();
+        let authorize = decode_vote(VoteInstruction::Authorize(voter, VoteAuthorize::Voter));
+        assert_eq!(authorize.function.function_name.as_str(), "authorize");
+        assert_eq!(properties(&authorize), vec![
+            ("voter_pubkey", voter.to_string()),
+            ("vote_authorize", "voter".to_string()),
+        ]);
+
+        let identity = decode_vote(VoteInstruction::UpdateValidatorIdentity);
+        assert_eq!(identity.function.function_name.as_str(), "update-validator-identity");
+        assert!(identity.properties.is_empty());
+
+        let withdraw = decode_vote(VoteInstruction::Withdraw(1_500));
+        assert_eq!(withdraw.function.function_name.as_str(), "withdraw");
+        assert_eq!(properties(&withdraw), vec![("lamports", "1500".to_string())]);
+    }
+
+    #[test]
+    fn instructions_newer_than_the_sdk_decode() {
+        let (hash, block_id, proof) =
+            (Hash::new(&[3; 32]), Hash::new(&[4; 32]), Hash::new(&[5; 32]));
+        // A tower rooted at 100 voting on 101 and 401 (an offset of 300, over two bytes).
+        let mut tower_sync = TOWER_SYNC.to_le_bytes().to_vec();
+        tower_sync.extend_from_slice(&100u64.to_le_bytes());
+        tower_sync.extend_from_slice(&[2, 1, 2, 0xac, 0x02, 1]);
+        tower_sync.extend_from_slice(hash.as_ref());
+        tower_sync.push(1);
+        tower_sync.extend_from_slice(&1_700_000_000i64.to_le_bytes());
+        tower_sync.extend_from_slice(block_id.as_ref());
+        let decoded = decode(tower_sync.clone()).unwrap();
+        assert_eq!(decoded.function.function_name.as_str(), "tower-sync");
+        assert_eq!(properties(&decoded), vec![
+            ("tower_sync.slots", "[101,401]".to_string()),
+            ("tower_sync.root", "100".to_string()),
+            ("tower_sync.hash", hash.to_string()),
+            ("tower_sync.timestamp", "1700000000".to_string()),
+            ("tower_sync.block_id", block_id.to_string()),
+        ]);
+
+        // Without a root nor a timestamp, and switching forks.
+        let mut compact = COMPACT_UPDATE_VOTE_STATE_SWITCH.to_le_bytes().to_vec();
+        compact.extend_from_slice(&u64::MAX.to_le_bytes());
+        compact.extend_from_slice(&[1, 42, 1]);
+        compact.extend_from_slice(hash.as_ref());
+        compact.push(0);
+        compact.extend_from_slice(proof.as_ref());
+        let decoded = decode(compact).unwrap();
+        assert_eq!(decoded.function.function_name.as_str(), "compact-update-vote-state-switch");
+        assert_eq!(properties(&decoded), vec![
+            ("vote_state_update.slots", "[42]".to_string()),
+            ("vote_state_update.root", "".to_string()),
+            ("vote_state_update.hash", hash.to_string()),
+            ("vote_state_update.timestamp", "".to_string()),
+            ("hash", proof.to_string()),
+        ]);
+
+        // A lockout of slot 42, rooted at 40.
+        let mut update = UPDATE_VOTE_STATE.to_le_bytes().to_vec();
+        update.extend_from_slice(&1u64.to_le_bytes());
+        update.extend_from_slice(&42u64.to_le_bytes());
+        update.extend_from_slice(&1u32.to_le_bytes());
+        update.push(1);
+        update.extend_from_slice(&40u64.to_le_bytes());
+        update.extend_from_slice(hash.as_ref());
+        update.push(0);
+        let decoded = decode(update).unwrap();
+        assert_eq!(decoded.function.function_name.as_str(), "update-vote-state");
+        assert_eq!(properties(&decoded)[..2], [
+            ("vote_state_update.slots", "[42]".to_string()),
+            ("vote_state_update.root", "40".to_string()),
+        ]);
+
+        let owner = Pubkey::new_unique();
+        let mut authorize = AUTHORIZE_CHECKED_WITH_SEED.to_le_bytes().to_vec();
+        authorize.extend_from_slice(&1u32.to_le_bytes());
+        authorize.extend_from_slice(owner.as_ref());
+        authorize.extend_from_slice(&4u64.to_le_bytes());
+        authorize.extend_from_slice(b"vote");
+        assert_eq!(properties(&decode(authorize).unwrap()), vec![
+            ("vote_authorize", "withdrawer".to_string()),
+            ("current_authority_derived_key_owner", owner.to_string()),
+            ("current_authority_derived_key_seed", "vote".to_string()),
+        ]);
+
+        // A tower sync without its block id, and an unknown instruction.
+        assert!(decode(tower_sync[..tower_sync.len() - 1].to_vec()).is_none());
+        assert!(decode(16u32.to_le_bytes().to_vec()).is_none());
+    }
+}
@@ -0,0 +1,340 @@
+//! The OpenBook v2 order book, which succeeded Serum (see `serum_market`) with an Anchor program
+//! of its own: every instruction starts with the first 8 bytes of
+//! `sha256("global:<instruction name>")`, followed by its borsh encoded arguments.
+//!
+//! Prices and sizes stay in the lots of the market, which every instruction decoded names as
+//! `market` when its accounts are known, so that fills can be grouped per market.
+
+use std::convert::TryFrom;
+
+use smallvec::{smallvec, SmallVec};
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS: &str = "opnb2LAfJYbRMAHHvqjCwQxanZn7ReEHp1k81EohpZb";
+
+const PLACE_ORDER: [u8; 8] = [51, 194, 155, 175, 109, 130, 96, 106];
+const PLACE_TAKE_ORDER: [u8; 8] = [3, 44, 71, 3, 26, 199, 203, 85];
+const CANCEL_ORDER: [u8; 8] = [95, 129, 237, 240, 8, 49, 223, 132];
+const CANCEL_ALL_ORDERS: [u8; 8] = [196, 83, 243, 171, 17, 100, 160, 143];
+const CONSUME_EVENTS: [u8; 8] = [221, 145, 177, 52, 31, 47, 63, 201];
+const SETTLE_FUNDS: [u8; 8] = [238, 64, 163, 96, 75, 171, 16, 33];
+const DEPOSIT: [u8; 8] = [242, 35, 198, 137, 82, 225, 242, 182];
+const REFILL: [u8; 8] = [128, 207, 142, 11, 54, 232, 38, 201];
+
+// The names of the variants of the enums of the arguments, by discriminant.
+const SIDES: &[&str] = &["bid", "ask"];
+const ORDER_TYPES: &[&str] = &[
+    "limit", "immediate-or-cancel", "post-only", "market", "post-only-slide", "fill-or-kill",
+];
+const SELF_TRADE_BEHAVIORS: &[&str] = &["decrement-take", "cancel-provide", "abort-transaction"];
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let market = ("market", "", PropertyType::Pubkey,
+                  "Market of the instruction, when the accounts of the instruction are known");
+    let side = ("side", "", PropertyType::Text, "Side of the order (bid or ask)");
+    let order = [
+        ("price_lots", "", PropertyType::I128, "Limit price, in quote lots per base lot"),
+        ("max_base_lots", "", PropertyType::I128, "Maximum size of the order, in base lots"),
+        ("max_quote_lots_including_fees", "", PropertyType::I128,
+         "Maximum size of the order, in quote lots, fees included"),
+        ("order_type", "", PropertyType::Text,
+         "Order type (limit, immediate-or-cancel, post-only, market, post-only-slide or \
+         fill-or-kill)"),
+        ("limit", "", PropertyType::U64, "Maximum number of orders matched"),
+    ];
+    let transfer = [
+        ("base_amount", "", PropertyType::U64, "Amount of base tokens, in base units"),
+        ("quote_amount", "", PropertyType::U64, "Amount of quote tokens, in base units"),
+        market,
+    ];
+
+    ProgramSchema::new("openbook-v2")
+        .function("place-order", &[
+            side, order[0], order[1], order[2], order[3], order[4],
+            ("client_order_id", "", PropertyType::U64, "Order id chosen by the client"),
+            ("self_trade_behavior", "", PropertyType::Text,
+             "What happens to orders matching orders of the same owner (decrement-take, \
+             cancel-provide or abort-transaction)"),
+            market,
+        ])
+        .function("place-take-order", &[
+            side, order[0], order[1], order[2], order[3], order[4], market,
+        ])
+        .function("cancel-order", &[
+            ("order_id", "", PropertyType::Decimal, "Id of the order cancelled"),
+            market,
+        ])
+        .function("cancel-all-orders", &[
+            ("side", "", PropertyType::Text,
+             "Side of the orders cancelled (bid or ask), absent for both"),
+            ("limit", "", PropertyType::U64, "Maximum number of orders cancelled"),
+            market,
+        ])
+        .function("consume-events", &[
+            ("limit", "", PropertyType::U64, "Maximum number of events consumed"),
+            market,
+        ])
+        .function("settle-funds", &[market])
+        .function("deposit", &transfer)
+        .function("refill", &transfer)
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    if instruction.data.len() < 8 {
+        error!("[spi-wrapper/openbook-v2] Attempt to parse instruction from program {} failed \
+            as it is shorter than a discriminator.", instruction.program);
+
+        return None;
+    }
+    let (discriminator, arguments) = instruction.data.split_at(8);
+    let mut arguments = Reader::new(arguments);
+
+    // The function, its arguments and where the market is in its accounts.
+    let decoded = match <[u8; 8]>::try_from(discriminator).ok()? {
+        PLACE_ORDER => place_order(&mut arguments)
+            .map(|properties| ("place-order", properties, 4)),
+        PLACE_TAKE_ORDER => place_take_order(&mut arguments)
+            .map(|properties| ("place-take-order", properties, 2)),
+        CANCEL_ORDER => arguments.u128().map(|order_id| {
+            let properties: Fields = smallvec![
+                ("order_id", order_id.to_string(), PropertyType::Decimal),
+            ];
+            ("cancel-order", properties, 2)
+        }),
+        CANCEL_ALL_ORDERS => cancel_all_orders(&mut arguments)
+            .map(|properties| ("cancel-all-orders", properties, 2)),
+        // A `usize`, which Anchor encodes as a u64.
+        CONSUME_EVENTS => arguments.u64().map(|limit| {
+            let properties: Fields = smallvec![("limit", limit.to_string(), PropertyType::U64)];
+            ("consume-events", properties, 1)
+        }),
+        SETTLE_FUNDS => Some(("settle-funds", smallvec![], 3)),
+        DEPOSIT => transfer(&mut arguments).map(|properties| ("deposit", properties, 4)),
+        REFILL => transfer(&mut arguments).map(|properties| ("refill", properties, 4)),
+        _ => {
+            debug!("[spi-wrapper/openbook-v2] Instruction {} of {} isn't one the processor \
+                decodes.", hex::encode(discriminator), instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let (function_name, fields, market_account) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/openbook-v2] Attempt to parse instruction from program {} \
+                failed due to truncated or invalid arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, value, value_type)| layout::property(&instruction, key, "", value, value_type))
+        .collect();
+    if let Some(market) = accounts.get(market_account) {
+        instruction_set.properties.push(layout::property(
+            &instruction, "market", "", market.to_string(), PropertyType::Pubkey));
+    }
+
+    Some(instruction_set)
+}
+
+// The properties of an instruction: key, value and type.
+type Fields = SmallVec<[(&'static str, String, PropertyType); 8]>;
+
+/// The fields `PlaceOrderArgs` and `PlaceTakeOrderArgs` start with.
+fn order(arguments: &mut Reader) -> Option<Fields> {
+    let side = arguments.name(SIDES)?;
+    let price_lots = arguments.i64()?;
+    let max_base_lots = arguments.i64()?;
+    let max_quote_lots_including_fees = arguments.i64()?;
+
+    Some(smallvec![
+        ("side", side.to_string(), PropertyType::Text),
+        ("price_lots", price_lots.to_string(), PropertyType::I128),
+        ("max_base_lots", max_base_lots.to_string(), PropertyType::I128),
+        ("max_quote_lots_including_fees", max_quote_lots_including_fees.to_string(),
+         PropertyType::I128),
+    ])
+}
+
+fn place_order(arguments: &mut Reader) -> Option<Fields> {
+    let mut properties = order(arguments)?;
+    let client_order_id = arguments.u64()?;
+    let order_type = arguments.name(ORDER_TYPES)?;
+    // Orders never expire when 0.
+    let _expiry_timestamp = arguments.u64()?;
+    let self_trade_behavior = arguments.name(SELF_TRADE_BEHAVIORS)?;
+    let limit = arguments.u8()?;
+
+    properties.extend(vec![
+        ("client_order_id", client_order_id.to_string(), PropertyType::U64),
+        ("order_type", order_type.to_string(), PropertyType::Text),
+        ("self_trade_behavior", self_trade_behavior.to_string(), PropertyType::Text),
+        ("limit", limit.to_string(), PropertyType::U64),
+    ]);
+    Some(properties)
+}
+
+fn place_take_order(arguments: &mut Reader) -> Option<Fields> {
+    let mut properties = order(arguments)?;
+    let order_type = arguments.name(ORDER_TYPES)?;
+    let limit = arguments.u8()?;
+
+    properties.push(("order_type", order_type.to_string(), PropertyType::Text));
+    properties.push(("limit", limit.to_string(), PropertyType::U64));
+    Some(properties)
+}
+
+fn cancel_all_orders(arguments: &mut Reader) -> Option<Fields> {
+    let side = arguments.option(|arguments| arguments.name(SIDES))?;
+    let limit = arguments.u8()?;
+
+    let mut properties: Fields = side.into_iter()
+        .map(|side| ("side", side.to_string(), PropertyType::Text))
+        .collect();
+    properties.push(("limit", limit.to_string(), PropertyType::U64));
+    Some(properties)
+}
+
+/// The arguments of `deposit` and `refill`.
+fn transfer(arguments: &mut Reader) -> Option<Fields> {
+    let base_amount = arguments.u64()?;
+    let quote_amount = arguments.u64()?;
+
+    Some(smallvec![
+        ("base_amount", base_amount.to_string(), PropertyType::U64),
+        ("quote_amount", quote_amount.to_string(), PropertyType::U64),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use solana_sdk::hash::hash;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    // Instruction data as sent to the program, discriminator included.
+    // A post only bid of 10 base lots at 2250 quote lots, with client order id 7.
+    const PLACE_ORDER_BID: &str = "33c29baf6d82606a00ca080000000000000a00000000000000ffffffffffffff\
+        7f0700000000000000020000000000000000000a";
+    // A market ask of 3 base lots, at 2000 quote lots at worst.
+    const PLACE_TAKE_ORDER_ASK: &str = "032c47031ac7cb5501d007000000000000030000000000000000e1f505\
+        00000000030a";
+    const CANCEL_ORDER_1234: &str = "5f81edf00831df84d2040000000000000000000000000000";
+    // The asks only, 20 at most.
+    const CANCEL_ALL_ASKS: &str = "c453f3ab1164a08f010114";
+    const CONSUME_EVENTS_8: &str = "dd91b1341f2f3fc90800000000000000";
+    // Settling everything the open orders account is owed.
+    const SETTLE_ALL_FUNDS: &str = "ee40a3604bab1021";
+    // 1 base token of 9 decimals and 50 quote tokens of 6 decimals.
+    const DEPOSIT_BOTH: &str = "f223c68952e1f2b600ca9a3b0000000080f0fa0200000000";
+
+    fn decode(data: &str, accounts: &[Pubkey]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, hex::decode(data).unwrap()), accounts)
+    }
+
+    #[test]
+    fn discriminators_are_the_anchor_ones() {
+        for (name, discriminator) in &[
+            ("place_order", PLACE_ORDER),
+            ("place_take_order", PLACE_TAKE_ORDER),
+            ("cancel_order", CANCEL_ORDER),
+            ("cancel_all_orders", CANCEL_ALL_ORDERS),
+            ("consume_events", CONSUME_EVENTS),
+            ("settle_funds", SETTLE_FUNDS),
+            ("deposit", DEPOSIT),
+            ("refill", REFILL),
+        ] {
+            let preimage = format!("global:{}", name);
+            let expected: [u8; 8] = hash(preimage.as_bytes()).to_bytes()[..8].try_into().unwrap();
+            assert_eq!(*discriminator, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn orders_decode_with_their_market() {
+        let accounts: Vec<_> = (0..17).map(|_| Pubkey::new_unique()).collect();
+
+        let place = decode(PLACE_ORDER_BID, &accounts).unwrap();
+        assert_eq!(place.function.function_name.as_str(), "place-order");
+        assert_eq!(properties(&place), vec![
+            ("side", "bid".to_string()),
+            ("price_lots", "2250".to_string()),
+            ("max_base_lots", "10".to_string()),
+            ("max_quote_lots_including_fees", i64::MAX.to_string()),
+            ("client_order_id", "7".to_string()),
+            ("order_type", "post-only".to_string()),
+            ("self_trade_behavior", "decrement-take".to_string()),
+            ("limit", "10".to_string()),
+            ("market", accounts[4].to_string()),
+        ]);
+
+        let take = decode(PLACE_TAKE_ORDER_ASK, &accounts).unwrap();
+        assert_eq!(properties(&take), vec![
+            ("side", "ask".to_string()),
+            ("price_lots", "2000".to_string()),
+            ("max_base_lots", "3".to_string()),
+            ("max_quote_lots_including_fees", "100000000".to_string()),
+            ("order_type", "market".to_string()),
+            ("limit", "10".to_string()),
+            ("market", accounts[2].to_string()),
+        ]);
+    }
+
+    #[test]
+    fn account_instructions_decode() {
+        let accounts: Vec<_> = (0..8).map(|_| Pubkey::new_unique()).collect();
+        let decoded: Vec<_> = [
+            CANCEL_ORDER_1234,
+            CANCEL_ALL_ASKS,
+            CONSUME_EVENTS_8,
+            SETTLE_ALL_FUNDS,
+            DEPOSIT_BOTH,
+        ].iter().map(|data| decode(data, &accounts).unwrap()).collect();
+
+        assert_eq!(properties(&decoded[0]),
+                   vec![("order_id", "1234".to_string()), ("market", accounts[2].to_string())]);
+        assert_eq!(&properties(&decoded[1])[..2],
+                   &[("side", "ask".to_string()), ("limit", "20".to_string())]);
+        assert_eq!(properties(&decoded[2]),
+                   vec![("limit", "8".to_string()), ("market", accounts[1].to_string())]);
+        assert_eq!(properties(&decoded[3]), vec![("market", accounts[3].to_string())]);
+        assert_eq!(properties(&decoded[4]), vec![
+            ("base_amount", "1000000000".to_string()),
+            ("quote_amount", "50000000".to_string()),
+            ("market", accounts[4].to_string()),
+        ]);
+
+        // Decoded outside of its transaction, and cancelling both sides.
+        assert_eq!(properties(&decode("c453f3ab1164a08f0014", &[]).unwrap()),
+                   vec![("limit", "20".to_string())]);
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        // Cut before the limit.
+        assert!(decode(&PLACE_ORDER_BID[..PLACE_ORDER_BID.len() - 2], &[]).is_none());
+        // An out of range order type.
+        assert!(decode("032c47031ac7cb5501d007000000000000030000000000000000e1f50500000000060a",
+                       &[]).is_none());
+        assert!(decode("0001020304050607", &[]).is_none());
+        assert!(decode("33c29b", &[]).is_none());
+    }
+}
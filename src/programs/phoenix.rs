@@ -0,0 +1,680 @@
+//! The Phoenix order book, whose instructions are a one byte tag followed by borsh encoded
+//! arguments (see [`PhoenixInstruction`]).
+//!
+//! Orders are priced in ticks and sized in lots of the market. They're emitted as such, and also
+//! as prices and sizes in tokens for the markets configured (see [`PhoenixMarket`]), once the
+//! decimals of their tokens are known.
+//!
+//! Order packets gained fields over time (expirations, failing silently), always at the end, so
+//! the fields every version starts with are decoded and whatever follows is ignored.
+
+use smallvec::smallvec;
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, Properties, PropertyType};
+use crate::programs::layout::{self, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS: &str = "PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY";
+
+const SIDES: &[&str] = &["bid", "ask"];
+const SELF_TRADE_BEHAVIORS: &[&str] = &["abort", "cancel-provide", "decrement-take"];
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let order_packet = [
+        ("order_type", "order_packet", PropertyType::Text,
+         "Order type (post-only, limit or immediate-or-cancel)"),
+        ("side", "order_packet", PropertyType::Text, "Side of the order (bid or ask)"),
+        ("price_in_ticks", "order_packet", PropertyType::U64,
+         "Limit price, in ticks, absent for immediate or cancel orders at any price"),
+        ("num_base_lots", "order_packet", PropertyType::U64, "Size of the order, in base lots"),
+        ("num_quote_lots", "order_packet", PropertyType::U64,
+         "Size of an immediate or cancel order, in quote lots"),
+        ("min_base_lots_to_fill", "order_packet", PropertyType::U64,
+         "Least an immediate or cancel order fills, in base lots"),
+        ("min_quote_lots_to_fill", "order_packet", PropertyType::U64,
+         "Least an immediate or cancel order fills, in quote lots"),
+        ("self_trade_behavior", "order_packet", PropertyType::Text,
+         "What happens to orders matching orders of the same trader (abort, cancel-provide or \
+         decrement-take)"),
+        ("match_limit", "order_packet", PropertyType::U64,
+         "Maximum number of orders matched"),
+        ("client_order_id", "order_packet", PropertyType::Decimal,
+         "Order id chosen by the client"),
+        ("reject_post_only", "order_packet", PropertyType::Bool,
+         "Whether a post only order crossing the book fails, rather than being repriced"),
+    ];
+    let cancel_orders = [
+        ("orders/{index}/side", "orders/{index}", PropertyType::Text,
+         "Side of the order (bid or ask)"),
+        ("orders/{index}/price_in_ticks", "orders/{index}", PropertyType::U64,
+         "Price of the order, in ticks"),
+        ("orders/{index}/order_sequence_number", "orders/{index}", PropertyType::U64,
+         "Sequence number of the order"),
+    ];
+
+    ProgramSchema::new("phoenix")
+        .function("swap", &order_packet)
+        .function("swap-with-free-funds", &order_packet)
+        .function("place-limit-order", &order_packet)
+        .function("place-limit-order-with-free-funds", &order_packet)
+        .function("cancel-all-orders", &[])
+        .function("cancel-all-orders-with-free-funds", &[])
+        .function("cancel-multiple-orders-by-id", &cancel_orders)
+        .function("cancel-multiple-orders-by-id-with-free-funds", &cancel_orders)
+        .function("withdraw-funds", &[
+            ("quote_lots_to_withdraw", "", PropertyType::U64,
+             "Quote lots withdrawn, absent to withdraw them all"),
+            ("base_lots_to_withdraw", "", PropertyType::U64,
+             "Base lots withdrawn, absent to withdraw them all"),
+        ])
+        .function("deposit-funds", &[
+            ("quote_lots_to_deposit", "", PropertyType::U64, "Quote lots deposited"),
+            ("base_lots_to_deposit", "", PropertyType::U64, "Base lots deposited"),
+        ])
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+impl Side {
+    fn unpack(reader: &mut Reader) -> Option<Self> {
+        match reader.u8()? {
+            0 => Some(Side::Bid),
+            1 => Some(Side::Ask),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        SIDES[self as usize]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelfTradeBehavior {
+    Abort,
+    CancelProvide,
+    DecrementTake,
+}
+
+impl SelfTradeBehavior {
+    fn unpack(reader: &mut Reader) -> Option<Self> {
+        match reader.u8()? {
+            0 => Some(SelfTradeBehavior::Abort),
+            1 => Some(SelfTradeBehavior::CancelProvide),
+            2 => Some(SelfTradeBehavior::DecrementTake),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        SELF_TRADE_BEHAVIORS[self as usize]
+    }
+}
+
+/// An order, up to the fields every version of the program encodes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OrderPacket {
+    PostOnly {
+        side: Side,
+        price_in_ticks: u64,
+        num_base_lots: u64,
+        client_order_id: u128,
+        reject_post_only: bool,
+    },
+    Limit {
+        side: Side,
+        price_in_ticks: u64,
+        num_base_lots: u64,
+        self_trade_behavior: SelfTradeBehavior,
+        match_limit: Option<u64>,
+        client_order_id: u128,
+    },
+    ImmediateOrCancel {
+        side: Side,
+        // Any price when absent.
+        price_in_ticks: Option<u64>,
+        num_base_lots: u64,
+        num_quote_lots: u64,
+        min_base_lots_to_fill: u64,
+        min_quote_lots_to_fill: u64,
+        self_trade_behavior: SelfTradeBehavior,
+        match_limit: Option<u64>,
+        client_order_id: u128,
+    },
+}
+
+impl OrderPacket {
+    fn unpack(reader: &mut Reader) -> Option<Self> {
+        match reader.u8()? {
+            0 => Some(OrderPacket::PostOnly {
+                side: Side::unpack(reader)?,
+                price_in_ticks: reader.u64()?,
+                num_base_lots: reader.u64()?,
+                client_order_id: reader.u128()?,
+                reject_post_only: reader.bool()?,
+            }),
+            1 => Some(OrderPacket::Limit {
+                side: Side::unpack(reader)?,
+                price_in_ticks: reader.u64()?,
+                num_base_lots: reader.u64()?,
+                self_trade_behavior: SelfTradeBehavior::unpack(reader)?,
+                match_limit: reader.option(Reader::u64)?,
+                client_order_id: reader.u128()?,
+            }),
+            2 => Some(OrderPacket::ImmediateOrCancel {
+                side: Side::unpack(reader)?,
+                price_in_ticks: reader.option(Reader::u64)?,
+                num_base_lots: reader.u64()?,
+                num_quote_lots: reader.u64()?,
+                min_base_lots_to_fill: reader.u64()?,
+                min_quote_lots_to_fill: reader.u64()?,
+                self_trade_behavior: SelfTradeBehavior::unpack(reader)?,
+                match_limit: reader.option(Reader::u64)?,
+                client_order_id: reader.u128()?,
+            }),
+            _ => None,
+        }
+    }
+
+    fn properties(&self, instruction: &Instruction) -> Properties {
+        let property = |key: &str, value: String, value_type| {
+            layout::property(instruction, key, "order_packet", value, value_type)
+        };
+        let optional = |key: &str, value: Option<u64>| {
+            value.map(|value| property(key, value.to_string(), PropertyType::U64))
+        };
+
+        match self {
+            OrderPacket::PostOnly {
+                side, price_in_ticks, num_base_lots, client_order_id, reject_post_only,
+            } => smallvec![
+                property("order_type", "post-only".to_string(), PropertyType::Text),
+                property("side", side.name().to_string(), PropertyType::Text),
+                property("price_in_ticks", price_in_ticks.to_string(), PropertyType::U64),
+                property("num_base_lots", num_base_lots.to_string(), PropertyType::U64),
+                property("client_order_id", client_order_id.to_string(), PropertyType::Decimal),
+                property("reject_post_only", (*reject_post_only as i32).to_string(),
+                         PropertyType::Bool),
+            ],
+            OrderPacket::Limit {
+                side, price_in_ticks, num_base_lots, self_trade_behavior, match_limit,
+                client_order_id,
+            } => {
+                let mut properties: Properties = smallvec![
+                    property("order_type", "limit".to_string(), PropertyType::Text),
+                    property("side", side.name().to_string(), PropertyType::Text),
+                    property("price_in_ticks", price_in_ticks.to_string(), PropertyType::U64),
+                    property("num_base_lots", num_base_lots.to_string(), PropertyType::U64),
+                    property("self_trade_behavior", self_trade_behavior.name().to_string(),
+                             PropertyType::Text),
+                    property("client_order_id", client_order_id.to_string(),
+                             PropertyType::Decimal),
+                ];
+                properties.extend(optional("match_limit", *match_limit));
+
+                properties
+            }
+            OrderPacket::ImmediateOrCancel {
+                side, price_in_ticks, num_base_lots, num_quote_lots, min_base_lots_to_fill,
+                min_quote_lots_to_fill, self_trade_behavior, match_limit, client_order_id,
+            } => {
+                let mut properties: Properties = smallvec![
+                    property("order_type", "immediate-or-cancel".to_string(), PropertyType::Text),
+                    property("side", side.name().to_string(), PropertyType::Text),
+                ];
+                properties.extend(optional("price_in_ticks", *price_in_ticks));
+                properties.extend(vec![
+                    property("num_base_lots", num_base_lots.to_string(), PropertyType::U64),
+                    property("num_quote_lots", num_quote_lots.to_string(), PropertyType::U64),
+                    property("min_base_lots_to_fill", min_base_lots_to_fill.to_string(),
+                             PropertyType::U64),
+                    property("min_quote_lots_to_fill", min_quote_lots_to_fill.to_string(),
+                             PropertyType::U64),
+                    property("self_trade_behavior", self_trade_behavior.name().to_string(),
+                             PropertyType::Text),
+                    property("client_order_id", client_order_id.to_string(),
+                             PropertyType::Decimal),
+                ]);
+                properties.extend(optional("match_limit", *match_limit));
+
+                properties
+            }
+        }
+    }
+}
+
+/// An order cancelled by `CancelMultipleOrdersById`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CancelOrderParams {
+    pub side: Side,
+    pub price_in_ticks: u64,
+    pub order_sequence_number: u64,
+}
+
+impl CancelOrderParams {
+    // Side, price and sequence number.
+    const LEN: usize = 1 + 8 + 8;
+
+    fn unpack(reader: &mut Reader) -> Option<Self> {
+        Some(CancelOrderParams {
+            side: Side::unpack(reader)?,
+            price_in_ticks: reader.u64()?,
+            order_sequence_number: reader.u64()?,
+        })
+    }
+}
+
+/// Everything is withdrawn when a side isn't set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WithdrawParams {
+    pub quote_lots_to_withdraw: Option<u64>,
+    pub base_lots_to_withdraw: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DepositParams {
+    pub quote_lots_to_deposit: u64,
+    pub base_lots_to_deposit: u64,
+}
+
+/// The instructions of the program decoded by the processor. The `WithFreeFunds` ones settle
+/// with the funds the trader deposited on the market rather than their token accounts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PhoenixInstruction {
+    Swap(OrderPacket),
+    SwapWithFreeFunds(OrderPacket),
+    PlaceLimitOrder(OrderPacket),
+    PlaceLimitOrderWithFreeFunds(OrderPacket),
+    CancelAllOrders,
+    CancelAllOrdersWithFreeFunds,
+    CancelMultipleOrdersById(Vec<CancelOrderParams>),
+    CancelMultipleOrdersByIdWithFreeFunds(Vec<CancelOrderParams>),
+    WithdrawFunds(WithdrawParams),
+    DepositFunds(DepositParams),
+}
+
+impl PhoenixInstruction {
+    /// Unpacks instruction data, or returns `None` for the instructions the processor doesn't
+    /// decode and invalid data.
+    pub fn unpack(data: &[u8]) -> Option<Self> {
+        let (tag, arguments) = data.split_first()?;
+        let mut reader = Reader::new(arguments);
+        let reader = &mut reader;
+
+        Some(match tag {
+            0 => PhoenixInstruction::Swap(OrderPacket::unpack(reader)?),
+            1 => PhoenixInstruction::SwapWithFreeFunds(OrderPacket::unpack(reader)?),
+            2 => PhoenixInstruction::PlaceLimitOrder(OrderPacket::unpack(reader)?),
+            3 => PhoenixInstruction::PlaceLimitOrderWithFreeFunds(OrderPacket::unpack(reader)?),
+            6 => PhoenixInstruction::CancelAllOrders,
+            7 => PhoenixInstruction::CancelAllOrdersWithFreeFunds,
+            10 => PhoenixInstruction::CancelMultipleOrdersById(
+                reader.vec(CancelOrderParams::LEN, CancelOrderParams::unpack)?),
+            11 => PhoenixInstruction::CancelMultipleOrdersByIdWithFreeFunds(
+                reader.vec(CancelOrderParams::LEN, CancelOrderParams::unpack)?),
+            12 => PhoenixInstruction::WithdrawFunds(WithdrawParams {
+                quote_lots_to_withdraw: reader.option(Reader::u64)?,
+                base_lots_to_withdraw: reader.option(Reader::u64)?,
+            }),
+            13 => PhoenixInstruction::DepositFunds(DepositParams {
+                quote_lots_to_deposit: reader.u64()?,
+                base_lots_to_deposit: reader.u64()?,
+            }),
+            _ => return None,
+        })
+    }
+
+    /// The function the instruction decodes to.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PhoenixInstruction::Swap(_) => "swap",
+            PhoenixInstruction::SwapWithFreeFunds(_) => "swap-with-free-funds",
+            PhoenixInstruction::PlaceLimitOrder(_) => "place-limit-order",
+            PhoenixInstruction::PlaceLimitOrderWithFreeFunds(_) => {
+                "place-limit-order-with-free-funds"
+            }
+            PhoenixInstruction::CancelAllOrders => "cancel-all-orders",
+            PhoenixInstruction::CancelAllOrdersWithFreeFunds => {
+                "cancel-all-orders-with-free-funds"
+            }
+            PhoenixInstruction::CancelMultipleOrdersById(_) => "cancel-multiple-orders-by-id",
+            PhoenixInstruction::CancelMultipleOrdersByIdWithFreeFunds(_) => {
+                "cancel-multiple-orders-by-id-with-free-funds"
+            }
+            PhoenixInstruction::WithdrawFunds(_) => "withdraw-funds",
+            PhoenixInstruction::DepositFunds(_) => "deposit-funds",
+        }
+    }
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction
+) -> Option<InstructionSet> {
+    let phoenix_instruction = match PhoenixInstruction::unpack(&instruction.data) {
+        Some(phoenix_instruction) => phoenix_instruction,
+        None => {
+            // Most of the instructions not decoded are the market's own (seats, evictions).
+            match instruction.data.first() {
+                Some(0..=3) | Some(6) | Some(7) | Some(10..=13) => {
+                    error!("[spi-wrapper/phoenix] Attempt to parse instruction from program {} \
+                        failed due to truncated or invalid arguments.", instruction.program);
+                }
+                tag => debug!("[spi-wrapper/phoenix] Instruction {:?} of {} isn't one the \
+                    processor decodes.", tag, instruction.transaction_hash),
+            }
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, phoenix_instruction.name());
+    instruction_set.properties = match &phoenix_instruction {
+        PhoenixInstruction::Swap(order_packet)
+        | PhoenixInstruction::SwapWithFreeFunds(order_packet)
+        | PhoenixInstruction::PlaceLimitOrder(order_packet)
+        | PhoenixInstruction::PlaceLimitOrderWithFreeFunds(order_packet) => {
+            order_packet.properties(&instruction)
+        }
+        PhoenixInstruction::CancelAllOrders
+        | PhoenixInstruction::CancelAllOrdersWithFreeFunds => smallvec![],
+        PhoenixInstruction::CancelMultipleOrdersById(orders)
+        | PhoenixInstruction::CancelMultipleOrdersByIdWithFreeFunds(orders) => {
+            orders.iter().enumerate()
+                .flat_map(|(index, order)| {
+                    let parent_key = format!("orders/{}", index);
+                    let property = |key: &str, value: String, value_type| {
+                        layout::property(&instruction, &format!("{}/{}", parent_key, key),
+                                         &parent_key, value, value_type)
+                    };
+                    vec![
+                        property("side", order.side.name().to_string(), PropertyType::Text),
+                        property("price_in_ticks", order.price_in_ticks.to_string(),
+                                 PropertyType::U64),
+                        property("order_sequence_number", order.order_sequence_number.to_string(),
+                                 PropertyType::U64),
+                    ]
+                })
+                .collect()
+        }
+        PhoenixInstruction::WithdrawFunds(params) => {
+            let lots = [
+                ("quote_lots_to_withdraw", params.quote_lots_to_withdraw),
+                ("base_lots_to_withdraw", params.base_lots_to_withdraw),
+            ];
+            lots.iter()
+                .filter_map(|(key, lots)| lots.map(|lots| {
+                    layout::property(&instruction, key, "", lots.to_string(), PropertyType::U64)
+                }))
+                .collect()
+        }
+        PhoenixInstruction::DepositFunds(params) => smallvec![
+            layout::property(&instruction, "quote_lots_to_deposit", "",
+                             params.quote_lots_to_deposit.to_string(), PropertyType::U64),
+            layout::property(&instruction, "base_lots_to_deposit", "",
+                             params.base_lots_to_deposit.to_string(), PropertyType::U64),
+        ],
+    };
+
+    Some(instruction_set)
+}
+
+/// What converting the ticks and lots of a market to prices and sizes takes: the sizes of its
+/// header, and the decimals of its tokens, or their mints for the decimals to be looked up.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PhoenixMarket {
+    // In base atoms.
+    pub base_lot_size: u64,
+    // In quote atoms.
+    pub quote_lot_size: u64,
+    pub tick_size_in_quote_lots_per_base_unit: u64,
+    pub base_decimals: Option<u8>,
+    pub quote_decimals: Option<u8>,
+    pub base_mint: Option<Pubkey>,
+    pub quote_mint: Option<Pubkey>,
+}
+
+#[cfg(feature = "runtime")]
+impl PhoenixMarket {
+    /// The price of `price_in_ticks`, in quote tokens per base token.
+    pub fn price(&self, price_in_ticks: u64, quote_decimals: u8) -> Option<String> {
+        let quote_atoms = price_in_ticks as u128
+            * self.tick_size_in_quote_lots_per_base_unit as u128
+            * self.quote_lot_size as u128;
+        crate::mints::ui_amount(&quote_atoms.to_string(), quote_decimals)
+    }
+
+    /// The size of `num_base_lots`, in base tokens.
+    pub fn size(&self, num_base_lots: u64, base_decimals: u8) -> Option<String> {
+        let base_atoms = num_base_lots as u128 * self.base_lot_size as u128;
+        crate::mints::ui_amount(&base_atoms.to_string(), base_decimals)
+    }
+}
+
+/// Adds the price of every `price_in_ticks` property of `instruction_set` as `price`, and the size
+/// of every `num_base_lots` one as `size`, when its market is configured in `registry` and the
+/// decimals of its tokens are known: from the market, or from the mint cache and the token
+/// balances of the transaction.
+#[cfg(feature = "runtime")]
+pub(crate) fn add_prices(
+    registry: &crate::ProcessorRegistry,
+    instruction_set: &mut InstructionSet,
+    transaction: &crate::RawTransaction,
+    account_indexes: &[u8],
+) {
+    use crate::interner::Symbol;
+    use crate::{BuiltinProcessor, InstructionProperty};
+
+    let program = instruction_set.function.program.as_str();
+    if registry.get(program) != Some(BuiltinProcessor::Phoenix) {
+        return;
+    }
+    // Every instruction of a market names it third, after the program and its log authority.
+    let market = account_indexes.get(2)
+        .and_then(|index| transaction.account_keys.get(*index as usize))
+        .and_then(|market| registry.phoenix_market(market));
+    let market = match market {
+        Some(market) => market,
+        None => return,
+    };
+    let decimals = |decimals: Option<u8>, mint: Option<Pubkey>| {
+        decimals.or_else(|| {
+            let mint = mint?;
+            match registry.mint_cache() {
+                Some(mints) => mints.decimals(&mint, transaction),
+                None => transaction.token_mints.iter()
+                    .find(|token| token.mint == mint)
+                    .map(|token| token.decimals),
+            }
+        })
+    };
+    let base_decimals = decimals(market.base_decimals, market.base_mint);
+    let quote_decimals = decimals(market.quote_decimals, market.quote_mint);
+
+    let converted: Vec<InstructionProperty> = instruction_set.properties.iter()
+        .filter_map(|property| {
+            let key = property.key.as_str();
+            let value: u64 = property.value.parse().ok()?;
+            let (key, value) = if let Some(prefix) = key.strip_suffix("price_in_ticks") {
+                (format!("{}price", prefix), market.price(value, quote_decimals?)?)
+            } else if let Some(prefix) = key.strip_suffix("num_base_lots") {
+                (format!("{}size", prefix), market.size(value, base_decimals?)?)
+            } else {
+                return None;
+            };
+
+            Some(InstructionProperty {
+                key: Symbol::from(key.as_str()),
+                value,
+                value_type: PropertyType::Decimal,
+                ..property.clone()
+            })
+        })
+        .collect();
+    instruction_set.properties.extend(converted);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    // Instruction data as sent to the program, tag included.
+    // An immediate or cancel ask of 1.5 SOL at 21 USDC or better, for 31 USDC at least.
+    const SWAP: &str = "000201010852000000000000dc0500000000000000000000000000000000000000000000\
+        c005d90100000000010000000000000000000000000000000000000000";
+    // A post only bid of 2 SOL at 22.5 USDC.
+    const PLACE_POST_ONLY_ORDER: &str = "020000e457000000000000d007000000000000070000000000000000\
+        000000000000000100000000";
+    // A limit ask as sent before order packets carried expirations, which ends at the
+    // `use_only_deposited_funds` flag.
+    const PLACE_LIMIT_ORDER_WITHOUT_EXPIRATION: &str = "0201013c5a000000000000f40100000000000002\
+        010a000000000000003930000000000000000000000000000000";
+    const CANCEL_ALL_ORDERS: &str = "06";
+    // A bid and an ask, whose sequence number has its top bit set.
+    const CANCEL_MULTIPLE_ORDERS_BY_ID: &str = "0a0200000000e457000000000000d2040000000000000\
+        13c5a0000000000002e16000000000080";
+    const WITHDRAW_QUOTE_FUNDS: &str = "0c0140420f000000000000";
+    const DEPOSIT_FUNDS: &str = "0d404b4c0000000000d007000000000000";
+
+    fn decode(data: &str) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, hex::decode(data).unwrap()))
+    }
+
+    #[test]
+    fn order_packets_unpack() {
+        assert_eq!(PhoenixInstruction::unpack(&hex::decode(SWAP).unwrap()),
+                   Some(PhoenixInstruction::Swap(OrderPacket::ImmediateOrCancel {
+                       side: Side::Ask,
+                       price_in_ticks: Some(21_000),
+                       num_base_lots: 1_500,
+                       num_quote_lots: 0,
+                       min_base_lots_to_fill: 0,
+                       min_quote_lots_to_fill: 31_000_000,
+                       self_trade_behavior: SelfTradeBehavior::CancelProvide,
+                       match_limit: None,
+                       client_order_id: 0,
+                   })));
+        assert_eq!(PhoenixInstruction::unpack(&hex::decode(PLACE_POST_ONLY_ORDER).unwrap()),
+                   Some(PhoenixInstruction::PlaceLimitOrder(OrderPacket::PostOnly {
+                       side: Side::Bid,
+                       price_in_ticks: 22_500,
+                       num_base_lots: 2_000,
+                       client_order_id: 7,
+                       reject_post_only: true,
+                   })));
+
+        let limit = decode(PLACE_LIMIT_ORDER_WITHOUT_EXPIRATION).unwrap();
+        assert_eq!(limit.function.function_name.as_str(), "place-limit-order");
+        assert_eq!(properties(&limit), vec![
+            ("order_type", "limit".to_string()),
+            ("side", "ask".to_string()),
+            ("price_in_ticks", "23100".to_string()),
+            ("num_base_lots", "500".to_string()),
+            ("self_trade_behavior", "decrement-take".to_string()),
+            ("client_order_id", "12345".to_string()),
+            ("match_limit", "10".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn cancellations_and_funds_decode() {
+        let cancel_all = decode(CANCEL_ALL_ORDERS).unwrap();
+        assert_eq!(cancel_all.function.function_name.as_str(), "cancel-all-orders");
+        assert!(cancel_all.properties.is_empty());
+
+        let cancel = decode(CANCEL_MULTIPLE_ORDERS_BY_ID).unwrap();
+        assert_eq!(properties(&cancel), vec![
+            ("orders/0/side", "bid".to_string()),
+            ("orders/0/price_in_ticks", "22500".to_string()),
+            ("orders/0/order_sequence_number", "1234".to_string()),
+            ("orders/1/side", "ask".to_string()),
+            ("orders/1/price_in_ticks", "23100".to_string()),
+            ("orders/1/order_sequence_number", "9223372036854781486".to_string()),
+        ]);
+        assert_eq!(cancel.properties[3].parent_key.as_str(), "orders/1");
+
+        let withdraw = decode(WITHDRAW_QUOTE_FUNDS).unwrap();
+        assert_eq!(properties(&withdraw), vec![("quote_lots_to_withdraw", "1000000".to_string())]);
+        let deposit = decode(DEPOSIT_FUNDS).unwrap();
+        assert_eq!(properties(&deposit), vec![
+            ("quote_lots_to_deposit", "5000000".to_string()),
+            ("base_lots_to_deposit", "2000".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        // The order packet cut short of its client order id.
+        assert!(decode(&PLACE_POST_ONLY_ORDER[..2 * 20]).is_none());
+        // More orders than the data holds.
+        assert!(decode("0affffffff00").is_none());
+        // Request seat.
+        assert!(decode("0e").is_none());
+        assert!(decode("").is_none());
+    }
+
+    // SOL/USDC: lots of 0.001 SOL, ticks of 0.001 USDC.
+    #[cfg(feature = "runtime")]
+    #[test]
+    fn ticks_and_lots_convert_to_prices_and_sizes() {
+        use solana_sdk::instruction::CompiledInstruction;
+
+        use crate::transaction::{decode_transaction, RawTransaction, TokenMint};
+        use crate::ProcessorRegistry;
+
+        let (market, trader, base_mint) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut registry = ProcessorRegistry::default();
+        registry.set_phoenix_market(market, PhoenixMarket {
+            base_lot_size: 1_000_000,
+            quote_lot_size: 1,
+            tick_size_in_quote_lots_per_base_unit: 1_000,
+            quote_decimals: Some(6),
+            base_mint: Some(base_mint),
+            ..PhoenixMarket::default()
+        });
+        let program: Pubkey = PROGRAM_ADDRESS.parse().unwrap();
+        let transaction = RawTransaction {
+            slot: 1,
+            block_time: None,
+            transaction_hash: "signature".to_string(),
+            account_keys: vec![trader, program, Pubkey::new_unique(), market],
+            num_required_signatures: 1,
+            instructions: vec![CompiledInstruction {
+                program_id_index: 1,
+                accounts: vec![1, 2, 3, 0],
+                data: hex::decode(PLACE_POST_ONLY_ORDER).unwrap(),
+            }],
+            inner_instructions: vec![],
+            // The decimals of the base token come with the balances of the transaction.
+            token_mints: vec![TokenMint { account_index: 0, mint: base_mint, decimals: 9 }],
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        };
+
+        let record = decode_transaction(&registry, &transaction);
+        let order = &record.instruction_sets[0];
+        let converted: Vec<_> = properties(order).into_iter()
+            .filter(|(key, _)| *key == "price" || *key == "size")
+            .collect();
+        assert_eq!(converted, vec![("price", "22.5".to_string()), ("size", "2".to_string())]);
+
+        // Nothing is converted for the markets not configured.
+        let mut other_market = transaction.clone();
+        other_market.account_keys[3] = Pubkey::new_unique();
+        let record = decode_transaction(&registry, &other_market);
+        assert!(properties(&record.instruction_sets[0]).iter().all(|(key, _)| *key != "price"));
+    }
+}
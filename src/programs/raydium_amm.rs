@@ -0,0 +1,247 @@
+//! The Raydium constant product AMM (v4): every instruction starts with a one byte tag, followed
+//! by its little-endian arguments. Pool creation, deposits, withdrawals and swaps are decoded, the
+//! instructions managing the pools and their OpenBook orders are not.
+//!
+//! Deposits and withdrawals gained a trailing minimum along the way: it's only emitted when
+//! present.
+
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+
+const INITIALIZE_2: u8 = 1;
+const DEPOSIT: u8 = 3;
+const WITHDRAW: u8 = 4;
+const SWAP_BASE_IN: u8 = 9;
+const SWAP_BASE_OUT: u8 = 11;
+
+// Where the pool is in the accounts of the instructions: after the programs and the rent sysvar
+// when it's created, after the token program otherwise.
+const INITIALIZE_AMM_ACCOUNT: usize = 4;
+const AMM_ACCOUNT: usize = 1;
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let amm = ("amm", "", PropertyType::Pubkey,
+               "Pool of the instruction, when the accounts of the instruction are known");
+
+    ProgramSchema::new("raydium-amm")
+        .function("initialize-2", &[
+            ("nonce", "", PropertyType::U64, "Nonce the authority of the pool is derived with"),
+            ("open_time", "", PropertyType::Timestamp, "Time the pool opens for swaps at"),
+            ("init_pc_amount", "", PropertyType::U64, "Amount of quote tokens deposited"),
+            ("init_coin_amount", "", PropertyType::U64, "Amount of base tokens deposited"),
+            amm,
+        ])
+        .function("deposit", &[
+            ("max_coin_amount", "", PropertyType::U64, "Maximum amount of base tokens deposited"),
+            ("max_pc_amount", "", PropertyType::U64, "Maximum amount of quote tokens deposited"),
+            ("base_side", "", PropertyType::U64,
+             "Side the amounts deposited are computed from (0: base, 1: quote)"),
+            ("other_amount_min", "", PropertyType::U64,
+             "Minimum amount of tokens of the other side deposited, when given"),
+            amm,
+        ])
+        .function("withdraw", &[
+            ("amount", "", PropertyType::U64, "Amount of pool tokens burned"),
+            ("min_coin_amount", "", PropertyType::U64,
+             "Minimum amount of base tokens withdrawn, when given"),
+            ("min_pc_amount", "", PropertyType::U64,
+             "Minimum amount of quote tokens withdrawn, when given"),
+            amm,
+        ])
+        .function("swap-base-in", &[
+            ("amount_in", "", PropertyType::U64, "Amount of source tokens swapped"),
+            ("minimum_amount_out", "", PropertyType::U64,
+             "Minimum amount of destination tokens received"),
+            amm,
+        ])
+        .function("swap-base-out", &[
+            ("max_amount_in", "", PropertyType::U64, "Maximum amount of source tokens swapped"),
+            ("amount_out", "", PropertyType::U64, "Amount of destination tokens received"),
+            amm,
+        ])
+}
+
+// The properties of an instruction: key and value, all of them u64 but the open time.
+type Fields = Vec<(&'static str, u64)>;
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    let (tag, arguments) = match instruction.data.split_first() {
+        Some(split) => split,
+        None => {
+            error!("[spi-wrapper/raydium-amm] Attempt to parse instruction from program {} failed \
+                as it is empty.", instruction.program);
+
+            return None;
+        }
+    };
+    let mut arguments = Reader::new(arguments);
+
+    let decoded = match *tag {
+        INITIALIZE_2 => initialize_2(&mut arguments)
+            .map(|fields| ("initialize-2", fields, INITIALIZE_AMM_ACCOUNT)),
+        DEPOSIT => deposit(&mut arguments).map(|fields| ("deposit", fields, AMM_ACCOUNT)),
+        WITHDRAW => withdraw(&mut arguments).map(|fields| ("withdraw", fields, AMM_ACCOUNT)),
+        SWAP_BASE_IN => swap(&mut arguments, ["amount_in", "minimum_amount_out"])
+            .map(|fields| ("swap-base-in", fields, AMM_ACCOUNT)),
+        SWAP_BASE_OUT => swap(&mut arguments, ["max_amount_in", "amount_out"])
+            .map(|fields| ("swap-base-out", fields, AMM_ACCOUNT)),
+        _ => {
+            debug!("[spi-wrapper/raydium-amm] Instruction {} of {} isn't one the processor \
+                decodes.", tag, instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let (function_name, fields, amm_account) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/raydium-amm] Attempt to parse instruction from program {} \
+                failed due to truncated arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, value)| {
+            let value_type = if key == "open_time" {
+                PropertyType::Timestamp
+            } else {
+                PropertyType::U64
+            };
+            layout::property(&instruction, key, "", value.to_string(), value_type)
+        })
+        .collect();
+    if let Some(amm) = accounts.get(amm_account) {
+        instruction_set.properties.push(layout::property(
+            &instruction, "amm", "", amm.to_string(), PropertyType::Pubkey));
+    }
+
+    Some(instruction_set)
+}
+
+fn initialize_2(arguments: &mut Reader) -> Option<Fields> {
+    Some(vec![
+        ("nonce", arguments.u8()? as u64),
+        ("open_time", arguments.u64()?),
+        ("init_pc_amount", arguments.u64()?),
+        ("init_coin_amount", arguments.u64()?),
+    ])
+}
+
+fn deposit(arguments: &mut Reader) -> Option<Fields> {
+    let mut fields = vec![
+        ("max_coin_amount", arguments.u64()?),
+        ("max_pc_amount", arguments.u64()?),
+        ("base_side", arguments.u64()?),
+    ];
+    if arguments.remaining() > 0 {
+        fields.push(("other_amount_min", arguments.u64()?));
+    }
+
+    Some(fields)
+}
+
+fn withdraw(arguments: &mut Reader) -> Option<Fields> {
+    let mut fields = vec![("amount", arguments.u64()?)];
+    if arguments.remaining() > 0 {
+        fields.push(("min_coin_amount", arguments.u64()?));
+        fields.push(("min_pc_amount", arguments.u64()?));
+    }
+
+    Some(fields)
+}
+
+/// The two amounts of a swap, named after its direction.
+fn swap(arguments: &mut Reader, keys: [&'static str; 2]) -> Option<Fields> {
+    Some(vec![(keys[0], arguments.u64()?), (keys[1], arguments.u64()?)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(data: &[u8], accounts: &[Pubkey]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, data.to_vec()), accounts)
+    }
+
+    /// The tag followed by the little-endian `amounts`.
+    fn data(tag: u8, amounts: &[u64]) -> Vec<u8> {
+        let mut data = vec![tag];
+        for amount in amounts {
+            data.extend_from_slice(&amount.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn swaps_decode_with_their_pool() {
+        let accounts: Vec<_> = (0..18).map(|_| Pubkey::new_unique()).collect();
+
+        let swap_in = decode(&data(SWAP_BASE_IN, &[1_000_000_000, 20_000_000]), &accounts)
+            .unwrap();
+        assert_eq!(swap_in.function.function_name.as_str(), "swap-base-in");
+        assert_eq!(properties(&swap_in), vec![
+            ("amount_in", "1000000000".to_string()),
+            ("minimum_amount_out", "20000000".to_string()),
+            ("amm", accounts[1].to_string()),
+        ]);
+
+        let swap_out = decode(&data(SWAP_BASE_OUT, &[21_000_000, 1_000_000_000]), &[]).unwrap();
+        assert_eq!(properties(&swap_out), vec![
+            ("max_amount_in", "21000000".to_string()),
+            ("amount_out", "1000000000".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn pools_are_created_and_funded() {
+        let accounts: Vec<_> = (0..21).map(|_| Pubkey::new_unique()).collect();
+        let mut initialize = vec![INITIALIZE_2, 254];
+        initialize.extend_from_slice(&data(0, &[1_700_000_000, 5_000_000, 100_000_000])[1..]);
+
+        let initialize = decode(&initialize, &accounts).unwrap();
+        assert_eq!(properties(&initialize), vec![
+            ("nonce", "254".to_string()),
+            ("open_time", "1700000000".to_string()),
+            ("init_pc_amount", "5000000".to_string()),
+            ("init_coin_amount", "100000000".to_string()),
+            ("amm", accounts[4].to_string()),
+        ]);
+        assert_eq!(initialize.properties[1].value_type, PropertyType::Timestamp);
+
+        // Deposits and withdrawals with and without their trailing minimums.
+        assert_eq!(decode(&data(DEPOSIT, &[10, 20, 0]), &[]).unwrap().properties.len(), 3);
+        let deposit = decode(&data(DEPOSIT, &[10, 20, 0, 19]), &[]).unwrap();
+        assert_eq!(properties(&deposit)[3], ("other_amount_min", "19".to_string()));
+        assert_eq!(decode(&data(WITHDRAW, &[500]), &[]).unwrap().properties.len(), 1);
+        let withdraw = decode(&data(WITHDRAW, &[500, 9, 18]), &[]).unwrap();
+        assert_eq!(properties(&withdraw)[1..], [
+            ("min_coin_amount", "9".to_string()),
+            ("min_pc_amount", "18".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        assert!(decode(&data(SWAP_BASE_IN, &[1]), &[]).is_none());
+        assert!(decode(&data(WITHDRAW, &[500, 9]), &[]).is_none());
+        assert!(decode(&data(2, &[]), &[]).is_none());
+        assert!(decode(&[], &[]).is_none());
+    }
+}
@@ -1,13 +1,106 @@
+//! The Serum DEX market program, every version of it, and OpenBook, the community fork of its
+//! third version taking over its markets, which kept its instructions.
+
 use serum_dex::instruction::MarketInstruction;
+use smallvec::smallvec;
 use tracing::error;
 
-use crate::{InstructionFunction, InstructionSet, InstructionProperty, Instruction};
+use crate::{InstructionFunction, InstructionSet, InstructionProperty, Instruction, PropertyType};
+use crate::schema::ProgramSchema;
 
 pub const PROGRAM_ADDRESS_V1: &str = "BJ3jrUzddfuSrZHXSCxMUUQsjKEyLmuuyZebkcaFp2fg";
 pub const PROGRAM_ADDRESS_V2: &str = "EUqojwWA2rd19FZrzeBncJsm38Jm1hEhE3zsmX3bRc2o";
 pub const PROGRAM_ADDRESS_V3: &str = "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin";
+pub const OPENBOOK_PROGRAM_ADDRESS: &str = "srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX";
+// The deployments of v3 and OpenBook on devnet.
+pub const DEVNET_PROGRAM_ADDRESS_V3: &str = "DESVgJVGajEgKGXhb6XmqDHGz3VjdgP7rEVESBgxmroY";
+pub const DEVNET_OPENBOOK_PROGRAM_ADDRESS: &str = "EoTcMgcDRTJVZDMZWBoU6rhYHZfkNTVEAfz3uUJRcYGj";
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    ProgramSchema::new("serum-market")
+        .function("initialize-market", &[
+            ("coin_lot_size", "", PropertyType::U64, "Lot size of the base currency"),
+            ("fee_rate_bps", "", PropertyType::U64, "Fee rate, in basis points"),
+            ("pc_dust_threshold", "", PropertyType::U64, "Dust threshold of the quote currency"),
+            ("pc_lot_size", "", PropertyType::U64, "Lot size of the quote currency"),
+            ("vault_signer_nonce", "", PropertyType::U64, "Nonce the vault signer is derived with"),
+        ])
+        .function("new-order", &[
+            ("client_id", "", PropertyType::U64, "Order id chosen by the client"),
+            ("limit_price", "", PropertyType::U64, "Limit price, in quote lots per base lot"),
+            ("max_qty", "", PropertyType::U64, "Maximum quantity, in base lots"),
+            ("order_type", "", PropertyType::U64,
+             "Order type (0: limit, 1: immediate or cancel, 2: post only)"),
+            ("side", "", PropertyType::U64, "Side of the order (0: bid, 1: ask)"),
+        ])
+        .function("match-orders", &[
+            ("orders", "", PropertyType::U64, "Maximum number of orders matched"),
+        ])
+        .function("consume-events", &[
+            ("events", "", PropertyType::U64, "Maximum number of events consumed"),
+        ])
+        .function("cancel-order", &[
+            ("side", "", PropertyType::U64, "Side of the order (0: bid, 1: ask)"),
+            ("order_id", "", PropertyType::Decimal, "Id of the order cancelled"),
+            ("owner_slot", "", PropertyType::U64, "Slot of the order in the open orders account"),
+        ])
+        .function("settle-funds", &[])
+        .function("cancel-order-by-client-id", &[
+            ("client_id", "", PropertyType::U64, "Order id chosen by the client"),
+        ])
+        .function("disable-market", &[])
+        .function("sweep-fees", &[])
+        .function("new-order-v2", &[
+            ("client_id", "", PropertyType::U64, "Order id chosen by the client"),
+            ("limit_price", "", PropertyType::U64, "Limit price, in quote lots per base lot"),
+            ("max_qty", "", PropertyType::U64, "Maximum quantity, in base lots"),
+            ("self_trade_behavior", "", PropertyType::U64,
+             "What happens to orders matching orders of the same owner (0: decrement take, 1: \
+             cancel provide, 2: abort transaction)"),
+            ("order_type", "", PropertyType::U64,
+             "Order type (0: limit, 1: immediate or cancel, 2: post only)"),
+            ("side", "", PropertyType::U64, "Side of the order (0: bid, 1: ask)"),
+        ])
+        .function("new-order-v3", &[
+            ("client_order_id", "", PropertyType::U64, "Order id chosen by the client"),
+            ("limit_price", "", PropertyType::U64, "Limit price, in quote lots per base lot"),
+            ("limit", "", PropertyType::U64, "Maximum number of orders matched or cancelled"),
+            ("max_coin_qty", "", PropertyType::U64, "Maximum quantity of base currency, in lots"),
+            ("self_trade_behavior", "", PropertyType::U64,
+             "What happens to orders matching orders of the same owner (0: decrement take, 1: \
+             cancel provide, 2: abort transaction)"),
+            ("order_type", "", PropertyType::U64,
+             "Order type (0: limit, 1: immediate or cancel, 2: post only)"),
+            ("side", "", PropertyType::U64, "Side of the order (0: bid, 1: ask)"),
+            ("max_native_pc_qty_including_fees", "", PropertyType::U64,
+             "Maximum quantity of quote currency, fees included"),
+        ])
+        .function("cancel-order-v2", &[
+            ("order_id", "", PropertyType::Decimal, "Id of the order cancelled"),
+            ("side", "", PropertyType::U64, "Side of the order (0: bid, 1: ask)"),
+        ])
+        .function("cancel-order-by-client-id-v2", &[
+            ("client_id", "", PropertyType::U64, "Order id chosen by the client"),
+        ])
+        .function("send-take", &[
+            ("side", "", PropertyType::U64, "Side of the order (0: bid, 1: ask)"),
+            ("max_native_pc_qty_including_fees", "", PropertyType::U64,
+             "Maximum quantity of quote currency, fees included"),
+            ("max_coin_qty", "", PropertyType::U64, "Maximum quantity of base currency, in lots"),
+            ("limit", "", PropertyType::U64, "Maximum number of orders matched or cancelled"),
+            ("limit_price", "", PropertyType::U64, "Limit price, in quote lots per base lot"),
+            ("min_coin_qty", "", PropertyType::U64, "Minimum quantity of base currency, in lots"),
+            ("min_native_pc_qty", "", PropertyType::U64, "Minimum quantity of quote currency"),
+        ])
+        .function("close-open-orders", &[])
+        .function("init-open-orders", &[])
+        .function("prune", &[
+            ("limit", "", PropertyType::U64, "Maximum number of orders matched or cancelled"),
+        ])
+}
 
-pub async fn fragment_instruction(
+pub fn fragment_instruction(
     instruction: Instruction
 ) -> Option<InstructionSet> {
     // Unpack the instruction via the spl_token_swap library
@@ -29,60 +122,68 @@ pub async fn fragment_instruction(
                 // 9. `[]` the rent sysvar
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        function_name: "initialize-market".to_string(),
-                        timestamp: instruction.timestamp.clone(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        function_name: "initialize-market".into(),
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        decoder_version: 0,
                     },
-                    properties: vec![
+                    properties: smallvec![
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "coin_lot_size".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "coin_lot_size".into(),
                             value: imi.coin_lot_size.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "fee_rate_bps".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "fee_rate_bps".into(),
                             value: imi.fee_rate_bps.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "pc_dust_threshold".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "pc_dust_threshold".into(),
                             value: imi.pc_dust_threshold.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "pc_lot_size".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "pc_lot_size".into(),
                             value: imi.pc_lot_size.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "vault_signer_nonce".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "vault_signer_nonce".into(),
                             value: imi.vault_signer_nonce.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                     ],
+                    raw_data: None,
                 })
             }
             MarketInstruction::NewOrder(noiv1) => {
@@ -98,60 +199,68 @@ pub async fn fragment_instruction(
                 // 9. `[writable]` (optional) the (M)SRM account used for fee discounts
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        function_name: "new-order".to_string(),
-                        timestamp: instruction.timestamp.clone(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        function_name: "new-order".into(),
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        decoder_version: 0,
                     },
-                    properties: vec![
+                    properties: smallvec![
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "client_id".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "client_id".into(),
                             value: noiv1.client_id.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "limit_price".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "limit_price".into(),
                             value: noiv1.limit_price.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "max_qty".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "max_qty".into(),
                             value: noiv1.max_qty.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "order_type".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "order_type".into(),
                             value: (noiv1.order_type as u8).to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "side".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "side".into(),
                             value: (noiv1.side as u8).to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                     ],
+                    raw_data: None,
                 })
             }
             MarketInstruction::MatchOrders(orders) => {
@@ -164,24 +273,28 @@ pub async fn fragment_instruction(
                 // 6. `[writable]` pc fee receivable account
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        function_name: "match-orders".to_string(),
-                        timestamp: instruction.timestamp.clone(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        function_name: "match-orders".into(),
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        decoder_version: 0,
                     },
-                    properties: vec![
+                    properties: smallvec![
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "orders".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "orders".into(),
                             value: orders.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         }
                     ],
+                    raw_data: None,
                 })
             }
             MarketInstruction::ConsumeEvents(count) => {
@@ -192,24 +305,28 @@ pub async fn fragment_instruction(
                 // accounts.len() - 1 `[writable]` pc fee receivable account
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        function_name: "consume-events".to_string(),
-                        timestamp: instruction.timestamp.clone(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        function_name: "consume-events".into(),
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        decoder_version: 0,
                     },
-                    properties: vec![
+                    properties: smallvec![
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "events".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "events".into(),
                             value: count.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         }
                     ],
+                    raw_data: None,
                 })
             }
             MarketInstruction::CancelOrder(coi) => {
@@ -230,42 +347,48 @@ pub async fn fragment_instruction(
 
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        function_name: "cancel-order".to_string(),
-                        timestamp: instruction.timestamp.clone(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        function_name: "cancel-order".into(),
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        decoder_version: 0,
                     },
-                    properties: vec![
+                    properties: smallvec![
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "side".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "side".into(),
                             value: (coi.side as u8).to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "order_id".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "order_id".into(),
                             value: coi.order_id.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::Decimal,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "owner_slot".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "owner_slot".into(),
                             value: coi.owner_slot.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                     ],
+                    raw_data: None,
                 })
             }
             MarketInstruction::SettleFunds => {
@@ -281,14 +404,17 @@ pub async fn fragment_instruction(
                 // 9. `[writable]` (optional) referrer pc wallet
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        function_name: "settle-funds".to_string(),
-                        timestamp: instruction.timestamp.clone(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        function_name: "settle-funds".into(),
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        decoder_version: 0,
                     },
-                    properties: vec![],
+                    properties: smallvec![],
+                    raw_data: None,
                 })
             }
             MarketInstruction::CancelOrderByClientId(client_id) => {
@@ -298,24 +424,28 @@ pub async fn fragment_instruction(
                 // 3. `[signer]` the OpenOrders owner
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        function_name: "cancel-order-by-client-id".to_string(),
-                        timestamp: instruction.timestamp.clone(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        function_name: "cancel-order-by-client-id".into(),
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        decoder_version: 0,
                     },
-                    properties: vec![
+                    properties: smallvec![
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "client_id".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "client_id".into(),
                             value: client_id.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         }
                     ],
+                    raw_data: None,
                 })
             }
             MarketInstruction::DisableMarket => {
@@ -323,14 +453,17 @@ pub async fn fragment_instruction(
                 // 1. `[signer]` disable authority
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        function_name: "disable-market".to_string(),
-                        timestamp: instruction.timestamp.clone(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        function_name: "disable-market".into(),
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        decoder_version: 0,
                     },
-                    properties: vec![],
+                    properties: smallvec![],
+                    raw_data: None,
                 })
             }
             MarketInstruction::SweepFees => {
@@ -342,14 +475,17 @@ pub async fn fragment_instruction(
                 // 5. `[]` spl token program
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        function_name: "sweep-fees".to_string(),
-                        timestamp: instruction.timestamp.clone(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        function_name: "sweep-fees".into(),
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        decoder_version: 0,
                     },
-                    properties: vec![],
+                    properties: smallvec![],
+                    raw_data: None,
                 })
             }
             MarketInstruction::NewOrderV2(order) => {
@@ -365,40 +501,45 @@ pub async fn fragment_instruction(
                 // 9. `[writable]` (optional) the (M)SRM account used for fee discounts
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        function_name: "new-order-v2".to_string(),
-                        timestamp: instruction.timestamp.clone(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        function_name: "new-order-v2".into(),
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        decoder_version: 0,
                     },
-                    properties: vec![
+                    properties: smallvec![
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "client_id".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "client_id".into(),
                             value: order.client_id.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "limit_price".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "limit_price".into(),
                             value: order.limit_price.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "max_qty".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "max_qty".into(),
                             value: order.max_qty.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         // pub enum SelfTradeBehavior {
                         //     DecrementTake = 0,
@@ -406,13 +547,14 @@ pub async fn fragment_instruction(
                         //     AbortTransaction = 2,
                         // }
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "self_trade_behavior".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "self_trade_behavior".into(),
                             value: (order.self_trade_behavior as u8).to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         // pub enum OrderType {
                         //     Limit = 0,
@@ -420,28 +562,31 @@ pub async fn fragment_instruction(
                         //     PostOnly = 2,
                         // }
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "order_type".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "order_type".into(),
                             value: (order.order_type as u8).to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         // pub enum Side {
                         //     Bid = 0,
                         //     Ask = 1,
                         // }
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "side".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "side".into(),
                             value: (order.side as u8).to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                     ],
+                    raw_data: None,
                 })
             }
             MarketInstruction::NewOrderV3(order) => {
@@ -460,49 +605,55 @@ pub async fn fragment_instruction(
                 // 12. `[writable]` (optional) the (M)SRM account used for fee discounts
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        function_name: "new-order-v3".to_string(),
-                        timestamp: instruction.timestamp.clone(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        function_name: "new-order-v3".into(),
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        decoder_version: 0,
                     },
-                    properties: vec![
+                    properties: smallvec![
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "client_order_id".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "client_order_id".into(),
                             value: order.client_order_id.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "limit_price".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "limit_price".into(),
                             value: order.limit_price.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "limit".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "limit".into(),
                             value: order.limit.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "max_coin_qty".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "max_coin_qty".into(),
                             value: order.max_coin_qty.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         // pub enum SelfTradeBehavior {
                         //     DecrementTake = 0,
@@ -510,13 +661,14 @@ pub async fn fragment_instruction(
                         //     AbortTransaction = 2,
                         // }
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "self_trade_behavior".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "self_trade_behavior".into(),
                             value: (order.self_trade_behavior as u8).to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         // pub enum OrderType {
                         //     Limit = 0,
@@ -524,37 +676,41 @@ pub async fn fragment_instruction(
                         //     PostOnly = 2,
                         // }
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "order_type".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "order_type".into(),
                             value: (order.order_type as u8).to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         // pub enum Side {
                         //     Bid = 0,
                         //     Ask = 1,
                         // }
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "side".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "side".into(),
                             value: (order.side as u8).to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "max_native_pc_qty_including_fees".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "max_native_pc_qty_including_fees".into(),
                             value: order.max_native_pc_qty_including_fees.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                     ],
+                    raw_data: None,
                 })
             }
             MarketInstruction::CancelOrderV2(order) => {
@@ -566,37 +722,42 @@ pub async fn fragment_instruction(
                 // 5. `[writable]` event_q
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        function_name: "cancel-order-v2".to_string(),
-                        timestamp: instruction.timestamp.clone(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        function_name: "cancel-order-v2".into(),
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        decoder_version: 0,
                     },
-                    properties: vec![
+                    properties: smallvec![
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "order_id".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "order_id".into(),
                             value: order.order_id.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::Decimal,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         // pub enum Side {
                         //     Bid = 0,
                         //     Ask = 1,
                         // }
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "side".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "side".into(),
                             value: (order.side as u8).to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                     ],
+                    raw_data: None,
                 })
             }
             MarketInstruction::CancelOrderByClientIdV2(client_id) => {
@@ -608,24 +769,28 @@ pub async fn fragment_instruction(
                 // 5. `[writable]` event_q
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        function_name: "cancel-order-by-client-id-v2".to_string(),
-                        timestamp: instruction.timestamp.clone(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        function_name: "cancel-order-by-client-id-v2".into(),
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        decoder_version: 0,
                     },
-                    properties: vec![
+                    properties: smallvec![
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "client_id".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "client_id".into(),
                             value: client_id.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                     ],
+                    raw_data: None,
                 })
             }
             MarketInstruction::SendTake(sti) => {
@@ -636,78 +801,88 @@ pub async fn fragment_instruction(
                 // 4. `[]`
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        function_name: "send-take".to_string(),
-                        timestamp: instruction.timestamp.clone(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        function_name: "send-take".into(),
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        decoder_version: 0,
                     },
-                    properties: vec![
+                    properties: smallvec![
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "side".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "side".into(),
                             value: (sti.side as u8).to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "max_native_pc_qty_including_fees".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "max_native_pc_qty_including_fees".into(),
                             value: sti.max_native_pc_qty_including_fees.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "max_coin_qty".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "max_coin_qty".into(),
                             value: sti.max_coin_qty.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "limit".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "limit".into(),
                             value: sti.limit.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "limit_price".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "limit_price".into(),
                             value: sti.limit_price.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "min_coin_qty".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "min_coin_qty".into(),
                             value: sti.min_coin_qty.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         },
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "min_native_pc_qty".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "min_native_pc_qty".into(),
                             value: sti.min_native_pc_qty.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         }
                     ],
+                    raw_data: None,
                 })
             }
             MarketInstruction::CloseOpenOrders => {
@@ -717,14 +892,17 @@ pub async fn fragment_instruction(
                 // 3. `[]` market
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        timestamp: instruction.timestamp.clone(),
-                        function_name: "close-open-orders".to_string(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        function_name: "close-open-orders".into(),
+                        decoder_version: 0,
                     },
-                    properties: vec![],
+                    properties: smallvec![],
+                    raw_data: None,
                 })
             }
             MarketInstruction::InitOpenOrders => {
@@ -734,37 +912,44 @@ pub async fn fragment_instruction(
                 // 3. `[]` market
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        timestamp: instruction.timestamp.clone(),
-                        function_name: "init-open-orders".to_string(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        function_name: "init-open-orders".into(),
+                        decoder_version: 0,
                     },
-                    properties: vec![],
+                    properties: smallvec![],
+                    raw_data: None,
                 })
             }
             MarketInstruction::Prune(limit) => {
                 Some(InstructionSet {
                     function: InstructionFunction {
-                        tx_instruction_id: instruction.tx_instruction_id.clone(),
+                        tx_instruction_id: instruction.tx_instruction_id,
                         transaction_hash: instruction.transaction_hash.clone(),
-                        parent_index: instruction.parent_index.clone(),
-                        program: instruction.program.clone(),
-                        timestamp: instruction.timestamp.clone(),
-                        function_name: "prune".to_string(),
+                        parent_index: instruction.parent_index,
+                        program: instruction.program,
+                        timestamp: instruction.timestamp,
+                        network: instruction.network,
+                        function_name: "prune".into(),
+                        decoder_version: 0,
                     },
-                    properties: vec![
+                    properties: smallvec![
                         InstructionProperty {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            key: "limit".to_string(),
+                            parent_index: instruction.parent_index,
+                            key: "limit".into(),
                             value: limit.to_string(),
-                            parent_key: "".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            value_type: PropertyType::U64,
+                            parent_key: "".into(),
+                            timestamp: instruction.timestamp,
                         }
                     ],
+                    raw_data: None,
                 })
             }
         };
@@ -773,3 +958,73 @@ pub async fn fragment_instruction(
     error!("{}", "[processors/programs/serum/market] FATAL: Unrecognised instruction.".to_string());
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU64;
+
+    use serum_dex::instruction::{
+        CancelOrderInstructionV2, NewOrderInstructionV3, SelfTradeBehavior,
+    };
+    use serum_dex::matching::{OrderType, Side};
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(program_id: &str, market_instruction: MarketInstruction) -> InstructionSet {
+        fragment_instruction(instruction(program_id, market_instruction.pack())).unwrap()
+    }
+
+    #[test]
+    fn orders_carry_their_side_lots_and_client_order_id() {
+        let new_order = decode(OPENBOOK_PROGRAM_ADDRESS, MarketInstruction::NewOrderV3(
+            NewOrderInstructionV3 {
+                side: Side::Ask,
+                limit_price: NonZeroU64::new(2_150).unwrap(),
+                max_coin_qty: NonZeroU64::new(40).unwrap(),
+                max_native_pc_qty_including_fees: NonZeroU64::new(86_172).unwrap(),
+                self_trade_behavior: SelfTradeBehavior::CancelProvide,
+                order_type: OrderType::PostOnly,
+                client_order_id: 1_700_000_042,
+                limit: 65_535,
+            },
+        ));
+        assert_eq!(new_order.function.function_name.as_str(), "new-order-v3");
+        assert_eq!(properties(&new_order), vec![
+            ("client_order_id", "1700000042".to_string()),
+            ("limit_price", "2150".to_string()),
+            ("limit", "65535".to_string()),
+            ("max_coin_qty", "40".to_string()),
+            ("self_trade_behavior", "1".to_string()),
+            ("order_type", "2".to_string()),
+            ("side", "1".to_string()),
+            ("max_native_pc_qty_including_fees", "86172".to_string()),
+        ]);
+
+        let cancel = decode(PROGRAM_ADDRESS_V3, MarketInstruction::CancelOrderV2(
+            CancelOrderInstructionV2 { side: Side::Bid, order_id: u128::MAX - 1 },
+        ));
+        assert_eq!(cancel.function.function_name.as_str(), "cancel-order-v2");
+        assert_eq!(properties(&cancel), vec![
+            ("order_id", "340282366920938463463374607431768211454".to_string()),
+            ("side", "0".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn crank_and_account_instructions_are_decoded() {
+        let consume = decode(OPENBOOK_PROGRAM_ADDRESS, MarketInstruction::ConsumeEvents(10));
+        assert_eq!(consume.function.function_name.as_str(), "consume-events");
+        assert_eq!(properties(&consume), vec![("events", "10".to_string())]);
+
+        for (market_instruction, function_name) in &[
+            (MarketInstruction::SettleFunds, "settle-funds"),
+            (MarketInstruction::InitOpenOrders, "init-open-orders"),
+            (MarketInstruction::CloseOpenOrders, "close-open-orders"),
+        ] {
+            let decoded = decode(OPENBOOK_PROGRAM_ADDRESS, market_instruction.clone());
+            assert_eq!(decoded.function.function_name.as_str(), function_name);
+            assert!(decoded.properties.is_empty());
+        }
+    }
+}
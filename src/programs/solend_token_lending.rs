@@ -1,14 +1,181 @@
+use smallvec::smallvec;
 use solana_program::program_error::ProgramError;
 use solana_sdk::pubkey::Pubkey;
 use crate::programs::solend::instruction::LendingInstruction;
 use tracing::error;
 
-use crate::{Instruction, InstructionFunction, InstructionProperty, InstructionSet};
+use crate::{Instruction, InstructionFunction, InstructionProperty, InstructionSet, PropertyType};
+use crate::schema::ProgramSchema;
 
 pub const PROGRAM_ADDRESS: &str = "So1endDq2YkqhipRh3WViPa8hdiSpxWy6z3Z6tMCpAo";
+pub const DEVNET_PROGRAM_ADDRESS: &str = "ALend7Ketfx5bxh6ghsCDXAoDrhvEmsXT3cynB6aPLgx";
 
-pub async fn fragment_instruction(
-    instruction: Instruction
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let account = |key, description| (key, "", PropertyType::Pubkey, description);
+    let obligation = account("obligation",
+                             "Obligation account, when the accounts of the instruction are known");
+    let reserve = account("reserve",
+                          "Reserve account, when the accounts of the instruction are known");
+    let repay_reserve = account("repay_reserve", "Reserve the liquidator repays liquidity to, \
+        when the accounts of the instruction are known");
+    let withdraw_reserve = account("withdraw_reserve", "Reserve the liquidator withdraws \
+        collateral from, when the accounts of the instruction are known");
+
+    ProgramSchema::new("solend-token-lending")
+        .function("init-lending-market", &[
+            ("owner", "", PropertyType::Pubkey, "Owner of the lending market"),
+            ("quote_currency", "", PropertyType::Pubkey,
+             "Currency the market values are quoted in"),
+        ])
+        .function("set-lending-market-owner", &[
+            ("new_owner", "", PropertyType::Pubkey, "New owner of the lending market"),
+        ])
+        .function("init-reserve", &[
+            ("liquidity_amount", "", PropertyType::U64, "Amount of liquidity tokens"),
+            ("flash_loan_fee_wad", "fees", PropertyType::U64,
+             "Fee on flash loans, as a wad (1e18 = 100%)"),
+            ("borrow_fee_wad", "config/fees", PropertyType::U64,
+             "Fee on borrowed liquidity, as a wad (1e18 = 100%)"),
+            ("host_fee_percentage", "config/fees", PropertyType::U64,
+             "Share of the fees paid to the host, in percent"),
+            ("liquidation_threshold", "config", PropertyType::U64,
+             "Loan to value ratio at which obligations get liquidated, in percent"),
+            ("loan_to_value_ratio", "config", PropertyType::U64,
+             "Maximum loan to value ratio, in percent"),
+            ("max_borrow_rate", "config", PropertyType::U64,
+             "Borrow rate at full utilization, in percent"),
+            ("min_borrow_rate", "config", PropertyType::U64,
+             "Borrow rate at no utilization, in percent"),
+            ("optimal_borrow_rate", "config", PropertyType::U64,
+             "Borrow rate at optimal utilization, in percent"),
+            ("optimal_utilization_rate", "config", PropertyType::U64,
+             "Optimal utilization of the reserve, in percent"),
+        ])
+        .function("refresh-reserve", &[])
+        .function("deposit-reserve-liquidity", &[
+            ("liquidity_amount", "", PropertyType::U64, "Amount of liquidity tokens"),
+        ])
+        .function("redeem-reserve-collateral", &[
+            ("collateral_amount", "", PropertyType::U64, "Amount of collateral tokens"),
+        ])
+        .function("init-obligation", &[])
+        .function("refresh-obligation", &[])
+        .function("deposit-obligation-collateral", &[
+            ("collateral_amount", "", PropertyType::U64, "Amount of collateral tokens"),
+            reserve,
+            obligation,
+        ])
+        .function("withdraw-obligation-collateral", &[
+            ("collateral_amount", "", PropertyType::U64, "Amount of collateral tokens"),
+            reserve,
+            obligation,
+        ])
+        .function("borrow-obligation-liquidity", &[
+            ("liquidity_amount", "", PropertyType::U64, "Amount of liquidity tokens"),
+            reserve,
+            obligation,
+        ])
+        .function("repay-obligation-liquidity", &[
+            ("liquidity_amount", "", PropertyType::U64, "Amount of liquidity tokens"),
+            reserve,
+            obligation,
+        ])
+        .function("liquidate-obligation", &[
+            ("liquidity_amount", "", PropertyType::U64, "Amount of liquidity tokens"),
+            repay_reserve,
+            withdraw_reserve,
+            obligation,
+        ])
+        .function("flash-loan", &[
+            ("amount", "", PropertyType::U64, "Amount of tokens, in base units"),
+        ])
+        .function("deposit-reserve-liquidity-and-obligation-collateral", &[
+            ("liquidity_amount", "", PropertyType::U64, "Amount of liquidity tokens"),
+            reserve,
+            obligation,
+        ])
+        .function("withdraw-obligation-collateral-and-redeem-reserve-collateral", &[
+            ("collateral_amount", "", PropertyType::U64, "Amount of collateral tokens"),
+            reserve,
+            obligation,
+        ])
+        .function("update-reserve-config", &[
+            ("borrow_fee_wad", "config/fees", PropertyType::U64,
+             "Fee on borrowed liquidity, as a wad (1e18 = 100%)"),
+            ("flash_loan_fee_wad", "config/fees", PropertyType::U64,
+             "Fee on flash loans, as a wad (1e18 = 100%)"),
+            ("host_fee_percentage", "config/fees", PropertyType::U64,
+             "Share of the fees paid to the host, in percent"),
+            ("optimal_utilization_rate", "config", PropertyType::U64,
+             "Optimal utilization of the reserve, in percent"),
+            ("optimal_borrow_rate", "config", PropertyType::U64,
+             "Borrow rate at optimal utilization, in percent"),
+            ("loan_to_value_ratio", "config", PropertyType::U64,
+             "Maximum loan to value ratio, in percent"),
+            ("max_borrow_rate", "config", PropertyType::U64,
+             "Borrow rate at full utilization, in percent"),
+            ("min_borrow_rate", "config", PropertyType::U64,
+             "Borrow rate at no utilization, in percent"),
+            ("liquidation_bonus", "config", PropertyType::U64,
+             "Bonus paid to liquidators, in percent"),
+            ("liquidation_threshold", "config", PropertyType::U64,
+             "Loan to value ratio at which obligations get liquidated, in percent"),
+            ("fee_receiver", "config", PropertyType::Pubkey, "Account receiving the fees"),
+            ("deposit_limit", "config", PropertyType::U64,
+             "Maximum amount of liquidity deposited in the reserve"),
+            ("borrow_limit", "config", PropertyType::U64,
+             "Maximum amount of liquidity borrowed from the reserve"),
+        ])
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    let mut instruction_set = unpack(&instruction)?;
+    for (key, index) in account_roles(instruction_set.function.function_name.as_str()) {
+        if let Some(account) = accounts.get(*index) {
+            instruction_set.properties.push(InstructionProperty {
+                tx_instruction_id: instruction.tx_instruction_id,
+                transaction_hash: instruction.transaction_hash.clone(),
+                parent_index: instruction.parent_index,
+                key: (*key).into(),
+                value: account.to_string(),
+                value_type: PropertyType::Pubkey,
+                parent_key: "".into(),
+                timestamp: instruction.timestamp,
+            });
+        }
+    }
+
+    Some(instruction_set)
+}
+
+/// Where the obligation and the reserves an instruction changes are in its accounts, by function.
+fn account_roles(function_name: &str) -> &'static [(&'static str, usize)] {
+    match function_name {
+        "deposit-obligation-collateral" => &[("reserve", 2), ("obligation", 3)],
+        "withdraw-obligation-collateral" => &[("reserve", 2), ("obligation", 3)],
+        "borrow-obligation-liquidity" => &[("reserve", 2), ("obligation", 4)],
+        "repay-obligation-liquidity" => &[("reserve", 2), ("obligation", 3)],
+        "liquidate-obligation" => {
+            &[("repay_reserve", 2), ("withdraw_reserve", 4), ("obligation", 6)]
+        }
+        "deposit-reserve-liquidity-and-obligation-collateral" => {
+            &[("reserve", 2), ("obligation", 8)]
+        }
+        "withdraw-obligation-collateral-and-redeem-reserve-collateral" => {
+            &[("reserve", 2), ("obligation", 3)]
+        }
+        _ => &[],
+    }
+}
+
+fn unpack(
+    instruction: &Instruction
 ) -> Option<InstructionSet> {
     // Unpack the instruction via the spl_token_swap library
     let unpack_result = LendingInstruction::unpack(
@@ -24,56 +191,65 @@ pub async fn fragment_instruction(
                 } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "init-lending-market".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "init-lending-market".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "owner".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "owner".into(),
                                 value: owner.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "quote_currency".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "quote_currency".into(),
                                 value: Pubkey::new_from_array(quote_currency).to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::SetLendingMarketOwner { new_owner } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "set-lending-market-owner".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "set-lending-market-owner".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "new_owner".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "new_owner".into(),
                                 value: new_owner.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::InitReserve {
@@ -82,328 +258,382 @@ pub async fn fragment_instruction(
                 } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "init-reserve".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "init-reserve".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidity_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidity_amount".into(),
                                 value: liquidity_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "flash_loan_fee_wad".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "flash_loan_fee_wad".into(),
                                 value: config.fees.flash_loan_fee_wad.to_string(),
-                                parent_key: "fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "borrow_fee_wad".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "borrow_fee_wad".into(),
                                 value: config.fees.borrow_fee_wad.to_string(),
-                                parent_key: "config/fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config/fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "host_fee_percentage".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "host_fee_percentage".into(),
                                 value: config.fees.host_fee_percentage.to_string(),
-                                parent_key: "config/fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config/fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidation_threshold".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidation_threshold".into(),
                                 value: config.liquidation_threshold.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "loan_to_value_ratio".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "loan_to_value_ratio".into(),
                                 value: config.loan_to_value_ratio.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "max_borrow_rate".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "max_borrow_rate".into(),
                                 value: config.max_borrow_rate.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "min_borrow_rate".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "min_borrow_rate".into(),
                                 value: config.min_borrow_rate.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "optimal_borrow_rate".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "optimal_borrow_rate".into(),
                                 value: config.optimal_borrow_rate.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "optimal_utilization_rate".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "optimal_utilization_rate".into(),
                                 value: config.optimal_utilization_rate.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::RefreshReserve => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "refresh-reserve".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "refresh-reserve".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![],
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::DepositReserveLiquidity { liquidity_amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "deposit-reserve-liquidity".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "deposit-reserve-liquidity".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidity_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidity_amount".into(),
                                 value: liquidity_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::RedeemReserveCollateral { collateral_amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "redeem-reserve-collateral".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "redeem-reserve-collateral".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "collateral_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "collateral_amount".into(),
                                 value: collateral_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::InitObligation => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "init-obligation".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "init-obligation".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![],
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::RefreshObligation => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "refresh-obligation".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "refresh-obligation".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![],
+                        properties: smallvec![],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::DepositObligationCollateral { collateral_amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "deposit-obligation-collateral".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "deposit-obligation-collateral".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "collateral_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "collateral_amount".into(),
                                 value: collateral_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::WithdrawObligationCollateral { collateral_amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "withdraw-obligation-collateral".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "withdraw-obligation-collateral".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "collateral_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "collateral_amount".into(),
                                 value: collateral_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::BorrowObligationLiquidity { liquidity_amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "borrow-obligation-liquidity".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "borrow-obligation-liquidity".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidity_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidity_amount".into(),
                                 value: liquidity_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::RepayObligationLiquidity { liquidity_amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "repay-obligation-liquidity".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "repay-obligation-liquidity".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidity_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidity_amount".into(),
                                 value: liquidity_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::LiquidateObligation { liquidity_amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "liquidate-obligation".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "liquidate-obligation".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidity_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidity_amount".into(),
                                 value: liquidity_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::FlashLoan { amount } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "flash-loan".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "flash-loan".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "amount".into(),
                                 value: amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::DepositReserveLiquidityAndObligationCollateral {
@@ -411,24 +641,28 @@ pub async fn fragment_instruction(
                 } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "deposit-reserve-liquidity-and-obligation-collateral".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "deposit-reserve-liquidity-and-obligation-collateral".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidity_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidity_amount".into(),
                                 value: liquidity_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::WithdrawObligationCollateralAndRedeemReserveCollateral {
@@ -436,155 +670,175 @@ pub async fn fragment_instruction(
                 } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "withdraw-obligation-collateral-and-redeem-reserve-collateral".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "withdraw-obligation-collateral-and-redeem-reserve-collateral".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "collateral_amount".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "collateral_amount".into(),
                                 value: collateral_amount.to_string(),
-                                parent_key: "".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "".into(),
+                                timestamp: instruction.timestamp,
                             }
                         ],
+                        raw_data: None,
                     })
                 }
                 LendingInstruction::UpdateReserveConfig { config } => {
                     Some(InstructionSet {
                         function: InstructionFunction {
-                            tx_instruction_id: instruction.tx_instruction_id.clone(),
+                            tx_instruction_id: instruction.tx_instruction_id,
                             transaction_hash: instruction.transaction_hash.clone(),
-                            parent_index: instruction.parent_index.clone(),
-                            program: instruction.program.clone(),
-                            function_name: "update-reserve-config".to_string(),
-                            timestamp: instruction.timestamp.clone(),
+                            parent_index: instruction.parent_index,
+                            program: instruction.program,
+                            function_name: "update-reserve-config".into(),
+                            timestamp: instruction.timestamp,
+                            network: instruction.network,
+                            decoder_version: 0,
                         },
-                        properties: vec![
+                        properties: smallvec![
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "borrow_fee_wad".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "borrow_fee_wad".into(),
                                 value: config.fees.borrow_fee_wad.to_string(),
-                                parent_key: "config/fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config/fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "flash_loan_fee_wad".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "flash_loan_fee_wad".into(),
                                 value: config.fees.flash_loan_fee_wad.to_string(),
-                                parent_key: "config/fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config/fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "host_fee_percentage".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "host_fee_percentage".into(),
                                 value: config.fees.host_fee_percentage.to_string(),
-                                parent_key: "config/fees".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config/fees".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "optimal_utilization_rate".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "optimal_utilization_rate".into(),
                                 value: config.optimal_utilization_rate.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "optimal_borrow_rate".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "optimal_borrow_rate".into(),
                                 value: config.optimal_borrow_rate.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "loan_to_value_ratio".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "loan_to_value_ratio".into(),
                                 value: config.loan_to_value_ratio.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "max_borrow_rate".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "max_borrow_rate".into(),
                                 value: config.max_borrow_rate.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "min_borrow_rate".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "min_borrow_rate".into(),
                                 value: config.min_borrow_rate.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidation_bonus".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidation_bonus".into(),
                                 value: config.liquidation_bonus.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "liquidation_threshold".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "liquidation_threshold".into(),
                                 value: config.liquidation_threshold.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "fee_receiver".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "fee_receiver".into(),
                                 value: config.fee_receiver.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::Pubkey,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "deposit_limit".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "deposit_limit".into(),
                                 value: config.deposit_limit.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                             InstructionProperty {
-                                tx_instruction_id: instruction.tx_instruction_id.clone(),
+                                tx_instruction_id: instruction.tx_instruction_id,
                                 transaction_hash: instruction.transaction_hash.clone(),
-                                parent_index: instruction.parent_index.clone(),
-                                key: "borrow_limit".to_string(),
+                                parent_index: instruction.parent_index,
+                                key: "borrow_limit".into(),
                                 value: config.borrow_limit.to_string(),
-                                parent_key: "config".to_string(),
-                                timestamp: instruction.timestamp.clone(),
+                                value_type: PropertyType::U64,
+                                parent_key: "config".into(),
+                                timestamp: instruction.timestamp,
                             },
                         ],
+                        raw_data: None,
                     })
                 }
             }
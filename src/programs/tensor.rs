@@ -0,0 +1,345 @@
+//! The Tensor NFT marketplace: its pools and single listings (tswap), and its listings of
+//! compressed NFTs (tcomp). Both are Anchor programs: every instruction starts with the first 8
+//! bytes of `sha256("global:<instruction name>")`, followed by its borsh encoded arguments. The
+//! two programs share instruction names, so the discriminators are told apart by program.
+//!
+//! Every trade decodes to `sale` (the instruction it was made with being its `instruction`
+//! property), whatever the side taken, so that the volume of the marketplace is the sales of
+//! the program; listings decode to `list` and `delist`.
+
+use std::convert::TryFrom;
+
+use smallvec::{smallvec, SmallVec};
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, Reader};
+use crate::schema::ProgramSchema;
+
+pub const TSWAP_PROGRAM_ADDRESS: &str = "TSWAPaqyCSx2KABk68Shruf4rp7CxcNi8hAsbdwmHbN";
+pub const TCOMP_PROGRAM_ADDRESS: &str = "TCMPhJdwDryooaGtiocG1u3xcYbRpiJzb283XfCZsDp";
+// The program compressed NFTs are minted with, which derives their asset ids.
+const BUBBLEGUM_PROGRAM_ADDRESS: &str = "BGUMAp9Gq7iTEuizy4pqaxsTyUCBK68MDfK752saRPUY";
+
+const BUY_NFT: [u8; 8] = [96, 0, 28, 190, 49, 107, 83, 222];
+const SELL_NFT_TOKEN_POOL: [u8; 8] = [57, 44, 192, 48, 83, 8, 107, 48];
+const LIST: [u8; 8] = [54, 174, 193, 67, 17, 41, 132, 38];
+const DELIST: [u8; 8] = [55, 136, 205, 107, 107, 173, 4, 31];
+const BUY: [u8; 8] = [102, 6, 61, 18, 1, 218, 235, 234];
+
+/// What the instructions of the programs decode to.
+pub fn describe() -> ProgramSchema {
+    let price = [
+        ("price", "", PropertyType::U64,
+         "Price, in lamports: the most paid for a purchase, the least received for a sale to a \
+         pool"),
+        ("price_sol", "", PropertyType::Decimal, "Price, in SOL"),
+    ];
+    let mint = ("mint", "", PropertyType::Pubkey,
+                "Mint of the NFT, when the accounts of the instruction are known");
+    let leaf = [
+        ("leaf_nonce", "", PropertyType::U64, "Nonce of the compressed NFT in its tree"),
+        ("merkle_tree", "", PropertyType::Pubkey,
+         "Tree of the compressed NFT, when the accounts of the instruction are known"),
+        ("asset_id", "", PropertyType::Pubkey,
+         "Asset id of the compressed NFT, when the accounts of the instruction are known"),
+    ];
+
+    ProgramSchema::new("tensor")
+        .function("sale", &[
+            ("instruction", "", PropertyType::Text,
+             "Instruction the trade was made with (buy-nft, sell-nft-token-pool or buy, for \
+             compressed NFTs)"),
+            price[0],
+            price[1],
+            ("seller_fee_basis_points", "", PropertyType::U64,
+             "Royalties of the compressed NFT, in basis points"),
+            ("royalty_pct", "", PropertyType::U64,
+             "Share of the royalties paid, in percent, when optional"),
+            mint,
+            leaf[0],
+            leaf[1],
+            leaf[2],
+        ])
+        .function("list", &[price[0], price[1], mint, leaf[0], leaf[1], leaf[2]])
+        .function("delist", &[mint])
+}
+
+// Where the NFT traded is found: its mint in the accounts, or its tree in the accounts and its
+// nonce in the arguments.
+enum Asset {
+    Mint(usize),
+    Leaf { tree: usize, nonce: u64 },
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    if instruction.data.len() < 8 {
+        error!("[spi-wrapper/tensor] Attempt to parse instruction from program {} failed as it \
+            is shorter than a discriminator.", instruction.program);
+
+        return None;
+    }
+    let (discriminator, arguments) = instruction.data.split_at(8);
+    let mut arguments = Reader::new(arguments);
+    let compressed = instruction.program.as_str() == TCOMP_PROGRAM_ADDRESS;
+
+    let decoded = match (compressed, <[u8; 8]>::try_from(discriminator).ok()?) {
+        (false, BUY_NFT) => pool_trade(&mut arguments, "buy-nft")
+            .map(|fields| ("sale", fields, Asset::Mint(5))),
+        (false, SELL_NFT_TOKEN_POOL) => pool_trade(&mut arguments, "sell-nft-token-pool")
+            .map(|fields| ("sale", fields, Asset::Mint(6))),
+        (false, LIST) => arguments.u64().map(|price| ("list", price_fields(price), Asset::Mint(2))),
+        (false, DELIST) => Some(("delist", smallvec![], Asset::Mint(2))),
+        (true, BUY) => compressed_buy(&mut arguments)
+            .map(|(fields, nonce)| ("sale", fields, Asset::Leaf { tree: 2, nonce })),
+        (true, LIST) => compressed_list(&mut arguments)
+            .map(|(fields, nonce)| ("list", fields, Asset::Leaf { tree: 3, nonce })),
+        _ => {
+            debug!("[spi-wrapper/tensor] Instruction {} of {} isn't one the processor decodes.",
+                hex::encode(discriminator), instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let (function_name, fields, asset) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/tensor] Attempt to parse instruction from program {} failed due \
+                to truncated or invalid arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, value, value_type)| layout::property(&instruction, key, "", value, value_type))
+        .collect();
+    let pubkey = |key: &str, account: &Pubkey| {
+        layout::property(&instruction, key, "", account.to_string(), PropertyType::Pubkey)
+    };
+    match asset {
+        Asset::Mint(index) => {
+            instruction_set.properties.extend(accounts.get(index).map(|mint| pubkey("mint", mint)));
+        }
+        Asset::Leaf { tree, nonce } => {
+            if let Some(tree) = accounts.get(tree) {
+                instruction_set.properties.push(pubkey("merkle_tree", tree));
+                instruction_set.properties.push(pubkey("asset_id", &asset_id(tree, nonce)));
+            }
+        }
+    }
+
+    Some(instruction_set)
+}
+
+// The properties of an instruction: key, value and type.
+type Fields = SmallVec<[(&'static str, String, PropertyType); 8]>;
+
+fn price_fields(price: u64) -> Fields {
+    smallvec![
+        ("price", price.to_string(), PropertyType::U64),
+        ("price_sol", layout::sol(price), PropertyType::Decimal),
+    ]
+}
+
+/// The arguments of `buy_nft` and `sell_nft_token_pool`: the config of the pool, the price, then
+/// the royalties optionally paid on top.
+fn pool_trade(arguments: &mut Reader, instruction: &str) -> Option<Fields> {
+    // `PoolConfig`: the pool and curve types, the starting price and delta of the curve, and the
+    // fees of market making pools.
+    arguments.u8()?;
+    arguments.u8()?;
+    arguments.u64()?;
+    arguments.u64()?;
+    arguments.bool()?;
+    arguments.option(Reader::u16)?;
+    let price = arguments.u64()?;
+
+    let mut fields: Fields =
+        smallvec![("instruction", instruction.to_string(), PropertyType::Text)];
+    fields.extend(price_fields(price));
+    // Followed by whether a rule set account is passed and the authorization data of programmable
+    // NFTs, whose payload the optional royalties of later versions come after: they're only read
+    // without authorization data.
+    if let (Some(_), Some(0)) = (arguments.bool(), arguments.u8()) {
+        let royalty_pct = arguments.option(Reader::u16).flatten();
+        fields.extend(royalty_pct
+            .map(|royalty_pct| ("royalty_pct", royalty_pct.to_string(), PropertyType::U64)));
+    }
+
+    Some(fields)
+}
+
+/// The arguments of the tcomp `buy`, and the nonce of the NFT bought.
+fn compressed_buy(arguments: &mut Reader) -> Option<(Fields, u64)> {
+    let nonce = arguments.u64()?;
+    // Its index in the tree, the root of the tree and the hash of its metadata.
+    arguments.u32()?;
+    arguments.bytes(32 + 32)?;
+    // The shares of its creators, and whether they're verified.
+    arguments.vec(1, Reader::u8)?;
+    arguments.vec(1, Reader::bool)?;
+    let seller_fee_basis_points = arguments.u16()?;
+    let max_amount = arguments.u64()?;
+    let royalty_pct = arguments.option(Reader::u16).flatten();
+
+    let mut fields: Fields = smallvec![("instruction", "buy".to_string(), PropertyType::Text)];
+    fields.extend(price_fields(max_amount));
+    fields.push(("seller_fee_basis_points", seller_fee_basis_points.to_string(),
+                 PropertyType::U64));
+    fields.extend(royalty_pct
+        .map(|royalty_pct| ("royalty_pct", royalty_pct.to_string(), PropertyType::U64)));
+    fields.push(("leaf_nonce", nonce.to_string(), PropertyType::U64));
+    Some((fields, nonce))
+}
+
+/// The arguments of the tcomp `list`, and the nonce of the NFT listed.
+fn compressed_list(arguments: &mut Reader) -> Option<(Fields, u64)> {
+    let nonce = arguments.u64()?;
+    // Its index in the tree, the root of the tree and the hashes of its data and creators.
+    arguments.u32()?;
+    arguments.bytes(32 + 32 + 32)?;
+    let amount = arguments.u64()?;
+
+    let mut fields = price_fields(amount);
+    fields.push(("leaf_nonce", nonce.to_string(), PropertyType::U64));
+    Some((fields, nonce))
+}
+
+/// The asset id of the compressed NFT of `nonce` in `tree`.
+fn asset_id(tree: &Pubkey, nonce: u64) -> Pubkey {
+    let bubblegum: Pubkey = BUBBLEGUM_PROGRAM_ADDRESS.parse().unwrap();
+    let seeds: &[&[u8]] = &[b"asset", tree.as_ref(), &nonce.to_le_bytes()];
+
+    Pubkey::find_program_address(seeds, &bubblegum).0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use solana_sdk::hash::hash;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    // Instruction data as sent to the programs, discriminator included.
+    // Buying from a trade pool for 12.5 SOL at most, paying 50% of the optional royalties.
+    const BUY_NFT_12_5_SOL: &str = "60001cbe316b53de020000f2052a0100000000e1f505000000000101640000\
+        dd0ee9020000000000013200";
+    // Selling to a token pool for 3 SOL at least, with the authorization data of a programmable
+    // NFT (whose payload is left out, as it isn't read).
+    const SELL_NFT_TOKEN_POOL_3_SOL: &str = "392cc03053086b300000005ed0b2000000000000000000000000\
+        0000005ed0b2000000000101";
+    const LIST_0_042_SOL: &str = "36aec1431129842680de800200000000";
+    const DELIST: &str = "3788cd6b6bad041f";
+    // Buying the compressed NFT of nonce 77 for 0.25 SOL at most, with 5% royalties.
+    const COMPRESSED_BUY: &str = "66063d1201daebea4d000000000000004d000000000000000000000000000000\
+        000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000\
+        0000000000000001000000640100000001f40180b2e60e0000000000";
+    // Listing it for 0.03 SOL.
+    const COMPRESSED_LIST: &str = "36aec143112984264d000000000000004d0000000000000000000000000000\
+        000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000\
+        0000000000000000000000000000000000000000000000000000000000000000000000000000000080c3c90100\
+        00000000";
+
+    fn decode(program: &str, data: &str, accounts: &[Pubkey]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(program, hex::decode(data).unwrap()), accounts)
+    }
+
+    #[test]
+    fn discriminators_are_the_anchor_ones() {
+        for (name, discriminator) in &[
+            ("buy_nft", BUY_NFT),
+            ("sell_nft_token_pool", SELL_NFT_TOKEN_POOL),
+            ("list", LIST),
+            ("delist", super::DELIST),
+            ("buy", BUY),
+        ] {
+            let preimage = format!("global:{}", name);
+            let expected: [u8; 8] = hash(preimage.as_bytes()).to_bytes()[..8].try_into().unwrap();
+            assert_eq!(*discriminator, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn pool_trades_and_listings_decode() {
+        let accounts: Vec<_> = (0..16).map(|_| Pubkey::new_unique()).collect();
+
+        let buy = decode(TSWAP_PROGRAM_ADDRESS, BUY_NFT_12_5_SOL, &accounts).unwrap();
+        assert_eq!(buy.function.function_name.as_str(), "sale");
+        assert_eq!(properties(&buy), vec![
+            ("instruction", "buy-nft".to_string()),
+            ("price", "12500000000".to_string()),
+            ("price_sol", "12.5".to_string()),
+            ("royalty_pct", "50".to_string()),
+            ("mint", accounts[5].to_string()),
+        ]);
+        let sell = decode(TSWAP_PROGRAM_ADDRESS, SELL_NFT_TOKEN_POOL_3_SOL, &[]).unwrap();
+        assert_eq!(properties(&sell), vec![
+            ("instruction", "sell-nft-token-pool".to_string()),
+            ("price", "3000000000".to_string()),
+            ("price_sol", "3".to_string()),
+        ]);
+
+        let list = decode(TSWAP_PROGRAM_ADDRESS, LIST_0_042_SOL, &accounts).unwrap();
+        assert_eq!(list.function.function_name.as_str(), "list");
+        assert_eq!(properties(&list), vec![
+            ("price", "42000000".to_string()),
+            ("price_sol", "0.042".to_string()),
+            ("mint", accounts[2].to_string()),
+        ]);
+        let delist = decode(TSWAP_PROGRAM_ADDRESS, DELIST, &accounts).unwrap();
+        assert_eq!(properties(&delist), vec![("mint", accounts[2].to_string())]);
+    }
+
+    #[test]
+    fn compressed_trades_and_listings_decode() {
+        let accounts: Vec<_> = (0..16).map(|_| Pubkey::new_unique()).collect();
+
+        let buy = decode(TCOMP_PROGRAM_ADDRESS, COMPRESSED_BUY, &accounts).unwrap();
+        assert_eq!(buy.function.function_name.as_str(), "sale");
+        let asset_id_77 = asset_id(&accounts[2], 77).to_string();
+        assert_eq!(properties(&buy), vec![
+            ("instruction", "buy".to_string()),
+            ("price", "250000000".to_string()),
+            ("price_sol", "0.25".to_string()),
+            ("seller_fee_basis_points", "500".to_string()),
+            ("leaf_nonce", "77".to_string()),
+            ("merkle_tree", accounts[2].to_string()),
+            ("asset_id", asset_id_77.clone()),
+        ]);
+        assert_ne!(asset_id(&accounts[2], 78).to_string(), asset_id_77);
+
+        // The same discriminator as the tswap `list`.
+        let list = decode(TCOMP_PROGRAM_ADDRESS, COMPRESSED_LIST, &accounts).unwrap();
+        assert_eq!(list.function.function_name.as_str(), "list");
+        assert_eq!(&properties(&list)[..3], &[
+            ("price", "30000000".to_string()),
+            ("price_sol", "0.03".to_string()),
+            ("leaf_nonce", "77".to_string()),
+        ]);
+        assert_eq!(properties(&list)[3], ("merkle_tree", accounts[3].to_string()));
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        // Cut within the pool config.
+        assert!(decode(TSWAP_PROGRAM_ADDRESS, &BUY_NFT_12_5_SOL[..2 * 12], &[]).is_none());
+        // Cut before the creator shares.
+        assert!(decode(TCOMP_PROGRAM_ADDRESS, &COMPRESSED_BUY[..2 * (8 + 8 + 4 + 64)], &[])
+            .is_none());
+        // A tcomp instruction sent to tswap.
+        assert!(decode(TSWAP_PROGRAM_ADDRESS, COMPRESSED_BUY, &[]).is_none());
+        assert!(decode(TSWAP_PROGRAM_ADDRESS, "0001020304050607", &[]).is_none());
+        assert!(decode(TCOMP_PROGRAM_ADDRESS, "66063d", &[]).is_none());
+    }
+}
@@ -0,0 +1,386 @@
+//! The Metaplex token metadata program, describing the tokens (NFTs first) of SPL mints: every
+//! instruction starts with a one byte tag, followed by its borsh encoded arguments.
+//!
+//! The metadata is flattened: its name, symbol, uri and royalties are top-level properties, each
+//! creator is nested under `creators/{index}` and its uses under `uses`. Names and symbols used to
+//! be padded with NULs, which are trimmed. The metadata accounts and mints are read from the
+//! accounts of the instructions, and are only emitted when they're known.
+
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, flag, top_level, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+
+const UPDATE_METADATA_ACCOUNT_V2: u8 = 15;
+const VERIFY_COLLECTION: u8 = 18;
+const SET_AND_VERIFY_COLLECTION: u8 = 25;
+const CREATE_METADATA_ACCOUNT_V3: u8 = 33;
+const MINT: u8 = 43;
+const TRANSFER: u8 = 49;
+
+const USE_METHODS: &[&str] = &["burn", "multiple", "single"];
+
+// The accounts emitted for each instruction: key, then where it is in its accounts.
+type Accounts = &'static [(&'static str, usize)];
+
+const CREATE_ACCOUNTS: Accounts = &[("metadata", 0), ("mint", 1)];
+const UPDATE_ACCOUNTS: Accounts = &[("metadata", 0)];
+const VERIFY_COLLECTION_ACCOUNTS: Accounts = &[("metadata", 0), ("collection_mint", 3)];
+const SET_AND_VERIFY_COLLECTION_ACCOUNTS: Accounts = &[("metadata", 0), ("collection_mint", 4)];
+const MINT_ACCOUNTS: Accounts = &[("token_owner", 1), ("metadata", 2), ("mint", 5)];
+const TRANSFER_ACCOUNTS: Accounts =
+    &[("token_owner", 1), ("destination_owner", 3), ("mint", 4), ("metadata", 5)];
+
+// The properties of an instruction: key, parent key, value and type.
+type Fields = Vec<(&'static str, String, String, PropertyType)>;
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let data = [
+        ("name", "", PropertyType::Text, "Name of the token"),
+        ("symbol", "", PropertyType::Text, "Symbol of the token"),
+        ("uri", "", PropertyType::Text, "URI of the JSON metadata of the token"),
+        ("seller_fee_basis_points", "", PropertyType::U64,
+         "Royalties paid to the creators on secondary sales, in basis points"),
+        ("address", "creators/{index}", PropertyType::Pubkey, "Address of a creator"),
+        ("verified", "creators/{index}", PropertyType::Bool,
+         "Whether the creator signed the metadata"),
+        ("share", "creators/{index}", PropertyType::U64,
+         "Share of the royalties of the creator, in percent"),
+        ("collection", "", PropertyType::Pubkey, "Mint of the collection of the token, if any"),
+        ("collection_verified", "", PropertyType::Bool,
+         "Whether the collection verified the token, if any"),
+        ("use_method", "uses", PropertyType::Text,
+         "How the token is used up (burn, multiple or single), if it is"),
+        ("remaining", "uses", PropertyType::U64, "Uses left"),
+        ("total", "uses", PropertyType::U64, "Uses in all"),
+    ];
+    let metadata = ("metadata", "", PropertyType::Pubkey,
+                    "Metadata account, when the accounts of the instruction are known");
+    let mint = ("mint", "", PropertyType::Pubkey,
+                "Mint of the token, when the accounts of the instruction are known");
+    let collection_mint = ("collection_mint", "", PropertyType::Pubkey,
+                           "Mint of the collection, when the accounts of the instruction are \
+                           known");
+    let amount = ("amount", "", PropertyType::U64, "Amount of tokens");
+    let token_owner = ("token_owner", "", PropertyType::Pubkey,
+                       "Owner of the token account, when the accounts of the instruction are \
+                       known");
+
+    let mut create = data.to_vec();
+    create.extend_from_slice(&[
+        ("is_mutable", "", PropertyType::Bool, "Whether the metadata can be updated"),
+        ("collection_size", "", PropertyType::U64,
+         "Size of the collection, when the token is a collection"),
+        metadata,
+        mint,
+    ]);
+    let mut update = data.to_vec();
+    update.extend_from_slice(&[
+        ("update_authority", "", PropertyType::Pubkey, "New update authority, if changed"),
+        ("primary_sale_happened", "", PropertyType::Bool,
+         "Whether the token was sold already, if changed"),
+        ("is_mutable", "", PropertyType::Bool, "Whether the metadata can be updated, if changed"),
+        metadata,
+    ]);
+
+    ProgramSchema::new("token-metadata")
+        .function("create-metadata-account-v3", &create)
+        .function("update-metadata-account-v2", &update)
+        .function("verify-collection", &[metadata, collection_mint])
+        .function("set-and-verify-collection", &[metadata, collection_mint])
+        .function("mint", &[amount, token_owner, metadata, mint])
+        .function("transfer", &[
+            amount,
+            token_owner,
+            ("destination_owner", "", PropertyType::Pubkey,
+             "Owner of the token account transferred to, when the accounts of the instruction are \
+             known"),
+            mint,
+            metadata,
+        ])
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    let (tag, arguments) = match instruction.data.split_first() {
+        Some(split) => split,
+        None => {
+            error!("[spi-wrapper/token-metadata] Attempt to parse instruction from program {} \
+                failed as it is empty.", instruction.program);
+
+            return None;
+        }
+    };
+    let mut arguments = Reader::new(arguments);
+
+    let decoded = match *tag {
+        CREATE_METADATA_ACCOUNT_V3 => create_metadata_account_v3(&mut arguments)
+            .map(|fields| ("create-metadata-account-v3", fields, CREATE_ACCOUNTS)),
+        UPDATE_METADATA_ACCOUNT_V2 => update_metadata_account_v2(&mut arguments)
+            .map(|fields| ("update-metadata-account-v2", fields, UPDATE_ACCOUNTS)),
+        VERIFY_COLLECTION => {
+            Some(("verify-collection", Fields::new(), VERIFY_COLLECTION_ACCOUNTS))
+        }
+        SET_AND_VERIFY_COLLECTION => {
+            Some(("set-and-verify-collection", Fields::new(), SET_AND_VERIFY_COLLECTION_ACCOUNTS))
+        }
+        MINT => amount(&mut arguments).map(|fields| ("mint", fields, MINT_ACCOUNTS)),
+        TRANSFER => amount(&mut arguments).map(|fields| ("transfer", fields, TRANSFER_ACCOUNTS)),
+        _ => {
+            debug!("[spi-wrapper/token-metadata] Instruction {} of {} isn't one the processor \
+                decodes.", tag, instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let (function_name, fields, known_accounts) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/token-metadata] Attempt to parse instruction from program {} \
+                failed due to truncated or invalid arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, parent_key, value, value_type)| {
+            layout::property(&instruction, key, &parent_key, value, value_type)
+        })
+        .collect();
+    for (key, index) in known_accounts {
+        if let Some(account) = accounts.get(*index) {
+            instruction_set.properties.push(layout::property(
+                &instruction, key, "", account.to_string(), PropertyType::Pubkey));
+        }
+    }
+
+    Some(instruction_set)
+}
+
+/// A string of the metadata, without the NULs it may be padded with.
+fn string(arguments: &mut Reader) -> Option<String> {
+    arguments.string().map(|string| string.trim_end_matches('\0').to_string())
+}
+
+/// `DataV2`: the name, symbol, uri, royalties and creators, then the collection and uses.
+fn data_v2(arguments: &mut Reader) -> Option<Fields> {
+    let mut fields = vec![
+        top_level("name", string(arguments)?, PropertyType::Text),
+        top_level("symbol", string(arguments)?, PropertyType::Text),
+        top_level("uri", string(arguments)?, PropertyType::Text),
+        top_level("seller_fee_basis_points", arguments.u16()?.to_string(), PropertyType::U64),
+    ];
+    // Creators: an address, whether it's verified and a share.
+    let creators = arguments.option(|arguments| {
+        arguments.vec(34, |arguments| {
+            Some((arguments.pubkey()?, arguments.bool()?, arguments.u8()?))
+        })
+    })?;
+    for (index, (address, verified, share)) in creators.into_iter().flatten().enumerate() {
+        let parent_key = format!("creators/{}", index);
+        fields.push(("address", parent_key.clone(), address.to_string(), PropertyType::Pubkey));
+        fields.push(("verified", parent_key.clone(), flag(verified), PropertyType::Bool));
+        fields.push(("share", parent_key, share.to_string(), PropertyType::U64));
+    }
+    let collection = arguments.option(|arguments| Some((arguments.bool()?, arguments.pubkey()?)))?;
+    if let Some((verified, key)) = collection {
+        fields.push(top_level("collection", key.to_string(), PropertyType::Pubkey));
+        fields.push(top_level("collection_verified", flag(verified), PropertyType::Bool));
+    }
+    let uses = arguments.option(|arguments| {
+        Some((arguments.name(USE_METHODS)?, arguments.u64()?, arguments.u64()?))
+    })?;
+    if let Some((use_method, remaining, total)) = uses {
+        let uses = || "uses".to_string();
+        fields.push(("use_method", uses(), use_method.to_string(), PropertyType::Text));
+        fields.push(("remaining", uses(), remaining.to_string(), PropertyType::U64));
+        fields.push(("total", uses(), total.to_string(), PropertyType::U64));
+    }
+
+    Some(fields)
+}
+
+/// `CreateMetadataAccountArgsV3`: the metadata, whether it's mutable, and the size of the
+/// collection when the token is one.
+fn create_metadata_account_v3(arguments: &mut Reader) -> Option<Fields> {
+    let mut fields = data_v2(arguments)?;
+    fields.push(top_level("is_mutable", flag(arguments.bool()?), PropertyType::Bool));
+    // `CollectionDetails::V1 { size }`, the only variant with a size.
+    let collection_details =
+        arguments.option(|arguments| Some((arguments.u8()?, arguments.u64()?)))?;
+    if let Some((0, size)) = collection_details {
+        fields.push(top_level("collection_size", size.to_string(), PropertyType::U64));
+    }
+
+    Some(fields)
+}
+
+/// `UpdateMetadataAccountArgsV2`: whatever is changed of the metadata, the update authority,
+/// whether the token was sold and whether it's mutable.
+fn update_metadata_account_v2(arguments: &mut Reader) -> Option<Fields> {
+    let mut fields = arguments.option(data_v2)?.unwrap_or_default();
+    if let Some(update_authority) = arguments.option(Reader::pubkey)? {
+        fields.push(top_level("update_authority", update_authority.to_string(),
+                              PropertyType::Pubkey));
+    }
+    if let Some(primary_sale_happened) = arguments.option(Reader::bool)? {
+        fields.push(top_level("primary_sale_happened", flag(primary_sale_happened),
+                              PropertyType::Bool));
+    }
+    if let Some(is_mutable) = arguments.option(Reader::bool)? {
+        fields.push(top_level("is_mutable", flag(is_mutable), PropertyType::Bool));
+    }
+
+    Some(fields)
+}
+
+/// `MintArgs::V1` and `TransferArgs::V1`: the amount, followed by authorization data for the
+/// rule sets of programmable NFTs, which isn't decoded.
+fn amount(arguments: &mut Reader) -> Option<Fields> {
+    match arguments.u8()? {
+        0 => Some(vec![top_level("amount", arguments.u64()?.to_string(), PropertyType::U64)]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    fn decode(data: &[u8], accounts: &[Pubkey]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, data.to_vec()), accounts)
+    }
+
+    fn string(data: &mut Vec<u8>, string: &str) {
+        data.extend_from_slice(&(string.len() as u32).to_le_bytes());
+        data.extend_from_slice(string.as_bytes());
+    }
+
+    /// A `DataV2` of two creators and a collection, without uses.
+    fn data_v2(creators: &[Pubkey; 2], collection: &Pubkey) -> Vec<u8> {
+        let mut data = Vec::new();
+        string(&mut data, "Degen Ape #42\0\0\0");
+        string(&mut data, "DAPE");
+        string(&mut data, "https://arweave.net/ape42");
+        data.extend_from_slice(&420u16.to_le_bytes());
+        data.extend_from_slice(&[1, 2, 0, 0, 0]);
+        data.extend_from_slice(creators[0].as_ref());
+        data.extend_from_slice(&[1, 0]);
+        data.extend_from_slice(creators[1].as_ref());
+        data.extend_from_slice(&[0, 100]);
+        data.extend_from_slice(&[1, 0]);
+        data.extend_from_slice(collection.as_ref());
+        data.push(0);
+        data
+    }
+
+    fn expected_data(creators: &[Pubkey; 2], collection: &Pubkey)
+    -> Vec<(&'static str, String)> {
+        vec![
+            ("name", "Degen Ape #42".to_string()),
+            ("symbol", "DAPE".to_string()),
+            ("uri", "https://arweave.net/ape42".to_string()),
+            ("seller_fee_basis_points", "420".to_string()),
+            ("creators/0.address", creators[0].to_string()),
+            ("creators/0.verified", "1".to_string()),
+            ("creators/0.share", "0".to_string()),
+            ("creators/1.address", creators[1].to_string()),
+            ("creators/1.verified", "0".to_string()),
+            ("creators/1.share", "100".to_string()),
+            ("collection", collection.to_string()),
+            ("collection_verified", "0".to_string()),
+        ]
+    }
+
+    #[test]
+    fn metadata_is_flattened() {
+        let creators = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let collection = Pubkey::new_unique();
+        let accounts: Vec<_> = (0..7).map(|_| Pubkey::new_unique()).collect();
+
+        // Mutable, not a collection.
+        let create = [&[CREATE_METADATA_ACCOUNT_V3][..], &data_v2(&creators, &collection), &[1, 0]]
+            .concat();
+        let create = decode(&create, &accounts).unwrap();
+        assert_eq!(create.function.function_name.as_str(), "create-metadata-account-v3");
+        let mut expected = expected_data(&creators, &collection);
+        expected.extend(vec![
+            ("is_mutable", "1".to_string()),
+            ("metadata", accounts[0].to_string()),
+            ("mint", accounts[1].to_string()),
+        ]);
+        assert_eq!(properties(&create), expected);
+
+        // Only the update authority changes, and the token is sold.
+        let new_authority = Pubkey::new_unique();
+        let update = [&[UPDATE_METADATA_ACCOUNT_V2, 0, 1][..], new_authority.as_ref(), &[1, 1, 0]]
+            .concat();
+        assert_eq!(properties(&decode(&update, &[]).unwrap()), vec![
+            ("update_authority", new_authority.to_string()),
+            ("primary_sale_happened", "1".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn collections_carry_their_size() {
+        let creators = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let collection = Pubkey::new_unique();
+        let mut create = [&[CREATE_METADATA_ACCOUNT_V3][..], &data_v2(&creators, &collection)]
+            .concat();
+        create.extend_from_slice(&[0, 1, 0]);
+        create.extend_from_slice(&10_000u64.to_le_bytes());
+
+        let create = properties(&decode(&create, &[]).unwrap());
+        assert_eq!(create[create.len() - 2..], [
+            ("is_mutable", "0".to_string()),
+            ("collection_size", "10000".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn programmable_nfts_are_minted_and_transferred() {
+        let accounts: Vec<_> = (0..17).map(|_| Pubkey::new_unique()).collect();
+        // `TransferArgs::V1` of 1 token, without authorization data.
+        let transfer = [&[TRANSFER, 0][..], &1u64.to_le_bytes(), &[0]].concat();
+
+        assert_eq!(properties(&decode(&transfer, &accounts).unwrap()), vec![
+            ("amount", "1".to_string()),
+            ("token_owner", accounts[1].to_string()),
+            ("destination_owner", accounts[3].to_string()),
+            ("mint", accounts[4].to_string()),
+            ("metadata", accounts[5].to_string()),
+        ]);
+        let mint = [&[MINT, 0][..], &1u64.to_le_bytes(), &[0]].concat();
+        assert_eq!(decode(&mint, &[]).unwrap().function.function_name.as_str(), "mint");
+
+        let verify = decode(&[VERIFY_COLLECTION], &accounts).unwrap();
+        assert_eq!(properties(&verify), vec![
+            ("metadata", accounts[0].to_string()),
+            ("collection_mint", accounts[3].to_string()),
+        ]);
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        let creators = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let data = data_v2(&creators, &Pubkey::new_unique());
+        assert!(decode(&[&[CREATE_METADATA_ACCOUNT_V3][..], &data[..40]].concat(), &[]).is_none());
+        // A transfer of an unknown version.
+        assert!(decode(&[TRANSFER, 1, 0, 0, 0, 0, 0, 0, 0, 0], &[]).is_none());
+        assert!(decode(&[200], &[]).is_none());
+        assert!(decode(&[], &[]).is_none());
+    }
+}
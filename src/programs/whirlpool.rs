@@ -0,0 +1,323 @@
+//! The Orca Whirlpool concentrated liquidity AMM, an Anchor program: every instruction starts
+//! with the first 8 bytes of `sha256("global:<instruction name>")`, followed by its borsh encoded
+//! arguments.
+//!
+//! Prices are square roots, in Q64.64 fixed point (`sqrt(price) * 2^64`), and emitted as such;
+//! ranges are bounds of tick indexes, each tick a 0.01% price step. Pools, positions and their
+//! mints are read from the accounts of the instructions, and are only emitted when they're known.
+
+use std::convert::TryFrom;
+
+use smallvec::{smallvec, SmallVec};
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, error};
+
+use crate::{Instruction, InstructionSet, PropertyType};
+use crate::programs::layout::{self, Reader};
+use crate::schema::ProgramSchema;
+
+pub const PROGRAM_ADDRESS: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+
+const INITIALIZE_POOL: [u8; 8] = [95, 180, 10, 172, 84, 174, 232, 40];
+const OPEN_POSITION: [u8; 8] = [135, 128, 47, 77, 15, 152, 240, 49];
+const INCREASE_LIQUIDITY: [u8; 8] = [46, 156, 243, 118, 13, 205, 251, 178];
+const DECREASE_LIQUIDITY: [u8; 8] = [160, 38, 208, 111, 104, 91, 44, 1];
+const CLOSE_POSITION: [u8; 8] = [123, 134, 81, 0, 49, 68, 98, 98];
+const COLLECT_FEES: [u8; 8] = [164, 152, 207, 99, 30, 186, 19, 182];
+const SWAP: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+
+// The accounts emitted for each instruction: key, then where it is in its accounts.
+type Accounts = &'static [(&'static str, usize)];
+
+const INITIALIZE_POOL_ACCOUNTS: Accounts =
+    &[("whirlpool", 4), ("token_mint_a", 1), ("token_mint_b", 2)];
+const OPEN_POSITION_ACCOUNTS: Accounts =
+    &[("whirlpool", 5), ("position", 2), ("position_mint", 3)];
+const LIQUIDITY_ACCOUNTS: Accounts = &[("whirlpool", 0), ("position", 3)];
+const CLOSE_POSITION_ACCOUNTS: Accounts = &[("position", 2), ("position_mint", 3)];
+const COLLECT_FEES_ACCOUNTS: Accounts = &[("whirlpool", 0), ("position", 2)];
+const SWAP_ACCOUNTS: Accounts = &[("whirlpool", 2)];
+
+/// What the instructions of the program decode to.
+pub fn describe() -> ProgramSchema {
+    let whirlpool = ("whirlpool", "", PropertyType::Pubkey,
+                     "Pool of the instruction, when the accounts of the instruction are known");
+    let position = ("position", "", PropertyType::Pubkey,
+                    "Position of the instruction, when the accounts of the instruction are known");
+    let position_mint = ("position_mint", "", PropertyType::Pubkey,
+                         "Mint of the NFT of the position, when the accounts of the instruction \
+                         are known");
+    let liquidity_amount = ("liquidity_amount", "", PropertyType::Decimal,
+                            "Liquidity added to or removed from the position");
+
+    ProgramSchema::new("whirlpool")
+        .function("initialize-pool", &[
+            ("tick_spacing", "", PropertyType::U64, "Ticks between the initializable ticks"),
+            ("initial_sqrt_price", "", PropertyType::Decimal,
+             "Square root of the initial price, in Q64.64 fixed point"),
+            whirlpool,
+            ("token_mint_a", "", PropertyType::Pubkey,
+             "Mint of the token A of the pool, when the accounts of the instruction are known"),
+            ("token_mint_b", "", PropertyType::Pubkey,
+             "Mint of the token B of the pool, when the accounts of the instruction are known"),
+        ])
+        .function("open-position", &[
+            ("tick_lower_index", "", PropertyType::I128, "Lower tick of the range of the position"),
+            ("tick_upper_index", "", PropertyType::I128, "Upper tick of the range of the position"),
+            whirlpool,
+            position,
+            position_mint,
+        ])
+        .function("increase-liquidity", &[
+            liquidity_amount,
+            ("token_max_a", "", PropertyType::U64, "Maximum amount of token A deposited"),
+            ("token_max_b", "", PropertyType::U64, "Maximum amount of token B deposited"),
+            whirlpool,
+            position,
+        ])
+        .function("decrease-liquidity", &[
+            liquidity_amount,
+            ("token_min_a", "", PropertyType::U64, "Minimum amount of token A withdrawn"),
+            ("token_min_b", "", PropertyType::U64, "Minimum amount of token B withdrawn"),
+            whirlpool,
+            position,
+        ])
+        .function("close-position", &[position, position_mint])
+        .function("collect-fees", &[whirlpool, position])
+        .function("swap", &[
+            ("amount", "", PropertyType::U64,
+             "Amount swapped: of input tokens when `amount_specified_is_input`, of output tokens \
+             otherwise"),
+            ("other_amount_threshold", "", PropertyType::U64,
+             "Least amount of output tokens, or most amount of input tokens, of the swap"),
+            ("sqrt_price_limit", "", PropertyType::Decimal,
+             "Square root of the price the swap stops at, in Q64.64 fixed point"),
+            ("amount_specified_is_input", "", PropertyType::Bool,
+             "Whether `amount` is the amount of input tokens"),
+            ("a_to_b", "", PropertyType::Bool, "Whether token A is swapped for token B"),
+            whirlpool,
+        ])
+}
+
+pub fn fragment_instruction(
+    // The instruction in question.
+    instruction: Instruction,
+    // The accounts it was invoked with, if known.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    if instruction.data.len() < 8 {
+        error!("[spi-wrapper/whirlpool] Attempt to parse instruction from program {} failed as \
+            it is shorter than a discriminator.", instruction.program);
+
+        return None;
+    }
+    let (discriminator, arguments) = instruction.data.split_at(8);
+    let mut arguments = Reader::new(arguments);
+
+    let decoded = match <[u8; 8]>::try_from(discriminator).ok()? {
+        INITIALIZE_POOL => initialize_pool(&mut arguments)
+            .map(|fields| ("initialize-pool", fields, INITIALIZE_POOL_ACCOUNTS)),
+        OPEN_POSITION => open_position(&mut arguments)
+            .map(|fields| ("open-position", fields, OPEN_POSITION_ACCOUNTS)),
+        INCREASE_LIQUIDITY => liquidity(&mut arguments, ["token_max_a", "token_max_b"])
+            .map(|fields| ("increase-liquidity", fields, LIQUIDITY_ACCOUNTS)),
+        DECREASE_LIQUIDITY => liquidity(&mut arguments, ["token_min_a", "token_min_b"])
+            .map(|fields| ("decrease-liquidity", fields, LIQUIDITY_ACCOUNTS)),
+        CLOSE_POSITION => Some(("close-position", smallvec![], CLOSE_POSITION_ACCOUNTS)),
+        COLLECT_FEES => Some(("collect-fees", smallvec![], COLLECT_FEES_ACCOUNTS)),
+        SWAP => swap(&mut arguments).map(|fields| ("swap", fields, SWAP_ACCOUNTS)),
+        _ => {
+            debug!("[spi-wrapper/whirlpool] Instruction {} of {} isn't one the processor \
+                decodes.", hex::encode(discriminator), instruction.transaction_hash);
+
+            return None;
+        }
+    };
+
+    let (function_name, fields, known_accounts) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            error!("[spi-wrapper/whirlpool] Attempt to parse instruction from program {} failed \
+                due to truncated or invalid arguments.", instruction.program);
+
+            return None;
+        }
+    };
+
+    let mut instruction_set = layout::instruction_set(&instruction, function_name);
+    instruction_set.properties = fields.into_iter()
+        .map(|(key, value, value_type)| layout::property(&instruction, key, "", value, value_type))
+        .collect();
+    for (key, index) in known_accounts {
+        if let Some(account) = accounts.get(*index) {
+            instruction_set.properties.push(layout::property(
+                &instruction, key, "", account.to_string(), PropertyType::Pubkey));
+        }
+    }
+
+    Some(instruction_set)
+}
+
+// The properties of an instruction: key, value and type.
+type Fields = SmallVec<[(&'static str, String, PropertyType); 5]>;
+
+/// The arguments of `initialize_pool`: the bump of the pool, its tick spacing and initial price.
+fn initialize_pool(arguments: &mut Reader) -> Option<Fields> {
+    let _whirlpool_bump = arguments.u8()?;
+    let tick_spacing = arguments.u16()?;
+    let initial_sqrt_price = arguments.u128()?;
+
+    Some(smallvec![
+        ("tick_spacing", tick_spacing.to_string(), PropertyType::U64),
+        ("initial_sqrt_price", initial_sqrt_price.to_string(), PropertyType::Decimal),
+    ])
+}
+
+/// The arguments of `open_position`: the bump of the position and its range.
+fn open_position(arguments: &mut Reader) -> Option<Fields> {
+    let _position_bump = arguments.u8()?;
+    let tick_lower_index = arguments.i32()?;
+    let tick_upper_index = arguments.i32()?;
+
+    Some(smallvec![
+        ("tick_lower_index", tick_lower_index.to_string(), PropertyType::I128),
+        ("tick_upper_index", tick_upper_index.to_string(), PropertyType::I128),
+    ])
+}
+
+/// The arguments of `increase_liquidity` and `decrease_liquidity`, the liquidity followed by the
+/// bounds of the amounts of tokens, named after the direction.
+fn liquidity(arguments: &mut Reader, bounds: [&'static str; 2]) -> Option<Fields> {
+    let liquidity_amount = arguments.u128()?;
+    let token_a = arguments.u64()?;
+    let token_b = arguments.u64()?;
+
+    Some(smallvec![
+        ("liquidity_amount", liquidity_amount.to_string(), PropertyType::Decimal),
+        (bounds[0], token_a.to_string(), PropertyType::U64),
+        (bounds[1], token_b.to_string(), PropertyType::U64),
+    ])
+}
+
+fn swap(arguments: &mut Reader) -> Option<Fields> {
+    let amount = arguments.u64()?;
+    let other_amount_threshold = arguments.u64()?;
+    let sqrt_price_limit = arguments.u128()?;
+    let amount_specified_is_input = arguments.bool()?;
+    let a_to_b = arguments.bool()?;
+
+    Some(smallvec![
+        ("amount", amount.to_string(), PropertyType::U64),
+        ("other_amount_threshold", other_amount_threshold.to_string(), PropertyType::U64),
+        ("sqrt_price_limit", sqrt_price_limit.to_string(), PropertyType::Decimal),
+        ("amount_specified_is_input", (amount_specified_is_input as u8).to_string(),
+         PropertyType::Bool),
+        ("a_to_b", (a_to_b as u8).to_string(), PropertyType::Bool),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use solana_sdk::hash::hash;
+
+    use super::*;
+    use crate::testing::{instruction, properties};
+
+    // Instruction data as sent to the program, discriminator included.
+    // Swapping 1 token A for 20 token B at least, down to the lowest price there is.
+    const SWAP_A_TO_B: &str = "f8c69e91e17587c800ca9a3b00000000002d310100000000503b010001000000\
+        00000000000000000101";
+    // A position from tick -22528 to 22528.
+    const OPEN_POSITION_22528: &str = "87802f4d0f98f031ff00a8ffff00580000";
+    // Adding 10^12 of liquidity, for 5 token A and 6 token B at most.
+    const INCREASE_LIQUIDITY_10_12: &str = "2e9cf3760dcdfbb20010a5d4e8000000000000000000000040\
+        4b4c0000000000808d5b0000000000";
+    // A pool of tick spacing 64, at a price of 1.
+    const INITIALIZE_POOL_AT_1: &str = "5fb40aac54aee828fe400000000000000000000100000000000000";
+    const CLOSE_POSITION_DATA: &str = "7b86510031446262";
+
+    fn decode(data: &str, accounts: &[Pubkey]) -> Option<InstructionSet> {
+        fragment_instruction(instruction(PROGRAM_ADDRESS, hex::decode(data).unwrap()), accounts)
+    }
+
+    #[test]
+    fn discriminators_are_the_anchor_ones() {
+        for (discriminator, name) in &[
+            (INITIALIZE_POOL, "initialize_pool"),
+            (OPEN_POSITION, "open_position"),
+            (INCREASE_LIQUIDITY, "increase_liquidity"),
+            (DECREASE_LIQUIDITY, "decrease_liquidity"),
+            (CLOSE_POSITION, "close_position"),
+            (COLLECT_FEES, "collect_fees"),
+            (SWAP, "swap"),
+        ] {
+            let preimage = format!("global:{}", name);
+            let expected: [u8; 8] = hash(preimage.as_bytes()).to_bytes()[..8].try_into().unwrap();
+            assert_eq!(*discriminator, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn swaps_carry_their_price_limit_and_direction() {
+        let accounts: Vec<_> = (0..11).map(|_| Pubkey::new_unique()).collect();
+
+        let swap = decode(SWAP_A_TO_B, &accounts).unwrap();
+        assert_eq!(swap.function.function_name.as_str(), "swap");
+        assert_eq!(properties(&swap), vec![
+            ("amount", "1000000000".to_string()),
+            ("other_amount_threshold", "20000000".to_string()),
+            ("sqrt_price_limit", "4295048016".to_string()),
+            ("amount_specified_is_input", "1".to_string()),
+            ("a_to_b", "1".to_string()),
+            ("whirlpool", accounts[2].to_string()),
+        ]);
+    }
+
+    #[test]
+    fn positions_carry_their_range_and_liquidity() {
+        let accounts: Vec<_> = (0..10).map(|_| Pubkey::new_unique()).collect();
+
+        let open = decode(OPEN_POSITION_22528, &accounts).unwrap();
+        assert_eq!(properties(&open), vec![
+            ("tick_lower_index", "-22528".to_string()),
+            ("tick_upper_index", "22528".to_string()),
+            ("whirlpool", accounts[5].to_string()),
+            ("position", accounts[2].to_string()),
+            ("position_mint", accounts[3].to_string()),
+        ]);
+
+        let increase = decode(INCREASE_LIQUIDITY_10_12, &[]).unwrap();
+        assert_eq!(increase.function.function_name.as_str(), "increase-liquidity");
+        assert_eq!(properties(&increase), vec![
+            ("liquidity_amount", "1000000000000".to_string()),
+            ("token_max_a", "5000000".to_string()),
+            ("token_max_b", "6000000".to_string()),
+        ]);
+
+        let close = decode(CLOSE_POSITION_DATA, &accounts).unwrap();
+        assert_eq!(properties(&close), vec![
+            ("position", accounts[2].to_string()),
+            ("position_mint", accounts[3].to_string()),
+        ]);
+    }
+
+    #[test]
+    fn pools_carry_their_initial_price() {
+        let pool = decode(INITIALIZE_POOL_AT_1, &[]).unwrap();
+        assert_eq!(properties(&pool), vec![
+            ("tick_spacing", "64".to_string()),
+            ("initial_sqrt_price", "18446744073709551616".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn truncated_and_unknown_instructions_are_skipped() {
+        assert!(decode(&SWAP_A_TO_B[..2 * 40], &[]).is_none());
+        // A direction that isn't a bool.
+        assert!(decode(&format!("{}02", &SWAP_A_TO_B[..SWAP_A_TO_B.len() - 2]), &[]).is_none());
+        assert!(decode("0001020304050607", &[]).is_none());
+        assert!(decode("f8c69e", &[]).is_none());
+    }
+}
@@ -0,0 +1,268 @@
+//! Projecting the properties decoded onto those a deployment cares about, the rest being storage
+//! noise: by program and function, an allowlist of the keys kept, and a cap on how many
+//! properties any instruction keeps.
+//!
+//! Projection is applied as instructions are decoded, before redaction (see
+//! [`Redaction`](crate::redaction::Redaction)): redaction sees the properties kept only, and the
+//! `<key>_truncated` properties it adds aren't projected out. The functions themselves are
+//! always kept, whatever is left of their properties.
+
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+use crate::interner::Symbol;
+use crate::schema::ProgramSchema;
+use crate::{InstructionProperty, InstructionSet};
+
+/// The keys of the properties kept of the instructions of a program, or of one of its functions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProjectionRule {
+    // The program id the rule applies to.
+    pub program: Symbol,
+    // The function the rule applies to, every function of the program when unset.
+    pub function: Option<Symbol>,
+    // The keys kept, along with the properties nested under them.
+    pub keys: Vec<Symbol>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ProjectionError {
+    #[error("no processor is registered for {0}")]
+    UnknownProgram(String),
+    #[error("the processor of {program} has no function `{function}`")]
+    UnknownFunction { program: String, function: String },
+    #[error("no property `{key}` is emitted by {scope}")]
+    UnknownKey { key: String, scope: String },
+}
+
+impl ProjectionRule {
+    /// Checks that what the rule lists is emitted according to `schema`, the schema of every
+    /// program by program id (see [`ProcessorRegistry::schema`]), for typos not to silently drop
+    /// every property. Programs whose processor describes no function can't be checked, any key
+    /// goes for them.
+    ///
+    /// [`ProcessorRegistry::schema`]: crate::ProcessorRegistry::schema
+    pub fn validate(
+        &self,
+        schema: &BTreeMap<String, ProgramSchema>,
+    ) -> Result<(), ProjectionError> {
+        let program = schema.get(self.program.as_str())
+            .ok_or_else(|| ProjectionError::UnknownProgram(self.program.to_string()))?;
+        if program.functions.is_empty() {
+            return Ok(());
+        }
+
+        let functions = match self.function {
+            Some(function) => vec![program.get(function.as_str()).ok_or_else(|| {
+                ProjectionError::UnknownFunction {
+                    program: program.program.clone(),
+                    function: function.to_string(),
+                }
+            })?],
+            None => program.functions.iter().collect(),
+        };
+        for key in &self.keys {
+            let declared = functions.iter()
+                .flat_map(|function| &function.keys)
+                .any(|declared| key.as_str() == declared.key
+                    || nested_under(&declared.parent_key, key.as_str()));
+            if !declared {
+                let scope = match self.function {
+                    Some(function) => format!("{} of {}", function, program.program),
+                    None => program.program.clone(),
+                };
+                return Err(ProjectionError::UnknownKey { key: key.to_string(), scope });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Which properties of the instructions decoded are kept. Keeps them all by default.
+///
+/// An instruction gets the keys of the most specific rule applying to it: a rule for its program
+/// and function, then for its program (the last one listed when several are as specific). What's
+/// left of its properties is then cut down to the first `max_properties`, in the order they were
+/// decoded in.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Projection {
+    pub rules: Vec<ProjectionRule>,
+    // The most properties any instruction keeps, no limit when unset.
+    pub max_properties: Option<usize>,
+}
+
+impl Projection {
+    fn keys(&self, program: Symbol, function: Symbol) -> Option<&[Symbol]> {
+        self.rules.iter()
+            .filter(|rule| rule.program == program
+                && rule.function.map_or(true, |rule_function| rule_function == function))
+            .max_by_key(|rule| rule.function.is_some())
+            .map(|rule| rule.keys.as_slice())
+    }
+
+    /// Projects the properties of an instruction set, returning how many were dropped.
+    pub fn apply(&self, instruction_set: &mut InstructionSet) -> usize {
+        let before = instruction_set.properties.len();
+        let function = &instruction_set.function;
+        if let Some(keys) = self.keys(function.program, function.function_name) {
+            instruction_set.properties.retain(|property| is_kept(keys, property));
+        }
+        if let Some(max) = self.max_properties {
+            instruction_set.properties.truncate(max);
+        }
+
+        before - instruction_set.properties.len()
+    }
+}
+
+fn is_kept(keys: &[Symbol], property: &InstructionProperty) -> bool {
+    keys.iter().any(|key| {
+        *key == property.key || nested_under(property.parent_key.as_str(), key.as_str())
+    })
+}
+
+/// Whether `parent_key` is `key`, or nested under it (e.g. `config_keys/0` under `config_keys`).
+fn nested_under(parent_key: &str, key: &str) -> bool {
+    parent_key.strip_prefix(key).map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::network::Network;
+    use crate::redaction::{RedactAction, Redaction, RedactionRule};
+    use crate::registry::ProcessorRegistry;
+    use crate::{Instruction, PropertyType};
+
+    const MEMO: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+
+    /// Decodes `memo` as a memo (`post`, or `pin` when it starts with `!`) carrying the memo, its
+    /// length and its words, nested under `words`.
+    fn decode(projection: Projection, redaction: Redaction, memo: &str) -> InstructionSet {
+        let mut registry = ProcessorRegistry::new();
+        registry.register_fn(MEMO, |ctx| {
+            let memo = String::from_utf8_lossy(ctx.data).into_owned();
+            let function_name = if memo.starts_with('!') { "pin" } else { "post" };
+            let mut instruction_set = ctx.instruction_set(function_name, vec![
+                ("memo", memo.clone()),
+                ("length", memo.len().to_string()),
+            ]);
+            for (index, word) in memo.split_whitespace().enumerate() {
+                let mut property = instruction_set.properties[0].clone();
+                property.key = "word".into();
+                property.value = word.to_string();
+                property.parent_key = Symbol::intern(&format!("words/{}", index));
+                instruction_set.properties.push(property);
+            }
+            Ok(vec![instruction_set])
+        });
+        registry.set_projection(projection);
+        registry.set_redaction(redaction);
+
+        registry.decode(Instruction {
+            tx_instruction_id: 0,
+            transaction_hash: Arc::from("signature"),
+            program: MEMO.into(),
+            data: memo.as_bytes().to_vec(),
+            parent_index: -1,
+            timestamp: 0,
+            network: Network::Mainnet,
+        }, None).unwrap()
+    }
+
+    fn keys(instruction_set: &InstructionSet) -> Vec<String> {
+        instruction_set.properties.iter()
+            .map(|property| match property.parent_key.as_str() {
+                "" => property.key.to_string(),
+                parent_key => format!("{}.{}", parent_key, property.key),
+            })
+            .collect()
+    }
+
+    fn rule(function: Option<&str>, keys: &[&str]) -> ProjectionRule {
+        ProjectionRule {
+            program: Symbol::intern(MEMO),
+            function: function.map(Symbol::intern),
+            keys: keys.iter().map(|key| Symbol::intern(key)).collect(),
+        }
+    }
+
+    #[test]
+    fn only_the_keys_listed_for_the_function_are_kept() {
+        let projection = Projection {
+            rules: vec![rule(None, &["length"]), rule(Some("pin"), &["memo", "words"])],
+            max_properties: None,
+        };
+        let post = decode(projection.clone(), Redaction::none(), "gm all");
+        assert_eq!(keys(&post), vec!["length"]);
+        assert_eq!(post.function.function_name.as_str(), "post");
+
+        let pin = decode(projection.clone(), Redaction::none(), "!gm all");
+        assert_eq!(keys(&pin), vec!["memo", "words/0.word", "words/1.word"]);
+        let mut unprojected = decode(Projection::default(), Redaction::none(), "!gm all");
+        assert_eq!(projection.apply(&mut unprojected), 1);
+    }
+
+    #[test]
+    fn instructions_keep_at_most_max_properties() {
+        let projection = Projection { rules: Vec::new(), max_properties: Some(3) };
+        let instruction_set = decode(projection, Redaction::none(), "a b c d");
+        assert_eq!(keys(&instruction_set), vec!["memo", "length", "words/0.word"]);
+    }
+
+    #[test]
+    fn projection_comes_before_redaction() {
+        let projection = Projection {
+            rules: vec![rule(None, &["memo", "length"])],
+            max_properties: Some(2),
+        };
+        let redaction = Redaction {
+            rules: vec![
+                RedactionRule {
+                    program: None,
+                    key: Some("memo".into()),
+                    action: RedactAction::Truncate(2),
+                },
+                // Projected out already.
+                RedactionRule {
+                    program: None,
+                    key: Some("word".into()),
+                    action: RedactAction::Hash,
+                },
+            ],
+            max_value_bytes: None,
+        };
+        let instruction_set = decode(projection, redaction, "hello world");
+
+        // The property redaction adds isn't counted in the projection.
+        assert_eq!(keys(&instruction_set), vec!["memo", "length", "memo_truncated"]);
+        assert_eq!(instruction_set.properties[0].value, "he");
+        assert_eq!(instruction_set.properties[1].value, "11");
+    }
+
+    #[test]
+    fn rules_are_checked_against_the_schema() {
+        let mut schema = BTreeMap::new();
+        schema.insert(MEMO.to_string(), ProgramSchema::new("memo")
+            .function("post", &[
+                ("memo", "", PropertyType::Text, "The memo."),
+                ("word", "words/{index}", PropertyType::Text, "A word of the memo."),
+            ])
+            .function("pin", &[("memo", "", PropertyType::Text, "The memo.")]));
+
+        assert_eq!(rule(None, &["memo", "words"]).validate(&schema), Ok(()));
+        assert_eq!(rule(Some("post"), &["mmeo"]).validate(&schema).unwrap_err().to_string(),
+                   "no property `mmeo` is emitted by post of memo");
+        assert_eq!(rule(Some("pin"), &["words"]).validate(&schema).unwrap_err().to_string(),
+                   "no property `words` is emitted by pin of memo");
+        assert_eq!(rule(Some("pinned"), &["memo"]).validate(&schema).unwrap_err().to_string(),
+                   "the processor of memo has no function `pinned`");
+        let other = ProjectionRule { program: "Other".into(), ..rule(None, &["memo"]) };
+        assert_eq!(other.validate(&schema),
+                   Err(ProjectionError::UnknownProgram("Other".to_string())));
+    }
+}
@@ -0,0 +1,291 @@
+//! Redacting the values of properties as they're decoded, so that every sink (and anything
+//! serializing the records) gets the same redacted values: values can be truncated, replaced
+//! with their hash or dropped, by program and by key, and no value is longer than 16 KiB by
+//! default.
+//!
+//! Values are truncated on a character boundary, a `<key>_truncated` property (`1`) recording
+//! that they were; hashes are the SHA-256 of the value, hex encoded.
+
+use smallvec::SmallVec;
+use solana_sdk::hash::hash;
+
+use crate::interner::Symbol;
+use crate::{InstructionProperty, InstructionSet, PropertyType};
+
+/// The most bytes a property value holds by default, longer values being truncated.
+pub const DEFAULT_MAX_VALUE_BYTES: usize = 16 * 1024;
+
+/// What is done to the value of a property a rule applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RedactAction {
+    // Keeps the first bytes of the value, at most this many.
+    Truncate(usize),
+    // Replaces the value with its hash.
+    Hash,
+    // Drops the property.
+    Drop,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RedactionRule {
+    // The program id whose properties the rule applies to, those of every program when unset.
+    pub program: Option<Symbol>,
+    // The key of the properties the rule applies to, every key when unset.
+    pub key: Option<Symbol>,
+    pub action: RedactAction,
+}
+
+/// How the values of properties are redacted.
+///
+/// A property gets the action of the most specific rule applying to it: a rule for its program
+/// and key, then for its key, then for its program (the last one listed when several are as
+/// specific). Values still longer than `max_value_bytes` afterwards are truncated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redaction {
+    pub rules: Vec<RedactionRule>,
+    // The most bytes any value holds, no limit when unset.
+    pub max_value_bytes: Option<usize>,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Redaction {
+            rules: Vec::new(),
+            max_value_bytes: Some(DEFAULT_MAX_VALUE_BYTES),
+        }
+    }
+}
+
+impl Redaction {
+    /// Leaves every value as it is.
+    pub fn none() -> Self {
+        Redaction { rules: Vec::new(), max_value_bytes: None }
+    }
+
+    fn action(&self, program: Symbol, key: Symbol) -> Option<RedactAction> {
+        self.rules.iter()
+            .filter(|rule| rule.program.map_or(true, |rule_program| rule_program == program)
+                && rule.key.map_or(true, |rule_key| rule_key == key))
+            .max_by_key(|rule| (rule.key.is_some(), rule.program.is_some()))
+            .map(|rule| rule.action)
+    }
+
+    /// Redacts the properties of an instruction set.
+    pub fn apply(&self, instruction_set: &mut InstructionSet) {
+        if self.rules.is_empty() && self.max_value_bytes.map_or(true, |max| {
+            instruction_set.properties.iter().all(|property| property.value.len() <= max)
+        }) {
+            return;
+        }
+
+        let program = instruction_set.function.program;
+        let mut truncated: SmallVec<[InstructionProperty; 1]> = SmallVec::new();
+        instruction_set.properties.retain(|property| {
+            let mut max = self.max_value_bytes;
+            match self.action(program, property.key) {
+                Some(RedactAction::Drop) => return false,
+                Some(RedactAction::Hash) => {
+                    property.value = hex::encode(hash(property.value.as_bytes()).to_bytes());
+                    property.value_type = PropertyType::Bytes;
+                    return true;
+                }
+                Some(RedactAction::Truncate(bytes)) => {
+                    max = Some(max.map_or(bytes, |max| max.min(bytes)));
+                }
+                None => {}
+            }
+
+            if let Some(max) = max {
+                if truncate(&mut property.value, max) {
+                    truncated.push(InstructionProperty {
+                        tx_instruction_id: property.tx_instruction_id,
+                        transaction_hash: property.transaction_hash.clone(),
+                        parent_index: property.parent_index,
+                        key: Symbol::intern(&format!("{}_truncated", property.key.as_str())),
+                        value: "1".to_string(),
+                        value_type: PropertyType::Bool,
+                        parent_key: property.parent_key,
+                        timestamp: property.timestamp,
+                    });
+                }
+            }
+            true
+        });
+        instruction_set.properties.extend(truncated);
+    }
+
+    /// Whether the raw data of the instructions of `program` may be kept: not when a rule hashes
+    /// or drops every property of the program, the data holding their plaintext.
+    pub fn keeps_raw_data(&self, program: Symbol) -> bool {
+        !self.rules.iter().any(|rule| {
+            rule.key.is_none()
+                && rule.program.map_or(true, |rule_program| rule_program == program)
+                && matches!(rule.action, RedactAction::Hash | RedactAction::Drop)
+        })
+    }
+}
+
+/// Cuts `value` down to at most `max` bytes, on a character boundary. Returns whether it was
+/// longer.
+fn truncate(value: &mut String, max: usize) -> bool {
+    if value.len() <= max {
+        return false;
+    }
+
+    let mut end = max;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value.truncate(end);
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::network::Network;
+    use crate::registry::ProcessorRegistry;
+    use crate::Instruction;
+
+    const MEMO: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+    const OTHER: &str = "Other11111111111111111111111111111111111111";
+
+    /// Decodes `memo` as sent to `program`, the memo and its length as properties.
+    fn decode(redaction: Redaction, program: &str, memo: &str) -> InstructionSet {
+        let mut registry = ProcessorRegistry::new();
+        for program in &[MEMO, OTHER] {
+            registry.register_fn(program, |ctx| {
+                let memo = String::from_utf8_lossy(ctx.data).into_owned();
+                let length = memo.len().to_string();
+                Ok(vec![ctx.instruction_set("memo", vec![("memo", memo), ("length", length)])])
+            });
+        }
+        registry.set_redaction(redaction);
+
+        registry.decode(Instruction {
+            tx_instruction_id: 0,
+            transaction_hash: Arc::from("signature"),
+            program: program.into(),
+            data: memo.as_bytes().to_vec(),
+            parent_index: -1,
+            timestamp: 0,
+            network: Network::Mainnet,
+        }, None).unwrap()
+    }
+
+    fn value<'a>(instruction_set: &'a InstructionSet, key: &str) -> Option<&'a str> {
+        instruction_set.properties.iter()
+            .find(|property| property.key.as_str() == key)
+            .map(|property| property.value.as_str())
+    }
+
+    fn rule(program: Option<&str>, key: Option<&str>, action: RedactAction) -> RedactionRule {
+        RedactionRule {
+            program: program.map(Symbol::intern),
+            key: key.map(Symbol::intern),
+            action,
+        }
+    }
+
+    #[test]
+    fn values_are_capped_by_default() {
+        let memo = "gm ".repeat(10_000);
+        let instruction_set = decode(Redaction::default(), MEMO, &memo);
+        assert_eq!(value(&instruction_set, "memo"), Some(&memo[..DEFAULT_MAX_VALUE_BYTES]));
+        assert_eq!(value(&instruction_set, "memo_truncated"), Some("1"));
+        assert_eq!(value(&instruction_set, "length"), Some("30000"));
+        assert_eq!(value(&instruction_set, "length_truncated"), None);
+
+        let instruction_set = decode(Redaction::default(), MEMO, "gm");
+        assert_eq!(value(&instruction_set, "memo"), Some("gm"));
+        assert_eq!(value(&instruction_set, "memo_truncated"), None);
+
+        let instruction_set = decode(Redaction::none(), MEMO, &memo);
+        assert_eq!(value(&instruction_set, "memo"), Some(memo.as_str()));
+    }
+
+    #[test]
+    fn truncation_keeps_whole_characters() {
+        let redaction = Redaction {
+            rules: vec![rule(None, Some("memo"), RedactAction::Truncate(10))],
+            ..Redaction::default()
+        };
+        // Each rocket takes 4 bytes, the third one straddling the limit.
+        let instruction_set = decode(redaction, MEMO, &"🚀".repeat(100));
+        assert_eq!(value(&instruction_set, "memo"), Some("🚀🚀"));
+        assert_eq!(value(&instruction_set, "memo_truncated"), Some("1"));
+        let truncated = instruction_set.properties.iter()
+            .find(|property| property.key.as_str() == "memo_truncated")
+            .unwrap();
+        assert_eq!(truncated.value_type, PropertyType::Bool);
+    }
+
+    #[test]
+    fn hashed_values_leave_no_plaintext() {
+        let redaction = Redaction {
+            rules: vec![rule(Some(MEMO), Some("memo"), RedactAction::Hash)],
+            ..Redaction::default()
+        };
+        let memo = "my seed phrase is ".repeat(2_000);
+        let instruction_set = decode(redaction.clone(), MEMO, &memo);
+        assert_eq!(value(&instruction_set, "memo"),
+                   Some(hex::encode(hash(memo.as_bytes()).to_bytes()).as_str()));
+        assert_eq!(value(&instruction_set, "memo_truncated"), None);
+        assert!(!serde_json::to_string(&instruction_set).unwrap().contains("seed phrase"));
+
+        // The rule is for the memo program only.
+        let instruction_set = decode(redaction, OTHER, "seed phrase");
+        assert_eq!(value(&instruction_set, "memo"), Some("seed phrase"));
+    }
+
+    #[test]
+    fn dropped_properties_are_gone() {
+        let redaction = Redaction {
+            rules: vec![rule(None, Some("memo"), RedactAction::Drop)],
+            ..Redaction::default()
+        };
+        let instruction_set = decode(redaction, MEMO, &"spam ".repeat(5_000));
+        assert_eq!(value(&instruction_set, "memo"), None);
+        assert_eq!(value(&instruction_set, "memo_truncated"), None);
+        assert_eq!(value(&instruction_set, "length"), Some("25000"));
+    }
+
+    #[test]
+    fn the_most_specific_rule_applies() {
+        let redaction = Redaction {
+            rules: vec![
+                rule(Some(MEMO), Some("memo"), RedactAction::Truncate(2)),
+                rule(None, Some("memo"), RedactAction::Hash),
+                rule(Some(MEMO), None, RedactAction::Drop),
+            ],
+            ..Redaction::default()
+        };
+        let instruction_set = decode(redaction.clone(), MEMO, "hello");
+        assert_eq!(value(&instruction_set, "memo"), Some("he"));
+        assert_eq!(value(&instruction_set, "length"), None);
+
+        let instruction_set = decode(redaction, OTHER, "hello");
+        assert_eq!(value(&instruction_set, "memo"),
+                   Some(hex::encode(hash(b"hello").to_bytes()).as_str()));
+        assert_eq!(value(&instruction_set, "length"), Some("5"));
+    }
+
+    #[test]
+    fn raw_data_is_withheld_when_every_property_is_redacted() {
+        let memo = Symbol::intern(MEMO);
+        let other = Symbol::intern(OTHER);
+        let redaction = Redaction {
+            rules: vec![
+                rule(None, Some("memo"), RedactAction::Drop),
+                rule(Some(MEMO), None, RedactAction::Hash),
+            ],
+            ..Redaction::default()
+        };
+        assert!(!redaction.keeps_raw_data(memo));
+        assert!(redaction.keeps_raw_data(other));
+        assert!(Redaction::default().keeps_raw_data(memo));
+    }
+}
@@ -0,0 +1,2106 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+#[cfg(feature = "runtime")]
+use std::time::Instant;
+
+use solana_sdk::instruction::CompiledInstruction;
+use sha3::{Digest, Sha3_256};
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+use tracing::{debug, info, trace_span, warn};
+
+use crate::data::{decode_instruction_data, DecodeError, EncodedContext};
+use crate::fast::{self, FastDecode};
+use crate::interner::Symbol;
+#[cfg(feature = "runtime")]
+use crate::mints::MintCache;
+#[cfg(feature = "runtime")]
+use crate::program_versions::ProgramVersions;
+use crate::naming;
+use crate::programs;
+#[cfg(feature = "layout-decoder")]
+use crate::programs::layout_registry::LayoutRegistry;
+#[cfg(feature = "phoenix")]
+use crate::programs::phoenix::PhoenixMarket;
+use crate::projection::Projection;
+use crate::redaction::Redaction;
+use crate::schema::ProgramSchema;
+#[cfg(feature = "runtime")]
+use crate::transaction::RawTransaction;
+#[cfg(feature = "runtime")]
+use crate::watchdog::{Watchdog, WatchdogConfig};
+use crate::{
+    Instruction, InstructionFunction, InstructionProperty, InstructionSet, Network, PropertyType,
+};
+
+/// The processors shipped with this crate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuiltinProcessor {
+    Aldrin,
+    AssociatedTokenAccount,
+    AuctionHouse,
+    BpfLoader,
+    BpfLoaderUpgradeable,
+    Bubblegum,
+    CandyMachine,
+    ComputeBudget,
+    Config,
+    Drift,
+    Governance,
+    Lifinity,
+    Loader,
+    MagicEden,
+    Memo,
+    NameService,
+    OpenbookV2,
+    Phoenix,
+    RaydiumAmm,
+    Secp256k1,
+    SerumMarket,
+    SolendTokenLending,
+    Stake,
+    System,
+    Tensor,
+    Token,
+    Token2022,
+    TokenLending,
+    TokenMetadata,
+    TokenSwap,
+    Vote,
+    Whirlpool,
+}
+
+impl BuiltinProcessor {
+    const NAMES: [(&'static str, BuiltinProcessor); 32] = [
+        ("aldrin", BuiltinProcessor::Aldrin),
+        ("associated-token-account", BuiltinProcessor::AssociatedTokenAccount),
+        ("auction-house", BuiltinProcessor::AuctionHouse),
+        ("bpf-loader", BuiltinProcessor::BpfLoader),
+        ("bpf-loader-upgradeable", BuiltinProcessor::BpfLoaderUpgradeable),
+        ("bubblegum", BuiltinProcessor::Bubblegum),
+        ("candy-machine", BuiltinProcessor::CandyMachine),
+        ("compute-budget", BuiltinProcessor::ComputeBudget),
+        ("config", BuiltinProcessor::Config),
+        ("drift", BuiltinProcessor::Drift),
+        ("governance", BuiltinProcessor::Governance),
+        ("lifinity", BuiltinProcessor::Lifinity),
+        ("loader", BuiltinProcessor::Loader),
+        ("magic-eden", BuiltinProcessor::MagicEden),
+        ("memo", BuiltinProcessor::Memo),
+        ("name-service", BuiltinProcessor::NameService),
+        ("openbook-v2", BuiltinProcessor::OpenbookV2),
+        ("phoenix", BuiltinProcessor::Phoenix),
+        ("raydium-amm", BuiltinProcessor::RaydiumAmm),
+        ("secp256k1", BuiltinProcessor::Secp256k1),
+        ("serum-market", BuiltinProcessor::SerumMarket),
+        ("solend-token-lending", BuiltinProcessor::SolendTokenLending),
+        ("stake", BuiltinProcessor::Stake),
+        ("system", BuiltinProcessor::System),
+        ("tensor", BuiltinProcessor::Tensor),
+        ("token", BuiltinProcessor::Token),
+        ("token-2022", BuiltinProcessor::Token2022),
+        ("token-lending", BuiltinProcessor::TokenLending),
+        ("token-metadata", BuiltinProcessor::TokenMetadata),
+        ("token-swap", BuiltinProcessor::TokenSwap),
+        ("vote", BuiltinProcessor::Vote),
+        ("whirlpool", BuiltinProcessor::Whirlpool),
+    ];
+
+    /// The name the processor goes by in configuration files.
+    pub fn name(self) -> &'static str {
+        BuiltinProcessor::NAMES.iter()
+            .find(|(_, processor)| *processor == self)
+            .map(|(name, _)| *name)
+            .unwrap()
+    }
+
+    /// The processor going by `name`.
+    pub fn from_name(name: &str) -> Option<BuiltinProcessor> {
+        BuiltinProcessor::NAMES.iter()
+            .find(|(candidate, _)| *candidate == name)
+            .map(|(_, processor)| *processor)
+    }
+
+    /// Whether the processor was compiled in. Every processor sits behind the cargo feature of
+    /// the same name as the processor (see [`BuiltinProcessor::name`]).
+    pub fn is_enabled(self) -> bool {
+        match self {
+            BuiltinProcessor::Aldrin => cfg!(feature = "aldrin"),
+            BuiltinProcessor::AssociatedTokenAccount => cfg!(feature = "associated-token-account"),
+            BuiltinProcessor::AuctionHouse => cfg!(feature = "auction-house"),
+            BuiltinProcessor::BpfLoader => cfg!(feature = "bpf-loader"),
+            BuiltinProcessor::BpfLoaderUpgradeable => cfg!(feature = "bpf-loader-upgradeable"),
+            BuiltinProcessor::Bubblegum => cfg!(feature = "bubblegum"),
+            BuiltinProcessor::CandyMachine => cfg!(feature = "candy-machine"),
+            BuiltinProcessor::ComputeBudget => cfg!(feature = "compute-budget"),
+            BuiltinProcessor::Config => cfg!(feature = "config"),
+            BuiltinProcessor::Drift => cfg!(feature = "drift"),
+            BuiltinProcessor::Governance => cfg!(feature = "governance"),
+            BuiltinProcessor::Lifinity => cfg!(feature = "lifinity"),
+            BuiltinProcessor::Loader => cfg!(feature = "loader"),
+            BuiltinProcessor::MagicEden => cfg!(feature = "magic-eden"),
+            BuiltinProcessor::Memo => cfg!(feature = "memo"),
+            BuiltinProcessor::NameService => cfg!(feature = "name-service"),
+            BuiltinProcessor::OpenbookV2 => cfg!(feature = "openbook-v2"),
+            BuiltinProcessor::Phoenix => cfg!(feature = "phoenix"),
+            BuiltinProcessor::RaydiumAmm => cfg!(feature = "raydium-amm"),
+            BuiltinProcessor::Secp256k1 => cfg!(feature = "secp256k1"),
+            BuiltinProcessor::SerumMarket => cfg!(feature = "serum-market"),
+            BuiltinProcessor::SolendTokenLending => cfg!(feature = "solend-token-lending"),
+            BuiltinProcessor::Stake => cfg!(feature = "stake"),
+            BuiltinProcessor::System => cfg!(feature = "system"),
+            BuiltinProcessor::Tensor => cfg!(feature = "tensor"),
+            BuiltinProcessor::Token => cfg!(feature = "token"),
+            BuiltinProcessor::Token2022 => cfg!(feature = "token-2022"),
+            BuiltinProcessor::TokenLending => cfg!(feature = "token-lending"),
+            BuiltinProcessor::TokenMetadata => cfg!(feature = "token-metadata"),
+            BuiltinProcessor::TokenSwap => cfg!(feature = "token-swap"),
+            BuiltinProcessor::Vote => cfg!(feature = "vote"),
+            BuiltinProcessor::Whirlpool => cfg!(feature = "whirlpool"),
+        }
+    }
+
+    /// The version of what the processor decodes instructions to, recorded with every function
+    /// it emits. Bumped whenever the processor starts emitting something else for instructions
+    /// it decoded before (other properties, formats or function names), so that the rows decoded
+    /// by the previous version get re-indexed (see `reindex`).
+    pub fn decoder_version(self) -> u32 {
+        match self {
+            BuiltinProcessor::Aldrin => 1,
+            BuiltinProcessor::AssociatedTokenAccount => 3,
+            BuiltinProcessor::AuctionHouse => 1,
+            BuiltinProcessor::BpfLoader => 1,
+            BuiltinProcessor::BpfLoaderUpgradeable => 1,
+            BuiltinProcessor::Bubblegum => 1,
+            BuiltinProcessor::CandyMachine => 1,
+            BuiltinProcessor::ComputeBudget => 1,
+            BuiltinProcessor::Config => 2,
+            BuiltinProcessor::Drift => 1,
+            BuiltinProcessor::Governance => 1,
+            BuiltinProcessor::Lifinity => 1,
+            BuiltinProcessor::Loader => 1,
+            BuiltinProcessor::MagicEden => 1,
+            BuiltinProcessor::Memo => 1,
+            BuiltinProcessor::NameService => 1,
+            BuiltinProcessor::OpenbookV2 => 1,
+            BuiltinProcessor::Phoenix => 1,
+            BuiltinProcessor::RaydiumAmm => 1,
+            BuiltinProcessor::Secp256k1 => 2,
+            BuiltinProcessor::SerumMarket => 1,
+            BuiltinProcessor::SolendTokenLending => 1,
+            BuiltinProcessor::Stake => 1,
+            BuiltinProcessor::System => 1,
+            BuiltinProcessor::Tensor => 1,
+            BuiltinProcessor::Token => 1,
+            BuiltinProcessor::Token2022 => 1,
+            BuiltinProcessor::TokenLending => 1,
+            BuiltinProcessor::TokenMetadata => 1,
+            BuiltinProcessor::TokenSwap => 1,
+            BuiltinProcessor::Vote => 1,
+            BuiltinProcessor::Whirlpool => 1,
+        }
+    }
+
+    /// The short namespace the keys of the properties the processor emits are prefixed with when
+    /// keys are namespaced (see [`ProcessorRegistry::set_key_namespacing`]).
+    pub fn namespace(self) -> &'static str {
+        match self {
+            BuiltinProcessor::Aldrin => "aldrin",
+            BuiltinProcessor::AssociatedTokenAccount => "ata",
+            BuiltinProcessor::AuctionHouse => "auction_house",
+            BuiltinProcessor::BpfLoader => "bpf_loader",
+            BuiltinProcessor::BpfLoaderUpgradeable => "bpf_upgradeable",
+            BuiltinProcessor::Bubblegum => "bubblegum",
+            BuiltinProcessor::CandyMachine => "candy_machine",
+            BuiltinProcessor::ComputeBudget => "compute_budget",
+            BuiltinProcessor::Config => "config",
+            BuiltinProcessor::Drift => "drift",
+            BuiltinProcessor::Governance => "governance",
+            BuiltinProcessor::Lifinity => "lifinity",
+            BuiltinProcessor::Loader => "loader",
+            BuiltinProcessor::MagicEden => "magic_eden",
+            BuiltinProcessor::Memo => "memo",
+            BuiltinProcessor::NameService => "name_service",
+            BuiltinProcessor::OpenbookV2 => "openbook",
+            BuiltinProcessor::Phoenix => "phoenix",
+            BuiltinProcessor::RaydiumAmm => "raydium",
+            BuiltinProcessor::Secp256k1 => "secp256k1",
+            BuiltinProcessor::SerumMarket => "serum",
+            BuiltinProcessor::SolendTokenLending => "solend",
+            BuiltinProcessor::Stake => "stake",
+            BuiltinProcessor::System => "system",
+            BuiltinProcessor::Tensor => "tensor",
+            BuiltinProcessor::Token => "token",
+            BuiltinProcessor::Token2022 => "token_2022",
+            BuiltinProcessor::TokenLending => "lending",
+            BuiltinProcessor::TokenMetadata => "metadata",
+            BuiltinProcessor::TokenSwap => "swap",
+            BuiltinProcessor::Vote => "vote",
+            BuiltinProcessor::Whirlpool => "whirlpool",
+        }
+    }
+
+    /// Every built-in processor compiled in along with the program ids it handles.
+    pub fn all() -> Vec<(&'static str, BuiltinProcessor)> {
+        #[allow(unused_mut)]
+        let mut all = Vec::new();
+        #[cfg(feature = "associated-token-account")]
+        all.push((programs::native_associated_token_account::PROGRAM_ADDRESS,
+                  BuiltinProcessor::AssociatedTokenAccount));
+        #[cfg(feature = "compute-budget")]
+        all.push((programs::native_compute_budget::PROGRAM_ADDRESS,
+                  BuiltinProcessor::ComputeBudget));
+        #[cfg(feature = "config")]
+        all.push((programs::native_config::PROGRAM_ADDRESS, BuiltinProcessor::Config));
+        #[cfg(feature = "loader")]
+        all.push((programs::native_loader::PROGRAM_ADDRESS, BuiltinProcessor::Loader));
+        #[cfg(feature = "bpf-loader")]
+        all.push((programs::bpf_loader::PROGRAM_ADDRESS, BuiltinProcessor::BpfLoader));
+        #[cfg(feature = "bpf-loader")]
+        all.push((programs::bpf_loader::PROGRAM_ADDRESS_2, BuiltinProcessor::BpfLoader));
+        #[cfg(feature = "bpf-loader-upgradeable")]
+        all.push((programs::bpf_loader_upgradeable::PROGRAM_ADDRESS,
+                  BuiltinProcessor::BpfLoaderUpgradeable));
+        #[cfg(feature = "memo")]
+        all.push((programs::native_memo::PROGRAM_ADDRESS_V1, BuiltinProcessor::Memo));
+        #[cfg(feature = "memo")]
+        all.push((programs::native_memo::PROGRAM_ADDRESS_V2, BuiltinProcessor::Memo));
+        #[cfg(feature = "secp256k1")]
+        all.push((programs::native_secp256k1::PROGRAM_ADDRESS, BuiltinProcessor::Secp256k1));
+        #[cfg(feature = "stake")]
+        all.push((programs::native_stake::PROGRAM_ADDRESS, BuiltinProcessor::Stake));
+        #[cfg(feature = "system")]
+        all.push((programs::native_system::PROGRAM_ADDRESS, BuiltinProcessor::System));
+        #[cfg(feature = "token")]
+        all.push((programs::native_token::PROGRAM_ADDRESS, BuiltinProcessor::Token));
+        #[cfg(feature = "token-2022")]
+        all.push((programs::native_token_2022::PROGRAM_ADDRESS, BuiltinProcessor::Token2022));
+        #[cfg(feature = "token-lending")]
+        all.push((programs::native_token_lending::PROGRAM_ADDRESS, BuiltinProcessor::TokenLending));
+        #[cfg(feature = "token-swap")]
+        all.push((programs::native_token_swap::PROGRAM_ADDRESS, BuiltinProcessor::TokenSwap));
+        #[cfg(feature = "serum-market")]
+        all.push((programs::serum_market::PROGRAM_ADDRESS_V1, BuiltinProcessor::SerumMarket));
+        #[cfg(feature = "serum-market")]
+        all.push((programs::serum_market::PROGRAM_ADDRESS_V2, BuiltinProcessor::SerumMarket));
+        #[cfg(feature = "serum-market")]
+        all.push((programs::serum_market::PROGRAM_ADDRESS_V3, BuiltinProcessor::SerumMarket));
+        #[cfg(feature = "serum-market")]
+        all.push((programs::serum_market::OPENBOOK_PROGRAM_ADDRESS,
+                  BuiltinProcessor::SerumMarket));
+        #[cfg(feature = "vote")]
+        all.push((programs::native_vote::PROGRAM_ADDRESS, BuiltinProcessor::Vote));
+        #[cfg(feature = "drift")]
+        all.push((programs::drift::PROGRAM_ADDRESS, BuiltinProcessor::Drift));
+        #[cfg(feature = "phoenix")]
+        all.push((programs::phoenix::PROGRAM_ADDRESS, BuiltinProcessor::Phoenix));
+        #[cfg(feature = "whirlpool")]
+        all.push((programs::whirlpool::PROGRAM_ADDRESS, BuiltinProcessor::Whirlpool));
+        #[cfg(feature = "raydium-amm")]
+        all.push((programs::raydium_amm::PROGRAM_ADDRESS, BuiltinProcessor::RaydiumAmm));
+        #[cfg(feature = "lifinity")]
+        all.push((programs::lifinity::PROGRAM_ADDRESS_V1, BuiltinProcessor::Lifinity));
+        #[cfg(feature = "lifinity")]
+        all.push((programs::lifinity::PROGRAM_ADDRESS_V2, BuiltinProcessor::Lifinity));
+        #[cfg(feature = "aldrin")]
+        all.push((programs::aldrin::PROGRAM_ADDRESS_V1, BuiltinProcessor::Aldrin));
+        #[cfg(feature = "aldrin")]
+        all.push((programs::aldrin::PROGRAM_ADDRESS_V2, BuiltinProcessor::Aldrin));
+        #[cfg(feature = "openbook-v2")]
+        all.push((programs::openbook_v2::PROGRAM_ADDRESS, BuiltinProcessor::OpenbookV2));
+        #[cfg(feature = "tensor")]
+        all.push((programs::tensor::TSWAP_PROGRAM_ADDRESS, BuiltinProcessor::Tensor));
+        #[cfg(feature = "tensor")]
+        all.push((programs::tensor::TCOMP_PROGRAM_ADDRESS, BuiltinProcessor::Tensor));
+        #[cfg(feature = "magic-eden")]
+        all.push((programs::magic_eden::PROGRAM_ADDRESS, BuiltinProcessor::MagicEden));
+        #[cfg(feature = "auction-house")]
+        all.push((programs::auction_house::PROGRAM_ADDRESS, BuiltinProcessor::AuctionHouse));
+        #[cfg(feature = "solend-token-lending")]
+        all.push((programs::solend_token_lending::PROGRAM_ADDRESS,
+                  BuiltinProcessor::SolendTokenLending));
+        #[cfg(feature = "token-metadata")]
+        all.push((programs::token_metadata::PROGRAM_ADDRESS, BuiltinProcessor::TokenMetadata));
+        #[cfg(feature = "bubblegum")]
+        all.push((programs::bubblegum::PROGRAM_ADDRESS, BuiltinProcessor::Bubblegum));
+        #[cfg(feature = "candy-machine")]
+        all.push((programs::candy_machine::CANDY_MACHINE_PROGRAM_ADDRESS,
+                  BuiltinProcessor::CandyMachine));
+        #[cfg(feature = "candy-machine")]
+        all.push((programs::candy_machine::CANDY_GUARD_PROGRAM_ADDRESS,
+                  BuiltinProcessor::CandyMachine));
+        #[cfg(feature = "governance")]
+        all.push((programs::governance::PROGRAM_ADDRESS, BuiltinProcessor::Governance));
+        #[cfg(feature = "name-service")]
+        all.push((programs::name_service::PROGRAM_ADDRESS, BuiltinProcessor::NameService));
+
+        all
+    }
+
+    /// Every built-in processor compiled in along with the program ids it handles on `network`:
+    /// those of mainnet (see [`BuiltinProcessor::all`]), but for the processors whose programs
+    /// are deployed elsewhere there (see [`BuiltinProcessor::deployments`]). Other clusters than
+    /// the public ones get the ids of mainnet, local validators usually cloning its programs.
+    pub fn all_on(network: Network) -> Vec<(&'static str, BuiltinProcessor)> {
+        let mut all: Vec<_> = BuiltinProcessor::all().into_iter()
+            .filter(|(_, processor)| processor.deployments(network).is_none())
+            .collect();
+        for (_, processor) in BuiltinProcessor::NAMES.iter() {
+            if let Some(program_ids) = processor.deployments(network) {
+                all.extend(program_ids.iter().map(|program_id| (*program_id, *processor)));
+            }
+        }
+
+        all
+    }
+
+    /// The ids the programs of the processor are deployed under on `network`, when they aren't
+    /// those of mainnet. Most programs, the native ones first, have the same ids everywhere.
+    pub fn deployments(self, network: Network) -> Option<&'static [&'static str]> {
+        match (self, network) {
+            #[cfg(feature = "serum-market")]
+            (BuiltinProcessor::SerumMarket, Network::Devnet) => {
+                Some(&[
+                    programs::serum_market::DEVNET_PROGRAM_ADDRESS_V3,
+                    programs::serum_market::DEVNET_OPENBOOK_PROGRAM_ADDRESS,
+                ])
+            }
+            #[cfg(feature = "solend-token-lending")]
+            (BuiltinProcessor::SolendTokenLending, Network::Devnet) => {
+                Some(&[programs::solend_token_lending::DEVNET_PROGRAM_ADDRESS])
+            }
+            #[cfg(feature = "token-lending")]
+            (BuiltinProcessor::TokenLending, Network::Devnet) => {
+                Some(&[programs::native_token_lending::DEVNET_PROGRAM_ADDRESS])
+            }
+            _ => None,
+        }
+    }
+
+    /// What the processor decodes instructions to, or nothing when it isn't compiled in.
+    pub fn describe(self) -> ProgramSchema {
+        match self {
+            #[cfg(feature = "aldrin")]
+            BuiltinProcessor::Aldrin => programs::aldrin::describe(),
+            #[cfg(feature = "associated-token-account")]
+            BuiltinProcessor::AssociatedTokenAccount => {
+                programs::native_associated_token_account::describe()
+            }
+            #[cfg(feature = "auction-house")]
+            BuiltinProcessor::AuctionHouse => programs::auction_house::describe(),
+            #[cfg(feature = "bpf-loader")]
+            BuiltinProcessor::BpfLoader => programs::bpf_loader::describe(),
+            #[cfg(feature = "bpf-loader-upgradeable")]
+            BuiltinProcessor::BpfLoaderUpgradeable => programs::bpf_loader_upgradeable::describe(),
+            #[cfg(feature = "bubblegum")]
+            BuiltinProcessor::Bubblegum => programs::bubblegum::describe(),
+            #[cfg(feature = "candy-machine")]
+            BuiltinProcessor::CandyMachine => programs::candy_machine::describe(),
+            #[cfg(feature = "compute-budget")]
+            BuiltinProcessor::ComputeBudget => programs::native_compute_budget::describe(),
+            #[cfg(feature = "config")]
+            BuiltinProcessor::Config => programs::native_config::describe(),
+            #[cfg(feature = "drift")]
+            BuiltinProcessor::Drift => programs::drift::describe(),
+            #[cfg(feature = "governance")]
+            BuiltinProcessor::Governance => programs::governance::describe(),
+            #[cfg(feature = "lifinity")]
+            BuiltinProcessor::Lifinity => programs::lifinity::describe(),
+            #[cfg(feature = "loader")]
+            BuiltinProcessor::Loader => programs::native_loader::describe(),
+            #[cfg(feature = "magic-eden")]
+            BuiltinProcessor::MagicEden => programs::magic_eden::describe(),
+            #[cfg(feature = "memo")]
+            BuiltinProcessor::Memo => programs::native_memo::describe(),
+            #[cfg(feature = "name-service")]
+            BuiltinProcessor::NameService => programs::name_service::describe(),
+            #[cfg(feature = "openbook-v2")]
+            BuiltinProcessor::OpenbookV2 => programs::openbook_v2::describe(),
+            #[cfg(feature = "phoenix")]
+            BuiltinProcessor::Phoenix => programs::phoenix::describe(),
+            #[cfg(feature = "raydium-amm")]
+            BuiltinProcessor::RaydiumAmm => programs::raydium_amm::describe(),
+            #[cfg(feature = "secp256k1")]
+            BuiltinProcessor::Secp256k1 => programs::native_secp256k1::describe(),
+            #[cfg(feature = "serum-market")]
+            BuiltinProcessor::SerumMarket => programs::serum_market::describe(),
+            #[cfg(feature = "solend-token-lending")]
+            BuiltinProcessor::SolendTokenLending => programs::solend_token_lending::describe(),
+            #[cfg(feature = "stake")]
+            BuiltinProcessor::Stake => programs::native_stake::describe(),
+            #[cfg(feature = "system")]
+            BuiltinProcessor::System => programs::native_system::describe(),
+            #[cfg(feature = "tensor")]
+            BuiltinProcessor::Tensor => programs::tensor::describe(),
+            #[cfg(feature = "token")]
+            BuiltinProcessor::Token => programs::native_token::describe(),
+            #[cfg(feature = "token-2022")]
+            BuiltinProcessor::Token2022 => programs::native_token_2022::describe(),
+            #[cfg(feature = "token-lending")]
+            BuiltinProcessor::TokenLending => programs::native_token_lending::describe(),
+            #[cfg(feature = "token-metadata")]
+            BuiltinProcessor::TokenMetadata => programs::token_metadata::describe(),
+            #[cfg(feature = "token-swap")]
+            BuiltinProcessor::TokenSwap => programs::native_token_swap::describe(),
+            #[cfg(feature = "vote")]
+            BuiltinProcessor::Vote => programs::native_vote::describe(),
+            #[cfg(feature = "whirlpool")]
+            BuiltinProcessor::Whirlpool => programs::whirlpool::describe(),
+            // Only reachable when some processors weren't compiled in.
+            #[allow(unreachable_patterns)]
+            processor => ProgramSchema::new(processor.name()),
+        }
+    }
+}
+
+/// Decodes the instructions of a program this crate doesn't know about, e.g. one loaded from a
+/// plugin.
+pub trait ProgramProcessor: Send + Sync {
+    /// The name the processor goes by in logs.
+    fn name(&self) -> &str;
+
+    /// The program ids the processor decodes by default.
+    fn program_ids(&self) -> Vec<String>;
+
+    /// Decodes an instruction of one of the programs, or returns `None` when it can't.
+    fn decode(&self, instruction: Instruction) -> Option<InstructionSet>;
+
+    /// Same as [`decode`](Self::decode), for an instruction of a transaction confirmed at `slot`
+    /// when it's known, for processors whose layouts changed over time to pick those in use then.
+    fn decode_at(&self, instruction: Instruction, _slot: Option<u64>) -> Option<InstructionSet> {
+        self.decode(instruction)
+    }
+
+    /// The version of what the processor decodes instructions to, to be bumped whenever that
+    /// changes (see [`BuiltinProcessor::decoder_version`]). 1 by default.
+    fn decoder_version(&self) -> u32 {
+        1
+    }
+
+    /// The functions the instructions decode to and the properties they carry, for consumers to
+    /// know about them. Describes nothing by default.
+    fn describe(&self) -> ProgramSchema {
+        ProgramSchema::new(self.name())
+    }
+}
+
+/// Errors a closure processor may return when it can't decode an instruction.
+#[derive(Debug, Error)]
+pub enum ProcessError {
+    #[error("Unable to deserialize the instruction: {0}")]
+    Deserialize(#[from] std::io::Error),
+    #[error("Unknown instruction {0}")]
+    UnknownInstruction(u8),
+    #[error("{0}")]
+    Other(String),
+}
+
+/// What a closure processor gets to decode an instruction.
+pub struct InstructionContext<'a> {
+    // The program invoked by the instruction.
+    pub program: Symbol,
+    // The data the program was invoked with.
+    pub data: &'a [u8],
+    // The accounts the program was invoked with. Empty when the instruction is decoded outside of
+    // its transaction.
+    pub accounts: &'a [Pubkey],
+    // The local unique identifier of the instruction according to the transaction.
+    pub tx_instruction_id: i16,
+    // The index of the parent instruction for inner instructions, -1 otherwise.
+    pub parent_index: i16,
+    pub transaction_hash: &'a Arc<str>,
+    pub timestamp: i64,
+    pub network: Network,
+    // The slot of the transaction, when decoded as part of it.
+    pub slot: Option<u64>,
+}
+
+impl InstructionContext<'_> {
+    /// The instruction set recording a call to `function_name` with top-level `properties`,
+    /// whose types are inferred from their values (see [`PropertyType::infer`]).
+    pub fn instruction_set(
+        &self,
+        function_name: &str,
+        properties: Vec<(&str, String)>,
+    ) -> InstructionSet {
+        let properties = properties.into_iter()
+            .map(|(key, value)| {
+                let value_type = PropertyType::infer(&value);
+                (key, value, value_type)
+            })
+            .collect();
+
+        self.typed_instruction_set(function_name, properties)
+    }
+
+    /// Same as [`InstructionContext::instruction_set`], with the type of every property stated.
+    pub fn typed_instruction_set(
+        &self,
+        function_name: &str,
+        properties: Vec<(&str, String, PropertyType)>,
+    ) -> InstructionSet {
+        InstructionSet {
+            function: InstructionFunction {
+                tx_instruction_id: self.tx_instruction_id,
+                transaction_hash: self.transaction_hash.clone(),
+                parent_index: self.parent_index,
+                program: self.program,
+                function_name: function_name.into(),
+                timestamp: self.timestamp,
+                network: self.network,
+                decoder_version: 0,
+            },
+            properties: properties.into_iter()
+                .map(|(key, value, value_type)| InstructionProperty {
+                    tx_instruction_id: self.tx_instruction_id,
+                    transaction_hash: self.transaction_hash.clone(),
+                    parent_index: self.parent_index,
+                    key: key.into(),
+                    value,
+                    value_type,
+                    parent_key: "".into(),
+                    timestamp: self.timestamp,
+                })
+                .collect(),
+            raw_data: None,
+        }
+    }
+}
+
+/// A processor registered as a closure, see [`ProcessorRegistry::register_fn`].
+pub type ProcessorFn = dyn Fn(&InstructionContext<'_>) -> Result<Vec<InstructionSet>, ProcessError>
+    + Send + Sync;
+
+/// A processor registered for a program.
+#[derive(Clone)]
+pub enum Processor {
+    Builtin(BuiltinProcessor),
+    Custom(Arc<dyn ProgramProcessor>),
+    Fn(Arc<ProcessorFn>),
+}
+
+impl Processor {
+    pub fn name(&self) -> &str {
+        match self {
+            Processor::Builtin(processor) => processor.name(),
+            Processor::Custom(processor) => processor.name(),
+            Processor::Fn(_) => "closure",
+        }
+    }
+
+    /// What the processor decodes instructions to. Closures can't describe themselves.
+    pub fn describe(&self) -> ProgramSchema {
+        match self {
+            Processor::Builtin(processor) => processor.describe(),
+            Processor::Custom(processor) => processor.describe(),
+            Processor::Fn(_) => ProgramSchema::new("closure"),
+        }
+    }
+
+    /// The namespace of the keys the processor emits, see [`BuiltinProcessor::namespace`].
+    /// Processors of plugins go by their name, closures by none.
+    pub fn namespace(&self) -> Option<&str> {
+        match self {
+            Processor::Builtin(processor) => Some(processor.namespace()),
+            Processor::Custom(processor) => Some(processor.name()),
+            Processor::Fn(_) => None,
+        }
+    }
+
+    /// The version recorded with the functions the processor emits. Closures aren't versioned,
+    /// so what they decoded is never re-indexed.
+    pub fn decoder_version(&self) -> u32 {
+        match self {
+            Processor::Builtin(processor) => processor.decoder_version(),
+            Processor::Custom(processor) => processor.decoder_version(),
+            Processor::Fn(_) => 0,
+        }
+    }
+}
+
+impl fmt::Debug for Processor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Processor::Builtin(processor) => write!(f, "Builtin({:?})", processor),
+            Processor::Custom(processor) => write!(f, "Custom({})", processor.name()),
+            Processor::Fn(_) => write!(f, "Fn"),
+        }
+    }
+}
+
+/// What became of an instruction handed to the registry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeStatus {
+    Decoded,
+    // A processor is registered for the program, but couldn't make sense of the instruction.
+    Failed,
+    // No processor is registered for the program.
+    Unsupported,
+    // The program is sampled and the transaction wasn't part of the sample.
+    SampledOut,
+    // The processor of the program went over its budget too many times in a row and isn't handed
+    // instructions until it's restored (see `watchdog`).
+    Degraded,
+}
+
+/// Which instructions keep the data they were invoked with, as the `raw_data` of their
+/// instruction sets (or of their decode failure), for them to be decoded again later.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RawCapture {
+    Never,
+    // Only the instructions a processor is registered for but failed to decode.
+    OnFailure,
+    Always,
+}
+
+impl Default for RawCapture {
+    fn default() -> Self {
+        RawCapture::OnFailure
+    }
+}
+
+/// Maps program ids to the processor decoding their instructions.
+///
+/// The default registry knows about every built-in processor compiled in, under its well-known
+/// program ids; processors can be re-registered under other ids (e.g. forks deployed elsewhere)
+/// or removed, and custom processors registered alongside them.
+///
+/// Registrations stack up: the latest processor registered for a program shadows the previous
+/// ones, which take over again once it's unregistered.
+///
+/// ```ignore
+/// let mut registry = ProcessorRegistry::default();
+/// registry.register_fn("Counter111111111111111111111111111111111111", |ctx| {
+///     match CounterInstruction::try_from_slice(ctx.data)? {
+///         CounterInstruction::Increment { amount } => Ok(vec![
+///             ctx.instruction_set("increment", vec![("amount", amount.to_string())]),
+///         ]),
+///     }
+/// });
+/// ```
+///
+/// See `examples/closure_processor.rs` for the whole thing.
+///
+/// Programs can also be sampled: only the instructions of a fraction of the transactions are
+/// decoded (see [`ProcessorRegistry::sample`]).
+///
+/// A registry is only ever changed while it's being set up: decoding takes it by shared reference,
+/// so once built (see [`ProcessorRegistry::builder`]) it can be shared between any number of
+/// threads behind an `Arc`. Processors hold no state of their own, and what's shared between
+/// decodes is synchronized: the mint cache and the program versions are handles shared by their
+/// clones (see [`MintCache`] and [`ProgramVersions`]), and symbols go through the process-wide
+/// intern table (see [`Symbol`]).
+pub struct ProcessorRegistry {
+    // The processors registered for each program, the latest one last.
+    processors: HashMap<Symbol, Vec<Processor>>,
+    // The programs whose processor has a fast path (see `decode_one`), looked up by address.
+    fast_paths: Vec<(Pubkey, BuiltinProcessor)>,
+    // The fraction of the transactions decoded, for sampled programs.
+    sampling: HashMap<Symbol, f64>,
+    // Where the decimals of amounts are looked up, when UI amounts are emitted.
+    #[cfg(feature = "runtime")]
+    mints: Option<MintCache>,
+    // Where the upgrades of programs are learnt and looked up, when instructions are stamped
+    // with the version of their program.
+    #[cfg(feature = "runtime")]
+    program_versions: Option<ProgramVersions>,
+    // The Phoenix markets whose ticks and lots are converted to prices and sizes, by address.
+    #[cfg(feature = "phoenix")]
+    phoenix_markets: HashMap<Pubkey, PhoenixMarket>,
+    // The layout descriptors programs are decoded with, reloaded as they change.
+    #[cfg(feature = "layout-decoder")]
+    layouts: Option<Arc<LayoutRegistry>>,
+    // The cluster the instructions decoded come from.
+    network: Network,
+    // Which instructions keep their raw data.
+    raw_capture: RawCapture,
+    // Which of the properties decoded are kept, before they're redacted.
+    projection: Projection,
+    // How the values of the properties decoded are redacted.
+    redaction: Redaction,
+    // Whether the keys of the properties decoded are prefixed with the namespace of their program.
+    key_namespacing: bool,
+    // The namespaces of programs, overriding those of their processors.
+    namespaces: HashMap<Symbol, String>,
+    // Whether the names put in their canonical form are also emitted as they were.
+    legacy_names: bool,
+    // Times the dispatches to processors, degrading the programs of those too slow.
+    #[cfg(feature = "runtime")]
+    watchdog: Watchdog,
+}
+
+impl Default for ProcessorRegistry {
+    fn default() -> Self {
+        ProcessorRegistry::for_network(Network::default())
+    }
+}
+
+// Registries are shared between threads once built.
+const _: fn() = || {
+    fn shareable<T: Send + Sync>() {}
+    shareable::<ProcessorRegistry>();
+};
+
+impl ProcessorRegistry {
+    /// Sets up a registry knowing about every built-in processor compiled in, to share between
+    /// threads once built.
+    ///
+    /// ```ignore
+    /// let registry = ProcessorRegistry::builder_for(Network::Devnet)
+    ///     .register_fn("Counter111111111111111111111111111111111111", decode_counter)
+    ///     .build();
+    /// let handles: Vec<_> = (0..4).map(|_| {
+    ///     let registry = registry.clone();
+    ///     std::thread::spawn(move || decode_transaction(&registry, &transaction))
+    /// }).collect();
+    /// ```
+    pub fn builder() -> ProcessorRegistryBuilder {
+        ProcessorRegistryBuilder { registry: ProcessorRegistry::default() }
+    }
+
+    /// Same as [`builder`](Self::builder), for a registry of `network` (see
+    /// [`ProcessorRegistry::for_network`]).
+    pub fn builder_for(network: Network) -> ProcessorRegistryBuilder {
+        ProcessorRegistryBuilder { registry: ProcessorRegistry::for_network(network) }
+    }
+
+    /// A registry knowing about every built-in processor compiled in, under the ids of their
+    /// deployments on `network` (see [`BuiltinProcessor::all_on`]), tagging what it decodes with
+    /// `network`.
+    pub fn for_network(network: Network) -> Self {
+        let mut registry = ProcessorRegistry::new();
+        registry.set_network(network);
+        for (program_id, processor) in BuiltinProcessor::all_on(network) {
+            registry.register(program_id, processor);
+        }
+
+        registry
+    }
+
+    /// An empty registry, decoding nothing.
+    pub fn new() -> Self {
+        ProcessorRegistry {
+            processors: HashMap::new(),
+            fast_paths: Vec::new(),
+            sampling: HashMap::new(),
+            #[cfg(feature = "runtime")]
+            mints: None,
+            #[cfg(feature = "runtime")]
+            program_versions: None,
+            #[cfg(feature = "phoenix")]
+            phoenix_markets: HashMap::new(),
+            #[cfg(feature = "layout-decoder")]
+            layouts: None,
+            network: Network::default(),
+            raw_capture: RawCapture::default(),
+            projection: Projection::default(),
+            redaction: Redaction::default(),
+            key_namespacing: false,
+            namespaces: HashMap::new(),
+            legacy_names: false,
+            #[cfg(feature = "runtime")]
+            watchdog: Watchdog::default(),
+        }
+    }
+
+    /// Decodes the instructions of `program_id` with `processor`, shadowing any previous one.
+    pub fn register(&mut self, program_id: &str, processor: BuiltinProcessor) {
+        self.push(program_id, Processor::Builtin(processor));
+    }
+
+    /// Decodes the instructions of `program_id` with a custom processor, shadowing any previous
+    /// one.
+    pub fn register_processor(&mut self, program_id: &str, processor: Arc<dyn ProgramProcessor>) {
+        self.warn_shadowing(program_id, processor.name());
+        self.push(program_id, Processor::Custom(processor));
+    }
+
+    /// Decodes the instructions of `program_id` with a closure, shadowing any previous processor.
+    ///
+    /// The closure is handed the instruction along with the accounts it was invoked with and the
+    /// slot of its transaction (see [`InstructionContext`]), and may decode it to any number of
+    /// instruction sets.
+    pub fn register_fn<F>(&mut self, program_id: &str, processor: F)
+    where
+        F: Fn(&InstructionContext<'_>) -> Result<Vec<InstructionSet>, ProcessError>
+            + Send + Sync + 'static,
+    {
+        self.warn_shadowing(program_id, "closure");
+        self.push(program_id, Processor::Fn(Arc::new(processor)));
+    }
+
+    /// Stops decoding the instructions of `program_id` with the latest processor registered for
+    /// it, handing them back to the one it shadowed, if any.
+    pub fn unregister(&mut self, program_id: &str) -> Option<Processor> {
+        let program = Symbol::lookup(program_id)?;
+        let processors = self.processors.get_mut(&program)?;
+        let processor = processors.pop();
+        if processors.is_empty() {
+            self.processors.remove(&program);
+        }
+        self.update_fast_path(program_id);
+
+        processor
+    }
+
+    fn push(&mut self, program_id: &str, processor: Processor) {
+        self.processors.entry(Symbol::intern(program_id)).or_default().push(processor);
+        self.update_fast_path(program_id);
+    }
+
+    // Keeps `fast_paths` in line with the processor now registered last for `program_id`.
+    fn update_fast_path(&mut self, program_id: &str) {
+        let address = match Pubkey::from_str(program_id) {
+            Ok(address) => address,
+            Err(_) => return,
+        };
+        self.fast_paths.retain(|(program, _)| *program != address);
+        if let Some(Processor::Builtin(processor)) = self.processor(program_id) {
+            if fast::has_fast_path(*processor) {
+                self.fast_paths.push((address, *processor));
+            }
+        }
+    }
+
+    fn warn_shadowing(&self, program_id: &str, name: &str) {
+        if let Some(shadowed) = self.processor(program_id) {
+            warn!("[spi-wrapper/registry] The {} processor shadows the {} processor for {}.",
+                name, shadowed.name(), program_id);
+        }
+    }
+
+    /// Only decodes the instructions of `program_id` in a `rate` (between 0 and 1, clamped)
+    /// fraction of the transactions. Which transactions are picked only depends on their
+    /// signature (see [`sampled_in`]), so re-processing or another replica picks the same ones.
+    pub fn sample(&mut self, program_id: &str, rate: f64) {
+        self.sampling.insert(Symbol::intern(program_id), rate.max(0.0).min(1.0));
+    }
+
+    /// The fraction of the transactions whose instructions of `program_id` are decoded.
+    pub fn sample_rate(&self, program_id: &str) -> f64 {
+        Symbol::lookup(program_id)
+            .and_then(|program| self.sampling.get(&program).copied())
+            .unwrap_or(1.0)
+    }
+
+    /// Emits the UI amount of every amount property whose mint decimals are known to `mints`,
+    /// as `<key>_ui` (see [`MintCache`]).
+    #[cfg(feature = "runtime")]
+    pub fn set_mint_cache(&mut self, mints: MintCache) {
+        self.mints = Some(mints);
+    }
+
+    #[cfg(feature = "runtime")]
+    pub fn mint_cache(&self) -> Option<&MintCache> {
+        self.mints.as_ref()
+    }
+
+    /// Learns the upgrades of programs from the transactions decoded into `versions`, and stamps
+    /// every instruction with the slot its program was last upgraded at, as
+    /// `program_version_slot` (see [`ProgramVersions`]).
+    #[cfg(feature = "runtime")]
+    pub fn set_program_versions(&mut self, versions: ProgramVersions) {
+        self.program_versions = Some(versions);
+    }
+
+    #[cfg(feature = "runtime")]
+    pub fn program_versions(&self) -> Option<&ProgramVersions> {
+        self.program_versions.as_ref()
+    }
+
+    /// Emits the prices and sizes of the orders placed and cancelled on the Phoenix `market`,
+    /// converted from its ticks and lots (see [`PhoenixMarket`]).
+    #[cfg(feature = "phoenix")]
+    pub fn set_phoenix_market(&mut self, market: Pubkey, settings: PhoenixMarket) {
+        self.phoenix_markets.insert(market, settings);
+    }
+
+    #[cfg(feature = "phoenix")]
+    pub fn phoenix_market(&self, market: &Pubkey) -> Option<&PhoenixMarket> {
+        self.phoenix_markets.get(market)
+    }
+
+    /// Decodes the programs there are layouts of in `layouts` with them, over any previous
+    /// processor, picking the version of their layouts by slot (see [`LayoutRegistry`]). The
+    /// programs whose layouts are only loaded later, once `layouts` is reloaded, are decoded with
+    /// them too, when no processor is registered for them.
+    #[cfg(feature = "layout-decoder")]
+    pub fn set_layouts(&mut self, layouts: Arc<LayoutRegistry>) {
+        for (program_id, processor) in layouts.processors() {
+            self.register_processor(&program_id, processor);
+        }
+        self.layouts = Some(layouts);
+    }
+
+    #[cfg(feature = "layout-decoder")]
+    pub fn layouts(&self) -> Option<&Arc<LayoutRegistry>> {
+        self.layouts.as_ref()
+    }
+
+    /// Tags every record decoded through the registry with `network`, mainnet by default.
+    pub fn set_network(&mut self, network: Network) {
+        self.network = network;
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Keeps the raw data of the instructions decoded along with their transaction according to
+    /// `capture`, only of those that failed to decode by default.
+    pub fn set_raw_capture(&mut self, capture: RawCapture) {
+        self.raw_capture = capture;
+    }
+
+    pub fn raw_capture(&self) -> RawCapture {
+        self.raw_capture
+    }
+
+    /// Keeps the properties decoded according to `projection`, before they're redacted. Every
+    /// property is kept by default (see [`Projection`]).
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    pub fn projection(&self) -> &Projection {
+        &self.projection
+    }
+
+    /// Redacts the values of the properties decoded according to `redaction`, which only caps
+    /// them at 16 KiB by default (see [`Redaction`]).
+    pub fn set_redaction(&mut self, redaction: Redaction) {
+        self.redaction = redaction;
+    }
+
+    pub fn redaction(&self) -> &Redaction {
+        &self.redaction
+    }
+
+    /// Prefixes the keys of the properties decoded (and their parent keys) with the namespace of
+    /// their program, e.g. `lending.liquidity_amount`, for the keys of different programs never to
+    /// collide. Off by default. The properties the pipeline adds to the instruction sets of a
+    /// transaction (UI amounts, the call tree) are namespaced along with the others, and what
+    /// reads the keys of the records sunk (aggregated sums, notification rules) gets them
+    /// namespaced.
+    pub fn set_key_namespacing(&mut self, enabled: bool) {
+        self.key_namespacing = enabled;
+    }
+
+    pub fn key_namespacing(&self) -> bool {
+        self.key_namespacing
+    }
+
+    /// Namespaces the keys of `program_id` with `namespace` rather than the namespace of its
+    /// processor (see [`Processor::namespace`]). Closures have none otherwise.
+    pub fn set_namespace(&mut self, program_id: &str, namespace: &str) {
+        self.namespaces.insert(Symbol::intern(program_id), namespace.to_string());
+    }
+
+    /// The namespace of the keys of `program_id`, if it has one.
+    pub fn namespace(&self, program_id: &str) -> Option<&str> {
+        let program = Symbol::lookup(program_id)?;
+        match self.namespaces.get(&program) {
+            Some(namespace) => Some(namespace.as_str()),
+            None => self.processors.get(&program)?.last()?.namespace(),
+        }
+    }
+
+    /// Prefixes the keys of `instruction_set` with the namespace of its program when keys are
+    /// namespaced (see [`ProcessorRegistry::set_key_namespacing`]). Whatever the registry decodes
+    /// is namespaced already, but for [`ProcessorRegistry::decode_in_transaction`], which leaves
+    /// it to the caller once done adding properties.
+    pub fn namespace_keys(&self, instruction_set: &mut InstructionSet) {
+        if !self.key_namespacing {
+            return;
+        }
+        let namespace = match self.namespace(instruction_set.function.program.as_str()) {
+            Some(namespace) => namespace,
+            None => return,
+        };
+
+        for property in &mut instruction_set.properties {
+            property.key = Symbol::intern(&qualify(namespace, property.key.as_str()));
+            if !property.parent_key.is_empty() {
+                property.parent_key =
+                    Symbol::intern(&qualify(namespace, property.parent_key.as_str()));
+            }
+        }
+    }
+
+    /// Also emits the function names and keys put in their canonical form (see [`naming`]) as
+    /// they were, for what filters on them to be migrated. Off by default.
+    ///
+    /// [`naming`]: crate::naming
+    pub fn set_legacy_names(&mut self, enabled: bool) {
+        self.legacy_names = enabled;
+    }
+
+    pub fn legacy_names(&self) -> bool {
+        self.legacy_names
+    }
+
+    /// Times the dispatches to processors against the budgets of `config` (see [`watchdog`]),
+    /// forgetting how they went so far.
+    ///
+    /// [`watchdog`]: crate::watchdog
+    #[cfg(feature = "runtime")]
+    pub fn set_watchdog(&mut self, config: WatchdogConfig) {
+        self.watchdog = Watchdog::new(config);
+    }
+
+    /// How the dispatches to processors went, and which programs are degraded.
+    #[cfg(feature = "runtime")]
+    pub fn watchdog(&self) -> &Watchdog {
+        &self.watchdog
+    }
+
+    /// Hands the instructions of `program_id` to its processor again, once its program was
+    /// degraded for being too slow. Returns whether it was.
+    #[cfg(feature = "runtime")]
+    pub fn restore(&self, program_id: &str) -> bool {
+        match Symbol::lookup(program_id) {
+            Some(program) => self.watchdog.restore(program),
+            None => false,
+        }
+    }
+
+    /// The built-in processor registered for `program_id`, if any.
+    pub fn get(&self, program_id: &str) -> Option<BuiltinProcessor> {
+        match self.processor(program_id)? {
+            Processor::Builtin(processor) => Some(*processor),
+            Processor::Custom(_) | Processor::Fn(_) => None,
+        }
+    }
+
+    /// The processor, built-in or not, registered for `program_id`, if any.
+    pub fn processor(&self, program_id: &str) -> Option<&Processor> {
+        self.processors.get(&Symbol::lookup(program_id)?)?.last()
+    }
+
+    /// The symbol of `program_id` when a processor is registered for it. The ids of other
+    /// programs aren't interned (see [`ProgramId`](crate::ProgramId)).
+    pub(crate) fn registered_symbol(&self, program_id: &str) -> Option<Symbol> {
+        match Symbol::lookup(program_id) {
+            Some(program) if self.processors.contains_key(&program) => Some(program),
+            _ if self.layouts_processor(program_id).is_some() => Some(Symbol::intern(program_id)),
+            _ => None,
+        }
+    }
+
+    /// The processor of `program_id` when there are layouts of it, loaded after they were set
+    /// (see [`ProcessorRegistry::set_layouts`]).
+    #[cfg_attr(not(feature = "layout-decoder"), allow(unused_variables))]
+    fn layouts_processor(&self, program_id: &str) -> Option<Processor> {
+        #[cfg(feature = "layout-decoder")]
+        if let Some(layouts) = &self.layouts {
+            return layouts.versioned(program_id).map(Processor::Custom);
+        }
+
+        None
+    }
+
+    /// What the processor registered for each program decodes its instructions to, by program
+    /// id. The keys are namespaced when the keys decoded are.
+    pub fn schema(&self) -> BTreeMap<String, ProgramSchema> {
+        self.processors.iter()
+            .filter_map(|(program, processors)| {
+                let mut schema = processors.last()?.describe();
+                if let Some(namespace) = self.namespace(program.as_str())
+                    .filter(|_| self.key_namespacing)
+                {
+                    for key in schema.functions.iter_mut().flat_map(|f| f.keys.iter_mut()) {
+                        key.key = qualify(namespace, &key.key);
+                        if !key.parent_key.is_empty() {
+                            key.parent_key = qualify(namespace, &key.parent_key);
+                        }
+                    }
+                }
+
+                Some((program.to_string(), schema))
+            })
+            .collect()
+    }
+
+    /// The schema of every program (see [`ProcessorRegistry::schema`]), as a JSON document.
+    pub fn schema_json(&self) -> String {
+        serde_json::to_string_pretty(&self.schema()).expect("Schemas serialize to JSON")
+    }
+
+    /// Decodes an instruction with the processor registered for its program.
+    ///
+    /// `instructions` are the top-level instructions of the transaction, which some programs
+    /// (secp256k1) need to interpret an instruction. Closure processors may decode an instruction
+    /// to several instruction sets, of which only the first is returned; decode it along with its
+    /// transaction ([`ProcessorRegistry::decode_in_transaction`]) to get them all.
+    pub fn decode(
+        &self,
+        instruction: Instruction,
+        instructions: Option<&[CompiledInstruction]>,
+    ) -> Option<InstructionSet> {
+        let mut decoded = None;
+        self.decode_with(instruction, instructions, &[], None, |mut instruction_set| {
+            if decoded.is_none() {
+                self.namespace_keys(&mut instruction_set);
+                decoded = Some(instruction_set);
+            }
+        });
+
+        decoded
+    }
+
+    /// Decodes an instruction invoked with `accounts` outside of its transaction, pushing
+    /// whatever it decodes to onto `decoded`. Instructions that can only be interpreted along with
+    /// the rest of their transaction (secp256k1) aren't decoded.
+    pub fn decode_with_accounts(
+        &self,
+        instruction: Instruction,
+        accounts: &[Pubkey],
+        slot: Option<u64>,
+        decoded: &mut Vec<InstructionSet>,
+    ) -> DecodeStatus {
+        self.decode_with(instruction, None, accounts, slot, |mut instruction_set| {
+            self.namespace_keys(&mut instruction_set);
+            decoded.push(instruction_set);
+        })
+    }
+
+    /// Decodes the data of an instruction of `program_id` without allocating, for callers decoding
+    /// one instruction at a time (see [`fast`](crate::fast)). Only the system, token and
+    /// token-lending processors have such a fast path: the instructions of other programs are
+    /// [`FastDecode::Unsupported`], to be decoded as usual. What the registry does on top of its
+    /// processors (sampling, projection, redaction, key namespacing) is skipped.
+    pub fn decode_one<'a>(&self, program_id: &Pubkey, data: &'a [u8]) -> FastDecode<'a> {
+        let processor = match self.fast_paths.iter().find(|(program, _)| program == program_id) {
+            Some((_, processor)) => *processor,
+            None => return FastDecode::Unsupported,
+        };
+
+        match fast::decode(processor, data) {
+            Some(mut instruction) => {
+                instruction.decoder_version = processor.decoder_version();
+                FastDecode::Decoded(instruction)
+            }
+            None => FastDecode::Failed,
+        }
+    }
+
+    /// Decodes an instruction of `transaction` invoked with `accounts`, pushing whatever it
+    /// decodes to onto `decoded`, whose keys are left for the caller to namespace (see
+    /// [`ProcessorRegistry::namespace_keys`]).
+    #[cfg(feature = "runtime")]
+    pub fn decode_in_transaction(
+        &self,
+        instruction: Instruction,
+        accounts: &[Pubkey],
+        transaction: &RawTransaction,
+        decoded: &mut Vec<InstructionSet>,
+    ) -> DecodeStatus {
+        self.decode_with(instruction, Some(&transaction.instructions), accounts,
+            Some(transaction.slot), |instruction_set| decoded.push(instruction_set))
+    }
+
+    /// Decodes an instruction of `program_id` out of its `data`, as strings straight out of RPC
+    /// JSON (see [`decode_instruction_data`]). Instructions that can only be interpreted along
+    /// with the rest of their transaction (secp256k1) aren't decoded.
+    pub fn process_from_encoded(
+        &self,
+        program_id: &str,
+        data: &str,
+        context: &EncodedContext,
+    ) -> Result<Vec<InstructionSet>, DecodeError> {
+        Pubkey::from_str(program_id)
+            .map_err(|_| DecodeError::InvalidProgramId(program_id.to_string()))?;
+        let program = self.registered_symbol(program_id)
+            .ok_or_else(|| DecodeError::Unsupported(program_id.to_string()))?;
+        let instruction = Instruction {
+            tx_instruction_id: context.tx_instruction_id,
+            transaction_hash: context.transaction_hash.clone(),
+            program,
+            data: decode_instruction_data(data, context.encoding)?,
+            parent_index: context.parent_index,
+            timestamp: context.timestamp,
+            network: self.network,
+        };
+
+        let mut decoded = Vec::new();
+        let status = self.decode_with_accounts(instruction, &context.accounts, context.slot,
+                                               &mut decoded);
+        match status {
+            DecodeStatus::Decoded => Ok(decoded),
+            DecodeStatus::Failed => Err(DecodeError::Failed(program_id.to_string())),
+            DecodeStatus::Unsupported => Err(DecodeError::Unsupported(program_id.to_string())),
+            DecodeStatus::SampledOut => Err(DecodeError::SampledOut(program_id.to_string())),
+            DecodeStatus::Degraded => Err(DecodeError::Degraded(program_id.to_string())),
+        }
+    }
+
+    fn decode_with(
+        &self,
+        instruction: Instruction,
+        instructions: Option<&[CompiledInstruction]>,
+        accounts: &[Pubkey],
+        slot: Option<u64>,
+        mut on_decoded: impl FnMut(InstructionSet),
+    ) -> DecodeStatus {
+        let span = trace_span!("decode_instruction", program = %instruction.program,
+                               instruction_index = instruction.tx_instruction_id,
+                               parent_index = instruction.parent_index);
+        let _entered = span.enter();
+
+        if let Some(rate) = self.sampling.get(&instruction.program) {
+            if !sampled_in(&instruction.transaction_hash, *rate) {
+                #[cfg(feature = "metrics")]
+                {
+                    crate::metrics::metrics().instructions_sampled_out
+                        .with_label_values(&[instruction.program.as_str()])
+                        .inc();
+                }
+                return DecodeStatus::SampledOut;
+            }
+        }
+
+        let layouts_processor;
+        let processor = match self.processors.get(&instruction.program).and_then(|p| p.last()) {
+            Some(processor) => processor,
+            None => match self.layouts_processor(instruction.program.as_str()) {
+                Some(processor) => {
+                    layouts_processor = processor;
+                    &layouts_processor
+                }
+                None => {
+                    info!("Looks like this program ({}) is an unsupported one.",
+                          instruction.program);
+
+                    return DecodeStatus::Unsupported;
+                }
+            },
+        };
+        #[cfg(feature = "runtime")]
+        if self.watchdog.is_degraded(instruction.program) {
+            debug!("[spi-wrapper/registry] Degraded processor, the instruction is kept raw.");
+            return DecodeStatus::Degraded;
+        }
+
+        #[cfg(feature = "metrics")]
+        let program = instruction.program;
+        let discriminant = instruction.data.first().copied();
+        #[cfg(feature = "runtime")]
+        let (watched, started) = (instruction.program, Instant::now());
+        let decoder_version = processor.decoder_version();
+        let mut decoded_any = false;
+        let mut on_decoded = |mut instruction_set: InstructionSet| {
+            naming::canonicalize(&mut instruction_set, processor.name(), self.legacy_names);
+            #[cfg(feature = "metrics")]
+            {
+                let function_name = instruction_set.function.function_name;
+                crate::metrics::metrics().instructions_decoded
+                    .with_label_values(&[program.as_str(), function_name.as_str()])
+                    .inc();
+            }
+            instruction_set.function.decoder_version = decoder_version;
+            #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+            let projected_out = self.projection.apply(&mut instruction_set);
+            #[cfg(feature = "metrics")]
+            if projected_out > 0 {
+                let function_name = instruction_set.function.function_name;
+                crate::metrics::metrics().properties_projected_out
+                    .with_label_values(&[program.as_str(), function_name.as_str()])
+                    .inc_by(projected_out as u64);
+            }
+            self.redaction.apply(&mut instruction_set);
+            decoded_any = true;
+            on_decoded(instruction_set);
+        };
+        match processor {
+            Processor::Builtin(processor) => {
+                decode_builtin(*processor, instruction, instructions, accounts).into_iter()
+                    .for_each(&mut on_decoded);
+            }
+            Processor::Custom(processor) => {
+                processor.decode_at(instruction, slot).into_iter().for_each(&mut on_decoded);
+            }
+            Processor::Fn(processor) => {
+                let context = InstructionContext {
+                    program: instruction.program,
+                    data: &instruction.data,
+                    accounts,
+                    tx_instruction_id: instruction.tx_instruction_id,
+                    parent_index: instruction.parent_index,
+                    transaction_hash: &instruction.transaction_hash,
+                    timestamp: instruction.timestamp,
+                    network: instruction.network,
+                    slot,
+                };
+                match processor(&context) {
+                    Ok(instruction_sets) => instruction_sets.into_iter().for_each(&mut on_decoded),
+                    Err(err) => debug!(error = %err,
+                                       "[spi-wrapper/registry] The closure processor failed."),
+                }
+            }
+        }
+        #[cfg(feature = "runtime")]
+        self.watchdog.observe(watched, processor.name(), discriminant, started.elapsed());
+
+        if decoded_any {
+            return DecodeStatus::Decoded;
+        }
+
+        debug!(discriminant = ?discriminant,
+               "[spi-wrapper/registry] Failed to decode the instruction.");
+        #[cfg(feature = "metrics")]
+        {
+            let discriminant = discriminant.map_or("none".to_string(), |d| d.to_string());
+            crate::metrics::metrics().decode_failures
+                .with_label_values(&[program.as_str(), discriminant.as_str()])
+                .inc();
+        }
+
+        DecodeStatus::Failed
+    }
+
+    /// Async version of [`ProcessorRegistry::decode`], which does no I/O.
+    #[deprecated(note = "decoding is synchronous, use `decode` instead")]
+    pub async fn dispatch(
+        &self,
+        instruction: Instruction,
+        instructions: Option<&[CompiledInstruction]>,
+    ) -> Option<InstructionSet> {
+        self.decode(instruction, instructions)
+    }
+}
+
+/// Sets up a [`ProcessorRegistry`], see [`ProcessorRegistry::builder`]. Every method does what the
+/// registry method of the same name (`set_` aside) does.
+pub struct ProcessorRegistryBuilder {
+    registry: ProcessorRegistry,
+}
+
+impl ProcessorRegistryBuilder {
+    /// Starts over from a registry decoding nothing, for only the processors registered next to
+    /// be known.
+    pub fn empty(mut self) -> Self {
+        self.registry.processors.clear();
+        self.registry.fast_paths.clear();
+        self
+    }
+
+    pub fn register(mut self, program_id: &str, processor: BuiltinProcessor) -> Self {
+        self.registry.register(program_id, processor);
+        self
+    }
+
+    pub fn register_processor(
+        mut self,
+        program_id: &str,
+        processor: Arc<dyn ProgramProcessor>,
+    ) -> Self {
+        self.registry.register_processor(program_id, processor);
+        self
+    }
+
+    pub fn register_fn<F>(mut self, program_id: &str, processor: F) -> Self
+    where
+        F: Fn(&InstructionContext<'_>) -> Result<Vec<InstructionSet>, ProcessError>
+            + Send + Sync + 'static,
+    {
+        self.registry.register_fn(program_id, processor);
+        self
+    }
+
+    pub fn unregister(mut self, program_id: &str) -> Self {
+        self.registry.unregister(program_id);
+        self
+    }
+
+    pub fn sample(mut self, program_id: &str, rate: f64) -> Self {
+        self.registry.sample(program_id, rate);
+        self
+    }
+
+    /// Looks the decimals of mints up in `mints`, a handle shared with whatever else holds a
+    /// clone of it (the resolver filling it, other registries).
+    #[cfg(feature = "runtime")]
+    pub fn mint_cache(mut self, mints: MintCache) -> Self {
+        self.registry.set_mint_cache(mints);
+        self
+    }
+
+    /// Stamps instructions with the version of their program, learnt into `versions`, a handle
+    /// shared with whatever else holds a clone of it (the resolver, the sink persisting it).
+    #[cfg(feature = "runtime")]
+    pub fn program_versions(mut self, versions: ProgramVersions) -> Self {
+        self.registry.set_program_versions(versions);
+        self
+    }
+
+    #[cfg(feature = "phoenix")]
+    pub fn phoenix_market(mut self, market: Pubkey, settings: PhoenixMarket) -> Self {
+        self.registry.set_phoenix_market(market, settings);
+        self
+    }
+
+    pub fn network(mut self, network: Network) -> Self {
+        self.registry.set_network(network);
+        self
+    }
+
+    pub fn raw_capture(mut self, capture: RawCapture) -> Self {
+        self.registry.set_raw_capture(capture);
+        self
+    }
+
+    pub fn projection(mut self, projection: Projection) -> Self {
+        self.registry.set_projection(projection);
+        self
+    }
+
+    pub fn redaction(mut self, redaction: Redaction) -> Self {
+        self.registry.set_redaction(redaction);
+        self
+    }
+
+    pub fn key_namespacing(mut self, enabled: bool) -> Self {
+        self.registry.set_key_namespacing(enabled);
+        self
+    }
+
+    pub fn namespace(mut self, program_id: &str, namespace: &str) -> Self {
+        self.registry.set_namespace(program_id, namespace);
+        self
+    }
+
+    pub fn legacy_names(mut self, enabled: bool) -> Self {
+        self.registry.set_legacy_names(enabled);
+        self
+    }
+
+    #[cfg(feature = "runtime")]
+    pub fn watchdog(mut self, config: WatchdogConfig) -> Self {
+        self.registry.set_watchdog(config);
+        self
+    }
+
+    /// The registry, which can't be changed anymore.
+    pub fn build(self) -> Arc<ProcessorRegistry> {
+        Arc::new(self.registry)
+    }
+}
+
+/// `key` in `namespace`.
+fn qualify(namespace: &str, key: &str) -> String {
+    format!("{}.{}", namespace, key)
+}
+
+/// Whether the transaction with `signature` is part of a `rate` sample. The same signature is
+/// always part of the same samples, a transaction sampled in at some rate being sampled in at
+/// any higher one.
+pub fn sampled_in(signature: &str, rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+
+    let hash = Sha3_256::digest(signature.as_bytes());
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&hash[..8]);
+    (u64::from_be_bytes(bytes) as f64 / u64::MAX as f64) < rate
+}
+
+#[cfg_attr(
+    not(all(
+        feature = "secp256k1",
+        any(
+            feature = "aldrin",
+            feature = "associated-token-account",
+            feature = "auction-house",
+            feature = "bubblegum",
+            feature = "candy-machine",
+            feature = "governance",
+            feature = "lifinity",
+            feature = "magic-eden",
+            feature = "name-service",
+            feature = "openbook-v2",
+            feature = "raydium-amm",
+            feature = "solend-token-lending",
+            feature = "tensor",
+            feature = "token-lending",
+            feature = "token-metadata",
+            feature = "whirlpool",
+        ),
+    )),
+    allow(unused_variables)
+)]
+fn decode_builtin(
+    processor: BuiltinProcessor,
+    instruction: Instruction,
+    instructions: Option<&[CompiledInstruction]>,
+    // The accounts the instruction was invoked with, empty when unknown.
+    accounts: &[Pubkey],
+) -> Option<InstructionSet> {
+    match processor {
+        #[cfg(feature = "aldrin")]
+        BuiltinProcessor::Aldrin => {
+            programs::aldrin::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "associated-token-account")]
+        BuiltinProcessor::AssociatedTokenAccount => {
+            programs::native_associated_token_account::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "auction-house")]
+        BuiltinProcessor::AuctionHouse => {
+            programs::auction_house::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "bpf-loader")]
+        BuiltinProcessor::BpfLoader => {
+            programs::bpf_loader::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "bpf-loader-upgradeable")]
+        BuiltinProcessor::BpfLoaderUpgradeable => {
+            programs::bpf_loader_upgradeable::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "bubblegum")]
+        BuiltinProcessor::Bubblegum => {
+            programs::bubblegum::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "candy-machine")]
+        BuiltinProcessor::CandyMachine => {
+            programs::candy_machine::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "compute-budget")]
+        BuiltinProcessor::ComputeBudget => {
+            programs::native_compute_budget::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "config")]
+        BuiltinProcessor::Config => {
+            programs::native_config::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "drift")]
+        BuiltinProcessor::Drift => {
+            programs::drift::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "governance")]
+        BuiltinProcessor::Governance => {
+            programs::governance::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "lifinity")]
+        BuiltinProcessor::Lifinity => {
+            programs::lifinity::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "loader")]
+        BuiltinProcessor::Loader => {
+            programs::native_loader::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "magic-eden")]
+        BuiltinProcessor::MagicEden => {
+            programs::magic_eden::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "memo")]
+        BuiltinProcessor::Memo => {
+            programs::native_memo::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "name-service")]
+        BuiltinProcessor::NameService => {
+            programs::name_service::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "openbook-v2")]
+        BuiltinProcessor::OpenbookV2 => {
+            programs::openbook_v2::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "phoenix")]
+        BuiltinProcessor::Phoenix => {
+            programs::phoenix::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "raydium-amm")]
+        BuiltinProcessor::RaydiumAmm => {
+            programs::raydium_amm::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "secp256k1")]
+        BuiltinProcessor::Secp256k1 => {
+            if let Some(og_instructs) = instructions {
+                programs::native_secp256k1::fragment_instruction(instruction, og_instructs)
+            } else {
+                None
+            }
+        }
+        #[cfg(feature = "serum-market")]
+        BuiltinProcessor::SerumMarket => {
+            programs::serum_market::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "solend-token-lending")]
+        BuiltinProcessor::SolendTokenLending => {
+            programs::solend_token_lending::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "stake")]
+        BuiltinProcessor::Stake => {
+            programs::native_stake::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "system")]
+        BuiltinProcessor::System => {
+            programs::native_system::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "tensor")]
+        BuiltinProcessor::Tensor => {
+            programs::tensor::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "token")]
+        BuiltinProcessor::Token => {
+            programs::native_token::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "token-2022")]
+        BuiltinProcessor::Token2022 => {
+            programs::native_token_2022::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "token-lending")]
+        BuiltinProcessor::TokenLending => {
+            programs::native_token_lending::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "token-metadata")]
+        BuiltinProcessor::TokenMetadata => {
+            programs::token_metadata::fragment_instruction(instruction, accounts)
+        }
+        #[cfg(feature = "token-swap")]
+        BuiltinProcessor::TokenSwap => {
+            programs::native_token_swap::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "vote")]
+        BuiltinProcessor::Vote => {
+            programs::native_vote::fragment_instruction(instruction)
+        }
+        #[cfg(feature = "whirlpool")]
+        BuiltinProcessor::Whirlpool => {
+            programs::whirlpool::fragment_instruction(instruction, accounts)
+        }
+        // Only reachable when some processors weren't compiled in.
+        #[allow(unreachable_patterns)]
+        processor => {
+            warn!("[spi-wrapper/registry] The {} processor isn't compiled in, enable the `{}` \
+            feature to decode {}.", processor.name(), processor.name(), instruction.program);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::DataEncoding;
+
+    #[test]
+    fn default_registry_knows_every_builtin() {
+        let registry = ProcessorRegistry::default();
+        for (program_id, processor) in BuiltinProcessor::all() {
+            assert_eq!(registry.get(program_id), Some(processor));
+        }
+    }
+
+    #[test]
+    fn registries_decode_the_deployments_of_their_network() {
+        let mainnet = ProcessorRegistry::for_network(Network::Mainnet);
+        let devnet = ProcessorRegistry::for_network(Network::Devnet);
+        for (program_id, processor) in BuiltinProcessor::all_on(Network::Devnet) {
+            assert_eq!(devnet.get(program_id), Some(processor));
+        }
+        assert_eq!(devnet.network(), Network::Devnet);
+        // The native programs have the same ids everywhere.
+        let token = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+        assert_eq!(devnet.get(token), mainnet.get(token));
+
+        #[cfg(feature = "token-lending")]
+        {
+            use programs::native_token_lending::{DEVNET_PROGRAM_ADDRESS, PROGRAM_ADDRESS};
+
+            assert_eq!(mainnet.get(PROGRAM_ADDRESS), Some(BuiltinProcessor::TokenLending));
+            assert_eq!(mainnet.get(DEVNET_PROGRAM_ADDRESS), None);
+            assert_eq!(devnet.get(DEVNET_PROGRAM_ADDRESS), Some(BuiltinProcessor::TokenLending));
+            assert_eq!(devnet.get(PROGRAM_ADDRESS), None);
+        }
+    }
+
+    #[test]
+    fn processors_round_trip_through_their_name() {
+        for (_, processor) in BuiltinProcessor::all() {
+            assert_eq!(BuiltinProcessor::from_name(processor.name()), Some(processor));
+        }
+        assert_eq!(BuiltinProcessor::from_name("tokens"), None);
+    }
+
+    #[test]
+    fn register_and_unregister() {
+        let mut registry = ProcessorRegistry::new();
+        registry.register("Fork111111111111111111111111111111111111111", BuiltinProcessor::Token);
+        assert_eq!(registry.get("Fork111111111111111111111111111111111111111"),
+                   Some(BuiltinProcessor::Token));
+        registry.unregister("Fork111111111111111111111111111111111111111");
+        assert_eq!(registry.get("Fork111111111111111111111111111111111111111"), None);
+    }
+
+    #[test]
+    fn closures_shadow_builtins_until_unregistered() {
+        const LENDING: &str = "LendZqTs7gn5CTSJU1jWKhKuVpjJGom45nnwPb2AMTi";
+        let mut registry = ProcessorRegistry::new();
+        registry.register(LENDING, BuiltinProcessor::TokenLending);
+        registry.register_fn(LENDING, |ctx| {
+            Ok(vec![ctx.instruction_set("shadowed", vec![("length", ctx.data.len().to_string())])])
+        });
+        assert_eq!(registry.processor(LENDING).unwrap().name(), "closure");
+        assert_eq!(registry.get(LENDING), None);
+
+        let instruction_set = registry.decode(Instruction {
+            tx_instruction_id: 0,
+            transaction_hash: Arc::from("signature"),
+            program: LENDING.into(),
+            data: vec![1, 2, 3],
+            parent_index: -1,
+            timestamp: 0,
+            network: Network::Mainnet,
+        }, None).unwrap();
+        assert_eq!(instruction_set.function.function_name.as_str(), "shadowed");
+        assert_eq!(instruction_set.properties[0].value, "3");
+
+        assert!(matches!(registry.unregister(LENDING), Some(Processor::Fn(_))));
+        assert_eq!(registry.get(LENDING), Some(BuiltinProcessor::TokenLending));
+        registry.unregister(LENDING);
+        assert!(registry.processor(LENDING).is_none());
+    }
+
+    #[test]
+    fn encoded_instructions_decode_or_say_why_not() {
+        const COUNTER: &str = "Counter111111111111111111111111111111111111";
+        let mut registry = ProcessorRegistry::new();
+        registry.register_fn(COUNTER, |ctx| match ctx.data.first() {
+            Some(_) => Ok(vec![ctx.instruction_set("increment", vec![
+                ("length", ctx.data.len().to_string()),
+                ("accounts", ctx.accounts.len().to_string()),
+            ])]),
+            None => Err(ProcessError::Other("empty instruction".to_string())),
+        });
+        let decode = |program_id: &str, data: &str, context: &EncodedContext| {
+            registry.process_from_encoded(program_id, data, context).map(|decoded| {
+                decoded.iter()
+                    .flat_map(|instruction_set| instruction_set.properties.iter())
+                    .map(|property| property.value.clone())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        // Past 128 bytes, as in json-encoded transactions.
+        let data = bs58::encode(vec![7; 300]).into_string();
+        let context = EncodedContext {
+            accounts: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            ..EncodedContext::default()
+        };
+        assert_eq!(decode(COUNTER, &data, &context), Ok(vec!["300".to_string(), "2".to_string()]));
+        let base64 = EncodedContext { encoding: DataEncoding::Base64, ..EncodedContext::default() };
+        assert_eq!(decode(COUNTER, "AQab", &base64), Ok(vec!["3".to_string(), "0".to_string()]));
+
+        assert_eq!(decode("counter", &data, &context),
+                   Err(DecodeError::InvalidProgramId("counter".to_string())));
+        let base58 = EncodedContext { encoding: DataEncoding::Base58, ..EncodedContext::default() };
+        assert!(matches!(decode(COUNTER, "0OIl", &base58),
+                         Err(DecodeError::InvalidData { encoding: DataEncoding::Base58, .. })));
+        assert_eq!(decode(COUNTER, "", &context), Err(DecodeError::Failed(COUNTER.to_string())));
+        let unknown = Pubkey::new_unique().to_string();
+        assert_eq!(decode(&unknown, &data, &context), Err(DecodeError::Unsupported(unknown)));
+    }
+
+    #[test]
+    fn decoded_functions_record_the_decoder_version() {
+        struct Versioned;
+
+        impl ProgramProcessor for Versioned {
+            fn name(&self) -> &str {
+                "versioned"
+            }
+
+            fn program_ids(&self) -> Vec<String> {
+                vec![]
+            }
+
+            fn decode(&self, instruction: Instruction) -> Option<InstructionSet> {
+                let context = InstructionContext {
+                    program: instruction.program,
+                    data: &instruction.data,
+                    accounts: &[],
+                    tx_instruction_id: instruction.tx_instruction_id,
+                    parent_index: instruction.parent_index,
+                    transaction_hash: &instruction.transaction_hash,
+                    timestamp: instruction.timestamp,
+                    network: instruction.network,
+                    slot: None,
+                };
+                Some(context.instruction_set("call", vec![]))
+            }
+
+            fn decoder_version(&self) -> u32 {
+                3
+            }
+        }
+
+        const CUSTOM: &str = "Versioned1111111111111111111111111111111111";
+        const CLOSURE: &str = "Unversioned111111111111111111111111111111111";
+        let mut registry = ProcessorRegistry::new();
+        registry.register_processor(CUSTOM, Arc::new(Versioned));
+        registry.register_fn(CLOSURE, |ctx| Ok(vec![ctx.instruction_set("call", vec![])]));
+
+        let decoder_version = |program: &str| {
+            registry.decode(Instruction {
+                tx_instruction_id: 0,
+                transaction_hash: Arc::from("signature"),
+                program: program.into(),
+                data: vec![],
+                parent_index: -1,
+                timestamp: 0,
+                network: Network::Mainnet,
+            }, None).unwrap().function.decoder_version
+        };
+        assert_eq!(decoder_version(CUSTOM), 3);
+        assert_eq!(decoder_version(CLOSURE), 0);
+        assert!(BuiltinProcessor::all().into_iter()
+            .all(|(_, processor)| processor.decoder_version() >= 1));
+    }
+
+    #[test]
+    fn schema_covers_every_registered_program() {
+        let mut registry = ProcessorRegistry::new();
+        registry.register("Custom1111111111111111111111111111111111111", BuiltinProcessor::Loader);
+        registry.register_fn("Closure111111111111111111111111111111111111", |_| Ok(vec![]));
+
+        let schema = registry.schema();
+        assert_eq!(schema.len(), 2);
+        assert_eq!(schema["Custom1111111111111111111111111111111111111"].program, "loader");
+        assert_eq!(schema["Closure111111111111111111111111111111111111"].program, "closure");
+        assert!(schema.values().all(|program| program.functions.is_empty()));
+
+        let json: serde_json::Value = serde_json::from_str(&registry.schema_json()).unwrap();
+        assert_eq!(json["Custom1111111111111111111111111111111111111"]["functions"],
+            serde_json::json!([]));
+    }
+
+    #[test]
+    fn registered_processors_follow_the_naming_policy() {
+        use crate::naming::{FunctionName, PropertyKey, RENAMED_FUNCTIONS};
+
+        let schema = ProcessorRegistry::default().schema();
+        let offenders: Vec<_> = schema.values()
+            .flat_map(|program| program.functions.iter().map(move |f| (&program.program, f)))
+            .flat_map(|(program, function)| {
+                let keys = function.keys.iter()
+                    .flat_map(|key| vec![key.key.as_str(), key.parent_key.as_str()])
+                    .filter(|key| !PropertyKey(key).is_canonical())
+                    .map(move |key| format!("{}/{}: key {:?}", program, function.name, key));
+                Some(format!("{}: function {:?}", program, function.name))
+                    .filter(|_| !FunctionName(&function.name).is_canonical())
+                    .into_iter()
+                    .chain(keys)
+            })
+            .collect();
+        assert!(offenders.is_empty(), "{:#?}", offenders);
+
+        for (processor, name, _) in RENAMED_FUNCTIONS {
+            let renamed = BuiltinProcessor::from_name(processor).unwrap();
+            assert!(!renamed.is_enabled() || renamed.describe().get(name).is_some(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn emitted_names_are_canonicalized_with_legacy_aliases() {
+        const COUNTER: &str = "Counter111111111111111111111111111111111111";
+        let builder = || ProcessorRegistry::builder()
+            .empty()
+            .register_fn(COUNTER, |ctx| {
+                Ok(vec![ctx.instruction_set("incrementBy", vec![("stepSize", "1".to_string())])])
+            });
+        let decoded = |registry: &ProcessorRegistry| {
+            let instruction_set = registry
+                .process_from_encoded(COUNTER, "AQ==", &EncodedContext::default()).unwrap()
+                .remove(0);
+            let keys: Vec<_> = instruction_set.properties.iter()
+                .map(|property| (property.key.to_string(), property.value.clone()))
+                .collect();
+            (instruction_set.function.function_name.to_string(), keys)
+        };
+
+        assert_eq!(decoded(&builder().build()), ("increment-by".to_string(), vec![
+            ("step_size".to_string(), "1".to_string()),
+        ]));
+        let aliased = builder().legacy_names(true).build();
+        assert_eq!(decoded(&aliased), ("increment-by".to_string(), vec![
+            ("step_size".to_string(), "1".to_string()),
+            ("stepSize".to_string(), "1".to_string()),
+            ("legacy_function_name".to_string(), "incrementBy".to_string()),
+        ]));
+    }
+
+    #[test]
+    fn sampling_is_deterministic() {
+        let signatures: Vec<String> = (0..10_000).map(|n| format!("signature-{}", n)).collect();
+        let sampled = |rate| signatures.iter().filter(|s| sampled_in(s, rate)).count();
+
+        assert_eq!(sampled(0.0), 0);
+        assert_eq!(sampled(1.0), signatures.len());
+        let one_percent = sampled(0.01);
+        assert!((50..150).contains(&one_percent), "{}", one_percent);
+        assert_eq!(sampled(0.01), one_percent);
+        // Anything sampled in at 1% is at 10%.
+        assert!(signatures.iter().filter(|s| sampled_in(s, 0.01)).all(|s| sampled_in(s, 0.1)));
+    }
+
+    #[test]
+    #[cfg(feature = "runtime")]
+    fn slow_processors_are_degraded_until_restored() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        use crate::watchdog::WatchdogStats;
+
+        const PROGRAM: &str = "S1ow111111111111111111111111111111111111111";
+        let (slow, calls) = (Arc::new(AtomicBool::new(true)), Arc::new(AtomicUsize::new(0)));
+        let registry = {
+            let (slow, calls) = (slow.clone(), calls.clone());
+            ProcessorRegistry::builder()
+                .empty()
+                .watchdog(WatchdogConfig {
+                    soft_budget: Duration::from_millis(1),
+                    hard_budget: Duration::from_millis(10),
+                    max_violations: 2,
+                })
+                .register_fn(PROGRAM, move |ctx| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    if slow.load(Ordering::SeqCst) {
+                        std::thread::sleep(Duration::from_millis(30));
+                    }
+                    Ok(vec![ctx.instruction_set("call", vec![])])
+                })
+                .build()
+        };
+        let decode = || {
+            let mut decoded = Vec::new();
+            let instruction = Instruction {
+                tx_instruction_id: 0,
+                transaction_hash: Arc::from("slow"),
+                program: PROGRAM.into(),
+                data: vec![7],
+                parent_index: -1,
+                timestamp: 0,
+                network: Network::Mainnet,
+            };
+            registry.decode_with_accounts(instruction, &[], None, &mut decoded)
+        };
+        let stats = || registry.watchdog().stats(Symbol::intern(PROGRAM)).unwrap();
+
+        // A violation, then a dispatch within the budget starting over.
+        assert_eq!(decode(), DecodeStatus::Decoded);
+        assert_eq!(stats().consecutive_violations, 1);
+        slow.store(false, Ordering::SeqCst);
+        assert_eq!(decode(), DecodeStatus::Decoded);
+        assert_eq!(stats().consecutive_violations, 0);
+
+        // Two in a row degrade the program, whose instructions aren't dispatched anymore.
+        slow.store(true, Ordering::SeqCst);
+        assert_eq!(decode(), DecodeStatus::Decoded);
+        assert_eq!(decode(), DecodeStatus::Decoded);
+        assert!(stats().degraded);
+        assert_eq!(decode(), DecodeStatus::Degraded);
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert_eq!(registry.watchdog().degraded(), vec![Symbol::intern(PROGRAM)]);
+
+        // Until restored.
+        slow.store(false, Ordering::SeqCst);
+        assert!(registry.restore(PROGRAM));
+        assert!(!registry.restore(PROGRAM));
+        assert_eq!(decode(), DecodeStatus::Decoded);
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+        assert_eq!(stats(), WatchdogStats {
+            dispatches: 5,
+            soft_violations: 3,
+            hard_violations: 3,
+            consecutive_violations: 0,
+            degraded: false,
+        });
+    }
+
+    #[test]
+    fn sampled_out_instructions_are_not_dispatched() {
+        const PROGRAM: &str = "Samp1ed111111111111111111111111111111111111";
+        let mut registry = ProcessorRegistry::new();
+        registry.register_fn(PROGRAM, |ctx| Ok(vec![ctx.instruction_set("call", vec![])]));
+        registry.sample(PROGRAM, 0.5);
+        assert_eq!(registry.sample_rate(PROGRAM), 0.5);
+
+        let decoded = (0..1000)
+            .filter(|n| {
+                let signature = format!("signature-{}", n);
+                let decoded = registry.decode(Instruction {
+                    tx_instruction_id: 0,
+                    transaction_hash: Arc::from(signature.as_str()),
+                    program: PROGRAM.into(),
+                    data: vec![],
+                    parent_index: -1,
+                    timestamp: 0,
+                    network: Network::Mainnet,
+                }, None).is_some();
+                assert_eq!(decoded, sampled_in(&signature, 0.5));
+                decoded
+            })
+            .count();
+        assert!((400..600).contains(&decoded), "{}", decoded);
+    }
+
+    #[test]
+    fn keys_are_namespaced_by_program() {
+        const COUNTER: &str = "Counter111111111111111111111111111111111111";
+        let builder = || ProcessorRegistry::builder()
+            .empty()
+            .key_namespacing(true)
+            .register_fn(COUNTER, |ctx| {
+                let mut instruction_set = ctx.instruction_set("increment", vec![
+                    ("counter", "1".to_string()),
+                    ("owner", Pubkey::default().to_string()),
+                ]);
+                instruction_set.properties[1].parent_key = "counter".into();
+                Ok(vec![instruction_set])
+            });
+        let keys = |registry: &ProcessorRegistry| {
+            registry.process_from_encoded(COUNTER, "AQ==", &EncodedContext::default()).unwrap()
+                .remove(0)
+                .properties
+                .iter()
+                .map(|property| (property.key.to_string(), property.parent_key.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        // Closures have no namespace of their own.
+        assert_eq!(keys(&builder().build()), vec![
+            ("counter".to_string(), "".to_string()),
+            ("owner".to_string(), "counter".to_string()),
+        ]);
+        assert_eq!(keys(&builder().namespace(COUNTER, "counter").build()), vec![
+            ("counter.counter".to_string(), "".to_string()),
+            ("counter.owner".to_string(), "counter.counter".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn namespaced_keys_of_builtins_never_collide() {
+        let namespaces: std::collections::HashSet<_> = BuiltinProcessor::NAMES.iter()
+            .map(|(_, processor)| processor.namespace())
+            .collect();
+        assert_eq!(namespaces.len(), BuiltinProcessor::NAMES.len());
+        assert!(namespaces.iter().all(|namespace| !namespace.contains('.')));
+
+        let mut registry = ProcessorRegistry::default();
+        registry.set_key_namespacing(true);
+        let schema = registry.schema();
+        let collisions = crate::schema::key_collisions(schema.values());
+        assert!(collisions.is_empty(), "{:#?}", collisions);
+        for (program_id, program) in &schema {
+            let prefix = format!("{}.", registry.namespace(program_id).unwrap());
+            assert!(program.functions.iter()
+                .flat_map(|function| function.keys.iter())
+                .all(|key| key.key.starts_with(&prefix)
+                    && (key.parent_key.is_empty() || key.parent_key.starts_with(&prefix))),
+                "{}", program.program);
+        }
+    }
+}
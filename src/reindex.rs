@@ -0,0 +1,197 @@
+//! Re-indexes the instructions a processor decoded before its latest version (see
+//! [`ProgramProcessor::decoder_version`]), so that the rows of a program don't mix what different
+//! versions emitted.
+//!
+//! The transactions with stale rows are decoded again out of the raw data the sink kept of their
+//! instructions (see [`RawCapture`]) when it kept it for every stale one, and fetched again from
+//! RPC otherwise. Out of raw data, instructions are decoded on their own, as with
+//! [`reprocess_raw`]; fetched again, whole transactions are written anew.
+//!
+//! Transactions are re-indexed in slot order, a batch at a time, checkpointing after each one, so
+//! that a re-index of a busy program can be interrupted and resumed.
+//!
+//! [`ProgramProcessor::decoder_version`]: crate::registry::ProgramProcessor::decoder_version
+//! [`RawCapture`]: crate::registry::RawCapture
+//! [`reprocess_raw`]: crate::reprocess::reprocess_raw
+
+use std::sync::Arc;
+
+use solana_sdk::commitment_config::CommitmentConfig;
+use tracing::{info, warn};
+
+use crate::checkpoint::{Checkpoint, Checkpointing};
+use crate::ingest::pool::RpcPool;
+use crate::ingest::{fetch_transaction, IngestError};
+use crate::interner::Symbol;
+use crate::registry::ProcessorRegistry;
+use crate::reprocess::RawInstruction;
+use crate::sinks::{self, Sink};
+use crate::transaction::decode_transaction;
+
+/// Which transactions with stale rows a sink returns.
+#[derive(Clone, Debug)]
+pub struct StaleFilter {
+    // The program whose instructions are checked.
+    pub program: Symbol,
+    // The version of its processor: instructions decoded by an older one are stale.
+    pub decoder_version: u32,
+    // The slot and signature of the last transaction re-indexed, only those after it are returned.
+    pub after: Option<(u64, String)>,
+    // How many transactions are returned at most.
+    pub limit: usize,
+}
+
+/// A transaction with stale rows.
+#[derive(Clone, Debug)]
+pub struct StaleTransaction {
+    pub slot: u64,
+    pub transaction_hash: String,
+    // The raw data of its stale instructions, when the sink kept it for every one of them.
+    pub raw_instructions: Option<Vec<RawInstruction>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReindexConfig {
+    // How many transactions are re-indexed (and checkpointed) at a time.
+    pub batch_size: usize,
+    // The commitment transactions are fetched again at.
+    pub commitment: CommitmentConfig,
+}
+
+impl Default for ReindexConfig {
+    fn default() -> Self {
+        ReindexConfig {
+            batch_size: 100,
+            commitment: CommitmentConfig::finalized(),
+        }
+    }
+}
+
+/// What came of a re-index.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReindexSummary {
+    // The transactions with stale rows.
+    pub transactions: usize,
+    // Those decoded again out of raw data.
+    pub from_raw_data: usize,
+    // Those fetched again from RPC.
+    pub refetched: usize,
+    // Those the node no longer has, left as they were.
+    pub missing: usize,
+    // The instructions that no longer decode out of their raw data, left as they were.
+    pub failed: usize,
+}
+
+/// The pipeline a re-index of `program` up to `decoder_version` checkpoints under, next to the
+/// pipeline of `checkpoint`. Bumping the version starts the re-index over.
+fn reindex_checkpointing(
+    checkpoint: &Checkpointing,
+    program: &str,
+    decoder_version: u32,
+) -> Checkpointing {
+    let pipeline = format!("{}-reindex-{}-v{}", checkpoint.pipeline, program, decoder_version);
+    Checkpointing::new(checkpoint.store.clone(), &pipeline)
+}
+
+/// Re-indexes every transaction whose instructions of `program` were decoded by an older version
+/// of the processor `registry` has for it, replacing their rows in `sink`.
+///
+/// With `checkpoint`, picks up after the last batch a previous re-index of the same program and
+/// version got through.
+pub async fn reindex<S: Sink + ?Sized>(
+    sink: &mut S,
+    registry: &ProcessorRegistry,
+    rpc: &Arc<RpcPool>,
+    program: &str,
+    config: &ReindexConfig,
+    checkpoint: Option<&Checkpointing>,
+) -> Result<ReindexSummary, IngestError> {
+    let decoder_version = registry.processor(program)
+        .ok_or_else(|| {
+            IngestError::Pipeline(format!("No processor is registered for {}", program))
+        })?
+        .decoder_version();
+    let checkpoint = checkpoint
+        .map(|checkpoint| reindex_checkpointing(checkpoint, program, decoder_version));
+
+    let mut filter = StaleFilter {
+        program: Symbol::intern(program),
+        decoder_version,
+        after: None,
+        limit: config.batch_size.max(1),
+    };
+    if let Some(checkpoint) = &checkpoint {
+        if let Some(saved) = checkpoint.load().await? {
+            filter.after = saved.last_slot.zip(saved.last_signature);
+        }
+    }
+
+    let mut summary = ReindexSummary::default();
+    loop {
+        let stale = sink.stale_transactions(&filter).await?;
+        let last = match stale.last() {
+            Some(last) => (last.slot, last.transaction_hash.clone()),
+            None => break,
+        };
+
+        let mut instruction_sets = Vec::new();
+        let mut records = Vec::new();
+        for transaction in &stale {
+            match &transaction.raw_instructions {
+                Some(raw_instructions) => {
+                    for raw_instruction in raw_instructions {
+                        let decoded = raw_instruction.to_instruction().ok()
+                            .and_then(|instruction| registry.decode(instruction, None));
+                        match decoded {
+                            Some(mut instruction_set) => {
+                                instruction_set.raw_data = Some(raw_instruction.raw_data.clone());
+                                instruction_sets.push(instruction_set);
+                            }
+                            None => summary.failed += 1,
+                        }
+                    }
+                    summary.from_raw_data += 1;
+                }
+                None => {
+                    match fetch_transaction(rpc, &transaction.transaction_hash, config.commitment)
+                        .await?
+                    {
+                        Some(raw) => {
+                            let mut record = decode_transaction(registry, &raw);
+                            record.finalized = config.commitment.is_finalized();
+                            records.push(record);
+                            summary.refetched += 1;
+                        }
+                        None => {
+                            warn!("[spi-wrapper/reindex] Transaction {} is no longer available, \
+                                its rows are left as they were.", transaction.transaction_hash);
+                            summary.missing += 1;
+                        }
+                    }
+                }
+            }
+        }
+        summary.transactions += stale.len();
+
+        if !instruction_sets.is_empty() {
+            sink.write_reprocessed(&instruction_sets).await?;
+        }
+        if !records.is_empty() {
+            sinks::write_transactions(sink, &records).await?;
+        }
+        sinks::flush(sink).await?;
+        // Everything up to `last` is written, so a resumed re-index starts after it.
+        if let Some(checkpoint) = &checkpoint {
+            checkpoint.save(&Checkpoint::new(Some(last.0), Some(last.1.clone()))).await?;
+        }
+        info!("[spi-wrapper/reindex] Re-indexed {} transactions of {} up to slot {}.",
+            summary.transactions, program, last.0);
+
+        if stale.len() < filter.limit {
+            break;
+        }
+        filter.after = Some(last);
+    }
+
+    Ok(summary)
+}
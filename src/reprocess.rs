@@ -0,0 +1,111 @@
+//! Decodes instructions again out of the raw data sinks kept of them (see [`RawCapture`]), so that
+//! a decoder fix reaches the instructions indexed before it without fetching their transactions
+//! again.
+//!
+//! Instructions are decoded on their own: closure processors don't get the accounts they were
+//! invoked with, secp256k1 doesn't see the other instructions of the transaction and no UI amount
+//! is added.
+//!
+//! [`RawCapture`]: crate::registry::RawCapture
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use tracing::{info, warn};
+
+use crate::interner::Symbol;
+use crate::network::Network;
+use crate::registry::ProcessorRegistry;
+use crate::sinks::{Sink, SinkError};
+use crate::{Instruction, InstructionSet, RawData};
+
+/// Which stored instructions are decoded again.
+#[derive(Clone, Debug)]
+pub struct RawFilter {
+    // The program invoked by the instructions.
+    pub program: Symbol,
+    // The block times of their transactions, the end excluded.
+    pub timestamps: Range<i64>,
+}
+
+/// The raw data a sink kept of an instruction.
+#[derive(Clone, Debug)]
+pub struct RawInstruction {
+    pub network: Network,
+    pub transaction_hash: Arc<str>,
+    pub tx_instruction_id: i16,
+    pub parent_index: i16,
+    pub program: Symbol,
+    pub timestamp: i64,
+    pub raw_data: RawData,
+    // Whether the instruction was decoded when it was written.
+    pub decoded: bool,
+}
+
+impl RawInstruction {
+    pub(crate) fn to_instruction(&self) -> Result<Instruction, base64::DecodeError> {
+        Ok(Instruction {
+            tx_instruction_id: self.tx_instruction_id,
+            transaction_hash: self.transaction_hash.clone(),
+            program: self.program,
+            data: self.raw_data.bytes()?,
+            parent_index: self.parent_index,
+            timestamp: self.timestamp,
+            network: self.network,
+        })
+    }
+}
+
+/// What came of decoding stored instructions again.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReprocessSummary {
+    // The instructions read from the sink.
+    pub read: usize,
+    // The instructions decoded, whose instruction sets superseded the previous ones.
+    pub decoded: usize,
+    // The instructions still failing to decode, left as they were.
+    pub failed: usize,
+}
+
+/// Decodes the instructions `filter` selects out of the raw data `sink` kept of them, and writes
+/// the instruction sets they decode to in place of the previous ones, which the sink marks as
+/// superseded.
+pub async fn reprocess_raw<S: Sink + ?Sized>(
+    sink: &mut S,
+    registry: &ProcessorRegistry,
+    filter: &RawFilter,
+) -> Result<ReprocessSummary, SinkError> {
+    let raw_instructions = sink.raw_instructions(filter).await?;
+    let mut summary = ReprocessSummary { read: raw_instructions.len(), ..Default::default() };
+
+    let mut instruction_sets: Vec<InstructionSet> = Vec::new();
+    for raw_instruction in &raw_instructions {
+        let instruction = match raw_instruction.to_instruction() {
+            Ok(instruction) => instruction,
+            Err(err) => {
+                warn!("[spi-wrapper/reprocess] Invalid raw data for instruction {}/{} of {}: {}",
+                    raw_instruction.parent_index, raw_instruction.tx_instruction_id,
+                    raw_instruction.transaction_hash, err);
+                summary.failed += 1;
+                continue;
+            }
+        };
+
+        match registry.decode(instruction, None) {
+            Some(mut instruction_set) => {
+                instruction_set.raw_data = Some(raw_instruction.raw_data.clone());
+                instruction_sets.push(instruction_set);
+                summary.decoded += 1;
+            }
+            None => summary.failed += 1,
+        }
+    }
+
+    if !instruction_sets.is_empty() {
+        sink.write_reprocessed(&instruction_sets).await?;
+    }
+    info!("[spi-wrapper/reprocess] Decoded {} of the {} stored instructions of {} again.",
+        summary.decoded, summary.read, filter.program);
+
+    Ok(summary)
+}
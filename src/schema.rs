@@ -0,0 +1,193 @@
+//! Describes what the processors emit: the function names of the programs and the properties
+//! each of their functions carries, for consumers (e.g. dashboards) to know about them without
+//! reading the decoders.
+//!
+//! The schemas only cover what the processors decode: the properties added on top of them by
+//! the pipeline (UI amounts, the call tree) aren't part of them.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::PropertyType;
+
+/// The placeholder standing for an index in the keys of properties repeated for each item of a
+/// list, e.g. `config_keys/{index}/pubkey`.
+pub const INDEX_PLACEHOLDER: &str = "{index}";
+
+/// What a processor emits.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProgramSchema {
+    // The name of the processor.
+    pub program: String,
+    pub functions: Vec<FunctionSchema>,
+}
+
+/// The properties a function of a program carries.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FunctionSchema {
+    pub name: String,
+    pub keys: Vec<KeySchema>,
+}
+
+/// A property a function carries. Not every property is present in every instruction set, e.g.
+/// optional authorities.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeySchema {
+    pub key: String,
+    pub parent_key: String,
+    pub value_type: PropertyType,
+    pub description: String,
+}
+
+impl ProgramSchema {
+    /// The schema of a processor emitting nothing, yet.
+    pub fn new(program: &str) -> Self {
+        ProgramSchema { program: program.to_string(), functions: Vec::new() }
+    }
+
+    /// Adds a function carrying `keys`, given as `(key, parent_key, value_type, description)`.
+    pub fn function(mut self, name: &str, keys: &[(&str, &str, PropertyType, &str)]) -> Self {
+        self.functions.push(FunctionSchema {
+            name: name.to_string(),
+            keys: keys.iter()
+                .map(|(key, parent_key, value_type, description)| KeySchema {
+                    key: key.to_string(),
+                    parent_key: parent_key.to_string(),
+                    value_type: *value_type,
+                    description: description.to_string(),
+                })
+                .collect(),
+        });
+
+        self
+    }
+
+    /// The function going by `name`.
+    pub fn get(&self, name: &str) -> Option<&FunctionSchema> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    /// How the property `key` of `parent_key`, emitted by the function `function_name`, is
+    /// declared, or `None` when it isn't.
+    pub fn key(&self, function_name: &str, key: &str, parent_key: &str) -> Option<&KeySchema> {
+        self.get(function_name)?
+            .keys
+            .iter()
+            .find(|declared| declared.matches(key, parent_key))
+    }
+}
+
+impl KeySchema {
+    /// Whether the property `key` of `parent_key` is the one declared, indexes standing for the
+    /// placeholders of the declaration.
+    pub fn matches(&self, key: &str, parent_key: &str) -> bool {
+        matches_pattern(&self.key, key) && matches_pattern(&self.parent_key, parent_key)
+    }
+}
+
+/// A key (of a parent key) several processors declare with different value types, which
+/// consumers reading keys regardless of their program can't tell apart.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyCollision {
+    pub key: String,
+    pub parent_key: String,
+    // The processor, function and value type of every declaration of the key.
+    pub declarations: Vec<(String, String, PropertyType)>,
+}
+
+/// The keys declared with different value types by several of the processors `schemas` describe,
+/// by key and parent key. Schemas can be listed several times, e.g. for every program a processor
+/// is registered for.
+pub fn key_collisions<'a>(
+    schemas: impl IntoIterator<Item = &'a ProgramSchema>,
+) -> Vec<KeyCollision> {
+    let mut declarations: BTreeMap<(&str, &str), Vec<(String, String, PropertyType)>> =
+        BTreeMap::new();
+    for schema in schemas {
+        for function in &schema.functions {
+            for key in &function.keys {
+                let declaration = (schema.program.clone(), function.name.clone(), key.value_type);
+                let declared = declarations.entry((key.key.as_str(), key.parent_key.as_str()))
+                    .or_default();
+                if !declared.contains(&declaration) {
+                    declared.push(declaration);
+                }
+            }
+        }
+    }
+
+    declarations.into_iter()
+        .filter(|(_, declared)| {
+            declared.iter().any(|(program, _, value_type)| {
+                declared.iter().any(|(other, _, other_type)| {
+                    other != program && other_type != value_type
+                })
+            })
+        })
+        .map(|((key, parent_key), declarations)| KeyCollision {
+            key: key.to_string(),
+            parent_key: parent_key.to_string(),
+            declarations,
+        })
+        .collect()
+}
+
+fn matches_pattern(pattern: &str, value: &str) -> bool {
+    match pattern.find(INDEX_PLACEHOLDER) {
+        None => pattern == value,
+        Some(start) => {
+            let (prefix, rest) = (&pattern[..start], &pattern[start + INDEX_PLACEHOLDER.len()..]);
+            let value = match value.strip_prefix(prefix) {
+                Some(value) => value,
+                None => return false,
+            };
+            let digits = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+
+            digits > 0 && matches_pattern(rest, &value[digits..])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_match_indexes() {
+        let schema = ProgramSchema::new("config").function("store", &[
+            ("config_keys/{index}/pubkey", "config_keys/{index}", PropertyType::Pubkey, ""),
+        ]);
+
+        assert!(schema.key("store", "config_keys/0/pubkey", "config_keys/0").is_some());
+        assert!(schema.key("store", "config_keys/12/pubkey", "config_keys/12").is_some());
+        assert!(schema.key("store", "config_keys//pubkey", "config_keys/").is_none());
+        assert!(schema.key("store", "config_keys/a/pubkey", "config_keys/a").is_none());
+        assert!(schema.key("store", "config_keys/0/signer", "config_keys/0").is_none());
+        assert!(schema.key("initialize", "config_keys/0/pubkey", "config_keys/0").is_none());
+    }
+
+    #[test]
+    fn keys_collide_across_processors_only() {
+        let lending = ProgramSchema::new("lending")
+            .function("deposit", &[("amount", "", PropertyType::U64, "")])
+            .function("refresh", &[("amount", "", PropertyType::Decimal, "")]);
+        let swap = ProgramSchema::new("swap")
+            .function("swap", &[("amount", "", PropertyType::U64, ""),
+                                ("amount", "fees", PropertyType::Decimal, "")]);
+        // The functions of one processor are its own business.
+        assert!(key_collisions(vec![&lending, &lending]).is_empty());
+        assert!(key_collisions(vec![&swap, &swap]).is_empty());
+
+        let collisions = key_collisions(vec![&lending, &swap, &lending]);
+        assert_eq!(collisions, vec![KeyCollision {
+            key: "amount".to_string(),
+            parent_key: "".to_string(),
+            declarations: vec![
+                ("lending".to_string(), "deposit".to_string(), PropertyType::U64),
+                ("lending".to_string(), "refresh".to_string(), PropertyType::Decimal),
+                ("swap".to_string(), "swap".to_string(), PropertyType::U64),
+            ],
+        }]);
+    }
+}
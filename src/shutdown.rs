@@ -0,0 +1,122 @@
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::health::IngestProgress;
+
+/// Errors that may be returned while shutting down.
+#[derive(Debug, Error)]
+pub enum ShutdownError {
+    #[error("Shutdown didn't complete within {deadline:?}, slots {unwritten:?} weren't written \
+    and the sink wasn't flushed")]
+    DeadlineExceeded { deadline: Duration, unwritten: Vec<u64> },
+}
+
+/// Coordinates a graceful shutdown: once triggered (by SIGINT/SIGTERM or by cancelling its
+/// token), the ingestion source stops, whatever is in flight is drained, the sinks are flushed
+/// and the final checkpoint saved, within a hard deadline.
+///
+/// The work itself is expected to stop on its own once the token is cancelled, like
+/// `Pipeline::run` does with `Pipeline::with_shutdown_token`.
+pub struct ShutdownCoordinator {
+    token: CancellationToken,
+    // How long draining may take once shutdown has been triggered.
+    deadline: Duration,
+}
+
+impl Default for ShutdownCoordinator {
+    fn default() -> Self {
+        ShutdownCoordinator::new(Duration::from_secs(30))
+    }
+}
+
+impl ShutdownCoordinator {
+    pub fn new(deadline: Duration) -> Self {
+        ShutdownCoordinator {
+            token: CancellationToken::new(),
+            deadline,
+        }
+    }
+
+    /// The token cancelled when shutdown is triggered.
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    pub fn trigger(&self) {
+        self.token.cancel();
+    }
+
+    /// Triggers shutdown on SIGINT or (on Unix) SIGTERM.
+    pub fn listen_for_signals(&self) -> io::Result<()> {
+        #[cfg(unix)]
+        let mut terminate = tokio::signal::unix::signal(
+            tokio::signal::unix::SignalKind::terminate())?;
+        let token = self.token.clone();
+
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            let terminated = terminate.recv();
+            #[cfg(not(unix))]
+            let terminated = futures::future::pending::<Option<()>>();
+
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminated => {}
+                _ = token.cancelled() => return,
+            }
+            info!("[spi-wrapper/shutdown] Shutting down, draining in-flight blocks.");
+            token.cancel();
+        });
+
+        Ok(())
+    }
+
+    /// Runs `work` until it completes. Once shutdown is triggered, it's given until the deadline
+    /// to drain; past that it's dropped and the slots `progress` still had in flight are
+    /// reported.
+    pub async fn run<F: Future>(
+        &self,
+        work: F,
+        progress: &IngestProgress,
+    ) -> Result<F::Output, ShutdownError> {
+        tokio::pin!(work);
+        tokio::select! {
+            output = &mut work => return Ok(output),
+            _ = self.token.cancelled() => {}
+        }
+
+        match tokio::time::timeout(self.deadline, work).await {
+            Ok(output) => Ok(output),
+            Err(_) => {
+                let unwritten = progress.unwritten_slots();
+                warn!("[spi-wrapper/shutdown] Aborting after {:?}: slots {:?} weren't written \
+                and the sink wasn't flushed.", self.deadline, unwritten);
+                Err(ShutdownError::DeadlineExceeded { deadline: self.deadline, unwritten })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn aborts_past_the_deadline() {
+        let coordinator = ShutdownCoordinator::new(Duration::from_millis(50));
+        let progress = IngestProgress::default();
+        progress.accept(7);
+        coordinator.trigger();
+
+        let result = coordinator.run(futures::future::pending::<()>(), &progress).await;
+        match result {
+            Err(ShutdownError::DeadlineExceeded { unwritten, .. }) => assert_eq!(unwritten, [7]),
+            Ok(()) => panic!("pending work completed"),
+        }
+    }
+}
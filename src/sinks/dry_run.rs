@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::block::BlockRecord;
+use crate::sinks::{Sink, SinkError};
+use crate::transaction::TransactionRecord;
+use crate::PropertyType;
+
+// How many decode failures are kept as samples.
+const FAILURE_SAMPLES: usize = 20;
+
+/// What a dry run would have written.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DryRunSummary {
+    pub blocks: u64,
+    pub transactions: u64,
+    pub instructions: u64,
+    // Instructions decoded, by program and function.
+    pub functions: BTreeMap<String, BTreeMap<String, u64>>,
+    // Instructions that failed to decode, by program and discriminant.
+    pub failures: BTreeMap<String, BTreeMap<String, u64>>,
+    // The first failures, to be looked into with `decode-tx`.
+    pub failure_samples: Vec<FailureSample>,
+    // The shortest and longest value of every property, along with its type, by program and
+    // property key.
+    pub property_lengths: BTreeMap<String, BTreeMap<String, LengthRange>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FailureSample {
+    pub signature: String,
+    pub slot: u64,
+    pub tx_instruction_id: i16,
+    pub parent_index: i16,
+    pub program: String,
+    pub discriminant: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct LengthRange {
+    pub min: usize,
+    pub max: usize,
+    // The type of the latest value.
+    pub value_type: PropertyType,
+}
+
+impl DryRunSummary {
+    fn record(&mut self, transaction: &TransactionRecord) {
+        self.transactions += 1;
+        for instruction_set in &transaction.instruction_sets {
+            let program = instruction_set.function.program.as_str();
+            self.instructions += 1;
+            *self.functions.entry(program.to_string()).or_default()
+                .entry(instruction_set.function.function_name.as_str().to_string())
+                .or_default() += 1;
+
+            let lengths = self.property_lengths.entry(program.to_string()).or_default();
+            for property in &instruction_set.properties {
+                let length = property.value.len();
+                lengths.entry(property.key.as_str().to_string())
+                    .and_modify(|range| {
+                        range.min = range.min.min(length);
+                        range.max = range.max.max(length);
+                        range.value_type = property.value_type;
+                    })
+                    .or_insert(LengthRange {
+                        min: length,
+                        max: length,
+                        value_type: property.value_type,
+                    });
+            }
+        }
+
+        for failure in &transaction.decode_failures {
+            let discriminant = failure.discriminant.map_or("none".to_string(), |d| d.to_string());
+            *self.failures.entry(failure.program.as_str().to_string()).or_default()
+                .entry(discriminant)
+                .or_default() += 1;
+            if self.failure_samples.len() < FAILURE_SAMPLES {
+                self.failure_samples.push(FailureSample {
+                    signature: transaction.transaction_hash.clone(),
+                    slot: transaction.slot,
+                    tx_instruction_id: failure.tx_instruction_id,
+                    parent_index: failure.parent_index,
+                    program: failure.program.as_str().to_string(),
+                    discriminant: failure.discriminant,
+                });
+            }
+        }
+    }
+
+    pub fn failure_count(&self) -> u64 {
+        self.failures.values().flat_map(BTreeMap::values).sum()
+    }
+}
+
+impl fmt::Display for DryRunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} blocks, {} transactions, {} instructions decoded, {} failed",
+            self.blocks, self.transactions, self.instructions, self.failure_count())?;
+
+        for (program, functions) in &self.functions {
+            writeln!(f, "\n{}", program)?;
+            for (function, count) in functions {
+                writeln!(f, "  {:<40} {:>10}", function, count)?;
+            }
+            for (discriminant, count) in self.failures.get(program).into_iter().flatten() {
+                writeln!(f, "  {:<40} {:>10}", format!("(failed, discriminant {})", discriminant),
+                    count)?;
+            }
+            for (key, range) in self.property_lengths.get(program).into_iter().flatten() {
+                writeln!(f, "  {:<40} {:>4}..{} {}", format!("[{}]", key), range.min, range.max,
+                    range.value_type.as_str())?;
+            }
+        }
+        // Programs nothing was decoded for.
+        for (program, failures) in &self.failures {
+            if self.functions.contains_key(program) {
+                continue;
+            }
+            writeln!(f, "\n{}", program)?;
+            for (discriminant, count) in failures {
+                writeln!(f, "  {:<40} {:>10}", format!("(failed, discriminant {})", discriminant),
+                    count)?;
+            }
+        }
+
+        if !self.failure_samples.is_empty() {
+            writeln!(f, "\nFailures:")?;
+            for sample in &self.failure_samples {
+                writeln!(f, "  {} (slot {}) instruction {}/{} of {}", sample.signature,
+                    sample.slot, sample.parent_index, sample.tx_instruction_id, sample.program)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes nothing, accounting for what it's handed instead. Swapped for the configured sink, it
+/// shows what an indexer would emit (and what it would fail to decode) without touching the
+/// storage.
+#[derive(Clone, Default)]
+pub struct DryRunSink {
+    summary: Arc<Mutex<DryRunSummary>>,
+}
+
+impl DryRunSink {
+    pub fn new() -> Self {
+        DryRunSink::default()
+    }
+
+    /// What was accounted for so far, shared with clones of the sink.
+    pub fn summary(&self) -> DryRunSummary {
+        self.summary.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Sink for DryRunSink {
+    async fn write_block_header(&mut self, _block: &BlockRecord) -> Result<(), SinkError> {
+        self.summary.lock().unwrap().blocks += 1;
+        Ok(())
+    }
+
+    async fn write_transactions(
+        &mut self,
+        transactions: &[TransactionRecord],
+    ) -> Result<(), SinkError> {
+        let mut summary = self.summary.lock().unwrap();
+        for transaction in transactions {
+            summary.record(transaction);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::call_tree::CallTree;
+    use crate::transaction::DecodeFailure;
+    use crate::{InstructionFunction, InstructionProperty, InstructionSet, Network, Symbol};
+
+    fn transfer(amount: &str) -> InstructionSet {
+        let transaction_hash: Arc<str> = Arc::from("signature");
+        InstructionSet {
+            function: InstructionFunction {
+                tx_instruction_id: 0,
+                transaction_hash: transaction_hash.clone(),
+                parent_index: -1,
+                program: Symbol::intern("token"),
+                function_name: Symbol::intern("transfer"),
+                timestamp: 0,
+                network: Network::Mainnet,
+                decoder_version: 0,
+            },
+            properties: smallvec::smallvec![InstructionProperty {
+                tx_instruction_id: 0,
+                transaction_hash,
+                parent_index: -1,
+                key: Symbol::intern("amount"),
+                value: amount.to_string(),
+                value_type: PropertyType::U64,
+                parent_key: Symbol::intern(""),
+                timestamp: 0,
+            }],
+            raw_data: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn accounts_for_what_it_is_handed() {
+        let mut sink = DryRunSink::new();
+        let transaction = TransactionRecord {
+            transaction_hash: "signature".to_string(),
+            slot: 42,
+            fee: 5000,
+            base_fee_lamports: None,
+            priority_fee_lamports: None,
+            compute_unit_price_micro_lamports: None,
+            succeeded: true,
+            timestamp: 0,
+            timestamp_estimated: false,
+            finalized: false,
+            network: Network::Mainnet,
+            instruction_sets: vec![transfer("1"), transfer("1000")],
+            decode_failures: vec![DecodeFailure {
+                tx_instruction_id: 1,
+                parent_index: -1,
+                program: Symbol::intern("token"),
+                discriminant: Some(250),
+                raw_data: None,
+            }],
+            call_tree: CallTree::default(),
+            signer_activity: Vec::new(),
+            account_lifecycle: Vec::new(),
+            confirmed: true,
+            simulation: None,
+        };
+        sink.write_transactions(&[transaction]).await.unwrap();
+
+        let summary = sink.summary();
+        assert_eq!(summary.transactions, 1);
+        assert_eq!(summary.functions["token"]["transfer"], 2);
+        assert_eq!(summary.failures["token"]["250"], 1);
+        assert_eq!(summary.failure_samples[0].slot, 42);
+        let amount = summary.property_lengths["token"]["amount"];
+        assert_eq!((amount.min, amount.max, amount.value_type), (1, 4, PropertyType::U64));
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+
+use crate::account::AccountRecord;
+use crate::aggregate::WindowAggregate;
+use crate::block::BlockRecord;
+use crate::checkpoint::Checkpoint;
+use crate::ingest::timestamps::TimestampUpdate;
+use crate::interner::Symbol;
+use crate::lending::positions::PositionSnapshot;
+use crate::program_versions::ProgramUpgrade;
+use crate::reindex::{StaleFilter, StaleTransaction};
+use crate::reprocess::{RawFilter, RawInstruction};
+use crate::sinks::{ReadFilter, RowCounts, Sink, SinkError};
+use crate::transaction::TransactionRecord;
+use crate::InstructionSet;
+
+/// Which transactions are worth keeping.
+#[derive(Clone, Debug)]
+pub struct TransactionFilter {
+    // Whether transactions that failed on-chain are kept.
+    pub include_failed: bool,
+    // Only transactions with a decoded instruction of one of these programs are kept. Every
+    // transaction is kept when empty.
+    pub programs: HashSet<Symbol>,
+}
+
+impl Default for TransactionFilter {
+    fn default() -> Self {
+        TransactionFilter {
+            include_failed: true,
+            programs: HashSet::new(),
+        }
+    }
+}
+
+impl TransactionFilter {
+    pub fn matches(&self, transaction: &TransactionRecord) -> bool {
+        if !self.include_failed && !transaction.succeeded {
+            return false;
+        }
+
+        self.programs.is_empty() || transaction.instruction_sets.iter()
+            .any(|instruction_set| self.programs.contains(&instruction_set.function.program))
+    }
+}
+
+/// Drops the transactions not matching a filter before they reach the wrapped sink. Blocks are
+/// still written, with only their matching transactions.
+pub struct FilteredSink<S> {
+    inner: S,
+    filter: TransactionFilter,
+}
+
+impl<S: Sink> FilteredSink<S> {
+    pub fn new(inner: S, filter: TransactionFilter) -> Self {
+        FilteredSink { inner, filter }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn matching(&self, transactions: &[TransactionRecord]) -> Vec<TransactionRecord> {
+        transactions.iter()
+            .filter(|transaction| self.filter.matches(transaction))
+            .cloned()
+            .collect()
+    }
+}
+
+#[async_trait]
+impl<S: Sink> Sink for FilteredSink<S> {
+    async fn write_block(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        if block.transactions.iter().all(|transaction| self.filter.matches(transaction)) {
+            return self.inner.write_block(block).await;
+        }
+
+        let filtered = BlockRecord {
+            slot: block.slot,
+            parent_slot: block.parent_slot,
+            blockhash: block.blockhash.clone(),
+            previous_blockhash: block.previous_blockhash.clone(),
+            block_time: block.block_time,
+            timestamp_estimated: block.timestamp_estimated,
+            finalized: block.finalized,
+            network: block.network,
+            epoch: block.epoch,
+            leader: block.leader.clone(),
+            transactions: self.matching(&block.transactions),
+        };
+        self.inner.write_block(&filtered).await
+    }
+
+    async fn write_block_header(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        self.inner.write_block_header(block).await
+    }
+
+    async fn write_transactions(
+        &mut self,
+        transactions: &[TransactionRecord],
+    ) -> Result<(), SinkError> {
+        if transactions.iter().all(|transaction| self.filter.matches(transaction)) {
+            return self.inner.write_transactions(transactions).await;
+        }
+
+        let matching = self.matching(transactions);
+        if matching.is_empty() {
+            return Ok(());
+        }
+        self.inner.write_transactions(&matching).await
+    }
+
+    async fn write_accounts(&mut self, accounts: &[AccountRecord]) -> Result<(), SinkError> {
+        self.inner.write_accounts(accounts).await
+    }
+
+    async fn write_aggregates(&mut self, aggregates: &[WindowAggregate]) -> Result<(), SinkError> {
+        self.inner.write_aggregates(aggregates).await
+    }
+
+    async fn write_positions(&mut self, snapshots: &[PositionSnapshot]) -> Result<(), SinkError> {
+        self.inner.write_positions(snapshots).await
+    }
+
+    async fn latest_positions(&mut self) -> Result<Vec<PositionSnapshot>, SinkError> {
+        self.inner.latest_positions().await
+    }
+
+    async fn write_program_upgrades(
+        &mut self,
+        upgrades: &[ProgramUpgrade],
+    ) -> Result<(), SinkError> {
+        self.inner.write_program_upgrades(upgrades).await
+    }
+
+    async fn program_upgrades(&mut self) -> Result<Vec<ProgramUpgrade>, SinkError> {
+        self.inner.program_upgrades().await
+    }
+
+    async fn mark_finalized(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        self.inner.mark_finalized(slots).await
+    }
+
+    async fn invalidate(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        self.inner.invalidate(slots).await
+    }
+
+    async fn rollback_from_slot(&mut self, slot: u64) -> Result<(), SinkError> {
+        self.inner.rollback_from_slot(slot).await
+    }
+
+    async fn has_transaction(&mut self, signature: &str) -> Result<Option<bool>, SinkError> {
+        self.inner.has_transaction(signature).await
+    }
+
+    async fn raw_instructions(
+        &mut self,
+        filter: &RawFilter,
+    ) -> Result<Vec<RawInstruction>, SinkError> {
+        self.inner.raw_instructions(filter).await
+    }
+
+    async fn write_reprocessed(
+        &mut self,
+        instruction_sets: &[InstructionSet],
+    ) -> Result<(), SinkError> {
+        self.inner.write_reprocessed(instruction_sets).await
+    }
+
+    async fn stale_transactions(
+        &mut self,
+        filter: &StaleFilter,
+    ) -> Result<Vec<StaleTransaction>, SinkError> {
+        self.inner.stale_transactions(filter).await
+    }
+
+    async fn read_transactions(
+        &mut self,
+        filter: &ReadFilter,
+    ) -> Result<Vec<TransactionRecord>, SinkError> {
+        self.inner.read_transactions(filter).await
+    }
+
+    async fn row_counts(
+        &mut self,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Option<RowCounts>, SinkError> {
+        self.inner.row_counts(first_slot, last_slot).await
+    }
+
+    async fn estimated_timestamps(
+        &mut self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, i64)>, SinkError> {
+        self.inner.estimated_timestamps(after, limit).await
+    }
+
+    async fn update_timestamps(&mut self, updates: &[TimestampUpdate]) -> Result<(), SinkError> {
+        self.inner.update_timestamps(updates).await
+    }
+
+    fn supports_transactions(&self) -> bool {
+        self.inner.supports_transactions()
+    }
+
+    async fn begin(&mut self) -> Result<(), SinkError> {
+        self.inner.begin().await
+    }
+
+    async fn commit(
+        &mut self,
+        pipeline: &str,
+        checkpoint: Option<&Checkpoint>,
+    ) -> Result<(), SinkError> {
+        self.inner.commit(pipeline, checkpoint).await
+    }
+
+    async fn committed_checkpoint(
+        &mut self,
+        pipeline: &str,
+    ) -> Result<Option<Checkpoint>, SinkError> {
+        self.inner.committed_checkpoint(pipeline).await
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        self.inner.flush().await
+    }
+
+    async fn shutdown(&mut self) -> Result<(), SinkError> {
+        self.inner.shutdown().await
+    }
+}
@@ -0,0 +1,516 @@
+pub mod dry_run;
+pub mod filter;
+pub mod router;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+use std::future::Future;
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tracing::{debug_span, error, Instrument, Span};
+
+use crate::account::AccountRecord;
+use crate::aggregate::WindowAggregate;
+use crate::block::BlockRecord;
+use crate::checkpoint::Checkpoint;
+use crate::ingest::timestamps::TimestampUpdate;
+use crate::lending::positions::PositionSnapshot;
+use crate::program_versions::ProgramUpgrade;
+use crate::reindex::{StaleFilter, StaleTransaction};
+use crate::reprocess::{RawFilter, RawInstruction};
+use crate::transaction::TransactionRecord;
+use crate::InstructionSet;
+
+/// Errors that may be returned while persisting records.
+#[derive(Debug, Error)]
+pub enum SinkError {
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[cfg(feature = "snapshot")]
+    #[error("Snapshot error: {0}")]
+    Snapshot(#[from] crate::snapshot::SnapshotError),
+    #[error("Unable to serialize record: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("{0}")]
+    Other(String),
+}
+
+/// Which transactions a sink reads back (see [`Sink::read_transactions`]).
+#[derive(Clone, Debug)]
+pub struct ReadFilter {
+    // The slots read, both inclusive.
+    pub first_slot: u64,
+    pub last_slot: u64,
+    // The slot and signature of the last transaction read, only those after it are returned.
+    pub after: Option<(u64, String)>,
+    // How many transactions are returned at most.
+    pub limit: usize,
+}
+
+/// How many rows of each kind a sink holds of some slots, or were read or written of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RowCounts {
+    pub transactions: u64,
+    // The functions and properties of their instructions, not counting superseded revisions.
+    pub functions: u64,
+    pub properties: u64,
+}
+
+impl RowCounts {
+    /// Counts the rows of `transaction`.
+    pub fn add(&mut self, transaction: &TransactionRecord) {
+        self.transactions += 1;
+        self.functions += transaction.instruction_sets.len() as u64;
+        self.properties += transaction.instruction_sets.iter()
+            .map(|instruction_set| instruction_set.properties.len() as u64)
+            .sum::<u64>();
+    }
+}
+
+/// A destination for processed records.
+#[async_trait]
+pub trait Sink: Send {
+    /// Persists a processed block along with every transaction in it.
+    async fn write_block(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        self.write_block_header(block).await?;
+        self.write_transactions(&block.transactions).await
+    }
+
+    /// Persists the fields of a block but not its transactions, which are streamed through
+    /// `write_transactions` afterwards. Sinks that don't track blocks ignore it.
+    async fn write_block_header(&mut self, _block: &BlockRecord) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Persists a batch of processed transactions.
+    async fn write_transactions(
+        &mut self,
+        transactions: &[TransactionRecord],
+    ) -> Result<(), SinkError>;
+
+    /// Persists a batch of account updates. Sinks that don't track accounts ignore them.
+    async fn write_accounts(&mut self, _accounts: &[AccountRecord]) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Persists the aggregates of closed windows. Late aggregates add to what was written for
+    /// their window before. Sinks that don't keep rollups ignore them.
+    async fn write_aggregates(
+        &mut self,
+        _aggregates: &[WindowAggregate],
+    ) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Persists snapshots of the positions of lending obligations (see
+    /// [`PositionTracker`](crate::lending::positions::PositionTracker)). Sinks that don't keep
+    /// positions ignore them.
+    async fn write_positions(
+        &mut self,
+        _snapshots: &[PositionSnapshot],
+    ) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// The snapshot of the latest slot of every position of a lending obligation (the last one
+    /// written of the slot), for tracking to pick up where it stopped. Sinks that don't keep
+    /// positions have none.
+    async fn latest_positions(&mut self) -> Result<Vec<PositionSnapshot>, SinkError> {
+        Ok(Vec::new())
+    }
+
+    /// Persists deployments and upgrades of programs (see
+    /// [`ProgramVersions`](crate::program_versions::ProgramVersions)), which are removed along
+    /// with their slots. Sinks that don't keep them ignore them.
+    async fn write_program_upgrades(
+        &mut self,
+        _upgrades: &[ProgramUpgrade],
+    ) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Every upgrade of a program written, for the versions of programs to be known from the
+    /// start. Sinks that don't keep them have none.
+    async fn program_upgrades(&mut self) -> Result<Vec<ProgramUpgrade>, SinkError> {
+        Ok(Vec::new())
+    }
+
+    /// Flags every record of the given slots as finalized. Sinks that don't track finality
+    /// ignore it.
+    async fn mark_finalized(&mut self, _slots: &[u64]) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Removes every record of the given slots, which were written at a lower commitment but
+    /// never finalized (their blocks were abandoned).
+    async fn invalidate(&mut self, _slots: &[u64]) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Drops every record at or after `slot`, whose blocks were replaced by a fork. Sinks that
+    /// can't delete (append-only logs) should emit tombstones for the affected slots instead.
+    async fn rollback_from_slot(&mut self, _slot: u64) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Whether the transaction `signature` was written before, or `None` when the sink can't tell
+    /// cheaply.
+    async fn has_transaction(&mut self, _signature: &str) -> Result<Option<bool>, SinkError> {
+        Ok(None)
+    }
+
+    /// The raw data kept of the instructions `filter` selects, for them to be decoded again (see
+    /// [`reprocess_raw`](crate::reprocess::reprocess_raw)). Sinks that don't keep raw data have
+    /// none.
+    async fn raw_instructions(
+        &mut self,
+        _filter: &RawFilter,
+    ) -> Result<Vec<RawInstruction>, SinkError> {
+        Ok(Vec::new())
+    }
+
+    /// Persists instruction sets decoded again out of raw data, marking whatever was written for
+    /// their instructions before as superseded. Sinks that don't keep raw data ignore them.
+    async fn write_reprocessed(
+        &mut self,
+        _instruction_sets: &[InstructionSet],
+    ) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// The transactions whose instructions `filter` selects were decoded by an older version of
+    /// their processor, in slot (then signature) order, for them to be re-indexed (see
+    /// [`reindex`](crate::reindex::reindex)). Sinks that can't tell have none.
+    async fn stale_transactions(
+        &mut self,
+        _filter: &StaleFilter,
+    ) -> Result<Vec<StaleTransaction>, SinkError> {
+        Ok(Vec::new())
+    }
+
+    /// The transactions of the slots `filter` selects, with the functions and properties of their
+    /// instructions, in slot (then signature) order, for them to be moved to another sink (see
+    /// [`export`](crate::migrate::export)). Sinks that can't be read back fail.
+    async fn read_transactions(
+        &mut self,
+        _filter: &ReadFilter,
+    ) -> Result<Vec<TransactionRecord>, SinkError> {
+        Err(SinkError::Other("the sink can't be read back".to_string()))
+    }
+
+    /// How many rows the sink holds of the slots between `first_slot` and `last_slot` (both
+    /// inclusive), or `None` when it can't tell.
+    async fn row_counts(
+        &mut self,
+        _first_slot: u64,
+        _last_slot: u64,
+    ) -> Result<Option<RowCounts>, SinkError> {
+        Ok(None)
+    }
+
+    /// The slots after `after` whose records have an estimated timestamp (see
+    /// [`TimestampResolver`](crate::ingest::timestamps::TimestampResolver)), along with it, in
+    /// slot order and at most `limit` of them. Sinks that don't track estimates have none.
+    async fn estimated_timestamps(
+        &mut self,
+        _after: Option<u64>,
+        _limit: usize,
+    ) -> Result<Vec<(u64, i64)>, SinkError> {
+        Ok(Vec::new())
+    }
+
+    /// Replaces the timestamp of every record of the slots updated. Sinks that don't track
+    /// estimates ignore it.
+    async fn update_timestamps(&mut self, _updates: &[TimestampUpdate]) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Whether the sink can persist what's written along with a checkpoint, atomically (see
+    /// [`CommitCoordinator`](crate::ingest::commit::CommitCoordinator)).
+    fn supports_transactions(&self) -> bool {
+        false
+    }
+
+    /// Starts a transaction: whatever is written until the next `commit` is persisted along with
+    /// the checkpoint committed, or not at all. Only called on sinks supporting transactions.
+    async fn begin(&mut self) -> Result<(), SinkError> {
+        Err(SinkError::Other("the sink doesn't support transactions".to_string()))
+    }
+
+    /// Commits the transaction begun, saving `checkpoint` (if any) under `pipeline` as part of it.
+    async fn commit(
+        &mut self,
+        _pipeline: &str,
+        _checkpoint: Option<&Checkpoint>,
+    ) -> Result<(), SinkError> {
+        Err(SinkError::Other("the sink doesn't support transactions".to_string()))
+    }
+
+    /// The checkpoint last committed under `pipeline`, if any. Sinks that don't support
+    /// transactions have none.
+    async fn committed_checkpoint(
+        &mut self,
+        _pipeline: &str,
+    ) -> Result<Option<Checkpoint>, SinkError> {
+        Ok(None)
+    }
+
+    /// Makes sure everything written so far has been persisted.
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+
+    /// Persists everything written so far and releases whatever the sink holds on to, before
+    /// the process exits. Nothing is written to the sink afterwards.
+    async fn shutdown(&mut self) -> Result<(), SinkError> {
+        self.flush().await
+    }
+}
+
+#[async_trait]
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    async fn write_block(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        (**self).write_block(block).await
+    }
+
+    async fn write_block_header(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        (**self).write_block_header(block).await
+    }
+
+    async fn write_transactions(
+        &mut self,
+        transactions: &[TransactionRecord],
+    ) -> Result<(), SinkError> {
+        (**self).write_transactions(transactions).await
+    }
+
+    async fn write_accounts(&mut self, accounts: &[AccountRecord]) -> Result<(), SinkError> {
+        (**self).write_accounts(accounts).await
+    }
+
+    async fn write_aggregates(&mut self, aggregates: &[WindowAggregate]) -> Result<(), SinkError> {
+        (**self).write_aggregates(aggregates).await
+    }
+
+    async fn write_positions(&mut self, snapshots: &[PositionSnapshot]) -> Result<(), SinkError> {
+        (**self).write_positions(snapshots).await
+    }
+
+    async fn latest_positions(&mut self) -> Result<Vec<PositionSnapshot>, SinkError> {
+        (**self).latest_positions().await
+    }
+
+    async fn write_program_upgrades(
+        &mut self,
+        upgrades: &[ProgramUpgrade],
+    ) -> Result<(), SinkError> {
+        (**self).write_program_upgrades(upgrades).await
+    }
+
+    async fn program_upgrades(&mut self) -> Result<Vec<ProgramUpgrade>, SinkError> {
+        (**self).program_upgrades().await
+    }
+
+    async fn mark_finalized(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        (**self).mark_finalized(slots).await
+    }
+
+    async fn invalidate(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        (**self).invalidate(slots).await
+    }
+
+    async fn rollback_from_slot(&mut self, slot: u64) -> Result<(), SinkError> {
+        (**self).rollback_from_slot(slot).await
+    }
+
+    async fn has_transaction(&mut self, signature: &str) -> Result<Option<bool>, SinkError> {
+        (**self).has_transaction(signature).await
+    }
+
+    async fn raw_instructions(
+        &mut self,
+        filter: &RawFilter,
+    ) -> Result<Vec<RawInstruction>, SinkError> {
+        (**self).raw_instructions(filter).await
+    }
+
+    async fn write_reprocessed(
+        &mut self,
+        instruction_sets: &[InstructionSet],
+    ) -> Result<(), SinkError> {
+        (**self).write_reprocessed(instruction_sets).await
+    }
+
+    async fn stale_transactions(
+        &mut self,
+        filter: &StaleFilter,
+    ) -> Result<Vec<StaleTransaction>, SinkError> {
+        (**self).stale_transactions(filter).await
+    }
+
+    async fn read_transactions(
+        &mut self,
+        filter: &ReadFilter,
+    ) -> Result<Vec<TransactionRecord>, SinkError> {
+        (**self).read_transactions(filter).await
+    }
+
+    async fn row_counts(
+        &mut self,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Option<RowCounts>, SinkError> {
+        (**self).row_counts(first_slot, last_slot).await
+    }
+
+    async fn estimated_timestamps(
+        &mut self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, i64)>, SinkError> {
+        (**self).estimated_timestamps(after, limit).await
+    }
+
+    async fn update_timestamps(&mut self, updates: &[TimestampUpdate]) -> Result<(), SinkError> {
+        (**self).update_timestamps(updates).await
+    }
+
+    fn supports_transactions(&self) -> bool {
+        (**self).supports_transactions()
+    }
+
+    async fn begin(&mut self) -> Result<(), SinkError> {
+        (**self).begin().await
+    }
+
+    async fn commit(
+        &mut self,
+        pipeline: &str,
+        checkpoint: Option<&Checkpoint>,
+    ) -> Result<(), SinkError> {
+        (**self).commit(pipeline, checkpoint).await
+    }
+
+    async fn committed_checkpoint(
+        &mut self,
+        pipeline: &str,
+    ) -> Result<Option<Checkpoint>, SinkError> {
+        (**self).committed_checkpoint(pipeline).await
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        (**self).flush().await
+    }
+
+    async fn shutdown(&mut self) -> Result<(), SinkError> {
+        (**self).shutdown().await
+    }
+}
+
+// The ingestion sources write through these, so every write gets a span (and shows up in the
+// metrics) whatever the sink.
+
+pub(crate) async fn write_block<S: Sink + ?Sized>(
+    sink: &mut S,
+    block: &BlockRecord,
+) -> Result<(), SinkError> {
+    let span = debug_span!("sink_write", kind = "block", slot = block.slot,
+                           batch_size = block.transactions.len());
+    traced_write("block", span, sink.write_block(block)).await
+}
+
+pub(crate) async fn write_block_header<S: Sink + ?Sized>(
+    sink: &mut S,
+    block: &BlockRecord,
+) -> Result<(), SinkError> {
+    let span = debug_span!("sink_write", kind = "header", slot = block.slot);
+    traced_write("header", span, sink.write_block_header(block)).await
+}
+
+pub(crate) async fn write_transactions<S: Sink + ?Sized>(
+    sink: &mut S,
+    transactions: &[TransactionRecord],
+) -> Result<(), SinkError> {
+    let span = debug_span!("sink_write", kind = "transactions", batch_size = transactions.len());
+    traced_write("transactions", span, sink.write_transactions(transactions)).await
+}
+
+pub(crate) async fn write_accounts<S: Sink + ?Sized>(
+    sink: &mut S,
+    accounts: &[AccountRecord],
+) -> Result<(), SinkError> {
+    let span = debug_span!("sink_write", kind = "accounts", batch_size = accounts.len());
+    traced_write("accounts", span, sink.write_accounts(accounts)).await
+}
+
+pub(crate) async fn write_aggregates<S: Sink + ?Sized>(
+    sink: &mut S,
+    aggregates: &[WindowAggregate],
+) -> Result<(), SinkError> {
+    let span = debug_span!("sink_write", kind = "aggregates", batch_size = aggregates.len());
+    traced_write("aggregates", span, sink.write_aggregates(aggregates)).await
+}
+
+pub(crate) async fn write_positions<S: Sink + ?Sized>(
+    sink: &mut S,
+    snapshots: &[PositionSnapshot],
+) -> Result<(), SinkError> {
+    let span = debug_span!("sink_write", kind = "positions", batch_size = snapshots.len());
+    traced_write("positions", span, sink.write_positions(snapshots)).await
+}
+
+pub(crate) async fn write_program_upgrades<S: Sink + ?Sized>(
+    sink: &mut S,
+    upgrades: &[ProgramUpgrade],
+) -> Result<(), SinkError> {
+    let span = debug_span!("sink_write", kind = "program_upgrades", batch_size = upgrades.len());
+    traced_write("program_upgrades", span, sink.write_program_upgrades(upgrades)).await
+}
+
+pub(crate) async fn flush<S: Sink + ?Sized>(sink: &mut S) -> Result<(), SinkError> {
+    #[cfg(feature = "metrics")]
+    let timer = crate::metrics::metrics().sink_flush_seconds.start_timer();
+    let result = traced_write("flush", debug_span!("sink_flush"), sink.flush()).await;
+    #[cfg(feature = "metrics")]
+    timer.observe_duration();
+
+    result
+}
+
+pub(crate) async fn begin<S: Sink + ?Sized>(sink: &mut S) -> Result<(), SinkError> {
+    traced_write("begin", debug_span!("sink_begin"), sink.begin()).await
+}
+
+pub(crate) async fn commit<S: Sink + ?Sized>(
+    sink: &mut S,
+    pipeline: &str,
+    checkpoint: Option<&Checkpoint>,
+) -> Result<(), SinkError> {
+    let span = debug_span!("sink_commit", slot = checkpoint.and_then(|checkpoint| {
+        checkpoint.last_slot
+    }));
+    traced_write("commit", span, sink.commit(pipeline, checkpoint)).await
+}
+
+pub(crate) async fn shutdown<S: Sink + ?Sized>(sink: &mut S) -> Result<(), SinkError> {
+    traced_write("shutdown", debug_span!("sink_shutdown"), sink.shutdown()).await
+}
+
+async fn traced_write<F: Future<Output = Result<(), SinkError>>>(
+    kind: &'static str,
+    span: Span,
+    write: F,
+) -> Result<(), SinkError> {
+    let result = write.instrument(span.clone()).await;
+    if let Err(err) = &result {
+        span.in_scope(|| error!(error = %err, "[spi-wrapper/sinks] Sink {} failed.", kind));
+    }
+    #[cfg(feature = "metrics")]
+    crate::metrics::record_sink_write(kind, &result);
+
+    result
+}
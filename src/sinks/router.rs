@@ -0,0 +1,844 @@
+//! Writing the records of different programs to different sinks, e.g. token transfers to one
+//! database and lending instructions to another.
+//!
+//! A [`SinkRouter`] sends every instruction set to the routes whose rule it matches (by program,
+//! function and network), to the default route when none does, and nowhere when it matches a drop
+//! route (or no route and there's no default). Transactions are split accordingly: each route gets
+//! the transactions it has instruction sets of, with only those, and what isn't decoded out of a
+//! transaction (its call tree, a transaction without any decoded instruction) goes along to the
+//! default route. Block headers, finality, rollbacks and timestamp updates go to every route.
+//!
+//! A route whose sink fails keeps its writes and tries them again, in order, before its next write
+//! and when flushed, so that the other routes carry on meanwhile; flushing fails (and the pipeline
+//! doesn't checkpoint past them) as long as some are left, and a route holding more than
+//! `max_pending` of them fails its next write.
+
+use std::collections::{HashSet, VecDeque};
+
+use async_trait::async_trait;
+use smallvec::SmallVec;
+use tracing::{debug_span, warn, Instrument};
+
+use crate::account::AccountRecord;
+use crate::aggregate::WindowAggregate;
+use crate::block::BlockRecord;
+use crate::ingest::timestamps::TimestampUpdate;
+use crate::interner::Symbol;
+use crate::lending::positions::PositionSnapshot;
+use crate::network::Network;
+use crate::program_versions::ProgramUpgrade;
+use crate::reindex::{StaleFilter, StaleTransaction};
+use crate::reprocess::{RawFilter, RawInstruction};
+use crate::sinks::{Sink, SinkError};
+use crate::transaction::TransactionRecord;
+use crate::InstructionSet;
+
+/// How many writes a failing route holds on to by default before failing.
+pub const DEFAULT_MAX_PENDING: usize = 1_000;
+
+// The route of the instruction sets no route matches, in logs and metrics.
+const DEFAULT_ROUTE: &str = "default";
+// The route of the instruction sets dropped, in metrics.
+#[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+const DROP_ROUTE: &str = "drop";
+
+/// Which instruction sets a route gets: every criterion set has to match, and a rule without any
+/// matches everything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RouteRule {
+    pub programs: HashSet<Symbol>,
+    pub functions: HashSet<Symbol>,
+    pub networks: HashSet<Network>,
+}
+
+impl RouteRule {
+    /// Whether the records of `program` (and `function`, on `network`, when known) match. Those
+    /// without a function (e.g. accounts, routed by their owner) or a network don't match rules
+    /// on them.
+    pub fn matches(
+        &self,
+        program: Symbol,
+        function: Option<Symbol>,
+        network: Option<Network>,
+    ) -> bool {
+        self.matches_program(Some(program), function, network)
+    }
+
+    /// Same as [`matches`](Self::matches), for records of programs whose id may not be interned
+    /// (e.g. account owners). Rules intern the programs they name, so those match rules on any
+    /// program only.
+    fn matches_program(
+        &self,
+        program: Option<Symbol>,
+        function: Option<Symbol>,
+        network: Option<Network>,
+    ) -> bool {
+        (self.programs.is_empty()
+            || program.map_or(false, |program| self.programs.contains(&program)))
+            && (self.functions.is_empty()
+                || function.map_or(false, |function| self.functions.contains(&function)))
+            && (self.networks.is_empty()
+                || network.map_or(false, |network| self.networks.contains(&network)))
+    }
+}
+
+/// A write to a route, held on to until it succeeds.
+enum Write {
+    Block(BlockRecord),
+    Header(BlockRecord),
+    Transactions(Vec<TransactionRecord>),
+    Accounts(Vec<AccountRecord>),
+    Aggregates(Vec<WindowAggregate>),
+    Positions(Vec<PositionSnapshot>),
+    Upgrades(Vec<ProgramUpgrade>),
+    Reprocessed(Vec<InstructionSet>),
+    Finalized(Vec<u64>),
+    Invalidated(Vec<u64>),
+    Rollback(u64),
+    Timestamps(Vec<TimestampUpdate>),
+}
+
+impl Write {
+    fn kind(&self) -> &'static str {
+        match self {
+            Write::Block(_) => "block",
+            Write::Header(_) => "header",
+            Write::Transactions(_) => "transactions",
+            Write::Accounts(_) => "accounts",
+            Write::Aggregates(_) => "aggregates",
+            Write::Positions(_) => "positions",
+            Write::Upgrades(_) => "program_upgrades",
+            Write::Reprocessed(_) => "reprocessed",
+            Write::Finalized(_) => "finalized",
+            Write::Invalidated(_) => "invalidated",
+            Write::Rollback(_) => "rollback",
+            Write::Timestamps(_) => "timestamps",
+        }
+    }
+
+    async fn apply(&self, sink: &mut dyn Sink) -> Result<(), SinkError> {
+        match self {
+            Write::Block(block) => sink.write_block(block).await,
+            Write::Header(block) => sink.write_block_header(block).await,
+            Write::Transactions(transactions) => sink.write_transactions(transactions).await,
+            Write::Accounts(accounts) => sink.write_accounts(accounts).await,
+            Write::Aggregates(aggregates) => sink.write_aggregates(aggregates).await,
+            Write::Positions(snapshots) => sink.write_positions(snapshots).await,
+            Write::Upgrades(upgrades) => sink.write_program_upgrades(upgrades).await,
+            Write::Reprocessed(instruction_sets) => sink.write_reprocessed(instruction_sets).await,
+            Write::Finalized(slots) => sink.mark_finalized(slots).await,
+            Write::Invalidated(slots) => sink.invalidate(slots).await,
+            Write::Rollback(slot) => sink.rollback_from_slot(*slot).await,
+            Write::Timestamps(updates) => sink.update_timestamps(updates).await,
+        }
+    }
+}
+
+struct Route {
+    name: String,
+    // The default route has none.
+    rule: Option<RouteRule>,
+    // Drop routes have none.
+    sink: Option<Box<dyn Sink>>,
+    // The writes that failed, oldest first.
+    pending: VecDeque<Write>,
+    // How many instruction sets were routed here.
+    records: u64,
+}
+
+impl Route {
+    fn new(name: &str, rule: Option<RouteRule>, sink: Option<Box<dyn Sink>>) -> Self {
+        Route { name: name.to_string(), rule, sink, pending: VecDeque::new(), records: 0 }
+    }
+
+    /// Writes `write` after the writes pending, keeping it along with them if one fails.
+    async fn send(&mut self, write: Write, max_pending: usize) -> Result<(), SinkError> {
+        if self.sink.is_none() {
+            return Ok(());
+        }
+
+        self.pending.push_back(write);
+        if let Err(err) = self.retry().await {
+            if self.pending.len() > max_pending {
+                // Left to the caller to write again.
+                self.pending.pop_back();
+                self.record_pending();
+                return Err(SinkError::Other(format!(
+                    "route {}: {} writes pending, the last one failing with: {}",
+                    self.name, self.pending.len(), err)));
+            }
+            warn!("[spi-wrapper/sinks/router] Route {} failed, holding on to {} writes: {}",
+                  self.name, self.pending.len(), err);
+        }
+
+        Ok(())
+    }
+
+    /// Writes whatever is pending, in order, stopping at the first failure.
+    async fn retry(&mut self) -> Result<(), SinkError> {
+        let Route { name, sink, pending, .. } = self;
+        let sink = match sink {
+            Some(sink) => sink,
+            None => return Ok(()),
+        };
+
+        let mut result = Ok(());
+        while let Some(write) = pending.front() {
+            let span = debug_span!("route_write", route = name.as_str(), kind = write.kind());
+            if let Err(err) = write.apply(sink.as_mut()).instrument(span).await {
+                #[cfg(feature = "metrics")]
+                crate::metrics::metrics().route_write_errors
+                    .with_label_values(&[name.as_str(), write.kind()])
+                    .inc();
+                result = Err(err);
+                break;
+            }
+            pending.pop_front();
+        }
+        self.record_pending();
+
+        result
+    }
+
+    fn record_pending(&self) {
+        #[cfg(feature = "metrics")]
+        crate::metrics::metrics().route_pending_writes
+            .with_label_values(&[self.name.as_str()])
+            .set(self.pending.len() as i64);
+    }
+}
+
+/// What went through a route of a [`SinkRouter`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RouteStats {
+    pub name: String,
+    // How many instruction sets were routed to it.
+    pub records: u64,
+    // How many writes it holds on to, its sink having failed them.
+    pub pending: usize,
+}
+
+/// Writes records to several sinks depending on what they are, see the module documentation.
+pub struct SinkRouter {
+    routes: Vec<Route>,
+    // The index of the default route, if any.
+    default: Option<usize>,
+    max_pending: usize,
+    // How many instruction sets were dropped.
+    dropped: u64,
+}
+
+impl SinkRouter {
+    /// A router without any route, sending everything to `default` (dropping it when unset).
+    pub fn new(default: Option<Box<dyn Sink>>) -> Self {
+        SinkRouter {
+            default: default.as_ref().map(|_| 0),
+            routes: default.into_iter()
+                .map(|sink| Route::new(DEFAULT_ROUTE, None, Some(sink)))
+                .collect(),
+            max_pending: DEFAULT_MAX_PENDING,
+            dropped: 0,
+        }
+    }
+
+    /// Sends whatever matches `rule` to `sink`.
+    pub fn route(mut self, name: &str, rule: RouteRule, sink: Box<dyn Sink>) -> Self {
+        self.routes.push(Route::new(name, Some(rule), Some(sink)));
+        self
+    }
+
+    /// Drops whatever matches `rule`, whichever other routes it matches.
+    pub fn drop_route(mut self, name: &str, rule: RouteRule) -> Self {
+        self.routes.push(Route::new(name, Some(rule), None));
+        self
+    }
+
+    /// How many writes a failing route holds on to before failing.
+    pub fn with_max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = max_pending;
+        self
+    }
+
+    /// What went through every route but the drop routes, the default route first.
+    pub fn stats(&self) -> Vec<RouteStats> {
+        self.routes.iter()
+            .filter(|route| route.sink.is_some())
+            .map(|route| RouteStats {
+                name: route.name.clone(),
+                records: route.records,
+                pending: route.pending.len(),
+            })
+            .collect()
+    }
+
+    /// How many instruction sets were dropped, by a drop route or for lack of a default route.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// The indexes of the routes the records of `program` (`function`, `network`) go to, `None`
+    /// for a program whose id isn't interned.
+    fn targets(
+        &self,
+        program: Option<Symbol>,
+        function: Option<Symbol>,
+        network: Option<Network>,
+    ) -> SmallVec<[usize; 2]> {
+        let mut targets = SmallVec::new();
+        for (index, route) in self.routes.iter().enumerate() {
+            match &route.rule {
+                Some(rule) if rule.matches_program(program, function, network) => {
+                    if route.sink.is_none() {
+                        return SmallVec::new();
+                    }
+                    targets.push(index);
+                }
+                _ => {}
+            }
+        }
+        if targets.is_empty() {
+            targets.extend(self.default);
+        }
+
+        targets
+    }
+
+    /// Splits `transactions` into what each route gets, by route index.
+    fn split(&mut self, transactions: &[TransactionRecord]) -> Vec<Vec<TransactionRecord>> {
+        let mut split = vec![Vec::new(); self.routes.len()];
+        for transaction in transactions {
+            let mut copies: Vec<Option<TransactionRecord>> = vec![None; self.routes.len()];
+            let network = Some(transaction.network);
+            for instruction_set in &transaction.instruction_sets {
+                let function = &instruction_set.function;
+                let targets = self.targets(Some(function.program), Some(function.function_name),
+                                           network);
+                self.count(&targets);
+                for index in targets {
+                    copy(&mut copies[index], transaction).instruction_sets
+                        .push(instruction_set.clone());
+                }
+            }
+            for failure in &transaction.decode_failures {
+                for index in self.targets(Some(failure.program), None, network) {
+                    copy(&mut copies[index], transaction).decode_failures.push(failure.clone());
+                }
+            }
+            for activity in &transaction.signer_activity {
+                let targets = self.targets(Some(activity.program), Some(activity.function_name),
+                                           network);
+                for index in targets {
+                    copy(&mut copies[index], transaction).signer_activity.push(activity.clone());
+                }
+            }
+            if let Some(default) = self.default {
+                copy(&mut copies[default], transaction);
+            }
+
+            for (index, copy) in copies.into_iter().enumerate() {
+                split[index].extend(copy);
+            }
+        }
+
+        split
+    }
+
+    /// Counts an instruction set routed to `targets`.
+    fn count(&mut self, targets: &[usize]) {
+        if targets.is_empty() {
+            self.dropped += 1;
+            #[cfg(feature = "metrics")]
+            crate::metrics::metrics().route_records.with_label_values(&[DROP_ROUTE]).inc();
+        }
+        for index in targets {
+            let route = &mut self.routes[*index];
+            route.records += 1;
+            #[cfg(feature = "metrics")]
+            crate::metrics::metrics().route_records.with_label_values(&[route.name.as_str()]).inc();
+        }
+    }
+
+    /// Sends every route its own write, those getting `None` being skipped.
+    async fn send_each(&mut self, writes: Vec<Option<Write>>) -> Result<(), SinkError> {
+        let max_pending = self.max_pending;
+        let mut errors = Vec::new();
+        for (route, write) in self.routes.iter_mut().zip(writes) {
+            if let Some(write) = write {
+                if let Err(err) = route.send(write, max_pending).await {
+                    errors.push(err.to_string());
+                }
+            }
+        }
+
+        aggregate(errors)
+    }
+
+    /// Sends every route a write of its own, made by `write`.
+    async fn send_all<F: Fn() -> Write>(&mut self, write: F) -> Result<(), SinkError> {
+        let writes = self.routes.iter()
+            .map(|route| route.sink.as_ref().map(|_| write()))
+            .collect();
+        self.send_each(writes).await
+    }
+
+    /// Groups `records` by route, as routed by `route`.
+    fn group<T: Clone, F: Fn(&T) -> SmallVec<[usize; 2]>>(
+        &self,
+        records: &[T],
+        route: F,
+    ) -> Vec<Vec<T>> {
+        let mut grouped = vec![Vec::new(); self.routes.len()];
+        for record in records {
+            for index in route(record) {
+                grouped[index].push(record.clone());
+            }
+        }
+
+        grouped
+    }
+
+    /// The sinks of every route but the drop routes.
+    fn sinks(&mut self) -> impl Iterator<Item = &mut Box<dyn Sink>> {
+        self.routes.iter_mut().filter_map(|route| route.sink.as_mut())
+    }
+
+    /// Writes what's pending then flushes (or shuts down) every route, failing with the error of
+    /// every route that failed.
+    async fn finish_all(&mut self, shutdown: bool) -> Result<(), SinkError> {
+        let mut errors = Vec::new();
+        for route in &mut self.routes {
+            let result = match route.retry().await {
+                Ok(()) => match &mut route.sink {
+                    Some(sink) if shutdown => sink.shutdown().await,
+                    Some(sink) => sink.flush().await,
+                    None => Ok(()),
+                },
+                Err(err) => Err(SinkError::Other(format!(
+                    "{} writes pending: {}", route.pending.len(), err))),
+            };
+            if let Err(err) = result {
+                errors.push(format!("route {}: {}", route.name, err));
+            }
+        }
+
+        aggregate(errors)
+    }
+}
+
+/// The copy of `transaction` a route gets, made the first time it gets some of it: everything
+/// but what's routed.
+fn copy<'a>(
+    copy: &'a mut Option<TransactionRecord>,
+    transaction: &TransactionRecord,
+) -> &'a mut TransactionRecord {
+    copy.get_or_insert_with(|| TransactionRecord {
+        transaction_hash: transaction.transaction_hash.clone(),
+        slot: transaction.slot,
+        fee: transaction.fee,
+        base_fee_lamports: transaction.base_fee_lamports,
+        priority_fee_lamports: transaction.priority_fee_lamports,
+        compute_unit_price_micro_lamports: transaction.compute_unit_price_micro_lamports,
+        succeeded: transaction.succeeded,
+        timestamp: transaction.timestamp,
+        timestamp_estimated: transaction.timestamp_estimated,
+        finalized: transaction.finalized,
+        network: transaction.network,
+        instruction_sets: Vec::new(),
+        decode_failures: Vec::new(),
+        call_tree: transaction.call_tree.clone(),
+        signer_activity: Vec::new(),
+        account_lifecycle: transaction.account_lifecycle.clone(),
+        confirmed: transaction.confirmed,
+        simulation: transaction.simulation.clone(),
+    })
+}
+
+/// `block`, with `transactions` rather than its own.
+fn with_transactions(block: &BlockRecord, transactions: Vec<TransactionRecord>) -> BlockRecord {
+    BlockRecord {
+        slot: block.slot,
+        parent_slot: block.parent_slot,
+        blockhash: block.blockhash.clone(),
+        previous_blockhash: block.previous_blockhash.clone(),
+        block_time: block.block_time,
+        timestamp_estimated: block.timestamp_estimated,
+        finalized: block.finalized,
+        network: block.network,
+        epoch: block.epoch,
+        leader: block.leader.clone(),
+        transactions,
+    }
+}
+
+fn aggregate(errors: Vec<String>) -> Result<(), SinkError> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(SinkError::Other(errors.join("; ")))
+    }
+}
+
+#[async_trait]
+impl Sink for SinkRouter {
+    async fn write_block(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        let writes = self.split(&block.transactions).into_iter()
+            .zip(&self.routes)
+            .map(|(transactions, route)| {
+                route.sink.as_ref().map(|_| Write::Block(with_transactions(block, transactions)))
+            })
+            .collect();
+        self.send_each(writes).await
+    }
+
+    async fn write_block_header(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        self.send_all(|| Write::Header(with_transactions(block, Vec::new()))).await
+    }
+
+    async fn write_transactions(
+        &mut self,
+        transactions: &[TransactionRecord],
+    ) -> Result<(), SinkError> {
+        let writes = self.split(transactions).into_iter()
+            .map(|transactions| Some(transactions).filter(|transactions| !transactions.is_empty())
+                .map(Write::Transactions))
+            .collect();
+        self.send_each(writes).await
+    }
+
+    async fn write_accounts(&mut self, accounts: &[AccountRecord]) -> Result<(), SinkError> {
+        let writes = self.group(accounts, |account| {
+            self.targets(Symbol::lookup(&account.owner), None, None)
+        }).into_iter()
+            .map(|accounts| Some(accounts).filter(|accounts| !accounts.is_empty())
+                .map(Write::Accounts))
+            .collect();
+        self.send_each(writes).await
+    }
+
+    async fn write_aggregates(&mut self, aggregates: &[WindowAggregate]) -> Result<(), SinkError> {
+        let writes = self.group(aggregates, |aggregate| {
+            self.targets(Some(aggregate.program), Some(aggregate.function_name),
+                         Some(aggregate.network))
+        }).into_iter()
+            .map(|aggregates| Some(aggregates).filter(|aggregates| !aggregates.is_empty())
+                .map(Write::Aggregates))
+            .collect();
+        self.send_each(writes).await
+    }
+
+    async fn write_positions(&mut self, snapshots: &[PositionSnapshot]) -> Result<(), SinkError> {
+        let writes = self.group(snapshots, |snapshot| {
+            self.targets(Some(snapshot.program), Some(snapshot.function_name),
+                         Some(snapshot.network))
+        }).into_iter()
+            .map(|snapshots| Some(snapshots).filter(|snapshots| !snapshots.is_empty())
+                .map(Write::Positions))
+            .collect();
+        self.send_each(writes).await
+    }
+
+    async fn latest_positions(&mut self) -> Result<Vec<PositionSnapshot>, SinkError> {
+        let mut positions = Vec::new();
+        for sink in self.sinks() {
+            positions.extend(sink.latest_positions().await?);
+        }
+
+        Ok(positions)
+    }
+
+    /// Upgrades go where the instructions of their program would, whatever their function.
+    async fn write_program_upgrades(
+        &mut self,
+        upgrades: &[ProgramUpgrade],
+    ) -> Result<(), SinkError> {
+        let writes = self.group(upgrades, |upgrade| {
+            self.targets(Some(upgrade.program), None, Some(upgrade.network))
+        }).into_iter()
+            .map(|upgrades| Some(upgrades).filter(|upgrades| !upgrades.is_empty())
+                .map(Write::Upgrades))
+            .collect();
+        self.send_each(writes).await
+    }
+
+    async fn program_upgrades(&mut self) -> Result<Vec<ProgramUpgrade>, SinkError> {
+        let mut upgrades = Vec::new();
+        for sink in self.sinks() {
+            upgrades.extend(sink.program_upgrades().await?);
+        }
+
+        Ok(upgrades)
+    }
+
+    async fn mark_finalized(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        self.send_all(|| Write::Finalized(slots.to_vec())).await
+    }
+
+    async fn invalidate(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        self.send_all(|| Write::Invalidated(slots.to_vec())).await
+    }
+
+    async fn rollback_from_slot(&mut self, slot: u64) -> Result<(), SinkError> {
+        self.send_all(|| Write::Rollback(slot)).await
+    }
+
+    async fn raw_instructions(
+        &mut self,
+        filter: &RawFilter,
+    ) -> Result<Vec<RawInstruction>, SinkError> {
+        let mut raw_instructions = Vec::new();
+        for sink in self.sinks() {
+            raw_instructions.extend(sink.raw_instructions(filter).await?);
+        }
+
+        Ok(raw_instructions)
+    }
+
+    async fn write_reprocessed(
+        &mut self,
+        instruction_sets: &[InstructionSet],
+    ) -> Result<(), SinkError> {
+        let writes = self.group(instruction_sets, |instruction_set| {
+            let function = &instruction_set.function;
+            self.targets(Some(function.program), Some(function.function_name),
+                         Some(function.network))
+        }).into_iter()
+            .map(|instruction_sets| Some(instruction_sets)
+                .filter(|instruction_sets| !instruction_sets.is_empty())
+                .map(Write::Reprocessed))
+            .collect();
+        self.send_each(writes).await
+    }
+
+    async fn stale_transactions(
+        &mut self,
+        filter: &StaleFilter,
+    ) -> Result<Vec<StaleTransaction>, SinkError> {
+        let mut stale = Vec::new();
+        for sink in self.sinks() {
+            stale.extend(sink.stale_transactions(filter).await?);
+        }
+        stale.sort_by(|a, b| (a.slot, &a.transaction_hash).cmp(&(b.slot, &b.transaction_hash)));
+        stale.dedup_by(|a, b| a.transaction_hash == b.transaction_hash);
+
+        Ok(stale)
+    }
+
+    async fn estimated_timestamps(
+        &mut self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, i64)>, SinkError> {
+        let mut estimated = Vec::new();
+        for sink in self.sinks() {
+            estimated.extend(sink.estimated_timestamps(after, limit).await?);
+        }
+        estimated.sort_unstable();
+        estimated.dedup_by_key(|(slot, _)| *slot);
+        estimated.truncate(limit);
+
+        Ok(estimated)
+    }
+
+    async fn update_timestamps(&mut self, updates: &[TimestampUpdate]) -> Result<(), SinkError> {
+        self.send_all(|| Write::Timestamps(updates.to_vec())).await
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        self.finish_all(false).await
+    }
+
+    async fn shutdown(&mut self) -> Result<(), SinkError> {
+        self.finish_all(true).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use smallvec::smallvec;
+
+    use super::*;
+    use crate::call_tree::CallTree;
+    use crate::InstructionFunction;
+
+    const TOKEN: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+    const LENDING: &str = "LendZqTs8gn5CTSJU1jWKhKuVpjJGom45nnwPb2AMTi";
+    const SPAM: &str = "Spam111111111111111111111111111111111111111";
+    const OTHER: &str = "Other11111111111111111111111111111111111111";
+
+    /// Keeps the transactions written, failing every write while `failing` is set.
+    #[derive(Clone, Default)]
+    struct SharedSink {
+        transactions: Arc<Mutex<Vec<TransactionRecord>>>,
+        failing: Arc<AtomicBool>,
+    }
+
+    impl SharedSink {
+        /// The instructions written, as `signature:function`, in the order they were written.
+        fn written(&self) -> Vec<String> {
+            self.transactions.lock().unwrap().iter()
+                .flat_map(|transaction| {
+                    let instructions: Vec<_> = transaction.instruction_sets.iter()
+                        .map(|instruction_set| format!("{}:{}", transaction.transaction_hash,
+                                                       instruction_set.function.function_name))
+                        .collect();
+                    if instructions.is_empty() {
+                        vec![transaction.transaction_hash.clone()]
+                    } else {
+                        instructions
+                    }
+                })
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl Sink for SharedSink {
+        async fn write_transactions(
+            &mut self,
+            transactions: &[TransactionRecord],
+        ) -> Result<(), SinkError> {
+            if self.failing.load(Ordering::Relaxed) {
+                return Err(SinkError::Other("connection refused".to_string()));
+            }
+            self.transactions.lock().unwrap().extend_from_slice(transactions);
+
+            Ok(())
+        }
+    }
+
+    fn instruction(signature: &str, program: &str, function_name: &str) -> InstructionSet {
+        InstructionSet {
+            function: InstructionFunction {
+                tx_instruction_id: 0,
+                transaction_hash: Arc::from(signature),
+                parent_index: -1,
+                program: program.into(),
+                function_name: function_name.into(),
+                timestamp: 0,
+                network: Network::Mainnet,
+                decoder_version: 0,
+            },
+            properties: smallvec![],
+            raw_data: None,
+        }
+    }
+
+    fn transaction(signature: &str, instructions: &[(&str, &str)]) -> TransactionRecord {
+        TransactionRecord {
+            transaction_hash: signature.to_string(),
+            slot: 1,
+            fee: 5000,
+            base_fee_lamports: None,
+            priority_fee_lamports: None,
+            compute_unit_price_micro_lamports: None,
+            succeeded: true,
+            timestamp: 0,
+            timestamp_estimated: false,
+            finalized: false,
+            network: Network::Mainnet,
+            instruction_sets: instructions.iter()
+                .map(|(program, function_name)| instruction(signature, program, function_name))
+                .collect(),
+            decode_failures: Vec::new(),
+            call_tree: CallTree::default(),
+            signer_activity: Vec::new(),
+            account_lifecycle: Vec::new(),
+            confirmed: true,
+            simulation: None,
+        }
+    }
+
+    fn rule(programs: &[&str], functions: &[&str]) -> RouteRule {
+        RouteRule {
+            programs: programs.iter().map(|program| Symbol::intern(program)).collect(),
+            functions: functions.iter().map(|function| Symbol::intern(function)).collect(),
+            networks: HashSet::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn mixed_batches_are_split_by_route() {
+        let (tokens, lending, default) =
+            (SharedSink::default(), SharedSink::default(), SharedSink::default());
+        let mut router = SinkRouter::new(Some(Box::new(default.clone())))
+            .route("tokens", rule(&[TOKEN], &[]), Box::new(tokens.clone()))
+            .route("lending", rule(&[LENDING], &["deposit", "borrow"]), Box::new(lending.clone()))
+            .drop_route("spam", rule(&[SPAM], &[]));
+
+        router.write_transactions(&[
+            transaction("swap", &[(TOKEN, "transfer"), (LENDING, "deposit"), (TOKEN, "burn")]),
+            transaction("refresh", &[(LENDING, "refresh-reserve")]),
+            transaction("spam", &[(SPAM, "airdrop"), (TOKEN, "transfer")]),
+            transaction("other", &[(OTHER, "noop")]),
+            transaction("undecoded", &[]),
+        ]).await.unwrap();
+        router.flush().await.unwrap();
+
+        assert_eq!(tokens.written(), vec!["swap:transfer", "swap:burn", "spam:transfer"]);
+        assert_eq!(lending.written(), vec!["swap:deposit"]);
+        // Every transaction reaches the default route, with only what no route matched.
+        assert_eq!(default.written(), vec![
+            "swap", "refresh:refresh-reserve", "spam", "other:noop", "undecoded",
+        ]);
+
+        let records: Vec<_> = router.stats().into_iter()
+            .map(|stats| (stats.name, stats.records))
+            .collect();
+        assert_eq!(records, vec![
+            ("default".to_string(), 2),
+            ("tokens".to_string(), 3),
+            ("lending".to_string(), 1),
+        ]);
+        assert_eq!(router.dropped(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_failing_route_keeps_its_records_for_later() {
+        let (tokens, lending) = (SharedSink::default(), SharedSink::default());
+        let mut router = SinkRouter::new(None)
+            .route("tokens", rule(&[TOKEN], &[]), Box::new(tokens.clone()))
+            .route("lending", rule(&[LENDING], &[]), Box::new(lending.clone()));
+
+        lending.failing.store(true, Ordering::Relaxed);
+        router.write_transactions(&[transaction("first", &[(TOKEN, "transfer"),
+                                                           (LENDING, "deposit")])])
+            .await.unwrap();
+        router.write_transactions(&[transaction("second", &[(LENDING, "borrow"),
+                                                            (TOKEN, "transfer")])])
+            .await.unwrap();
+        // The healthy route carries on, the flush failing until the failing one recovers.
+        assert_eq!(tokens.written(), vec!["first:transfer", "second:transfer"]);
+        let err = router.flush().await.unwrap_err().to_string();
+        assert!(err.starts_with("route lending: 2 writes pending"), "{}", err);
+        assert_eq!(router.stats()[1].pending, 2);
+
+        lending.failing.store(false, Ordering::Relaxed);
+        router.flush().await.unwrap();
+        assert_eq!(lending.written(), vec!["first:deposit", "second:borrow"]);
+        assert_eq!(tokens.written(), vec!["first:transfer", "second:transfer"]);
+        assert_eq!(router.stats()[1].pending, 0);
+    }
+
+    #[tokio::test]
+    async fn a_route_holding_too_many_writes_fails() {
+        let lending = SharedSink::default();
+        let mut router = SinkRouter::new(None)
+            .route("lending", rule(&[LENDING], &[]), Box::new(lending.clone()))
+            .with_max_pending(1);
+
+        lending.failing.store(true, Ordering::Relaxed);
+        router.write_transactions(&[transaction("first", &[(LENDING, "deposit")])])
+            .await.unwrap();
+        assert!(router.write_transactions(&[transaction("second", &[(LENDING, "deposit")])])
+            .await.is_err());
+
+        // The write that failed is left to the caller, the one held on to is written first.
+        lending.failing.store(false, Ordering::Relaxed);
+        router.write_transactions(&[transaction("second", &[(LENDING, "deposit")])])
+            .await.unwrap();
+        assert_eq!(lending.written(), vec!["first:deposit", "second:deposit"]);
+    }
+}
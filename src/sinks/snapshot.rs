@@ -0,0 +1,139 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use tracing::warn;
+
+use crate::sinks::{Sink, SinkError};
+use crate::snapshot;
+use crate::transaction::TransactionRecord;
+
+/// Writes transactions to snapshot files (see [`snapshot`]) in a directory, one per flush, named
+/// after the slots they span: `<first slot>-<last slot>.spis`, suffixed with `-1`, `-2`... when
+/// a flush spans the slots of another one.
+///
+/// Snapshots can't be edited: records are only dropped by a rollback while they haven't been
+/// flushed.
+pub struct SnapshotSink {
+    dir: PathBuf,
+    pending: Vec<TransactionRecord>,
+}
+
+impl SnapshotSink {
+    /// A sink writing to `dir`, created if needed.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, SinkError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .map_err(|err| SinkError::Other(format!("Unable to create {}: {}", dir.display(),
+                                                    err)))?;
+
+        Ok(SnapshotSink { dir, pending: Vec::new() })
+    }
+
+    /// Where the transactions pending would be written.
+    fn next_path(&self) -> PathBuf {
+        let slots = self.pending.iter().map(|transaction| transaction.slot);
+        let (first, last) = (slots.clone().min().unwrap_or(0), slots.max().unwrap_or(0));
+        let name = format!("{}-{}", first, last);
+
+        let mut path = self.dir.join(format!("{}.spis", name));
+        let mut suffix = 0;
+        while path.exists() {
+            suffix += 1;
+            path = self.dir.join(format!("{}-{}.spis", name, suffix));
+        }
+
+        path
+    }
+}
+
+#[async_trait]
+impl Sink for SnapshotSink {
+    async fn write_transactions(
+        &mut self,
+        transactions: &[TransactionRecord],
+    ) -> Result<(), SinkError> {
+        self.pending.extend_from_slice(transactions);
+        Ok(())
+    }
+
+    async fn rollback_from_slot(&mut self, slot: u64) -> Result<(), SinkError> {
+        self.pending.retain(|transaction| transaction.slot < slot);
+        warn!("[spi-wrapper/sinks/snapshot] Rolled back from slot {}, the snapshots written \
+        already are left as they are.", slot);
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), SinkError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        snapshot::write_records(self.next_path(), &self.pending)?;
+        self.pending.clear();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::call_tree::CallTree;
+    use crate::snapshot::{read_snapshot, Batch};
+    use crate::Network;
+
+    fn transaction(signature: &str, slot: u64) -> TransactionRecord {
+        TransactionRecord {
+            transaction_hash: signature.to_string(),
+            slot,
+            fee: 5000,
+            base_fee_lamports: None,
+            priority_fee_lamports: None,
+            compute_unit_price_micro_lamports: None,
+            succeeded: true,
+            timestamp: 0,
+            timestamp_estimated: false,
+            finalized: false,
+            network: Network::Mainnet,
+            instruction_sets: Vec::new(),
+            decode_failures: Vec::new(),
+            call_tree: CallTree::default(),
+            signer_activity: Vec::new(),
+            account_lifecycle: Vec::new(),
+            confirmed: true,
+            simulation: None,
+        }
+    }
+
+    fn signatures(path: &Path) -> Vec<String> {
+        match read_snapshot(path).unwrap() {
+            Batch::Transactions(transactions) => transactions.into_iter()
+                .map(|transaction| transaction.transaction_hash)
+                .collect(),
+            Batch::InstructionSets(_) => panic!("{} holds instruction sets", path.display()),
+        }
+    }
+
+    #[tokio::test]
+    async fn every_flush_writes_a_snapshot() {
+        let dir = std::env::temp_dir().join(format!("spi-snapshot-sink-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut sink = SnapshotSink::open(&dir).unwrap();
+
+        sink.write_transactions(&[transaction("a", 10), transaction("b", 12)]).await.unwrap();
+        sink.flush().await.unwrap();
+        sink.write_transactions(&[transaction("c", 12), transaction("d", 10)]).await.unwrap();
+        sink.write_transactions(&[transaction("e", 14)]).await.unwrap();
+        sink.rollback_from_slot(13).await.unwrap();
+        sink.flush().await.unwrap();
+        // Nothing to write.
+        sink.flush().await.unwrap();
+
+        assert_eq!(signatures(&dir.join("10-12.spis")), vec!["a", "b"]);
+        assert_eq!(signatures(&dir.join("10-12-1.spis")), vec!["c", "d"]);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,1691 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::account::AccountRecord;
+use crate::aggregate::WindowAggregate;
+use crate::block::BlockRecord;
+use crate::call_tree::CallTree;
+use crate::checkpoint::Checkpoint;
+use crate::ingest::timestamps::TimestampUpdate;
+use crate::interner::Symbol;
+use crate::lending::positions::PositionSnapshot;
+use crate::network::Network;
+use crate::program_versions::ProgramUpgrade;
+use crate::reindex::{StaleFilter, StaleTransaction};
+use crate::reprocess::{RawFilter, RawInstruction};
+use crate::sinks::{ReadFilter, RowCounts, Sink, SinkError};
+use crate::transaction::TransactionRecord;
+use crate::{InstructionFunction, InstructionProperty, InstructionSet, PropertyType, RawData};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS blocks (
+        network TEXT NOT NULL,
+        slot INTEGER NOT NULL,
+        parent_slot INTEGER NOT NULL,
+        blockhash TEXT NOT NULL,
+        previous_blockhash TEXT NOT NULL,
+        block_time INTEGER,
+        finalized INTEGER NOT NULL DEFAULT 0,
+        timestamp_estimated INTEGER NOT NULL DEFAULT 0,
+        epoch INTEGER,
+        leader TEXT,
+        PRIMARY KEY (network, slot)
+    );
+    CREATE TABLE IF NOT EXISTS transactions (
+        network TEXT NOT NULL,
+        transaction_hash TEXT NOT NULL,
+        slot INTEGER NOT NULL,
+        fee INTEGER NOT NULL,
+        succeeded INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL,
+        finalized INTEGER NOT NULL DEFAULT 0,
+        timestamp_estimated INTEGER NOT NULL DEFAULT 0,
+        base_fee_lamports INTEGER,
+        priority_fee_lamports INTEGER,
+        compute_unit_price_micro_lamports INTEGER,
+        PRIMARY KEY (network, transaction_hash)
+    );
+    CREATE INDEX IF NOT EXISTS transactions_slot ON transactions (network, slot);
+    CREATE TABLE IF NOT EXISTS instruction_functions (
+        network TEXT NOT NULL,
+        transaction_hash TEXT NOT NULL,
+        tx_instruction_id INTEGER NOT NULL,
+        parent_index INTEGER NOT NULL,
+        program TEXT NOT NULL,
+        function_name TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        revision INTEGER NOT NULL DEFAULT 0,
+        superseded INTEGER NOT NULL DEFAULT 0,
+        decoder_version INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (network, transaction_hash, parent_index, tx_instruction_id, revision)
+    );
+    CREATE TABLE IF NOT EXISTS instruction_properties (
+        network TEXT NOT NULL,
+        transaction_hash TEXT NOT NULL,
+        tx_instruction_id INTEGER NOT NULL,
+        parent_index INTEGER NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        value_type TEXT NOT NULL DEFAULT 'unknown',
+        parent_key TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        revision INTEGER NOT NULL DEFAULT 0,
+        superseded INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX IF NOT EXISTS instruction_properties_instruction
+        ON instruction_properties (network, transaction_hash, parent_index, tx_instruction_id);
+    CREATE TABLE IF NOT EXISTS raw_instructions (
+        network TEXT NOT NULL,
+        transaction_hash TEXT NOT NULL,
+        tx_instruction_id INTEGER NOT NULL,
+        parent_index INTEGER NOT NULL,
+        program TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        data TEXT NOT NULL,
+        data_len INTEGER NOT NULL,
+        decoded INTEGER NOT NULL,
+        PRIMARY KEY (network, transaction_hash, parent_index, tx_instruction_id)
+    );
+    CREATE INDEX IF NOT EXISTS raw_instructions_program
+        ON raw_instructions (network, program, timestamp);
+    CREATE TABLE IF NOT EXISTS accounts (
+        pubkey TEXT PRIMARY KEY,
+        owner TEXT NOT NULL,
+        lamports INTEGER NOT NULL,
+        executable INTEGER NOT NULL,
+        data_len INTEGER NOT NULL,
+        slot INTEGER NOT NULL,
+        write_version INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS instruction_aggregates (
+        network TEXT NOT NULL,
+        window_start INTEGER NOT NULL,
+        window_end INTEGER NOT NULL,
+        program TEXT NOT NULL,
+        function_name TEXT NOT NULL,
+        late INTEGER NOT NULL,
+        count INTEGER NOT NULL,
+        sums TEXT NOT NULL,
+        PRIMARY KEY (network, window_start, program, function_name, late)
+    );
+    CREATE TABLE IF NOT EXISTS lending_positions (
+        network TEXT NOT NULL,
+        program TEXT NOT NULL,
+        obligation TEXT NOT NULL,
+        reserve TEXT NOT NULL,
+        deposited_amount INTEGER NOT NULL,
+        borrowed_amount INTEGER NOT NULL,
+        slot INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL,
+        transaction_hash TEXT NOT NULL,
+        tx_instruction_id INTEGER NOT NULL,
+        parent_index INTEGER NOT NULL,
+        function_name TEXT NOT NULL,
+        PRIMARY KEY (network, transaction_hash, parent_index, tx_instruction_id, reserve)
+    );
+    CREATE INDEX IF NOT EXISTS lending_positions_obligation
+        ON lending_positions (network, obligation, reserve, slot);
+    CREATE TABLE IF NOT EXISTS signer_activity (
+        network TEXT NOT NULL,
+        signer TEXT NOT NULL,
+        slot INTEGER NOT NULL,
+        transaction_hash TEXT NOT NULL,
+        parent_index INTEGER NOT NULL,
+        instruction_index INTEGER NOT NULL,
+        fee_payer INTEGER NOT NULL,
+        program TEXT NOT NULL,
+        function_name TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        PRIMARY KEY (network, signer, slot, transaction_hash, parent_index, instruction_index)
+    );
+    CREATE INDEX IF NOT EXISTS signer_activity_transaction
+        ON signer_activity (network, transaction_hash);
+    CREATE TABLE IF NOT EXISTS account_lifecycle (
+        network TEXT NOT NULL,
+        account TEXT NOT NULL,
+        slot INTEGER NOT NULL,
+        transaction_hash TEXT NOT NULL,
+        event TEXT NOT NULL,
+        owner_program TEXT,
+        lamports INTEGER NOT NULL,
+        rent_exempt INTEGER NOT NULL,
+        source_instruction_index INTEGER,
+        timestamp INTEGER NOT NULL,
+        PRIMARY KEY (network, account, slot, transaction_hash, event)
+    );
+    CREATE INDEX IF NOT EXISTS account_lifecycle_transaction
+        ON account_lifecycle (network, transaction_hash);
+    CREATE TABLE IF NOT EXISTS program_upgrades (
+        network TEXT NOT NULL,
+        program TEXT NOT NULL,
+        slot INTEGER NOT NULL,
+        transaction_hash TEXT,
+        PRIMARY KEY (network, program, slot)
+    );
+    CREATE TABLE IF NOT EXISTS checkpoints (
+        pipeline TEXT PRIMARY KEY,
+        last_slot INTEGER,
+        last_signature TEXT,
+        updated_at INTEGER NOT NULL,
+        ranges TEXT NOT NULL
+    );
+";
+
+/// Persists records into a SQLite database, one table per record kind.
+///
+/// Writes are idempotent: re-writing a transaction replaces its previous rows. Every row carries
+/// the network of its record, so several networks can share a database; the slots finalized,
+/// invalidated or rolled back are those of the sink's network.
+///
+/// The raw data captured of instructions goes to `raw_instructions`. Instructions decoded again
+/// out of it get a new `revision` of their function and properties, the previous ones being kept
+/// with `superseded` set.
+///
+/// Functions record the `decoder_version` of the processor that decoded them, 0 for those written
+/// before versions were kept (the column is added to databases created before).
+///
+/// Snapshots of lending positions go to `lending_positions`, in the order they were taken, and are
+/// removed along with the slots they were taken in. The latest position is that of the latest
+/// slot, whatever the order slots were written in.
+///
+/// The activity of the signers of transactions goes to `signer_activity`, keyed by signer then
+/// slot, so that what a wallet signed over a range of slots is read off its primary key.
+///
+/// The accounts transactions created and closed go to `account_lifecycle`, keyed by account then
+/// slot.
+///
+/// The deployments and upgrades of programs go to `program_upgrades`, keyed by program then slot,
+/// and are removed along with their slots.
+///
+/// Blocks and transactions record whether their timestamp was estimated (`timestamp_estimated`),
+/// and estimates replaced later update every record of their slot.
+///
+/// Transactions record their fee broken down into base and priority fees, null when it doesn't
+/// add up (see `fees`).
+///
+/// The sink supports transactions: between `begin` and `commit`, writes are only persisted along
+/// with the checkpoint committed (to `checkpoints`, one row per pipeline), and are rolled back if
+/// the sink is dropped before.
+pub struct SqliteSink {
+    connection: Connection,
+    network: Network,
+}
+
+impl SqliteSink {
+    /// Opens (or creates) the database at the given path and makes sure the schema exists.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteSink, SinkError> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        // Columns added since the tables were first created.
+        for (table, column, definition) in &[
+            ("instruction_functions", "decoder_version", "INTEGER NOT NULL DEFAULT 0"),
+            ("blocks", "timestamp_estimated", "INTEGER NOT NULL DEFAULT 0"),
+            ("transactions", "timestamp_estimated", "INTEGER NOT NULL DEFAULT 0"),
+            ("blocks", "epoch", "INTEGER"),
+            ("blocks", "leader", "TEXT"),
+            ("transactions", "base_fee_lamports", "INTEGER"),
+            ("transactions", "priority_fee_lamports", "INTEGER"),
+            ("transactions", "compute_unit_price_micro_lamports", "INTEGER"),
+        ] {
+            let columns: Vec<String> = connection.prepare(&format!("PRAGMA table_info({})", table))?
+                .query_map(params![], |row| row.get(1))?
+                .collect::<Result<_, _>>()?;
+            if !columns.iter().any(|existing| existing == column) {
+                connection.execute_batch(&format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))?;
+            }
+        }
+        connection.execute_batch("CREATE INDEX IF NOT EXISTS instruction_functions_version
+            ON instruction_functions (network, program, decoder_version);
+            CREATE INDEX IF NOT EXISTS blocks_timestamp_estimated
+            ON blocks (network, slot) WHERE timestamp_estimated = 1")?;
+
+        Ok(SqliteSink { connection, network: Network::default() })
+    }
+
+    /// Finalizes, invalidates and rolls back the slots of `network`, mainnet by default.
+    pub fn with_network(mut self, network: Network) -> Self {
+        self.network = network;
+        self
+    }
+
+    fn insert_block(
+        connection: &Connection,
+        block: &BlockRecord,
+    ) -> Result<(), SinkError> {
+        connection.execute(
+            "INSERT OR REPLACE INTO blocks
+                (network, slot, parent_slot, blockhash, previous_blockhash, block_time, finalized,
+                 timestamp_estimated, epoch, leader)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                block.network.as_str(),
+                block.slot as i64,
+                block.parent_slot as i64,
+                block.blockhash,
+                block.previous_blockhash,
+                block.block_time,
+                block.finalized,
+                block.timestamp_estimated,
+                block.epoch.map(|epoch| epoch as i64),
+                block.leader,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn insert_transactions(
+        connection: &Connection,
+        transactions: &[TransactionRecord],
+    ) -> Result<(), SinkError> {
+        for transaction in transactions {
+            connection.execute(
+                "INSERT OR REPLACE INTO transactions
+                    (network, transaction_hash, slot, fee, succeeded, timestamp, finalized,
+                     timestamp_estimated, base_fee_lamports, priority_fee_lamports,
+                     compute_unit_price_micro_lamports)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    transaction.network.as_str(),
+                    transaction.transaction_hash,
+                    transaction.slot as i64,
+                    transaction.fee as i64,
+                    transaction.succeeded,
+                    transaction.timestamp,
+                    transaction.finalized,
+                    transaction.timestamp_estimated,
+                    transaction.base_fee_lamports.map(|fee| fee as i64),
+                    transaction.priority_fee_lamports.map(|fee| fee as i64),
+                    transaction.compute_unit_price_micro_lamports.map(|price| price as i64),
+                ],
+            )?;
+            for table in &[
+                "instruction_properties", "instruction_functions", "raw_instructions",
+                "signer_activity", "account_lifecycle",
+            ] {
+                connection.execute(
+                    &format!("DELETE FROM {} WHERE network = ?1 AND transaction_hash = ?2", table),
+                    params![transaction.network.as_str(), transaction.transaction_hash],
+                )?;
+            }
+
+            for instruction_set in &transaction.instruction_sets {
+                SqliteSink::insert_instruction_set(connection, instruction_set, 0)?;
+                if let Some(raw_data) = &instruction_set.raw_data {
+                    let function = &instruction_set.function;
+                    SqliteSink::insert_raw_instruction(connection, &RawInstruction {
+                        network: function.network,
+                        transaction_hash: function.transaction_hash.clone(),
+                        tx_instruction_id: function.tx_instruction_id,
+                        parent_index: function.parent_index,
+                        program: function.program,
+                        timestamp: function.timestamp,
+                        raw_data: raw_data.clone(),
+                        decoded: true,
+                    })?;
+                }
+            }
+
+            for activity in &transaction.signer_activity {
+                connection.execute(
+                    "INSERT OR REPLACE INTO signer_activity
+                        (network, signer, slot, transaction_hash, parent_index, instruction_index,
+                         fee_payer, program, function_name, timestamp)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    params![
+                        activity.network.as_str(),
+                        activity.signer,
+                        activity.slot as i64,
+                        &*activity.transaction_hash,
+                        activity.parent_index,
+                        activity.instruction_index,
+                        activity.fee_payer,
+                        activity.program.as_str(),
+                        activity.function_name.as_str(),
+                        activity.timestamp,
+                    ],
+                )?;
+            }
+
+            for event in &transaction.account_lifecycle {
+                connection.execute(
+                    "INSERT OR REPLACE INTO account_lifecycle
+                        (network, account, slot, transaction_hash, event, owner_program, lamports,
+                         rent_exempt, source_instruction_index, timestamp)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    params![
+                        event.network.as_str(),
+                        event.account,
+                        event.slot as i64,
+                        &*event.transaction_hash,
+                        event.event.as_str(),
+                        event.owner_program,
+                        event.lamports as i64,
+                        event.rent_exempt,
+                        event.source_instruction_index,
+                        event.timestamp,
+                    ],
+                )?;
+            }
+
+            for failure in &transaction.decode_failures {
+                if let Some(raw_data) = &failure.raw_data {
+                    SqliteSink::insert_raw_instruction(connection, &RawInstruction {
+                        network: transaction.network,
+                        transaction_hash: Arc::from(transaction.transaction_hash.as_str()),
+                        tx_instruction_id: failure.tx_instruction_id,
+                        parent_index: failure.parent_index,
+                        program: failure.program,
+                        timestamp: transaction.timestamp,
+                        raw_data: raw_data.clone(),
+                        decoded: false,
+                    })?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert_instruction_set(
+        connection: &Connection,
+        instruction_set: &InstructionSet,
+        revision: i64,
+    ) -> Result<(), SinkError> {
+        let function = &instruction_set.function;
+        connection.execute(
+            "INSERT OR REPLACE INTO instruction_functions
+                (network, transaction_hash, tx_instruction_id, parent_index, program,
+                 function_name, timestamp, revision, decoder_version)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                function.network.as_str(),
+                &*function.transaction_hash,
+                function.tx_instruction_id,
+                function.parent_index,
+                function.program.as_str(),
+                function.function_name.as_str(),
+                function.timestamp,
+                revision,
+                function.decoder_version,
+            ],
+        )?;
+
+        for property in &instruction_set.properties {
+            connection.execute(
+                "INSERT INTO instruction_properties
+                    (network, transaction_hash, tx_instruction_id, parent_index, key, value,
+                     value_type, parent_key, timestamp, revision)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    function.network.as_str(),
+                    &*property.transaction_hash,
+                    property.tx_instruction_id,
+                    property.parent_index,
+                    property.key.as_str(),
+                    property.value,
+                    property.value_type.as_str(),
+                    property.parent_key.as_str(),
+                    property.timestamp,
+                    revision,
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn insert_raw_instruction(
+        connection: &Connection,
+        raw_instruction: &RawInstruction,
+    ) -> Result<(), SinkError> {
+        connection.execute(
+            "INSERT OR REPLACE INTO raw_instructions
+                (network, transaction_hash, tx_instruction_id, parent_index, program, timestamp,
+                 data, data_len, decoded)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                raw_instruction.network.as_str(),
+                &*raw_instruction.transaction_hash,
+                raw_instruction.tx_instruction_id,
+                raw_instruction.parent_index,
+                raw_instruction.program.as_str(),
+                raw_instruction.timestamp,
+                raw_instruction.raw_data.data,
+                raw_instruction.raw_data.len as i64,
+                raw_instruction.decoded,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Writes `instruction_set` as the latest revision of its instruction, superseding the
+    /// previous ones. The call tree properties of the instruction, which can't be derived again
+    /// out of its raw data, are carried over.
+    fn supersede(
+        connection: &Connection,
+        instruction_set: &InstructionSet,
+    ) -> Result<(), SinkError> {
+        let function = &instruction_set.function;
+        let revision: i64 = connection.query_row(
+            "SELECT COALESCE(MAX(revision) + 1, 0) FROM instruction_functions
+                WHERE network = ?1 AND transaction_hash = ?2 AND parent_index = ?3
+                    AND tx_instruction_id = ?4",
+            params![
+                function.network.as_str(),
+                &*function.transaction_hash,
+                function.parent_index,
+                function.tx_instruction_id,
+            ],
+            |row| row.get(0),
+        )?;
+
+        connection.execute(
+            "INSERT INTO instruction_properties
+                (network, transaction_hash, tx_instruction_id, parent_index, key, value,
+                 value_type, parent_key, timestamp, revision)
+                SELECT network, transaction_hash, tx_instruction_id, parent_index, key, value,
+                    value_type, parent_key, timestamp, ?5
+                FROM instruction_properties
+                WHERE network = ?1 AND transaction_hash = ?2 AND parent_index = ?3
+                    AND tx_instruction_id = ?4 AND superseded = 0 AND parent_key = ''
+                    AND key IN ('stack_height', 'call_path')",
+            params![
+                function.network.as_str(),
+                &*function.transaction_hash,
+                function.parent_index,
+                function.tx_instruction_id,
+                revision,
+            ],
+        )?;
+        for table in &["instruction_functions", "instruction_properties"] {
+            connection.execute(
+                &format!("UPDATE {} SET superseded = 1
+                    WHERE network = ?1 AND transaction_hash = ?2 AND parent_index = ?3
+                        AND tx_instruction_id = ?4 AND revision < ?5", table),
+                params![
+                    function.network.as_str(),
+                    &*function.transaction_hash,
+                    function.parent_index,
+                    function.tx_instruction_id,
+                    revision,
+                ],
+            )?;
+        }
+        SqliteSink::insert_instruction_set(connection, instruction_set, revision)?;
+        connection.execute(
+            "UPDATE signer_activity SET function_name = ?5
+                WHERE network = ?1 AND transaction_hash = ?2 AND parent_index = ?3
+                    AND instruction_index = ?4",
+            params![
+                function.network.as_str(),
+                &*function.transaction_hash,
+                function.parent_index,
+                function.tx_instruction_id,
+                function.function_name.as_str(),
+            ],
+        )?;
+        connection.execute(
+            "UPDATE raw_instructions SET decoded = 1
+                WHERE network = ?1 AND transaction_hash = ?2 AND parent_index = ?3
+                    AND tx_instruction_id = ?4",
+            params![
+                function.network.as_str(),
+                &*function.transaction_hash,
+                function.parent_index,
+                function.tx_instruction_id,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Deletes the blocks of `network` between `first_slot` and `last_slot` (both inclusive) and
+    /// every transaction (with its instructions) in them.
+    fn delete_slots(
+        connection: &Connection,
+        network: Network,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<(), SinkError> {
+        let network = network.as_str();
+        let first_slot = first_slot as i64;
+        let last_slot = last_slot.min(i64::MAX as u64) as i64;
+        connection.execute(
+            "DELETE FROM instruction_properties WHERE network = ?1 AND transaction_hash IN
+                (SELECT transaction_hash FROM transactions
+                    WHERE network = ?1 AND slot BETWEEN ?2 AND ?3)",
+            params![network, first_slot, last_slot],
+        )?;
+        connection.execute(
+            "DELETE FROM instruction_functions WHERE network = ?1 AND transaction_hash IN
+                (SELECT transaction_hash FROM transactions
+                    WHERE network = ?1 AND slot BETWEEN ?2 AND ?3)",
+            params![network, first_slot, last_slot],
+        )?;
+        connection.execute(
+            "DELETE FROM raw_instructions WHERE network = ?1 AND transaction_hash IN
+                (SELECT transaction_hash FROM transactions
+                    WHERE network = ?1 AND slot BETWEEN ?2 AND ?3)",
+            params![network, first_slot, last_slot],
+        )?;
+        connection.execute(
+            "DELETE FROM signer_activity WHERE network = ?1 AND transaction_hash IN
+                (SELECT transaction_hash FROM transactions
+                    WHERE network = ?1 AND slot BETWEEN ?2 AND ?3)",
+            params![network, first_slot, last_slot],
+        )?;
+        connection.execute(
+            "DELETE FROM lending_positions WHERE network = ?1 AND slot BETWEEN ?2 AND ?3",
+            params![network, first_slot, last_slot])?;
+        connection.execute(
+            "DELETE FROM account_lifecycle WHERE network = ?1 AND slot BETWEEN ?2 AND ?3",
+            params![network, first_slot, last_slot])?;
+        connection.execute(
+            "DELETE FROM program_upgrades WHERE network = ?1 AND slot BETWEEN ?2 AND ?3",
+            params![network, first_slot, last_slot])?;
+        connection.execute(
+            "DELETE FROM transactions WHERE network = ?1 AND slot BETWEEN ?2 AND ?3",
+            params![network, first_slot, last_slot])?;
+        connection.execute("DELETE FROM blocks WHERE network = ?1 AND slot BETWEEN ?2 AND ?3",
+            params![network, first_slot, last_slot])?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for SqliteSink {
+    async fn write_block(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        // Writes go through savepoints, which nest within the transaction begun, if any.
+        let tx = self.connection.savepoint()?;
+        SqliteSink::insert_block(&tx, block)?;
+        SqliteSink::insert_transactions(&tx, &block.transactions)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    async fn write_block_header(&mut self, block: &BlockRecord) -> Result<(), SinkError> {
+        let tx = self.connection.savepoint()?;
+        SqliteSink::insert_block(&tx, block)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    async fn write_transactions(
+        &mut self,
+        transactions: &[TransactionRecord],
+    ) -> Result<(), SinkError> {
+        let tx = self.connection.savepoint()?;
+        SqliteSink::insert_transactions(&tx, transactions)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    async fn write_accounts(&mut self, accounts: &[AccountRecord]) -> Result<(), SinkError> {
+        let tx = self.connection.savepoint()?;
+        for account in accounts {
+            // Only ever move an account forward; late notifications must not clobber newer state.
+            tx.execute(
+                "INSERT INTO accounts
+                    (pubkey, owner, lamports, executable, data_len, slot, write_version)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                    ON CONFLICT (pubkey) DO UPDATE SET
+                        owner = excluded.owner,
+                        lamports = excluded.lamports,
+                        executable = excluded.executable,
+                        data_len = excluded.data_len,
+                        slot = excluded.slot,
+                        write_version = excluded.write_version
+                    WHERE excluded.slot > accounts.slot
+                        OR (excluded.slot = accounts.slot
+                            AND excluded.write_version > accounts.write_version)",
+                params![
+                    account.pubkey,
+                    account.owner,
+                    account.lamports as i64,
+                    account.executable,
+                    account.data_len as i64,
+                    account.slot as i64,
+                    account.write_version as i64,
+                ],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    async fn write_aggregates(&mut self, aggregates: &[WindowAggregate]) -> Result<(), SinkError> {
+        let tx = self.connection.savepoint()?;
+        for aggregate in aggregates {
+            let mut aggregate = aggregate.clone();
+            // A window is only written once, but late instructions may keep arriving for it.
+            if aggregate.late {
+                let written = tx.query_row(
+                    "SELECT count, sums FROM instruction_aggregates
+                        WHERE network = ?1 AND window_start = ?2 AND program = ?3
+                            AND function_name = ?4 AND late = 1",
+                    params![
+                        aggregate.network.as_str(),
+                        aggregate.window_start,
+                        aggregate.program.as_str(),
+                        aggregate.function_name.as_str(),
+                    ],
+                    |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+                ).optional()?;
+                if let Some((count, sums)) = written {
+                    let sums: BTreeMap<String, String> = serde_json::from_str(&sums)?;
+                    aggregate.merge(&WindowAggregate {
+                        count: count as u64,
+                        sums: sums.into_iter()
+                            .filter_map(|(key, sum)| Some((key, sum.parse().ok()?)))
+                            .collect(),
+                        ..aggregate.clone()
+                    });
+                }
+            }
+
+            // As strings, sums may not fit in an INTEGER.
+            let sums: BTreeMap<&String, String> = aggregate.sums.iter()
+                .map(|(key, sum)| (key, sum.to_string()))
+                .collect();
+            tx.execute(
+                "INSERT OR REPLACE INTO instruction_aggregates
+                    (network, window_start, window_end, program, function_name, late, count,
+                     sums)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    aggregate.network.as_str(),
+                    aggregate.window_start,
+                    aggregate.window_end,
+                    aggregate.program.as_str(),
+                    aggregate.function_name.as_str(),
+                    aggregate.late,
+                    aggregate.count as i64,
+                    serde_json::to_string(&sums)?,
+                ],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    async fn write_positions(&mut self, snapshots: &[PositionSnapshot]) -> Result<(), SinkError> {
+        let tx = self.connection.savepoint()?;
+        for snapshot in snapshots {
+            tx.execute(
+                "INSERT OR REPLACE INTO lending_positions
+                    (network, program, obligation, reserve, deposited_amount, borrowed_amount,
+                     slot, timestamp, transaction_hash, tx_instruction_id, parent_index,
+                     function_name)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                params![
+                    snapshot.network.as_str(),
+                    snapshot.program.as_str(),
+                    snapshot.obligation,
+                    snapshot.reserve,
+                    snapshot.deposited_amount as i64,
+                    snapshot.borrowed_amount as i64,
+                    snapshot.slot as i64,
+                    snapshot.timestamp,
+                    snapshot.transaction_hash,
+                    snapshot.tx_instruction_id,
+                    snapshot.parent_index,
+                    snapshot.function_name.as_str(),
+                ],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    async fn latest_positions(&mut self) -> Result<Vec<PositionSnapshot>, SinkError> {
+        let network = self.network;
+        // The snapshot of the latest slot of every position, the last one written of the slot
+        // (the largest rowid): a slot written after a later one doesn't take over.
+        let mut statement = self.connection.prepare(
+            "SELECT program, obligation, reserve, deposited_amount, borrowed_amount, slot,
+                    timestamp, transaction_hash, tx_instruction_id, parent_index, function_name
+                FROM lending_positions AS latest
+                WHERE network = ?1 AND rowid = (
+                    SELECT rowid FROM lending_positions
+                        WHERE network = latest.network AND obligation = latest.obligation
+                            AND reserve = latest.reserve AND program = latest.program
+                        ORDER BY slot DESC, rowid DESC LIMIT 1
+                )",
+        )?;
+        let snapshots = statement
+            .query_map(params![network.as_str()], |row| {
+                Ok(PositionSnapshot {
+                    network,
+                    program: Symbol::intern(&row.get::<_, String>(0)?),
+                    obligation: row.get(1)?,
+                    reserve: row.get(2)?,
+                    deposited_amount: row.get::<_, i64>(3)? as u64,
+                    borrowed_amount: row.get::<_, i64>(4)? as u64,
+                    slot: row.get::<_, i64>(5)? as u64,
+                    timestamp: row.get(6)?,
+                    transaction_hash: row.get(7)?,
+                    tx_instruction_id: row.get(8)?,
+                    parent_index: row.get(9)?,
+                    function_name: Symbol::intern(&row.get::<_, String>(10)?),
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(snapshots)
+    }
+
+    async fn write_program_upgrades(
+        &mut self,
+        upgrades: &[ProgramUpgrade],
+    ) -> Result<(), SinkError> {
+        let tx = self.connection.savepoint()?;
+        for upgrade in upgrades {
+            // The signature of an upgrade read off its ProgramData account is unknown, and
+            // doesn't replace the one known.
+            tx.execute(
+                "INSERT INTO program_upgrades (network, program, slot, transaction_hash)
+                    VALUES (?1, ?2, ?3, ?4)
+                    ON CONFLICT (network, program, slot) DO UPDATE SET
+                        transaction_hash = COALESCE(excluded.transaction_hash, transaction_hash)",
+                params![
+                    upgrade.network.as_str(),
+                    upgrade.program.as_str(),
+                    upgrade.slot as i64,
+                    upgrade.transaction_hash,
+                ],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    async fn program_upgrades(&mut self) -> Result<Vec<ProgramUpgrade>, SinkError> {
+        let network = self.network;
+        let mut statement = self.connection.prepare(
+            "SELECT program, slot, transaction_hash FROM program_upgrades WHERE network = ?1
+                ORDER BY program, slot",
+        )?;
+        let upgrades = statement
+            .query_map(params![network.as_str()], |row| {
+                Ok(ProgramUpgrade {
+                    network,
+                    program: Symbol::intern(&row.get::<_, String>(0)?),
+                    slot: row.get::<_, i64>(1)? as u64,
+                    transaction_hash: row.get(2)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(upgrades)
+    }
+
+    async fn mark_finalized(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        let tx = self.connection.savepoint()?;
+        for slot in slots {
+            let network = self.network.as_str();
+            tx.execute("UPDATE blocks SET finalized = 1 WHERE network = ?1 AND slot = ?2",
+                params![network, *slot as i64])?;
+            tx.execute(
+                "UPDATE transactions SET finalized = 1 WHERE network = ?1 AND slot = ?2",
+                params![network, *slot as i64],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    async fn invalidate(&mut self, slots: &[u64]) -> Result<(), SinkError> {
+        let tx = self.connection.savepoint()?;
+        for slot in slots {
+            SqliteSink::delete_slots(&tx, self.network, *slot, *slot)?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    async fn rollback_from_slot(&mut self, slot: u64) -> Result<(), SinkError> {
+        // Account rows are left alone: they only ever hold the latest state we've seen.
+        let tx = self.connection.savepoint()?;
+        SqliteSink::delete_slots(&tx, self.network, slot, u64::MAX)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    async fn has_transaction(&mut self, signature: &str) -> Result<Option<bool>, SinkError> {
+        let found = self.connection.query_row(
+            "SELECT 1 FROM transactions WHERE network = ?1 AND transaction_hash = ?2",
+            params![self.network.as_str(), signature],
+            |_| Ok(()),
+        ).optional()?;
+
+        Ok(Some(found.is_some()))
+    }
+
+    async fn raw_instructions(
+        &mut self,
+        filter: &RawFilter,
+    ) -> Result<Vec<RawInstruction>, SinkError> {
+        let network = self.network;
+        let mut statement = self.connection.prepare(
+            "SELECT transaction_hash, tx_instruction_id, parent_index, program, timestamp, data,
+                    data_len, decoded
+                FROM raw_instructions
+                WHERE network = ?1 AND program = ?2 AND timestamp >= ?3 AND timestamp < ?4
+                ORDER BY timestamp, transaction_hash, parent_index, tx_instruction_id",
+        )?;
+        let rows = statement.query_map(
+            params![
+                network.as_str(),
+                filter.program.as_str(),
+                filter.timestamps.start,
+                filter.timestamps.end,
+            ],
+            |row| Ok(RawInstruction {
+                network,
+                transaction_hash: Arc::from(row.get::<_, String>(0)?),
+                tx_instruction_id: row.get(1)?,
+                parent_index: row.get(2)?,
+                program: Symbol::intern(&row.get::<_, String>(3)?),
+                timestamp: row.get(4)?,
+                raw_data: RawData {
+                    data: row.get(5)?,
+                    len: row.get::<_, i64>(6)? as usize,
+                },
+                decoded: row.get(7)?,
+            }),
+        )?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    async fn write_reprocessed(
+        &mut self,
+        instruction_sets: &[InstructionSet],
+    ) -> Result<(), SinkError> {
+        let tx = self.connection.savepoint()?;
+        for instruction_set in instruction_sets {
+            SqliteSink::supersede(&tx, instruction_set)?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    async fn stale_transactions(
+        &mut self,
+        filter: &StaleFilter,
+    ) -> Result<Vec<StaleTransaction>, SinkError> {
+        let network = self.network;
+        let (after_slot, after_signature) = match &filter.after {
+            Some((slot, signature)) => (*slot as i64, signature.as_str()),
+            None => (-1, ""),
+        };
+        let mut statement = self.connection.prepare(
+            "SELECT DISTINCT t.slot, t.transaction_hash
+                FROM instruction_functions f
+                JOIN transactions t
+                    ON t.network = f.network AND t.transaction_hash = f.transaction_hash
+                WHERE f.network = ?1 AND f.program = ?2 AND f.superseded = 0
+                    AND f.decoder_version < ?3
+                    AND (t.slot > ?4 OR (t.slot = ?4 AND t.transaction_hash > ?5))
+                ORDER BY t.slot, t.transaction_hash
+                LIMIT ?6",
+        )?;
+        let transactions: Vec<(i64, String)> = statement.query_map(
+            params![
+                network.as_str(),
+                filter.program.as_str(),
+                filter.decoder_version,
+                after_slot,
+                after_signature,
+                filter.limit as i64,
+            ],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?.collect::<Result<_, _>>()?;
+
+        // The stale instructions of every transaction, along with their raw data if kept.
+        let mut instructions = self.connection.prepare(
+            "SELECT f.tx_instruction_id, f.parent_index, f.timestamp, r.data, r.data_len,
+                    r.decoded
+                FROM instruction_functions f
+                LEFT JOIN raw_instructions r
+                    ON r.network = f.network AND r.transaction_hash = f.transaction_hash
+                        AND r.parent_index = f.parent_index
+                        AND r.tx_instruction_id = f.tx_instruction_id
+                WHERE f.network = ?1 AND f.transaction_hash = ?2 AND f.program = ?3
+                    AND f.superseded = 0 AND f.decoder_version < ?4
+                ORDER BY f.parent_index, f.tx_instruction_id",
+        )?;
+        let mut stale = Vec::with_capacity(transactions.len());
+        for (slot, transaction_hash) in transactions {
+            let shared_hash: Arc<str> = Arc::from(transaction_hash.as_str());
+            let raw_instructions: Option<Vec<RawInstruction>> = instructions.query_map(
+                params![
+                    network.as_str(),
+                    transaction_hash,
+                    filter.program.as_str(),
+                    filter.decoder_version,
+                ],
+                |row| {
+                    let data: Option<String> = row.get(3)?;
+                    Ok(match data {
+                        Some(data) => Some(RawInstruction {
+                            network,
+                            transaction_hash: shared_hash.clone(),
+                            tx_instruction_id: row.get(0)?,
+                            parent_index: row.get(1)?,
+                            program: filter.program,
+                            timestamp: row.get(2)?,
+                            raw_data: RawData { data, len: row.get::<_, i64>(4)? as usize },
+                            decoded: row.get(5)?,
+                        }),
+                        None => None,
+                    })
+                },
+            )?.collect::<Result<Vec<_>, _>>()?.into_iter().collect();
+
+            stale.push(StaleTransaction { slot: slot as u64, transaction_hash, raw_instructions });
+        }
+
+        Ok(stale)
+    }
+
+    async fn read_transactions(
+        &mut self,
+        filter: &ReadFilter,
+    ) -> Result<Vec<TransactionRecord>, SinkError> {
+        let network = self.network;
+        let (after_slot, after_signature) = match &filter.after {
+            Some((slot, signature)) => (*slot as i64, signature.as_str()),
+            None => (-1, ""),
+        };
+        let mut statement = self.connection.prepare(
+            "SELECT transaction_hash, slot, fee, succeeded, timestamp, finalized,
+                    timestamp_estimated, base_fee_lamports, priority_fee_lamports,
+                    compute_unit_price_micro_lamports
+                FROM transactions
+                WHERE network = ?1 AND slot BETWEEN ?2 AND ?3
+                    AND (slot > ?4 OR (slot = ?4 AND transaction_hash > ?5))
+                ORDER BY slot, transaction_hash
+                LIMIT ?6",
+        )?;
+        let mut transactions: Vec<TransactionRecord> = statement.query_map(
+            params![
+                network.as_str(),
+                filter.first_slot.min(i64::MAX as u64) as i64,
+                filter.last_slot.min(i64::MAX as u64) as i64,
+                after_slot,
+                after_signature,
+                filter.limit as i64,
+            ],
+            |row| Ok(TransactionRecord {
+                transaction_hash: row.get(0)?,
+                slot: row.get::<_, i64>(1)? as u64,
+                fee: row.get::<_, i64>(2)? as u64,
+                base_fee_lamports: row.get::<_, Option<i64>>(7)?.map(|fee| fee as u64),
+                priority_fee_lamports: row.get::<_, Option<i64>>(8)?.map(|fee| fee as u64),
+                compute_unit_price_micro_lamports: row.get::<_, Option<i64>>(9)?
+                    .map(|price| price as u64),
+                succeeded: row.get(3)?,
+                timestamp: row.get(4)?,
+                timestamp_estimated: row.get(6)?,
+                finalized: row.get(5)?,
+                network,
+                instruction_sets: Vec::new(),
+                decode_failures: Vec::new(),
+                call_tree: CallTree::default(),
+                signer_activity: Vec::new(),
+                account_lifecycle: Vec::new(),
+                confirmed: true,
+                simulation: None,
+            }),
+        )?.collect::<Result<_, _>>()?;
+
+        // The latest revision of every instruction, in the order they were written.
+        let mut read_functions = self.connection.prepare(
+            "SELECT f.tx_instruction_id, f.parent_index, f.program, f.function_name, f.timestamp,
+                    f.decoder_version, f.revision, r.data, r.data_len
+                FROM instruction_functions f
+                LEFT JOIN raw_instructions r
+                    ON r.network = f.network AND r.transaction_hash = f.transaction_hash
+                        AND r.parent_index = f.parent_index
+                        AND r.tx_instruction_id = f.tx_instruction_id
+                WHERE f.network = ?1 AND f.transaction_hash = ?2 AND f.superseded = 0
+                ORDER BY f.rowid",
+        )?;
+        let mut read_properties = self.connection.prepare(
+            "SELECT key, value, value_type, parent_key, timestamp
+                FROM instruction_properties
+                WHERE network = ?1 AND transaction_hash = ?2 AND parent_index = ?3
+                    AND tx_instruction_id = ?4 AND revision = ?5
+                ORDER BY rowid",
+        )?;
+        for transaction in &mut transactions {
+            let transaction_hash: Arc<str> = Arc::from(transaction.transaction_hash.as_str());
+            let functions = read_functions.query_map(
+                params![network.as_str(), transaction.transaction_hash],
+                |row| {
+                    let function = InstructionFunction {
+                        tx_instruction_id: row.get(0)?,
+                        transaction_hash: transaction_hash.clone(),
+                        parent_index: row.get(1)?,
+                        program: Symbol::intern(&row.get::<_, String>(2)?),
+                        function_name: Symbol::intern(&row.get::<_, String>(3)?),
+                        timestamp: row.get(4)?,
+                        network,
+                        decoder_version: row.get(5)?,
+                    };
+                    let raw_data = match row.get::<_, Option<String>>(7)? {
+                        Some(data) => Some(RawData { data, len: row.get::<_, i64>(8)? as usize }),
+                        None => None,
+                    };
+                    Ok((function, row.get::<_, i64>(6)?, raw_data))
+                },
+            )?.collect::<Result<Vec<_>, _>>()?;
+
+            for (function, revision, raw_data) in functions {
+                let properties = read_properties.query_map(
+                    params![
+                        network.as_str(),
+                        &*transaction_hash,
+                        function.parent_index,
+                        function.tx_instruction_id,
+                        revision,
+                    ],
+                    |row| Ok(InstructionProperty {
+                        tx_instruction_id: function.tx_instruction_id,
+                        transaction_hash: transaction_hash.clone(),
+                        parent_index: function.parent_index,
+                        key: Symbol::intern(&row.get::<_, String>(0)?),
+                        value: row.get(1)?,
+                        value_type: PropertyType::from_name(&row.get::<_, String>(2)?),
+                        parent_key: Symbol::intern(&row.get::<_, String>(3)?),
+                        timestamp: row.get(4)?,
+                    }),
+                )?.collect::<Result<_, _>>()?;
+                let instruction_set = InstructionSet { function, properties, raw_data };
+                transaction.instruction_sets.push(instruction_set);
+            }
+        }
+
+        Ok(transactions)
+    }
+
+    async fn row_counts(
+        &mut self,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Option<RowCounts>, SinkError> {
+        let network = self.network.as_str();
+        let first_slot = first_slot.min(i64::MAX as u64) as i64;
+        let last_slot = last_slot.min(i64::MAX as u64) as i64;
+        let slots = params![network, first_slot, last_slot];
+        let count = |sql: &str| -> Result<u64, SinkError> {
+            Ok(self.connection.query_row(sql, slots, |row| row.get::<_, i64>(0))? as u64)
+        };
+        let instructions = |table: &str| format!(
+            "SELECT COUNT(*) FROM {} i
+                JOIN transactions t
+                    ON t.network = i.network AND t.transaction_hash = i.transaction_hash
+                WHERE i.network = ?1 AND i.superseded = 0 AND t.slot BETWEEN ?2 AND ?3", table);
+
+        Ok(Some(RowCounts {
+            transactions: count(
+                "SELECT COUNT(*) FROM transactions WHERE network = ?1 AND slot BETWEEN ?2 AND ?3")?,
+            functions: count(&instructions("instruction_functions"))?,
+            properties: count(&instructions("instruction_properties"))?,
+        }))
+    }
+
+    async fn estimated_timestamps(
+        &mut self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, i64)>, SinkError> {
+        let mut statement = self.connection.prepare(
+            "SELECT slot, block_time FROM blocks
+                WHERE network = ?1 AND timestamp_estimated = 1 AND slot > ?2
+                ORDER BY slot
+                LIMIT ?3",
+        )?;
+        let after = after.map_or(-1, |slot| slot as i64);
+        let rows = statement.query_map(
+            params![self.network.as_str(), after, limit as i64],
+            |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, Option<i64>>(1)?.unwrap_or(0))),
+        )?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    async fn update_timestamps(&mut self, updates: &[TimestampUpdate]) -> Result<(), SinkError> {
+        let tx = self.connection.savepoint()?;
+        for update in updates {
+            let (network, slot) = (self.network.as_str(), update.slot as i64);
+            tx.execute(
+                "UPDATE blocks SET block_time = ?3, timestamp_estimated = ?4
+                    WHERE network = ?1 AND slot = ?2",
+                params![network, slot, update.timestamp, update.estimated],
+            )?;
+            // Instructions are keyed by their transaction only.
+            for table in &["instruction_functions", "instruction_properties", "raw_instructions"] {
+                tx.execute(
+                    &format!("UPDATE {} SET timestamp = ?3
+                        WHERE network = ?1 AND transaction_hash IN
+                            (SELECT transaction_hash FROM transactions
+                                WHERE network = ?1 AND slot = ?2)", table),
+                    params![network, slot, update.timestamp],
+                )?;
+            }
+            for table in &["signer_activity", "lending_positions", "account_lifecycle"] {
+                tx.execute(
+                    &format!("UPDATE {} SET timestamp = ?3 WHERE network = ?1 AND slot = ?2",
+                             table),
+                    params![network, slot, update.timestamp],
+                )?;
+            }
+            tx.execute(
+                "UPDATE transactions SET timestamp = ?3, timestamp_estimated = ?4
+                    WHERE network = ?1 AND slot = ?2",
+                params![network, slot, update.timestamp, update.estimated],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn supports_transactions(&self) -> bool {
+        true
+    }
+
+    async fn begin(&mut self) -> Result<(), SinkError> {
+        self.connection.execute_batch("BEGIN")?;
+
+        Ok(())
+    }
+
+    async fn commit(
+        &mut self,
+        pipeline: &str,
+        checkpoint: Option<&Checkpoint>,
+    ) -> Result<(), SinkError> {
+        if let Some(checkpoint) = checkpoint {
+            self.connection.execute(
+                "INSERT OR REPLACE INTO checkpoints
+                    (pipeline, last_slot, last_signature, updated_at, ranges)
+                    VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    pipeline,
+                    checkpoint.last_slot.map(|slot| slot as i64),
+                    checkpoint.last_signature,
+                    checkpoint.updated_at,
+                    serde_json::to_string(&checkpoint.ranges)?,
+                ],
+            )?;
+        }
+        self.connection.execute_batch("COMMIT")?;
+
+        Ok(())
+    }
+
+    async fn committed_checkpoint(
+        &mut self,
+        pipeline: &str,
+    ) -> Result<Option<Checkpoint>, SinkError> {
+        let row = self.connection.query_row(
+            "SELECT last_slot, last_signature, updated_at, ranges FROM checkpoints
+                WHERE pipeline = ?1",
+            params![pipeline],
+            |row| Ok((
+                row.get::<_, Option<i64>>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+            )),
+        ).optional()?;
+
+        match row {
+            Some((last_slot, last_signature, updated_at, ranges)) => Ok(Some(Checkpoint {
+                last_slot: last_slot.map(|slot| slot as u64),
+                last_signature,
+                updated_at,
+                ranges: serde_json::from_str(&ranges)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    async fn shutdown(&mut self) -> Result<(), SinkError> {
+        // Every write is committed by now, so there's nothing left to persist; just fold the
+        // write-ahead log (if any) back into the database so it's self-contained.
+        self.connection.query_row("PRAGMA wal_checkpoint(TRUNCATE)", params![], |_| Ok(()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::instruction::CompiledInstruction;
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+    use crate::checkpoint::file::FileCheckpointStore;
+    use crate::checkpoint::{Checkpoint, Checkpointing};
+    use crate::ingest::pool::RpcPool;
+    use crate::ingest::timestamps::{BlockTimeSource, TimestampConfig, TimestampResolver};
+    use crate::ingest::IngestError;
+    use crate::registry::{InstructionContext, ProcessorRegistry, ProgramProcessor, RawCapture};
+    use crate::reindex::{reindex, ReindexConfig, ReindexSummary};
+    use crate::reprocess::reprocess_raw;
+    use crate::transaction::{decode_transaction, RawTransaction};
+    use crate::Instruction;
+
+    fn registry(program: &Pubkey, function_name: &'static str) -> ProcessorRegistry {
+        let mut registry = ProcessorRegistry::new();
+        registry.set_raw_capture(RawCapture::Always);
+        registry.register_fn(&program.to_string(), move |ctx| {
+            Ok(vec![ctx.instruction_set(function_name, vec![("amount", ctx.data[0].to_string())])])
+        });
+        registry
+    }
+
+    #[tokio::test]
+    async fn reprocessing_supersedes_what_was_decoded() {
+        let program = Pubkey::new_unique();
+        let transaction = RawTransaction {
+            slot: 42,
+            block_time: Some(1_000),
+            transaction_hash: "signature".to_string(),
+            account_keys: vec![Pubkey::new_unique(), program],
+            num_required_signatures: 1,
+            instructions: vec![
+                CompiledInstruction { program_id_index: 1, accounts: vec![0], data: vec![7] },
+            ],
+            inner_instructions: vec![],
+            token_mints: vec![],
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        };
+        let mut sink = SqliteSink::open(":memory:").unwrap();
+        let record = decode_transaction(&registry(&program, "increment"), &transaction);
+        sink.write_transactions(&[record]).await.unwrap();
+
+        let filter = RawFilter {
+            program: Symbol::intern(&program.to_string()),
+            timestamps: 0..2_000,
+        };
+        let summary = reprocess_raw(&mut sink, &registry(&program, "decrement"), &filter)
+            .await
+            .unwrap();
+        assert_eq!((summary.read, summary.decoded, summary.failed), (1, 1, 0));
+
+        let functions: Vec<(String, i64, bool)> = sink.connection
+            .prepare("SELECT function_name, revision, superseded FROM instruction_functions
+                ORDER BY revision")
+            .unwrap()
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(functions, vec![
+            ("increment".to_string(), 0, true),
+            ("decrement".to_string(), 1, false),
+        ]);
+        // The call tree properties are carried over to the new revision.
+        let current: Vec<(String, String)> = sink.connection
+            .prepare("SELECT key, value FROM instruction_properties WHERE superseded = 0
+                ORDER BY key")
+            .unwrap()
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(current, vec![
+            ("amount".to_string(), "7".to_string()),
+            ("call_path".to_string(), "0".to_string()),
+            ("stack_height".to_string(), "1".to_string()),
+        ]);
+
+        let outside = RawFilter { timestamps: 0..1_000, ..filter };
+        assert!(sink.raw_instructions(&outside).await.unwrap().is_empty());
+    }
+
+    // Decodes every instruction to a transfer of its first byte, as of a given version.
+    struct Versioned(u32);
+
+    impl ProgramProcessor for Versioned {
+        fn name(&self) -> &str {
+            "versioned"
+        }
+
+        fn program_ids(&self) -> Vec<String> {
+            vec![]
+        }
+
+        fn decode(&self, instruction: Instruction) -> Option<InstructionSet> {
+            let context = InstructionContext {
+                program: instruction.program,
+                data: &instruction.data,
+                accounts: &[],
+                tx_instruction_id: instruction.tx_instruction_id,
+                parent_index: instruction.parent_index,
+                transaction_hash: &instruction.transaction_hash,
+                timestamp: instruction.timestamp,
+                network: instruction.network,
+                slot: None,
+            };
+            let amount = instruction.data[0].to_string();
+            Some(context.instruction_set("transfer", vec![("amount", amount)]))
+        }
+
+        fn decoder_version(&self) -> u32 {
+            self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn stale_rows_are_reindexed_where_the_last_reindex_stopped() {
+        let program = Pubkey::new_unique();
+        let versioned = |version| {
+            let mut registry = ProcessorRegistry::new();
+            registry.set_raw_capture(RawCapture::Always);
+            registry.register_processor(&program.to_string(), Arc::new(Versioned(version)));
+            registry
+        };
+        let records: Vec<_> = (0..3u8)
+            .map(|n| decode_transaction(&versioned(1), &RawTransaction {
+                slot: 40 + n as u64,
+                block_time: Some(1_000),
+                transaction_hash: format!("signature-{}", n),
+                account_keys: vec![Pubkey::new_unique(), program],
+                num_required_signatures: 1,
+                instructions: vec![
+                    CompiledInstruction { program_id_index: 1, accounts: vec![0], data: vec![n] },
+                ],
+                inner_instructions: vec![],
+                token_mints: vec![],
+                fee: 5000,
+                succeeded: true,
+                stack_heights: vec![],
+                log_messages: vec![],
+                pre_balances: vec![],
+                post_balances: vec![],
+            }))
+            .collect();
+        let mut sink = SqliteSink::open(":memory:").unwrap();
+        sink.write_transactions(&records).await.unwrap();
+
+        let filter = StaleFilter {
+            program: Symbol::intern(&program.to_string()),
+            decoder_version: 2,
+            after: None,
+            limit: 2,
+        };
+        let stale = sink.stale_transactions(&filter).await.unwrap();
+        let signatures: Vec<_> = stale.iter()
+            .map(|stale| stale.transaction_hash.as_str())
+            .collect();
+        assert_eq!(signatures, vec!["signature-0", "signature-1"]);
+        assert_eq!(stale[1].raw_instructions.as_ref().unwrap()[0].raw_data, RawData::new(&[1]));
+        let after = StaleFilter { after: Some((41, "signature-1".to_string())), ..filter.clone() };
+        assert_eq!(sink.stale_transactions(&after).await.unwrap().len(), 1);
+        let current = StaleFilter { decoder_version: 1, ..filter.clone() };
+        assert!(sink.stale_transactions(&current).await.unwrap().is_empty());
+
+        // A re-index interrupted after its first batch picks up after it.
+        let directory = std::env::temp_dir()
+            .join(format!("spi-wrapper-reindex-checkpoints-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let checkpoint = Checkpointing::new(Arc::new(FileCheckpointStore::new(&directory)),
+                                            "indexer");
+        let pipeline = format!("indexer-reindex-{}-v2", program);
+        checkpoint.store.save(&pipeline, &Checkpoint::new(Some(41), Some("signature-1".into())))
+            .await
+            .unwrap();
+        // Everything is decoded again out of raw data, so RPC is never called.
+        let rpc = Arc::new(RpcPool::single("http://127.0.0.1:1".to_string()));
+        let config = ReindexConfig { batch_size: 2, ..ReindexConfig::default() };
+        let registry = versioned(2);
+        let summary = reindex(&mut sink, &registry, &rpc, &program.to_string(), &config,
+                              Some(&checkpoint)).await.unwrap();
+        assert_eq!(summary, ReindexSummary {
+            transactions: 1,
+            from_raw_data: 1,
+            ..ReindexSummary::default()
+        });
+        assert_eq!(sink.stale_transactions(&filter).await.unwrap().len(), 2);
+
+        let summary = reindex(&mut sink, &registry, &rpc, &program.to_string(), &config, None)
+            .await
+            .unwrap();
+        assert_eq!(summary.from_raw_data, 2);
+        assert!(sink.stale_transactions(&filter).await.unwrap().is_empty());
+        let versions: Vec<(String, u32)> = sink.connection
+            .prepare("SELECT transaction_hash, decoder_version FROM instruction_functions
+                WHERE superseded = 0 ORDER BY transaction_hash")
+            .unwrap()
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(versions, (0..3).map(|n| (format!("signature-{}", n), 2)).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn the_latest_positions_are_those_of_the_latest_slot_not_rolled_back() {
+        let snapshot = |slot: u64, signature: &str, reserve: &str, deposited_amount| {
+            PositionSnapshot {
+                network: Network::Mainnet,
+                program: "lending".into(),
+                obligation: "obligation".to_string(),
+                reserve: reserve.to_string(),
+                deposited_amount,
+                borrowed_amount: 0,
+                slot,
+                timestamp: 0,
+                transaction_hash: signature.to_string(),
+                tx_instruction_id: 0,
+                parent_index: -1,
+                function_name: "deposit-obligation-collateral".into(),
+            }
+        };
+        let mut sink = SqliteSink::open(":memory:").unwrap();
+        sink.write_positions(&[
+            snapshot(50, "deposit-1", "sol", 100),
+            snapshot(50, "deposit-2", "sol", 300),
+            snapshot(50, "deposit-2", "usdc", 20),
+        ]).await.unwrap();
+        sink.write_positions(&[snapshot(51, "deposit-3", "sol", 400)]).await.unwrap();
+        // A slot written late doesn't take over.
+        sink.write_positions(&[snapshot(49, "deposit-0", "usdc", 5)]).await.unwrap();
+
+        let latest = |positions: Vec<PositionSnapshot>| {
+            let mut positions: Vec<_> = positions.into_iter()
+                .map(|position| (position.reserve, position.slot, position.deposited_amount))
+                .collect();
+            positions.sort();
+            positions
+        };
+        assert_eq!(latest(sink.latest_positions().await.unwrap()), vec![
+            ("sol".to_string(), 51, 400),
+            ("usdc".to_string(), 50, 20),
+        ]);
+
+        sink.rollback_from_slot(51).await.unwrap();
+        assert_eq!(latest(sink.latest_positions().await.unwrap()), vec![
+            ("sol".to_string(), 50, 300),
+            ("usdc".to_string(), 50, 20),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn every_signer_of_a_transaction_gets_its_instructions() {
+        let program = Pubkey::new_unique();
+        let (payer, cosigner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let transaction = RawTransaction {
+            slot: 42,
+            block_time: Some(1_000),
+            transaction_hash: "multisig".to_string(),
+            account_keys: vec![payer, cosigner, Pubkey::new_unique(), program],
+            num_required_signatures: 2,
+            instructions: vec![
+                CompiledInstruction { program_id_index: 3, accounts: vec![2], data: vec![7] },
+                CompiledInstruction { program_id_index: 3, accounts: vec![2], data: vec![8] },
+            ],
+            inner_instructions: vec![],
+            token_mints: vec![],
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        };
+        let record = decode_transaction(&registry(&program, "increment"), &transaction);
+        let mut sink = SqliteSink::open(":memory:").unwrap();
+        // Written twice, as when a transaction is delivered again.
+        sink.write_transactions(&[record.clone(), record]).await.unwrap();
+
+        let activity = |sink: &SqliteSink| -> Vec<(String, bool, i64)> {
+            sink.connection
+                .prepare("SELECT signer, fee_payer, instruction_index FROM signer_activity
+                    ORDER BY fee_payer DESC, instruction_index")
+                .unwrap()
+                .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap()
+        };
+        assert_eq!(activity(&sink), vec![
+            (payer.to_string(), true, 0),
+            (payer.to_string(), true, 1),
+            (cosigner.to_string(), false, 0),
+            (cosigner.to_string(), false, 1),
+        ]);
+
+        sink.rollback_from_slot(42).await.unwrap();
+        assert!(activity(&sink).is_empty());
+    }
+
+    /// Has a time for every block, as once the blocks read without one got it.
+    struct KnownTimes;
+
+    #[async_trait]
+    impl BlockTimeSource for KnownTimes {
+        async fn block_time(&self, slot: u64) -> Result<Option<i64>, IngestError> {
+            Ok(Some(1_000 + slot as i64))
+        }
+    }
+
+    #[tokio::test]
+    async fn blocks_keep_their_epoch_and_leader() {
+        let leader = Pubkey::new_unique().to_string();
+        let mut sink = SqliteSink::open(":memory:").unwrap();
+        for (slot, epoch, leader) in &[(432_000, Some(1), Some(leader.clone())), (7, None, None)] {
+            sink.write_block(&BlockRecord {
+                slot: *slot,
+                parent_slot: slot - 1,
+                blockhash: format!("blockhash-{}", slot),
+                previous_blockhash: "previous".to_string(),
+                block_time: None,
+                timestamp_estimated: false,
+                finalized: true,
+                network: Network::Mainnet,
+                epoch: *epoch,
+                leader: leader.clone(),
+                transactions: vec![],
+            }).await.unwrap();
+        }
+
+        let blocks: Vec<(i64, Option<i64>, Option<String>)> = sink.connection
+            .prepare("SELECT slot, epoch, leader FROM blocks ORDER BY slot")
+            .unwrap()
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(blocks, vec![(7, None, None), (432_000, Some(1), Some(leader))]);
+    }
+
+    #[tokio::test]
+    async fn repaired_timestamps_reach_every_record_of_the_slot() {
+        let program = Pubkey::new_unique();
+        let transaction = RawTransaction {
+            slot: 42,
+            block_time: Some(900),
+            transaction_hash: "estimated".to_string(),
+            account_keys: vec![Pubkey::new_unique(), Pubkey::new_unique(), program],
+            num_required_signatures: 1,
+            instructions: vec![
+                CompiledInstruction { program_id_index: 2, accounts: vec![1], data: vec![7] },
+            ],
+            inner_instructions: vec![],
+            token_mints: vec![],
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        };
+        let mut block = BlockRecord {
+            slot: 42,
+            parent_slot: 41,
+            blockhash: "blockhash".to_string(),
+            previous_blockhash: "previous".to_string(),
+            block_time: Some(900),
+            timestamp_estimated: false,
+            finalized: true,
+            network: Network::Mainnet,
+            epoch: None,
+            leader: None,
+            transactions: vec![decode_transaction(&registry(&program, "increment"), &transaction)],
+        };
+        block.set_timestamp_estimated(true);
+        let mut sink = SqliteSink::open(":memory:").unwrap();
+        sink.write_block(&block).await.unwrap();
+        assert_eq!(sink.estimated_timestamps(None, 10).await.unwrap(), vec![(42, 900)]);
+
+        let resolver = TimestampResolver::new(Arc::new(KnownTimes), TimestampConfig::default());
+        assert_eq!(resolver.repair(&mut sink, 1).await.unwrap(), 1);
+        assert!(sink.estimated_timestamps(None, 10).await.unwrap().is_empty());
+
+        let timestamps = |table: &str| -> Vec<i64> {
+            sink.connection
+                .prepare(&format!("SELECT timestamp FROM {}", table))
+                .unwrap()
+                .query_map(params![], |row| row.get(0))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap()
+        };
+        for table in &["transactions", "instruction_functions", "signer_activity"] {
+            assert_eq!(timestamps(table), vec![1_042], "{}", table);
+        }
+        // Nothing left to repair.
+        assert_eq!(resolver.repair(&mut sink, 1).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,526 @@
+//! A compact file format for batches of decoded records, to ship them between services without
+//! the weight of JSON.
+//!
+//! A snapshot starts with a header, uncompressed: the `SPIS` magic, the schema version it was
+//! written with (major then minor, little-endian `u16`s), the kind of records it holds (a byte)
+//! and how many (a little-endian `u64`). The rest is a zstd stream of the records, each preceded
+//! by its length (a little-endian `u32`).
+//!
+//! Records are encoded as JSON, which zstd shrinks to a fraction of its size, so that readers skip
+//! the fields added by newer minor versions instead of failing on them. Snapshots of another major
+//! version aren't read at all.
+//!
+//! Not to be confused with the test snapshots of [`testing`](crate::testing), or the position
+//! snapshots of lending obligations.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::transaction::TransactionRecord;
+use crate::InstructionSet;
+
+const MAGIC: &[u8; 4] = b"SPIS";
+/// The schema version snapshots are written with. Readers skip whatever a newer minor version
+/// adds, and refuse other major versions.
+pub const SCHEMA_VERSION: (u16, u16) = (1, 0);
+const COMPRESSION_LEVEL: i32 = 3;
+// Where the record count is in the header, after the magic, version and kind.
+const COUNT_OFFSET: u64 = 9;
+// Records longer than this are taken for corruption rather than allocated for.
+const MAX_RECORD_LEN: usize = 64 << 20;
+
+/// Errors that may be returned while writing or reading snapshots.
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Not a snapshot (bad magic header)")]
+    NotASnapshot,
+    #[error("Snapshot schema version {major}.{minor} isn't supported, only {}.x is",
+            SCHEMA_VERSION.0)]
+    UnsupportedVersion { major: u16, minor: u16 },
+    #[error("Unknown record kind {0}")]
+    UnknownKind(u8),
+    #[error("Expected a snapshot of {expected}, found one of {found}")]
+    WrongKind { expected: &'static str, found: &'static str },
+    #[error("Unable to serialize record: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Record {index} is invalid: {source}")]
+    Record { index: u64, source: serde_json::Error },
+    #[error("Record {index} is {len} bytes long, which is more than a record can be")]
+    RecordTooLong { index: u64, len: usize },
+    #[error("The snapshot ends after {found} of its {expected} records")]
+    Truncated { expected: u64, found: u64 },
+}
+
+/// What a snapshot holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordKind {
+    InstructionSets,
+    Transactions,
+}
+
+impl RecordKind {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(RecordKind::InstructionSets),
+            1 => Some(RecordKind::Transactions),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            RecordKind::InstructionSets => 0,
+            RecordKind::Transactions => 1,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            RecordKind::InstructionSets => "instruction sets",
+            RecordKind::Transactions => "transactions",
+        }
+    }
+}
+
+/// A record snapshots are made of.
+pub trait SnapshotRecord: Serialize + DeserializeOwned {
+    const KIND: RecordKind;
+}
+
+impl SnapshotRecord for InstructionSet {
+    const KIND: RecordKind = RecordKind::InstructionSets;
+}
+
+impl SnapshotRecord for TransactionRecord {
+    const KIND: RecordKind = RecordKind::Transactions;
+}
+
+/// The records of a snapshot.
+#[derive(Clone)]
+pub enum Batch {
+    InstructionSets(Vec<InstructionSet>),
+    Transactions(Vec<TransactionRecord>),
+}
+
+impl Batch {
+    pub fn kind(&self) -> RecordKind {
+        match self {
+            Batch::InstructionSets(_) => RecordKind::InstructionSets,
+            Batch::Transactions(_) => RecordKind::Transactions,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Batch::InstructionSets(instruction_sets) => instruction_sets.len(),
+            Batch::Transactions(transactions) => transactions.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The header of a snapshot.
+struct Header {
+    version: (u16, u16),
+    kind: RecordKind,
+    count: u64,
+}
+
+impl Header {
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.version.0.to_le_bytes())?;
+        writer.write_all(&self.version.1.to_le_bytes())?;
+        writer.write_all(&[self.kind.to_byte()])?;
+        writer.write_all(&self.count.to_le_bytes())
+    }
+
+    fn read<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        let mut magic = [0; 4];
+        match reader.read_exact(&mut magic) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(SnapshotError::NotASnapshot);
+            }
+            result => result?,
+        }
+        if &magic != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+
+        let mut fields = [0; 13];
+        reader.read_exact(&mut fields)?;
+        let major = u16::from_le_bytes([fields[0], fields[1]]);
+        let minor = u16::from_le_bytes([fields[2], fields[3]]);
+        if major != SCHEMA_VERSION.0 {
+            return Err(SnapshotError::UnsupportedVersion { major, minor });
+        }
+        let kind = RecordKind::from_byte(fields[4])
+            .ok_or(SnapshotError::UnknownKind(fields[4]))?;
+        let mut count = [0; 8];
+        count.copy_from_slice(&fields[5..]);
+
+        Ok(Header { version: (major, minor), kind, count: u64::from_le_bytes(count) })
+    }
+}
+
+/// Writes `batch` to a snapshot at `path`, replacing whatever is there.
+pub fn write_snapshot<P: AsRef<Path>>(path: P, batch: &Batch) -> Result<(), SnapshotError> {
+    match batch {
+        Batch::InstructionSets(instruction_sets) => write_records(path, instruction_sets),
+        Batch::Transactions(transactions) => write_records(path, transactions),
+    }
+}
+
+/// Writes `records` to a snapshot at `path`, replacing whatever is there.
+pub fn write_records<P, T>(path: P, records: &[T]) -> Result<(), SnapshotError>
+where
+    P: AsRef<Path>,
+    T: SnapshotRecord,
+{
+    let header = Header { version: SCHEMA_VERSION, kind: T::KIND, count: records.len() as u64 };
+    write_encoded(path.as_ref(), &header, records.iter().map(serde_json::to_vec))
+}
+
+/// Writes the records `encoded` under `header`.
+fn write_encoded<I>(path: &Path, header: &Header, encoded: I) -> Result<(), SnapshotError>
+where
+    I: Iterator<Item = serde_json::Result<Vec<u8>>>,
+{
+    let mut file = BufWriter::new(File::create(path)?);
+    header.write(&mut file)?;
+
+    let mut body = zstd::stream::write::Encoder::new(file, COMPRESSION_LEVEL)?;
+    for record in encoded {
+        let record = record?;
+        body.write_all(&(record.len() as u32).to_le_bytes())?;
+        body.write_all(&record)?;
+    }
+    body.finish()?.flush()?;
+
+    Ok(())
+}
+
+/// Writes the records of a snapshot one at a time, without holding on to all of them. The count
+/// of the header is only filled in by `finish`: until then, the snapshot reads as empty, or
+/// truncated.
+pub struct SnapshotWriter<T> {
+    body: zstd::stream::write::Encoder<'static, BufWriter<File>>,
+    count: u64,
+    record: PhantomData<fn(&T)>,
+}
+
+impl<T: SnapshotRecord> SnapshotWriter<T> {
+    /// Creates a snapshot of records of `T` at `path`, replacing whatever is there.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        Header { version: SCHEMA_VERSION, kind: T::KIND, count: 0 }.write(&mut file)?;
+        let body = zstd::stream::write::Encoder::new(file, COMPRESSION_LEVEL)?;
+
+        Ok(SnapshotWriter { body, count: 0, record: PhantomData })
+    }
+
+    pub fn write(&mut self, record: &T) -> Result<(), SnapshotError> {
+        let record = serde_json::to_vec(record)?;
+        self.body.write_all(&(record.len() as u32).to_le_bytes())?;
+        self.body.write_all(&record)?;
+        self.count += 1;
+
+        Ok(())
+    }
+
+    /// How many records were written so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Ends the stream of records and fills in their count.
+    pub fn finish(self) -> Result<u64, SnapshotError> {
+        let mut file = self.body.finish()?;
+        file.flush()?;
+        let file = file.get_mut();
+        file.seek(SeekFrom::Start(COUNT_OFFSET))?;
+        file.write_all(&self.count.to_le_bytes())?;
+        file.flush()?;
+
+        Ok(self.count)
+    }
+}
+
+/// Reads the snapshot at `path` whole.
+pub fn read_snapshot<P: AsRef<Path>>(path: P) -> Result<Batch, SnapshotError> {
+    let (header, body) = open(path.as_ref())?;
+    match header.kind {
+        RecordKind::InstructionSets => {
+            let reader = SnapshotReader::<InstructionSet>::new(header, body);
+            Ok(Batch::InstructionSets(reader.collect::<Result<_, _>>()?))
+        }
+        RecordKind::Transactions => {
+            let reader = SnapshotReader::<TransactionRecord>::new(header, body);
+            Ok(Batch::Transactions(reader.collect::<Result<_, _>>()?))
+        }
+    }
+}
+
+/// Opens the snapshot at `path`: its header, and the stream of its records.
+fn open(path: &Path) -> Result<(Header, Box<dyn Read + Send>), SnapshotError> {
+    let mut file = File::open(path)?;
+    let header = Header::read(&mut file)?;
+    let body = zstd::stream::read::Decoder::new(file)?;
+
+    Ok((header, Box::new(body)))
+}
+
+/// Reads the records of a snapshot one at a time, without loading the whole of it.
+pub struct SnapshotReader<T> {
+    header: Header,
+    body: Box<dyn Read + Send>,
+    // How many records were read, all of them once one fails.
+    read: u64,
+    record: PhantomData<fn() -> T>,
+}
+
+impl<T: SnapshotRecord> SnapshotReader<T> {
+    /// Opens the snapshot at `path`, which must hold records of `T`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        let (header, body) = open(path.as_ref())?;
+        if header.kind != T::KIND {
+            return Err(SnapshotError::WrongKind {
+                expected: T::KIND.name(),
+                found: header.kind.name(),
+            });
+        }
+
+        Ok(SnapshotReader::new(header, body))
+    }
+
+    fn new(header: Header, body: Box<dyn Read + Send>) -> Self {
+        SnapshotReader { header, body, read: 0, record: PhantomData }
+    }
+
+    /// How many records the snapshot holds.
+    pub fn count(&self) -> u64 {
+        self.header.count
+    }
+
+    /// The schema version the snapshot was written with.
+    pub fn schema_version(&self) -> (u16, u16) {
+        self.header.version
+    }
+
+    fn read_record(&mut self, index: u64) -> Result<T, SnapshotError> {
+        let mut len = [0; 4];
+        self.read_exact(&mut len, index)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_RECORD_LEN {
+            return Err(SnapshotError::RecordTooLong { index, len });
+        }
+
+        let mut record = vec![0; len];
+        self.read_exact(&mut record, index)?;
+        serde_json::from_slice(&record).map_err(|source| SnapshotError::Record { index, source })
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8], index: u64) -> Result<(), SnapshotError> {
+        self.body.read_exact(buf).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => {
+                SnapshotError::Truncated { expected: self.header.count, found: index }
+            }
+            _ => err.into(),
+        })
+    }
+}
+
+impl<T: SnapshotRecord> Iterator for SnapshotReader<T> {
+    type Item = Result<T, SnapshotError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.read >= self.header.count {
+            return None;
+        }
+
+        let index = self.read;
+        let record = self.read_record(index);
+        // Nothing can be read past a failed record.
+        self.read = if record.is_ok() { index + 1 } else { self.header.count };
+
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::Value;
+    use smallvec::smallvec;
+
+    use super::*;
+    use crate::call_tree::CallTree;
+    use crate::{InstructionFunction, InstructionProperty, Network, PropertyType};
+
+    fn transaction(n: u64) -> TransactionRecord {
+        let transaction_hash: Arc<str> = Arc::from(format!("{:0>88}", n));
+        TransactionRecord {
+            transaction_hash: transaction_hash.to_string(),
+            slot: 100 + n,
+            fee: 5000,
+            base_fee_lamports: Some(5000),
+            priority_fee_lamports: Some(0),
+            compute_unit_price_micro_lamports: Some(0),
+            succeeded: true,
+            timestamp: 1_700_000_000,
+            timestamp_estimated: false,
+            finalized: true,
+            network: Network::Mainnet,
+            instruction_sets: vec![InstructionSet {
+                function: InstructionFunction {
+                    tx_instruction_id: 0,
+                    transaction_hash: transaction_hash.clone(),
+                    parent_index: -1,
+                    program: "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA".into(),
+                    function_name: "transfer".into(),
+                    timestamp: 1_700_000_000,
+                    network: Network::Mainnet,
+                    decoder_version: 1,
+                },
+                properties: smallvec![InstructionProperty {
+                    tx_instruction_id: 0,
+                    transaction_hash,
+                    parent_index: -1,
+                    key: "amount".into(),
+                    value: (n * 1_000).to_string(),
+                    value_type: PropertyType::U64,
+                    parent_key: "".into(),
+                    timestamp: 1_700_000_000,
+                }],
+                raw_data: None,
+            }],
+            decode_failures: Vec::new(),
+            call_tree: CallTree::default(),
+            signer_activity: Vec::new(),
+            account_lifecycle: Vec::new(),
+            confirmed: true,
+            simulation: None,
+        }
+    }
+
+    fn as_json<T: Serialize>(records: &[T]) -> Vec<Value> {
+        records.iter().map(|record| serde_json::to_value(record).unwrap()).collect()
+    }
+
+    fn path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("spi-snapshot-{}-{}.spis", name, std::process::id()))
+    }
+
+    #[test]
+    fn batches_round_trip() {
+        let path = path("round-trip");
+        let transactions: Vec<_> = (0..1_000).map(transaction).collect();
+        write_snapshot(&path, &Batch::Transactions(transactions.clone())).unwrap();
+
+        let json = serde_json::to_vec(&transactions).unwrap().len() as u64;
+        let size = std::fs::metadata(&path).unwrap().len();
+        assert!(size * 5 < json, "{} bytes, {} as JSON", size, json);
+
+        match read_snapshot(&path).unwrap() {
+            Batch::Transactions(read) => assert_eq!(as_json(&read), as_json(&transactions)),
+            Batch::InstructionSets(_) => panic!("read instruction sets"),
+        }
+        let streamed = SnapshotReader::<TransactionRecord>::open(&path).unwrap();
+        assert_eq!(streamed.count(), 1_000);
+        let streamed: Vec<_> = streamed.map(Result::unwrap).collect();
+        assert_eq!(as_json(&streamed), as_json(&transactions));
+
+        let instruction_sets: Vec<_> = transactions.into_iter()
+            .flat_map(|transaction| transaction.instruction_sets)
+            .collect();
+        write_records(&path, &instruction_sets).unwrap();
+        match read_snapshot(&path).unwrap() {
+            Batch::InstructionSets(read) => assert_eq!(as_json(&read), as_json(&instruction_sets)),
+            Batch::Transactions(_) => panic!("read transactions"),
+        }
+        assert!(matches!(SnapshotReader::<TransactionRecord>::open(&path),
+                         Err(SnapshotError::WrongKind { .. })));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn records_are_streamed_into_snapshots() {
+        let path = path("streamed");
+        let transactions: Vec<_> = (0..10).map(transaction).collect();
+        let mut writer = SnapshotWriter::create(&path).unwrap();
+        for transaction in &transactions {
+            writer.write(transaction).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), 10);
+
+        let reader = SnapshotReader::<TransactionRecord>::open(&path).unwrap();
+        assert_eq!(reader.count(), 10);
+        let read: Vec<_> = reader.map(Result::unwrap).collect();
+        assert_eq!(as_json(&read), as_json(&transactions));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn fields_of_newer_minor_versions_are_skipped() {
+        let path = path("newer-minor");
+        let transactions: Vec<_> = (0..3).map(transaction).collect();
+        // A field added at the end of the record, and of its instruction sets.
+        let newer: Vec<_> = as_json(&transactions).into_iter()
+            .map(|mut record| {
+                record["instruction_sets"][0]["program_version"] = Value::from("1.2.0");
+                record["tip_lamports"] = Value::from(1_000);
+                record
+            })
+            .collect();
+        let header = Header {
+            version: (SCHEMA_VERSION.0, SCHEMA_VERSION.1 + 1),
+            kind: RecordKind::Transactions,
+            count: newer.len() as u64,
+        };
+        write_encoded(&path, &header, newer.iter().map(serde_json::to_vec)).unwrap();
+
+        let reader = SnapshotReader::<TransactionRecord>::open(&path).unwrap();
+        assert_eq!(reader.schema_version(), (SCHEMA_VERSION.0, SCHEMA_VERSION.1 + 1));
+        let read: Vec<_> = reader.map(Result::unwrap).collect();
+        assert_eq!(as_json(&read), as_json(&transactions));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn other_major_versions_and_truncated_snapshots_fail() {
+        let path = path("versions");
+        let transactions: Vec<_> = (0..3).map(transaction).collect();
+        let header = Header {
+            version: (SCHEMA_VERSION.0 + 1, 0),
+            kind: RecordKind::Transactions,
+            count: 3,
+        };
+        write_encoded(&path, &header, transactions.iter().map(serde_json::to_vec)).unwrap();
+        let err = read_snapshot(&path).err().unwrap();
+        assert!(matches!(err, SnapshotError::UnsupportedVersion { major: 2, minor: 0 }), "{}", err);
+        assert_eq!(err.to_string(), "Snapshot schema version 2.0 isn't supported, only 1.x is");
+
+        // Announcing more records than it holds.
+        let header = Header { version: SCHEMA_VERSION, kind: RecordKind::Transactions, count: 5 };
+        write_encoded(&path, &header, transactions.iter().map(serde_json::to_vec)).unwrap();
+        let read: Vec<_> = SnapshotReader::<TransactionRecord>::open(&path).unwrap().collect();
+        assert_eq!(read.len(), 4);
+        assert!(matches!(read[3], Err(SnapshotError::Truncated { expected: 5, found: 3 })));
+
+        std::fs::write(&path, b"{\"transactions\": []}").unwrap();
+        assert!(matches!(read_snapshot(&path), Err(SnapshotError::NotASnapshot)));
+        let _ = std::fs::remove_file(&path);
+    }
+}
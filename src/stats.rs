@@ -0,0 +1,305 @@
+//! Throughput and lag of ingestion, as it goes: where it is compared with the tip of the chain,
+//! how many instructions and transactions it gets through per second over the last minute and
+//! the last five, how many transactions are waiting for each sink and, when live ingestion
+//! shares the pipeline with a backfill, how the two are scheduled.
+//!
+//! Whoever writes blocks records them into a [`StatsReporter`]; its [`StatsSnapshot`]s are logged
+//! periodically (see [`StatsReporter::spawn_logger`]), served by the health endpoint (see
+//! `health::serve_with_stats`) and, with the `metrics` feature, published as gauges.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::clock::DEFAULT_MS_PER_SLOT;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+use crate::health::IngestProgress;
+
+/// How often the stats are logged by default.
+pub const DEFAULT_STATS_INTERVAL: Duration = Duration::from_secs(30);
+
+const ONE_MINUTE: Duration = Duration::from_secs(60);
+const FIVE_MINUTES: Duration = Duration::from_secs(300);
+
+/// Counts events per second, over the last five minutes.
+#[derive(Debug)]
+pub struct RollingCounter {
+    started: Instant,
+    // The events of every second (counted from `started`) that had any, the oldest first.
+    buckets: VecDeque<(u64, u64)>,
+}
+
+impl RollingCounter {
+    pub fn new(started: Instant) -> Self {
+        RollingCounter { started, buckets: VecDeque::new() }
+    }
+
+    pub fn record(&mut self, count: u64, now: Instant) {
+        let second = now.saturating_duration_since(self.started).as_secs();
+        match self.buckets.back_mut() {
+            Some((last, total)) if *last == second => *total += count,
+            _ => self.buckets.push_back((second, count)),
+        }
+
+        // Nothing older than the longest window is ever asked for.
+        while matches!(self.buckets.front(),
+                       Some((oldest, _)) if oldest + FIVE_MINUTES.as_secs() < second) {
+            self.buckets.pop_front();
+        }
+    }
+
+    /// Events per second over the last `window`, or since the counter started when that's more
+    /// recent.
+    pub fn rate(&self, window: Duration, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.started);
+        let second = elapsed.as_secs();
+        let total: u64 = self.buckets.iter()
+            .rev()
+            .take_while(|(bucket, _)| bucket + window.as_secs() >= second)
+            .map(|(_, count)| count)
+            .sum();
+
+        total as f64 / window.min(elapsed).as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// A rate over the last minute and the last five.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Rates {
+    pub one_minute: f64,
+    pub five_minutes: f64,
+}
+
+impl Rates {
+    fn of(counter: &RollingCounter, now: Instant) -> Self {
+        Rates {
+            one_minute: counter.rate(ONE_MINUTE, now),
+            five_minutes: counter.rate(FIVE_MINUTES, now),
+        }
+    }
+}
+
+/// The stats of ingestion at some point in time.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct StatsSnapshot {
+    // The last slot fully written to the sink.
+    pub current_slot: Option<u64>,
+    // The tip of the chain, as last reported by the ingestion source.
+    pub tip_slot: Option<u64>,
+    // How far behind the tip ingestion is, once both slots are known.
+    pub lag_slots: Option<u64>,
+    // The same lag at the target slot time of the cluster.
+    pub lag_seconds: Option<f64>,
+    pub instructions_per_second: Rates,
+    pub transactions_per_second: Rates,
+    // Transactions waiting to be written, by sink.
+    pub queue_depths: BTreeMap<String, usize>,
+    // The scheduling of live ingestion and backfill, when they share the pipeline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduler: Option<SchedulerStats>,
+}
+
+/// Where live ingestion and a backfill sharing the pipeline are (see `ingest::scheduler`), as of
+/// the last block written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchedulerStats {
+    // How far behind the tip live ingestion is, once both slots are known.
+    pub live_lag_slots: Option<u64>,
+    // The backfill blocks announced that are still to be written.
+    pub backfill_remaining: u64,
+    // The backfill blocks in the pipeline.
+    pub backfill_in_flight: usize,
+    // How many backfill blocks may be in the pipeline at once.
+    pub backfill_throttle: usize,
+}
+
+/// Where ingestion is and how fast it goes, recorded by whoever writes blocks to the sink.
+pub struct StatsReporter {
+    progress: Arc<IngestProgress>,
+    counters: Mutex<Counters>,
+}
+
+struct Counters {
+    instructions: RollingCounter,
+    transactions: RollingCounter,
+    queue_depths: BTreeMap<String, usize>,
+    scheduler: Option<SchedulerStats>,
+}
+
+impl StatsReporter {
+    /// Reports the slots recorded in `progress`, with rates counted from now on.
+    pub fn new(progress: Arc<IngestProgress>) -> Self {
+        let now = Instant::now();
+        StatsReporter {
+            progress,
+            counters: Mutex::new(Counters {
+                instructions: RollingCounter::new(now),
+                transactions: RollingCounter::new(now),
+                queue_depths: BTreeMap::new(),
+                scheduler: None,
+            }),
+        }
+    }
+
+    /// Where the current and tip slots are recorded.
+    pub fn progress(&self) -> &Arc<IngestProgress> {
+        &self.progress
+    }
+
+    /// Counts the transactions and instructions of a block just written.
+    pub fn record_block(&self, transactions: usize, instructions: usize) {
+        {
+            let now = Instant::now();
+            let mut counters = self.counters.lock().unwrap();
+            counters.transactions.record(transactions as u64, now);
+            counters.instructions.record(instructions as u64, now);
+        }
+
+        #[cfg(feature = "metrics")]
+        publish(&self.snapshot());
+    }
+
+    /// Records how many transactions are waiting to be written to `sink`.
+    pub fn set_queue_depth(&self, sink: &str, depth: usize) {
+        self.counters.lock().unwrap().queue_depths.insert(sink.to_string(), depth);
+    }
+
+    /// Records where the scheduling of live ingestion and backfill is.
+    pub fn set_scheduler(&self, scheduler: SchedulerStats) {
+        self.counters.lock().unwrap().scheduler = Some(scheduler);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        self.snapshot_at(Instant::now())
+    }
+
+    /// The stats as of `now`.
+    pub fn snapshot_at(&self, now: Instant) -> StatsSnapshot {
+        let (current_slot, tip_slot) = (self.progress.current_slot(), self.progress.tip_slot());
+        let lag_slots = self.progress.lag_slots();
+        let counters = self.counters.lock().unwrap();
+
+        StatsSnapshot {
+            current_slot,
+            tip_slot,
+            lag_slots,
+            lag_seconds: lag_slots.map(|lag| (lag * DEFAULT_MS_PER_SLOT) as f64 / 1000.0),
+            instructions_per_second: Rates::of(&counters.instructions, now),
+            transactions_per_second: Rates::of(&counters.transactions, now),
+            queue_depths: counters.queue_depths.clone(),
+            scheduler: counters.scheduler,
+        }
+    }
+
+    /// Logs the current stats as a structured line.
+    pub fn log(&self) {
+        let stats = self.snapshot();
+        info!(
+            current_slot = ?stats.current_slot,
+            tip_slot = ?stats.tip_slot,
+            lag_slots = ?stats.lag_slots,
+            lag_seconds = ?stats.lag_seconds,
+            instructions_per_second_1m = stats.instructions_per_second.one_minute,
+            instructions_per_second_5m = stats.instructions_per_second.five_minutes,
+            transactions_per_second_1m = stats.transactions_per_second.one_minute,
+            transactions_per_second_5m = stats.transactions_per_second.five_minutes,
+            queue_depths = ?stats.queue_depths,
+            scheduler = ?stats.scheduler,
+            "[spi-wrapper/stats] Pipeline stats."
+        );
+    }
+
+    /// Logs the stats every `interval` from a background task, until the returned logger is
+    /// dropped. Has to be called within a tokio runtime.
+    pub fn spawn_logger(self: Arc<Self>, interval: Duration) -> StatsLogger {
+        StatsLogger(tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            // The first tick completes right away, with nothing to report yet.
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                self.log();
+            }
+        }))
+    }
+}
+
+/// Logs the stats periodically, until dropped.
+pub struct StatsLogger(JoinHandle<()>);
+
+impl Drop for StatsLogger {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn publish(stats: &StatsSnapshot) {
+    let metrics = crate::metrics::metrics();
+    if let Some(slot) = stats.current_slot {
+        metrics.current_slot.set(slot as i64);
+    }
+    if let Some(slot) = stats.tip_slot {
+        metrics.tip_slot.set(slot as i64);
+    }
+    if let (Some(lag_slots), Some(lag_seconds)) = (stats.lag_slots, stats.lag_seconds) {
+        metrics.slot_lag.set(lag_slots as i64);
+        metrics.lag_seconds.set(lag_seconds);
+    }
+    for (window, rate) in &[("1m", stats.instructions_per_second.one_minute),
+                            ("5m", stats.instructions_per_second.five_minutes)] {
+        metrics.instructions_per_second.with_label_values(&[window]).set(*rate);
+    }
+    for (window, rate) in &[("1m", stats.transactions_per_second.one_minute),
+                            ("5m", stats.transactions_per_second.five_minutes)] {
+        metrics.transactions_per_second.with_label_values(&[window]).set(*rate);
+    }
+    for (sink, depth) in &stats.queue_depths {
+        metrics.sink_queue_depth.with_label_values(&[sink]).set(*depth as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_cover_their_window() {
+        let start = Instant::now();
+        let mut counter = RollingCounter::new(start);
+        // 10 events per second for two minutes, then nothing.
+        for second in 0..120 {
+            counter.record(10, start + Duration::from_secs(second));
+        }
+
+        let now = start + Duration::from_secs(120);
+        assert_eq!(counter.rate(ONE_MINUTE, now), 10.0);
+        // Five minutes haven't gone by yet, so the rate is over the two minutes that have.
+        assert_eq!(counter.rate(FIVE_MINUTES, now), 10.0);
+
+        let now = start + Duration::from_secs(180);
+        assert_eq!(counter.rate(ONE_MINUTE, now), 0.0);
+        assert_eq!(counter.rate(FIVE_MINUTES, now), 1200.0 / 180.0);
+
+        // Only seconds 100 to 119 are within the last five minutes.
+        let now = start + Duration::from_secs(400);
+        assert_eq!(counter.rate(FIVE_MINUTES, now), 200.0 / 300.0);
+    }
+
+    #[test]
+    fn lag_is_reported_once_both_slots_are_known() {
+        let reporter = StatsReporter::new(Arc::new(IngestProgress::default()));
+        reporter.progress().set_tip_slot(1_000);
+        assert_eq!(reporter.snapshot().lag_slots, None);
+
+        reporter.progress().set_current_slot(900);
+        reporter.set_queue_depth("sqlite", 12);
+        let stats = reporter.snapshot();
+        assert_eq!(stats.lag_slots, Some(100));
+        assert_eq!(stats.lag_seconds, Some(40.0));
+        assert_eq!(stats.queue_depths.get("sqlite"), Some(&12));
+    }
+}
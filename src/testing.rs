@@ -0,0 +1,510 @@
+//! Testing processors against recorded transactions, without hitting RPC.
+//!
+//! A fixture is a transaction as returned by `getTransaction` with the base64 encoding, saved
+//! under `tests/fixtures/<name>.json` by [`record`]. Next to it, `<name>.expected.json` holds the
+//! snapshot of what it decodes to, which [`assert_fixture`] replays it against. Single instruction
+//! sets are snapshotted under `tests/snapshots` by [`snapshot_instruction_set`].
+//!
+//! Snapshots are canonical: properties are sorted and timestamps left out, so that they only
+//! change along with what's decoded. When one doesn't match, the new one is written next to it
+//! with a `.new` extension, to be reviewed and renamed over it. Running the tests with
+//! `SPI_UPDATE_SNAPSHOTS=1` writes every snapshot in place instead.
+//!
+//! The unit tests of the processors build their instructions with `instruction` and compare
+//! what they decode to with `properties`.
+
+#[cfg(feature = "runtime")]
+use std::fmt::Debug;
+#[cfg(feature = "runtime")]
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+#[cfg(feature = "runtime")]
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "runtime")]
+use serde_json::{json, Value};
+#[cfg(feature = "runtime")]
+use solana_client::client_error::ClientError;
+#[cfg(feature = "runtime")]
+use solana_client::rpc_config::RpcTransactionConfig;
+#[cfg(feature = "runtime")]
+use solana_client::rpc_request::RpcRequest;
+#[cfg(feature = "runtime")]
+use solana_sdk::commitment_config::CommitmentConfig;
+#[cfg(feature = "runtime")]
+use solana_transaction_status::UiTransactionEncoding;
+#[cfg(feature = "runtime")]
+use thiserror::Error;
+
+#[cfg(feature = "runtime")]
+use crate::ingest::pool::RpcPool;
+#[cfg(feature = "runtime")]
+use crate::registry::ProcessorRegistry;
+#[cfg(feature = "runtime")]
+use crate::transaction::{decode_transaction, RawTransaction, TransactionRecord};
+use crate::InstructionSet;
+#[cfg(test)]
+use crate::{Instruction, Network, Symbol};
+
+/// Where the fixtures of this repository live.
+pub const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+/// Where the instruction set snapshots of this repository live.
+pub const SNAPSHOTS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots");
+
+/// Set to write the snapshots instead of asserting against them.
+pub const UPDATE_SNAPSHOTS_VAR: &str = "SPI_UPDATE_SNAPSHOTS";
+
+/// Errors that may be returned while recording or loading fixtures.
+#[cfg(feature = "runtime")]
+#[derive(Debug, Error)]
+pub enum FixtureError {
+    #[error("RPC request failed: {0}")]
+    Rpc(#[from] ClientError),
+    #[error("Unable to access {path}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("{path} is not a valid fixture: {source}")]
+    Json { path: PathBuf, source: serde_json::Error },
+    #[error("The transaction {0} wasn't found")]
+    NotFound(String),
+    #[error("{0} can't be turned into a raw transaction (json-encoded or unsigned)")]
+    Undecodable(PathBuf),
+    #[error("Unable to serve the fixtures: {0}")]
+    Serve(std::io::Error),
+    #[cfg(feature = "snapshot")]
+    #[error("Snapshot error: {0}")]
+    Snapshot(#[from] crate::snapshot::SnapshotError),
+}
+
+/// What an instruction decodes to, as kept in the snapshots: properties sorted by parent key then
+/// key, without the transaction hash or timestamps.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct InstructionSnapshot {
+    pub tx_instruction_id: i16,
+    pub parent_index: i16,
+    pub program: String,
+    pub function: String,
+    pub properties: Vec<PropertySnapshot>,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PropertySnapshot {
+    // First, for properties to be sorted by parent key.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub parent_key: String,
+    pub key: String,
+    pub value: String,
+}
+
+impl From<&InstructionSet> for InstructionSnapshot {
+    fn from(instruction_set: &InstructionSet) -> Self {
+        let mut properties: Vec<PropertySnapshot> = instruction_set.properties.iter()
+            .map(|property| PropertySnapshot {
+                parent_key: property.parent_key.to_string(),
+                key: property.key.to_string(),
+                value: property.value.clone(),
+            })
+            .collect();
+        properties.sort();
+
+        InstructionSnapshot {
+            tx_instruction_id: instruction_set.function.tx_instruction_id,
+            parent_index: instruction_set.function.parent_index,
+            program: instruction_set.function.program.to_string(),
+            function: instruction_set.function.function_name.to_string(),
+            properties,
+        }
+    }
+}
+
+/// The path of the fixture `name` of this repository.
+pub fn fixture_path(name: &str) -> PathBuf {
+    Path::new(FIXTURES_DIR).join(format!("{}.json", name))
+}
+
+#[cfg(feature = "runtime")]
+fn snapshot_path(fixture: &Path) -> PathBuf {
+    fixture.with_extension("expected.json")
+}
+
+/// Fetches a transaction and saves it as a fixture at `path`, as returned by RPC.
+#[cfg(feature = "runtime")]
+pub async fn record(
+    rpc: &Arc<RpcPool>,
+    signature: &str,
+    path: &Path,
+) -> Result<(), FixtureError> {
+    let params = json!([signature, RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(CommitmentConfig::confirmed()),
+    }]);
+    // As JSON, for what the solana-transaction-status types drop (stack heights) to be kept.
+    let transaction = rpc.call(move |rpc| {
+        rpc.send::<Option<Value>>(RpcRequest::GetTransaction, params.clone())
+    }).await?
+        .ok_or_else(|| FixtureError::NotFound(signature.to_string()))?;
+
+    let contents = serde_json::to_string_pretty(&transaction)
+        .map_err(|source| FixtureError::Json { path: path.to_path_buf(), source })?;
+    fs::write(path, contents + "\n")
+        .map_err(|source| FixtureError::Io { path: path.to_path_buf(), source })
+}
+
+/// Loads the fixture at `path`.
+#[cfg(feature = "runtime")]
+pub fn load(path: &Path) -> Result<RawTransaction, FixtureError> {
+    let transaction: Value = read_json(path)?;
+    RawTransaction::from_confirmed_json(transaction)
+        .map_err(|source| FixtureError::Json { path: path.to_path_buf(), source })?
+        .ok_or_else(|| FixtureError::Undecodable(path.to_path_buf()))
+}
+
+/// Decodes the fixture at `path` with `registry`.
+#[cfg(feature = "runtime")]
+pub fn replay(
+    registry: &ProcessorRegistry,
+    path: &Path,
+) -> Result<TransactionRecord, FixtureError> {
+    Ok(decode_transaction(registry, &load(path)?))
+}
+
+/// Decodes the fixtures at `paths` with `registry`, writing the records to a snapshot (see
+/// [`snapshot`](crate::snapshot)) at `output`, to compare or ship them elsewhere. Returns them.
+#[cfg(feature = "snapshot")]
+pub fn replay_to_snapshot(
+    registry: &ProcessorRegistry,
+    paths: &[PathBuf],
+    output: &Path,
+) -> Result<Vec<TransactionRecord>, FixtureError> {
+    let records = paths.iter()
+        .map(|path| replay(registry, path))
+        .collect::<Result<Vec<_>, _>>()?;
+    crate::snapshot::write_records(output, &records)?;
+
+    Ok(records)
+}
+
+/// Replays the fixture `name` of this repository with the default registry, asserting that it
+/// decodes to its snapshot. Returns the record for further asserts.
+#[cfg(feature = "runtime")]
+pub fn assert_fixture(name: &str) -> TransactionRecord {
+    assert_fixture_with(&ProcessorRegistry::default(), &fixture_path(name))
+}
+
+/// Replays the fixture at `path` with `registry`, asserting that it decodes to its snapshot.
+#[cfg(feature = "runtime")]
+pub fn assert_fixture_with(registry: &ProcessorRegistry, path: &Path) -> TransactionRecord {
+    let record = replay(registry, path).unwrap_or_else(|err| panic!("{}", err));
+    let actual: Vec<InstructionSnapshot> =
+        record.instruction_sets.iter().map(InstructionSnapshot::from).collect();
+    assert_snapshot(&snapshot_path(path), &actual);
+
+    record
+}
+
+/// Asserts that `instruction_set` matches the snapshot `name` of this repository, which may be
+/// nested in directories (`lending/flash-loan`).
+#[cfg(feature = "runtime")]
+pub fn snapshot_instruction_set(name: &str, instruction_set: &InstructionSet) {
+    let path = Path::new(SNAPSHOTS_DIR).join(format!("{}.json", name));
+    assert_snapshot(&path, &InstructionSnapshot::from(instruction_set));
+}
+
+/// Compares `actual` with the snapshot at `path`, parsed so that line endings don't matter.
+#[cfg(feature = "runtime")]
+fn assert_snapshot<T>(path: &Path, actual: &T)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let contents = serde_json::to_string_pretty(actual).unwrap() + "\n";
+    let pending = path.with_extension("json.new");
+    let write = |path: &Path| {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(path, &contents)
+            .unwrap_or_else(|err| panic!("Unable to write {}: {}", path.display(), err));
+    };
+
+    if std::env::var_os(UPDATE_SNAPSHOTS_VAR).is_some() {
+        write(path);
+        let _ = fs::remove_file(&pending);
+        return;
+    }
+
+    match read_json::<T>(path) {
+        Ok(expected) if expected == *actual => {
+            let _ = fs::remove_file(&pending);
+        }
+        Ok(expected) => {
+            write(&pending);
+            panic!("{} doesn't match, the new snapshot was written to {} (rename it over the old \
+                one, or run with {}=1 to accept every new snapshot)\n\
+                expected: {:#?}\nactual: {:#?}",
+                path.display(), pending.display(), UPDATE_SNAPSHOTS_VAR, expected, actual);
+        }
+        Err(FixtureError::Io { .. }) => {
+            write(&pending);
+            panic!("{} doesn't exist, the new snapshot was written to {}", path.display(),
+                pending.display());
+        }
+        Err(err) => panic!("{}", err),
+    }
+}
+
+/// Asserts that the instruction decoded to `function`.
+pub fn assert_function(instruction_set: &InstructionSet, function: &str) {
+    assert_eq!(instruction_set.function.function_name.as_str(), function,
+        "instruction {} of {}", instruction_set.function.tx_instruction_id,
+        instruction_set.function.transaction_hash);
+}
+
+/// Asserts that the instruction has the property `key` (at the top level) set to `value`.
+pub fn assert_property(instruction_set: &InstructionSet, key: &str, value: &str) {
+    let property = instruction_set.properties.iter()
+        .find(|property| property.key.as_str() == key && property.parent_key.is_empty())
+        .unwrap_or_else(|| panic!("{} has no property {}", instruction_set.function.function_name,
+            key));
+    assert_eq!(property.value, value, "{}.{}", instruction_set.function.function_name, key);
+}
+
+/// The first top-level instruction of `program` in the transaction `signature`, carrying `data`,
+/// for processors to be tested on.
+#[cfg(test)]
+pub(crate) fn instruction(program: &str, data: Vec<u8>) -> Instruction {
+    Instruction {
+        tx_instruction_id: 0,
+        transaction_hash: "signature".into(),
+        program: program.into(),
+        data,
+        parent_index: -1,
+        timestamp: 0,
+        network: Network::Mainnet,
+    }
+}
+
+/// The keys and values of the properties of `instruction_set`, in order. Nested properties are
+/// keyed `<parent key>.<key>` (interned, to be compared as plain strings), unless their key
+/// already starts with their parent key (`orders/0/side`).
+#[cfg(test)]
+pub(crate) fn properties(instruction_set: &InstructionSet) -> Vec<(&'static str, String)> {
+    instruction_set.properties.iter()
+        .map(|property| {
+            let key = match property.parent_key.as_str() {
+                "" => property.key.as_str(),
+                parent_key if property.key.starts_with(parent_key) => property.key.as_str(),
+                parent_key => Symbol::intern(&format!("{}.{}", parent_key, property.key)).as_str(),
+            };
+            (key, property.value.clone())
+        })
+        .collect()
+}
+
+/// A JSON-RPC node answering from fixtures, for what reads from RPC (pollers, backfills) to be
+/// run offline. Every slot a fixture was confirmed in has a block holding the fixtures of the
+/// slot, the tip being the last of them; the slots in between are skipped.
+///
+/// Only what the ingestion reads is served: `getSlot`, `getBlocks`, `getBlock`,
+/// `getTransaction` and `getSignaturesForAddress`, batched or not.
+#[cfg(feature = "runtime")]
+pub struct MockRpc {
+    url: String,
+}
+
+#[cfg(feature = "runtime")]
+impl MockRpc {
+    /// Serves the fixtures at `paths` on a port of the loopback interface, until the process
+    /// exits.
+    pub fn serve(paths: &[PathBuf]) -> Result<MockRpc, FixtureError> {
+        let mut transactions = Vec::with_capacity(paths.len());
+        for path in paths {
+            let fixture: Value = read_json(path)?;
+            let raw = load(path)?;
+            transactions.push((raw, fixture));
+        }
+        // Newest first, the order signatures are listed in.
+        transactions.sort_by(|(a, _), (b, _)| {
+            b.slot.cmp(&a.slot).then_with(|| a.transaction_hash.cmp(&b.transaction_hash))
+        });
+        let node = Arc::new(MockNode { transactions });
+
+        let url = serve_http(move |body| {
+            let request: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+            let response = match request {
+                Value::Array(requests) => {
+                    Value::Array(requests.iter().map(|request| node.answer(request)).collect())
+                }
+                request => node.answer(&request),
+            };
+            response.to_string()
+        }).map_err(FixtureError::Serve)?;
+
+        Ok(MockRpc { url })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+#[cfg(feature = "runtime")]
+struct MockNode {
+    // The fixtures and what they were read from, newest first.
+    transactions: Vec<(RawTransaction, Value)>,
+}
+
+#[cfg(feature = "runtime")]
+impl MockNode {
+    fn answer(&self, request: &Value) -> Value {
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        let result = match request.get("method").and_then(Value::as_str) {
+            Some("getSlot") => Ok(json!(self.slots().last().copied().unwrap_or(0))),
+            Some("getBlocks") => {
+                let start = params[0].as_u64().unwrap_or(0);
+                let end = params[1].as_u64().unwrap_or(u64::MAX);
+                Ok(json!(self.slots().into_iter()
+                    .filter(|slot| (start..=end).contains(slot))
+                    .collect::<Vec<_>>()))
+            }
+            Some("getBlock") => self.block(params[0].as_u64().unwrap_or(0)),
+            Some("getTransaction") => Ok(self.transactions.iter()
+                .find(|(raw, _)| params[0] == raw.transaction_hash.as_str())
+                .map_or(Value::Null, |(_, fixture)| fixture.clone())),
+            Some("getSignaturesForAddress") => Ok(self.signatures(&params)),
+            method => Err(json!({"code": -32601, "message": format!("{:?} isn't served", method)})),
+        };
+
+        match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(error) => json!({"jsonrpc": "2.0", "id": id, "error": error}),
+        }
+    }
+
+    /// The slots with a block, in order.
+    fn slots(&self) -> Vec<u64> {
+        let mut slots: Vec<u64> = self.transactions.iter().map(|(raw, _)| raw.slot).collect();
+        slots.sort_unstable();
+        slots.dedup();
+
+        slots
+    }
+
+    fn block(&self, slot: u64) -> Result<Value, Value> {
+        let slots = self.slots();
+        let position = slots.iter().position(|candidate| *candidate == slot).ok_or_else(|| json!({
+            "code": -32007,
+            "message": format!("Slot {} was skipped, or missing due to ledger jump to recent \
+                snapshot", slot),
+        }))?;
+        let parent_slot = position.checked_sub(1).map_or(slot.saturating_sub(1), |p| slots[p]);
+        let blockhash = |slot: u64| solana_sdk::hash::hashv(&[&slot.to_le_bytes()]).to_string();
+        let fixtures: Vec<&Value> = self.transactions.iter().rev()
+            .filter(|(raw, _)| raw.slot == slot)
+            .map(|(_, fixture)| fixture)
+            .collect();
+
+        Ok(json!({
+            "blockhash": blockhash(slot),
+            "previousBlockhash": blockhash(parent_slot),
+            "parentSlot": parent_slot,
+            "transactions": fixtures.iter().map(|fixture| &fixture["transaction"])
+                .collect::<Vec<_>>(),
+            "blockTime": fixtures[0]["blockTime"],
+            "blockHeight": null,
+        }))
+    }
+
+    fn signatures(&self, params: &Value) -> Value {
+        let address = params[0].as_str().unwrap_or_default();
+        let config = &params[1];
+        let limit = config["limit"].as_u64().map_or(1000, |limit| limit as usize);
+        let mentioning = self.transactions.iter()
+            .filter(|(raw, _)| raw.account_keys.iter().any(|key| key.to_string() == address));
+        let older = mentioning
+            .skip_while(|(raw, _)| config["before"].as_str()
+                .map_or(false, |before| raw.transaction_hash != before))
+            .skip(config["before"].is_string() as usize);
+
+        Value::Array(older
+            .take_while(|(raw, _)| config["until"] != raw.transaction_hash.as_str())
+            .take(limit)
+            .map(|(raw, fixture)| json!({
+                "signature": raw.transaction_hash,
+                "slot": raw.slot,
+                "err": null,
+                "memo": null,
+                "blockTime": fixture["blockTime"],
+                "confirmationStatus": "confirmed",
+            }))
+            .collect())
+    }
+}
+
+/// Serves HTTP on a port of the loopback interface until the process exits, answering every
+/// request with `200 OK` and the JSON `handler` returns for its body. Returns the url served.
+pub fn serve_http<F>(handler: F) -> std::io::Result<String>
+where
+    F: Fn(&[u8]) -> String + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let handler = Arc::new(handler);
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let handler = handler.clone();
+            thread::spawn(move || {
+                let _ = answer_http(stream, handler.as_ref());
+            });
+        }
+    });
+
+    Ok(url)
+}
+
+fn answer_http<F: Fn(&[u8]) -> String>(mut stream: TcpStream, handler: &F) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 8192];
+    let body_start = loop {
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buffer[..read]);
+        if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+    };
+
+    let headers = String::from_utf8_lossy(&request[..body_start]).to_ascii_lowercase();
+    let length = headers.lines()
+        .find_map(|line| line.strip_prefix("content-length:"))
+        .and_then(|length| length.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while request.len() < body_start + length {
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let body = handler(&request[body_start..]);
+    write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+        Connection: close\r\n\r\n{}", body.len(), body)?;
+    stream.flush()
+}
+
+#[cfg(feature = "runtime")]
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, FixtureError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|source| FixtureError::Io { path: path.to_path_buf(), source })?;
+    serde_json::from_str(&contents)
+        .map_err(|source| FixtureError::Json { path: path.to_path_buf(), source })
+}
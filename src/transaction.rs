@@ -0,0 +1,867 @@
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use serde::{Serialize, Deserialize};
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::pubkey::Pubkey;
+use serde_json::Value;
+use solana_transaction_status::{
+    EncodedConfirmedTransaction, EncodedTransactionWithStatusMeta, InnerInstructions,
+    UiInnerInstructions, UiInstruction,
+};
+use tracing::debug_span;
+
+use crate::attribution::{attribute, SignerActivity};
+use crate::call_tree::CallTree;
+use crate::encoding::{decode_message, EncodingError};
+use crate::fees;
+use crate::ingest::filter::DecodeFilters;
+use crate::interner::Symbol;
+use crate::lifecycle::{self, AccountLifecycleEvent, LifecycleHints};
+use crate::network::Network;
+use crate::registry::{DecodeStatus, ProcessorRegistry, RawCapture};
+use crate::{Instruction, InstructionSet, RawData};
+
+// The program validators vote with, whose transactions are the bulk of every block.
+static VOTE_PROGRAM: Lazy<Pubkey> =
+    Lazy::new(|| "Vote111111111111111111111111111111111111111".parse().unwrap());
+
+/// A confirmed transaction flattened into the pieces the processors care about, regardless of the
+/// encoding the RPC node handed it to us in.
+#[derive(Clone)]
+pub struct RawTransaction {
+    // The slot the transaction was confirmed in.
+    pub slot: u64,
+    // The block time reported by the node, if any.
+    pub block_time: Option<i64>,
+    // The first signature of the transaction.
+    pub transaction_hash: String,
+    // Every account key referenced by the message, in message order: the static keys, then the
+    // addresses v0 transactions load from lookup tables (only ever streamed by geyser).
+    pub account_keys: Vec<Pubkey>,
+    // How many of the account keys signed the transaction, the fee payer first.
+    pub num_required_signatures: u8,
+    // The top-level instructions of the message.
+    pub instructions: Vec<CompiledInstruction>,
+    // The instructions invoked through CPI, grouped by the top-level instruction index.
+    pub inner_instructions: Vec<InnerInstructions>,
+    // The mint of every token account holding a balance before or after the transaction, by
+    // account index.
+    pub token_mints: Vec<TokenMint>,
+    // The fee paid for the transaction, in lamports.
+    pub fee: u64,
+    // Whether the transaction executed successfully.
+    pub succeeded: bool,
+    // The stack height of every inner instruction, grouped like `inner_instructions`, when the
+    // node reports them (see `call_tree`).
+    pub stack_heights: Vec<Vec<u32>>,
+    // The program logs, which the stack heights are recovered from otherwise.
+    pub log_messages: Vec<String>,
+    // The lamports of every account key before and after the transaction, by account index, when
+    // the node reports them (see `lifecycle`).
+    pub pre_balances: Vec<u64>,
+    pub post_balances: Vec<u64>,
+}
+
+impl RawTransaction {
+    /// Builds a raw transaction out of an RPC encoded one. Returns `None` when the transaction is
+    /// `jsonParsed` (the raw instruction data is not available) or carries no signature, see
+    /// [`RawTransaction::try_from_encoded`] to know which.
+    pub fn from_encoded(
+        slot: u64,
+        block_time: Option<i64>,
+        encoded: &EncodedTransactionWithStatusMeta,
+    ) -> Option<RawTransaction> {
+        RawTransaction::try_from_encoded(slot, block_time, encoded).ok()
+    }
+
+    /// Same as [`RawTransaction::from_encoded`], returning why the transaction can't be built
+    /// (see [`crate::encoding`]).
+    pub fn try_from_encoded(
+        slot: u64,
+        block_time: Option<i64>,
+        encoded: &EncodedTransactionWithStatusMeta,
+    ) -> Result<RawTransaction, EncodingError> {
+        let message = decode_message(&encoded.transaction)?;
+
+        let (fee, succeeded, inner_instructions, token_mints, log_messages) = match &encoded.meta {
+            Some(meta) => (
+                meta.fee,
+                meta.err.is_none(),
+                meta.inner_instructions
+                    .as_ref()
+                    .map(|iis| iis.iter().map(compile_inner_instructions).collect())
+                    .unwrap_or_default(),
+                token_mints(meta.pre_token_balances.iter().chain(&meta.post_token_balances)
+                    .flatten()
+                    .map(|balance| (balance.account_index, balance.mint.as_str(),
+                        balance.ui_token_amount.decimals))),
+                meta.log_messages.clone().unwrap_or_default(),
+            ),
+            None => (0, true, Vec::new(), Vec::new(), Vec::new()),
+        };
+        let (pre_balances, post_balances) = encoded.meta.as_ref()
+            .map(|meta| (meta.pre_balances.clone(), meta.post_balances.clone()))
+            .unwrap_or_default();
+
+        Ok(RawTransaction {
+            slot,
+            block_time,
+            transaction_hash: message.signature,
+            account_keys: message.account_keys,
+            num_required_signatures: message.num_required_signatures,
+            instructions: message.instructions,
+            inner_instructions,
+            token_mints,
+            fee,
+            succeeded,
+            // Dropped by the solana-transaction-status types, see `from_confirmed_json`.
+            stack_heights: Vec::new(),
+            log_messages,
+            pre_balances,
+            post_balances,
+        })
+    }
+
+    /// Same as [`RawTransaction::from_encoded`], out of a `getTransaction` response as JSON,
+    /// keeping the stack heights the node reports for the inner instructions.
+    pub fn from_confirmed_json(confirmed: Value) -> serde_json::Result<Option<RawTransaction>> {
+        // The meta may be flattened next to the transaction or nested along with it.
+        let meta = confirmed.get("meta").unwrap_or(&confirmed["transaction"]["meta"]);
+        let stack_heights = reported_stack_heights(meta);
+        let confirmed: EncodedConfirmedTransaction = serde_json::from_value(confirmed)?;
+
+        Ok(RawTransaction::from_encoded(confirmed.slot, confirmed.block_time,
+                                        &confirmed.transaction)
+            .map(|transaction| RawTransaction { stack_heights, ..transaction }))
+    }
+
+    /// The accounts that signed the transaction, the fee payer first.
+    pub fn signers(&self) -> &[Pubkey] {
+        let count = (self.num_required_signatures as usize).min(self.account_keys.len());
+        &self.account_keys[..count]
+    }
+
+    /// Whether the transaction only casts votes: every top-level instruction invokes the vote
+    /// program, as the transactions validators vote with do.
+    pub fn is_vote(&self) -> bool {
+        !self.instructions.is_empty() && self.instructions.iter().all(|instruction| {
+            self.account_keys.get(instruction.program_id_index as usize) == Some(&*VOTE_PROGRAM)
+        })
+    }
+
+    /// Flattens the top-level and inner instructions into the shape the processors expect.
+    ///
+    /// Top-level instructions are numbered by their position in the message and have a
+    /// `parent_index` of -1, inner instructions are numbered by their position within their
+    /// parent and carry the parent's index.
+    /// Instructions are tagged with the default network, mainnet. The id of every program invoked
+    /// is interned, registered with a processor or not.
+    pub fn instructions(&self) -> Vec<Instruction> {
+        let mut instructions = Vec::new();
+        let intern = |program: &Pubkey| Some(Symbol::intern(&program.to_string()));
+        self.for_each_instruction(Network::default(), intern, |instruction, _| {
+            instructions.push(instruction)
+        });
+
+        instructions
+    }
+
+    /// Same as [`RawTransaction::instructions`], handing out each instruction along with the
+    /// compiled instruction it comes from. Instructions of programs `program_symbol` has no
+    /// symbol for are left out.
+    fn for_each_instruction(
+        &self,
+        network: Network,
+        program_symbol: impl Fn(&Pubkey) -> Option<Symbol>,
+        mut f: impl FnMut(Instruction, &CompiledInstruction),
+    ) {
+        let timestamp = self.block_time.unwrap_or_default();
+        let transaction_hash: Arc<str> = Arc::from(self.transaction_hash.as_str());
+
+        for (idx, compiled) in self.instructions.iter().enumerate() {
+            if let Some(instruction) = self.to_instruction(
+                compiled, &program_symbol, &transaction_hash, idx as i16, -1, timestamp, network) {
+                f(instruction, compiled);
+            }
+
+            let inner = self.inner_instructions.iter()
+                .filter(|ii| ii.index as usize == idx)
+                .flat_map(|ii| ii.instructions.iter());
+            for (inner_idx, compiled) in inner.enumerate() {
+                if let Some(instruction) = self.to_instruction(compiled, &program_symbol,
+                    &transaction_hash, inner_idx as i16, idx as i16, timestamp, network) {
+                    f(instruction, compiled);
+                }
+            }
+        }
+    }
+
+    fn to_instruction(
+        &self,
+        compiled: &CompiledInstruction,
+        program_symbol: impl Fn(&Pubkey) -> Option<Symbol>,
+        transaction_hash: &Arc<str>,
+        tx_instruction_id: i16,
+        parent_index: i16,
+        timestamp: i64,
+        network: Network,
+    ) -> Option<Instruction> {
+        let program = self.account_keys.get(compiled.program_id_index as usize)?;
+
+        Some(Instruction {
+            tx_instruction_id,
+            transaction_hash: transaction_hash.clone(),
+            program: program_symbol(program)?,
+            data: compiled.data.clone(),
+            parent_index,
+            timestamp,
+            network,
+        })
+    }
+}
+
+/// The mint of a token account, as reported by the token balances of a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TokenMint {
+    pub account_index: u8,
+    pub mint: Pubkey,
+    pub decimals: u8,
+}
+
+/// The mints of token balances (account index, mint, decimals), deduplicated. Balances of
+/// unparsable mints are dropped.
+pub(crate) fn token_mints<'a, I>(balances: I) -> Vec<TokenMint>
+where
+    I: IntoIterator<Item = (u8, &'a str, u8)>,
+{
+    let mut mints: Vec<TokenMint> = balances.into_iter()
+        .filter_map(|(account_index, mint, decimals)| Some(TokenMint {
+            account_index,
+            mint: mint.parse().ok()?,
+            decimals,
+        }))
+        .collect();
+    mints.sort_unstable();
+    mints.dedup();
+
+    mints
+}
+
+/// Inner instructions come back from RPC with base58 data; parsed inner instructions carry no
+/// raw data at all and are dropped.
+fn compile_inner_instructions(ui: &UiInnerInstructions) -> InnerInstructions {
+    InnerInstructions {
+        index: ui.index,
+        instructions: ui.instructions.iter()
+            .filter_map(|instruction| match instruction {
+                UiInstruction::Compiled(compiled) => Some(CompiledInstruction {
+                    program_id_index: compiled.program_id_index,
+                    accounts: compiled.accounts.clone(),
+                    data: bs58::decode(&compiled.data).into_vec().ok()?,
+                }),
+                UiInstruction::Parsed(_) => None,
+            })
+            .collect(),
+    }
+}
+
+/// The stack heights reported for the inner instructions of a status meta, grouped like
+/// [`RawTransaction::inner_instructions`]. Empty unless every inner instruction has one.
+fn reported_stack_heights(meta: &Value) -> Vec<Vec<u32>> {
+    let groups = match meta.get("innerInstructions").and_then(Value::as_array) {
+        Some(groups) => groups,
+        None => return Vec::new(),
+    };
+
+    groups.iter()
+        .map(|group| group.get("instructions")?.as_array()?.iter()
+            // Parsed inner instructions are dropped, like in `compile_inner_instructions`.
+            .filter(|instruction| instruction.get("programIdIndex").is_some())
+            .map(|instruction| Some(instruction.get("stackHeight")?.as_u64()? as u32))
+            .collect::<Option<Vec<u32>>>())
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default()
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TransactionRecord {
+    // The first signature of the transaction.
+    pub transaction_hash: String,
+    // The slot the transaction was confirmed in.
+    pub slot: u64,
+    // The fee paid for the transaction, in lamports.
+    pub fee: u64,
+    // The fee broken down into what was paid for the signatures and for the compute units
+    // requested, at the price set for them, when it adds up (see `fees`).
+    #[serde(default)]
+    pub base_fee_lamports: Option<u64>,
+    #[serde(default)]
+    pub priority_fee_lamports: Option<u64>,
+    #[serde(default)]
+    pub compute_unit_price_micro_lamports: Option<u64>,
+    // Whether the transaction executed successfully.
+    pub succeeded: bool,
+    // The block time of the transaction.
+    pub timestamp: i64,
+    // Whether the block time is an estimate, the block having none (see
+    // `ingest::timestamps`).
+    #[serde(default)]
+    pub timestamp_estimated: bool,
+    // Whether the transaction was read at finalized commitment (or has been confirmed final
+    // since).
+    pub finalized: bool,
+    // The cluster the transaction was confirmed on.
+    #[serde(default)]
+    pub network: Network,
+    // Every instruction of the transaction we managed to decode.
+    pub instruction_sets: Vec<InstructionSet>,
+    // The instructions of supported programs we didn't manage to decode, or kept raw as their
+    // processor is degraded (see `watchdog`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub decode_failures: Vec<DecodeFailure>,
+    // Which instruction invoked which.
+    #[serde(default, skip_serializing_if = "CallTree::is_empty")]
+    pub call_tree: CallTree,
+    // Every decoded instruction, once for every signer of the transaction.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signer_activity: Vec<SignerActivity>,
+    // The accounts the transaction created and closed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub account_lifecycle: Vec<AccountLifecycleEvent>,
+    // Whether the transaction landed: false for the transactions decoded before they did (see
+    // `encoding::process_unconfirmed_transaction`), whose slot is the one they were simulated at.
+    #[serde(default = "confirmed_by_default")]
+    pub confirmed: bool,
+    // What simulating the transaction returned, for unconfirmed ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub simulation: Option<Simulation>,
+}
+
+fn confirmed_by_default() -> bool {
+    true
+}
+
+impl TransactionRecord {
+    /// Whether this record is the confirmed one of the unconfirmed `pending` record, which it
+    /// supersedes: both are of the same transaction, by signature, on the same cluster.
+    pub fn confirms(&self, pending: &TransactionRecord) -> bool {
+        self.confirmed && !pending.confirmed
+            && self.transaction_hash == pending.transaction_hash
+            && self.network == pending.network
+    }
+}
+
+/// What `simulateTransaction` returned for a transaction that hasn't landed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Simulation {
+    // The slot of the bank the transaction was simulated against.
+    pub slot: u64,
+    // Why the transaction failed, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub err: Option<String>,
+    // The program logs, Anchor events included.
+    #[serde(default)]
+    pub logs: Vec<String>,
+    // The compute units the top-level instructions consumed, as logged (see
+    // `fees::compute_units_consumed`).
+    #[serde(default)]
+    pub units_consumed: Option<u64>,
+}
+
+/// An instruction a processor was registered for but couldn't decode.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DecodeFailure {
+    pub tx_instruction_id: i16,
+    pub parent_index: i16,
+    pub program: Symbol,
+    // The first byte of the instruction data, which most programs dispatch on.
+    pub discriminant: Option<u8>,
+    // The data of the instruction, unless raw data isn't captured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_data: Option<RawData>,
+}
+
+/// Decodes every instruction (inner instructions included) of a confirmed transaction. Every
+/// instruction set gets the `stack_height` and `call_path` of its instruction in the call tree of
+/// the transaction (see [`CallTree`]), and its raw data when the registry captures it (see
+/// [`RawCapture`]). The decoded instructions are then attributed to every signer (see
+/// [`crate::attribution`]), and the accounts created and closed derived from them and the balances
+/// (see [`crate::lifecycle`]). Instructions are stamped with the version of their program when the
+/// registry tracks them (see [`crate::program_versions`]).
+pub fn decode_transaction(
+    registry: &ProcessorRegistry,
+    transaction: &RawTransaction,
+) -> TransactionRecord {
+    decode_instructions(registry, &DecodeFilters::none(), transaction, true)
+}
+
+/// Same as [`decode_transaction`], for a transaction that hasn't landed: the record isn't
+/// `confirmed`, its timestamp is an estimate, and the program upgrades it carries aren't learnt.
+pub(crate) fn decode_unconfirmed_transaction(
+    registry: &ProcessorRegistry,
+    transaction: &RawTransaction,
+    simulation: Option<Simulation>,
+) -> TransactionRecord {
+    let record = decode_instructions(registry, &DecodeFilters::none(), transaction, false);
+
+    TransactionRecord { timestamp_estimated: true, simulation, ..record }
+}
+
+/// Same as [`decode_transaction`], returning `None` when the transaction only casts votes and
+/// votes are skipped, was sunk before or doesn't match the account or the mint filter, and
+/// skipping the instructions the program filter (or a strict mint filter) doesn't let through.
+///
+/// Votes are checked first, then the signatures sunk before, then the cheap account filter, then
+/// the mint filter, all without decoding anything. Instructions are filtered each on their own:
+/// an inner instruction of an allowed program is decoded even when the instruction invoking it
+/// isn't.
+pub fn decode_transaction_filtered(
+    registry: &ProcessorRegistry,
+    filters: &DecodeFilters,
+    transaction: &RawTransaction,
+) -> Option<TransactionRecord> {
+    if filters.skip_votes && transaction.is_vote() {
+        #[cfg(feature = "metrics")]
+        crate::metrics::metrics().skipped_by_filter.with_label_values(&["votes"]).inc();
+
+        return None;
+    }
+    if let Some(dedup) = &filters.dedup {
+        if !dedup.admits(transaction) {
+            return None;
+        }
+    }
+    if let Some(accounts) = &filters.accounts {
+        if !accounts.admits(transaction) {
+            return None;
+        }
+    }
+    if let Some(mints) = &filters.mints {
+        if !mints.admits(transaction) {
+            return None;
+        }
+    }
+
+    Some(decode_instructions(registry, filters, transaction, true))
+}
+
+fn decode_instructions(
+    registry: &ProcessorRegistry,
+    filters: &DecodeFilters,
+    transaction: &RawTransaction,
+    confirmed: bool,
+) -> TransactionRecord {
+    let span = debug_span!("decode_transaction", slot = transaction.slot,
+                           signature = %transaction.transaction_hash);
+    let _entered = span.enter();
+
+    let call_tree = CallTree::build(transaction);
+    let capture = registry.raw_capture();
+    let mut instruction_sets = Vec::new();
+    let mut decode_failures = Vec::new();
+    let mut accounts = Vec::new();
+    let mut lifecycle_hints = LifecycleHints::default();
+    if let Some(versions) = registry.program_versions().filter(|_| confirmed) {
+        versions.observe(registry.network(), transaction);
+    }
+    // Only the instructions of registered programs can be decoded, and the ids of the others
+    // aren't worth interning.
+    let registered = |program: &Pubkey| registry.registered_symbol(&program.to_string());
+    transaction.for_each_instruction(registry.network(), registered, |instruction, compiled| {
+        if let Some(programs) = &filters.programs {
+            if !programs.admits(instruction.program) {
+                return;
+            }
+        }
+        if let Some(mints) = &filters.mints {
+            if !mints.admits_instruction(transaction, &compiled.accounts) {
+                return;
+            }
+        }
+
+        accounts.clear();
+        accounts.extend(compiled.accounts.iter()
+            .filter_map(|index| transaction.account_keys.get(*index as usize))
+            .copied());
+        let mut failure = DecodeFailure {
+            tx_instruction_id: instruction.tx_instruction_id,
+            parent_index: instruction.parent_index,
+            program: instruction.program,
+            discriminant: instruction.data.first().copied(),
+            raw_data: None,
+        };
+        // The data holds the plaintext of what the redaction may hash or drop.
+        let keeps_raw_data = registry.redaction().keeps_raw_data(instruction.program);
+        let capture = if keeps_raw_data { capture } else { RawCapture::Never };
+        let decoded = instruction_sets.len();
+        let status = registry.decode_in_transaction(
+            instruction, &accounts, transaction, &mut instruction_sets);
+        // The instructions of degraded processors are kept raw whatever the capture, to be
+        // decoded again once they're restored.
+        let degraded = status == DecodeStatus::Degraded;
+        if status == DecodeStatus::Failed || degraded {
+            if capture != RawCapture::Never || (degraded && keeps_raw_data) {
+                failure.raw_data = Some(RawData::new(&compiled.data));
+            }
+            decode_failures.push(failure);
+        }
+        for instruction_set in &mut instruction_sets[decoded..] {
+            if capture == RawCapture::Always {
+                instruction_set.raw_data = Some(RawData::new(&compiled.data));
+            }
+            call_tree.annotate(instruction_set);
+            lifecycle_hints.note(instruction_set, &accounts);
+            if let Some(mints) = registry.mint_cache() {
+                mints.add_ui_amounts(instruction_set, transaction, &compiled.accounts);
+            }
+            if let Some(versions) = registry.program_versions() {
+                versions.stamp(instruction_set, transaction.slot);
+            }
+            #[cfg(feature = "phoenix")]
+            crate::programs::phoenix::add_prices(registry, instruction_set, transaction,
+                                                 &compiled.accounts);
+        }
+    });
+    let signer_activity = attribute(transaction, &instruction_sets);
+    let account_lifecycle = lifecycle::derive(transaction, &lifecycle_hints, registry.network());
+    // Last, what's derived out of the properties reading their keys as the processors emit them.
+    for instruction_set in &mut instruction_sets {
+        registry.namespace_keys(instruction_set);
+    }
+    let fees = fees::breakdown(transaction);
+
+    TransactionRecord {
+        transaction_hash: transaction.transaction_hash.clone(),
+        slot: transaction.slot,
+        fee: transaction.fee,
+        base_fee_lamports: fees.map(|fees| fees.base_fee_lamports),
+        priority_fee_lamports: fees.map(|fees| fees.priority_fee_lamports),
+        compute_unit_price_micro_lamports: fees.map(|fees| fees.compute_unit_price_micro_lamports),
+        succeeded: transaction.succeeded,
+        timestamp: transaction.block_time.unwrap_or_default(),
+        timestamp_estimated: false,
+        finalized: false,
+        network: registry.network(),
+        instruction_sets,
+        decode_failures,
+        call_tree,
+        signer_activity,
+        account_lifecycle,
+        confirmed,
+        simulation: None,
+    }
+}
+
+/// Async version of [`decode_transaction`], kept for existing callers.
+#[deprecated(note = "decoding is synchronous, use `decode_transaction` instead")]
+pub async fn process_transaction(
+    registry: &Arc<ProcessorRegistry>,
+    transaction: &RawTransaction,
+) -> TransactionRecord {
+    decode_transaction(registry, transaction)
+}
+
+// The fixture is a token transfer, which has to be decoded.
+#[cfg(all(test, feature = "token"))]
+mod tests {
+    use std::io;
+    use std::sync::Mutex;
+
+    use spl_token::instruction::TokenInstruction;
+    use tracing_subscriber::fmt::format::FmtSpan;
+
+    use super::*;
+    use crate::ingest::filter::{MintFilter, ProgramFilter};
+
+    #[derive(Clone, Default)]
+    struct CapturedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for CapturedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn decoding_a_transaction_opens_spans() {
+        let output = CapturedOutput::default();
+        let writer = output.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_span_events(FmtSpan::NEW)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+
+        let (payer, source, destination) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let transaction = RawTransaction {
+            slot: 42,
+            block_time: None,
+            transaction_hash: "fixture-signature".to_string(),
+            account_keys: vec![payer, source, destination, spl_token::id()],
+            num_required_signatures: 1,
+            instructions: vec![CompiledInstruction {
+                program_id_index: 3,
+                accounts: vec![1, 2, 0],
+                data: TokenInstruction::Transfer { amount: 10 }.pack(),
+            }],
+            inner_instructions: vec![],
+            token_mints: vec![],
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        };
+        let record = tracing::subscriber::with_default(subscriber, || {
+            decode_transaction(&ProcessorRegistry::default(), &transaction)
+        });
+        assert_eq!(record.instruction_sets.len(), 1);
+        assert_eq!((record.base_fee_lamports, record.priority_fee_lamports), (Some(5000), Some(0)));
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("decode_transaction{slot=42 signature=fixture-signature}"),
+                "{}", output);
+        assert!(output.contains(&format!(
+            "decode_instruction{{program={} instruction_index=0 parent_index=-1}}",
+            spl_token::id())), "{}", output);
+    }
+
+    #[test]
+    fn allowed_inner_instructions_of_other_programs_are_decoded() {
+        let (payer, source, destination) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let router = Pubkey::new_unique();
+        let transfer = CompiledInstruction {
+            program_id_index: 3,
+            accounts: vec![1, 2, 0],
+            data: TokenInstruction::Transfer { amount: 10 }.pack(),
+        };
+        let transaction = RawTransaction {
+            slot: 42,
+            block_time: None,
+            transaction_hash: "fixture-signature".to_string(),
+            account_keys: vec![payer, source, destination, spl_token::id(), router],
+            num_required_signatures: 1,
+            // The router isn't on the allowlist, the transfer it invokes is.
+            instructions: vec![
+                CompiledInstruction { program_id_index: 4, ..transfer.clone() },
+                transfer.clone(),
+            ],
+            inner_instructions: vec![InnerInstructions { index: 0, instructions: vec![transfer] }],
+            token_mints: vec![],
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        };
+        // Decodable, so that only the filter keeps the router's instruction out.
+        let mut registry = ProcessorRegistry::default();
+        registry.register(&router.to_string(), crate::BuiltinProcessor::Token);
+        let token = Symbol::intern(&spl_token::id().to_string());
+
+        let allowed = DecodeFilters {
+            programs: Some(ProgramFilter::default().allow(Some(token))),
+            ..DecodeFilters::default()
+        };
+        let record = decode_transaction_filtered(&registry, &allowed, &transaction).unwrap();
+        let decoded: Vec<_> = record.instruction_sets.iter()
+            .map(|set| (set.function.tx_instruction_id, set.function.parent_index))
+            .collect();
+        assert_eq!(decoded, vec![(0, 0), (1, -1)]);
+
+        let denied = DecodeFilters {
+            programs: Some(ProgramFilter::default().deny(Some(token))),
+            ..DecodeFilters::default()
+        };
+        let record = decode_transaction_filtered(&registry, &denied, &transaction).unwrap();
+        assert!(record.instruction_sets.is_empty());
+    }
+
+    #[test]
+    fn unknown_programs_are_not_interned() {
+        let (payer, source, destination) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let unknown = Pubkey::new_unique();
+        let transfer = CompiledInstruction {
+            program_id_index: 3,
+            accounts: vec![1, 2, 0],
+            data: TokenInstruction::Transfer { amount: 10 }.pack(),
+        };
+        let transaction = RawTransaction {
+            slot: 42,
+            block_time: None,
+            transaction_hash: "fixture-signature".to_string(),
+            account_keys: vec![payer, source, destination, spl_token::id(), unknown],
+            num_required_signatures: 1,
+            instructions: vec![CompiledInstruction { program_id_index: 4, ..transfer.clone() }],
+            inner_instructions: vec![InnerInstructions { index: 0, instructions: vec![transfer] }],
+            token_mints: vec![],
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        };
+
+        let record = decode_transaction(&ProcessorRegistry::default(), &transaction);
+        assert_eq!(record.instruction_sets.len(), 1);
+        assert_eq!(Symbol::lookup(&unknown.to_string()), None);
+        let programs: Vec<_> = record.call_tree.nodes.iter()
+            .map(|node| (node.program.to_string(), node.program.symbol().is_some()))
+            .collect();
+        assert_eq!(programs, vec![
+            (unknown.to_string(), false),
+            (spl_token::id().to_string(), true),
+        ]);
+    }
+
+    #[test]
+    fn mint_filter_on_a_multi_mint_transaction() {
+        let payer = Pubkey::new_unique();
+        let (usdc, wsol, bonk) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let transfer = |source: u8, destination: u8| CompiledInstruction {
+            program_id_index: 5,
+            accounts: vec![source, destination, 0],
+            data: TokenInstruction::Transfer { amount: 10 }.pack(),
+        };
+        let mut account_keys = vec![payer];
+        account_keys.extend((0..4).map(|_| Pubkey::new_unique()));
+        account_keys.push(spl_token::id());
+        // Swaps USDC for wrapped SOL: two token accounts of each mint.
+        let transaction = RawTransaction {
+            slot: 42,
+            block_time: None,
+            transaction_hash: "fixture-signature".to_string(),
+            account_keys,
+            num_required_signatures: 1,
+            instructions: vec![transfer(1, 2), transfer(3, 4)],
+            inner_instructions: vec![],
+            token_mints: vec![(1, usdc), (2, usdc), (3, wsol), (4, wsol)].into_iter()
+                .map(|(account_index, mint)| TokenMint { account_index, mint, decimals: 6 })
+                .collect(),
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        };
+        let registry = ProcessorRegistry::default();
+        let decode = |mints: Vec<Pubkey>, strict: bool| {
+            let filters = DecodeFilters {
+                mints: Some(MintFilter::new(mints, strict)),
+                ..DecodeFilters::default()
+            };
+            decode_transaction_filtered(&registry, &filters, &transaction)
+                .map(|record| record.instruction_sets.iter()
+                    .map(|set| set.function.tx_instruction_id)
+                    .collect::<Vec<_>>())
+        };
+
+        assert_eq!(decode(vec![usdc], false), Some(vec![0, 1]));
+        assert_eq!(decode(vec![usdc], true), Some(vec![0]));
+        assert_eq!(decode(vec![wsol], true), Some(vec![1]));
+        assert_eq!(decode(vec![usdc, wsol], true), Some(vec![0, 1]));
+        assert_eq!(decode(vec![bonk], false), None);
+    }
+
+    #[test]
+    fn vote_transactions_are_skipped_unless_included() {
+        let (validator, vote_account) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // A vote on slot 41, as `VoteInstruction::Vote` is serialized: its tag, its slots, the hash
+        // of the bank and no timestamp.
+        let mut data = 2u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&1u64.to_le_bytes());
+        data.extend_from_slice(&41u64.to_le_bytes());
+        data.extend_from_slice(&[7; 32]);
+        data.push(0);
+        let vote = CompiledInstruction { program_id_index: 2, accounts: vec![1, 0], data };
+        let mut transaction = RawTransaction {
+            slot: 42,
+            block_time: None,
+            transaction_hash: "fixture-signature".to_string(),
+            account_keys: vec![validator, vote_account, *VOTE_PROGRAM, spl_token::id()],
+            num_required_signatures: 1,
+            instructions: vec![vote.clone()],
+            inner_instructions: vec![],
+            token_mints: vec![],
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        };
+        let registry = ProcessorRegistry::default();
+        // Skipped by default.
+        let skip_votes = DecodeFilters::default();
+        assert!(transaction.is_vote());
+        assert!(decode_transaction_filtered(&registry, &skip_votes, &transaction).is_none());
+        let record = decode_transaction_filtered(&registry, &DecodeFilters::none(), &transaction)
+            .unwrap();
+        if cfg!(feature = "vote") {
+            assert_eq!(record.instruction_sets.len(), 1);
+            assert_eq!(record.instruction_sets[0].function.function_name.as_str(), "vote");
+        }
+
+        // Votes along with other instructions aren't those of a validator voting.
+        transaction.instructions.push(CompiledInstruction {
+            program_id_index: 3,
+            accounts: vec![0],
+            data: TokenInstruction::SyncNative.pack(),
+        });
+        assert!(!transaction.is_vote());
+        assert!(decode_transaction_filtered(&registry, &skip_votes, &transaction).is_some());
+    }
+
+    #[test]
+    fn raw_data_is_captured_as_configured() {
+        let (payer, source, destination) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let transfer = TokenInstruction::Transfer { amount: 10 }.pack();
+        let transaction = RawTransaction {
+            slot: 42,
+            block_time: None,
+            transaction_hash: "fixture-signature".to_string(),
+            account_keys: vec![payer, source, destination, spl_token::id()],
+            num_required_signatures: 1,
+            instructions: vec![
+                CompiledInstruction { program_id_index: 3, accounts: vec![1, 2, 0],
+                                      data: transfer.clone() },
+                CompiledInstruction { program_id_index: 3, accounts: vec![], data: vec![255] },
+            ],
+            inner_instructions: vec![],
+            token_mints: vec![],
+            fee: 5000,
+            succeeded: true,
+            stack_heights: vec![],
+            log_messages: vec![],
+            pre_balances: vec![],
+            post_balances: vec![],
+        };
+        let decode = |capture| {
+            let mut registry = ProcessorRegistry::default();
+            registry.set_raw_capture(capture);
+            let mut record = decode_transaction(&registry, &transaction);
+            (record.instruction_sets.remove(0).raw_data, record.decode_failures.remove(0).raw_data)
+        };
+
+        assert_eq!(decode(RawCapture::Never), (None, None));
+        assert_eq!(decode(RawCapture::OnFailure), (None, Some(RawData::new(&[255]))));
+        let (decoded, failed) = decode(RawCapture::Always);
+        assert_eq!(decoded.unwrap().bytes().unwrap(), transfer);
+        assert_eq!(failed, Some(RawData { data: "/w==".to_string(), len: 1 }));
+    }
+}
@@ -0,0 +1,533 @@
+//! Cross-checks our decoding against the `jsonParsed` output of RPC, for the programs RPC parses.
+//!
+//! RPC names instructions and fields in camelCase and lists the accounts an instruction was
+//! invoked with along with its data, where our processors only extract the data. Every program
+//! has a table of the parsed fields that map to one of our property keys; parsed fields not in
+//! the table (the accounts, mostly) aren't compared.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_client::rpc_request::RpcRequest;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_transaction_status::{
+    EncodedConfirmedTransaction, EncodedTransaction, UiInstruction, UiMessage,
+    UiParsedInstruction, UiTransactionEncoding,
+};
+
+use crate::ingest::pool::RpcPool;
+use crate::ingest::{fetch_transaction, IngestError};
+use crate::registry::ProcessorRegistry;
+use crate::transaction::{decode_transaction, TransactionRecord};
+use crate::InstructionSet;
+
+/// A parsed field compared to one of our properties.
+struct Field {
+    // The parsed instruction type the field belongs to, `*` for any.
+    instruction: &'static str,
+    // The path of the field in the parsed `info`, nested objects separated by dots.
+    parsed: &'static str,
+    // The key of our property.
+    key: &'static str,
+}
+
+const fn field(instruction: &'static str, parsed: &'static str, key: &'static str) -> Field {
+    Field { instruction, parsed, key }
+}
+
+/// The programs RPC parses, along with their field mappings.
+const PROGRAMS: &[(&str, &[Field])] = &[
+    ("11111111111111111111111111111111", &[
+        field("*", "lamports", "lamports"),
+        field("*", "owner", "owner"),
+        field("*", "space", "space"),
+        field("*", "seed", "seed"),
+        field("*", "base", "base"),
+        field("initializeNonce", "nonceAuthority", "authority"),
+        field("authorizeNonce", "newAuthorized", "authority"),
+    ]),
+    ("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA", &[
+        field("*", "amount", "amount"),
+        field("*", "decimals", "decimals"),
+        field("*", "tokenAmount.amount", "amount"),
+        field("*", "tokenAmount.decimals", "decimals"),
+        field("initializeMint", "mintAuthority", "mint_authority"),
+        field("initializeMint", "freezeAuthority", "freeze_authority"),
+        field("initializeMultisig", "m", "m"),
+        field("setAuthority", "authorityType", "authority_type"),
+        field("setAuthority", "newAuthority", "new_authority"),
+    ]),
+    ("Stake11111111111111111111111111111111111111", &[
+        field("*", "lamports", "lamports"),
+        field("initialize", "authorized.staker", "staker"),
+        field("initialize", "authorized.withdrawer", "withdrawer"),
+        field("*", "lockup.unixTimestamp", "unix_timestamp"),
+        field("*", "lockup.epoch", "epoch"),
+        field("*", "lockup.custodian", "custodian"),
+        field("authorize", "authorityType", "stake_authorize"),
+    ]),
+    ("Vote111111111111111111111111111111111111111", &[
+        field("*", "lamports", "lamports"),
+        field("*", "commission", "commission"),
+        field("*", "vote.hash", "hash"),
+        field("*", "vote.timestamp", "timestamp"),
+        field("initialize", "authorizedVoter", "authorized_voter"),
+        field("initialize", "authorizedWithdrawer", "authorized_withdrawer"),
+        field("authorize", "authorityType", "vote_authorize"),
+    ]),
+    ("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL", &[
+        field("*", "source", "funder"),
+        field("*", "account", "associated_account"),
+        field("*", "wallet", "wallet"),
+        field("*", "mint", "mint"),
+    ]),
+    // RPC parses memos to the memo itself, compared as if it were `{"memo": <memo>}`.
+    ("Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo", &[field("memo", "memo", "memo")]),
+    ("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr", &[field("memo", "memo", "memo")]),
+];
+
+/// An instruction as parsed by RPC.
+#[derive(Clone, Debug)]
+pub struct ParsedInstruction {
+    pub tx_instruction_id: i16,
+    pub parent_index: i16,
+    pub program_id: String,
+    // `{"type": ..., "info": {...}}`, or a plain string for memos.
+    pub parsed: Value,
+}
+
+/// A disagreement between RPC and our decoding.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Mismatch {
+    // RPC parsed the instruction, we didn't decode it.
+    NotDecoded { tx_instruction_id: i16, parent_index: i16, program: String, expected: String },
+    // We decoded the instruction to another function.
+    Function {
+        tx_instruction_id: i16,
+        parent_index: i16,
+        program: String,
+        expected: String,
+        actual: String,
+    },
+    // A mapped field RPC has which we don't.
+    MissingKey {
+        tx_instruction_id: i16,
+        parent_index: i16,
+        program: String,
+        function: String,
+        key: String,
+    },
+    Value {
+        tx_instruction_id: i16,
+        parent_index: i16,
+        program: String,
+        function: String,
+        key: String,
+        expected: String,
+        actual: String,
+    },
+    // The number of instructions of a program parsed by RPC and decoded by us.
+    Count { program: String, parsed: usize, decoded: usize },
+}
+
+/// The mismatches found in a transaction.
+#[derive(Clone, Debug, Serialize)]
+pub struct VerifyReport {
+    pub signature: String,
+    pub slot: u64,
+    // The instructions of the programs RPC parses.
+    pub compared: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// Whether RPC parses the instructions of `program_id` (as far as we compare them).
+pub fn is_verifiable(program_id: &str) -> bool {
+    fields(program_id).is_some()
+}
+
+fn fields(program_id: &str) -> Option<&'static [Field]> {
+    PROGRAMS.iter()
+        .find(|(program, _)| *program == program_id)
+        .map(|(_, fields)| *fields)
+}
+
+/// Fetches a transaction both raw and `jsonParsed`, decodes the raw one and compares both.
+/// Returns `None` when the node doesn't have the transaction.
+pub async fn verify_transaction(
+    rpc: &Arc<RpcPool>,
+    registry: &ProcessorRegistry,
+    signature: &str,
+    commitment: CommitmentConfig,
+) -> Result<Option<VerifyReport>, IngestError> {
+    let params = json!([signature, RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::JsonParsed),
+        commitment: Some(commitment),
+    }]);
+    let parsed = rpc.call(move |rpc| {
+        rpc.send::<Option<EncodedConfirmedTransaction>>(RpcRequest::GetTransaction, params.clone())
+    }).await?;
+    let parsed = match parsed {
+        Some(parsed) => parsed,
+        None => return Ok(None),
+    };
+    let raw = match fetch_transaction(rpc, signature, commitment).await? {
+        Some(raw) => raw,
+        None => return Ok(None),
+    };
+
+    let record = decode_transaction(registry, &raw);
+    let parsed = parsed_instructions(&parsed);
+
+    Ok(Some(VerifyReport {
+        signature: signature.to_string(),
+        slot: raw.slot,
+        compared: parsed.iter().filter(|p| is_verifiable(&p.program_id)).count(),
+        mismatches: compare(&parsed, &record),
+    }))
+}
+
+/// Flattens the parsed instructions of a `jsonParsed` transaction the way
+/// `RawTransaction::instructions` does. Instructions RPC couldn't parse are left out.
+pub fn parsed_instructions(transaction: &EncodedConfirmedTransaction) -> Vec<ParsedInstruction> {
+    let message = match &transaction.transaction.transaction {
+        EncodedTransaction::Json(transaction) => &transaction.message,
+        _ => return Vec::new(),
+    };
+    let instructions = match message {
+        UiMessage::Parsed(message) => &message.instructions,
+        UiMessage::Raw(_) => return Vec::new(),
+    };
+    let inner_instructions = transaction.transaction.meta.as_ref()
+        .and_then(|meta| meta.inner_instructions.as_ref());
+
+    let mut parsed = Vec::new();
+    for (idx, instruction) in instructions.iter().enumerate() {
+        parsed.extend(parsed_instruction(instruction, idx as i16, -1));
+
+        let inner = inner_instructions.into_iter().flatten()
+            .filter(|ii| ii.index as usize == idx)
+            .flat_map(|ii| ii.instructions.iter());
+        for (inner_idx, instruction) in inner.enumerate() {
+            parsed.extend(parsed_instruction(instruction, inner_idx as i16, idx as i16));
+        }
+    }
+
+    parsed
+}
+
+fn parsed_instruction(
+    instruction: &UiInstruction,
+    tx_instruction_id: i16,
+    parent_index: i16,
+) -> Option<ParsedInstruction> {
+    match instruction {
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed)) => Some(ParsedInstruction {
+            tx_instruction_id,
+            parent_index,
+            program_id: parsed.program_id.clone(),
+            parsed: parsed.parsed.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// Compares the instructions parsed by RPC with the ones we decoded, for the programs RPC
+/// parses.
+pub fn compare(parsed: &[ParsedInstruction], record: &TransactionRecord) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    let mut counts: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+
+    for instruction in parsed {
+        let fields = match fields(&instruction.program_id) {
+            Some(fields) => fields,
+            None => continue,
+        };
+        counts.entry(&instruction.program_id).or_default().0 += 1;
+
+        let (instruction_type, info) = match &instruction.parsed {
+            Value::String(memo) => ("memo", Some(json!({ "memo": memo }))),
+            parsed => (
+                parsed.get("type").and_then(Value::as_str).unwrap_or_default(),
+                parsed.get("info").cloned(),
+            ),
+        };
+        let expected = kebab_case(instruction_type);
+        let (tx_instruction_id, parent_index) =
+            (instruction.tx_instruction_id, instruction.parent_index);
+        let program = instruction.program_id.clone();
+
+        let decoded = record.instruction_sets.iter().find(|set| {
+            set.function.tx_instruction_id == tx_instruction_id
+                && set.function.parent_index == parent_index
+        });
+        let decoded = match decoded {
+            Some(decoded) => decoded,
+            None => {
+                mismatches.push(Mismatch::NotDecoded {
+                    tx_instruction_id, parent_index, program, expected,
+                });
+                continue;
+            }
+        };
+
+        let function = decoded.function.function_name.as_str().to_string();
+        if function != expected {
+            mismatches.push(Mismatch::Function {
+                tx_instruction_id,
+                parent_index,
+                program: program.clone(),
+                expected: expected.clone(),
+                actual: function.clone(),
+            });
+        }
+
+        let applicable = fields.iter()
+            .filter(|field| field.instruction == "*" || field.instruction == instruction_type);
+        for field in applicable {
+            let expected = match info.as_ref().and_then(|info| lookup(info, field.parsed)) {
+                Some(expected) => expected,
+                None => continue,
+            };
+            match property(decoded, field.key) {
+                None => mismatches.push(Mismatch::MissingKey {
+                    tx_instruction_id,
+                    parent_index,
+                    program: program.clone(),
+                    function: function.clone(),
+                    key: field.key.to_string(),
+                }),
+                Some(actual) if actual != expected => mismatches.push(Mismatch::Value {
+                    tx_instruction_id,
+                    parent_index,
+                    program: program.clone(),
+                    function: function.clone(),
+                    key: field.key.to_string(),
+                    expected,
+                    actual: actual.to_string(),
+                }),
+                Some(_) => {}
+            }
+        }
+    }
+
+    for instruction_set in &record.instruction_sets {
+        let program = instruction_set.function.program.as_str();
+        if is_verifiable(program) {
+            counts.entry(program).or_default().1 += 1;
+        }
+    }
+    for (program, (parsed, decoded)) in counts {
+        if parsed != decoded {
+            mismatches.push(Mismatch::Count { program: program.to_string(), parsed, decoded });
+        }
+    }
+
+    mismatches
+}
+
+/// `initializeAccount2` to `initialize-account-2`, the way our function names go.
+fn kebab_case(name: &str) -> String {
+    let mut kebab = String::with_capacity(name.len() + 4);
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        let starts_word = match previous {
+            Some(previous) => (c.is_ascii_uppercase() && !previous.is_ascii_uppercase())
+                || (c.is_ascii_digit() && !previous.is_ascii_digit()),
+            None => false,
+        };
+        if starts_word {
+            kebab.push('-');
+        }
+        kebab.push(c.to_ascii_lowercase());
+        previous = Some(c);
+    }
+
+    kebab
+}
+
+/// The field at a dotted `path` of `value`, as a string.
+fn lookup(value: &Value, path: &str) -> Option<String> {
+    let value = path.split('.').try_fold(value, |value, key| value.get(key))?;
+    match value {
+        Value::Null => None,
+        Value::String(string) => Some(string.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+fn property<'a>(instruction_set: &'a InstructionSet, key: &str) -> Option<&'a str> {
+    instruction_set.properties.iter()
+        .find(|property| property.key.as_str() == key)
+        .map(|property| property.value.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::call_tree::CallTree;
+    use crate::{InstructionFunction, InstructionProperty, Network, PropertyType, Symbol};
+
+    const TOKEN: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+
+    fn decoded(tx_instruction_id: i16, function_name: &str, properties: &[(&str, &str)])
+        -> InstructionSet {
+        let transaction_hash: Arc<str> = Arc::from("signature");
+        InstructionSet {
+            function: InstructionFunction {
+                tx_instruction_id,
+                transaction_hash: transaction_hash.clone(),
+                parent_index: -1,
+                program: Symbol::intern(TOKEN),
+                function_name: Symbol::intern(function_name),
+                timestamp: 0,
+                network: Network::Mainnet,
+                decoder_version: 0,
+            },
+            properties: properties.iter()
+                .map(|(key, value)| InstructionProperty {
+                    tx_instruction_id,
+                    transaction_hash: transaction_hash.clone(),
+                    parent_index: -1,
+                    key: Symbol::intern(key),
+                    value: value.to_string(),
+                    value_type: PropertyType::infer(value),
+                    parent_key: Symbol::intern(""),
+                    timestamp: 0,
+                })
+                .collect(),
+            raw_data: None,
+        }
+    }
+
+    fn parsed(tx_instruction_id: i16, parsed: Value) -> ParsedInstruction {
+        ParsedInstruction {
+            tx_instruction_id,
+            parent_index: -1,
+            program_id: TOKEN.to_string(),
+            parsed,
+        }
+    }
+
+    #[test]
+    fn names_are_kebab_cased() {
+        assert_eq!(kebab_case("transferChecked"), "transfer-checked");
+        assert_eq!(kebab_case("initializeAccount2"), "initialize-account-2");
+        assert_eq!(kebab_case("transfer"), "transfer");
+    }
+
+    #[test]
+    fn reports_field_level_mismatches() {
+        let parsed = vec![
+            parsed(0, json!({"type": "transfer", "info": {
+                "source": "a", "destination": "b", "authority": "c", "amount": "10",
+            }})),
+            parsed(1, json!({"type": "transferChecked", "info": {
+                "source": "a", "mint": "m", "destination": "b", "authority": "c",
+                "tokenAmount": {"amount": "10", "decimals": 6, "uiAmount": 0.00001},
+            }})),
+            parsed(2, json!({"type": "burn", "info": {"amount": "5"}})),
+        ];
+        let record = TransactionRecord {
+            transaction_hash: "signature".to_string(),
+            slot: 1,
+            fee: 5000,
+            base_fee_lamports: None,
+            priority_fee_lamports: None,
+            compute_unit_price_micro_lamports: None,
+            succeeded: true,
+            timestamp: 0,
+            timestamp_estimated: false,
+            finalized: false,
+            network: Network::Mainnet,
+            instruction_sets: vec![
+                decoded(0, "transfer", &[("amount", "10")]),
+                decoded(1, "transfer-checked", &[("amount", "11")]),
+            ],
+            decode_failures: vec![],
+            call_tree: CallTree::default(),
+            signer_activity: Vec::new(),
+            account_lifecycle: Vec::new(),
+            confirmed: true,
+            simulation: None,
+        };
+
+        assert_eq!(compare(&parsed, &record), vec![
+            Mismatch::Value {
+                tx_instruction_id: 1,
+                parent_index: -1,
+                program: TOKEN.to_string(),
+                function: "transfer-checked".to_string(),
+                key: "amount".to_string(),
+                expected: "10".to_string(),
+                actual: "11".to_string(),
+            },
+            Mismatch::MissingKey {
+                tx_instruction_id: 1,
+                parent_index: -1,
+                program: TOKEN.to_string(),
+                function: "transfer-checked".to_string(),
+                key: "decimals".to_string(),
+            },
+            Mismatch::NotDecoded {
+                tx_instruction_id: 2,
+                parent_index: -1,
+                program: TOKEN.to_string(),
+                expected: "burn".to_string(),
+            },
+            Mismatch::Count { program: TOKEN.to_string(), parsed: 3, decoded: 2 },
+        ]);
+    }
+
+    #[test]
+    fn memos_are_compared_to_the_string_rpc_parses_them_to() {
+        const MEMO: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+        let parsed_memo = |tx_instruction_id, memo: &str| ParsedInstruction {
+            program_id: MEMO.to_string(),
+            ..parsed(tx_instruction_id, json!(memo))
+        };
+        let decoded_memo = |tx_instruction_id, memo: &str| {
+            let mut instruction_set = decoded(tx_instruction_id, "memo", &[("memo", memo)]);
+            instruction_set.function.program = Symbol::intern(MEMO);
+            instruction_set
+        };
+        let record = TransactionRecord {
+            transaction_hash: "signature".to_string(),
+            slot: 1,
+            fee: 5000,
+            base_fee_lamports: None,
+            priority_fee_lamports: None,
+            compute_unit_price_micro_lamports: None,
+            succeeded: true,
+            timestamp: 0,
+            timestamp_estimated: false,
+            finalized: false,
+            network: Network::Mainnet,
+            instruction_sets: vec![decoded_memo(0, "invoice 4521"), decoded_memo(1, "invoice")],
+            decode_failures: vec![],
+            call_tree: CallTree::default(),
+            signer_activity: Vec::new(),
+            account_lifecycle: Vec::new(),
+            confirmed: true,
+            simulation: None,
+        };
+
+        let parsed = [parsed_memo(0, "invoice 4521"), parsed_memo(1, "invoice 4522")];
+        assert_eq!(compare(&parsed, &record), vec![
+            Mismatch::Value {
+                tx_instruction_id: 1,
+                parent_index: -1,
+                program: MEMO.to_string(),
+                function: "memo".to_string(),
+                key: "memo".to_string(),
+                expected: "invoice 4522".to_string(),
+                actual: "invoice".to_string(),
+            },
+        ]);
+    }
+}
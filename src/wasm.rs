@@ -0,0 +1,40 @@
+//! The decoders, exported to JavaScript so that a frontend decodes instructions exactly like the
+//! indexer. Build without the runtime and with the processors whose dependencies build for wasm32
+//! (see `tests/feature_combinations.rs`), e.g.:
+//!
+//! `wasm-pack build --target web -- --no-default-features --features wasm,token,token-lending`
+
+use once_cell::sync::Lazy;
+use solana_sdk::instruction::CompiledInstruction;
+use wasm_bindgen::prelude::*;
+
+use crate::data::{decode_instruction_data, DataEncoding};
+use crate::{Instruction, InstructionSet, ProcessorRegistry};
+
+static REGISTRY: Lazy<ProcessorRegistry> = Lazy::new(ProcessorRegistry::default);
+
+/// Decodes a top-level instruction of `program_id` out of its base58 encoded data, to the JSON of
+/// the instruction sets it decodes to: none when the program isn't supported or the data can't
+/// be decoded. Throws when `data_base58` isn't base58.
+#[wasm_bindgen]
+pub fn decode_instruction(program_id: &str, data_base58: &str) -> Result<JsValue, JsValue> {
+    let data = decode_instruction_data(data_base58, DataEncoding::Base58)
+        .map_err(|err| JsValue::from_str(&err.to_string()))?;
+    // The instruction is the only one of its transaction as far as secp256k1 is concerned, which
+    // reads the signatures it verifies out of the transaction's instructions.
+    let instructions = [CompiledInstruction { program_id_index: 0, accounts: vec![], data }];
+    let instruction = Instruction {
+        tx_instruction_id: 0,
+        transaction_hash: "".into(),
+        program: program_id.into(),
+        data: instructions[0].data.clone(),
+        parent_index: -1,
+        timestamp: 0,
+        network: Default::default(),
+    };
+
+    let decoded: Vec<InstructionSet> = REGISTRY.decode(instruction, Some(&instructions))
+        .into_iter()
+        .collect();
+    JsValue::from_serde(&decoded).map_err(|err| JsValue::from_str(&err.to_string()))
+}
@@ -0,0 +1,191 @@
+//! Timing the dispatches of the registry, for slow processors not to stall the pipeline unseen.
+//!
+//! Every instruction handed to a processor is timed against two budgets. Going over the soft one
+//! logs a warning naming the program, the discriminant of the instruction and how long it took.
+//! Going over the hard one counts as a violation: once a processor goes over it for so many
+//! dispatches in a row, its program is degraded, its instructions no longer handed to it but kept
+//! raw (see [`DecodeStatus::Degraded`]) to be decoded again later, until an operator restores it
+//! (see [`ProcessorRegistry::restore`]).
+//!
+//! Budgets are checked as dispatches return: a processor that never does isn't caught.
+//!
+//! [`DecodeStatus::Degraded`]: crate::registry::DecodeStatus::Degraded
+//! [`ProcessorRegistry::restore`]: crate::registry::ProcessorRegistry::restore
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::interner::Symbol;
+
+/// The budgets of dispatches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    // What a dispatch may take before a warning is logged, at most the hard budget.
+    pub soft_budget: Duration,
+    // What a dispatch may take before it counts as a violation.
+    pub hard_budget: Duration,
+    // How many violations in a row degrade a program, 0 never to.
+    pub max_violations: u32,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig {
+            soft_budget: Duration::from_millis(5),
+            hard_budget: Duration::from_millis(100),
+            max_violations: 3,
+        }
+    }
+}
+
+/// How the dispatches of a program went.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WatchdogStats {
+    pub dispatches: u64,
+    // Dispatches over the soft budget, those over the hard one included.
+    pub soft_violations: u64,
+    pub hard_violations: u64,
+    // Hard violations since the last dispatch within the budget.
+    pub consecutive_violations: u32,
+    pub degraded: bool,
+}
+
+#[derive(Default)]
+struct ProgramHealth {
+    dispatches: AtomicU64,
+    soft_violations: AtomicU64,
+    hard_violations: AtomicU64,
+    consecutive_violations: AtomicU32,
+    degraded: AtomicBool,
+}
+
+/// Watches the dispatches of a registry, by program.
+#[derive(Default)]
+pub struct Watchdog {
+    config: WatchdogConfig,
+    programs: RwLock<HashMap<Symbol, Arc<ProgramHealth>>>,
+}
+
+impl Watchdog {
+    pub fn new(config: WatchdogConfig) -> Self {
+        Watchdog { config, programs: RwLock::new(HashMap::new()) }
+    }
+
+    pub fn config(&self) -> WatchdogConfig {
+        self.config
+    }
+
+    fn health(&self, program: Symbol) -> Arc<ProgramHealth> {
+        if let Some(health) = self.programs.read().unwrap().get(&program) {
+            return health.clone();
+        }
+
+        self.programs.write().unwrap().entry(program).or_default().clone()
+    }
+
+    /// Records that `processor` took `elapsed` to decode an instruction of `program`, starting
+    /// with `discriminant`. Returns whether that degraded the program.
+    pub fn observe(
+        &self,
+        program: Symbol,
+        processor: &str,
+        discriminant: Option<u8>,
+        elapsed: Duration,
+    ) -> bool {
+        let health = self.health(program);
+        health.dispatches.fetch_add(1, Ordering::Relaxed);
+        if elapsed <= self.config.soft_budget {
+            health.consecutive_violations.store(0, Ordering::Relaxed);
+            return false;
+        }
+
+        health.soft_violations.fetch_add(1, Ordering::Relaxed);
+        record_violation(program, "soft");
+        let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+        warn!(%program, processor, ?discriminant, elapsed_ms,
+              "[spi-wrapper/watchdog] Decoding an instruction of {} took {:.1}ms, over the budget \
+              of {:?}.", program, elapsed_ms, self.config.soft_budget);
+        if elapsed <= self.config.hard_budget {
+            health.consecutive_violations.store(0, Ordering::Relaxed);
+            return false;
+        }
+
+        health.hard_violations.fetch_add(1, Ordering::Relaxed);
+        record_violation(program, "hard");
+        let violations = health.consecutive_violations.fetch_add(1, Ordering::Relaxed) + 1;
+        let degrades = self.config.max_violations > 0 && violations >= self.config.max_violations;
+        if degrades && !health.degraded.swap(true, Ordering::Relaxed) {
+            warn!(%program, processor, violations,
+                  "[spi-wrapper/watchdog] {} went over the hard budget of {:?} {} times in a row, \
+                  its instructions are kept raw until it's restored.", program,
+                  self.config.hard_budget, violations);
+            record_degraded(program, true);
+            return true;
+        }
+
+        false
+    }
+
+    /// Whether the instructions of `program` are no longer decoded.
+    pub fn is_degraded(&self, program: Symbol) -> bool {
+        self.programs.read().unwrap().get(&program)
+            .map_or(false, |health| health.degraded.load(Ordering::Relaxed))
+    }
+
+    /// Decodes the instructions of `program` again, with a clean slate. Returns whether it was
+    /// degraded.
+    pub fn restore(&self, program: Symbol) -> bool {
+        let health = match self.programs.read().unwrap().get(&program) {
+            Some(health) => health.clone(),
+            None => return false,
+        };
+        health.consecutive_violations.store(0, Ordering::Relaxed);
+        let degraded = health.degraded.swap(false, Ordering::Relaxed);
+        if degraded {
+            record_degraded(program, false);
+        }
+
+        degraded
+    }
+
+    /// The programs degraded.
+    pub fn degraded(&self) -> Vec<Symbol> {
+        self.programs.read().unwrap().iter()
+            .filter(|(_, health)| health.degraded.load(Ordering::Relaxed))
+            .map(|(program, _)| *program)
+            .collect()
+    }
+
+    /// How the dispatches of `program` went, if it had any.
+    pub fn stats(&self, program: Symbol) -> Option<WatchdogStats> {
+        let programs = self.programs.read().unwrap();
+        let health = programs.get(&program)?;
+        Some(WatchdogStats {
+            dispatches: health.dispatches.load(Ordering::Relaxed),
+            soft_violations: health.soft_violations.load(Ordering::Relaxed),
+            hard_violations: health.hard_violations.load(Ordering::Relaxed),
+            consecutive_violations: health.consecutive_violations.load(Ordering::Relaxed),
+            degraded: health.degraded.load(Ordering::Relaxed),
+        })
+    }
+}
+
+#[allow(unused_variables)]
+fn record_violation(program: Symbol, budget: &str) {
+    #[cfg(feature = "metrics")]
+    crate::metrics::metrics().dispatch_budget_violations
+        .with_label_values(&[program.as_str(), budget])
+        .inc();
+}
+
+#[allow(unused_variables)]
+fn record_degraded(program: Symbol, degraded: bool) {
+    #[cfg(feature = "metrics")]
+    crate::metrics::metrics().processors_degraded
+        .with_label_values(&[program.as_str()])
+        .set(degraded as i64);
+}
@@ -0,0 +1,131 @@
+//! Decoding through one registry shared between threads.
+
+use std::sync::Arc;
+use std::thread;
+
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::{system_instruction, system_program};
+use spl_token::instruction::TokenInstruction;
+use spi_wrapper::mints::{MintCache, MintCacheConfig};
+use spi_wrapper::{decode_transaction, Network, ProcessError, ProcessorRegistry, RawTransaction};
+
+const INSTRUCTIONS: usize = 100_000;
+const INSTRUCTIONS_PER_TRANSACTION: usize = 4;
+const THREADS: usize = 16;
+const MINTS: usize = 8;
+const COUNTER_PROGRAM: &str = "Counter111111111111111111111111111111111111";
+
+/// The `n`th transaction: a token transfer, a `mint-to` whose UI amount comes from the mint cache,
+/// a system transfer and a counter increment decoded by a closure.
+fn transaction(n: usize, mints: &[Pubkey]) -> RawTransaction {
+    let (payer, source, destination) =
+        (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let mint = mints[n % mints.len()];
+    let amount = n as u64 * 1_000;
+
+    RawTransaction {
+        slot: n as u64,
+        block_time: Some(1_700_000_000 + n as i64),
+        transaction_hash: format!("{:0>88}", n),
+        account_keys: vec![
+            payer, source, destination, mint, spl_token::id(), system_program::id(),
+            COUNTER_PROGRAM.parse().unwrap(),
+        ],
+        num_required_signatures: 1,
+        instructions: vec![
+            CompiledInstruction {
+                program_id_index: 4,
+                accounts: vec![1, 2, 0],
+                data: TokenInstruction::Transfer { amount }.pack(),
+            },
+            CompiledInstruction {
+                program_id_index: 4,
+                accounts: vec![3, 2, 0],
+                data: TokenInstruction::MintTo { amount }.pack(),
+            },
+            CompiledInstruction {
+                program_id_index: 5,
+                accounts: vec![0, 2],
+                data: system_instruction::transfer(&payer, &destination, amount).data,
+            },
+            CompiledInstruction {
+                program_id_index: 6,
+                accounts: vec![2],
+                data: amount.to_le_bytes().to_vec(),
+            },
+        ],
+        inner_instructions: vec![],
+        token_mints: vec![],
+        fee: 5000,
+        succeeded: true,
+        stack_heights: vec![],
+        log_messages: vec![],
+        pre_balances: vec![],
+        post_balances: vec![],
+    }
+}
+
+#[test]
+fn a_shared_registry_decodes_like_a_single_thread() {
+    let mints: Vec<Pubkey> = (0..MINTS).map(|_| Pubkey::new_unique()).collect();
+    let cache = MintCache::new(MintCacheConfig::default());
+    for (index, mint) in mints.iter().enumerate() {
+        cache.seed(*mint, index as u8 + 1);
+    }
+    let registry = ProcessorRegistry::builder()
+        .network(Network::Devnet)
+        .mint_cache(cache)
+        .register_fn(COUNTER_PROGRAM, |ctx| {
+            let mut amount = [0; 8];
+            amount.copy_from_slice(ctx.data.get(..8)
+                .ok_or_else(|| ProcessError::Other("too short".to_string()))?);
+            Ok(vec![ctx.instruction_set("increment", vec![
+                ("amount", u64::from_le_bytes(amount).to_string()),
+            ])])
+        })
+        .build();
+
+    let transactions: Arc<Vec<RawTransaction>> = Arc::new(
+        (0..INSTRUCTIONS / INSTRUCTIONS_PER_TRANSACTION)
+            .map(|n| transaction(n, &mints))
+            .collect());
+    let decode = |transaction: &RawTransaction| {
+        serde_json::to_string(&decode_transaction(&registry, transaction)).unwrap()
+    };
+    let expected: Vec<String> = transactions.iter().map(decode).collect();
+    // Every instruction decoded, the UI amounts included.
+    let decoded: usize = transactions.iter()
+        .map(|transaction| decode_transaction(&registry, transaction).instruction_sets.len())
+        .sum();
+    assert_eq!(decoded, INSTRUCTIONS);
+    assert!(expected[1].contains("amount_ui"), "{}", expected[1]);
+
+    let handles: Vec<_> = (0..THREADS)
+        .map(|thread| {
+            let (registry, transactions) = (registry.clone(), transactions.clone());
+            thread::spawn(move || {
+                transactions.iter()
+                    .enumerate()
+                    .skip(thread)
+                    .step_by(THREADS)
+                    .map(|(index, transaction)| {
+                        let record = decode_transaction(&registry, transaction);
+                        (index, serde_json::to_string(&record).unwrap())
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let mut concurrent = vec![String::new(); expected.len()];
+    for handle in handles {
+        for (index, record) in handle.join().unwrap() {
+            concurrent[index] = record;
+        }
+    }
+
+    assert_eq!(concurrent.len(), expected.len());
+    for (index, (concurrent, expected)) in concurrent.iter().zip(&expected).enumerate() {
+        assert_eq!(concurrent, expected, "transaction {}", index);
+    }
+}
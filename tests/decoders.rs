@@ -0,0 +1,627 @@
+//! Arbitrary and mutated instruction data fed to every built-in processor, which has to decode it
+//! or give up without panicking, and round trips of instructions packed by the upstream crates.
+//! Whatever they decode to has to match the schemas the processors declare.
+
+use proptest::prelude::*;
+use solana_sdk::instruction::CompiledInstruction;
+use spi_wrapper::{
+    BuiltinProcessor, Instruction, InstructionSet, Network, ProcessorRegistry, PropertyType,
+};
+
+fn instruction(program_id: &str, data: Vec<u8>) -> Instruction {
+    Instruction {
+        tx_instruction_id: 0,
+        transaction_hash: "signature".into(),
+        program: program_id.into(),
+        data,
+        parent_index: -1,
+        timestamp: 0,
+        network: Network::Mainnet,
+    }
+}
+
+/// Decodes `data` as the only instruction of a transaction, which the secp256k1 processor reads
+/// the signatures from.
+fn decode(
+    registry: &ProcessorRegistry,
+    program_id: &str,
+    data: Vec<u8>,
+) -> Option<InstructionSet> {
+    let top_level = [CompiledInstruction {
+        program_id_index: 0,
+        accounts: vec![],
+        data: data.clone(),
+    }];
+    registry.decode(instruction(program_id, data), Some(&top_level))
+}
+
+/// The (first) program id `processor` is registered for.
+#[allow(dead_code)]
+fn program_id(processor: BuiltinProcessor) -> &'static str {
+    BuiltinProcessor::all().into_iter()
+        .find(|(_, builtin)| *builtin == processor)
+        .map(|(program_id, _)| program_id)
+        .unwrap()
+}
+
+fn program_ids() -> impl Strategy<Value = &'static str> {
+    let program_ids: Vec<_> = BuiltinProcessor::all().into_iter()
+        .map(|(program_id, _)| program_id)
+        .collect();
+    prop::sample::select(program_ids)
+}
+
+/// Valid encodings of a few instructions of every program, to be mutated.
+#[allow(unused_mut)]
+fn valid_encodings() -> Vec<Vec<u8>> {
+    let mut encodings = vec![
+        // System transfer, stake and vote `Authorize` style enum tags followed by a u64.
+        [2u32.to_le_bytes().to_vec(), 1_000u64.to_le_bytes().to_vec()].concat(),
+        // Token transfer.
+        [vec![3], 1_000u64.to_le_bytes().to_vec()].concat(),
+        // Secp256k1 with one signature whose offsets point into its own data.
+        [vec![1], vec![0; 11], vec![0; 100]].concat(),
+        // Upgradeable loader buffer without an authority.
+        vec![1, 0, 0, 0, 0],
+    ];
+    #[cfg(feature = "token")]
+    encodings.push(spl_token::instruction::TokenInstruction::InitializeMint {
+        decimals: 6,
+        mint_authority: solana_sdk::pubkey::Pubkey::new_unique(),
+        freeze_authority: solana_sdk::program_option::COption::None,
+    }.pack());
+    // Drift `place_perp_order`, behind its Anchor discriminator.
+    #[cfg(feature = "drift")]
+    encodings.push(hex::decode("45a15dca787e4cb901010003809698000000000000ac23fc06000000010001\
+        0200013cf1536500000000000000010a0000").unwrap());
+    // Phoenix `PlaceLimitOrder` of a post only order.
+    #[cfg(feature = "phoenix")]
+    encodings.push(hex::decode("020000e457000000000000d00700000000000007000000000000000000000000\
+        0000000100000000").unwrap());
+    // Lifinity and Aldrin `swap`, behind its Anchor discriminator.
+    #[cfg(any(feature = "lifinity", feature = "aldrin"))]
+    encodings.push(hex::decode("f8c69e91e17587c8009435770000000060e316000000000001").unwrap());
+    // OpenBook v2 `place_take_order`.
+    #[cfg(feature = "openbook-v2")]
+    encodings.push(hex::decode("032c47031ac7cb5501d007000000000000030000000000000000e1f50500000000\
+        030a").unwrap());
+    // Tensor `buy_nft` and Magic Eden `execute_sale_v2`, behind their Anchor discriminators.
+    #[cfg(feature = "tensor")]
+    encodings.push(hex::decode("60001cbe316b53de020000f2052a0100000000e1f5050000000001016400\
+        00dd0ee9020000000000013200").unwrap());
+    #[cfg(feature = "magic-eden")]
+    encodings.push(hex::decode("5bdc31dfcc8135c1fffe00f902950000000001000000000000000\
+        0f1536500000000ffffffffffffffffceffc800").unwrap());
+
+    encodings
+}
+
+#[derive(Debug, Clone)]
+enum Mutation {
+    Flip { index: usize, byte: u8 },
+    Truncate(usize),
+    Append(Vec<u8>),
+}
+
+fn mutations() -> impl Strategy<Value = Mutation> {
+    prop_oneof![
+        (any::<usize>(), any::<u8>()).prop_map(|(index, byte)| Mutation::Flip { index, byte }),
+        any::<usize>().prop_map(Mutation::Truncate),
+        prop::collection::vec(any::<u8>(), 1..16).prop_map(Mutation::Append),
+    ]
+}
+
+fn mutate(mut data: Vec<u8>, mutations: &[Mutation]) -> Vec<u8> {
+    for mutation in mutations {
+        match mutation {
+            Mutation::Flip { index, byte } if !data.is_empty() => {
+                let index = index % data.len();
+                data[index] ^= byte;
+            }
+            Mutation::Flip { .. } => {}
+            Mutation::Truncate(len) => data.truncate(len % (data.len() + 1)),
+            Mutation::Append(bytes) => data.extend_from_slice(bytes),
+        }
+    }
+
+    data
+}
+
+fn property<'a>(instruction_set: &'a InstructionSet, key: &str) -> Option<&'a str> {
+    instruction_set.properties.iter()
+        .find(|property| property.key.as_str() == key)
+        .map(|property| property.value.as_str())
+}
+
+/// The keys of the properties whose processor didn't state their type.
+fn untyped(instruction_set: &InstructionSet) -> Vec<&str> {
+    instruction_set.properties.iter()
+        .filter(|property| property.value_type == PropertyType::Unknown)
+        .map(|property| property.key.as_str())
+        .collect()
+}
+
+/// The function and properties of `instruction_set` that the schema of its processor doesn't
+/// declare, or declares with another type.
+fn undeclared(registry: &ProcessorRegistry, instruction_set: &InstructionSet) -> Vec<String> {
+    let program_id = instruction_set.function.program.as_str();
+    let schema = registry.processor(program_id).unwrap().describe();
+    let function_name = instruction_set.function.function_name.as_str();
+    if schema.get(function_name).is_none() {
+        return vec![format!("function {:?}", function_name)];
+    }
+
+    instruction_set.properties.iter()
+        .filter(|property| {
+            schema.key(function_name, &property.key, &property.parent_key)
+                .map_or(true, |declared| declared.value_type != property.value_type)
+        })
+        .map(|property| format!("{}/{} ({})", property.parent_key.as_str(),
+            property.key.as_str(), property.value_type.as_str()))
+        .collect()
+}
+
+/// Instructions packed by the upstream crates, with every kind of property their processors
+/// emit.
+#[allow(unused_mut)]
+fn packed_samples() -> Vec<(BuiltinProcessor, Vec<u8>)> {
+    let mut samples = Vec::new();
+    // `Create`, with and without data, and `CreateIdempotent`.
+    #[cfg(feature = "associated-token-account")]
+    samples.extend(vec![vec![], vec![0], vec![1]].into_iter()
+        .map(|data| (BuiltinProcessor::AssociatedTokenAccount, data)));
+    // A UTF-8 memo and one that isn't.
+    #[cfg(feature = "memo")]
+    samples.extend(vec![b"invoice 4521".to_vec(), vec![0xff, 0xfe]].into_iter()
+        .map(|data| (BuiltinProcessor::Memo, data)));
+    #[cfg(feature = "system")]
+    {
+        use solana_sdk::pubkey::Pubkey;
+        use solana_sdk::system_instruction::SystemInstruction;
+
+        let (base, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let instructions = vec![
+            SystemInstruction::CreateAccount { lamports: 1, space: 2, owner },
+            SystemInstruction::Assign { owner },
+            SystemInstruction::Transfer { lamports: 1 },
+            SystemInstruction::CreateAccountWithSeed {
+                base,
+                seed: "seed".to_string(),
+                lamports: 1,
+                space: 2,
+                owner,
+            },
+            SystemInstruction::AuthorizeNonceAccount(owner),
+            SystemInstruction::TransferWithSeed {
+                lamports: 1,
+                from_seed: "seed".to_string(),
+                from_owner: owner,
+            },
+        ];
+        samples.extend(instructions.iter().map(|instruction| {
+            (BuiltinProcessor::System, bincode::serialize(instruction).unwrap())
+        }));
+    }
+    #[cfg(feature = "stake")]
+    {
+        use solana_sdk::pubkey::Pubkey;
+        use solana_sdk::stake::instruction::{LockupArgs, StakeInstruction};
+        use solana_sdk::stake::state::{Authorized, Lockup, StakeAuthorize};
+
+        let (staker, withdrawer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let instructions = vec![
+            StakeInstruction::Initialize(
+                Authorized { staker, withdrawer },
+                Lockup { unix_timestamp: 1, epoch: 2, custodian: withdrawer },
+            ),
+            StakeInstruction::Authorize(staker, StakeAuthorize::Withdrawer),
+            StakeInstruction::Withdraw(1),
+            StakeInstruction::SetLockup(LockupArgs {
+                unix_timestamp: Some(1),
+                epoch: Some(2),
+                custodian: Some(staker),
+            }),
+        ];
+        samples.extend(instructions.iter().map(|instruction| {
+            (BuiltinProcessor::Stake, bincode::serialize(instruction).unwrap())
+        }));
+    }
+    #[cfg(feature = "token")]
+    {
+        use solana_sdk::program_option::COption;
+        use solana_sdk::pubkey::Pubkey;
+        use spl_token::instruction::{AuthorityType, TokenInstruction};
+
+        let authority = Pubkey::new_unique();
+        let instructions = vec![
+            TokenInstruction::InitializeMint {
+                decimals: 6,
+                mint_authority: authority,
+                freeze_authority: COption::Some(authority),
+            },
+            TokenInstruction::InitializeAccount2 { owner: authority },
+            TokenInstruction::InitializeMultisig { m: 2 },
+            TokenInstruction::Approve { amount: 1 },
+            TokenInstruction::SetAuthority {
+                authority_type: AuthorityType::MintTokens,
+                new_authority: COption::Some(authority),
+            },
+            TokenInstruction::TransferChecked { amount: 1, decimals: 6 },
+        ];
+        samples.extend(instructions.iter()
+            .map(|instruction| (BuiltinProcessor::Token, instruction.pack())));
+    }
+    #[cfg(feature = "token-2022")]
+    {
+        use solana_sdk::pubkey::Pubkey;
+
+        // Not packed by an upstream crate, none building against this solana version: an
+        // initialize-mint-2 with a freeze authority, a transfer fee config, a confidential
+        // deposit, an interest rate update, a transfer hook, a metadata pointer and a multiplier
+        // update.
+        let authority = Pubkey::new_unique();
+        let key = authority.as_ref();
+        let instructions: Vec<Vec<u8>> = vec![
+            [&[20, 6][..], key, &[1], key].concat(),
+            [&[26, 0, 1][..], key, &[1], key, &[50, 0], &[0xff; 8]].concat(),
+            [&[27, 5][..], &[1, 0, 0, 0, 0, 0, 0, 0], &[6]].concat(),
+            vec![33, 1, 0xe7, 0xff],
+            [&[36, 0][..], key, key].concat(),
+            [&[39, 0][..], key, &[0; 32]].concat(),
+            [&[43, 1][..], &2.0f64.to_bits().to_le_bytes(), &[0; 8]].concat(),
+        ];
+        samples.extend(instructions.into_iter()
+            .map(|data| (BuiltinProcessor::Token2022, data)));
+    }
+    // Whirlpool `swap` and `open_position`, behind their Anchor discriminators.
+    #[cfg(feature = "whirlpool")]
+    samples.extend(vec![
+        hex::decode("f8c69e91e17587c800ca9a3b00000000002d310100000000503b01000100000000000000000\
+            000000101").unwrap(),
+        hex::decode("87802f4d0f98f031ff00a8ffff00580000").unwrap(),
+    ].into_iter().map(|data| (BuiltinProcessor::Whirlpool, data)));
+    // Raydium `Initialize2`, `Deposit` with its minimum, `Withdraw` and `SwapBaseIn`.
+    #[cfg(feature = "raydium-amm")]
+    samples.extend(vec![
+        [&[1, 254][..], &[0; 24]].concat(),
+        [&[3][..], &[0; 32]].concat(),
+        [&[4][..], &[0; 8]].concat(),
+        [&[9][..], &[0; 16]].concat(),
+    ].into_iter().map(|data| (BuiltinProcessor::RaydiumAmm, data)));
+    // Token metadata `CreateMetadataAccountV3` of a creator and uses, `UpdateMetadataAccountV2`
+    // of the update authority, `VerifyCollection` and a pNFT `Transfer`.
+    #[cfg(feature = "token-metadata")]
+    samples.extend(vec![
+        [&[33, 3, 0, 0, 0][..], b"Ape", &[0; 4], &[0; 4], &[0xf4, 0x01, 1, 1, 0, 0, 0], &[7; 32],
+         &[1, 100, 0, 1, 1], &[0; 16], &[1, 0]].concat(),
+        [&[15, 0, 1][..], &[7; 32], &[0, 0]].concat(),
+        vec![18],
+        [&[49, 0][..], &[1, 0, 0, 0, 0, 0, 0, 0], &[0]].concat(),
+    ].into_iter().map(|data| (BuiltinProcessor::TokenMetadata, data)));
+    // Candy Machine `initialize_v2` of hidden settings and `mint_v2`.
+    #[cfg(feature = "candy-machine")]
+    samples.extend(vec![
+        [&[67, 153, 175, 39, 218, 16, 38, 32][..], &[0; 8], &[3, 0, 0, 0], b"APE", &[0; 11],
+         &[1, 0, 0, 0], &[7; 32], &[1, 100, 0, 1], &[1, 0, 0, 0], b"?", &[0; 4], &[9; 32], &[4]]
+            .concat(),
+        vec![120, 121, 23, 146, 173, 110, 199, 205],
+    ].into_iter().map(|data| (BuiltinProcessor::CandyMachine, data)));
+    // Auction House `sell`, `execute_sale` and `deposit`.
+    #[cfg(feature = "auction-house")]
+    samples.extend(vec![
+        [&[51, 230, 133, 164, 1, 127, 131, 173][..], &[0; 19]].concat(),
+        [&[37, 74, 217, 157, 79, 49, 35, 6][..], &[0; 19]].concat(),
+        [&[242, 35, 198, 137, 82, 225, 242, 182][..], &[0; 9]].concat(),
+    ].into_iter().map(|data| (BuiltinProcessor::AuctionHouse, data)));
+    // Bubblegum `mint_v1` of a collection, uses and a creator, and `transfer`.
+    #[cfg(feature = "bubblegum")]
+    samples.extend(vec![
+        [&[145, 98, 192, 118, 184, 147, 118, 104][..], &[1, 0, 0, 0], b"?", &[0; 8],
+         &[0xf4, 0x01, 0, 1, 1, 255, 1, 0, 1, 0], &[7; 32], &[1, 1], &[0; 16],
+         &[0, 1, 0, 0, 0], &[8; 32], &[1, 100]].concat(),
+        [&[163, 52, 200, 231, 140, 3, 69, 186][..], &[0; 108]].concat(),
+    ].into_iter().map(|data| (BuiltinProcessor::Bubblegum, data)));
+    // Governance `CreateProposal` of two options, `CastVote` approving one and
+    // `DepositGoverningTokens`.
+    #[cfg(feature = "governance")]
+    samples.extend(vec![
+        [&[6, 1, 0, 0, 0][..], b"?", &[0; 4], &[0, 2, 0, 0, 0, 1, 0, 0, 0], b"Y", &[1, 0, 0, 0],
+         b"N", &[1]].concat(),
+        vec![13, 0, 1, 0, 0, 0, 0, 100],
+        [&[1][..], &[0; 8]].concat(),
+    ].into_iter().map(|data| (BuiltinProcessor::Governance, data)));
+    // Name service `Create` of a domain, `Update` of its record and `Transfer`.
+    #[cfg(feature = "name-service")]
+    samples.extend(vec![
+        [&[0, 32, 0, 0, 0][..], &[7; 32], &[0; 12]].concat(),
+        vec![1, 0, 0, 0, 0, 1, 0, 0, 0, 9],
+        [&[2][..], &[8; 32]].concat(),
+    ].into_iter().map(|data| (BuiltinProcessor::NameService, data)));
+    // Compute budget `SetComputeUnitLimit`, `SetComputeUnitPrice` and the deprecated
+    // `RequestUnits`.
+    #[cfg(feature = "compute-budget")]
+    samples.extend(vec![
+        vec![2, 0xe0, 0x93, 0x04, 0],
+        [&[3][..], &[0x50, 0xc3], &[0; 6]].concat(),
+        [&[0][..], &[0; 8]].concat(),
+    ].into_iter().map(|data| (BuiltinProcessor::ComputeBudget, data)));
+    #[cfg(feature = "serum-market")]
+    {
+        use std::num::NonZeroU64;
+
+        use serum_dex::instruction::{MarketInstruction, NewOrderInstructionV3, SelfTradeBehavior};
+        use serum_dex::matching::{OrderType, Side};
+
+        let instructions = vec![
+            MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+                side: Side::Bid,
+                limit_price: NonZeroU64::new(1).unwrap(),
+                max_coin_qty: NonZeroU64::new(2).unwrap(),
+                max_native_pc_qty_including_fees: NonZeroU64::new(3).unwrap(),
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                order_type: OrderType::Limit,
+                client_order_id: 4,
+                limit: 5,
+            }),
+            MarketInstruction::ConsumeEvents(6),
+            MarketInstruction::SettleFunds,
+            MarketInstruction::InitOpenOrders,
+        ];
+        samples.extend(instructions.iter()
+            .map(|instruction| (BuiltinProcessor::SerumMarket, instruction.pack())));
+    }
+    #[cfg(feature = "token-lending")]
+    {
+        use solana_sdk::pubkey::Pubkey;
+        use spl_token_lending::instruction::LendingInstruction;
+
+        let instructions = vec![
+            LendingInstruction::InitLendingMarket {
+                owner: Pubkey::new_unique(),
+                quote_currency: [1; 32],
+            },
+            LendingInstruction::DepositReserveLiquidity { liquidity_amount: 1 },
+            LendingInstruction::FlashLoan { amount: 1 },
+        ];
+        samples.extend(instructions.iter()
+            .map(|instruction| (BuiltinProcessor::TokenLending, instruction.pack())));
+    }
+    #[cfg(feature = "drift")]
+    {
+        // Drift has no crate to pack its instructions, these are `place_perp_order`,
+        // `cancel_order`, `deposit` and `liquidate_perp` as sent to the program.
+        let instructions = [
+            "45a15dca787e4cb901010003809698000000000000ac23fc060000000100010200013cf1536500000000\
+            000000010a0000",
+            "5f81edf00831df84012a000000",
+            "f223c68952e1f2b6000000e1f5050000000000",
+            "4b2377f7bf128b02000000f2052a0100000001002d310100000000",
+        ];
+        samples.extend(instructions.iter()
+            .map(|data| (BuiltinProcessor::Drift, hex::decode(data).unwrap())));
+    }
+    #[cfg(feature = "phoenix")]
+    {
+        // Likewise for Phoenix: `Swap`, `CancelMultipleOrdersById` and `WithdrawFunds`.
+        let instructions = [
+            "000201010852000000000000dc0500000000000000000000000000000000000000000000c005d9010000\
+            0000010000000000000000000000000000000000000000",
+            "0a0200000000e457000000000000d204000000000000013c5a0000000000002e16000000000080",
+            "0c0140420f000000000000",
+        ];
+        samples.extend(instructions.iter()
+            .map(|data| (BuiltinProcessor::Phoenix, hex::decode(data).unwrap())));
+    }
+    #[cfg(feature = "lifinity")]
+    samples.push((BuiltinProcessor::Lifinity,
+                  hex::decode("f8c69e91e17587c800ca9a3b00000000002d310100000000").unwrap()));
+    #[cfg(feature = "aldrin")]
+    {
+        // `swap`, `create_basket` and `redeem_basket`.
+        let instructions = [
+            "f8c69e91e17587c8009435770000000060e316000000000001",
+            "2f699b940fa9cad300ca9a3b0000000000e40b5402000000e070720000000000",
+            "2585de39bda0972900ca9a3b000000000003164e0200000040ea700000000000",
+        ];
+        samples.extend(instructions.iter()
+            .map(|data| (BuiltinProcessor::Aldrin, hex::decode(data).unwrap())));
+    }
+    #[cfg(feature = "openbook-v2")]
+    {
+        // `place_order`, `cancel_order`, `cancel_all_orders` and `deposit`.
+        let instructions = [
+            "33c29baf6d82606a00ca080000000000000a00000000000000ffffffffffffff7f07000000000000000200\
+            00000000000000000a",
+            "5f81edf00831df84d2040000000000000000000000000000",
+            "c453f3ab1164a08f010114",
+            "f223c68952e1f2b600ca9a3b0000000080f0fa0200000000",
+        ];
+        samples.extend(instructions.iter()
+            .map(|data| (BuiltinProcessor::OpenbookV2, hex::decode(data).unwrap())));
+    }
+    #[cfg(feature = "tensor")]
+    {
+        // `buy_nft`, `list` and `delist` of tswap.
+        let instructions = [
+            "60001cbe316b53de020000f2052a0100000000e1f505000000000101640000dd0ee90200000000000132\
+            00",
+            "36aec1431129842680de800200000000",
+            "3788cd6b6bad041f",
+        ];
+        samples.extend(instructions.iter()
+            .map(|data| (BuiltinProcessor::Tensor, hex::decode(data).unwrap())));
+    }
+    #[cfg(feature = "magic-eden")]
+    {
+        // `sell`, `buy_v2` and `execute_sale_v2`.
+        let instructions = [
+            "33e685a4017f83adfefd00f90295000000000100000000000000ffffffffffffffff",
+            "b817ee6167c5d33d008c864700000000010000000000000000f1536500000000fa0000000000",
+            "5bdc31dfcc8135c1fffe00f9029500000000010000000000000000f1536500000000ffffffffffffffffce\
+            ffc800",
+        ];
+        samples.extend(instructions.iter()
+            .map(|data| (BuiltinProcessor::MagicEden, hex::decode(data).unwrap())));
+    }
+
+    samples
+}
+
+#[test]
+fn every_property_is_typed() {
+    let registry = ProcessorRegistry::default();
+    for (processor, data) in packed_samples() {
+        let decoded = decode(&registry, program_id(processor), data).unwrap();
+        assert_eq!(untyped(&decoded), Vec::<&str>::new(), "{:?} {}",
+            processor, decoded.function.function_name);
+    }
+
+    // Whatever the generic encodings decode to, for every program.
+    for (program_id, _) in BuiltinProcessor::all() {
+        for data in valid_encodings() {
+            if let Some(decoded) = decode(&registry, program_id, data) {
+                assert_eq!(untyped(&decoded), Vec::<&str>::new(), "{} {}",
+                    program_id, decoded.function.function_name);
+            }
+        }
+    }
+}
+
+#[test]
+fn every_property_is_declared() {
+    let registry = ProcessorRegistry::default();
+    for (processor, data) in packed_samples() {
+        let decoded = decode(&registry, program_id(processor), data).unwrap();
+        assert_eq!(undeclared(&registry, &decoded), Vec::<String>::new(), "{:?} {}",
+            processor, decoded.function.function_name);
+    }
+
+    for (program_id, _) in BuiltinProcessor::all() {
+        for data in valid_encodings() {
+            if let Some(decoded) = decode(&registry, program_id, data) {
+                assert_eq!(undeclared(&registry, &decoded), Vec::<String>::new(), "{} {}",
+                    program_id, decoded.function.function_name);
+            }
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_data_never_panics(
+        program_id in program_ids(),
+        data in prop::collection::vec(any::<u8>(), 0..512),
+    ) {
+        decode(&ProcessorRegistry::default(), program_id, data);
+    }
+
+    #[test]
+    fn mutated_encodings_never_panic(
+        program_id in program_ids(),
+        encoding in prop::sample::select(valid_encodings()),
+        mutations in prop::collection::vec(mutations(), 1..4),
+    ) {
+        decode(&ProcessorRegistry::default(), program_id, mutate(encoding, &mutations));
+    }
+
+    #[test]
+    fn arbitrary_data_decodes_to_typed_properties(
+        program_id in program_ids(),
+        data in prop::collection::vec(any::<u8>(), 0..512),
+    ) {
+        if let Some(decoded) = decode(&ProcessorRegistry::default(), program_id, data) {
+            prop_assert!(untyped(&decoded).is_empty(), "{:?}", untyped(&decoded));
+        }
+    }
+
+    #[test]
+    fn mutated_encodings_decode_to_declared_properties(
+        program_id in program_ids(),
+        encoding in prop::sample::select(valid_encodings()),
+        mutations in prop::collection::vec(mutations(), 1..4),
+    ) {
+        let registry = ProcessorRegistry::default();
+        if let Some(decoded) = decode(&registry, program_id, mutate(encoding, &mutations)) {
+            let undeclared = undeclared(&registry, &decoded);
+            prop_assert!(undeclared.is_empty(), "{:?}", undeclared);
+        }
+    }
+}
+
+#[cfg(feature = "token")]
+proptest! {
+    #[test]
+    fn token_instructions_round_trip(amount in any::<u64>(), decimals in any::<u8>()) {
+        use spl_token::instruction::TokenInstruction;
+
+        let registry = ProcessorRegistry::default();
+        let cases = vec![
+            (TokenInstruction::Transfer { amount }, "transfer"),
+            (TokenInstruction::MintTo { amount }, "mint-to"),
+            (TokenInstruction::Burn { amount }, "burn"),
+            (TokenInstruction::TransferChecked { amount, decimals }, "transfer-checked"),
+        ];
+        for (token_instruction, function) in cases {
+            let decoded = decode(&registry, program_id(BuiltinProcessor::Token),
+                token_instruction.pack()).unwrap();
+            prop_assert_eq!(decoded.function.function_name.as_str(), function);
+            let expected = amount.to_string();
+            prop_assert_eq!(property(&decoded, "amount"), Some(expected.as_str()));
+        }
+    }
+}
+
+#[cfg(feature = "system")]
+proptest! {
+    #[test]
+    fn system_instructions_round_trip(lamports in any::<u64>(), space in any::<u64>()) {
+        use solana_sdk::pubkey::Pubkey;
+        use solana_sdk::system_instruction::SystemInstruction;
+
+        let registry = ProcessorRegistry::default();
+        let system_program = program_id(BuiltinProcessor::System);
+
+        let transfer = bincode::serialize(&SystemInstruction::Transfer { lamports }).unwrap();
+        let decoded = decode(&registry, system_program, transfer).unwrap();
+        prop_assert_eq!(decoded.function.function_name.as_str(), "transfer");
+        let expected = lamports.to_string();
+        prop_assert_eq!(property(&decoded, "lamports"), Some(expected.as_str()));
+
+        let owner = Pubkey::new_unique();
+        let create_account = bincode::serialize(&SystemInstruction::CreateAccount {
+            lamports,
+            space,
+            owner,
+        }).unwrap();
+        let decoded = decode(&registry, system_program, create_account).unwrap();
+        prop_assert_eq!(decoded.function.function_name.as_str(), "create-account");
+        let (expected_space, expected_owner) = (space.to_string(), owner.to_string());
+        prop_assert_eq!(property(&decoded, "space"), Some(expected_space.as_str()));
+        prop_assert_eq!(property(&decoded, "owner"), Some(expected_owner.as_str()));
+    }
+}
+
+#[cfg(feature = "token-lending")]
+proptest! {
+    #[test]
+    fn lending_instructions_round_trip(amount in any::<u64>()) {
+        use spl_token_lending::instruction::LendingInstruction;
+
+        let registry = ProcessorRegistry::default();
+        let lending_program = program_id(BuiltinProcessor::TokenLending);
+        let cases = vec![
+            (LendingInstruction::DepositReserveLiquidity { liquidity_amount: amount },
+             "deposit-reserve-liquidity", "liquidity_amount"),
+            (LendingInstruction::RedeemReserveCollateral { collateral_amount: amount },
+             "redeem-reserve-collateral", "collateral_amount"),
+            (LendingInstruction::BorrowObligationLiquidity { liquidity_amount: amount },
+             "borrow-obligation-liquidity", "liquidity_amount"),
+        ];
+        for (lending_instruction, function, key) in cases {
+            let decoded = decode(&registry, lending_program, lending_instruction.pack()).unwrap();
+            prop_assert_eq!(decoded.function.function_name.as_str(), function);
+            let expected = amount.to_string();
+            prop_assert_eq!(property(&decoded, key), Some(expected.as_str()));
+        }
+    }
+}
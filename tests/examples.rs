@@ -0,0 +1,143 @@
+//! Runs the example binaries against a JSON-RPC node serving the fixtures of `tests/fixtures`
+//! (see `MockRpc`) and a local webhook, so that they keep working along with the crate.
+//!
+//! The examples are built along with the tests by `cargo test`; when they weren't (running this
+//! test alone), they're built first.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+use spi_wrapper::snapshot::{read_snapshot, Batch};
+use spi_wrapper::testing::{fixture_path, serve_http, MockRpc};
+
+const FEATURES: &str = "cli,snapshot,notify,test-utils,lending";
+const TOKEN_LENDING: &str = "LendZqTs8gn5CTSJU1jWKhKuVpjJGom45nnwPb2AMTi";
+
+/// Runs the example `name` until it exits, asserting that it succeeds. Returns what it printed.
+fn run_example(name: &str, args: &[&str], envs: &[(&str, &str)]) -> String {
+    // Tests run from `target/<profile>/deps`, the examples live in `target/<profile>/examples`.
+    let mut dir = std::env::current_exe().unwrap();
+    dir.pop();
+    if dir.ends_with("deps") {
+        dir.pop();
+    }
+    let binary = dir.join("examples").join(format!("{}{}", name, std::env::consts::EXE_SUFFIX));
+    if !binary.exists() {
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+        let mut build = Command::new(cargo);
+        build.args(&["build", "--example", name, "--features", FEATURES]);
+        if !cfg!(debug_assertions) {
+            build.arg("--release");
+        }
+        assert!(build.status().unwrap().success(), "unable to build the {} example", name);
+    }
+
+    let output = Command::new(&binary)
+        .args(args)
+        .envs(envs.iter().copied())
+        .env("RUST_LOG", "warn")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{} failed: {}", name,
+            String::from_utf8_lossy(&output.stderr));
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("spi-example-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
+
+fn fixtures(names: &[&str]) -> Vec<PathBuf> {
+    names.iter().map(|name| fixture_path(name)).collect()
+}
+
+#[test]
+fn follow_to_sqlite_resumes_from_its_checkpoint() {
+    let rpc = MockRpc::serve(&fixtures(&[
+        "token-transfer",
+        "system-create-account",
+        "lending-deposit-reserve-liquidity",
+        "lending-liquidate-obligation",
+    ])).unwrap();
+    let dir = temp_dir("follow");
+    let db = dir.join("index.sqlite");
+    let db = db.to_str().unwrap();
+    let follow = || run_example("follow_to_sqlite",
+        &["--db", db, "--start-slot", "110000001", "--exit-when-caught-up"],
+        &[("SPI_RPC_URL", rpc.url())]);
+    let transactions = || rusqlite::Connection::open(db).unwrap()
+        .query_row("SELECT COUNT(*) FROM transactions", [], |row| row.get::<_, i64>(0))
+        .unwrap();
+
+    assert!(follow().contains("Indexed up to slot 110000004"));
+    assert_eq!(transactions(), 4);
+    // Nothing new since the checkpoint.
+    assert!(follow().contains("Indexed up to slot 110000004"));
+    assert_eq!(transactions(), 4);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn backfill_program_to_snapshot_writes_every_transaction_of_the_program() {
+    let rpc = MockRpc::serve(&fixtures(&[
+        "token-transfer",
+        "system-create-account",
+        "lending-deposit-reserve-liquidity",
+        "lending-liquidate-obligation",
+        "router-nested-cpi",
+    ])).unwrap();
+    let dir = temp_dir("backfill");
+    let out = dir.join("snapshots");
+
+    let output = run_example("backfill_program_to_snapshot",
+        &["--rpc-url", rpc.url(), "--out", out.to_str().unwrap(), "--requests-per-second", "100"],
+        &[("SPI_PROGRAM", TOKEN_LENDING)]);
+    assert!(output.contains("Backfilled 2 transactions"), "{}", output);
+
+    let mut slots = HashSet::new();
+    for entry in fs::read_dir(&out).unwrap() {
+        match read_snapshot(entry.unwrap().path()).unwrap() {
+            Batch::Transactions(transactions) => {
+                slots.extend(transactions.iter().map(|transaction| transaction.slot));
+            }
+            Batch::InstructionSets(_) => panic!("the snapshots hold transactions"),
+        }
+    }
+    assert_eq!(slots, vec![110000003, 110000004].into_iter().collect());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn stream_liquidations_posts_liquidations_to_webhooks() {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let webhook = {
+        let received = received.clone();
+        serve_http(move |body| {
+            received.lock().unwrap().push(serde_json::from_slice::<Value>(body).unwrap());
+            "{}".to_string()
+        }).unwrap()
+    };
+    let replayed = fixtures(&["lending-deposit-reserve-liquidity", "lending-liquidate-obligation"]);
+
+    let output = run_example("stream_liquidations", &[
+        "--webhook", &webhook,
+        "--replay", replayed[0].to_str().unwrap(),
+        "--replay", replayed[1].to_str().unwrap(),
+    ], &[("SPI_WEBHOOK_SECRET", "secret")]);
+    assert!(output.contains("1 liquidations decoded"), "{}", output);
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0]["function"]["function_name"], "liquidate-obligation");
+    assert!(received[0]["properties"].as_array().unwrap().iter()
+        .any(|property| property["key"] == "liquidity_amount" && property["value"] == "2500000"));
+}
@@ -0,0 +1,96 @@
+//! Checks that every processor builds on its own, without the dependencies of the others, and
+//! that the decoders build for wasm32.
+//!
+//! Each combination is a full `cargo check`, so these are ignored by default:
+//! `cargo test --test feature_combinations -- --ignored`.
+
+use std::path::Path;
+use std::process::Command;
+
+// The processors whose dependencies build for wasm32: the vote and config programs (and
+// secp256k1) need the full solana-sdk, the upgradeable loader the account decoder.
+const WASM_FEATURES: &str = "wasm,aldrin,associated-token-account,auction-house,bpf-loader,\
+bubblegum,candy-machine,compute-budget,drift,governance,lifinity,loader,magic-eden,memo,\
+name-service,openbook-v2,phoenix,raydium-amm,serum-market,solend-token-lending,stake,system,\
+tensor,token,token-2022,token-lending,token-metadata,token-swap,whirlpool";
+
+fn check(features: &str) {
+    check_target(None, features);
+}
+
+fn check_target(target: Option<&str>, features: &str) {
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    let mut command = Command::new(env!("CARGO"));
+    command.args(&["check", "--lib", "--no-default-features", "--features", features]);
+    if let Some(target) = target {
+        command.args(&["--target", target]);
+    }
+    let status = command
+        .arg("--manifest-path")
+        .arg(Path::new(manifest_dir).join("Cargo.toml"))
+        // A target directory of their own, so the feature sets don't invalidate each other.
+        .env("CARGO_TARGET_DIR", Path::new(manifest_dir).join("target/feature-combinations"))
+        .status()
+        .unwrap();
+
+    assert!(status.success(), "the crate doesn't build for {} with only `{}`",
+            target.unwrap_or("the host"), features);
+}
+
+#[test]
+#[ignore]
+fn builds_with_token_only() {
+    check("token");
+}
+
+#[test]
+#[ignore]
+fn builds_with_each_processor_alone() {
+    for feature in &[
+        "aldrin",
+        "associated-token-account",
+        "auction-house",
+        "bpf-loader",
+        "bpf-loader-upgradeable",
+        "bubblegum",
+        "candy-machine",
+        "compute-budget",
+        "config",
+        "drift",
+        "governance",
+        "lifinity",
+        "loader",
+        "magic-eden",
+        "memo",
+        "name-service",
+        "openbook-v2",
+        "phoenix",
+        "raydium-amm",
+        "secp256k1",
+        "serum-market",
+        "solend-token-lending",
+        "stake",
+        "system",
+        "tensor",
+        "token-2022",
+        "token-lending",
+        "token-metadata",
+        "token-swap",
+        "vote",
+        "whirlpool",
+    ] {
+        check(feature);
+    }
+}
+
+#[test]
+#[ignore]
+fn builds_with_every_program() {
+    check("all-programs");
+}
+
+#[test]
+#[ignore]
+fn builds_for_wasm() {
+    check_target(Some("wasm32-unknown-unknown"), WASM_FEATURES);
+}
@@ -0,0 +1,114 @@
+//! Replays the recorded transactions of `tests/fixtures` against their snapshots.
+//!
+//! The seed fixtures (token, system and lending) were put together offline in the format RPC
+//! returns, with made-up accounts and signatures, so they don't depend on any node's history.
+//!
+//! A new processor gets a fixture of its own: record a transaction invoking it with
+//!
+//! SPI_RECORD=<name>=<signature> cargo test --features test-utils --test fixtures -- --ignored
+//!
+//! then write its snapshot with `SPI_UPDATE_SNAPSHOTS=1`, check it and add a test below.
+
+#[cfg(feature = "token")]
+use spi_wrapper::testing::{assert_fixture, assert_function, assert_property};
+
+#[test]
+#[cfg(feature = "token")]
+fn token_transfer() {
+    let record = assert_fixture("token-transfer");
+    assert_eq!(record.instruction_sets.len(), 1);
+    assert_function(&record.instruction_sets[0], "transfer");
+    assert_property(&record.instruction_sets[0], "amount", "1000000");
+}
+
+#[test]
+#[cfg(all(feature = "system", feature = "token"))]
+fn system_create_account() {
+    let record = assert_fixture("system-create-account");
+    assert_function(&record.instruction_sets[0], "create-account");
+    assert_property(&record.instruction_sets[0], "owner", &spl_token::id().to_string());
+    assert_property(&record.instruction_sets[0], "space", "165");
+    assert_function(&record.instruction_sets[1], "transfer");
+}
+
+// The deposit invokes the token program, its inner instructions are part of the snapshot.
+#[test]
+#[cfg(all(feature = "token-lending", feature = "token"))]
+fn lending_deposit_reserve_liquidity() {
+    let record = assert_fixture("lending-deposit-reserve-liquidity");
+    let deposit = &record.instruction_sets[0];
+    assert_function(deposit, "deposit-reserve-liquidity");
+    assert_property(deposit, "liquidity_amount", "5000000");
+
+    let inner: Vec<_> = record.instruction_sets.iter()
+        .filter(|set| set.function.parent_index == 0)
+        .collect();
+    assert_eq!(inner.len(), 2);
+    assert_property(inner[0], "amount", "5000000");
+}
+
+// A router invoking an AMM which invokes a pool, as reported by a recent node (with the stack
+// heights of the inner instructions) and by an older one (with only the invoke logs).
+#[test]
+#[cfg(all(feature = "system", feature = "token"))]
+fn nested_cpi_call_paths() {
+    use spi_wrapper::call_tree::StackHeights;
+
+    for (fixture, stack_heights) in &[
+        ("router-nested-cpi", StackHeights::Reported),
+        ("router-nested-cpi-logs", StackHeights::Logs),
+    ] {
+        let record = assert_fixture(fixture);
+        assert_eq!(record.call_tree.stack_heights, *stack_heights, "{}", fixture);
+        assert_property(&record.instruction_sets[0], "call_path", "0/0.0/0.0.0/0.0.0.0");
+        assert_property(&record.instruction_sets[0], "stack_height", "4");
+
+        let pool = record.call_tree.node(1, 0).unwrap();
+        let amm = record.call_tree.caller(pool).unwrap();
+        assert_eq!(record.call_tree.callees(amm).count(), 2);
+        assert_eq!(record.call_tree.caller(amm).unwrap().call_path, "0");
+    }
+}
+
+/// Records the fixtures listed in `SPI_RECORD` (`name=signature`, comma separated) from
+/// `SPI_RPC_URL`, mainnet by default.
+#[tokio::test]
+#[ignore]
+async fn record_fixtures() {
+    use std::sync::Arc;
+
+    use spi_wrapper::ingest::pool::RpcPool;
+    use spi_wrapper::testing::{fixture_path, record};
+
+    let fixtures = std::env::var("SPI_RECORD").expect("SPI_RECORD isn't set");
+    let url = std::env::var("SPI_RPC_URL")
+        .unwrap_or_else(|_| "https://api.mainnet-beta.solana.com".to_string());
+    let rpc = Arc::new(RpcPool::single(url));
+
+    for fixture in fixtures.split(',') {
+        let (name, signature) = fixture.split_once('=').expect("expected name=signature");
+        let path = fixture_path(name);
+        record(&rpc, signature, &path).await.unwrap();
+        println!("Recorded {} to {}", signature, path.display());
+    }
+}
+
+// What the fixtures decode to survives a round trip through a snapshot file.
+#[test]
+#[cfg(all(feature = "snapshot", feature = "token", feature = "system"))]
+fn fixtures_replay_to_a_snapshot() {
+    use spi_wrapper::snapshot::SnapshotReader;
+    use spi_wrapper::testing::{fixture_path, replay_to_snapshot};
+    use spi_wrapper::{ProcessorRegistry, TransactionRecord};
+
+    let fixtures = vec![fixture_path("token-transfer"), fixture_path("system-create-account")];
+    let output = std::env::temp_dir().join(format!("spi-fixtures-{}.spis", std::process::id()));
+    let records = replay_to_snapshot(&ProcessorRegistry::default(), &fixtures, &output).unwrap();
+
+    let read: Vec<TransactionRecord> = SnapshotReader::open(&output).unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let _ = std::fs::remove_file(&output);
+    assert_eq!(serde_json::to_value(&read).unwrap(), serde_json::to_value(&records).unwrap());
+    assert_eq!(read[1].instruction_sets[0].function.function_name.as_str(), "create-account");
+}
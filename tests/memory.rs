@@ -0,0 +1,474 @@
+//! Memory use of decoded records, measured with a counting global allocator.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use serde_json::json;
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::message::Message;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::Transaction;
+use solana_transaction_status::UiConfirmedBlock;
+use spl_token::instruction::TokenInstruction;
+#[cfg(feature = "token-lending")]
+use spl_token_lending::instruction::LendingInstruction;
+#[cfg(feature = "token-lending")]
+use spl_token_lending::state::{ReserveConfig, ReserveFees};
+use spi_wrapper::ingest::pipeline::{Pipeline, PipelineConfig};
+use spi_wrapper::mints::{ui_amount, MintCache, MintCacheConfig};
+use spi_wrapper::sinks::{Sink, SinkError};
+use spi_wrapper::{
+    decode_block, decode_block_streaming, decode_transaction, Instruction, InstructionSet,
+    Network, ProcessorRegistry, RawTransaction, Symbol, TokenMint, TransactionRecord,
+};
+use tokio::sync::mpsc;
+
+const BATCH_SIZE: usize = 1_000_000;
+const INSTRUCTIONS_PER_TRANSACTION: usize = 4;
+// About as many transactions as the busiest mainnet blocks.
+const BLOCK_TRANSACTIONS: usize = 4000;
+const SOAK_TRANSACTIONS: usize = 10_000;
+const SOAK_MINTS_CACHED: usize = 8;
+
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(allocated, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// The allocator counts every thread, so measurements can't overlap.
+static MEASURING: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+fn allocated() -> usize {
+    ALLOCATED.load(Ordering::SeqCst)
+}
+
+/// Starts tracking the peak from the bytes allocated right now, which it returns.
+fn reset_peak() -> usize {
+    let allocated = allocated();
+    PEAK.store(allocated, Ordering::SeqCst);
+
+    allocated
+}
+
+fn peak() -> usize {
+    PEAK.load(Ordering::SeqCst)
+}
+
+fn allocations() -> usize {
+    ALLOCATIONS.load(Ordering::SeqCst)
+}
+
+/// The records as they were before interning: every one owning its strings.
+#[allow(dead_code)]
+struct OwnedFunction {
+    tx_instruction_id: i16,
+    transaction_hash: String,
+    parent_index: i16,
+    program: String,
+    function_name: String,
+    timestamp: i64,
+}
+
+#[allow(dead_code)]
+struct OwnedProperty {
+    tx_instruction_id: i16,
+    transaction_hash: String,
+    parent_index: i16,
+    key: String,
+    value: String,
+    parent_key: String,
+    timestamp: i64,
+}
+
+#[allow(dead_code)]
+struct OwnedSet {
+    function: OwnedFunction,
+    properties: Vec<OwnedProperty>,
+}
+
+fn to_owned(set: &InstructionSet) -> OwnedSet {
+    OwnedSet {
+        function: OwnedFunction {
+            tx_instruction_id: set.function.tx_instruction_id,
+            transaction_hash: set.function.transaction_hash.to_string(),
+            parent_index: set.function.parent_index,
+            program: set.function.program.to_string(),
+            function_name: set.function.function_name.to_string(),
+            timestamp: set.function.timestamp,
+        },
+        properties: set.properties.iter()
+            .map(|property| OwnedProperty {
+                tx_instruction_id: property.tx_instruction_id,
+                transaction_hash: property.transaction_hash.to_string(),
+                parent_index: property.parent_index,
+                key: property.key.to_string(),
+                value: property.value.clone(),
+                parent_key: property.parent_key.to_string(),
+                timestamp: property.timestamp,
+            })
+            .collect(),
+    }
+}
+
+fn batch() -> Vec<Instruction> {
+    let data = TokenInstruction::Transfer { amount: 42 }.pack();
+    let program = Symbol::intern(&spl_token::id().to_string());
+
+    (0..BATCH_SIZE / INSTRUCTIONS_PER_TRANSACTION)
+        .flat_map(|transaction| {
+            // Signatures are 88 characters once base58 encoded.
+            let transaction_hash = format!("{:0>88}", transaction).into();
+            let data = data.clone();
+            (0..INSTRUCTIONS_PER_TRANSACTION).map(move |index| Instruction {
+                tx_instruction_id: index as i16,
+                transaction_hash: Arc::clone(&transaction_hash),
+                program,
+                data: data.clone(),
+                parent_index: -1,
+                timestamp: 0,
+                network: Network::Mainnet,
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn interned_records_use_less_memory() {
+    let _measuring = MEASURING.lock().unwrap();
+    let decoded = spi_wrapper::decode(batch(), None);
+    assert_eq!(decoded.len(), BATCH_SIZE);
+    assert!(decoded.iter().all(|set| set.function.function_name == "transfer"
+        && set.properties.iter().any(|property| property.key == "amount")));
+
+    // Cloning shares the transaction hashes the same way decoding does.
+    let before = allocated();
+    let interned_copy = decoded.clone();
+    let interned = allocated() - before;
+
+    let before = allocated();
+    let owned: Vec<OwnedSet> = decoded.iter().map(to_owned).collect();
+    let owned_bytes = allocated() - before;
+    assert_eq!(owned.len(), interned_copy.len());
+
+    assert!(interned * 10 < owned_bytes * 6,
+            "{} interned records take {} bytes, owned ones {}", BATCH_SIZE, interned, owned_bytes);
+}
+
+/// A block of token transfers as returned by `getBlock` with the base64 encoding.
+fn fixture_block() -> UiConfirmedBlock {
+    let payer = Pubkey::new_unique();
+    let (source, destination) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let transactions: Vec<_> = (0..BLOCK_TRANSACTIONS)
+        .map(|index| {
+            let instructions: Vec<_> = (0..INSTRUCTIONS_PER_TRANSACTION as u64)
+                .map(|amount| spl_token::instruction::transfer(
+                    &spl_token::id(), &source, &destination, &payer, &[],
+                    amount + index as u64).unwrap())
+                .collect();
+            let transaction = Transaction::new_unsigned(Message::new(&instructions, Some(&payer)));
+
+            json!({
+                "transaction": [
+                    base64::encode(bincode::serialize(&transaction).unwrap()), "base64"],
+                "meta": {
+                    "err": null,
+                    "status": { "Ok": null },
+                    "fee": 5000,
+                    "preBalances": [],
+                    "postBalances": [],
+                },
+            })
+        })
+        .collect();
+
+    serde_json::from_value(json!({
+        "blockhash": bs58::encode([1u8; 32]).into_string(),
+        "previousBlockhash": bs58::encode([0u8; 32]).into_string(),
+        "parentSlot": 0,
+        "transactions": transactions,
+        "blockTime": 1_600_000_000,
+    })).unwrap()
+}
+
+/// The bytes taken by the fixture block once decoded as a whole.
+fn materialized_size(registry: &ProcessorRegistry, block: &UiConfirmedBlock) -> usize {
+    let before = allocated();
+    let record = decode_block(registry, 1, block);
+    let size = allocated() - before;
+    assert_eq!(record.transactions.len(), BLOCK_TRANSACTIONS);
+
+    size
+}
+
+#[test]
+fn streaming_a_block_stays_under_budget() {
+    let _measuring = MEASURING.lock().unwrap();
+    let registry = ProcessorRegistry::default();
+    let block = fixture_block();
+    let materialized = materialized_size(&registry, &block);
+
+    let mut batch = Vec::with_capacity(100);
+    let mut streamed = 0;
+    let baseline = reset_peak();
+    decode_block_streaming(&registry, 1, &block, &mut |transaction| {
+        batch.push(transaction);
+        if batch.len() == batch.capacity() {
+            streamed += batch.len();
+            batch.clear();
+        }
+    });
+    let streaming_peak = peak() - baseline;
+    assert_eq!(streamed + batch.len(), BLOCK_TRANSACTIONS);
+
+    assert!(streaming_peak * 4 < materialized,
+            "streaming peaked at {} bytes, the whole block takes {}", streaming_peak, materialized);
+}
+
+struct CountingSink {
+    transactions: usize,
+}
+
+#[async_trait]
+impl Sink for CountingSink {
+    async fn write_transactions(
+        &mut self,
+        transactions: &[TransactionRecord],
+    ) -> Result<(), SinkError> {
+        self.transactions += transactions.len();
+        Ok(())
+    }
+}
+
+#[test]
+fn pipeline_stays_under_budget() {
+    let _measuring = MEASURING.lock().unwrap();
+    let registry = Arc::new(ProcessorRegistry::default());
+    let block = fixture_block();
+    let materialized = materialized_size(&registry, &block);
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let pipeline = Pipeline::new(registry, PipelineConfig {
+        max_in_flight: 200,
+        batch_size: 100,
+        ..PipelineConfig::default()
+    });
+    let (sender, receiver) = mpsc::channel(1);
+    let mut sink = CountingSink { transactions: 0 };
+
+    // The block is handed over to the pipeline, which frees it while decoding it.
+    let baseline = reset_peak();
+    runtime.block_on(async {
+        sender.send((1, block)).await.unwrap();
+        drop(sender);
+        pipeline.run(receiver, &mut sink).await.unwrap();
+    });
+    let pipeline_peak = peak().saturating_sub(baseline);
+    assert_eq!(sink.transactions, BLOCK_TRANSACTIONS);
+
+    assert!(pipeline_peak * 2 < materialized,
+            "the pipeline peaked at {} bytes, the whole block takes {}", pipeline_peak,
+            materialized);
+}
+
+/// The `n`th transaction of the soak test: a transfer of a new mint, whose decimals come with the
+/// token balances, then a `mint-to` of the mint of the previous transaction, whose decimals can
+/// only come from the mint cache.
+fn soak_transaction(n: usize, mint: Pubkey, previous_mint: Pubkey) -> RawTransaction {
+    let (payer, source, destination) =
+        (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let decimals = soak_decimals(n);
+
+    RawTransaction {
+        slot: n as u64,
+        block_time: None,
+        transaction_hash: format!("{:0>88}", n),
+        account_keys: vec![payer, source, destination, previous_mint, spl_token::id()],
+        num_required_signatures: 1,
+        instructions: vec![
+            CompiledInstruction {
+                program_id_index: 4,
+                accounts: vec![1, 2, 0],
+                data: TokenInstruction::Transfer { amount: 1_500_000 }.pack(),
+            },
+            CompiledInstruction {
+                program_id_index: 4,
+                accounts: vec![3, 2, 0],
+                data: TokenInstruction::MintTo { amount: 2_500_000 }.pack(),
+            },
+        ],
+        inner_instructions: vec![],
+        token_mints: vec![
+            TokenMint { account_index: 1, mint, decimals },
+            TokenMint { account_index: 2, mint, decimals },
+        ],
+        fee: 5000,
+        succeeded: true,
+        stack_heights: vec![],
+        log_messages: vec![],
+        pre_balances: vec![],
+        post_balances: vec![],
+    }
+}
+
+fn soak_decimals(n: usize) -> u8 {
+    (n % 9) as u8 + 1
+}
+
+#[test]
+fn tiny_caches_stay_bounded() {
+    let _measuring = MEASURING.lock().unwrap();
+    let mints = MintCache::new(MintCacheConfig {
+        capacity: SOAK_MINTS_CACHED,
+        ..MintCacheConfig::default()
+    });
+    let mut registry = ProcessorRegistry::default();
+    registry.set_mint_cache(mints.clone());
+
+    let mut previous_mint = Pubkey::new_unique();
+    let mut warmed_up = 0;
+    for n in 0..SOAK_TRANSACTIONS {
+        // Leaves the interner and the cache time to fill up.
+        if n == SOAK_TRANSACTIONS / 10 {
+            warmed_up = allocated();
+        }
+
+        let mint = Pubkey::new_unique();
+        let record = decode_transaction(&registry, &soak_transaction(n, mint, previous_mint));
+        let ui_amounts: Vec<_> = record.instruction_sets.iter()
+            .map(|set| {
+                let ui_amount = set.properties.iter()
+                    .find(|property| property.key.as_str() == "amount_ui")
+                    .map(|property| property.value.clone());
+                (set.function.function_name.as_str(), ui_amount)
+            })
+            .collect();
+        // The first `mint-to` is of a mint no transaction told the decimals of.
+        let minted = if n == 0 { None } else { ui_amount("2500000", soak_decimals(n - 1)) };
+        assert_eq!(ui_amounts, vec![
+            ("transfer", ui_amount("1500000", soak_decimals(n))),
+            ("mint-to", minted),
+        ], "transaction {}", n);
+
+        previous_mint = mint;
+    }
+    let growth = allocated().saturating_sub(warmed_up);
+
+    let stats = mints.stats();
+    assert_eq!(mints.len(), SOAK_MINTS_CACHED);
+    assert_eq!(stats.evictions as usize, SOAK_TRANSACTIONS - SOAK_MINTS_CACHED);
+    assert_eq!(stats.hits as usize, SOAK_TRANSACTIONS - 1);
+    // An unbounded cache would have grown by tens of bytes per mint.
+    assert!(growth < 16 * 1024, "grew by {} bytes once warmed up", growth);
+}
+
+#[cfg(feature = "token-lending")]
+const LENDING_PROGRAM: &str = "LendZqTs8gn5CTSJU1jWKhKuVpjJGom45nnwPb2AMTi";
+
+/// Every lending instruction, along with whether all of its properties are integers (which take
+/// a single allocation each to format).
+#[cfg(feature = "token-lending")]
+fn lending_instructions() -> Vec<(LendingInstruction, bool)> {
+    let config = ReserveConfig {
+        optimal_utilization_rate: 80,
+        loan_to_value_ratio: 50,
+        liquidation_bonus: 5,
+        liquidation_threshold: 55,
+        min_borrow_rate: 0,
+        optimal_borrow_rate: 4,
+        max_borrow_rate: 30,
+        fees: ReserveFees {
+            borrow_fee_wad: 100_000_000_000,
+            flash_loan_fee_wad: 3_000_000_000_000_000,
+            host_fee_percentage: 20,
+        },
+    };
+
+    vec![
+        (LendingInstruction::InitLendingMarket {
+            owner: Pubkey::new_unique(),
+            quote_currency: [1; 32],
+        }, false),
+        (LendingInstruction::SetLendingMarketOwner { new_owner: Pubkey::new_unique() }, false),
+        (LendingInstruction::InitReserve { liquidity_amount: 1_000, config }, true),
+        (LendingInstruction::RefreshReserve, true),
+        (LendingInstruction::DepositReserveLiquidity { liquidity_amount: 1_000 }, true),
+        (LendingInstruction::RedeemReserveCollateral { collateral_amount: 1_000 }, true),
+        (LendingInstruction::InitObligation, true),
+        (LendingInstruction::RefreshObligation, true),
+        (LendingInstruction::DepositObligationCollateral { collateral_amount: 1_000 }, true),
+        (LendingInstruction::WithdrawObligationCollateral { collateral_amount: 1_000 }, true),
+        (LendingInstruction::BorrowObligationLiquidity { liquidity_amount: 1_000 }, true),
+        (LendingInstruction::RepayObligationLiquidity { liquidity_amount: 1_000 }, true),
+        (LendingInstruction::LiquidateObligation { liquidity_amount: 1_000 }, true),
+        (LendingInstruction::FlashLoan { amount: 1_000 }, true),
+    ]
+}
+
+#[test]
+#[cfg(feature = "token-lending")]
+fn short_property_lists_are_stored_inline() {
+    let _measuring = MEASURING.lock().unwrap();
+    let registry = ProcessorRegistry::default();
+    let transaction_hash: Arc<str> = Arc::from("transaction");
+    let decode = |lending_instruction: &LendingInstruction| {
+        let instruction = Instruction {
+            tx_instruction_id: 0,
+            transaction_hash: transaction_hash.clone(),
+            program: Symbol::intern(LENDING_PROGRAM),
+            data: lending_instruction.pack(),
+            parent_index: -1,
+            timestamp: 0,
+            network: Network::Mainnet,
+        };
+        let before = allocations();
+        let instruction_set = registry.decode(instruction, None).unwrap();
+
+        (allocations() - before, instruction_set)
+    };
+
+    let instructions = lending_instructions();
+    // Interns the function names and property keys, which would otherwise count.
+    for (instruction, _) in &instructions {
+        decode(instruction);
+    }
+
+    for (instruction, integers_only) in &instructions {
+        let (allocations, instruction_set) = decode(instruction);
+        let properties = &instruction_set.properties;
+        let function_name = instruction_set.function.function_name;
+
+        if properties.len() <= 4 {
+            assert!(!properties.spilled(), "{} allocated its properties", function_name);
+        } else {
+            assert_eq!(properties.capacity(), properties.len(),
+                       "{} reallocated its properties", function_name);
+        }
+        if *integers_only {
+            // One allocation per formatted value, plus the list itself if it's too long to be
+            // stored inline.
+            assert_eq!(allocations, properties.len() + properties.spilled() as usize,
+                       "{} took too many allocations", function_name);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Loads the example processor plugin and decodes through it.
+
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+use spi_wrapper::plugin::{PluginError, PluginLoader};
+use spi_wrapper::{Instruction, Network, ProcessorRegistry, Symbol};
+
+const COUNTER_PROGRAM: &str = "Counter111111111111111111111111111111111111";
+
+/// Builds the example plugin, returning the path of the library.
+fn build_example_plugin() -> PathBuf {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // A target directory of its own, the one of the tests is locked while they run.
+    let target_dir = manifest_dir.join("target/plugin-test");
+    let status = Command::new(env!("CARGO"))
+        .args(&["build", "-p", "spi-example-processor"])
+        .current_dir(manifest_dir)
+        .env("CARGO_TARGET_DIR", &target_dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "the example plugin doesn't build");
+
+    target_dir.join("debug")
+        .join(format!("{}spi_example_processor{}", DLL_PREFIX, DLL_SUFFIX))
+}
+
+#[test]
+fn decodes_through_the_example_plugin() {
+    let mut loader = PluginLoader::new();
+    loader.load(build_example_plugin()).unwrap();
+    let mut registry = ProcessorRegistry::new();
+    loader.register(&mut registry);
+    assert_eq!(registry.processor(COUNTER_PROGRAM).unwrap().name(), "counter");
+
+    let mut data = vec![1];
+    data.extend_from_slice(&42u64.to_le_bytes());
+    let instruction_set = registry.decode(Instruction {
+        tx_instruction_id: 3,
+        transaction_hash: Arc::from("transaction"),
+        program: Symbol::intern(COUNTER_PROGRAM),
+        data,
+        parent_index: -1,
+        timestamp: 1_600_000_000,
+        network: Network::Mainnet,
+    }, None).unwrap();
+
+    assert_eq!(instruction_set.function.function_name, "increment");
+    assert_eq!(instruction_set.function.tx_instruction_id, 3);
+    assert_eq!(instruction_set.properties.len(), 1);
+    assert_eq!(instruction_set.properties[0].key, "amount");
+    assert_eq!(instruction_set.properties[0].value, "42");
+}
+
+#[test]
+fn missing_libraries_are_reported() {
+    let error = PluginLoader::new().load("/nonexistent/libprocessor.so").err().unwrap();
+    assert!(matches!(error, PluginError::Load { .. }), "{}", error);
+}
@@ -0,0 +1,81 @@
+//! What the lending processor decodes every instruction to, kept as snapshots under
+//! `tests/snapshots/lending`. See `spi_wrapper::testing` for how to update them.
+
+use solana_sdk::pubkey::Pubkey;
+use spi_wrapper::testing::snapshot_instruction_set;
+use spi_wrapper::{BuiltinProcessor, Instruction, InstructionSet, Network, ProcessorRegistry};
+use spl_token_lending::instruction::LendingInstruction;
+use spl_token_lending::state::{ReserveConfig, ReserveFees};
+
+fn decode(lending_instruction: LendingInstruction) -> InstructionSet {
+    let program_id = BuiltinProcessor::all().into_iter()
+        .find(|(_, processor)| *processor == BuiltinProcessor::TokenLending)
+        .map(|(program_id, _)| program_id)
+        .unwrap();
+    let instruction = Instruction {
+        tx_instruction_id: 0,
+        transaction_hash: "signature".into(),
+        program: program_id.into(),
+        data: lending_instruction.pack(),
+        parent_index: -1,
+        timestamp: 1_600_000_000,
+        network: Network::Mainnet,
+    };
+
+    ProcessorRegistry::default().decode(instruction, None).unwrap()
+}
+
+#[test]
+fn lending_market_instructions() {
+    let owner = Pubkey::new_from_array([1; 32]);
+    snapshot_instruction_set("lending/init-lending-market", &decode(
+        LendingInstruction::InitLendingMarket { owner, quote_currency: [2; 32] }));
+    snapshot_instruction_set("lending/set-lending-market-owner", &decode(
+        LendingInstruction::SetLendingMarketOwner { new_owner: owner }));
+}
+
+#[test]
+fn reserve_instructions() {
+    let config = ReserveConfig {
+        optimal_utilization_rate: 80,
+        loan_to_value_ratio: 50,
+        liquidation_bonus: 5,
+        liquidation_threshold: 55,
+        min_borrow_rate: 0,
+        optimal_borrow_rate: 4,
+        max_borrow_rate: 30,
+        fees: ReserveFees {
+            borrow_fee_wad: 100_000_000_000,
+            flash_loan_fee_wad: 3_000_000_000_000_000,
+            host_fee_percentage: 20,
+        },
+    };
+    snapshot_instruction_set("lending/init-reserve", &decode(
+        LendingInstruction::InitReserve { liquidity_amount: 1_000, config }));
+    snapshot_instruction_set("lending/refresh-reserve", &decode(
+        LendingInstruction::RefreshReserve));
+    snapshot_instruction_set("lending/deposit-reserve-liquidity", &decode(
+        LendingInstruction::DepositReserveLiquidity { liquidity_amount: 5_000_000 }));
+    snapshot_instruction_set("lending/redeem-reserve-collateral", &decode(
+        LendingInstruction::RedeemReserveCollateral { collateral_amount: 4_950_000 }));
+    snapshot_instruction_set("lending/flash-loan", &decode(
+        LendingInstruction::FlashLoan { amount: 1_000_000 }));
+}
+
+#[test]
+fn obligation_instructions() {
+    snapshot_instruction_set("lending/init-obligation", &decode(
+        LendingInstruction::InitObligation));
+    snapshot_instruction_set("lending/refresh-obligation", &decode(
+        LendingInstruction::RefreshObligation));
+    snapshot_instruction_set("lending/deposit-obligation-collateral", &decode(
+        LendingInstruction::DepositObligationCollateral { collateral_amount: 4_950_000 }));
+    snapshot_instruction_set("lending/withdraw-obligation-collateral", &decode(
+        LendingInstruction::WithdrawObligationCollateral { collateral_amount: 1_000_000 }));
+    snapshot_instruction_set("lending/borrow-obligation-liquidity", &decode(
+        LendingInstruction::BorrowObligationLiquidity { liquidity_amount: 2_000_000 }));
+    snapshot_instruction_set("lending/repay-obligation-liquidity", &decode(
+        LendingInstruction::RepayObligationLiquidity { liquidity_amount: 2_000_000 }));
+    snapshot_instruction_set("lending/liquidate-obligation", &decode(
+        LendingInstruction::LiquidateObligation { liquidity_amount: 500_000 }));
+}
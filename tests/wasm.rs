@@ -0,0 +1,37 @@
+//! Decodes lending instructions through the JavaScript exports:
+//!
+//! `wasm-pack test --node -- --no-default-features --features wasm,token-lending`
+
+#![cfg(target_arch = "wasm32")]
+
+use serde_json::{json, Value};
+use spi_wrapper::wasm::decode_instruction;
+use spl_token_lending::instruction::LendingInstruction;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const LENDING_PROGRAM: &str = "LendZqTs8gn5CTSJU1jWKhKuVpjJGom45nnwPb2AMTi";
+
+fn decode(program_id: &str, data: &[u8]) -> Value {
+    decode_instruction(program_id, &bs58::encode(data).into_string())
+        .unwrap()
+        .into_serde()
+        .unwrap()
+}
+
+#[wasm_bindgen_test]
+fn decodes_lending_instructions() {
+    let data = LendingInstruction::DepositReserveLiquidity { liquidity_amount: 5_000_000 }.pack();
+    let decoded = decode(LENDING_PROGRAM, &data);
+
+    assert_eq!(decoded[0]["function"]["program"], LENDING_PROGRAM);
+    assert_eq!(decoded[0]["function"]["function_name"], "deposit-reserve-liquidity");
+    assert_eq!(decoded[0]["properties"][0]["key"], "liquidity_amount");
+    assert_eq!(decoded[0]["properties"][0]["value"], "5000000");
+}
+
+#[wasm_bindgen_test]
+fn undecodable_instructions_decode_to_nothing() {
+    assert_eq!(decode(LENDING_PROGRAM, &[255]), json!([]));
+    assert_eq!(decode("Unknown111111111111111111111111111111111111", &[0]), json!([]));
+    assert!(decode_instruction(LENDING_PROGRAM, "not base58: 0OIl").is_err());
+}